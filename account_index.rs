@@ -0,0 +1,72 @@
+/*!
+Enumeration of registered accounts.
+NOTES:
+  - Every registration and unregistration goes through `internal_register_account` and
+    `storage_unregister`, which keep `registered_accounts` in sync with the token accounts.
+  - Accounts registered before the index existed, e.g. on migrated V1 state, are added by the
+    owner with `index_accounts`.
+  - The contract pays for the index entries.
+*/
+use near_sdk::{near_bindgen, AccountId};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_accounts(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<AccountId> {
+        self.registered_accounts
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_number_of_accounts(&self) -> u32 {
+        self.registered_accounts.len()
+    }
+
+    /// Adds already registered accounts to the index. Unregistered accounts are skipped.
+    pub fn index_accounts(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids {
+            if self.token.accounts.contains_key(&account_id) {
+                self.registered_accounts.insert(account_id);
+            }
+        }
+    }
+}
+
+impl Contract {
+    /// Registers `account_id` with the token and adds it to the index.
+    pub(crate) fn internal_register_account(&mut self, account_id: &AccountId) {
+        self.token.internal_register_account(account_id);
+        self.registered_accounts.insert(account_id.clone());
+        self.dust_sweeping.touch(account_id);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_accounts_follow_registrations() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        assert_eq!(contract.get_number_of_accounts(), 3);
+        assert_eq!(
+            contract.get_accounts(Some(1), Some(1)),
+            vec![env::current_account_id()]
+        );
+
+        call_as(&mut context, accounts(1), 1);
+        contract.storage_unregister(None);
+        assert_eq!(
+            contract.get_accounts(None, None),
+            vec![owner(), accounts(0)]
+        );
+    }
+}
@@ -0,0 +1,47 @@
+/*!
+Owner methods of the token and the owner check shared by every subsystem.
+NOTES:
+  - `assert_owner` routes the owner through the council or the timelock when they are enabled,
+    and fails for good once ownership is renounced, see `ownership`.
+*/
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::{env, near_bindgen};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn update_image(&mut self, image: String) {
+        self.assert_owner();
+        self.internal_update_metadata(None, None, Some(image), None, None, None);
+    }
+
+    /// Updates the given metadata fields, leaving the ones passed as `None` unchanged.
+    /// Decimals can only change before any tokens have been transferred. Name and symbol
+    /// changes need dual control, see `metadata_control`.
+    pub fn update_metadata(
+        &mut self,
+        icon: Option<String>,
+        reference: Option<String>,
+        reference_hash: Option<Base64VecU8>,
+        decimals: Option<u8>,
+    ) {
+        self.assert_owner();
+        self.internal_update_metadata(None, None, icon, reference, reference_hash, decimals);
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_owner(&self) {
+        self.assert_not_renounced();
+        if self.council.is_active() || self.timelock.is_enabled() {
+            self.assert_routed_admin_call();
+            return;
+        }
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "Owner's method"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+/*!
+Merkle airdrop campaigns with claimed indexes kept in a bitmap.
+NOTES:
+  - A creator locks the campaign total in the contract and publishes the Merkle root of its
+    `(index, account_id, amount)` entries. Leaves are `sha256(0x00 || borsh(index, account_id,
+    amount))`, inner nodes are hashed as in `balance_proofs`.
+  - Claimed indexes are stored as bits in 128-bit words per campaign, so a claim costs at most
+    one new word and `is_claimed` is a single lookup. An index can never be claimed twice,
+    whatever the frontend does.
+  - After `expires_at` the creator can take back the unclaimed tokens.
+  - The creator pays for the campaign storage, the contract for the bitmap words.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance, CryptoHash};
+
+use crate::balance_proofs::{root_from_proof, ProofStep};
+use crate::pool_ledger::Pool;
+use crate::*;
+
+const BITS_PER_WORD: u32 = 128;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AirdropCampaign {
+    pub creator_id: AccountId,
+    pub root: Base58CryptoHash,
+    pub total: U128,
+    pub claimed: U128,
+    pub expires_at: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Airdrops {
+    pub campaigns: LookupMap<u64, AirdropCampaign>,
+    /// Claimed bits of each campaign by word index.
+    pub claimed: LookupMap<(u64, u32), u128>,
+    pub next_campaign_id: u64,
+}
+
+impl Airdrops {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            campaigns: LookupMap::new([prefix.as_slice(), b"c"].concat()),
+            claimed: LookupMap::new([prefix.as_slice(), b"b"].concat()),
+            next_campaign_id: 0,
+        }
+    }
+
+    pub fn is_claimed(&self, campaign_id: u64, index: u32) -> bool {
+        let word = self
+            .claimed
+            .get(&(campaign_id, index / BITS_PER_WORD))
+            .copied()
+            .unwrap_or(0);
+        word & (1u128 << (index % BITS_PER_WORD)) != 0
+    }
+
+    fn set_claimed(&mut self, campaign_id: u64, index: u32) {
+        let key = (campaign_id, index / BITS_PER_WORD);
+        let word = self.claimed.get(&key).copied().unwrap_or(0);
+        self.claimed
+            .insert(key, word | (1u128 << (index % BITS_PER_WORD)));
+    }
+
+    fn unwrap_campaign(&self, campaign_id: u64) -> AirdropCampaign {
+        self.campaigns
+            .get(&campaign_id)
+            .cloned()
+            .expect("Airdrop campaign not found")
+    }
+}
+
+pub fn airdrop_leaf_hash(index: u32, account_id: &AccountId, amount: Balance) -> CryptoHash {
+    let mut data = vec![0];
+    data.extend((index, account_id, amount).try_to_vec().unwrap());
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(&data));
+    hash
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Locks `total` of the caller's tokens for the entries committed to by `root` and returns
+    /// the campaign id. The attached deposit covers the campaign storage.
+    #[payable]
+    pub fn create_airdrop(&mut self, root: Base58CryptoHash, total: U128, expires_at: U64) -> U64 {
+        self.assert_module_active(Module::Airdrops);
+        assert!(total.0 > 0, "The amount should be a positive number");
+        assert!(
+            expires_at.0 > env::block_timestamp(),
+            "Expiry must be in the future"
+        );
+        let initial_storage = env::storage_usage();
+        let creator_id = env::predecessor_account_id();
+        self.internal_lock_to(Pool::Airdrops, &creator_id, total.0);
+        let campaign_id = self.airdrops.next_campaign_id;
+        self.airdrops.next_campaign_id += 1;
+        self.airdrops.campaigns.insert(
+            campaign_id,
+            AirdropCampaign {
+                creator_id,
+                root,
+                total,
+                claimed: U128(0),
+                expires_at,
+            },
+        );
+        self.airdrops.campaigns.flush();
+        refund_deposit(env::storage_usage() - initial_storage);
+        campaign_id.into()
+    }
+
+    /// Claims entry `index` of a campaign for the caller, who must be registered.
+    pub fn claim_airdrop(
+        &mut self,
+        campaign_id: U64,
+        index: u32,
+        amount: U128,
+        proof: Vec<ProofStep>,
+    ) {
+        let mut campaign = self.airdrops.unwrap_campaign(campaign_id.0);
+        assert!(
+            env::block_timestamp() <= campaign.expires_at.0,
+            "Airdrop campaign has expired"
+        );
+        assert!(
+            !self.airdrops.is_claimed(campaign_id.0, index),
+            "Already claimed"
+        );
+        let account_id = env::predecessor_account_id();
+        let leaf = airdrop_leaf_hash(index, &account_id, amount.0);
+        assert!(
+            root_from_proof(leaf, &proof) == CryptoHash::from(campaign.root),
+            "Invalid proof"
+        );
+        campaign.claimed = (campaign.claimed.0 + amount.0).into();
+        assert!(
+            campaign.claimed.0 <= campaign.total.0,
+            "Airdrop campaign is exhausted"
+        );
+        self.airdrops.set_claimed(campaign_id.0, index);
+        self.airdrops.campaigns.insert(campaign_id.0, campaign);
+        self.internal_unlock_from(Pool::Airdrops, &account_id, amount.0);
+        log!(
+            "@{} claimed {} from airdrop {}",
+            account_id,
+            amount.0,
+            campaign_id.0
+        );
+    }
+
+    /// Returns the unclaimed tokens of an expired campaign to its creator.
+    #[payable]
+    pub fn reclaim_airdrop(&mut self, campaign_id: U64) -> U128 {
+        assert_one_yocto();
+        let campaign = self.airdrops.unwrap_campaign(campaign_id.0);
+        assert_eq!(
+            env::predecessor_account_id(),
+            campaign.creator_id,
+            "Only the creator can reclaim an airdrop"
+        );
+        assert!(
+            env::block_timestamp() > campaign.expires_at.0,
+            "Airdrop campaign has not expired"
+        );
+        self.airdrops.campaigns.remove(&campaign_id.0);
+        let remaining = campaign.total.0 - campaign.claimed.0;
+        if remaining > 0 {
+            self.internal_unlock_from(Pool::Airdrops, &campaign.creator_id, remaining);
+        }
+        remaining.into()
+    }
+
+    pub fn get_airdrop(&self, campaign_id: U64) -> Option<AirdropCampaign> {
+        self.airdrops.campaigns.get(&campaign_id.0).cloned()
+    }
+
+    pub fn is_claimed(&self, campaign_id: U64, index: u32) -> bool {
+        self.airdrops.is_claimed(campaign_id.0, index)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::balance_proofs::node_hash;
+    use crate::test_fixtures::{call_as, setup};
+
+    #[test]
+    #[should_panic(expected = "Already claimed")]
+    fn test_claim_airdrop_once() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+
+        let leaves = [
+            airdrop_leaf_hash(0, &accounts(1), 300),
+            airdrop_leaf_hash(1, &accounts(2), 700),
+        ];
+        let root = node_hash(&leaves[0], &leaves[1]);
+        let campaign_id = contract.create_airdrop(root.into(), U128(1_000), U64(100));
+
+        call_as(&mut context, accounts(1), 0);
+        let proof = || {
+            vec![ProofStep {
+                hash: leaves[1].into(),
+                is_left: false,
+            }]
+        };
+        contract.claim_airdrop(campaign_id, 0, U128(300), proof());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 300);
+        assert!(contract.is_claimed(campaign_id, 0));
+        assert!(!contract.is_claimed(campaign_id, 1));
+        contract.claim_airdrop(campaign_id, 0, U128(300), proof());
+    }
+}
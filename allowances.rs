@@ -0,0 +1,440 @@
+/*!
+ERC-20 style allowances next to the NEP-141 API.
+NOTES:
+  - An owner approves a spender for an amount, which the spender can then move from the owner's
+    balance with `transfer_from`. `transfer_from` goes through the same checks, fees and hooks as
+    an `ft_transfer` by the owner.
+  - `approve` overwrites the allowance. Prefer `increase_allowance` and `decrease_allowance` to
+    avoid the usual race where the spender uses the old allowance before the new one lands.
+  - `burn_from` burns from the owner's balance out of the allowance the same way, e.g. for a
+    custodian redeeming tokens on behalf of its users.
+  - `approve` can limit an allowance to a time, `valid_until`, and to one use, `single_use`:
+    the first spend of a single-use allowance, of any amount, clears it. An expired allowance
+    counts as zero everywhere. Increases and decreases keep the terms of the allowance.
+  - `get_allowances` lists an owner's allowances, expired ones included, and
+    `revoke_allowances` clears several at once. Allowances set before the listing existed are
+    listed once they are changed again.
+  - Owners pay for the storage of their allowances. An allowance used up, decreased to zero or
+    revoked is removed and its storage refunded to the owner. `approve` also removes the owner's
+    expired allowances.
+*/
+use near_contract_standards::fungible_token::events::FtBurn;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const MAX_ALLOWANCES_PER_OWNER: usize = 50;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Default, PartialEq)]
+pub struct AllowanceTerms {
+    /// Timestamp in nanoseconds from which the allowance is void.
+    pub valid_until: Option<u64>,
+    pub single_use: bool,
+}
+
+impl AllowanceTerms {
+    fn is_expired(&self) -> bool {
+        matches!(self.valid_until, Some(valid_until) if env::block_timestamp() >= valid_until)
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct AllowanceIndex {
+    /// Terms of the allowances that have any.
+    pub terms: LookupMap<(AccountId, AccountId), AllowanceTerms>,
+    /// Spenders of each owner.
+    pub spenders: LookupMap<AccountId, Vec<AccountId>>,
+}
+
+impl AllowanceIndex {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            terms: LookupMap::new([prefix.as_slice(), b"t"].concat()),
+            spenders: LookupMap::new([prefix.as_slice(), b"s"].concat()),
+        }
+    }
+
+    fn terms(&self, owner_id: &AccountId, spender_id: &AccountId) -> AllowanceTerms {
+        self.terms
+            .get(&(owner_id.clone(), spender_id.clone()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn flush(&mut self) {
+        self.terms.flush();
+        self.spenders.flush();
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AllowanceInfo {
+    pub spender_id: AccountId,
+    pub allowance: U128,
+    pub valid_until: Option<U64>,
+    pub single_use: bool,
+    pub expired: bool,
+}
+
+fn emit_approval(
+    owner_id: &AccountId,
+    spender_id: &AccountId,
+    allowance: Balance,
+    terms: &AllowanceTerms,
+) {
+    emit_event(
+        EVENT_VERSION,
+        "approval",
+        json!({
+            "owner_id": owner_id,
+            "spender_id": spender_id,
+            "allowance": U128(allowance),
+            "valid_until": terms.valid_until.map(U64),
+            "single_use": terms.single_use,
+        }),
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the allowance of `spender_id` over the caller's tokens to `amount`, until
+    /// `valid_until` and for a single use if given. The attached deposit covers the allowance
+    /// storage.
+    #[payable]
+    pub fn approve(
+        &mut self,
+        spender_id: AccountId,
+        amount: U128,
+        valid_until: Option<U64>,
+        single_use: Option<bool>,
+    ) {
+        let terms = AllowanceTerms {
+            valid_until: valid_until.map(|valid_until| valid_until.0),
+            single_use: single_use.unwrap_or(false),
+        };
+        assert!(
+            !terms.is_expired(),
+            "The allowance would already be expired"
+        );
+        self.internal_approve(spender_id, amount.0, terms);
+    }
+
+    /// Raises the allowance of `spender_id`, keeping its terms.
+    #[payable]
+    pub fn increase_allowance(&mut self, spender_id: AccountId, amount: U128) -> U128 {
+        let owner_id = env::predecessor_account_id();
+        let allowance = self.allowance(&owner_id, &spender_id) + amount.0;
+        let terms = self.allowance_index.terms(&owner_id, &spender_id);
+        assert!(
+            !terms.is_expired(),
+            "The allowance is expired, approve it again"
+        );
+        self.internal_approve(spender_id, allowance, terms);
+        allowance.into()
+    }
+
+    #[payable]
+    pub fn decrease_allowance(&mut self, spender_id: AccountId, amount: U128) -> U128 {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let allowance = self
+            .allowance(&owner_id, &spender_id)
+            .saturating_sub(amount.0);
+        self.internal_set_allowance(&owner_id, &spender_id, allowance);
+        allowance.into()
+    }
+
+    /// Moves `amount` from `owner_id` to `receiver_id` out of the caller's allowance.
+    #[payable]
+    pub fn transfer_from(
+        &mut self,
+        owner_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        let spender_id = env::predecessor_account_id();
+        self.use_allowance(&owner_id, &spender_id, amount.0);
+        self.internal_ft_transfer(&owner_id, receiver_id, amount.0, memo);
+    }
+
+    /// Burns `amount` of the balance of `owner_id` out of the caller's allowance.
+    #[payable]
+    pub fn burn_from(&mut self, owner_id: AccountId, amount: U128) {
+        assert_one_yocto();
+        self.assert_not_paused();
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        let spender_id = env::predecessor_account_id();
+        self.freezes.assert_not_frozen(&owner_id);
+        self.use_allowance(&owner_id, &spender_id, amount.0);
+        self.with_checkpoints(std::slice::from_ref(&owner_id), |this| {
+            this.token.internal_withdraw(&owner_id, amount.0)
+        });
+        FtBurn {
+            owner_id: &owner_id,
+            amount: &amount,
+            memo: Some("Burn from allowance"),
+        }
+        .emit();
+        self.notify_spend(&owner_id, None, amount.0);
+    }
+
+    /// Clears the caller's allowances of `spender_ids` and refunds their storage.
+    #[payable]
+    pub fn revoke_allowances(&mut self, spender_ids: Vec<AccountId>) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        for spender_id in spender_ids {
+            self.internal_set_allowance(&owner_id, &spender_id, 0);
+        }
+    }
+
+    pub fn ft_allowance(&self, owner_id: AccountId, spender_id: AccountId) -> U128 {
+        self.allowance(&owner_id, &spender_id).into()
+    }
+
+    /// Allowances granted by `owner_id`, expired ones included with an allowance of zero.
+    pub fn get_allowances(
+        &self,
+        owner_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<AllowanceInfo> {
+        let spender_ids = match self.allowance_index.spenders.get(&owner_id) {
+            Some(spender_ids) => spender_ids,
+            None => return vec![],
+        };
+        spender_ids
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .map(|spender_id| {
+                let terms = self.allowance_index.terms(&owner_id, spender_id);
+                AllowanceInfo {
+                    spender_id: spender_id.clone(),
+                    allowance: self.allowance(&owner_id, spender_id).into(),
+                    valid_until: terms.valid_until.map(U64),
+                    single_use: terms.single_use,
+                    expired: terms.is_expired(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Allowance of `spender_id` over the tokens of `owner_id`, zero once expired.
+    pub(crate) fn allowance(&self, owner_id: &AccountId, spender_id: &AccountId) -> Balance {
+        if self
+            .allowance_index
+            .terms(owner_id, spender_id)
+            .is_expired()
+        {
+            return 0;
+        }
+        self.allowances
+            .get(&(owner_id.clone(), spender_id.clone()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Takes `amount` out of the allowance of `spender_id`, all of it if it is single-use.
+    pub(crate) fn use_allowance(
+        &mut self,
+        owner_id: &AccountId,
+        spender_id: &AccountId,
+        amount: Balance,
+    ) {
+        let allowance = self.allowance(owner_id, spender_id);
+        assert!(
+            amount <= allowance,
+            "The allowance of @{} is {}",
+            spender_id,
+            allowance
+        );
+        let allowance = if self.allowance_index.terms(owner_id, spender_id).single_use {
+            0
+        } else {
+            allowance - amount
+        };
+        self.internal_set_allowance(owner_id, spender_id, allowance);
+    }
+
+    /// Sets the caller's allowance with new terms, charging the attached deposit for storage.
+    fn internal_approve(
+        &mut self,
+        spender_id: AccountId,
+        allowance: Balance,
+        terms: AllowanceTerms,
+    ) {
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let owner_id = env::predecessor_account_id();
+        assert_ne!(owner_id, spender_id, "Cannot approve yourself");
+        let initial_storage = env::storage_usage();
+        self.remove_expired_allowances(&owner_id);
+        if allowance > 0 {
+            let key = (owner_id.clone(), spender_id.clone());
+            if terms == AllowanceTerms::default() {
+                self.allowance_index.terms.remove(&key);
+            } else {
+                self.allowance_index.terms.insert(key, terms);
+            }
+        }
+        // Clearing the allowance refunds everything released, the expired allowances included.
+        self.internal_set_allowance(&owner_id, &spender_id, allowance);
+        if allowance > 0 {
+            refund_released_storage(&owner_id, initial_storage);
+        }
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+    }
+
+    fn remove_expired_allowances(&mut self, owner_id: &AccountId) {
+        let spender_ids = self
+            .allowance_index
+            .spenders
+            .get(owner_id)
+            .cloned()
+            .unwrap_or_default();
+        for spender_id in spender_ids {
+            if self
+                .allowance_index
+                .terms(owner_id, &spender_id)
+                .is_expired()
+            {
+                self.remove_allowance(owner_id, &spender_id);
+            }
+        }
+    }
+
+    fn remove_allowance(&mut self, owner_id: &AccountId, spender_id: &AccountId) {
+        let key = (owner_id.clone(), spender_id.clone());
+        self.allowances.remove(&key);
+        self.allowance_index.terms.remove(&key);
+        if let Some(spender_ids) = self.allowance_index.spenders.get_mut(owner_id) {
+            spender_ids.retain(|id| id != spender_id);
+            if spender_ids.is_empty() {
+                self.allowance_index.spenders.remove(owner_id);
+            }
+        }
+    }
+
+    pub(crate) fn internal_set_allowance(
+        &mut self,
+        owner_id: &AccountId,
+        spender_id: &AccountId,
+        allowance: Balance,
+    ) {
+        let key = (owner_id.clone(), spender_id.clone());
+        let terms = self.allowance_index.terms(owner_id, spender_id);
+        if allowance == 0 {
+            let initial_storage = env::storage_usage();
+            self.remove_allowance(owner_id, spender_id);
+            self.allowances.flush();
+            self.allowance_index.flush();
+            refund_released_storage(owner_id, initial_storage);
+        } else {
+            let mut spender_ids = self
+                .allowance_index
+                .spenders
+                .get(owner_id)
+                .cloned()
+                .unwrap_or_default();
+            if !spender_ids.contains(spender_id) {
+                assert!(
+                    spender_ids.len() < MAX_ALLOWANCES_PER_OWNER,
+                    "At most {} allowances per owner",
+                    MAX_ALLOWANCES_PER_OWNER
+                );
+                spender_ids.push(spender_id.clone());
+                self.allowance_index
+                    .spenders
+                    .insert(owner_id.clone(), spender_ids);
+            }
+            self.allowances.insert(key, allowance);
+            self.allowances.flush();
+            self.allowance_index.flush();
+        }
+        emit_approval(owner_id, spender_id, allowance, &terms);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(2)]);
+        contract.approve(accounts(1), U128(1_000), None, None);
+        call_as(&mut context, accounts(1), 1);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_transfer_from_uses_allowance() {
+        let (_, mut contract) = setup();
+        let owner = owner();
+        contract.transfer_from(owner.clone(), accounts(2), U128(600), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 600);
+        assert_eq!(contract.ft_allowance(owner, accounts(1)).0, 400);
+    }
+
+    #[test]
+    fn test_burn_from_uses_allowance() {
+        let (_, mut contract) = setup();
+        let owner = owner();
+        contract.burn_from(owner.clone(), U128(600));
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 600);
+        assert_eq!(contract.ft_allowance(owner, accounts(1)).0, 400);
+    }
+
+    #[test]
+    fn test_single_use_and_expired_allowances() {
+        let (mut context, mut contract) = setup();
+        let owner = owner();
+        testing_env!(context
+            .predecessor_account_id(owner.clone())
+            .attached_deposit(10_000_000_000_000_000_000_000)
+            .build());
+        contract.approve(accounts(2), U128(500), None, Some(true));
+        contract.approve(accounts(3), U128(500), Some(U64(100)), None);
+        assert_eq!(contract.get_allowances(owner.clone(), None, None).len(), 3);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.transfer_from(owner.clone(), accounts(2), U128(100), None);
+        assert_eq!(contract.ft_allowance(owner.clone(), accounts(2)).0, 0);
+
+        testing_env!(context.block_timestamp(100).build());
+        assert_eq!(contract.ft_allowance(owner.clone(), accounts(3)).0, 0);
+        let allowances = contract.get_allowances(owner.clone(), None, None);
+        assert!(allowances[1].expired);
+
+        testing_env!(context.predecessor_account_id(owner.clone()).build());
+        contract.revoke_allowances(vec![accounts(1), accounts(3)]);
+        assert!(contract.get_allowances(owner, None, None).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "The allowance of @bob is 1000")]
+    fn test_transfer_from_above_allowance() {
+        let (_, mut contract) = setup();
+        contract.transfer_from(owner(), accounts(2), U128(1_001), None);
+    }
+}
@@ -0,0 +1,243 @@
+/*!
+Archival of small inactive accounts into Merkle-committed batches.
+NOTES:
+  - Like dust sweeping, for the long tail of tiny holders, but the tokens stay theirs. The owner
+    archives a batch of accounts with `archive_inactive_accounts`. Accounts that hold more than
+    zero and at most the threshold of `set_archive_threshold`, and have been inactive for
+    `min_inactive_epochs` as recorded by `dust_sweeping`, are unregistered and their balances
+    committed to the Merkle root of the batch; only the root is kept. Other accounts in the
+    batch are skipped. The owner account, the contract account and stakers are never archived.
+  - Leaves and proofs follow `balance_proofs`, over the archived accounts sorted by account ID.
+    The batch is logged as an NEP-297 event with every archived balance, so indexers can serve
+    the proofs.
+  - Anyone can restore an archived account with `unarchive` and its proof. The account is
+    registered again if needed and gets its balance back. The storage deposit of an archived
+    account stays with the contract and pays for its restoration.
+  - Archived balances still count in the total supply, `get_archived_balance` reports them.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::{LookupSet, Vector};
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, CryptoHash};
+
+use crate::balance_proofs::{leaf_hash, merkle_root, root_from_proof, ProofStep};
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const MAX_ARCHIVED_ACCOUNTS: usize = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ArchiveBatch {
+    pub root: Base58CryptoHash,
+    pub accounts: u32,
+    pub total: U128,
+    pub epoch_height: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Archive {
+    pub batches: Vector<ArchiveBatch>,
+    /// Accounts restored from each batch.
+    pub restored: LookupSet<(u32, AccountId)>,
+    /// Tokens in archived accounts not restored yet.
+    pub archived: Balance,
+    /// Largest balance archived with an account.
+    pub threshold: Balance,
+}
+
+impl Archive {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            batches: Vector::new([prefix.as_slice(), b"b"].concat()),
+            restored: LookupSet::new([prefix.as_slice(), b"r"].concat()),
+            archived: 0,
+            threshold: 0,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_archive_threshold(&mut self, threshold: U128) {
+        self.assert_owner();
+        self.archive.threshold = threshold.0;
+    }
+
+    /// Archives the given accounts that hold at most the archive threshold and have been
+    /// inactive for at least `min_inactive_epochs`. Returns the batch id, or `None` if no
+    /// account could be archived.
+    pub fn archive_inactive_accounts(
+        &mut self,
+        account_ids: Vec<AccountId>,
+        min_inactive_epochs: u64,
+    ) -> Option<u32> {
+        self.assert_owner();
+        assert!(
+            account_ids.len() <= MAX_ARCHIVED_ACCOUNTS,
+            "At most {} accounts can be archived at once",
+            MAX_ARCHIVED_ACCOUNTS
+        );
+        let mut account_ids = account_ids;
+        account_ids.sort();
+        account_ids.dedup();
+        let mut entries: Vec<(AccountId, Balance)> = vec![];
+        for account_id in account_ids {
+            let inactive_epochs =
+                env::epoch_height().saturating_sub(self.dust_sweeping.last_active(&account_id));
+            if inactive_epochs < min_inactive_epochs {
+                continue;
+            }
+            if let Some(balance) = self.archivable_balance(&account_id) {
+                self.internal_archive_account(&account_id, balance);
+                entries.push((account_id, balance));
+            }
+        }
+        if entries.is_empty() {
+            return None;
+        }
+        let leaves = entries
+            .iter()
+            .map(|(account_id, balance)| leaf_hash(account_id, *balance))
+            .collect();
+        let (root, _) = merkle_root(leaves, 0);
+        let total: Balance = entries.iter().map(|(_, balance)| balance).sum();
+        let batch_id = self.archive.batches.len();
+        self.archive.batches.push(ArchiveBatch {
+            root: root.into(),
+            accounts: entries.len() as u32,
+            total: total.into(),
+            epoch_height: env::epoch_height().into(),
+        });
+        self.archive.archived += total;
+        emit_event(
+            EVENT_VERSION,
+            "accounts_archived",
+            json!({
+                "batch_id": batch_id,
+                "root": Base58CryptoHash::from(root),
+                "accounts": entries
+                    .iter()
+                    .map(|(account_id, balance)| json!({
+                        "account_id": account_id,
+                        "balance": U128(*balance),
+                    }))
+                    .collect::<Vec<_>>(),
+            }),
+        );
+        Some(batch_id)
+    }
+
+    /// Restores `balance` to `account_id` from the batch `batch_id` given the Merkle proof of
+    /// the pair.
+    pub fn unarchive(
+        &mut self,
+        batch_id: u32,
+        account_id: AccountId,
+        balance: U128,
+        proof: Vec<ProofStep>,
+    ) {
+        let batch = self
+            .archive
+            .batches
+            .get(batch_id)
+            .cloned()
+            .expect("No archive batch found");
+        let leaf = leaf_hash(&account_id, balance.0);
+        assert!(
+            root_from_proof(leaf, &proof) == CryptoHash::from(batch.root),
+            "Invalid proof"
+        );
+        assert!(
+            self.archive.restored.insert((batch_id, account_id.clone())),
+            "The account was already restored from this batch"
+        );
+        if !self.token.accounts.contains_key(&account_id) {
+            self.internal_register_account(&account_id);
+        }
+        let previous = self.token.accounts.get(&account_id).unwrap();
+        self.token
+            .accounts
+            .insert(&account_id, &(previous + balance.0));
+        self.archive.archived -= balance.0;
+        self.record_balance_change(&account_id, previous, previous + balance.0);
+        log!(
+            "Restored {} to @{} from archive batch {}",
+            balance.0,
+            account_id,
+            batch_id
+        );
+    }
+
+    pub fn get_archive_batch(&self, batch_id: u32) -> Option<ArchiveBatch> {
+        self.archive.batches.get(batch_id).cloned()
+    }
+
+    pub fn get_archived_balance(&self) -> U128 {
+        self.archive.archived.into()
+    }
+}
+
+impl Contract {
+    /// The balance of `account_id` if it can be archived.
+    fn archivable_balance(&self, account_id: &AccountId) -> Option<Balance> {
+        if account_id == &self.owner_id
+            || account_id == &env::current_account_id()
+            || self.staking.accounts.contains_key(account_id)
+        {
+            return None;
+        }
+        self.token
+            .accounts
+            .get(account_id)
+            .filter(|balance| *balance > 0 && *balance <= self.archive.threshold)
+    }
+
+    /// Unregisters `account_id` without refunding its storage, keeping `balance` in the supply.
+    fn internal_archive_account(&mut self, account_id: &AccountId, balance: Balance) {
+        self.token.accounts.remove(account_id);
+        self.registered_accounts.remove(account_id);
+        if let Some(top_up) = self.storage_grace.top_ups.remove(account_id) {
+            self.rescue.release(top_up);
+        }
+        self.dust_sweeping.last_active.remove(account_id);
+        self.transfer_limits.usage.remove(account_id);
+        self.record_balance_change(account_id, balance, 0);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_archive_and_unarchive() {
+        let (mut context, mut contract) = setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner(), 1);
+        contract.ft_transfer(accounts(1), U128(5), None);
+        contract.ft_transfer(accounts(2), U128(7), None);
+        contract.set_archive_threshold(U128(10));
+
+        testing_env!(context.epoch_height(10).build());
+        let batch_id = contract
+            .archive_inactive_accounts(vec![accounts(2), accounts(1)], 5)
+            .unwrap();
+        assert!(contract.storage_balance_of(accounts(1)).is_none());
+        assert_eq!(contract.get_archived_balance().0, 12);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
+
+        let leaves = vec![leaf_hash(&accounts(1), 5), leaf_hash(&accounts(2), 7)];
+        let (_, proof) = merkle_root(leaves, 1);
+        contract.unarchive(batch_id, accounts(2), U128(7), proof);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 7);
+        assert_eq!(contract.get_archived_balance().0, 5);
+    }
+}
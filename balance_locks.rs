@@ -0,0 +1,296 @@
+/*!
+Locks on part of an account balance, without moving the tokens.
+NOTES:
+  - The owner or the DAO approves locker contracts, e.g. a game holding collateral. A locker
+    locks an amount of an account out of the account's allowance to it, under a `lock_id` of its
+    choosing, and unlocks it by id. Lock ids are per locker.
+  - Locked tokens stay on the account and keep counting for votes and dividends, but the account
+    cannot spend them: every balance change that would drop an account below its locked total
+    fails, whatever the path. An `ft_transfer_call` refund is capped by what the receiver has
+    unlocked.
+  - If a locker is removed, the accounts it locked can release its locks themselves.
+  - The locker pays for the storage of its locks and gets it back on unlock.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::{LookupMap, UnorderedSet};
+use near_sdk::{env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const MAX_LOCKS_PER_ACCOUNT: usize = 20;
+const MAX_LOCK_ID_LEN: usize = 64;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BalanceLock {
+    pub locker_id: AccountId,
+    pub lock_id: String,
+    pub amount: U128,
+    pub locked_at: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+pub struct AccountLocks {
+    pub total: Balance,
+    pub locks: Vec<BalanceLock>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct BalanceLocks {
+    pub lockers: UnorderedSet<AccountId>,
+    pub accounts: LookupMap<AccountId, AccountLocks>,
+    /// Account of each lock, by locker and lock id.
+    pub lock_accounts: LookupMap<(AccountId, String), AccountId>,
+}
+
+impl BalanceLocks {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            lockers: UnorderedSet::new([prefix.as_slice(), b"l"].concat()),
+            accounts: LookupMap::new([prefix.as_slice(), b"a"].concat()),
+            lock_accounts: LookupMap::new([prefix.as_slice(), b"i"].concat()),
+        }
+    }
+
+    pub fn locked(&self, account_id: &AccountId) -> Balance {
+        self.accounts
+            .get(account_id)
+            .map_or(0, |account| account.total)
+    }
+
+    /// Panics if `account_id` holding `balance` would not cover its locks.
+    pub fn assert_covered(&self, account_id: &AccountId, balance: Balance) {
+        let locked = self.locked(account_id);
+        assert!(
+            balance >= locked,
+            "The account @{} has {} locked",
+            account_id,
+            locked
+        );
+    }
+
+    fn remove(&mut self, locker_id: &AccountId, lock_id: &str) -> BalanceLock {
+        let account_id = self
+            .lock_accounts
+            .remove(&(locker_id.clone(), lock_id.to_string()))
+            .unwrap_or_else(|| env::panic_str("Unknown lock"));
+        let mut account = self.accounts.get(&account_id).cloned().unwrap();
+        let index = account
+            .locks
+            .iter()
+            .position(|lock| &lock.locker_id == locker_id && lock.lock_id == lock_id)
+            .unwrap();
+        let lock = account.locks.swap_remove(index);
+        account.total -= lock.amount.0;
+        if account.locks.is_empty() {
+            self.accounts.remove(&account_id);
+        } else {
+            self.accounts.insert(account_id, account);
+        }
+        lock
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn add_locker(&mut self, locker_id: AccountId) {
+        self.assert_owner_or_dao();
+        self.balance_locks.lockers.insert(locker_id.clone());
+        log!("@{} can lock balances", locker_id);
+    }
+
+    pub fn remove_locker(&mut self, locker_id: AccountId) {
+        self.assert_owner_or_dao();
+        assert!(
+            self.balance_locks.lockers.remove(&locker_id),
+            "Not a locker"
+        );
+        log!("@{} can no longer lock balances", locker_id);
+    }
+
+    /// Locks `amount` of `account_id` under `lock_id`, out of the account's allowance to the
+    /// caller. The attached deposit covers the storage of the lock.
+    #[payable]
+    pub fn lock(&mut self, account_id: AccountId, amount: U128, lock_id: String) {
+        let locker_id = env::predecessor_account_id();
+        assert!(
+            self.balance_locks.lockers.contains(&locker_id),
+            "Only approved lockers can lock balances"
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        assert!(
+            !lock_id.is_empty() && lock_id.len() <= MAX_LOCK_ID_LEN,
+            "A lock id has 1 to {} bytes",
+            MAX_LOCK_ID_LEN
+        );
+        let key = (locker_id.clone(), lock_id.clone());
+        assert!(
+            !self.balance_locks.lock_accounts.contains_key(&key),
+            "The lock id is taken"
+        );
+        let mut account = self
+            .balance_locks
+            .accounts
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default();
+        assert!(
+            account.locks.len() < MAX_LOCKS_PER_ACCOUNT,
+            "At most {} locks per account",
+            MAX_LOCKS_PER_ACCOUNT
+        );
+        let balance = self.token.ft_balance_of(account_id.clone()).0;
+        assert!(
+            account.total + amount.0 <= balance,
+            "The account @{} has {} unlocked",
+            account_id,
+            balance - account.total
+        );
+        self.use_allowance(&account_id, &locker_id, amount.0);
+
+        let initial_storage = env::storage_usage();
+        account.total += amount.0;
+        account.locks.push(BalanceLock {
+            locker_id: locker_id.clone(),
+            lock_id: lock_id.clone(),
+            amount,
+            locked_at: env::block_timestamp().into(),
+        });
+        self.balance_locks
+            .accounts
+            .insert(account_id.clone(), account);
+        self.balance_locks
+            .lock_accounts
+            .insert(key, account_id.clone());
+        self.balance_locks.accounts.flush();
+        self.balance_locks.lock_accounts.flush();
+        refund_deposit(env::storage_usage() - initial_storage);
+        log!(
+            "@{} locked {} of @{} as {}",
+            locker_id,
+            amount.0,
+            account_id,
+            lock_id
+        );
+    }
+
+    /// Releases the caller's lock `lock_id`.
+    pub fn unlock(&mut self, lock_id: String) -> U128 {
+        let locker_id = env::predecessor_account_id();
+        self.internal_release_lock(&locker_id, &lock_id, &locker_id)
+    }
+
+    /// Releases a lock on the caller's balance held by a locker that was removed.
+    pub fn release_orphaned_lock(&mut self, locker_id: AccountId, lock_id: String) -> U128 {
+        assert!(
+            !self.balance_locks.lockers.contains(&locker_id),
+            "The locker is still approved"
+        );
+        let account_id = env::predecessor_account_id();
+        assert_eq!(
+            self.balance_locks
+                .lock_accounts
+                .get(&(locker_id.clone(), lock_id.clone())),
+            Some(&account_id),
+            "Not a lock on your balance"
+        );
+        self.internal_release_lock(&locker_id, &lock_id, &account_id)
+    }
+
+    pub fn get_locks(&self, account_id: AccountId) -> Vec<BalanceLock> {
+        self.balance_locks
+            .accounts
+            .get(&account_id)
+            .map_or(vec![], |account| account.locks.clone())
+    }
+
+    pub fn get_locked_balance(&self, account_id: AccountId) -> U128 {
+        self.balance_locks.locked(&account_id).into()
+    }
+
+    /// Balance of `account_id` that is not locked.
+    pub fn get_transferable_balance(&self, account_id: AccountId) -> U128 {
+        let balance = self.token.ft_balance_of(account_id.clone()).0;
+        balance
+            .saturating_sub(self.balance_locks.locked(&account_id))
+            .into()
+    }
+
+    pub fn get_lockers(&self) -> Vec<AccountId> {
+        self.balance_locks.lockers.iter().cloned().collect()
+    }
+}
+
+impl Contract {
+    /// Removes a lock and refunds its storage to the locker.
+    fn internal_release_lock(
+        &mut self,
+        locker_id: &AccountId,
+        lock_id: &str,
+        released_by: &AccountId,
+    ) -> U128 {
+        let initial_storage = env::storage_usage();
+        let lock = self.balance_locks.remove(locker_id, lock_id);
+        self.balance_locks.accounts.flush();
+        self.balance_locks.lock_accounts.flush();
+        refund_released_storage(locker_id, initial_storage);
+        log!(
+            "@{} released lock {} of @{} on {}",
+            released_by,
+            lock_id,
+            locker_id,
+            lock.amount.0
+        );
+        lock.amount
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        contract.add_locker(accounts(3));
+        contract.approve(accounts(3), U128(1_000), None, None);
+        call_as(&mut context, accounts(3), ONE_NEAR);
+        contract.lock(owner(), U128(1_000), "match-1".to_string());
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "has 1000 locked")]
+    fn test_locked_tokens_cannot_move() {
+        let (mut context, mut contract) = setup();
+        let owner = owner();
+        assert_eq!(
+            contract.get_transferable_balance(owner.clone()).0,
+            TOTAL_SUPPLY - 1_000
+        );
+        testing_env!(context
+            .predecessor_account_id(owner)
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(1), U128(TOTAL_SUPPLY - 999), None);
+    }
+
+    #[test]
+    fn test_unlock_frees_tokens() {
+        let (mut context, mut contract) = setup();
+        let owner = owner();
+        assert_eq!(contract.unlock("match-1".to_string()).0, 1_000);
+        assert!(contract.get_locks(owner.clone()).is_empty());
+        testing_env!(context
+            .predecessor_account_id(owner)
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(1), U128(TOTAL_SUPPLY), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY);
+    }
+}
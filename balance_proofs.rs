@@ -0,0 +1,236 @@
+/*!
+Balance snapshots committed as Merkle roots, with per-account inclusion proofs.
+NOTES:
+  - The owner creates a snapshot of the given accounts at the current block. The token does not
+    enumerate its holders, so the caller supplies the account list; large holder sets must be
+    split across several snapshots to fit into the gas limit.
+  - Leaves are `sha256(0x00 || borsh(account_id, balance))` in the order of sorted account ids,
+    inner nodes are `sha256(0x01 || left || right)`. The prefixes keep leaves and nodes apart.
+    A node without a sibling is promoted to the next level unchanged.
+  - A proof lists the sibling hashes from the leaf up, each with the side it is on, so it can be
+    verified without knowing the tree size.
+  - The owner pays for the snapshot storage.
+*/
+use std::convert::TryFrom;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::{LookupMap, Vector};
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, CryptoHash};
+
+use crate::*;
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BalanceSnapshot {
+    pub block_height: U64,
+    pub root: Base58CryptoHash,
+    pub accounts: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProofStep {
+    pub hash: Base58CryptoHash,
+    /// Whether the sibling is the left operand of the parent hash.
+    pub is_left: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BalanceProof {
+    pub snapshot_id: U64,
+    pub account_id: AccountId,
+    pub balance: U128,
+    pub root: Base58CryptoHash,
+    pub path: Vec<ProofStep>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct BalanceProofs {
+    pub snapshots: Vector<BalanceSnapshot>,
+    /// Sorted `(account_id, balance)` pairs of each snapshot.
+    pub entries: LookupMap<u32, Vec<(AccountId, Balance)>>,
+}
+
+impl BalanceProofs {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            snapshots: Vector::new([prefix.as_slice(), b"s"].concat()),
+            entries: LookupMap::new([prefix.as_slice(), b"e"].concat()),
+        }
+    }
+}
+
+fn sha256(data: &[u8]) -> CryptoHash {
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(data));
+    hash
+}
+
+pub fn leaf_hash(account_id: &AccountId, balance: Balance) -> CryptoHash {
+    let mut data = vec![LEAF_PREFIX];
+    data.extend((account_id, balance).try_to_vec().unwrap());
+    sha256(&data)
+}
+
+pub fn node_hash(left: &CryptoHash, right: &CryptoHash) -> CryptoHash {
+    sha256(&[&[NODE_PREFIX][..], left, right].concat())
+}
+
+/// Computes the root that `path` proves `leaf` to be part of.
+pub fn root_from_proof(leaf: CryptoHash, path: &[ProofStep]) -> CryptoHash {
+    path.iter().fold(leaf, |hash, step| {
+        let sibling: CryptoHash = step.hash.into();
+        if step.is_left {
+            node_hash(&sibling, &hash)
+        } else {
+            node_hash(&hash, &sibling)
+        }
+    })
+}
+
+/// Computes the root over `leaves`, collecting the proof path of the leaf at `index`.
+pub(crate) fn merkle_root(
+    mut level: Vec<CryptoHash>,
+    mut index: usize,
+) -> (CryptoHash, Vec<ProofStep>) {
+    let mut path = vec![];
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            path.push(ProofStep {
+                hash: level[sibling].into(),
+                is_left: sibling < index,
+            });
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node_hash(left, right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+        index /= 2;
+    }
+    (level[0], path)
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Commits the current balances of `account_ids` to a Merkle root and returns the snapshot id.
+    /// The attached deposit covers the snapshot storage.
+    #[payable]
+    pub fn create_balance_snapshot(&mut self, account_ids: Vec<AccountId>) -> U64 {
+        self.assert_owner();
+        let mut account_ids = account_ids;
+        account_ids.sort();
+        account_ids.dedup();
+        assert!(!account_ids.is_empty(), "Snapshot must include accounts");
+        let initial_storage = env::storage_usage();
+        let entries: Vec<(AccountId, Balance)> = account_ids
+            .into_iter()
+            .map(|account_id| {
+                let balance = self.token.accounts.get(&account_id).unwrap_or(0);
+                (account_id, balance)
+            })
+            .collect();
+        let leaves = entries
+            .iter()
+            .map(|(account_id, balance)| leaf_hash(account_id, *balance))
+            .collect();
+        let (root, _) = merkle_root(leaves, 0);
+        let snapshot_id = self.balance_proofs.snapshots.len();
+        self.balance_proofs.snapshots.push(BalanceSnapshot {
+            block_height: env::block_height().into(),
+            root: root.into(),
+            accounts: entries.len() as u32,
+        });
+        self.balance_proofs.entries.insert(snapshot_id, entries);
+        self.balance_proofs.snapshots.flush();
+        self.balance_proofs.entries.flush();
+        refund_deposit(env::storage_usage() - initial_storage);
+        log!(
+            "Balance snapshot {} with root {}",
+            snapshot_id,
+            String::from(&Base58CryptoHash::from(root))
+        );
+        U64(snapshot_id.into())
+    }
+
+    pub fn get_balance_snapshot(&self, snapshot_id: U64) -> Option<BalanceSnapshot> {
+        let snapshot_id = u32::try_from(snapshot_id.0).ok()?;
+        self.balance_proofs.snapshots.get(snapshot_id).cloned()
+    }
+
+    /// Returns the balance of `account_id` in the snapshot with its Merkle proof, or `None` if
+    /// the account is not part of the snapshot.
+    pub fn get_balance_proof(
+        &self,
+        snapshot_id: U64,
+        account_id: AccountId,
+    ) -> Option<BalanceProof> {
+        let id = u32::try_from(snapshot_id.0).ok()?;
+        let snapshot = self.balance_proofs.snapshots.get(id)?;
+        let entries = self.balance_proofs.entries.get(&id)?;
+        let index = entries
+            .binary_search_by(|(entry_id, _)| entry_id.cmp(&account_id))
+            .ok()?;
+        let leaves = entries
+            .iter()
+            .map(|(account_id, balance)| leaf_hash(account_id, *balance))
+            .collect();
+        let (_, path) = merkle_root(leaves, index);
+        Some(BalanceProof {
+            snapshot_id,
+            balance: entries[index].1.into(),
+            account_id,
+            root: snapshot.root,
+            path,
+        })
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{owner, setup};
+
+    fn verify(proof: &BalanceProof) -> bool {
+        let leaf = leaf_hash(&proof.account_id, proof.balance.0);
+        root_from_proof(leaf, &proof.path) == CryptoHash::from(proof.root)
+    }
+
+    #[test]
+    fn test_balance_proofs_verify() {
+        let owner = owner();
+        let (_, mut contract) = setup(&[]);
+        let account_ids = vec![
+            owner.clone(),
+            accounts(1),
+            accounts(2),
+            accounts(3),
+            accounts(4),
+        ];
+        let snapshot_id = contract.create_balance_snapshot(account_ids.clone());
+
+        for account_id in account_ids {
+            let proof = contract
+                .get_balance_proof(snapshot_id, account_id.clone())
+                .unwrap();
+            assert!(verify(&proof));
+        }
+        let proof = contract.get_balance_proof(snapshot_id, owner).unwrap();
+        assert_eq!(proof.balance.0, TOTAL_SUPPLY);
+        assert!(contract
+            .get_balance_proof(snapshot_id, accounts(5))
+            .is_none());
+    }
+}
@@ -0,0 +1,56 @@
+/*!
+Batch views for wallets and dashboards.
+NOTES:
+  - Each view answers for many accounts in one RPC call, in the order of `account_ids`, with the
+    same values as the single-account view.
+*/
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::storage_management::{StorageBalance, StorageManagement};
+use near_sdk::json_types::U128;
+use near_sdk::{near_bindgen, AccountId};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn ft_balance_of_many(&self, account_ids: Vec<AccountId>) -> Vec<U128> {
+        account_ids
+            .into_iter()
+            .map(|account_id| self.ft_balance_of(account_id))
+            .collect()
+    }
+
+    pub fn storage_balance_of_many(
+        &self,
+        account_ids: Vec<AccountId>,
+    ) -> Vec<Option<StorageBalance>> {
+        account_ids
+            .into_iter()
+            .map(|account_id| self.storage_balance_of(account_id))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{owner, setup};
+
+    #[test]
+    fn test_batch_views() {
+        let owner = owner();
+        let (_, contract) = setup(&[]);
+        assert_eq!(
+            contract.ft_balance_of_many(vec![owner.clone(), accounts(1)]),
+            vec![U128(TOTAL_SUPPLY), U128(0)]
+        );
+        let storage = contract.storage_balance_of_many(vec![accounts(1), owner]);
+        assert!(storage[0].is_none());
+        assert_eq!(
+            storage[1].as_ref().unwrap().total,
+            contract.storage_balance_bounds().min
+        );
+    }
+}
@@ -0,0 +1,314 @@
+/*!
+Continuous minting and redemption of ASTRO for NEAR along a bonding curve.
+NOTES:
+  - Optional: nothing happens until the owner or the DAO sets a curve. The price of a whole
+    token rises linearly with the supply minted through the curve, from `base_price` by `slope`
+    yoctoNEAR per whole token minted. The shape can only change while no curve tokens are out.
+  - `curve_mint` mints `amount` for the price of the area under the curve, refunding the NEAR
+    attached above it, and fails if that is above `max_cost`. The reserve keeps
+    `reserve_ratio_bps` of the price; the rest are proceeds the owner or the DAO can withdraw.
+  - `curve_redeem` burns `amount` and pays its share of the reserve, by the area under the curve,
+    and fails if that is below `min_proceeds`. Paying by share keeps the reserve solvent whatever
+    the reserve ratio was at each mint, so the owner can change the ratio at any time; it applies
+    to later mints. Only as many tokens as were minted through the curve can be redeemed.
+  - Curve mints count against the emission schedule. Retiring the module stops mints; redemptions
+    keep working.
+  - The reserve and the proceeds are not idle NEAR, so neither liquid staking nor `rescue_near`
+    can use them.
+*/
+use near_contract_standards::fungible_token::events::FtBurn;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, Balance, Promise};
+
+use crate::*;
+
+const MAX_BPS: u16 = 10_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CurveConfig {
+    /// Price in yoctoNEAR of one whole ASTRO when no curve tokens are out.
+    pub base_price: U128,
+    /// Price increase in yoctoNEAR per whole ASTRO minted through the curve.
+    pub slope: U128,
+    pub reserve_ratio_bps: u16,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct BondingCurve {
+    pub config: Option<CurveConfig>,
+    /// Tokens minted through the curve and not redeemed.
+    pub supply: Balance,
+    pub reserve: Balance,
+    pub proceeds: Balance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BondingCurveView {
+    pub config: Option<CurveConfig>,
+    pub supply: U128,
+    pub reserve: U128,
+    pub proceeds: U128,
+    pub spot_price: U128,
+}
+
+impl BondingCurve {
+    /// NEAR held for the curve.
+    pub fn held(&self) -> Balance {
+        self.reserve + self.proceeds
+    }
+
+    /// Area under the curve between supplies `from` and `to`, in yoctoNEAR.
+    fn area(&self, from: Balance, to: Balance, one_token: Balance) -> Balance {
+        let config = self.config.as_ref().expect("No bonding curve is set");
+        let amount = to - from;
+        mul_div(amount, config.base_price.0, one_token)
+            + mul_div(
+                mul_div(amount, from + to, one_token),
+                config.slope.0,
+                2 * one_token,
+            )
+    }
+
+    fn buy_price(&self, amount: Balance, one_token: Balance) -> Balance {
+        self.area(self.supply, self.supply + amount, one_token)
+    }
+
+    fn sell_price(&self, amount: Balance, one_token: Balance) -> Balance {
+        assert!(
+            amount <= self.supply,
+            "Only {} tokens can be redeemed",
+            self.supply
+        );
+        if amount == 0 {
+            return 0;
+        }
+        mul_div(
+            self.area(self.supply - amount, self.supply, one_token),
+            self.reserve,
+            self.area(0, self.supply, one_token),
+        )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_bonding_curve(&mut self, base_price: U128, slope: U128, reserve_ratio_bps: u16) {
+        self.assert_owner_or_dao();
+        assert_eq!(
+            self.bonding_curve.supply, 0,
+            "The curve can only change while no curve tokens are out"
+        );
+        assert!(base_price.0 > 0, "The base price should be positive");
+        assert_valid_reserve_ratio(reserve_ratio_bps);
+        self.bonding_curve.config = Some(CurveConfig {
+            base_price,
+            slope,
+            reserve_ratio_bps,
+        });
+        log!(
+            "Bonding curve from {} yoctoNEAR, slope {}, reserve ratio {} bps",
+            base_price.0,
+            slope.0,
+            reserve_ratio_bps
+        );
+    }
+
+    pub fn set_reserve_ratio(&mut self, reserve_ratio_bps: u16) {
+        self.assert_owner_or_dao();
+        assert_valid_reserve_ratio(reserve_ratio_bps);
+        let config = self
+            .bonding_curve
+            .config
+            .as_mut()
+            .expect("No bonding curve is set");
+        config.reserve_ratio_bps = reserve_ratio_bps;
+        log!(
+            "Bonding curve reserve ratio set to {} bps",
+            reserve_ratio_bps
+        );
+    }
+
+    /// Mints `amount` on the curve for the attached NEAR, at most `max_cost` of it.
+    #[payable]
+    pub fn curve_mint(&mut self, amount: U128, max_cost: U128) -> U128 {
+        self.assert_module_active(Module::BondingCurve);
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.token.accounts.contains_key(&account_id),
+            "The account {} is not registered",
+            account_id
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        let cost = self.bonding_curve.buy_price(amount.0, self.one_token());
+        assert!(
+            cost <= max_cost.0,
+            "The cost of {} is above the maximum of {}",
+            cost,
+            max_cost.0
+        );
+        let deposit = env::attached_deposit();
+        assert!(deposit >= cost, "Must attach at least {} yoctoNEAR", cost);
+        let ratio_bps = self
+            .bonding_curve
+            .config
+            .as_ref()
+            .unwrap()
+            .reserve_ratio_bps;
+        let reserved = mul_div(cost, u128::from(ratio_bps), u128::from(MAX_BPS));
+        self.bonding_curve.reserve += reserved;
+        self.bonding_curve.proceeds += cost - reserved;
+        self.bonding_curve.supply += amount.0;
+        self.internal_mint(&env::current_account_id(), &account_id, amount.0);
+        if deposit > cost {
+            Promise::new(account_id.clone()).transfer(deposit - cost);
+        }
+        log!(
+            "@{} minted {} on the bonding curve for {} yoctoNEAR",
+            account_id,
+            amount.0,
+            cost
+        );
+        cost.into()
+    }
+
+    /// Burns `amount` of the caller's tokens for their share of the reserve, at least
+    /// `min_proceeds`.
+    #[payable]
+    pub fn curve_redeem(&mut self, amount: U128, min_proceeds: U128) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        let proceeds = self.bonding_curve.sell_price(amount.0, self.one_token());
+        assert!(
+            proceeds >= min_proceeds.0,
+            "The proceeds of {} are below the minimum of {}",
+            proceeds,
+            min_proceeds.0
+        );
+        self.bonding_curve.supply -= amount.0;
+        self.bonding_curve.reserve -= proceeds;
+        self.with_checkpoints(std::slice::from_ref(&account_id), |this| {
+            this.token.internal_withdraw(&account_id, amount.0)
+        });
+        FtBurn {
+            owner_id: &account_id,
+            amount: &amount,
+            memo: Some("Bonding curve redemption"),
+        }
+        .emit();
+        if proceeds > 0 {
+            Promise::new(account_id.clone()).transfer(proceeds);
+        }
+        log!(
+            "@{} redeemed {} on the bonding curve for {} yoctoNEAR",
+            account_id,
+            amount.0,
+            proceeds
+        );
+        proceeds.into()
+    }
+
+    /// Sends the curve proceeds outside the reserve to the caller.
+    pub fn withdraw_curve_proceeds(&mut self) -> U128 {
+        self.assert_owner_or_dao();
+        let proceeds = std::mem::take(&mut self.bonding_curve.proceeds);
+        if proceeds > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(proceeds);
+        }
+        log!("Withdrew {} yoctoNEAR of curve proceeds", proceeds);
+        proceeds.into()
+    }
+
+    /// NEAR it costs to mint `amount` on the curve now.
+    pub fn buy_price(&self, amount: U128) -> U128 {
+        self.bonding_curve
+            .buy_price(amount.0, self.one_token())
+            .into()
+    }
+
+    /// NEAR redeeming `amount` on the curve pays now.
+    pub fn sell_price(&self, amount: U128) -> U128 {
+        self.bonding_curve
+            .sell_price(amount.0, self.one_token())
+            .into()
+    }
+
+    pub fn get_bonding_curve(&self) -> BondingCurveView {
+        let curve = &self.bonding_curve;
+        let spot_price = curve.config.as_ref().map_or(0, |config| {
+            config.base_price.0 + mul_div(curve.supply, config.slope.0, self.one_token())
+        });
+        BondingCurveView {
+            config: curve.config.clone(),
+            supply: curve.supply.into(),
+            reserve: curve.reserve.into(),
+            proceeds: curve.proceeds.into(),
+            spot_price: spot_price.into(),
+        }
+    }
+}
+
+fn assert_valid_reserve_ratio(reserve_ratio_bps: u16) {
+    assert!(
+        reserve_ratio_bps > 0 && reserve_ratio_bps <= MAX_BPS,
+        "The reserve ratio is 1 to {} bps",
+        MAX_BPS
+    );
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, owner, ONE_NEAR};
+
+    const ONE_ASTRO: Balance = 1_000_000_000_000_000_000;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = test_fixtures::setup(&[]);
+        contract.set_bonding_curve(U128(ONE_NEAR / 100), U128(ONE_NEAR / 100), 5_000);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_mint_and_redeem() {
+        let (mut context, mut contract) = setup();
+        let owner = owner();
+        // 10 * 0.01 + 0.01 * 10^2 / 2
+        assert_eq!(
+            contract.buy_price(U128(10 * ONE_ASTRO)).0,
+            ONE_NEAR * 6 / 10
+        );
+        contract.curve_mint(U128(10 * ONE_ASTRO), U128(ONE_NEAR));
+        assert_eq!(
+            contract.ft_balance_of(owner.clone()).0,
+            TOTAL_SUPPLY + 10 * ONE_ASTRO
+        );
+        let curve = contract.get_bonding_curve();
+        assert_eq!(curve.reserve.0, ONE_NEAR * 3 / 10);
+        assert_eq!(curve.spot_price.0, ONE_NEAR * 11 / 100);
+
+        testing_env!(context.attached_deposit(1).build());
+        assert_eq!(
+            contract.sell_price(U128(10 * ONE_ASTRO)).0,
+            ONE_NEAR * 3 / 10
+        );
+        contract.curve_redeem(U128(10 * ONE_ASTRO), U128(ONE_NEAR * 3 / 10));
+        assert_eq!(contract.ft_balance_of(owner).0, TOTAL_SUPPLY);
+        assert_eq!(contract.get_bonding_curve().reserve.0, 0);
+        assert_eq!(contract.withdraw_curve_proceeds().0, ONE_NEAR * 3 / 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "is above the maximum of")]
+    fn test_mint_slippage() {
+        let (_, mut contract) = setup();
+        contract.curve_mint(U128(10 * ONE_ASTRO), U128(ONE_NEAR / 2));
+    }
+}
@@ -0,0 +1,300 @@
+/*!
+Bridge controller mode for Rainbow Bridge and Aurora connectors.
+NOTES:
+  - The owner or the DAO registers connector accounts. A connector burns ASTRO leaving NEAR with
+    `bridge_burn` and mints ASTRO coming back with `bridge_mint`, quoting the proof it verified.
+    A proof can only be used once.
+  - A connector burns the tokens of an account out of the account's allowance to it, or its own
+    tokens. Frozen accounts cannot bridge out.
+  - A connector mints at most what it burned plus its `mint_cap`, so a compromised connector
+    cannot inflate the supply by more than its cap. Bridge mints do not use the emission
+    schedule: they give back supply burned when leaving NEAR.
+  - The owner or the DAO can pause the bridge in an emergency, which stops both directions.
+  - Both operations emit the NEP-141 burn and mint events and a `bridge_burn` or `bridge_mint` event carrying
+    what the connector relays: the recipient address or the proof.
+  - Messaging bridges without a connector contract mint with relayer signatures instead, see
+    `remote_mint`.
+*/
+#![cfg_attr(not(feature = "bridge"), allow(dead_code, unused_imports))]
+
+use near_contract_standards::fungible_token::events::{FtBurn, FtMint};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::{LookupSet, UnorderedMap};
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const MAX_PROOF_REF_LEN: usize = 256;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BridgeConnector {
+    /// Amount the connector can mint on top of what it burned.
+    pub mint_cap: U128,
+    pub minted: U128,
+    pub burned: U128,
+}
+
+impl Default for BridgeConnector {
+    fn default() -> Self {
+        Self {
+            mint_cap: U128(0),
+            minted: U128(0),
+            burned: U128(0),
+        }
+    }
+}
+
+impl BridgeConnector {
+    pub fn mintable(&self) -> Balance {
+        (self.burned.0 + self.mint_cap.0).saturating_sub(self.minted.0)
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Bridge {
+    pub connectors: UnorderedMap<AccountId, BridgeConnector>,
+    pub used_proofs: LookupSet<String>,
+    pub paused: bool,
+}
+
+impl Bridge {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            connectors: UnorderedMap::new([prefix.as_slice(), b"c"].concat()),
+            used_proofs: LookupSet::new([prefix.as_slice(), b"p"].concat()),
+            paused: false,
+        }
+    }
+
+    /// Panics unless the caller is a connector and the bridge is running.
+    fn assert_connector(&self) -> (AccountId, BridgeConnector) {
+        assert!(!self.paused, "The bridge is paused");
+        let connector_id = env::predecessor_account_id();
+        let connector = self
+            .connectors
+            .get(&connector_id)
+            .cloned()
+            .unwrap_or_else(|| env::panic_str("Only bridge connectors can do this"));
+        (connector_id, connector)
+    }
+}
+
+fn emit_bridge_event(event: &str, data: near_sdk::serde_json::Value) {
+    emit_event(EVENT_VERSION, event, data);
+}
+
+/// Whether `recipient_hex` is a 20-byte address in hex, with or without `0x`.
+fn is_eth_address(recipient_hex: &str) -> bool {
+    let hex = recipient_hex.strip_prefix("0x").unwrap_or(recipient_hex);
+    hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(feature = "bridge")]
+#[near_bindgen]
+impl Contract {
+    /// Registers `connector_id` or updates its mint cap.
+    pub fn set_bridge_connector(&mut self, connector_id: AccountId, mint_cap: U128) {
+        self.assert_owner_or_dao();
+        let mut connector = self
+            .bridge
+            .connectors
+            .get(&connector_id)
+            .cloned()
+            .unwrap_or_default();
+        connector.mint_cap = mint_cap;
+        self.bridge
+            .connectors
+            .insert(connector_id.clone(), connector);
+        log!(
+            "Bridge connector @{} has a mint cap of {}",
+            connector_id,
+            mint_cap.0
+        );
+    }
+
+    pub fn remove_bridge_connector(&mut self, connector_id: AccountId) {
+        self.assert_owner_or_dao();
+        assert!(
+            self.bridge.connectors.remove(&connector_id).is_some(),
+            "Not a bridge connector"
+        );
+        log!("Removed bridge connector @{}", connector_id);
+    }
+
+    pub fn pause_bridge(&mut self) {
+        self.assert_owner_or_dao();
+        self.bridge.paused = true;
+        log!("@{} paused the bridge", env::predecessor_account_id());
+    }
+
+    pub fn unpause_bridge(&mut self) {
+        self.assert_owner_or_dao();
+        self.bridge.paused = false;
+        log!("@{} unpaused the bridge", env::predecessor_account_id());
+    }
+
+    /// Burns `amount` of `account_id` for `recipient_hex` on the other side.
+    #[payable]
+    pub fn bridge_burn(&mut self, account_id: AccountId, amount: U128, recipient_hex: String) {
+        assert_one_yocto();
+        let (connector_id, mut connector) = self.bridge.assert_connector();
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        assert!(
+            is_eth_address(&recipient_hex),
+            "The recipient must be a hex address"
+        );
+        self.freezes.assert_not_frozen(&account_id);
+        if account_id != connector_id {
+            self.use_allowance(&account_id, &connector_id, amount.0);
+        }
+        self.with_checkpoints(std::slice::from_ref(&account_id), |this| {
+            this.token.internal_withdraw(&account_id, amount.0)
+        });
+        connector.burned = (connector.burned.0 + amount.0).into();
+        self.bridge
+            .connectors
+            .insert(connector_id.clone(), connector);
+        FtBurn {
+            owner_id: &account_id,
+            amount: &amount,
+            memo: Some("Bridge"),
+        }
+        .emit();
+        let recipient = recipient_hex
+            .strip_prefix("0x")
+            .unwrap_or(&recipient_hex)
+            .to_lowercase();
+        emit_bridge_event(
+            "bridge_burn",
+            json!({
+                "connector_id": connector_id,
+                "account_id": account_id,
+                "amount": amount,
+                "recipient": recipient,
+            }),
+        );
+    }
+
+    /// Mints `amount` to `account_id` for the transfer proven by `proof_ref`. The attached
+    /// deposit covers the storage of the proof record.
+    #[payable]
+    pub fn bridge_mint(&mut self, account_id: AccountId, amount: U128, proof_ref: String) {
+        let (connector_id, mut connector) = self.bridge.assert_connector();
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        assert!(
+            !proof_ref.is_empty() && proof_ref.len() <= MAX_PROOF_REF_LEN,
+            "The proof reference has 1 to {} bytes",
+            MAX_PROOF_REF_LEN
+        );
+        let mintable = connector.mintable();
+        assert!(
+            amount.0 <= mintable,
+            "The connector can mint at most {}",
+            mintable
+        );
+        let initial_storage = env::storage_usage();
+        assert!(
+            self.bridge.used_proofs.insert(proof_ref.clone()),
+            "The proof was already used"
+        );
+        refund_deposit(env::storage_usage() - initial_storage);
+        self.minter_allowances
+            .use_allowance(&connector_id, amount.0);
+        self.with_checkpoints(std::slice::from_ref(&account_id), |this| {
+            this.token.internal_deposit(&account_id, amount.0)
+        });
+        connector.minted = (connector.minted.0 + amount.0).into();
+        self.bridge
+            .connectors
+            .insert(connector_id.clone(), connector);
+        FtMint {
+            owner_id: &account_id,
+            amount: &amount,
+            memo: Some("Bridge"),
+        }
+        .emit();
+        emit_bridge_event(
+            "bridge_mint",
+            json!({
+                "connector_id": connector_id,
+                "account_id": account_id,
+                "amount": amount,
+                "proof_ref": proof_ref,
+            }),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_bridge_connectors(&self) -> Vec<(AccountId, BridgeConnector)> {
+        self.bridge
+            .connectors
+            .iter()
+            .map(|(connector_id, connector)| (connector_id.clone(), connector.clone()))
+            .collect()
+    }
+
+    pub fn is_bridge_paused(&self) -> bool {
+        self.bridge.paused
+    }
+
+    pub fn is_bridge_proof_used(&self, proof_ref: String) -> bool {
+        self.bridge.used_proofs.contains(&proof_ref)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "bridge"))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    const RECIPIENT: &str = "0x8ba1f109551bD432803012645Ac136ddd64DBA72";
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        contract.set_bridge_connector(accounts(1), U128(0));
+        contract.approve(accounts(1), U128(1_000), None, None);
+        call_as(&mut context, accounts(1), 1);
+        contract.bridge_burn(owner(), U128(1_000), RECIPIENT.to_string());
+        testing_env!(context
+            .attached_deposit(1_000_000_000_000_000_000_000)
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_bridge_mints_what_it_burned() {
+        let (_, mut contract) = setup();
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 1_000);
+        contract.bridge_mint(accounts(1), U128(1_000), "tx-1".to_string());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
+        assert!(contract.is_bridge_proof_used("tx-1".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "The connector can mint at most 1000")]
+    fn test_bridge_mint_cap() {
+        let (_, mut contract) = setup();
+        contract.bridge_mint(accounts(1), U128(1_001), "tx-1".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "The bridge is paused")]
+    fn test_bridge_pause() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.pause_bridge();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.bridge_mint(accounts(1), U128(1_000), "tx-1".to_string());
+    }
+}
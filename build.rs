@@ -0,0 +1,33 @@
+//! Embeds the build metadata returned by the `build_info` view: the git commit of the sources,
+//! the rustc version and the enabled features. Nothing time dependent is embedded, so two builds
+//! of the same commit with the same toolchain produce the same wasm.
+use std::env;
+use std::process::Command;
+
+/// Trimmed stdout of the command, `None` if it cannot run or fails.
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn main() {
+    let commit = output("git", &["rev-parse", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    let dirty = output("git", &["status", "--porcelain", "--untracked-files=no"])
+        .is_some_and(|status| !status.is_empty());
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(str::to_lowercase))
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=ASTRO_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=ASTRO_GIT_DIRTY={}", dirty);
+    println!("cargo:rustc-env=ASTRO_RUSTC_VERSION={}", rustc_version);
+    println!("cargo:rustc-env=ASTRO_FEATURES={}", features.join(","));
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+}
@@ -1,5 +1,13 @@
 #!/bin/bash
 set -e
 cd "`dirname $0`"
-RUSTFLAGS='-C link-arg=-s' cargo build --all --target wasm32-unknown-unknown --release
-cp target/wasm32-unknown-unknown/release/*.wasm ./main.wasm
\ No newline at end of file
+# Release wasm size budget in bytes, keep in sync with tests/wasm_size.rs.
+WASM_SIZE_BUDGET=${WASM_SIZE_BUDGET:-450000}
+RUSTFLAGS='-C link-arg=-s' cargo build -p fungible-token --target wasm32-unknown-unknown --release
+cp target/wasm32-unknown-unknown/release/fungible_token.wasm ./main.wasm
+WASM_SIZE=$(wc -c < main.wasm)
+if [ "$WASM_SIZE" -gt "$WASM_SIZE_BUDGET" ]; then
+  echo "main.wasm is $WASM_SIZE bytes, over the budget of $WASM_SIZE_BUDGET bytes" >&2
+  exit 1
+fi
+echo "main.wasm is $WASM_SIZE bytes (budget $WASM_SIZE_BUDGET)"
@@ -0,0 +1,41 @@
+/*!
+Build metadata embedded at compile time by `build.rs`.
+NOTES:
+  - `build_info` returns the git commit the wasm was built from, whether the working tree had
+    uncommitted changes, the rustc version and the enabled Cargo features. An auditor rebuilds that
+    commit with the same toolchain and features, e.g. with `build.sh`, and compares the hash of
+    the result with the code hash of the deployed contract.
+  - The values are whatever the build machine reported; only the rebuild proves them.
+*/
+use near_sdk::near_bindgen;
+use near_sdk::serde::Serialize;
+
+use crate::*;
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_commit: String,
+    /// The sources had uncommitted changes, so the commit alone does not reproduce the build.
+    pub git_dirty: bool,
+    pub rustc_version: String,
+    pub features: Vec<String>,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn build_info(&self) -> BuildInfo {
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: env!("ASTRO_GIT_COMMIT").to_string(),
+            git_dirty: env!("ASTRO_GIT_DIRTY") == "true",
+            rustc_version: env!("ASTRO_RUSTC_VERSION").to_string(),
+            features: env!("ASTRO_FEATURES")
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
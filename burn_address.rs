@@ -0,0 +1,86 @@
+/*!
+Canonical burn address.
+NOTES:
+  - The owner can set a burn account, e.g. `system` or `burn.astro.near`. An `ft_transfer` to it,
+    or a transfer made on behalf of a holder, burns the amount: the total supply goes down and an
+    `FtBurn` event is logged, instead of the tokens sitting in a dead account. Fees and the usual
+    checks of a transfer still apply.
+  - The burn account does not need to be registered. If it holds tokens when it is set, they are
+    burned too.
+  - `ft_transfer_call` to the burn account is rejected, there is no receiver to call.
+*/
+use near_contract_standards::fungible_token::events::FtBurn;
+use near_sdk::json_types::U128;
+use near_sdk::{near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the account whose incoming transfers are burned, or `None` for no burn account.
+    pub fn set_burn_account(&mut self, account_id: Option<AccountId>) {
+        self.assert_owner();
+        if let Some(account_id) = &account_id {
+            let balance = self.token.accounts.get(account_id).unwrap_or(0);
+            if balance > 0 {
+                self.with_checkpoints(std::slice::from_ref(account_id), |this| {
+                    this.token.internal_withdraw(account_id, balance)
+                });
+                FtBurn {
+                    owner_id: account_id,
+                    amount: &U128(balance),
+                    memo: Some("Burn account balance"),
+                }
+                .emit();
+            }
+        }
+        self.burn_account_id = account_id;
+    }
+
+    pub fn get_burn_account(&self) -> Option<AccountId> {
+        self.burn_account_id.clone()
+    }
+}
+
+impl Contract {
+    pub(crate) fn is_burn_account(&self, account_id: &AccountId) -> bool {
+        self.burn_account_id.as_ref() == Some(account_id)
+    }
+
+    /// Burns a transfer of `amount` from `sender_id` to the burn account. Called in place of the
+    /// transfer, within its checkpoints.
+    pub(crate) fn internal_burn_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        self.token.internal_withdraw(sender_id, amount);
+        FtBurn {
+            owner_id: sender_id,
+            amount: &U128(amount),
+            memo: Some(memo.as_deref().unwrap_or("Transfer to the burn account")),
+        }
+        .emit();
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+
+    #[test]
+    fn test_transfer_to_the_burn_account_burns() {
+        let owner = owner();
+        let burn_account: AccountId = "system".parse().unwrap();
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner.clone(), 1);
+        contract.set_burn_account(Some(burn_account.clone()));
+        contract.ft_transfer(burn_account.clone(), U128(100), None);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 100);
+        assert_eq!(contract.ft_balance_of(owner).0, TOTAL_SUPPLY - 100);
+        assert_eq!(contract.ft_balance_of(burn_account).0, 0);
+    }
+}
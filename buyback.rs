@@ -0,0 +1,215 @@
+/*!
+Buyback-and-burn of ASTRO with attached NEAR through a Ref Finance pool.
+NOTES:
+  - `buyback_and_burn` wraps the attached NEAR on the wNEAR contract and swaps it for ASTRO with
+    an instant swap on Ref (`ft_transfer_call` with swap actions). Ref enforces
+    `min_tokens_out` and sends the ASTRO back to this contract with `ft_transfer`.
+  - ASTRO sent to the contract account by the configured Ref contract is kept apart from the
+    treasury as unburned proceeds. The callback burns them. Ref's `ft_transfer` is not chained to
+    the swap, so proceeds arriving after the callback are burned by the next buyback or by
+    `burn_buyback_proceeds`.
+  - If the swap fails, Ref refunds the wNEAR and the callback unwraps it and returns the NEAR to
+    the caller.
+  - The contract account must be registered with the wNEAR contract and with Ref.
+*/
+use near_contract_standards::fungible_token::events::FtBurn;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::{
+    env, ext_contract, log, near_bindgen, AccountId, Balance, Gas, Promise, PromiseError,
+    PromiseOrValue,
+};
+
+use crate::*;
+
+const GAS_FOR_NEAR_DEPOSIT: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_SWAP: Gas = Gas(100_000_000_000_000);
+const GAS_FOR_ON_BUYBACK: Gas = Gas(40_000_000_000_000);
+const GAS_FOR_NEAR_WITHDRAW: Gas = Gas(10_000_000_000_000);
+
+#[ext_contract(ext_wrap)]
+#[allow(dead_code)]
+pub trait WrappedNear {
+    fn near_deposit(&mut self);
+    fn near_withdraw(&mut self, amount: U128) -> Promise;
+    fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128>;
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BuybackConfig {
+    pub ref_contract_id: AccountId,
+    pub wnear_id: AccountId,
+    pub pool_id: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Buyback {
+    pub config: Option<BuybackConfig>,
+    /// Swap proceeds held by the contract account until they are burned.
+    pub unburned: Balance,
+}
+
+impl Buyback {
+    pub fn new() -> Self {
+        Self {
+            config: None,
+            unburned: 0,
+        }
+    }
+
+    /// Whether `account_id` is the exchange that returns the bought tokens.
+    pub fn is_exchange(&self, account_id: &AccountId) -> bool {
+        matches!(&self.config, Some(config) if &config.ref_contract_id == account_id)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_buyback_config(&mut self, config: Option<BuybackConfig>) {
+        self.assert_owner_or_dao();
+        self.buyback.config = config;
+    }
+
+    pub fn get_buyback_config(&self) -> Option<BuybackConfig> {
+        self.buyback.config.clone()
+    }
+
+    pub fn get_unburned_buyback_proceeds(&self) -> U128 {
+        self.buyback.unburned.into()
+    }
+
+    /// Swaps the attached NEAR for ASTRO on Ref and burns the tokens received. The swap fails
+    /// if it would return less than `min_tokens_out`, in which case the NEAR is refunded.
+    #[payable]
+    pub fn buyback_and_burn(&mut self, min_tokens_out: U128) -> Promise {
+        let config = self
+            .buyback
+            .config
+            .clone()
+            .expect("Buyback is not configured");
+        let amount = env::attached_deposit();
+        assert!(amount > 1, "Requires attached NEAR to buy back with");
+        // One yoctoNEAR is kept for the `ft_transfer_call` deposit.
+        let amount_in = amount - 1;
+        let msg = json!({
+            "actions": [{
+                "pool_id": config.pool_id,
+                "token_in": config.wnear_id,
+                "token_out": env::current_account_id(),
+                "amount_in": U128(amount_in),
+                "min_amount_out": min_tokens_out,
+            }],
+        })
+        .to_string();
+        ext_wrap::ext(config.wnear_id.clone())
+            .with_attached_deposit(amount_in)
+            .with_static_gas(GAS_FOR_NEAR_DEPOSIT)
+            .near_deposit()
+            .then(
+                ext_wrap::ext(config.wnear_id)
+                    .with_attached_deposit(1)
+                    .with_static_gas(GAS_FOR_SWAP)
+                    .ft_transfer_call(config.ref_contract_id, U128(amount_in), None, msg),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_BUYBACK)
+                    .on_buyback(env::predecessor_account_id(), U128(amount_in)),
+            )
+    }
+
+    /// Refunds the NEAR of a failed swap and burns the proceeds received so far. Returns the
+    /// amount burned.
+    #[private]
+    pub fn on_buyback(
+        &mut self,
+        caller_id: AccountId,
+        amount_in: U128,
+        #[callback_result] used: Result<U128, PromiseError>,
+    ) -> U128 {
+        let used = used.map(|used| used.0).unwrap_or(0);
+        if used < amount_in.0 {
+            let refund = amount_in.0 - used;
+            let wnear_id = self.buyback.config.as_ref().unwrap().wnear_id.clone();
+            ext_wrap::ext(wnear_id)
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_NEAR_WITHDRAW)
+                .near_withdraw(U128(refund))
+                .then(Promise::new(caller_id.clone()).transfer(refund));
+            log!(
+                "Buyback swap failed, refunding {} to @{}",
+                refund,
+                caller_id
+            );
+            self.internal_record_callback_failure("buyback", vec![caller_id], refund);
+        }
+        self.internal_burn_buyback_proceeds().into()
+    }
+
+    /// Burns buyback proceeds that arrived after their swap callback.
+    pub fn burn_buyback_proceeds(&mut self) -> U128 {
+        self.internal_burn_buyback_proceeds().into()
+    }
+}
+
+impl Contract {
+    fn internal_burn_buyback_proceeds(&mut self) -> Balance {
+        let amount = std::mem::take(&mut self.buyback.unburned);
+        if amount == 0 {
+            return 0;
+        }
+        let contract_id = env::current_account_id();
+        self.with_checkpoints(std::slice::from_ref(&contract_id), |this| {
+            this.token.internal_withdraw(&contract_id, amount)
+        });
+        FtBurn {
+            owner_id: &contract_id,
+            amount: &U128(amount),
+            memo: Some("Buyback"),
+        }
+        .emit();
+        amount
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_swap_proceeds_are_burned() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner.clone(), 0);
+        contract.set_buyback_config(Some(BuybackConfig {
+            ref_contract_id: owner.clone(),
+            wnear_id: accounts(1),
+            pool_id: 0,
+        }));
+
+        // The pool account pays out the bought tokens.
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(0), U128(1_000), None);
+        assert_eq!(contract.get_unburned_buyback_proceeds().0, 1_000);
+        assert_eq!(contract.get_treasury_balance().0, 0);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let burned = contract.on_buyback(owner, U128(5), Ok(U128(5)));
+        assert_eq!(burned.0, 1_000);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 1_000);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 0);
+    }
+}
@@ -0,0 +1,137 @@
+/*!
+Audit log of failed cross-contract calls.
+NOTES:
+  - Every callback that finds its promise failed records the method, the accounts involved, the
+    amount at stake and the time: `ft_transfer_call` resolutions, buybacks, dividend claims,
+    rescues, treasury withdrawals, LP withdrawals and liquid staking calls.
+  - The log is a ring buffer of the last `MAX_CALLBACK_FAILURES` entries, so its storage is
+    bounded and paid by the contract. Entries are numbered from 0 in the order they happened; an
+    entry is overwritten `MAX_CALLBACK_FAILURES` entries later.
+  - Each entry is also logged as a `callback_failure` event, so indexers keep the full history.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::Vector;
+use near_sdk::{env, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+pub const MAX_CALLBACK_FAILURES: u32 = 1_000;
+const DEFAULT_LIMIT: u64 = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CallbackFailure {
+    pub index: U64,
+    /// Method that started the failed call.
+    pub method: String,
+    pub account_ids: Vec<AccountId>,
+    pub amount: U128,
+    pub timestamp: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct CallbackFailures {
+    pub entries: Vector<CallbackFailure>,
+    /// Failures recorded so far, including the overwritten ones.
+    pub count: u64,
+}
+
+impl CallbackFailures {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            entries: Vector::new(prefix),
+            count: 0,
+        }
+    }
+
+    fn push(&mut self, failure: CallbackFailure) {
+        if self.entries.len() < MAX_CALLBACK_FAILURES {
+            self.entries.push(failure);
+        } else {
+            let slot = (self.count % u64::from(MAX_CALLBACK_FAILURES)) as u32;
+            self.entries.set(slot, failure);
+        }
+        self.count += 1;
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Failures from number `from_index` on, oldest first. Numbers that were overwritten are
+    /// skipped.
+    pub fn get_callback_failures(
+        &self,
+        from_index: Option<U64>,
+        limit: Option<U64>,
+    ) -> Vec<CallbackFailure> {
+        let log = &self.callback_failures;
+        let oldest = log.count - u64::from(log.entries.len());
+        let from_index = from_index.map_or(oldest, |index| index.0.max(oldest));
+        let limit = limit.map_or(DEFAULT_LIMIT, |limit| limit.0);
+        (from_index..log.count)
+            .take(limit as usize)
+            .filter_map(|index| {
+                log.entries
+                    .get((index % u64::from(MAX_CALLBACK_FAILURES)) as u32)
+                    .cloned()
+            })
+            .collect()
+    }
+
+    pub fn get_callback_failure_count(&self) -> U64 {
+        self.callback_failures.count.into()
+    }
+}
+
+impl Contract {
+    /// Records that the call started by `method` failed.
+    pub(crate) fn internal_record_callback_failure(
+        &mut self,
+        method: &str,
+        account_ids: Vec<AccountId>,
+        amount: Balance,
+    ) {
+        let failure = CallbackFailure {
+            index: self.callback_failures.count.into(),
+            method: method.to_string(),
+            account_ids,
+            amount: amount.into(),
+            timestamp: env::block_timestamp().into(),
+        };
+        emit_event(EVENT_VERSION, "callback_failure", json!(&failure));
+        self.callback_failures.push(failure);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::setup;
+
+    #[test]
+    fn test_callback_failures_ring_buffer() {
+        let (context, mut contract) = setup(&[]);
+        for amount in 0..u128::from(MAX_CALLBACK_FAILURES) + 2 {
+            // Each failure comes from its own callback, with its own log limit.
+            testing_env!(context.build());
+            contract.internal_record_callback_failure("rescue_ft", vec![accounts(1)], amount);
+        }
+        assert_eq!(
+            contract.get_callback_failure_count().0,
+            u64::from(MAX_CALLBACK_FAILURES) + 2
+        );
+        let failures = contract.get_callback_failures(None, Some(U64(2)));
+        assert_eq!(failures[0].index.0, 2);
+        assert_eq!(failures[1].amount.0, 3);
+        let latest = contract.get_callback_failures(Some(U64(1_001)), None);
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].amount.0, 1_001);
+    }
+}
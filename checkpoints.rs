@@ -0,0 +1,417 @@
+/*!
+Balance checkpoints by block height, used to weight governance votes by historical balances.
+NOTES:
+  - A checkpoint `(block_height, balance)` holds the balance from that block on. Several changes
+    within one block overwrite the same checkpoint.
+  - The first change of an account also records its balance before the change at height 0, so
+    accounts that held tokens before checkpointing started still have a correct history.
+  - Accounts whose balance never changed have no checkpoints; their current balance applies.
+  - Account histories are kept in a `ChunkedHistory`, so an update rewrites at most one chunk.
+  - Total supply checkpoints also record the block timestamp. `supply_history` pages through
+    them for charts of the supply over time. They are kept in a `ChunkedHistory` under a single
+    key, like an account history.
+  - Checkpoint storage is paid by the contract. To bound it, histories only keep their last
+    `MAX_CHUNKS` chunks of checkpoints, see `chunked`, except for checkpoints still needed at the
+    snapshot of an active governance proposal: dust transfers to a voter cannot push its
+    snapshot balance out. Balances at heights before the kept history cannot be queried anymore
+    and panic instead of returning a wrong value.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, AccountId, Balance, BlockHeight};
+
+use crate::chunked::ChunkedHistory;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+pub struct Checkpoint {
+    pub block_height: BlockHeight,
+    pub balance: Balance,
+}
+
+/// Total supply checkpoint, with the timestamp of its block.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+pub struct SupplyRecord {
+    pub block_height: BlockHeight,
+    pub timestamp: u64,
+    pub total_supply: Balance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SupplyCheckpoint {
+    pub block_height: U64,
+    pub timestamp: U64,
+    pub total_supply: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Checkpoints {
+    pub accounts: ChunkedHistory<AccountId, Checkpoint>,
+    /// Total supply checkpoints, all under the unit key.
+    pub total_supply: ChunkedHistory<(), SupplyRecord>,
+}
+
+/// Index of the last checkpoint at or before `block_height` among `len` sorted checkpoints.
+fn search(len: u32, block_height: BlockHeight, at: impl Fn(u32) -> BlockHeight) -> Option<u32> {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if at(mid) <= block_height {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low.checked_sub(1)
+}
+
+/// Records a change of the value tracked in `history` for `account_id` from `previous` to
+/// `balance`. The first change also records `previous` at height 0. Checkpoints needed for the
+/// value at `keep_from` are not pruned.
+pub fn record_history(
+    history: &mut ChunkedHistory<AccountId, Checkpoint>,
+    account_id: &AccountId,
+    previous: Balance,
+    balance: Balance,
+    keep_from: Option<BlockHeight>,
+) {
+    let can_prune = |next: &Checkpoint| keep_from.is_none_or(|height| next.block_height <= height);
+    if previous == balance {
+        return;
+    }
+    let block_height = env::block_height();
+    match history.last(account_id) {
+        None => {
+            history.push(
+                account_id,
+                Checkpoint {
+                    block_height: 0,
+                    balance: previous,
+                },
+                can_prune,
+            );
+        }
+        Some(last) if last.block_height == block_height => {
+            history.set_last(
+                account_id,
+                Checkpoint {
+                    block_height,
+                    balance,
+                },
+            );
+            return;
+        }
+        Some(_) => {}
+    }
+    history.push(
+        account_id,
+        Checkpoint {
+            block_height,
+            balance,
+        },
+        can_prune,
+    );
+}
+
+/// Value tracked in `history` for `account_id` at `block_height`, or `None` if it has no
+/// checkpoint at or before that height. Panics if that part of the history was pruned.
+pub fn history_at(
+    history: &ChunkedHistory<AccountId, Checkpoint>,
+    account_id: &AccountId,
+    block_height: BlockHeight,
+) -> Option<Balance> {
+    let len = history.len(account_id);
+    if len == 0 {
+        return None;
+    }
+    let first = history.first(account_id);
+    let at = |i| history.get(account_id, i).unwrap().block_height;
+    let index = search(len - first, block_height, |i| at(first + i)).map(|i| first + i);
+    assert!(
+        index.is_some() || first == 0,
+        "The history of {} before block {} was pruned",
+        account_id,
+        at(first)
+    );
+    history
+        .get(account_id, index?)
+        .map(|checkpoint| checkpoint.balance)
+}
+
+impl Checkpoints {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            accounts: ChunkedHistory::new([prefix.as_slice(), b"a"].concat()),
+            total_supply: ChunkedHistory::new([prefix.as_slice(), b"s"].concat()),
+        }
+    }
+
+    /// Records a balance change of `account_id` from `previous` to `balance`, keeping the
+    /// checkpoints needed at `keep_from`.
+    pub fn record_account(
+        &mut self,
+        account_id: &AccountId,
+        previous: Balance,
+        balance: Balance,
+        keep_from: Option<BlockHeight>,
+    ) {
+        record_history(&mut self.accounts, account_id, previous, balance, keep_from);
+    }
+
+    /// Records a total supply change from `previous` to `total_supply`, keeping the checkpoints
+    /// needed at `keep_from`.
+    pub fn record_total_supply(
+        &mut self,
+        previous: Balance,
+        total_supply: Balance,
+        keep_from: Option<BlockHeight>,
+    ) {
+        let can_prune =
+            |next: &SupplyRecord| keep_from.is_none_or(|height| next.block_height <= height);
+        if previous == total_supply {
+            return;
+        }
+        let record = SupplyRecord {
+            block_height: env::block_height(),
+            timestamp: env::block_timestamp(),
+            total_supply,
+        };
+        match self.total_supply.last(&()) {
+            None => {
+                let initial = SupplyRecord {
+                    block_height: 0,
+                    timestamp: 0,
+                    total_supply: previous,
+                };
+                self.total_supply.push(&(), initial, can_prune);
+            }
+            Some(last) if last.block_height == record.block_height => {
+                self.total_supply.set_last(&(), record);
+                return;
+            }
+            Some(_) => {}
+        }
+        self.total_supply.push(&(), record, can_prune);
+    }
+
+    /// Balance of `account_id` at `block_height`, or `None` if it never changed.
+    pub fn account_balance_at(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+    ) -> Option<Balance> {
+        history_at(&self.accounts, account_id, block_height)
+    }
+
+    /// Total supply at `block_height`, or `None` if it never changed. Panics if that part of the
+    /// history was pruned.
+    pub fn total_supply_at(&self, block_height: BlockHeight) -> Option<Balance> {
+        let len = self.total_supply.len(&());
+        if len == 0 {
+            return None;
+        }
+        let first = self.total_supply.first(&());
+        let at = |i| self.total_supply.get(&(), i).unwrap().block_height;
+        let index = search(len - first, block_height, |i| at(first + i)).map(|i| first + i);
+        assert!(
+            index.is_some() || first == 0,
+            "The total supply history before block {} was pruned",
+            at(first)
+        );
+        self.total_supply
+            .get(&(), index?)
+            .map(|record| record.total_supply)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn ft_balance_of_at(&self, account_id: AccountId, block_height: U64) -> U128 {
+        self.checkpoints
+            .account_balance_at(&account_id, block_height.0)
+            .unwrap_or_else(|| self.token.accounts.get(&account_id).unwrap_or(0))
+            .into()
+    }
+
+    pub fn ft_total_supply_at(&self, block_height: U64) -> U128 {
+        self.checkpoints
+            .total_supply_at(block_height.0)
+            .unwrap_or(self.token.total_supply)
+            .into()
+    }
+
+    /// Total supply checkpoints in order, from the supply before the first mint or burn.
+    /// Indices of pruned checkpoints are skipped.
+    pub fn supply_history(
+        &self,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<SupplyCheckpoint> {
+        let history = &self.checkpoints.total_supply;
+        let from_index = from_index.unwrap_or(0).max(history.first(&()));
+        let to_index = from_index
+            .saturating_add(limit.unwrap_or(u32::MAX))
+            .min(history.len(&()));
+        (from_index..to_index)
+            .filter_map(|i| history.get(&(), i))
+            .map(|record| SupplyCheckpoint {
+                block_height: record.block_height.into(),
+                timestamp: record.timestamp.into(),
+                total_supply: record.total_supply.into(),
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Checkpoints a balance change of `account_id` and moves its delegated votes.
+    pub(crate) fn record_balance_change(
+        &mut self,
+        account_id: &AccountId,
+        previous: Balance,
+        balance: Balance,
+    ) {
+        let keep_from = self.governance.oldest_snapshot();
+        self.checkpoints
+            .record_account(account_id, previous, balance, keep_from);
+        self.move_votes(account_id, previous, balance);
+    }
+
+    /// Runs `f` and checkpoints the balances of `account_ids` and the total supply it changed.
+    /// Panics if `f` takes an account below its locked or reserved balance.
+    pub(crate) fn with_checkpoints<T>(
+        &mut self,
+        account_ids: &[AccountId],
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let previous: Vec<Balance> = account_ids
+            .iter()
+            .map(|account_id| self.token.accounts.get(account_id).unwrap_or(0))
+            .collect();
+        let previous_total_supply = self.token.total_supply;
+        self.settle_dividends(account_ids, &previous);
+        let result = f(self);
+        for (account_id, previous) in account_ids.iter().zip(previous) {
+            let balance = self.token.accounts.get(account_id).unwrap_or(0);
+            if balance < previous {
+                self.balance_locks.assert_covered(account_id, balance);
+                let unlocked = balance - self.balance_locks.locked(account_id);
+                self.reservations.assert_covered(account_id, unlocked);
+            }
+            self.record_balance_change(account_id, previous, balance);
+        }
+        let keep_from = self.governance.oldest_snapshot();
+        self.checkpoints.record_total_supply(
+            previous_total_supply,
+            self.token.total_supply,
+            keep_from,
+        );
+        self.metrics
+            .record_supply_change(previous_total_supply, self.token.total_supply);
+        self.debug_assert_invariants();
+        result
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::chunked::{CHUNK_SIZE, MAX_CHUNKS};
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_balance_at_block_height() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 1);
+        testing_env!(context.block_index(20).build());
+        contract.ft_transfer(accounts(1), U128(100), None);
+        testing_env!(context.block_index(30).build());
+        contract.ft_transfer(accounts(1), U128(50), None);
+
+        assert_eq!(contract.ft_balance_of_at(accounts(1), U64(15)).0, 0);
+        assert_eq!(contract.ft_balance_of_at(accounts(1), U64(20)).0, 100);
+        assert_eq!(contract.ft_balance_of_at(accounts(1), U64(29)).0, 100);
+        assert_eq!(contract.ft_balance_of_at(accounts(1), U64(40)).0, 150);
+        assert_eq!(
+            contract.ft_balance_of_at(owner(), U64(25)).0,
+            TOTAL_SUPPLY - 100
+        );
+        assert_eq!(contract.ft_total_supply_at(U64(25)).0, TOTAL_SUPPLY);
+    }
+
+    #[test]
+    fn test_supply_history() {
+        let owner = owner();
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(owner.clone())
+            .block_index(10)
+            .block_timestamp(1_000);
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(owner.clone());
+        contract.internal_mint(&owner, &owner, 100);
+        testing_env!(context.block_index(20).block_timestamp(2_000).build());
+        contract.internal_mint(&owner, &owner, 50);
+
+        let history = contract.supply_history(Some(1), None);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].block_height.0, 10);
+        assert_eq!(history[0].total_supply.0, TOTAL_SUPPLY + 100);
+        assert_eq!(history[1].timestamp.0, 2_000);
+        assert_eq!(history[1].total_supply.0, TOTAL_SUPPLY + 150);
+    }
+
+    #[test]
+    #[should_panic(expected = "before block 32 was pruned")]
+    fn test_pruned_history() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.current_account_id(accounts(0)).build());
+        let mut contract = Contract::new_default_meta(accounts(2));
+        // With the balance before the first change, the last change opens a new chunk.
+        let changes = Balance::from(MAX_CHUNKS * CHUNK_SIZE);
+        for block_height in 1..changes as u64 {
+            testing_env!(context.block_index(block_height).build());
+            let balance = Balance::from(block_height);
+            contract
+                .checkpoints
+                .record_account(&accounts(1), balance - 1, balance, None);
+        }
+        assert_eq!(contract.ft_balance_of_at(accounts(1), U64(40)).0, 40);
+        assert_eq!(contract.ft_balance_of_at(accounts(1), U64(1)).0, 1);
+        testing_env!(context.block_index(changes as u64).build());
+        contract
+            .checkpoints
+            .record_account(&accounts(1), changes - 1, changes, None);
+        assert_eq!(contract.ft_balance_of_at(accounts(1), U64(40)).0, 40);
+        contract.ft_balance_of_at(accounts(1), U64(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "The total supply history before block 31 was pruned")]
+    fn test_pruned_supply_history() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.current_account_id(accounts(0)).build());
+        let mut contract = Contract::new_default_meta(accounts(2));
+        // The initial mint records the supply before and after it at height 0, so the change at
+        // block `h` has the index `h + 1` and the one at block 255 opens a new chunk.
+        let changes = Balance::from(MAX_CHUNKS * CHUNK_SIZE);
+        for block_height in 1..changes as u64 {
+            testing_env!(context.block_index(block_height).build());
+            let total_supply = Balance::from(block_height);
+            contract
+                .checkpoints
+                .record_total_supply(total_supply - 1, total_supply, None);
+        }
+        assert_eq!(contract.ft_total_supply_at(U64(40)).0, 40);
+        let history = contract.supply_history(None, None);
+        assert_eq!(history.len() as u32, (MAX_CHUNKS - 1) * CHUNK_SIZE + 1);
+        assert_eq!(history[0].block_height.0, 31);
+        contract.ft_total_supply_at(U64(30));
+    }
+}
@@ -0,0 +1,221 @@
+/*!
+Append-mostly per-key histories stored in fixed-size chunks.
+NOTES:
+  - Keeping a whole history as one `Vec` value rewrites all of it on every append, so the cost of
+    a balance change grows with the number of past checkpoints. Chunks bound the rewrite to
+    `CHUNK_SIZE` entries.
+  - Chunks are stored under `(key, chunk_index)` and the length under `key`.
+  - Only the last `MAX_CHUNKS` chunks of a key are kept: opening a new chunk removes the oldest
+    ones, so a key costs a bounded amount of storage however often it is appended to. The caller
+    can hold pruning back while older entries are still needed: a chunk is only removed once the
+    entries after it are enough on their own, which the caller decides from the first of them.
+    Held back chunks are removed when a later chunk is opened. Indices are not renumbered,
+    entries before `first` are gone.
+  - Run `cargo test bench_ -- --ignored --nocapture` to compare the layouts.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::store::LookupMap;
+
+/// Number of entries per chunk.
+pub const CHUNK_SIZE: u32 = 32;
+/// Number of chunks kept per key.
+pub const MAX_CHUNKS: u32 = 8;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ChunkedHistory<K, T>
+where
+    K: BorshSerialize + Ord + Clone,
+    T: BorshSerialize + BorshDeserialize + Clone,
+{
+    lengths: LookupMap<K, u32>,
+    chunks: LookupMap<(K, u32), Vec<T>>,
+    /// Index of the oldest chunk kept, for keys that were pruned.
+    first_chunks: LookupMap<K, u32>,
+}
+
+impl<K, T> ChunkedHistory<K, T>
+where
+    K: BorshSerialize + Ord + Clone,
+    T: BorshSerialize + BorshDeserialize + Clone,
+{
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            lengths: LookupMap::new([prefix.as_slice(), b"l"].concat()),
+            chunks: LookupMap::new([prefix.as_slice(), b"c"].concat()),
+            first_chunks: LookupMap::new([prefix.as_slice(), b"f"].concat()),
+        }
+    }
+
+    pub fn len(&self, key: &K) -> u32 {
+        self.lengths.get(key).copied().unwrap_or(0)
+    }
+
+    /// Index of the oldest entry of `key` that was not pruned.
+    pub fn first(&self, key: &K) -> u32 {
+        self.first_chunk(key) * CHUNK_SIZE
+    }
+
+    fn first_chunk(&self, key: &K) -> u32 {
+        self.first_chunks.get(key).copied().unwrap_or(0)
+    }
+
+    pub fn get(&self, key: &K, index: u32) -> Option<T> {
+        if index >= self.len(key) || index < self.first(key) {
+            return None;
+        }
+        self.chunks
+            .get(&(key.clone(), index / CHUNK_SIZE))
+            .map(|chunk| chunk[(index % CHUNK_SIZE) as usize].clone())
+    }
+
+    pub fn last(&self, key: &K) -> Option<T> {
+        self.len(key)
+            .checked_sub(1)
+            .and_then(|index| self.get(key, index))
+    }
+
+    /// Appends `value` to the history of `key`. If this opens a chunk beyond `MAX_CHUNKS`, the
+    /// oldest chunks are removed as long as `can_prune` holds for the first entry after them.
+    pub fn push(&mut self, key: &K, value: T, can_prune: impl Fn(&T) -> bool) {
+        let len = self.len(key);
+        let chunk_key = (key.clone(), len / CHUNK_SIZE);
+        let mut chunk = self.chunks.get(&chunk_key).cloned().unwrap_or_default();
+        chunk.push(value);
+        self.chunks.insert(chunk_key, chunk);
+        self.lengths.insert(key.clone(), len + 1);
+        if len.is_multiple_of(CHUNK_SIZE) {
+            self.prune(key, len / CHUNK_SIZE, can_prune);
+        }
+    }
+
+    fn prune(&mut self, key: &K, last_chunk: u32, can_prune: impl Fn(&T) -> bool) {
+        let mut first_chunk = self.first_chunk(key);
+        while last_chunk - first_chunk >= MAX_CHUNKS {
+            let next = &self.chunks[&(key.clone(), first_chunk + 1)][0];
+            if !can_prune(next) {
+                break;
+            }
+            self.chunks.remove(&(key.clone(), first_chunk));
+            first_chunk += 1;
+        }
+        if first_chunk > 0 {
+            self.first_chunks.insert(key.clone(), first_chunk);
+        }
+    }
+
+    /// Replaces the last entry of `key`. Panics if the history is empty.
+    pub fn set_last(&mut self, key: &K, value: T) {
+        let index = self.len(key).checked_sub(1).expect("History is empty");
+        let chunk_key = (key.clone(), index / CHUNK_SIZE);
+        let mut chunk = self.chunks.get(&chunk_key).cloned().unwrap();
+        chunk[(index % CHUNK_SIZE) as usize] = value;
+        self.chunks.insert(chunk_key, chunk);
+    }
+
+    pub fn flush(&mut self) {
+        self.lengths.flush();
+        self.chunks.flush();
+        self.first_chunks.flush();
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{env, testing_env};
+
+    use super::*;
+
+    #[test]
+    fn test_push_get_set_last() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut history: ChunkedHistory<String, u64> = ChunkedHistory::new(b"h".to_vec());
+        let key = "alice".to_string();
+        for value in 0..100 {
+            history.push(&key, value, |_| true);
+        }
+        history.set_last(&key, 1_000);
+        assert_eq!(history.len(&key), 100);
+        assert_eq!(history.get(&key, 0), Some(0));
+        assert_eq!(history.get(&key, 33), Some(33));
+        assert_eq!(history.get(&key, 100), None);
+        assert_eq!(history.last(&key), Some(1_000));
+        assert_eq!(history.len(&"bob".to_string()), 0);
+    }
+
+    #[test]
+    fn test_oldest_chunk_is_pruned() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut history: ChunkedHistory<String, u32> = ChunkedHistory::new(b"h".to_vec());
+        let key = "alice".to_string();
+        for value in 0..MAX_CHUNKS * CHUNK_SIZE {
+            history.push(&key, value, |_| true);
+        }
+        assert_eq!(history.first(&key), 0);
+        history.push(&key, MAX_CHUNKS * CHUNK_SIZE, |_| true);
+        assert_eq!(history.first(&key), CHUNK_SIZE);
+        assert_eq!(history.get(&key, CHUNK_SIZE - 1), None);
+        assert_eq!(history.get(&key, CHUNK_SIZE), Some(CHUNK_SIZE));
+        assert_eq!(history.len(&key), MAX_CHUNKS * CHUNK_SIZE + 1);
+    }
+
+    #[test]
+    fn test_pruning_held_back() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut history: ChunkedHistory<String, u32> = ChunkedHistory::new(b"h".to_vec());
+        let key = "alice".to_string();
+        for value in 0..(MAX_CHUNKS + 1) * CHUNK_SIZE + 1 {
+            history.push(&key, value, |next| *next <= CHUNK_SIZE);
+        }
+        assert_eq!(history.first(&key), CHUNK_SIZE);
+        assert_eq!(history.get(&key, CHUNK_SIZE), Some(CHUNK_SIZE));
+        // The next chunk removes the held back one too.
+        for value in 0..CHUNK_SIZE {
+            history.push(&key, value, |_| true);
+        }
+        assert_eq!(history.first(&key), 3 * CHUNK_SIZE);
+        assert_eq!(history.get(&key, 3 * CHUNK_SIZE), Some(3 * CHUNK_SIZE));
+    }
+
+    /// Compares gas and storage of appending `n` entries to one history in both layouts.
+    #[test]
+    #[ignore]
+    fn bench_append_layouts() {
+        let key = "alice.near".to_string();
+        println!(
+            "{:>6} {:>16} {:>16} {:>10} {:>10}",
+            "n", "vec gas", "chunked gas", "vec B", "chunk B"
+        );
+        for &n in &[10u64, 100, 1_000] {
+            testing_env!(VMContextBuilder::new().build());
+            let mut vec_layout: LookupMap<String, Vec<(u64, u128)>> = LookupMap::new(b"v".to_vec());
+            let gas_before = env::used_gas().0;
+            let storage_before = env::storage_usage();
+            for i in 0..n {
+                let mut history = vec_layout.get(&key).cloned().unwrap_or_default();
+                history.push((i, u128::from(i)));
+                vec_layout.insert(key.clone(), history);
+                vec_layout.flush();
+            }
+            let vec_gas = env::used_gas().0 - gas_before;
+            let vec_storage = env::storage_usage() - storage_before;
+
+            testing_env!(VMContextBuilder::new().build());
+            let mut chunked: ChunkedHistory<String, (u64, u128)> =
+                ChunkedHistory::new(b"c".to_vec());
+            let gas_before = env::used_gas().0;
+            let storage_before = env::storage_usage();
+            for i in 0..n {
+                chunked.push(&key, (i, u128::from(i)), |_| true);
+                chunked.flush();
+            }
+            let chunked_gas = env::used_gas().0 - gas_before;
+            let chunked_storage = env::storage_usage() - storage_before;
+
+            println!(
+                "{:>6} {:>16} {:>16} {:>10} {:>10}",
+                n, vec_gas, chunked_gas, vec_storage, chunked_storage
+            );
+        }
+    }
+}
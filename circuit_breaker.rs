@@ -0,0 +1,135 @@
+/*!
+Circuit breaker of the staking and farming rewards.
+NOTES:
+  - If a bug is found in the reward math, the owner or the DAO halts all rewards with
+    `halt_rewards`: staking, reward tokens and farms stop emitting. Halting does not run the
+    reward math, so it works even if that math panics; the rewards accrued since the last update
+    of each pool are not emitted.
+  - `emergency_withdraw` unstakes all the caller's unlocked stake without settling rewards: the
+    pending and unclaimed rewards are forfeited and stay in the contract. It works at any time,
+    halted or not, and only relies on the recorded stake. Farmers use `emergency_withdraw_lp`,
+    which skips the reward math as well while rewards are halted.
+  - `resume_rewards` restarts emissions from the current time; nothing is emitted for the halted
+    period.
+*/
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn halt_rewards(&mut self) {
+        self.assert_owner_or_dao();
+        assert!(!self.staking.rewards_halted, "Rewards are already halted");
+        self.staking.rewards_halted = true;
+        self.farming.rewards_halted = true;
+        log!("@{} halted the rewards", env::predecessor_account_id());
+    }
+
+    pub fn resume_rewards(&mut self) {
+        self.assert_owner_or_dao();
+        assert!(self.staking.rewards_halted, "Rewards are not halted");
+        // Moves the pools to the current time while nothing is emitted.
+        self.staking.update();
+        let lp_token_ids: Vec<AccountId> = self.farming.farms.keys().cloned().collect();
+        for lp_token_id in lp_token_ids {
+            self.farming.updated_farm(&lp_token_id);
+        }
+        self.staking.rewards_halted = false;
+        self.farming.rewards_halted = false;
+        log!("@{} resumed the rewards", env::predecessor_account_id());
+    }
+
+    pub fn are_rewards_halted(&self) -> bool {
+        self.staking.rewards_halted
+    }
+
+    /// Unstakes all the caller's stake not locked in stake positions and gives up its rewards.
+    /// Returns the amount unstaked.
+    #[payable]
+    pub fn emergency_withdraw(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = self
+            .staking
+            .accounts
+            .get(&account_id)
+            .cloned()
+            .expect("Nothing staked");
+        let amount = account
+            .staked
+            .saturating_sub(self.locked_stake(&account_id));
+        assert!(amount > 0, "All the stake is locked in positions");
+        account.staked -= amount;
+        account.unclaimed = 0;
+        account.reward_per_share_paid = self.staking.acc_reward_per_share;
+        if account.staked == 0 {
+            self.staking.accounts.remove(&account_id);
+        } else {
+            self.staking
+                .accounts
+                .insert(account_id.clone(), account.clone());
+        }
+        self.staking
+            .reward_tokens
+            .forfeit(&account_id, account.staked);
+        self.staking.total_staked = self.staking.total_staked.saturating_sub(amount);
+        self.internal_unlock(&account_id, amount);
+        log!(
+            "Account @{} withdrew {} of stake in emergency",
+            account_id,
+            amount
+        );
+        amount.into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_halt_and_emergency_withdraw() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner.clone(), 1);
+        contract.fund_reward_pool(U128(1_000));
+        contract.set_reward_rate(U128(10));
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.stake(U128(100), None);
+
+        testing_env!(context.block_timestamp(10 * SECOND).build());
+        contract.halt_rewards();
+        testing_env!(context
+            .block_timestamp(20 * SECOND)
+            .attached_deposit(1)
+            .build());
+        assert_eq!(contract.get_unclaimed_rewards(owner.clone()).0, 0);
+        assert_eq!(contract.emergency_withdraw().0, 100);
+        assert_eq!(contract.get_staked_balance(owner.clone()).0, 0);
+        assert_eq!(contract.ft_balance_of(owner).0, TOTAL_SUPPLY - 1_000);
+    }
+
+    #[test]
+    fn test_resume_skips_the_halted_period() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner.clone(), 1);
+        contract.fund_reward_pool(U128(1_000));
+        contract.set_reward_rate(U128(10));
+        contract.halt_rewards();
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.stake(U128(100), None);
+
+        testing_env!(context.block_timestamp(10 * SECOND).build());
+        contract.resume_rewards();
+        testing_env!(context.block_timestamp(15 * SECOND).build());
+        assert_eq!(contract.get_unclaimed_rewards(owner).0, 50);
+    }
+}
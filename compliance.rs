@@ -0,0 +1,274 @@
+/*!
+Compliance mode: KYC checks of new receivers against an external registry.
+NOTES:
+  - Disabled by default. Once the owner or the DAO sets a registry with `set_kyc_registry`, the
+    first transfer to an account the contract has not approved yet is held: the amount moves to
+    the contract custody and the registry is asked `is_kyc_approved` for the receiver. If it
+    approves, the approval is cached and the transfer goes through with all the usual checks;
+    otherwise the amount goes back to the sender. Later transfers to a cached account are not
+    held.
+  - Holds cover `ft_transfer` and every transfer made on behalf of a holder. `ft_transfer_call`
+    cannot wait for the registry, it is rejected until the receiver is approved; anyone can ask
+    for the check of an account with `check_kyc`.
+  - If the callback fails, the hold stays and its sender takes it back with
+    `cancel_held_transfer`.
+  - `set_kyc_registry(None)` turns the mode off and keeps the cached approvals. Retiring the
+    `Compliance` module, e.g. through a governance proposal, turns it off for good.
+  - Approvals and holds are paid by the contract. Transfers to the contract account or the burn
+    account are never held.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::{LookupMap, LookupSet};
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, near_bindgen, AccountId, Balance, Gas, Promise,
+    PromiseError,
+};
+
+use crate::pool_ledger::Pool;
+use crate::retirement::Module;
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const GAS_FOR_KYC_CHECK: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_ON_KYC_CHECKED: Gas = Gas(20_000_000_000_000);
+
+#[ext_contract(ext_kyc_registry)]
+#[allow(dead_code)]
+pub trait KycRegistry {
+    fn is_kyc_approved(&self, account_id: AccountId) -> bool;
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HeldTransfer {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Compliance {
+    pub registry_id: Option<AccountId>,
+    pub approved: LookupSet<AccountId>,
+    pub held: LookupMap<u64, HeldTransfer>,
+    pub next_hold_id: u64,
+}
+
+impl Compliance {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            registry_id: None,
+            approved: LookupSet::new([prefix.as_slice(), b"a"].concat()),
+            held: LookupMap::new([prefix.as_slice(), b"h"].concat()),
+            next_hold_id: 0,
+        }
+    }
+}
+
+fn emit_compliance_event(event: &str, data: near_sdk::serde_json::Value) {
+    emit_event(EVENT_VERSION, event, data);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the KYC registry, which turns the compliance mode on, or `None` to turn it off.
+    pub fn set_kyc_registry(&mut self, registry_id: Option<AccountId>) {
+        self.assert_owner_or_dao();
+        if registry_id.is_some() {
+            self.assert_module_active(Module::Compliance);
+        }
+        self.compliance.registry_id = registry_id;
+    }
+
+    pub fn get_kyc_registry(&self) -> Option<AccountId> {
+        self.compliance.registry_id.clone()
+    }
+
+    pub fn is_kyc_approved(&self, account_id: AccountId) -> bool {
+        self.compliance.approved.contains(&account_id)
+    }
+
+    /// Asks the registry whether `account_id` is approved and caches the answer.
+    pub fn check_kyc(&mut self, account_id: AccountId) -> Promise {
+        let registry_id = self.assert_compliance_enabled();
+        self.internal_check_kyc(registry_id, account_id, None)
+    }
+
+    #[private]
+    pub fn on_kyc_checked(
+        &mut self,
+        account_id: AccountId,
+        hold_id: Option<u64>,
+        #[callback_result] approved: Result<bool, PromiseError>,
+    ) -> bool {
+        let approved = match approved {
+            Ok(approved) => approved,
+            Err(_) => {
+                self.internal_record_callback_failure("check_kyc", vec![account_id.clone()], 0);
+                false
+            }
+        };
+        if approved {
+            self.compliance.approved.insert(account_id.clone());
+        }
+        emit_compliance_event(
+            "kyc_checked",
+            json!({ "account_id": account_id, "approved": approved }),
+        );
+        // The sender may have cancelled the hold in the meantime.
+        if let Some(hold_id) = hold_id.filter(|hold_id| self.compliance.held.contains_key(hold_id))
+        {
+            let held = self.internal_release_hold(hold_id);
+            if approved {
+                self.internal_ft_transfer(
+                    &held.sender_id,
+                    held.receiver_id,
+                    held.amount.0,
+                    held.memo,
+                );
+            }
+        }
+        approved
+    }
+
+    /// Gives a held transfer back to its sender.
+    #[payable]
+    pub fn cancel_held_transfer(&mut self, hold_id: u64) {
+        assert_one_yocto();
+        let held = self
+            .compliance
+            .held
+            .get(&hold_id)
+            .expect("No held transfer found");
+        assert_eq!(
+            held.sender_id,
+            env::predecessor_account_id(),
+            "Only the sender can cancel a held transfer"
+        );
+        self.internal_release_hold(hold_id);
+    }
+
+    pub fn get_held_transfer(&self, hold_id: u64) -> Option<HeldTransfer> {
+        self.compliance.held.get(&hold_id).cloned()
+    }
+}
+
+impl Contract {
+    fn assert_compliance_enabled(&self) -> AccountId {
+        self.assert_module_active(Module::Compliance);
+        self.compliance
+            .registry_id
+            .clone()
+            .expect("The compliance mode is off")
+    }
+
+    /// The registry to ask about `receiver_id` if a transfer to it needs a KYC check.
+    fn kyc_registry_for(&self, receiver_id: &AccountId) -> Option<AccountId> {
+        if self.retired_modules.contains(&Module::Compliance)
+            || receiver_id == &env::current_account_id()
+            || self.is_burn_account(receiver_id)
+            || self.compliance.approved.contains(receiver_id)
+        {
+            return None;
+        }
+        self.compliance.registry_id.clone()
+    }
+
+    /// Panics if `receiver_id` still needs a KYC check. Used where a transfer cannot be held.
+    pub(crate) fn assert_kyc_approved(&self, receiver_id: &AccountId) {
+        assert!(
+            self.kyc_registry_for(receiver_id).is_none(),
+            "@{} is not KYC approved yet, call check_kyc first",
+            receiver_id
+        );
+    }
+
+    /// Holds the transfer if `receiver_id` needs a KYC check, returns whether it did.
+    pub(crate) fn hold_for_kyc(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: &Option<String>,
+    ) -> bool {
+        let registry_id = match self.kyc_registry_for(receiver_id) {
+            Some(registry_id) => registry_id,
+            None => return false,
+        };
+        self.freezes.assert_not_frozen(sender_id);
+        self.freezes.assert_not_frozen(receiver_id);
+        self.internal_lock_to(Pool::ComplianceHolds, sender_id, amount);
+        let hold_id = self.compliance.next_hold_id;
+        self.compliance.next_hold_id += 1;
+        let held = HeldTransfer {
+            sender_id: sender_id.clone(),
+            receiver_id: receiver_id.clone(),
+            amount: amount.into(),
+            memo: memo.clone(),
+        };
+        emit_compliance_event(
+            "transfer_held",
+            json!({ "hold_id": hold_id, "transfer": &held }),
+        );
+        self.compliance.held.insert(hold_id, held);
+        self.internal_check_kyc(registry_id, receiver_id.clone(), Some(hold_id));
+        true
+    }
+
+    fn internal_check_kyc(
+        &self,
+        registry_id: AccountId,
+        account_id: AccountId,
+        hold_id: Option<u64>,
+    ) -> Promise {
+        ext_kyc_registry::ext(registry_id)
+            .with_static_gas(GAS_FOR_KYC_CHECK)
+            .is_kyc_approved(account_id.clone())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_KYC_CHECKED)
+                    .on_kyc_checked(account_id, hold_id),
+            )
+    }
+
+    /// Removes the hold `hold_id` and gives its amount back to the sender.
+    fn internal_release_hold(&mut self, hold_id: u64) -> HeldTransfer {
+        let held = self
+            .compliance
+            .held
+            .remove(&hold_id)
+            .expect("No held transfer found");
+        self.internal_unlock_from(Pool::ComplianceHolds, &held.sender_id, held.amount.0);
+        held
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_first_transfer_is_held_until_approved() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 1);
+        contract.set_kyc_registry(Some(accounts(4)));
+        contract.ft_transfer(accounts(1), U128(10), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.get_held_transfer(0).unwrap().amount.0, 10);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        assert!(contract.on_kyc_checked(accounts(1), Some(0), Ok(true)));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 10);
+        assert!(contract.is_kyc_approved(accounts(1)));
+        assert!(contract.get_held_transfer(0).is_none());
+    }
+}
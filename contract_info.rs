@@ -0,0 +1,120 @@
+/*!
+One view of the version, the optional subsystems switched on and their key settings.
+NOTES:
+  - `get_contract_info` lets front-ends adapt to the deployment in a single call instead of
+    probing each subsystem. The subsystems keep their own detailed views.
+  - `version` is the crate version the code was built from, `state_version` the layout of the
+    state, see `migration`.
+*/
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::*;
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EnabledModules {
+    pub transfer_fee: bool,
+    pub transfer_burn: bool,
+    pub paused: bool,
+    pub launch_guard: bool,
+    pub staking: bool,
+    pub sale: bool,
+    pub bridge: bool,
+    pub farming: bool,
+    pub bonding_curve: bool,
+    pub dividends: bool,
+    pub council: bool,
+    pub timelock: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractConfig {
+    pub fee_bps: u16,
+    pub burn_bps: u16,
+    pub staking_reward_rate: U128,
+    pub max_icon_bytes: u32,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractInfo {
+    pub version: String,
+    pub state_version: u8,
+    pub owner_id: AccountId,
+    pub dao_id: Option<AccountId>,
+    pub ownership_renounced: bool,
+    pub total_supply: U128,
+    pub enabled: EnabledModules,
+    pub retired_modules: Vec<Module>,
+    pub config: ContractConfig,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_contract_info(&self) -> ContractInfo {
+        let now = env::block_timestamp();
+        let active = |module| !self.retired_modules.contains(&module);
+        let fee = &self.transfer_fee;
+        ContractInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            state_version: self.get_state_version(),
+            owner_id: self.owner_id.clone(),
+            dao_id: self.dao_id.clone(),
+            ownership_renounced: self.ownership_renounced,
+            total_supply: self.internal_total_supply().into(),
+            enabled: EnabledModules {
+                transfer_fee: active(Module::Fees) && fee.fee_bps > 0,
+                transfer_burn: active(Module::Fees) && fee.burn_enabled && fee.burn_bps > 0,
+                paused: self.get_pause().is_some(),
+                launch_guard: self
+                    .launch_guard
+                    .as_ref()
+                    .is_some_and(LaunchGuard::is_active),
+                staking: active(Module::Staking),
+                sale: active(Module::Sale)
+                    && self
+                        .sale
+                        .config
+                        .as_ref()
+                        .is_some_and(|config| now >= config.start_at.0 && now < config.end_at.0),
+                bridge: !self.bridge.paused && !self.bridge.connectors.is_empty(),
+                farming: active(Module::Farming) && !self.farming.farms.is_empty(),
+                bonding_curve: active(Module::BondingCurve) && self.bonding_curve.config.is_some(),
+                dividends: !self.dividends.tokens.is_empty(),
+                council: self.council.is_active(),
+                timelock: self.timelock.is_enabled(),
+            },
+            retired_modules: self.retired_modules.clone(),
+            config: ContractConfig {
+                fee_bps: fee.fee_bps,
+                burn_bps: fee.burn_bps,
+                staking_reward_rate: self.staking.reward_rate.into(),
+                max_icon_bytes: self.max_icon_bytes,
+            },
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_contract_info() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        contract.pause(None);
+        contract.retire_module(Module::Staking);
+
+        let info = contract.get_contract_info();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(info.enabled.paused);
+        assert!(!info.enabled.staking);
+        assert!(!info.enabled.transfer_fee);
+        assert_eq!(info.retired_modules, vec![Module::Staking]);
+    }
+}
@@ -0,0 +1,314 @@
+/*!
+M-of-N admin council.
+NOTES:
+  - Once the owner sets a council, admin methods no longer accept the owner key alone. Council
+    members propose a batch of admin calls, e.g. `set_transfer_fee` or `pause`, and the batch is
+    sent by the contract to itself once `threshold` members confirmed it. Admin checks accept
+    the contract account as the caller for that.
+  - Proposals expire `proposal_period` after creation. The batch runs as one receipt, so either
+    every call succeeds or none does. While the timelock is enabled, an approved batch is queued
+    there instead of being sent right away.
+  - Changing the council is itself an admin operation. Only the confirmations of current members
+    count.
+  - The proposing member pays for the proposal storage.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::LookupMap;
+use near_sdk::{env, log, near_bindgen, AccountId, Gas, Promise};
+
+use crate::*;
+
+/// Default proposal period of 7 days in nanoseconds.
+const DEFAULT_PROPOSAL_PERIOD: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+const MAX_COUNCIL_MEMBERS: usize = 20;
+const MAX_CALL_GAS: Gas = Gas(50_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AdminCall {
+    pub method_name: String,
+    /// JSON arguments of the call.
+    pub args: Base64VecU8,
+    pub deposit: U128,
+    pub gas: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CouncilProposal {
+    pub proposer_id: AccountId,
+    pub description: String,
+    pub calls: Vec<AdminCall>,
+    pub confirmations: Vec<AccountId>,
+    pub expires_at: U64,
+    pub executed: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CouncilInfo {
+    pub members: Vec<AccountId>,
+    pub threshold: u8,
+    pub proposal_period: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Council {
+    pub members: Vec<AccountId>,
+    pub threshold: u8,
+    pub proposal_period: u64,
+    pub proposals: LookupMap<u64, CouncilProposal>,
+    pub next_proposal_id: u64,
+}
+
+impl Council {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            members: vec![],
+            threshold: 0,
+            proposal_period: DEFAULT_PROPOSAL_PERIOD,
+            proposals: LookupMap::new(prefix),
+            next_proposal_id: 0,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.members.is_empty()
+    }
+
+    pub fn unwrap_proposal(&self, proposal_id: u64) -> CouncilProposal {
+        self.proposals
+            .get(&proposal_id)
+            .cloned()
+            .expect("Council proposal not found")
+    }
+
+    fn assert_member(&self) -> AccountId {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.members.contains(&account_id),
+            "Only council members can do this"
+        );
+        account_id
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the council. An empty council hands admin operations back to the owner key.
+    pub fn set_council(&mut self, members: Vec<AccountId>, threshold: u8) {
+        self.assert_owner();
+        assert!(
+            members.len() <= MAX_COUNCIL_MEMBERS,
+            "A council has at most {} members",
+            MAX_COUNCIL_MEMBERS
+        );
+        assert!(
+            members.is_empty() || (threshold > 0 && usize::from(threshold) <= members.len()),
+            "The threshold must be between 1 and the number of members"
+        );
+        for (i, member_id) in members.iter().enumerate() {
+            assert!(
+                !members[..i].contains(member_id),
+                "Duplicate member {}",
+                member_id
+            );
+        }
+        log!("Council set to {:?} with threshold {}", members, threshold);
+        self.council.members = members;
+        self.council.threshold = threshold;
+    }
+
+    pub fn set_council_proposal_period(&mut self, proposal_period: U64) {
+        self.assert_owner();
+        self.council.proposal_period = proposal_period.0;
+    }
+
+    /// Proposes a batch of admin calls, counting as the proposer's confirmation, and returns
+    /// the proposal id. The attached deposit covers the proposal storage.
+    #[payable]
+    pub fn propose_admin_calls(&mut self, description: String, calls: Vec<AdminCall>) -> U64 {
+        let proposer_id = self.council.assert_member();
+        assert!(!calls.is_empty(), "Proposal must have at least one call");
+        for call in calls.iter() {
+            assert!(
+                call.gas.0 <= MAX_CALL_GAS.0,
+                "A call can use at most {} gas",
+                MAX_CALL_GAS.0
+            );
+        }
+        let initial_storage = env::storage_usage();
+        let proposal_id = self.council.next_proposal_id;
+        self.council.next_proposal_id += 1;
+        self.council.proposals.insert(
+            proposal_id,
+            CouncilProposal {
+                proposer_id: proposer_id.clone(),
+                description,
+                calls,
+                confirmations: vec![],
+                expires_at: (env::block_timestamp() + self.council.proposal_period).into(),
+                executed: false,
+            },
+        );
+        log!("@{} created council proposal {}", proposer_id, proposal_id);
+        self.internal_confirm_admin_calls(proposal_id, proposer_id);
+        self.council.proposals.flush();
+        refund_deposit(env::storage_usage() - initial_storage);
+        proposal_id.into()
+    }
+
+    /// Confirms a proposal. The confirmation that reaches the threshold sends the calls.
+    pub fn confirm_admin_calls(&mut self, proposal_id: U64) {
+        let member_id = self.council.assert_member();
+        self.internal_confirm_admin_calls(proposal_id.0, member_id);
+    }
+
+    pub fn get_council(&self) -> CouncilInfo {
+        CouncilInfo {
+            members: self.council.members.clone(),
+            threshold: self.council.threshold,
+            proposal_period: self.council.proposal_period.into(),
+        }
+    }
+
+    pub fn get_council_proposal(&self, proposal_id: U64) -> Option<CouncilProposal> {
+        self.council.proposals.get(&proposal_id.0).cloned()
+    }
+
+    pub fn get_council_proposals(
+        &self,
+        from_index: U64,
+        limit: u64,
+    ) -> Vec<(U64, CouncilProposal)> {
+        (from_index.0..self.council.next_proposal_id)
+            .take(limit as usize)
+            .filter_map(|proposal_id| {
+                self.get_council_proposal(proposal_id.into())
+                    .map(|proposal| (proposal_id.into(), proposal))
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    fn internal_confirm_admin_calls(&mut self, proposal_id: u64, member_id: AccountId) {
+        let mut proposal = self.council.unwrap_proposal(proposal_id);
+        assert!(!proposal.executed, "The proposal was already executed");
+        assert!(
+            env::block_timestamp() < proposal.expires_at.0,
+            "The proposal has expired"
+        );
+        assert!(
+            !proposal.confirmations.contains(&member_id),
+            "Already confirmed"
+        );
+        proposal.confirmations.push(member_id);
+        let members = &self.council.members;
+        let confirmations = proposal
+            .confirmations
+            .iter()
+            .filter(|account_id| members.contains(account_id))
+            .count();
+        if confirmations >= usize::from(self.council.threshold) {
+            proposal.executed = true;
+            if self.timelock.is_enabled() {
+                let action_id =
+                    self.internal_queue_action(proposal.calls.clone(), env::current_account_id());
+                log!(
+                    "Council proposal {} queued as timelock action {}",
+                    proposal_id,
+                    action_id
+                );
+            } else {
+                send_admin_calls(&proposal.calls);
+                log!("Council proposal {} executed", proposal_id);
+            }
+        }
+        self.council.proposals.insert(proposal_id, proposal);
+    }
+
+    /// Panics unless the caller is the contract itself, sending admin calls approved by the
+    /// council or released by the timelock.
+    pub(crate) fn assert_routed_admin_call(&self) {
+        let reason = if self.timelock.is_enabled() {
+            "Admin operations must go through the timelock"
+        } else {
+            "Admin operations need council approval"
+        };
+        assert!(
+            env::predecessor_account_id() == env::current_account_id(),
+            "{}",
+            reason
+        );
+    }
+}
+
+/// Sends `calls` from the contract to itself as a single batch.
+pub(crate) fn send_admin_calls(calls: &[AdminCall]) -> Promise {
+    calls
+        .iter()
+        .fold(Promise::new(env::current_account_id()), |promise, call| {
+            promise.function_call(
+                call.method_name.clone(),
+                call.args.0.clone(),
+                call.deposit.0,
+                Gas(call.gas.0),
+            )
+        })
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = test_fixtures::setup(&[]);
+        contract.set_council(vec![accounts(1), accounts(2), accounts(3)], 2);
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "Admin operations need council approval")]
+    fn test_owner_key_alone_is_not_enough() {
+        let (_, mut contract) = setup();
+        contract.set_transfer_fee(100);
+    }
+
+    #[test]
+    fn test_council_executes_admin_calls() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let proposal_id = contract.propose_admin_calls(
+            "Set a 1% transfer fee".to_string(),
+            vec![AdminCall {
+                method_name: "set_transfer_fee".to_string(),
+                args: br#"{"bps": 100}"#.to_vec().into(),
+                deposit: U128(0),
+                gas: U64(10_000_000_000_000),
+            }],
+        );
+        assert!(!contract.get_council_proposal(proposal_id).unwrap().executed);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0)
+            .build());
+        contract.confirm_admin_calls(proposal_id);
+        assert!(contract.get_council_proposal(proposal_id).unwrap().executed);
+        assert!(get_created_receipts()
+            .iter()
+            .any(|receipt| receipt.receiver_id == accounts(0)));
+
+        // The call the contract sent to itself.
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.set_transfer_fee(100);
+        assert_eq!(contract.get_transfer_fee().fee_bps, 100);
+    }
+}
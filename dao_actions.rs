@@ -0,0 +1,124 @@
+/*!
+Adapter for Sputnik DAO proposals acting on the token.
+NOTES:
+  - A Sputnik DAO set as `dao_id` runs admin actions through a `FunctionCall` proposal calling
+    `act_on_proposal` with the JSON of one `DaoAction`. Only the DAO can call it, and only
+    directly: with the timelock enabled, DAO calls are queued like any other admin call.
+  - The JSON is parsed strictly: unknown actions, unknown fields and missing required fields are
+    rejected, so a malformed proposal fails instead of doing something else.
+  - Mints count against the minter allowance of the DAO and the emission schedule. No action
+    is accepted once ownership is renounced.
+*/
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json;
+use near_sdk::{env, log, near_bindgen, AccountId};
+
+use crate::*;
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde", deny_unknown_fields)]
+pub enum DaoAction {
+    Mint {
+        receiver_id: AccountId,
+        amount: U128,
+    },
+    Pause {
+        expires_at: Option<U64>,
+    },
+    Unpause,
+    SetTransferFee {
+        bps: u16,
+    },
+    SetTransferBurnBps {
+        bps: u16,
+    },
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Runs the admin action of an approved DAO proposal, e.g.
+    /// `{"Mint": {"receiver_id": "alice.near", "amount": "100"}}`.
+    pub fn act_on_proposal(&mut self, action_json: String) {
+        let dao_id = self.assert_dao();
+        let action: DaoAction = serde_json::from_str(&action_json)
+            .unwrap_or_else(|err| env::panic_str(&format!("Invalid DAO action: {}", err)));
+        match action {
+            DaoAction::Mint {
+                receiver_id,
+                amount,
+            } => {
+                self.internal_mint(&dao_id, &receiver_id, amount.0);
+                log!("The DAO minted {} to @{}", amount.0, receiver_id);
+            }
+            DaoAction::Pause { expires_at } => self.internal_pause(expires_at),
+            DaoAction::Unpause => self.internal_unpause(),
+            DaoAction::SetTransferFee { bps } => {
+                self.internal_set_transfer_fee(bps);
+                log!("The DAO set the transfer fee to {} bps", bps);
+            }
+            DaoAction::SetTransferBurnBps { bps } => {
+                self.internal_set_transfer_burn_bps(bps);
+                log!("The DAO set the transfer burn to {} bps", bps);
+            }
+        }
+    }
+}
+
+impl Contract {
+    /// Panics unless the caller is the DAO, or the timelock executing a call. Returns the DAO.
+    fn assert_dao(&self) -> AccountId {
+        self.assert_not_renounced();
+        let dao_id = self.dao_id.clone().expect("No DAO is set");
+        if self.timelock.is_enabled() {
+            self.assert_routed_admin_call();
+        } else {
+            assert_eq!(
+                env::predecessor_account_id(),
+                dao_id,
+                "Only the DAO can act on proposals"
+            );
+        }
+        dao_id
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    fn setup() -> Contract {
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, owner(), 0);
+        contract.set_dao(Some(accounts(1)));
+        call_as(&mut context, accounts(1), 0);
+        contract
+    }
+
+    #[test]
+    fn test_dao_actions() {
+        let mut contract = setup();
+        contract.act_on_proposal(r#"{"SetTransferFee": {"bps": 50}}"#.to_string());
+        assert_eq!(contract.get_transfer_fee().fee_bps, 50);
+        contract.act_on_proposal(r#"{"Pause": {"expires_at": null}}"#.to_string());
+        assert!(contract.get_pause().is_some());
+        contract.act_on_proposal(r#""Unpause""#.to_string());
+        assert!(contract.get_pause().is_none());
+        let action = format!(
+            r#"{{"Mint": {{"receiver_id": "{}", "amount": "7"}}}}"#,
+            OWNER_ID
+        );
+        contract.act_on_proposal(action);
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY + 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid DAO action")]
+    fn test_unknown_fields_are_rejected() {
+        let mut contract = setup();
+        contract.act_on_proposal(r#"{"SetTransferFee": {"bps": 50, "to": "bob"}}"#.to_string());
+    }
+}
@@ -0,0 +1,123 @@
+/*!
+Export of the state kept about an account, for data access requests.
+NOTES:
+  - `export_account_data` gathers in one document everything stored under the account id:
+    balances, storage, staking, locks, delegation, freezes, limits, keys, recovery settings and
+    the roles granted to the account.
+  - Records kept under their own ids rather than the account id are not included: streams,
+    escrows, payment links, airdrop claims, allowances, votes, matching contributions and failed
+    transfer calls. They are available from their module views.
+*/
+use near_contract_standards::storage_management::{StorageBalance, StorageManagement};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{near_bindgen, AccountId, PublicKey};
+
+use crate::freezes::FreezeRecord;
+use crate::receiver_capabilities::ReceiverCapabilities;
+use crate::recovery::{GuardianConfig, GuardianSet, RecoveryRequest};
+use crate::stake_positions::StakePositionView;
+use crate::storage_grace::StorageShortfall;
+use crate::transfer_limits::TransferAllowance;
+use crate::*;
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountRoles {
+    pub mint_submitter: bool,
+    pub guardian_service: bool,
+    pub exchange: bool,
+    pub allowed_receiver: bool,
+    pub fee_exempt: bool,
+    pub transfer_limit_exempt: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountDataExport {
+    pub account_id: AccountId,
+    pub balance: U128,
+    pub storage_balance: Option<StorageBalance>,
+    pub storage_shortfall: StorageShortfall,
+    pub last_active_epoch: Option<U64>,
+    pub staked: U128,
+    pub unclaimed_rewards: U128,
+    pub locked_stake: U128,
+    pub stake_positions: Vec<StakePositionView>,
+    pub delegate: AccountId,
+    pub votes: U128,
+    pub frozen: bool,
+    pub freeze_history: Vec<FreezeRecord>,
+    pub transfer_allowance: TransferAllowance,
+    pub signing_key: Option<PublicKey>,
+    pub transfer_nonce: U64,
+    pub guardian: Option<GuardianConfig>,
+    pub recovery_guardians: Option<GuardianSet>,
+    pub recovery_request: Option<RecoveryRequest>,
+    pub receiver_capabilities: Option<ReceiverCapabilities>,
+    pub roles: AccountRoles,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn export_account_data(&self, account_id: AccountId) -> AccountDataExport {
+        let id = || account_id.clone();
+        AccountDataExport {
+            balance: self.ft_balance_of(id()),
+            storage_balance: self.storage_balance_of(id()),
+            storage_shortfall: self.get_storage_shortfall(id()),
+            last_active_epoch: self.get_last_active_epoch(id()),
+            staked: self.get_staked_balance(id()),
+            unclaimed_rewards: self.get_unclaimed_rewards(id()),
+            locked_stake: self.get_locked_stake(id()),
+            stake_positions: self.get_stake_positions(id(), None, None),
+            delegate: self.get_delegate(id()),
+            votes: self.get_votes(id()),
+            frozen: self.is_frozen(id()),
+            freeze_history: self.get_freeze_history(id()),
+            transfer_allowance: self.get_transfer_allowance(id()),
+            signing_key: self.get_signing_key(id()),
+            transfer_nonce: self.get_transfer_nonce(id()),
+            guardian: self.get_guardian(id()),
+            recovery_guardians: self.get_recovery_guardians(id()),
+            recovery_request: self.get_recovery_request(id()),
+            receiver_capabilities: self.get_receiver_capabilities(id()),
+            roles: AccountRoles {
+                mint_submitter: self.is_mint_submitter(id()),
+                guardian_service: self.recovery.guardian_services.contains(&account_id),
+                exchange: self.buyback.is_exchange(&account_id),
+                allowed_receiver: self.is_allowed_receiver(id()),
+                fee_exempt: self.is_fee_exempt(id()),
+                transfer_limit_exempt: self.is_transfer_limit_exempt(id()),
+            },
+            account_id,
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{owner, setup};
+
+    #[test]
+    fn test_export_account_data() {
+        let owner = owner();
+        let (_, mut contract) = setup(&[]);
+        contract.stake(U128(1_000), None);
+        contract.add_mint_submitter(owner.clone());
+
+        let export = contract.export_account_data(owner.clone());
+        assert_eq!(export.balance.0, TOTAL_SUPPLY - 1_000);
+        assert_eq!(export.staked.0, 1_000);
+        assert!(export.storage_balance.is_some());
+        assert!(export.roles.mint_submitter);
+        assert!(!export.frozen);
+
+        let export = contract.export_account_data(accounts(1));
+        assert_eq!(export.balance.0, 0);
+        assert!(export.storage_balance.is_none());
+    }
+}
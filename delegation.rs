@@ -0,0 +1,223 @@
+/*!
+Delegation of governance voting power.
+NOTES:
+  - By default an account votes with its own balance. After `delegate(to)` its balance counts
+    towards `to` instead, until it delegates back to itself.
+  - Accounts involved in a delegation keep a voting power history next to the balance
+    checkpoints. It is updated incrementally on every balance change, so `get_votes` is O(1).
+  - An account without a voting power history has the voting power of its balance history, so
+    the power at heights before its first delegation falls back to `ft_balance_of_at`.
+  - The delegator pays for the storage of the delegation; later updates are paid by the contract
+    like balance checkpoints.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::store::LookupMap;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, BlockHeight};
+
+use crate::checkpoints::{history_at, record_history, Checkpoint};
+use crate::chunked::ChunkedHistory;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Delegation {
+    /// Delegatee of each account that does not vote for itself.
+    pub delegates: LookupMap<AccountId, AccountId>,
+    pub votes: ChunkedHistory<AccountId, Checkpoint>,
+}
+
+impl Delegation {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            delegates: LookupMap::new([prefix.as_slice(), b"d"].concat()),
+            votes: ChunkedHistory::new([prefix.as_slice(), b"v"].concat()),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Delegates the caller's voting power to `to`. Delegating to oneself revokes a delegation.
+    /// The attached deposit covers the delegation storage.
+    #[payable]
+    pub fn delegate(&mut self, to: AccountId) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.token.accounts.contains_key(&account_id),
+            "The account {} is not registered",
+            account_id
+        );
+        let previous_id = self.delegate_of(&account_id);
+        assert_ne!(previous_id, to, "Already delegated to @{}", to);
+        let initial_storage = env::storage_usage();
+        for id in [&account_id, &previous_id, &to] {
+            self.start_vote_history(id);
+        }
+        let balance = self.token.accounts.get(&account_id).unwrap_or(0);
+        let keep_from = self.governance.oldest_snapshot();
+        let previous_votes = self.current_votes(&previous_id);
+        record_history(
+            &mut self.delegation.votes,
+            &previous_id,
+            previous_votes,
+            previous_votes - balance,
+            keep_from,
+        );
+        let votes = self.current_votes(&to);
+        record_history(
+            &mut self.delegation.votes,
+            &to,
+            votes,
+            votes + balance,
+            keep_from,
+        );
+        if to == account_id {
+            self.delegation.delegates.remove(&account_id);
+        } else {
+            self.delegation
+                .delegates
+                .insert(account_id.clone(), to.clone());
+        }
+        self.delegation.delegates.flush();
+        self.delegation.votes.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        log!("@{} delegated {} votes to @{}", account_id, balance, to);
+    }
+
+    pub fn get_delegate(&self, account_id: AccountId) -> AccountId {
+        self.delegate_of(&account_id)
+    }
+
+    pub fn get_votes(&self, account_id: AccountId) -> U128 {
+        self.current_votes(&account_id).into()
+    }
+
+    pub fn get_votes_at(&self, account_id: AccountId, block_height: U64) -> U128 {
+        self.votes_at(&account_id, block_height.0).into()
+    }
+}
+
+impl Contract {
+    fn delegate_of(&self, account_id: &AccountId) -> AccountId {
+        self.delegation
+            .delegates
+            .get(account_id)
+            .cloned()
+            .unwrap_or_else(|| account_id.clone())
+    }
+
+    fn current_votes(&self, account_id: &AccountId) -> Balance {
+        self.delegation
+            .votes
+            .last(account_id)
+            .map(|checkpoint| checkpoint.balance)
+            .unwrap_or_else(|| self.token.accounts.get(account_id).unwrap_or(0))
+    }
+
+    pub(crate) fn votes_at(&self, account_id: &AccountId, block_height: BlockHeight) -> Balance {
+        history_at(&self.delegation.votes, account_id, block_height).unwrap_or_else(|| {
+            self.ft_balance_of_at(account_id.clone(), block_height.into())
+                .0
+        })
+    }
+
+    /// Starts the voting power history of `account_id` with its balance at the current block.
+    fn start_vote_history(&mut self, account_id: &AccountId) {
+        if self.delegation.votes.len(account_id) == 0 {
+            let balance = self.token.accounts.get(account_id).unwrap_or(0);
+            self.delegation.votes.push(
+                account_id,
+                Checkpoint {
+                    block_height: env::block_height(),
+                    balance,
+                },
+                |_| true,
+            );
+        }
+    }
+
+    /// Moves the voting power of a balance change of `account_id` to its delegatee.
+    pub(crate) fn move_votes(
+        &mut self,
+        account_id: &AccountId,
+        previous: Balance,
+        balance: Balance,
+    ) {
+        if previous == balance || self.delegation.votes.len(account_id) == 0 {
+            // Accounts never involved in a delegation vote with their balance history.
+            return;
+        }
+        let delegate_id = self.delegate_of(account_id);
+        let votes = self
+            .delegation
+            .votes
+            .last(&delegate_id)
+            .expect("Delegatee has no voting power history")
+            .balance;
+        let keep_from = self.governance.oldest_snapshot();
+        record_history(
+            &mut self.delegation.votes,
+            &delegate_id,
+            votes,
+            votes - previous + balance,
+            keep_from,
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{owner, setup, ONE_NEAR};
+
+    #[test]
+    fn test_delegated_votes_follow_transfers() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        testing_env!(context.block_index(20).build());
+        contract.delegate(accounts(1));
+        assert_eq!(contract.get_votes(accounts(1)).0, TOTAL_SUPPLY);
+        assert_eq!(contract.get_votes(owner.clone()).0, 0);
+
+        testing_env!(context.attached_deposit(1).block_index(30).build());
+        contract.ft_transfer(accounts(1), U128(100), None);
+        assert_eq!(contract.get_votes(accounts(1)).0, TOTAL_SUPPLY);
+
+        assert_eq!(
+            contract.get_votes_at(owner.clone(), U64(15)).0,
+            TOTAL_SUPPLY
+        );
+        assert_eq!(contract.get_votes_at(accounts(1), U64(15)).0, 0);
+        assert_eq!(contract.get_votes_at(accounts(1), U64(25)).0, TOTAL_SUPPLY);
+
+        testing_env!(context.attached_deposit(ONE_NEAR).block_index(40).build());
+        contract.delegate(owner.clone());
+        assert_eq!(contract.get_votes(owner).0, TOTAL_SUPPLY - 100);
+        assert_eq!(contract.get_votes(accounts(1)).0, 100);
+    }
+
+    #[test]
+    fn test_forced_unregister_burns_delegated_votes() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        testing_env!(context.attached_deposit(1).block_index(20).build());
+        contract.ft_transfer(accounts(1), U128(100), None);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR)
+            .block_index(30)
+            .build());
+        contract.delegate(accounts(2));
+        assert_eq!(contract.get_votes(accounts(2)).0, 100);
+
+        testing_env!(context.attached_deposit(1).block_index(40).build());
+        assert!(contract.storage_unregister(Some(true)));
+        assert_eq!(contract.get_votes(accounts(2)).0, 0);
+        assert_eq!(contract.get_votes_at(accounts(2), U64(35)).0, 100);
+        assert_eq!(contract.ft_total_supply_at(U64(35)).0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_total_supply_at(U64(40)).0, TOTAL_SUPPLY - 100);
+        assert!(get_logs().iter().any(|log| log.contains("\"ft_burn\"")));
+    }
+}
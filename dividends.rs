@@ -0,0 +1,275 @@
+/*!
+Revenue sharing with ASTRO holders in deposited tokens.
+NOTES:
+  - The owner or the DAO lists up to `MAX_DIVIDEND_TOKENS` dividend tokens, e.g. wNEAR or USDC.
+    A listed token sent with `ft_transfer_call` and `"dividends"` as `msg` is shared at once
+    between all holders, pro rata to their balance.
+  - Dividends use the accumulated-per-share model of staking: each token keeps an
+    `acc_dividend_per_share` and each holder remembers the value at their last settlement.
+    Holders are settled before every balance change, so no snapshot is needed and
+    `claim_dividends` is O(1).
+  - Tokens held by the contract account, i.e. the treasury, stakes, locks and escrows, get no
+    dividends: the eligible supply is the total supply without the contract balance.
+  - Listed tokens cannot be removed, so dividends already shared stay claimable.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{
+    assert_one_yocto, env, is_promise_success, log, near_bindgen, AccountId, Balance, Gas,
+};
+
+use crate::rescue::ext_ft;
+use crate::*;
+
+/// Fixed point precision of `acc_dividend_per_share`. It is higher than for staking rewards as
+/// dividend tokens can have fewer decimals than ASTRO, so products go through `mul_div`.
+const ACC_DIVIDEND_PRECISION: u128 = 1_000_000_000_000_000_000_000_000;
+const MAX_DIVIDEND_TOKENS: usize = 5;
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_ON_DIVIDENDS_CLAIMED: Gas = Gas(10_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DividendToken {
+    pub token_id: AccountId,
+    pub acc_dividend_per_share: U128,
+    pub total_distributed: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Default)]
+pub struct HolderDividend {
+    /// Value of `acc_dividend_per_share` when the dividends of this holder were last settled.
+    pub dividend_per_share_paid: Balance,
+    /// Dividends settled but not claimed yet.
+    pub unclaimed: Balance,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Dividends {
+    pub tokens: Vec<DividendToken>,
+    /// Dividends of each holder, in the order of `tokens`.
+    pub holders: LookupMap<AccountId, Vec<HolderDividend>>,
+}
+
+impl Dividends {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            tokens: vec![],
+            holders: LookupMap::new(prefix),
+        }
+    }
+
+    fn token_index(&self, token_id: &AccountId) -> Option<usize> {
+        self.tokens
+            .iter()
+            .position(|token| &token.token_id == token_id)
+    }
+
+    fn unwrap_token_index(&self, token_id: &AccountId) -> usize {
+        self.token_index(token_id)
+            .unwrap_or_else(|| env::panic_str("Not a dividend token"))
+    }
+
+    /// Dividends of `account_id` for the token at `index`, settled for a balance of `balance`.
+    fn settled(&self, account_id: &AccountId, index: usize, balance: Balance) -> HolderDividend {
+        let holder = self
+            .holders
+            .get(account_id)
+            .and_then(|holder| holder.get(index))
+            .copied()
+            .unwrap_or_default();
+        let acc_dividend_per_share = self.tokens[index].acc_dividend_per_share.0;
+        HolderDividend {
+            dividend_per_share_paid: acc_dividend_per_share,
+            unclaimed: holder.unclaimed
+                + mul_div(
+                    balance,
+                    acc_dividend_per_share - holder.dividend_per_share_paid,
+                    ACC_DIVIDEND_PRECISION,
+                ),
+        }
+    }
+
+    /// Moves the pending dividends of `account_id`, holding `balance`, into `unclaimed`.
+    pub fn settle(&mut self, account_id: &AccountId, balance: Balance) {
+        if self.tokens.is_empty() {
+            return;
+        }
+        let holder: Vec<HolderDividend> = (0..self.tokens.len())
+            .map(|index| self.settled(account_id, index, balance))
+            .collect();
+        self.holders.insert(account_id.clone(), holder);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn add_dividend_token(&mut self, token_id: AccountId) {
+        self.assert_owner_or_dao();
+        assert!(
+            self.dividends.token_index(&token_id).is_none(),
+            "Already a dividend token"
+        );
+        assert!(
+            self.dividends.tokens.len() < MAX_DIVIDEND_TOKENS,
+            "At most {} dividend tokens",
+            MAX_DIVIDEND_TOKENS
+        );
+        log!("Added dividend token {}", token_id);
+        self.dividends.tokens.push(DividendToken {
+            token_id,
+            acc_dividend_per_share: U128(0),
+            total_distributed: U128(0),
+        });
+    }
+
+    /// Sends the caller's dividends in `token_id`. Returns the amount claimed.
+    #[payable]
+    pub fn claim_dividends(&mut self, token_id: AccountId) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let index = self.dividends.unwrap_token_index(&token_id);
+        let balance = self.token.ft_balance_of(account_id.clone()).0;
+        self.dividends.settle(&account_id, balance);
+        let mut holder = self.dividends.holders.get(&account_id).unwrap().clone();
+        let amount = std::mem::take(&mut holder[index].unclaimed);
+        assert!(amount > 0, "No dividends to claim");
+        self.dividends.holders.insert(account_id.clone(), holder);
+        log!(
+            "Account @{} claimed {} of {} in dividends",
+            account_id,
+            amount,
+            token_id
+        );
+        ext_ft::ext(token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(
+                account_id.clone(),
+                amount.into(),
+                Some("Dividends".to_string()),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_DIVIDENDS_CLAIMED)
+                    .on_dividends_claimed(account_id, token_id, amount.into()),
+            );
+        amount.into()
+    }
+
+    /// Gives back the dividends of a failed claim.
+    #[private]
+    pub fn on_dividends_claimed(
+        &mut self,
+        account_id: AccountId,
+        token_id: AccountId,
+        amount: U128,
+    ) {
+        if !is_promise_success() {
+            let index = self.dividends.unwrap_token_index(&token_id);
+            let mut holder = self.dividends.holders.get(&account_id).unwrap().clone();
+            holder[index].unclaimed += amount.0;
+            self.dividends.holders.insert(account_id.clone(), holder);
+            log!("Dividend claim of @{} failed", account_id);
+            self.internal_record_callback_failure(
+                "claim_dividends",
+                vec![account_id, token_id],
+                amount.0,
+            );
+        }
+    }
+
+    pub fn claimable_dividends(&self, account_id: AccountId, token_id: AccountId) -> U128 {
+        let index = self.dividends.unwrap_token_index(&token_id);
+        let balance = self.token.ft_balance_of(account_id.clone()).0;
+        self.dividends
+            .settled(&account_id, index, balance)
+            .unclaimed
+            .into()
+    }
+
+    pub fn get_dividend_tokens(&self) -> Vec<DividendToken> {
+        self.dividends.tokens.clone()
+    }
+}
+
+impl Contract {
+    /// Shares `amount` of `token_id` between the holders.
+    pub(crate) fn internal_distribute_dividends(&mut self, token_id: &AccountId, amount: Balance) {
+        let index = self.dividends.unwrap_token_index(token_id);
+        let eligible_supply = self.token.total_supply
+            - self
+                .token
+                .accounts
+                .get(&env::current_account_id())
+                .unwrap_or(0);
+        assert!(eligible_supply > 0, "No holders to share dividends with");
+        let token = &mut self.dividends.tokens[index];
+        token.acc_dividend_per_share = (token.acc_dividend_per_share.0
+            + mul_div(amount, ACC_DIVIDEND_PRECISION, eligible_supply))
+        .into();
+        token.total_distributed = (token.total_distributed.0 + amount).into();
+        log!("Shared {} of {} as dividends", amount, token_id);
+    }
+
+    /// Settles the dividends of `account_ids` before their balances change.
+    pub(crate) fn settle_dividends(&mut self, account_ids: &[AccountId], balances: &[Balance]) {
+        let contract_id = env::current_account_id();
+        for (account_id, balance) in account_ids.iter().zip(balances) {
+            if account_id != &contract_id {
+                self.dividends.settle(account_id, *balance);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(mul_div(7, 3, 2), 10);
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), u128::MAX);
+        assert_eq!(
+            mul_div(
+                1_000_000_000_000_000_000_000_000_000_000,
+                ACC_DIVIDEND_PRECISION,
+                90_000_000_000_000_000_000_000_000
+            ),
+            11_111_111_111_111_111_111_111_111_111
+        );
+    }
+
+    #[test]
+    fn test_dividends_follow_transfers() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        contract.add_dividend_token(accounts(5));
+
+        // The owner holds the whole supply when the first dividends are shared. The amounts
+        // divide the supply, so no dividends are lost to rounding.
+        testing_env!(context.predecessor_account_id(accounts(5)).build());
+        contract.internal_distribute_dividends(&accounts(5), 900);
+        call_as(&mut context, owner.clone(), 1);
+        contract.ft_transfer(accounts(1), U128(TOTAL_SUPPLY / 2), None);
+        contract.internal_distribute_dividends(&accounts(5), 900);
+
+        assert_eq!(
+            contract.claimable_dividends(owner.clone(), accounts(5)).0,
+            1_350
+        );
+        assert_eq!(
+            contract.claimable_dividends(accounts(1), accounts(5)).0,
+            450
+        );
+        assert_eq!(contract.claim_dividends(accounts(5)).0, 1_350);
+        assert_eq!(contract.claimable_dividends(owner, accounts(5)).0, 0);
+    }
+}
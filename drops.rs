@@ -0,0 +1,218 @@
+/*!
+Linkdrop-style drops: tokens escrowed under a public key, claimed with a signature of its key.
+NOTES:
+  - The creator generates a key pair, calls `create_drop` with the public key and shares the
+    secret key in a claim link. Like payment links, the creator attaches enough deposit to cover
+    the drop storage and the registration of the claimer, refunded if the claimer is already
+    registered.
+  - The claimer signs the Borsh serialization of the contract account id followed by the account
+    id receiving the tokens. The signature binds the receiver, so anyone can submit the claim and
+    pay the gas: a relayer can onboard accounts that hold no NEAR, and the claim cannot be
+    redirected by whoever sees it.
+  - The creator can cancel an unclaimed drop to get the tokens and the deposit back. Drops are
+    part of the payment links module and stop being created when it is retired.
+*/
+use std::convert::TryInto;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{
+    assert_one_yocto, env, log, near_bindgen, AccountId, Balance, CurveType, PublicKey,
+};
+
+use crate::meta_transactions::ed25519_verify;
+use crate::pool_ledger::Pool;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenDrop {
+    pub creator_id: AccountId,
+    pub amount: U128,
+    /// NEAR reserved to register the claimer with the token.
+    pub registration_deposit: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Drops {
+    pub drops: LookupMap<PublicKey, TokenDrop>,
+}
+
+impl Drops {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            drops: LookupMap::new(prefix),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Escrows `amount` of the caller's tokens under the ed25519 `public_key`. The attached
+    /// deposit must cover the drop storage and the minimum storage balance of a new account.
+    #[payable]
+    pub fn create_drop(&mut self, public_key: PublicKey, amount: U128) {
+        self.assert_module_active(Module::PaymentLinks);
+        let amount: Balance = amount.into();
+        assert!(amount > 0, "The amount should be a positive number");
+        assert!(
+            public_key.curve_type() == CurveType::ED25519,
+            "Only ed25519 keys are supported"
+        );
+        assert!(
+            !self.drops.drops.contains_key(&public_key),
+            "A drop already exists for this key"
+        );
+        let initial_storage = env::storage_usage();
+        let creator_id = env::predecessor_account_id();
+        let registration_deposit = self.storage_balance_bounds().min.0;
+        self.internal_lock_to(Pool::Drops, &creator_id, amount);
+        self.rescue.hold(registration_deposit);
+        self.drops.drops.insert(
+            public_key,
+            TokenDrop {
+                creator_id: creator_id.clone(),
+                amount: amount.into(),
+                registration_deposit: registration_deposit.into(),
+            },
+        );
+        self.drops.drops.flush();
+        log!("Account @{} created a drop of {}", creator_id, amount);
+        let storage_used = env::storage_usage() - initial_storage;
+        let storage_cost = env::storage_byte_cost() * Balance::from(storage_used);
+        assert!(
+            env::attached_deposit() >= storage_cost + registration_deposit,
+            "Must attach {} yoctoNEAR to cover storage and registration",
+            storage_cost + registration_deposit
+        );
+        let refund = env::attached_deposit() - storage_cost - registration_deposit;
+        if refund > 1 {
+            Promise::new(creator_id).transfer(refund);
+        }
+    }
+
+    /// Sends the tokens of the drop of `public_key` to `account_id`, registering it with the
+    /// token if needed. `signature` is the signature of the claim by the drop key.
+    pub fn claim_drop(
+        &mut self,
+        public_key: PublicKey,
+        account_id: AccountId,
+        signature: Base64VecU8,
+    ) -> U128 {
+        let drop = self.unwrap_drop(&public_key);
+        let signature: [u8; 64] = signature
+            .0
+            .as_slice()
+            .try_into()
+            .expect("The signature must be 64 bytes");
+        let key: [u8; 32] = public_key.as_bytes()[1..].try_into().unwrap();
+        let message = (env::current_account_id(), &account_id)
+            .try_to_vec()
+            .unwrap();
+        assert!(
+            ed25519_verify(&signature, &message, &key),
+            "Invalid signature"
+        );
+        self.remove_drop(&public_key, &drop);
+        if self.token.accounts.contains_key(&account_id) {
+            Promise::new(drop.creator_id.clone()).transfer(drop.registration_deposit.0);
+        } else {
+            self.internal_register_account(&account_id);
+        }
+        self.internal_unlock_from(Pool::Drops, &account_id, drop.amount.0);
+        log!(
+            "Account @{} claimed {} from a drop of @{}",
+            account_id,
+            drop.amount.0,
+            drop.creator_id
+        );
+        drop.amount
+    }
+
+    /// Returns the tokens and the deposits of an unclaimed drop to its creator.
+    #[payable]
+    pub fn cancel_drop(&mut self, public_key: PublicKey) {
+        assert_one_yocto();
+        let drop = self.unwrap_drop(&public_key);
+        assert_eq!(
+            env::predecessor_account_id(),
+            drop.creator_id,
+            "Only the creator can cancel a drop"
+        );
+        self.remove_drop(&public_key, &drop);
+        self.internal_unlock_from(Pool::Drops, &drop.creator_id, drop.amount.0);
+        Promise::new(drop.creator_id.clone()).transfer(drop.registration_deposit.0);
+        log!("Drop of @{} cancelled", drop.creator_id);
+    }
+
+    pub fn get_drop(&self, public_key: PublicKey) -> Option<TokenDrop> {
+        self.drops.drops.get(&public_key).cloned()
+    }
+}
+
+impl Contract {
+    fn unwrap_drop(&self, public_key: &PublicKey) -> TokenDrop {
+        self.drops
+            .drops
+            .get(public_key)
+            .cloned()
+            .expect("Drop not found")
+    }
+
+    /// Deletes a drop, releasing the registration deposit and refunding its storage.
+    fn remove_drop(&mut self, public_key: &PublicKey, drop: &TokenDrop) {
+        let initial_storage = env::storage_usage();
+        self.drops.drops.remove(public_key);
+        self.drops.drops.flush();
+        self.rescue.release(drop.registration_deposit.0);
+        refund_released_storage(&drop.creator_id, initial_storage);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as};
+
+    /// Signature of the claim for `accounts(3)` with the RFC 8032 test key 1, whose public key
+    /// is below.
+    const SIGNATURE: &str =
+        "wMylCfVNuTBUDrrA69Lo2shit/KkM836Sry/Ggsztmt5cuvEoN8H8OhfnfAxkua/4A94sQq2hZoz1CzwyIRBCg==";
+    const PUBLIC_KEY: &str = "ed25519:FVen3X669xLzsi6N2V91DoiyzHzg1uAgqiT8jZ9nS96Z";
+
+    fn signature() -> Base64VecU8 {
+        near_sdk::serde_json::from_str(&format!("\"{}\"", SIGNATURE)).unwrap()
+    }
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        contract.create_drop(PUBLIC_KEY.parse().unwrap(), U128(1_000));
+        // A relayer submits the claim.
+        call_as(&mut context, accounts(4), 0);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_claim_drop_registers_claimer() {
+        let (_, mut contract) = setup();
+        assert_eq!(
+            contract
+                .claim_drop(PUBLIC_KEY.parse().unwrap(), accounts(3), signature())
+                .0,
+            1_000
+        );
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 1_000);
+        assert!(contract.get_drop(PUBLIC_KEY.parse().unwrap()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid signature")]
+    fn test_claim_cannot_be_redirected() {
+        let (_, mut contract) = setup();
+        contract.claim_drop(PUBLIC_KEY.parse().unwrap(), accounts(4), signature());
+    }
+}
@@ -0,0 +1,193 @@
+/*!
+Sweeping of inactive accounts that only hold dust.
+NOTES:
+  - The epoch of the last registration or outgoing transfer of every account is recorded.
+    Receiving tokens does not count as activity, so dust sent to an abandoned account does not
+    keep it alive. Accounts last active before the records existed count from `since_epoch`.
+  - The owner sweeps a batch of accounts with `sweep_inactive_accounts`. Accounts that hold at
+    most `dust_threshold` and have been inactive for `min_inactive_epochs` are unregistered:
+    their dust goes to the treasury and their storage deposit stays with the contract, which
+    also gets back the released storage. Other accounts in the batch are skipped.
+  - The owner account, the contract account and stakers are never swept.
+  - Every swept account is logged as an NEP-297 event. A batch stops early if the gas runs low,
+    see `gas_budget`.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupMap;
+use near_sdk::{env, near_bindgen, AccountId, Balance, EpochHeight};
+
+use crate::gas_budget::GasBudget;
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const MAX_SWEPT_ACCOUNTS: usize = 100;
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SweepResult {
+    /// Accounts of the batch considered, from the start.
+    pub processed: u32,
+    pub swept: Vec<AccountId>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct DustSweeping {
+    pub last_active: LookupMap<AccountId, EpochHeight>,
+    /// Epoch from which activity is recorded.
+    pub since_epoch: EpochHeight,
+    /// Largest balance swept with an account.
+    pub dust_threshold: Balance,
+}
+
+impl DustSweeping {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            last_active: LookupMap::new(prefix),
+            since_epoch: env::epoch_height(),
+            dust_threshold: 0,
+        }
+    }
+
+    pub fn last_active(&self, account_id: &AccountId) -> EpochHeight {
+        self.last_active
+            .get(account_id)
+            .copied()
+            .unwrap_or(self.since_epoch)
+    }
+
+    /// Records activity of `account_id` in the current epoch.
+    pub fn touch(&mut self, account_id: &AccountId) {
+        let epoch = env::epoch_height();
+        if self.last_active.get(account_id) != Some(&epoch) {
+            self.last_active.insert(account_id.clone(), epoch);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_dust_threshold(&mut self, dust_threshold: U128) {
+        self.assert_owner();
+        self.dust_sweeping.dust_threshold = dust_threshold.0;
+    }
+
+    /// Unregisters the given accounts that hold dust and have been inactive for at least
+    /// `min_inactive_epochs`. Stops early if the gas runs low and returns the number of accounts
+    /// processed with the swept ones.
+    pub fn sweep_inactive_accounts(
+        &mut self,
+        account_ids: Vec<AccountId>,
+        min_inactive_epochs: u64,
+    ) -> SweepResult {
+        self.assert_owner();
+        assert!(
+            account_ids.len() <= MAX_SWEPT_ACCOUNTS,
+            "At most {} accounts can be swept at once",
+            MAX_SWEPT_ACCOUNTS
+        );
+        let mut budget = GasBudget::default();
+        let mut result = SweepResult {
+            processed: 0,
+            swept: vec![],
+        };
+        for account_id in account_ids {
+            if !budget.has_room() {
+                break;
+            }
+            result.processed += 1;
+            let inactive_epochs =
+                env::epoch_height().saturating_sub(self.dust_sweeping.last_active(&account_id));
+            if inactive_epochs < min_inactive_epochs || !self.is_sweepable(&account_id) {
+                continue;
+            }
+            let dust = self.internal_sweep_account(&account_id);
+            emit_event(
+                EVENT_VERSION,
+                "account_swept",
+                json!({
+                    "account_id": account_id,
+                    "dust": U128(dust),
+                    "inactive_epochs": U64(inactive_epochs),
+                }),
+            );
+            result.swept.push(account_id);
+        }
+        result
+    }
+
+    pub fn get_last_active_epoch(&self, account_id: AccountId) -> Option<U64> {
+        if self.token.accounts.contains_key(&account_id) {
+            Some(self.dust_sweeping.last_active(&account_id).into())
+        } else {
+            None
+        }
+    }
+}
+
+impl Contract {
+    fn is_sweepable(&self, account_id: &AccountId) -> bool {
+        account_id != &self.owner_id
+            && account_id != &env::current_account_id()
+            && !self.staking.accounts.contains_key(account_id)
+            && self
+                .token
+                .accounts
+                .get(account_id)
+                .is_some_and(|balance| balance <= self.dust_sweeping.dust_threshold)
+    }
+
+    /// Moves the balance of `account_id` to the treasury and unregisters it without refunding
+    /// its storage. Returns the dust moved.
+    fn internal_sweep_account(&mut self, account_id: &AccountId) -> Balance {
+        let dust = self.token.ft_balance_of(account_id.clone()).0;
+        if dust > 0 {
+            self.internal_lock(account_id, dust);
+            self.treasury_balance += dust;
+        }
+        self.token.accounts.remove(account_id);
+        self.registered_accounts.remove(account_id);
+        if let Some(top_up) = self.storage_grace.top_ups.remove(account_id) {
+            self.rescue.release(top_up);
+        }
+        self.dust_sweeping.last_active.remove(account_id);
+        self.on_account_closed(account_id.clone(), 0);
+        dust
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_sweep_inactive_dust_accounts() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner.clone(), 1);
+        contract.ft_transfer(accounts(1), U128(5), None);
+        contract.ft_transfer(accounts(2), U128(5), None);
+        contract.set_dust_threshold(U128(10));
+
+        // accounts(2) sends tokens later and stays active.
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .epoch_height(10)
+            .build());
+        contract.ft_transfer(owner.clone(), U128(1), None);
+
+        testing_env!(context.predecessor_account_id(owner).build());
+        let swept = contract.sweep_inactive_accounts(vec![accounts(1), accounts(2)], 5);
+        assert_eq!(swept.processed, 2);
+        assert_eq!(swept.swept, vec![accounts(1)]);
+        assert!(contract.get_last_active_epoch(accounts(1)).is_none());
+        assert_eq!(contract.get_last_active_epoch(accounts(2)), Some(U64(10)));
+        assert_eq!(contract.treasury_balance, 5);
+    }
+}
@@ -0,0 +1,215 @@
+/*!
+Dutch auction pricing for the sale.
+NOTES:
+  - A sale with `DutchAuction` pricing starts at the sale `price` and falls linearly to
+    `floor_price` at the end of the sale. With a `step_duration` the price falls in steps
+    instead, once per step.
+  - Buyers pay the current price when they buy, but every buyer pays the clearing price in the
+    end: the price at which the allocation sold out, or the floor if it never did. Buyers claim
+    the difference with `claim_auction_refund` once the auction cleared.
+  - Refunds are claimable until the next sale is configured. The proceeds withdrawn are the
+    tokens sold at the clearing price.
+  - An auction cannot have a presale, whose discounts are set on a fixed price.
+*/
+#![cfg_attr(not(feature = "sale"), allow(dead_code, unused_imports))]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, log, near_bindgen, Balance, Promise};
+
+use crate::sale::{Sale, SaleConfig};
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SalePricing {
+    #[default]
+    Fixed,
+    DutchAuction {
+        /// Price in yoctoNEAR of one whole ASTRO at the end of the sale.
+        floor_price: U128,
+        /// Nanoseconds between price drops. The price falls continuously if not set.
+        step_duration: Option<U64>,
+    },
+}
+
+impl SalePricing {
+    pub fn assert_valid(&self, config: &SaleConfig) {
+        if let SalePricing::DutchAuction {
+            floor_price,
+            step_duration,
+        } = self
+        {
+            assert!(
+                floor_price.0 > 0 && floor_price.0 < config.price.0,
+                "The floor price must be positive and below the start price"
+            );
+            assert!(
+                step_duration.is_none_or(|step| step.0 > 0),
+                "The step duration should be a positive number"
+            );
+            assert!(config.presale.is_none(), "An auction cannot have a presale");
+        }
+    }
+}
+
+impl SaleConfig {
+    /// Auction price at `timestamp`, from `price` at the start down to the floor at the end.
+    pub(crate) fn auction_price_at(&self, timestamp: u64) -> Balance {
+        let (floor_price, step_duration) = match &self.pricing {
+            SalePricing::DutchAuction {
+                floor_price,
+                step_duration,
+            } => (floor_price.0, *step_duration),
+            SalePricing::Fixed => return self.price.0,
+        };
+        let duration = self.end_at.0 - self.start_at.0;
+        let mut elapsed = timestamp.clamp(self.start_at.0, self.end_at.0) - self.start_at.0;
+        if let Some(step) = step_duration {
+            elapsed -= elapsed % step.0;
+        }
+        self.price.0
+            - mul_div(
+                self.price.0 - floor_price,
+                u128::from(elapsed),
+                u128::from(duration),
+            )
+    }
+}
+
+impl Sale {
+    /// Price every auction buyer pays in the end, known once the allocation sold out or the
+    /// sale ended. `None` for fixed pricing.
+    pub fn clearing_price(&self) -> Option<Balance> {
+        match self.config.as_ref().map(|config| &config.pricing) {
+            Some(SalePricing::DutchAuction { floor_price, .. }) => self
+                .sold_out_price
+                .or_else(|| Some(floor_price.0).filter(|_| self.has_ended())),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "sale")]
+#[near_bindgen]
+impl Contract {
+    /// Refunds the caller what they paid above the clearing price of the auction.
+    pub fn claim_auction_refund(&mut self) -> U128 {
+        let clearing_price = self
+            .sale
+            .clearing_price()
+            .expect("The auction has not cleared");
+        let account_id = env::predecessor_account_id();
+        let mut purchase = self.sale.purchase(&account_id);
+        assert!(purchase.amount.0 > 0, "No purchase in this auction");
+        // Rounded up, so that refunds never exceed what the proceeds leave.
+        let owed = mul_div(purchase.amount.0, clearing_price, self.one_token()) + 1;
+        let refund = purchase.paid.0.saturating_sub(owed);
+        assert!(refund > 0, "Nothing to refund");
+        purchase.paid = owed.into();
+        self.sale
+            .purchases
+            .insert((self.sale.sale_id, account_id.clone()), purchase);
+        self.sale.refunded += refund;
+        log!(
+            "Refunded {} yoctoNEAR of the auction to @{}",
+            refund,
+            account_id
+        );
+        Promise::new(account_id).transfer(refund);
+        refund.into()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Price in yoctoNEAR of one whole ASTRO in the current sale.
+    pub fn current_price(&self) -> U128 {
+        let config = self.sale.config.as_ref().expect("No sale is configured");
+        config.price_at(env::block_timestamp()).into()
+    }
+
+    pub fn get_clearing_price(&self) -> Option<U128> {
+        self.sale.clearing_price().map(U128)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "sale"))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, owner, ONE_NEAR};
+
+    const ONE_ASTRO: Balance = 1_000_000_000_000_000_000;
+
+    fn setup(step_duration: Option<U64>) -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = test_fixtures::setup(&[accounts(1), accounts(2)]);
+        contract.set_sale_config(SaleConfig {
+            price: U128(ONE_NEAR),
+            start_at: U64(0),
+            end_at: U64(1_000),
+            allocation: U128(8 * ONE_ASTRO),
+            per_wallet_cap: U128(4 * ONE_ASTRO),
+            mint: true,
+            presale: None,
+            pricing: SalePricing::DutchAuction {
+                floor_price: U128(ONE_NEAR / 10),
+                step_duration,
+            },
+            vesting: None,
+        });
+        (context, contract)
+    }
+
+    #[test]
+    fn test_price_decay() {
+        let (mut context, contract) = setup(None);
+        testing_env!(context.block_timestamp(500).build());
+        assert_eq!(contract.current_price().0, ONE_NEAR * 55 / 100);
+        testing_env!(context.block_timestamp(2_000).build());
+        assert_eq!(contract.current_price().0, ONE_NEAR / 10);
+    }
+
+    #[test]
+    fn test_price_decay_in_steps() {
+        let (mut context, contract) = setup(Some(U64(300)));
+        testing_env!(context.block_timestamp(500).build());
+        assert_eq!(contract.current_price().0, ONE_NEAR * 73 / 100);
+    }
+
+    #[test]
+    fn test_buyers_settle_at_clearing_price() {
+        let (mut context, mut contract) = setup(None);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(5 * ONE_NEAR)
+            .build());
+        assert_eq!(contract.buy(None).0, 4 * ONE_ASTRO);
+
+        // The rest sells out halfway through the auction.
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(10 * ONE_NEAR)
+            .block_timestamp(500)
+            .build());
+        assert_eq!(contract.buy(None).0, 4 * ONE_ASTRO);
+        assert_eq!(
+            contract.get_clearing_price(),
+            Some(U128(ONE_NEAR * 55 / 100))
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        assert_eq!(
+            contract.claim_auction_refund().0,
+            4 * ONE_NEAR * 45 / 100 - 1
+        );
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .block_timestamp(1_000)
+            .build());
+        assert_eq!(contract.withdraw_sale_proceeds().0, 8 * ONE_NEAR * 55 / 100);
+    }
+}
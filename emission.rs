@@ -0,0 +1,141 @@
+/*!
+Capped supply with a scheduled emission curve.
+NOTES:
+  - Until the owner sets an emission schedule, minting is unrestricted. The schedule can be set
+    once and cannot be changed afterwards.
+  - Starting from the time the schedule is set, `per_epoch` tokens become mintable at the end of
+    every epoch. Allowances that were not used carry over.
+  - The total supply can never exceed `max_supply`. Burned tokens free room under the cap but do
+    not increase the emission allowance.
+  - Remote mints do not use the emission allowance but cannot take the supply above
+    `max_supply` either. Bridge mints are not checked: they give back supply burned when leaving
+    NEAR.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, log, near_bindgen, Balance};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EmissionSchedule {
+    pub max_supply: U128,
+    pub start: U64,
+    /// Epoch length in nanoseconds.
+    pub epoch_length: U64,
+    pub per_epoch: U128,
+    /// Tokens minted since `start`.
+    pub minted: U128,
+}
+
+impl EmissionSchedule {
+    /// Tokens that can be minted at the current block given the current `total_supply`.
+    pub fn mintable(&self, total_supply: Balance) -> Balance {
+        let epochs = (env::block_timestamp() - self.start.0) / self.epoch_length.0;
+        let emitted = self.per_epoch.0.saturating_mul(Balance::from(epochs));
+        std::cmp::min(
+            emitted - self.minted.0,
+            self.max_supply.0.saturating_sub(total_supply),
+        )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Caps the supply at `max_supply` and lets `per_epoch` tokens be minted every
+    /// `epoch_length` nanoseconds from now on. Can only be called once.
+    pub fn set_emission_schedule(&mut self, max_supply: U128, epoch_length: U64, per_epoch: U128) {
+        self.assert_owner();
+        assert!(self.emission.is_none(), "Emission schedule is already set");
+        assert!(
+            max_supply.0 >= self.token.total_supply,
+            "Max supply is below the total supply"
+        );
+        assert!(epoch_length.0 > 0, "Epoch length must be positive");
+        self.emission = Some(EmissionSchedule {
+            max_supply,
+            start: env::block_timestamp().into(),
+            epoch_length,
+            per_epoch,
+            minted: U128(0),
+        });
+        log!(
+            "Emission schedule set: max supply {}, {} per epoch of {} ns",
+            max_supply.0,
+            per_epoch.0,
+            epoch_length.0
+        );
+    }
+
+    pub fn get_emission_schedule(&self) -> Option<EmissionSchedule> {
+        self.emission.clone()
+    }
+
+    pub fn max_supply(&self) -> Option<U128> {
+        self.emission.as_ref().map(|schedule| schedule.max_supply)
+    }
+
+    pub fn mintable_now(&self) -> U128 {
+        match &self.emission {
+            Some(schedule) => schedule.mintable(self.token.total_supply),
+            None => Balance::MAX - self.token.total_supply,
+        }
+        .into()
+    }
+}
+
+impl Contract {
+    /// Counts `amount` against the emission schedule. Panics if it exceeds the mintable amount.
+    pub(crate) fn use_emission(&mut self, amount: Balance) {
+        let total_supply = self.token.total_supply;
+        if let Some(schedule) = self.emission.as_mut() {
+            let mintable = schedule.mintable(total_supply);
+            assert!(
+                amount <= mintable,
+                "Mint of {} exceeds the emission schedule, mintable now: {}",
+                amount,
+                mintable
+            );
+            schedule.minted = (schedule.minted.0 + amount).into();
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_mint_follows_schedule() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner.clone(), 0);
+        contract.set_emission_schedule(U128(TOTAL_SUPPLY + 150), U64(10), U128(100));
+        assert_eq!(contract.mintable_now().0, 0);
+
+        testing_env!(context.block_timestamp(25).build());
+        assert_eq!(contract.mintable_now().0, 150);
+        contract.internal_mint(&owner, &owner, 120);
+        assert_eq!(contract.mintable_now().0, 30);
+
+        testing_env!(context.block_timestamp(1_000).build());
+        assert_eq!(contract.mintable_now().0, 30);
+        assert_eq!(contract.max_supply().unwrap().0, TOTAL_SUPPLY + 150);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds the emission schedule")]
+    fn test_mint_beyond_schedule() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner.clone(), 0);
+        contract.set_emission_schedule(U128(TOTAL_SUPPLY * 2), U64(10), U128(100));
+        testing_env!(context.block_timestamp(10).build());
+        contract.internal_mint(&owner, &owner, 101);
+    }
+}
@@ -0,0 +1,103 @@
+/*!
+Daily distribution of emissions into the staking reward pool.
+NOTES:
+  - The owner or the DAO sets the daily emission. Then anyone can call `distribute_emissions` once
+    every 24 hours to move it into the staking reward pool.
+  - With an emission schedule the daily emission is minted, within what the schedule allows at the
+    time. Without one it is released from the treasury, within the treasury balance.
+  - A day missed is not caught up: the next distribution is due 24 hours after the last one.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, log, near_bindgen, Balance};
+
+use crate::*;
+
+const DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct EmissionDistributor {
+    pub daily_amount: Balance,
+    /// Timestamp of the last distribution.
+    pub last_distribution: Option<u64>,
+}
+
+impl EmissionDistributor {
+    pub fn next_distribution(&self) -> u64 {
+        self.last_distribution
+            .map_or(env::block_timestamp(), |last| last + DAY)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_daily_emission(&mut self, amount: U128) {
+        self.assert_owner_or_dao();
+        self.emission_distributor.daily_amount = amount.0;
+    }
+
+    /// Moves the daily emission into the staking reward pool. Returns the amount distributed.
+    pub fn distribute_emissions(&mut self) -> U128 {
+        let now = env::block_timestamp();
+        let next_distribution = self.emission_distributor.next_distribution();
+        assert!(
+            now >= next_distribution,
+            "The next distribution is due at {}",
+            next_distribution
+        );
+        let daily_amount = self.emission_distributor.daily_amount;
+        assert!(daily_amount > 0, "No daily emission is set");
+        self.emission_distributor.last_distribution = Some(now);
+        let amount = if self.emission.is_some() {
+            let amount = std::cmp::min(daily_amount, self.mintable_now().0);
+            self.use_emission(amount);
+            let contract_id = env::current_account_id();
+            self.internal_register_contract_account();
+            self.with_checkpoints(std::slice::from_ref(&contract_id), |this| {
+                this.token.internal_deposit(&contract_id, amount)
+            });
+            amount
+        } else {
+            let amount = std::cmp::min(daily_amount, self.treasury_balance);
+            self.treasury_balance -= amount;
+            amount
+        };
+        self.staking.update();
+        self.staking.reward_pool += amount;
+        log!("Distributed {} of emissions to the staking rewards", amount);
+        amount.into()
+    }
+
+    pub fn get_daily_emission(&self) -> U128 {
+        self.emission_distributor.daily_amount.into()
+    }
+
+    /// Earliest timestamp of the next `distribute_emissions`.
+    pub fn get_next_distribution_time(&self) -> U64 {
+        self.emission_distributor.next_distribution().into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    #[should_panic(expected = "The next distribution is due at")]
+    fn test_distribute_once_a_day() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        contract.set_emission_schedule(U128(TOTAL_SUPPLY * 2), U64(DAY), U128(100));
+        contract.set_daily_emission(U128(100));
+
+        testing_env!(context.block_timestamp(DAY).build());
+        assert_eq!(contract.distribute_emissions().0, 100);
+        assert_eq!(contract.get_staking_info().reward_pool.0, 100);
+        assert_eq!(contract.get_next_distribution_time().0, 2 * DAY);
+        testing_env!(context.block_timestamp(2 * DAY - 1).build());
+        contract.distribute_emissions();
+    }
+}
@@ -0,0 +1,206 @@
+/*!
+Escrow for OTC deals settled in ASTRO, with an arbiter resolving disputes.
+NOTES:
+  - The depositor locks `amount` in the contract for the counterparty.
+  - While the escrow is active, the depositor or the arbiter can release the tokens to the
+    counterparty, and the counterparty or the arbiter can refund them to the depositor.
+  - Once `deadline` has passed the depositor can also refund, so funds are never stuck when the
+    other parties stop responding.
+  - Every transition is logged as an NEP-297 event.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance};
+
+use crate::pool_ledger::Pool;
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum EscrowStatus {
+    Active,
+    Released,
+    Refunded,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Escrow {
+    pub depositor_id: AccountId,
+    pub counterparty_id: AccountId,
+    pub arbiter_id: AccountId,
+    pub amount: U128,
+    pub deadline: U64,
+    pub status: EscrowStatus,
+}
+
+fn emit_escrow_event(event: &str, escrow_id: u64, escrow: &Escrow) {
+    emit_event(
+        EVENT_VERSION,
+        event,
+        json!({
+            "escrow_id": U64(escrow_id),
+            "depositor_id": escrow.depositor_id,
+            "counterparty_id": escrow.counterparty_id,
+            "arbiter_id": escrow.arbiter_id,
+            "amount": escrow.amount,
+        }),
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Locks `amount` of the caller's tokens for `counterparty` and returns the escrow id.
+    /// `deadline` is a timestamp in nanoseconds. The attached deposit covers the escrow storage.
+    #[payable]
+    pub fn create_escrow(
+        &mut self,
+        counterparty: AccountId,
+        arbiter: AccountId,
+        amount: U128,
+        deadline: U64,
+    ) -> U64 {
+        self.assert_module_active(Module::Escrows);
+        let amount: Balance = amount.into();
+        assert!(amount > 0, "The amount should be a positive number");
+        assert!(
+            deadline.0 > env::block_timestamp(),
+            "Deadline must be in the future"
+        );
+        let depositor_id = env::predecessor_account_id();
+        let counterparty_id = counterparty;
+        let arbiter_id = arbiter;
+        assert_ne!(
+            depositor_id, counterparty_id,
+            "Depositor and counterparty should be different"
+        );
+        assert!(
+            arbiter_id != depositor_id && arbiter_id != counterparty_id,
+            "Arbiter must be a third party"
+        );
+        assert!(
+            self.token.accounts.contains_key(&counterparty_id),
+            "The account {} is not registered",
+            counterparty_id
+        );
+        let initial_storage = env::storage_usage();
+        self.internal_lock_to(Pool::Escrows, &depositor_id, amount);
+        let escrow_id = self.next_escrow_id;
+        self.next_escrow_id += 1;
+        let escrow = Escrow {
+            depositor_id,
+            counterparty_id,
+            arbiter_id,
+            amount: amount.into(),
+            deadline,
+            status: EscrowStatus::Active,
+        };
+        emit_escrow_event("escrow_create", escrow_id, &escrow);
+        self.escrows.insert(escrow_id, escrow);
+        self.escrows.flush();
+        refund_deposit(env::storage_usage() - initial_storage);
+        escrow_id.into()
+    }
+
+    /// Releases the escrowed tokens to the counterparty. Callable by the depositor or arbiter.
+    #[payable]
+    pub fn release_escrow(&mut self, id: U64) {
+        assert_one_yocto();
+        let mut escrow = self.unwrap_active_escrow(id.0);
+        let caller_id = env::predecessor_account_id();
+        assert!(
+            caller_id == escrow.depositor_id || caller_id == escrow.arbiter_id,
+            "Only the depositor or the arbiter can release an escrow"
+        );
+        escrow.status = EscrowStatus::Released;
+        self.escrows.insert(id.0, escrow.clone());
+        self.internal_unlock_from(Pool::Escrows, &escrow.counterparty_id, escrow.amount.0);
+        emit_escrow_event("escrow_release", id.0, &escrow);
+    }
+
+    /// Returns the escrowed tokens to the depositor. Callable by the counterparty or arbiter at
+    /// any time, and by the depositor once the deadline has passed.
+    #[payable]
+    pub fn refund_escrow(&mut self, id: U64) {
+        assert_one_yocto();
+        let mut escrow = self.unwrap_active_escrow(id.0);
+        let caller_id = env::predecessor_account_id();
+        let timed_out = env::block_timestamp() > escrow.deadline.0;
+        assert!(
+            caller_id == escrow.counterparty_id
+                || caller_id == escrow.arbiter_id
+                || (caller_id == escrow.depositor_id && timed_out),
+            "Not allowed to refund this escrow"
+        );
+        escrow.status = EscrowStatus::Refunded;
+        self.escrows.insert(id.0, escrow.clone());
+        self.internal_unlock_from(Pool::Escrows, &escrow.depositor_id, escrow.amount.0);
+        emit_escrow_event("escrow_refund", id.0, &escrow);
+    }
+
+    pub fn get_escrow(&self, id: U64) -> Option<Escrow> {
+        self.escrows.get(&id.0).cloned()
+    }
+}
+
+impl Contract {
+    fn unwrap_active_escrow(&self, escrow_id: u64) -> Escrow {
+        let escrow = self
+            .escrows
+            .get(&escrow_id)
+            .cloned()
+            .expect("Escrow not found");
+        assert_eq!(escrow.status, EscrowStatus::Active, "Escrow is not active");
+        escrow
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, owner};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        contract.create_escrow(accounts(1), accounts(2), U128(1_000), U64(100));
+        (context, contract)
+    }
+
+    #[test]
+    fn test_arbiter_release() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.release_escrow(U64(0));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(
+            contract.get_escrow(U64(0)).unwrap().status,
+            EscrowStatus::Released
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Not allowed to refund this escrow")]
+    fn test_depositor_refund_before_deadline() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).build());
+        contract.refund_escrow(U64(0));
+    }
+
+    #[test]
+    fn test_depositor_refund_after_deadline() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).block_timestamp(101).build());
+        contract.refund_escrow(U64(0));
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY);
+    }
+}
@@ -0,0 +1,61 @@
+/*!
+Envelope of the custom events.
+NOTES:
+  - Every event of the contract other than the NEP-141 ones is logged as an NEP-297 event of the
+    `astro` standard: `EVENT_JSON:{"standard":"astro","version":..,"event":..,"data":[..]}`.
+    Indexers parse a single envelope and dispatch on the event name.
+  - Each subsystem versions its own events with its `EVENT_VERSION`, so the schema of one
+    subsystem can change without touching the others. Event names are unique across subsystems.
+  - NEP-141 `ft_mint`, `ft_burn` and `ft_transfer` events keep the `nep141` standard.
+*/
+use near_sdk::log;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::{self, Value};
+
+pub const EVENT_STANDARD: &str = "astro";
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AstroEvent<'a> {
+    pub standard: &'a str,
+    pub version: &'a str,
+    pub event: &'a str,
+    pub data: Vec<Value>,
+}
+
+impl AstroEvent<'_> {
+    pub fn emit(&self) {
+        log!("EVENT_JSON:{}", serde_json::to_string(self).unwrap());
+    }
+}
+
+/// Logs the event `event` of a subsystem whose events are at `version`.
+pub(crate) fn emit_event(version: &str, event: &str, data: Value) {
+    AstroEvent {
+        standard: EVENT_STANDARD,
+        version,
+        event,
+        data: vec![data],
+    }
+    .emit();
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::serde_json::json;
+    use near_sdk::test_utils::{get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    #[test]
+    fn test_event_envelope() {
+        testing_env!(VMContextBuilder::new().build());
+        emit_event("1.0.0", "approval", json!({ "allowance": "5" }));
+        let expected = concat!(
+            r#"EVENT_JSON:{"standard":"astro","version":"1.0.0","event":"approval","#,
+            r#""data":[{"allowance":"5"}]}"#
+        );
+        assert_eq!(get_logs(), vec![expected]);
+    }
+}
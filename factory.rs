@@ -0,0 +1,292 @@
+/*!
+Factory mode: deploys new community tokens on sub-accounts of this contract.
+NOTES:
+  - A contract cannot embed its own wasm, so the owner uploads the code to deploy once with
+    `set_factory_code`, passing the raw wasm as the whole input like `upgrade`. Uploading this
+    contract's own build makes it deploy copies of itself.
+  - `create_token` creates `<subaccount>.<this account>`, funds it with the attached deposit,
+    deploys the code and calls `new` with the given owner, supply and metadata, all in one batch:
+    if any step fails nothing is created and the deposit is refunded. The deposit must cover the
+    storage of the code on the new account.
+  - The given owner holds the admin powers of the created token, not this contract's owner.
+  - Created tokens are recorded once their initialization succeeded and listed by `get_tokens`.
+  - The contract pays for the storage of the code and the records out of its own balance.
+*/
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LazyOption;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::store::UnorderedMap;
+use near_sdk::{env, is_promise_success, log, near_bindgen, AccountId, Gas, Promise};
+
+use crate::*;
+
+const GAS_FOR_TOKEN_INIT: Gas = Gas(50_000_000_000_000);
+const GAS_FOR_CALLBACK: Gas = Gas(10_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreatedToken {
+    pub owner_id: AccountId,
+    pub total_supply: U128,
+    pub symbol: String,
+    pub created_at: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Factory {
+    pub code: LazyOption<Vec<u8>>,
+    pub tokens: UnorderedMap<AccountId, CreatedToken>,
+}
+
+impl Factory {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            code: LazyOption::new([prefix.as_slice(), b"c"].concat(), None),
+            tokens: UnorderedMap::new([prefix.as_slice(), b"t"].concat()),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Stores the wasm passed as input as the code of the tokens created from now on.
+    pub fn set_factory_code(&mut self) {
+        self.assert_owner();
+        let code = env::input().expect("Expected the contract code as input");
+        assert!(!code.is_empty(), "The code cannot be empty");
+        log!("Factory code set, {} bytes", code.len());
+        self.factory.code.set(&code);
+    }
+
+    /// Deploys a token on `<subaccount>.<this account>` with `total_supply` owned by `owner_id`.
+    /// The attached deposit funds the new account.
+    #[payable]
+    pub fn create_token(
+        &mut self,
+        subaccount: String,
+        metadata: FungibleTokenMetadata,
+        total_supply: U128,
+        owner_id: AccountId,
+    ) -> Promise {
+        self.assert_owner_or_dao();
+        assert!(
+            !subaccount.contains('.'),
+            "The sub-account must be a direct sub-account"
+        );
+        let token_id: AccountId = format!("{}.{}", subaccount, env::current_account_id())
+            .parse()
+            .expect("Invalid sub-account");
+        assert!(
+            !self.factory.tokens.contains_key(&token_id),
+            "The token {} already exists",
+            token_id
+        );
+        metadata.assert_valid();
+        crate::metadata_validation::assert_valid_fields(&metadata);
+        let code = self.factory.code.get().expect("No factory code is set");
+        let token = CreatedToken {
+            owner_id: owner_id.clone(),
+            total_supply,
+            symbol: metadata.symbol.clone(),
+            created_at: env::block_timestamp().into(),
+        };
+        let args = json!({
+            "owner_id": owner_id,
+            "total_supply": total_supply,
+            "metadata": metadata,
+        });
+        Promise::new(token_id.clone())
+            .create_account()
+            .transfer(env::attached_deposit())
+            .deploy_contract(code)
+            .function_call(
+                "new".to_string(),
+                args.to_string().into_bytes(),
+                0,
+                GAS_FOR_TOKEN_INIT,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_CALLBACK)
+                    .on_token_created(
+                        token_id,
+                        token,
+                        env::predecessor_account_id(),
+                        U128(env::attached_deposit()),
+                    ),
+            )
+    }
+
+    #[private]
+    pub fn on_token_created(
+        &mut self,
+        token_id: AccountId,
+        token: CreatedToken,
+        creator_id: AccountId,
+        deposit: U128,
+    ) -> bool {
+        if is_promise_success() {
+            log!("Created the token {} for @{}", token_id, token.owner_id);
+            self.factory.tokens.insert(token_id, token);
+            true
+        } else {
+            log!("Creating the token {} failed", token_id);
+            if deposit.0 > 0 {
+                Promise::new(creator_id).transfer(deposit.0);
+            }
+            false
+        }
+    }
+
+    pub fn get_tokens(
+        &self,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<(AccountId, CreatedToken)> {
+        self.factory
+            .tokens
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(100) as usize)
+            .map(|(token_id, token)| (token_id.clone(), token.clone()))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::metadata::FungibleTokenMetadataProvider;
+    use near_sdk::mock::VmAction;
+    use near_sdk::serde_json::Value;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    /// The owner's factory holding the code `b"wasm"`.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        context.context.input = b"wasm".to_vec();
+        call_as(&mut context, owner(), 0);
+        contract.set_factory_code();
+        context.context.input = vec![];
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "No factory code is set")]
+    fn test_create_token_requires_code() {
+        let owner = owner();
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, owner.clone(), 0);
+        let metadata = contract.ft_metadata();
+        contract.create_token("community".to_string(), metadata, U128(1_000), owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner's method")]
+    fn test_set_factory_code_is_owner_only() {
+        let (mut context, mut contract) = setup();
+        context.context.input = b"other".to_vec();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.set_factory_code();
+    }
+
+    #[test]
+    fn test_create_token_deploys_and_initializes_the_token() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(5 * ONE_NEAR).build());
+        let metadata = contract.ft_metadata();
+        contract.create_token("community".to_string(), metadata, U128(1_000), accounts(3));
+
+        let token_id: AccountId = format!("community.{}", accounts(0)).parse().unwrap();
+        let receipt = get_created_receipts()
+            .into_iter()
+            .find(|receipt| receipt.receiver_id == token_id)
+            .expect("No receipt to the new token");
+        assert_eq!(receipt.actions[0], VmAction::CreateAccount);
+        assert_eq!(
+            receipt.actions[1],
+            VmAction::Transfer {
+                deposit: 5 * ONE_NEAR
+            }
+        );
+        assert_eq!(
+            receipt.actions[2],
+            VmAction::DeployContract {
+                code: b"wasm".to_vec()
+            }
+        );
+        match &receipt.actions[3] {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                ..
+            } => {
+                assert_eq!(function_name, "new");
+                let args: Value = near_sdk::serde_json::from_slice(args).unwrap();
+                assert_eq!(args["owner_id"], accounts(3).as_str());
+                assert_eq!(args["total_supply"], "1000");
+                assert_eq!(args["metadata"]["symbol"], "ASTRO");
+            }
+            action => panic!("Unexpected action {:?}", action),
+        }
+
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .attached_deposit(0)
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        let token = CreatedToken {
+            owner_id: accounts(3),
+            total_supply: U128(1_000),
+            symbol: "ASTRO".to_string(),
+            created_at: U64(0),
+        };
+        assert!(contract.on_token_created(token_id.clone(), token, owner(), U128(5 * ONE_NEAR)));
+        let tokens = contract.get_tokens(None, None);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].0, token_id);
+        assert_eq!(tokens[0].1.owner_id, accounts(3));
+    }
+
+    #[test]
+    fn test_failed_creation_refunds_the_deposit() {
+        let (mut context, mut contract) = setup();
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        let token = CreatedToken {
+            owner_id: accounts(3),
+            total_supply: U128(1_000),
+            symbol: "ASTRO".to_string(),
+            created_at: U64(0),
+        };
+        let token_id: AccountId = format!("community.{}", accounts(0)).parse().unwrap();
+        assert!(!contract.on_token_created(token_id, token, owner(), U128(5 * ONE_NEAR)));
+        assert!(contract.get_tokens(None, None).is_empty());
+        let refund = get_created_receipts()
+            .into_iter()
+            .find(|receipt| receipt.receiver_id == owner())
+            .expect("No refund to the creator");
+        assert_eq!(
+            refund.actions,
+            vec![VmAction::Transfer {
+                deposit: 5 * ONE_NEAR
+            }]
+        );
+    }
+}
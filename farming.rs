@@ -0,0 +1,491 @@
+/*!
+Liquidity mining: ASTRO rewards for staked LP tokens.
+NOTES:
+  - The owner or the DAO adds a farm for an LP token contract, e.g. the NEP-141 wrapper of a Ref
+    Finance pool share, and funds its rewards out of the treasury.
+  - Farmers deposit LP tokens with `ft_transfer_call` on the LP token, with this contract as the
+    receiver and `"farm"` as `msg`. Only registered ASTRO accounts can farm, as rewards are paid
+    in ASTRO. The farmer record is paid by the contract, as `ft_on_transfer` carries no NEAR.
+  - Each farm emits `reward_per_block` ASTRO per block between its farmers, with the same
+    accumulated-reward-per-share model as staking, and never more than it was funded with.
+  - `harvest` claims the rewards of a farm, `withdraw_lp` harvests and sends LP tokens back.
+    `emergency_withdraw_lp` sends all the LP tokens back without harvesting: the unclaimed
+    rewards go back to the farm, or are forfeited while rewards are halted, see
+    `circuit_breaker`. Both keep working when the module is retired.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::{LookupMap, UnorderedMap};
+use near_sdk::{
+    assert_one_yocto, env, is_promise_success, log, near_bindgen, AccountId, Balance, Gas, Promise,
+};
+
+use crate::rescue::ext_ft;
+use crate::*;
+
+/// Fixed point precision of `acc_reward_per_share`. Rewards are bounded by the total supply, so
+/// `reward * ACC_REWARD_PRECISION` fits in a `u128` even for tiny LP stakes. Large stakes of LP
+/// tokens with many decimals get less than one unit per share at a time: what the accumulator
+/// cannot hold is carried into the next emission instead of being lost.
+const ACC_REWARD_PRECISION: u128 = 1_000_000_000_000;
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_ON_LP_WITHDRAWN: Gas = Gas(10_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Farm {
+    pub reward_per_block: U128,
+    /// Funded rewards that were not emitted yet.
+    pub reward_pool: U128,
+    /// Emitted rewards too small to be credited to `acc_reward_per_share` yet.
+    pub reward_carry: U128,
+    pub total_staked: U128,
+    pub acc_reward_per_share: U128,
+    pub last_reward_block: U64,
+}
+
+impl Farm {
+    /// `acc_reward_per_share` after emitting the rewards up to the current block, the rewards
+    /// emitted and the new `reward_carry`. Nothing is emitted while rewards are `halted`.
+    fn accrued(&self, halted: bool) -> (Balance, Balance, Balance) {
+        let blocks = env::block_height() - self.last_reward_block.0;
+        if self.total_staked.0 == 0 || halted {
+            return (self.acc_reward_per_share.0, 0, self.reward_carry.0);
+        }
+        let reward = (self.reward_per_block.0 * Balance::from(blocks)).min(self.reward_pool.0);
+        let to_credit = reward + self.reward_carry.0;
+        let per_share = mul_div(to_credit, ACC_REWARD_PRECISION, self.total_staked.0);
+        let credited = mul_div(per_share, self.total_staked.0, ACC_REWARD_PRECISION);
+        (
+            self.acc_reward_per_share.0 + per_share,
+            reward,
+            to_credit - credited,
+        )
+    }
+
+    /// Emits the rewards accrued since the last update.
+    fn update(&mut self, halted: bool) {
+        let (acc_reward_per_share, reward, reward_carry) = self.accrued(halted);
+        self.acc_reward_per_share = acc_reward_per_share.into();
+        self.reward_pool = (self.reward_pool.0 - reward).into();
+        self.reward_carry = reward_carry.into();
+        self.last_reward_block = env::block_height().into();
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Default)]
+pub struct Farmer {
+    pub staked: Balance,
+    /// Value of `acc_reward_per_share` when the rewards of this farmer were last settled.
+    pub reward_per_share_paid: Balance,
+    /// Rewards settled but not harvested yet.
+    pub unclaimed: Balance,
+}
+
+impl Farmer {
+    fn pending(&self, acc_reward_per_share: Balance) -> Balance {
+        self.unclaimed
+            + mul_div(
+                self.staked,
+                acc_reward_per_share - self.reward_per_share_paid,
+                ACC_REWARD_PRECISION,
+            )
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Farming {
+    /// Farms by LP token.
+    pub farms: UnorderedMap<AccountId, Farm>,
+    /// Farmers by LP token and account.
+    pub farmers: LookupMap<(AccountId, AccountId), Farmer>,
+    /// Set by the reward circuit breaker, see `circuit_breaker`.
+    pub rewards_halted: bool,
+}
+
+impl Farming {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            farms: UnorderedMap::new([prefix.as_slice(), b"f"].concat()),
+            farmers: LookupMap::new([prefix.as_slice(), b"a"].concat()),
+            rewards_halted: false,
+        }
+    }
+
+    /// Updates the farm of `lp_token_id` and returns it.
+    pub(crate) fn updated_farm(&mut self, lp_token_id: &AccountId) -> Farm {
+        let farm = self
+            .farms
+            .get_mut(lp_token_id)
+            .unwrap_or_else(|| env::panic_str("No farm for this LP token"));
+        farm.update(self.rewards_halted);
+        farm.clone()
+    }
+
+    /// Returns the farmer with the pending rewards moved into `unclaimed`. The farm must be
+    /// updated.
+    fn settled_farmer(
+        &self,
+        lp_token_id: &AccountId,
+        farm: &Farm,
+        account_id: &AccountId,
+    ) -> Farmer {
+        let mut farmer = self
+            .farmers
+            .get(&(lp_token_id.clone(), account_id.clone()))
+            .copied()
+            .unwrap_or_default();
+        farmer.unclaimed = farmer.pending(farm.acc_reward_per_share.0);
+        farmer.reward_per_share_paid = farm.acc_reward_per_share.0;
+        farmer
+    }
+
+    fn save(
+        &mut self,
+        lp_token_id: &AccountId,
+        account_id: &AccountId,
+        farm: Farm,
+        farmer: Farmer,
+    ) {
+        let key = (lp_token_id.clone(), account_id.clone());
+        if farmer.staked == 0 && farmer.unclaimed == 0 {
+            self.farmers.remove(&key);
+        } else {
+            self.farmers.insert(key, farmer);
+        }
+        self.farms.insert(lp_token_id.clone(), farm);
+    }
+
+    /// ASTRO set aside for the rewards of all farms and not credited to farmers yet.
+    pub fn reward_pools(&self) -> Balance {
+        self.farms
+            .values()
+            .map(|farm| farm.reward_pool.0 + farm.reward_carry.0)
+            .sum()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn add_farm(&mut self, lp_token_id: AccountId, reward_per_block: U128) {
+        self.assert_owner_or_dao();
+        self.assert_module_active(Module::Farming);
+        assert!(
+            self.farming.farms.get(&lp_token_id).is_none(),
+            "A farm for this LP token exists"
+        );
+        self.farming.farms.insert(
+            lp_token_id.clone(),
+            Farm {
+                reward_per_block,
+                reward_pool: U128(0),
+                reward_carry: U128(0),
+                total_staked: U128(0),
+                acc_reward_per_share: U128(0),
+                last_reward_block: env::block_height().into(),
+            },
+        );
+        log!(
+            "Added a farm for {} at {} per block",
+            lp_token_id,
+            reward_per_block.0
+        );
+    }
+
+    pub fn set_farm_reward_rate(&mut self, lp_token_id: AccountId, reward_per_block: U128) {
+        self.assert_owner_or_dao();
+        let mut farm = self.farming.updated_farm(&lp_token_id);
+        farm.reward_per_block = reward_per_block;
+        self.farming.farms.insert(lp_token_id, farm);
+    }
+
+    /// Moves `amount` of the treasury into the rewards of the farm of `lp_token_id`.
+    pub fn fund_farm(&mut self, lp_token_id: AccountId, amount: U128) {
+        self.assert_owner_or_dao();
+        assert!(
+            amount.0 <= self.treasury_balance,
+            "The treasury holds {}",
+            self.treasury_balance
+        );
+        let mut farm = self.farming.updated_farm(&lp_token_id);
+        farm.reward_pool = (farm.reward_pool.0 + amount.0).into();
+        self.farming.farms.insert(lp_token_id.clone(), farm);
+        self.treasury_balance -= amount.0;
+        log!("Funded the farm for {} with {}", lp_token_id, amount.0);
+    }
+
+    /// Sends the caller's rewards in the farm of `lp_token_id`. Returns the amount harvested.
+    #[payable]
+    pub fn harvest(&mut self, lp_token_id: AccountId) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let reward = self.internal_harvest(&lp_token_id, &account_id);
+        assert!(reward > 0, "No rewards to harvest");
+        reward.into()
+    }
+
+    /// Harvests and sends `amount` of the caller's LP tokens back.
+    #[payable]
+    pub fn withdraw_lp(&mut self, lp_token_id: AccountId, amount: U128) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.internal_harvest(&lp_token_id, &account_id);
+        self.internal_withdraw_lp(lp_token_id, account_id, amount.0)
+    }
+
+    /// Sends all the caller's LP tokens back and gives up their unclaimed rewards.
+    #[payable]
+    pub fn emergency_withdraw_lp(&mut self, lp_token_id: AccountId) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        if self.farming.rewards_halted {
+            return self.internal_emergency_withdraw_lp(lp_token_id, account_id);
+        }
+        let mut farm = self.farming.updated_farm(&lp_token_id);
+        let mut farmer = self
+            .farming
+            .settled_farmer(&lp_token_id, &farm, &account_id);
+        let forfeited = std::mem::take(&mut farmer.unclaimed);
+        farm.reward_pool = (farm.reward_pool.0 + forfeited).into();
+        let amount = farmer.staked;
+        self.farming.save(&lp_token_id, &account_id, farm, farmer);
+        log!(
+            "Account @{} gave up {} of farm rewards",
+            account_id,
+            forfeited
+        );
+        self.internal_withdraw_lp(lp_token_id, account_id, amount)
+    }
+
+    /// Gives back the LP tokens of a failed withdrawal.
+    #[private]
+    pub fn on_lp_withdrawn(&mut self, lp_token_id: AccountId, account_id: AccountId, amount: U128) {
+        if !is_promise_success() {
+            self.internal_farm_deposit(&lp_token_id, &account_id, amount.0);
+            log!("LP withdrawal of @{} failed", account_id);
+            self.internal_record_callback_failure(
+                "withdraw_lp",
+                vec![account_id, lp_token_id],
+                amount.0,
+            );
+        }
+    }
+
+    pub fn get_farms(&self) -> Vec<(AccountId, Farm)> {
+        self.farming
+            .farms
+            .iter()
+            .map(|(lp_token_id, farm)| (lp_token_id.clone(), farm.clone()))
+            .collect()
+    }
+
+    pub fn get_farmed_balance(&self, lp_token_id: AccountId, account_id: AccountId) -> U128 {
+        self.farming
+            .farmers
+            .get(&(lp_token_id, account_id))
+            .map_or(0, |farmer| farmer.staked)
+            .into()
+    }
+
+    /// Rewards harvestable right now, including emissions not yet written to state.
+    pub fn get_farm_rewards(&self, lp_token_id: AccountId, account_id: AccountId) -> U128 {
+        let farm = self
+            .farming
+            .farms
+            .get(&lp_token_id)
+            .expect("No farm for this LP token");
+        let (acc_reward_per_share, _, _) = farm.accrued(self.farming.rewards_halted);
+        self.farming
+            .farmers
+            .get(&(lp_token_id, account_id))
+            .map_or(0, |farmer| farmer.pending(acc_reward_per_share))
+            .into()
+    }
+}
+
+impl Contract {
+    /// Stakes `amount` of `lp_token_id` deposited by `account_id`.
+    pub(crate) fn internal_farm_deposit(
+        &mut self,
+        lp_token_id: &AccountId,
+        account_id: &AccountId,
+        amount: Balance,
+    ) {
+        assert!(
+            self.token.accounts.contains_key(account_id),
+            "The account {} is not registered",
+            account_id
+        );
+        let mut farm = self.farming.updated_farm(lp_token_id);
+        let mut farmer = self.farming.settled_farmer(lp_token_id, &farm, account_id);
+        farmer.staked += amount;
+        farm.total_staked = (farm.total_staked.0 + amount).into();
+        self.farming.save(lp_token_id, account_id, farm, farmer);
+        log!(
+            "Account @{} farms {} of {}",
+            account_id,
+            amount,
+            lp_token_id
+        );
+    }
+
+    fn internal_harvest(&mut self, lp_token_id: &AccountId, account_id: &AccountId) -> Balance {
+        let farm = self.farming.updated_farm(lp_token_id);
+        let mut farmer = self.farming.settled_farmer(lp_token_id, &farm, account_id);
+        let reward = std::mem::take(&mut farmer.unclaimed);
+        self.farming.save(lp_token_id, account_id, farm, farmer);
+        if reward > 0 {
+            self.internal_unlock(account_id, reward);
+            log!("Account @{} harvested {}", account_id, reward);
+        }
+        reward
+    }
+
+    /// Unstakes `amount` of the LP tokens of `account_id` and sends them back. Rewards must be
+    /// settled.
+    fn internal_withdraw_lp(
+        &mut self,
+        lp_token_id: AccountId,
+        account_id: AccountId,
+        amount: Balance,
+    ) -> Promise {
+        let mut farm = self.farming.updated_farm(&lp_token_id);
+        let mut farmer = self
+            .farming
+            .settled_farmer(&lp_token_id, &farm, &account_id);
+        assert!(
+            amount > 0 && amount <= farmer.staked,
+            "Not enough LP tokens farmed"
+        );
+        farmer.staked -= amount;
+        farm.total_staked = (farm.total_staked.0 - amount).into();
+        self.farming.save(&lp_token_id, &account_id, farm, farmer);
+        self.internal_send_lp(lp_token_id, account_id, amount)
+    }
+
+    /// Sends all the LP tokens of `account_id` back without touching the reward bookkeeping,
+    /// which is halted. The unclaimed rewards are forfeited.
+    fn internal_emergency_withdraw_lp(
+        &mut self,
+        lp_token_id: AccountId,
+        account_id: AccountId,
+    ) -> Promise {
+        let farmer = self
+            .farming
+            .farmers
+            .remove(&(lp_token_id.clone(), account_id.clone()))
+            .filter(|farmer| farmer.staked > 0)
+            .expect("No LP tokens farmed");
+        if let Some(farm) = self.farming.farms.get_mut(&lp_token_id) {
+            farm.total_staked = farm.total_staked.0.saturating_sub(farmer.staked).into();
+        }
+        log!(
+            "Account @{} withdrew {} LP tokens in emergency",
+            account_id,
+            farmer.staked
+        );
+        self.internal_send_lp(lp_token_id, account_id, farmer.staked)
+    }
+
+    fn internal_send_lp(
+        &self,
+        lp_token_id: AccountId,
+        account_id: AccountId,
+        amount: Balance,
+    ) -> Promise {
+        ext_ft::ext(lp_token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(account_id.clone(), amount.into(), None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_LP_WITHDRAWN)
+                    .on_lp_withdrawn(lp_token_id, account_id, amount.into()),
+            )
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    const ONE_ASTRO: Balance = 1_000_000_000_000_000_000;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner(), 1);
+        contract.treasury_deposit(U128(1_000 * ONE_ASTRO));
+        contract.add_farm(accounts(5), U128(10 * ONE_ASTRO));
+        contract.fund_farm(accounts(5), U128(1_000 * ONE_ASTRO));
+
+        testing_env!(context.predecessor_account_id(accounts(5)).build());
+        contract.ft_on_transfer(accounts(1), U128(300), "farm".to_string());
+        contract.ft_on_transfer(accounts(2), U128(100), "farm".to_string());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_farm_rewards_per_block() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_index(10)
+            .build());
+        assert_eq!(
+            contract.get_farm_rewards(accounts(5), accounts(2)).0,
+            25 * ONE_ASTRO
+        );
+        assert_eq!(contract.harvest(accounts(5)).0, 75 * ONE_ASTRO);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 75 * ONE_ASTRO);
+        contract.withdraw_lp(accounts(5), U128(300));
+        assert_eq!(contract.get_farmed_balance(accounts(5), accounts(1)).0, 0);
+    }
+
+    #[test]
+    fn test_emergency_withdraw_forfeits_rewards() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_index(10)
+            .build());
+        contract.emergency_withdraw_lp(accounts(5));
+        assert_eq!(contract.get_farmed_balance(accounts(5), accounts(2)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+        assert_eq!(contract.get_farms()[0].1.reward_pool.0, 925 * ONE_ASTRO);
+    }
+
+    #[test]
+    fn test_large_lp_stakes_carry_uncredited_rewards() {
+        const ONE_LP: Balance = ONE_NEAR;
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.treasury_deposit(U128(ONE_ASTRO));
+        contract.add_farm(accounts(4), U128(ONE_ASTRO / 10_000));
+        contract.fund_farm(accounts(4), U128(ONE_ASTRO));
+        testing_env!(context.predecessor_account_id(accounts(4)).build());
+        contract.ft_on_transfer(accounts(1), U128(1_000 * ONE_LP), "farm".to_string());
+
+        // 25 blocks of rewards are 2.5 units per share: half a unit is carried.
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_index(25)
+            .build());
+        assert_eq!(contract.harvest(accounts(4)).0, ONE_ASTRO / 10_000 * 20);
+        let farm = contract.get_farms()[1].1.clone();
+        assert_eq!(farm.reward_carry.0, ONE_ASTRO / 10_000 * 5);
+        assert_eq!(
+            farm.reward_pool.0 + farm.reward_carry.0,
+            ONE_ASTRO - ONE_ASTRO / 10_000 * 20
+        );
+
+        testing_env!(context.block_index(30).build());
+        assert_eq!(contract.harvest(accounts(4)).0, ONE_ASTRO / 10_000 * 10);
+        assert_eq!(contract.get_farms()[1].1.reward_carry.0, 0);
+    }
+}
@@ -0,0 +1,183 @@
+/*!
+Transfer fee targeting a USD value, priced by an oracle contract.
+NOTES:
+  - The owner sets a price oracle and, with `set_fee_target_usd(cents)`, the USD value the fee
+    should take from each whole token transferred. The oracle answers `get_price_usd(asset_id)`
+    with the USD price of one whole token in millionths of a dollar.
+  - Anyone can call `refresh_fee` once an hour. It asks the oracle for the price and moves the fee
+    towards `target / price`, by at most `MAX_FEE_STEP_BPS` per refresh and within
+    `MAX_TRANSFER_FEE_BPS`, so a bad quote cannot jump the fee.
+  - A failed or missing quote leaves the fee unchanged. `set_fee_target_usd(None)` stops the
+    refreshes; the fee keeps its last value and the owner can set it again by hand.
+*/
+#![cfg_attr(not(feature = "fees"), allow(dead_code, unused_imports))]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::{env, ext_contract, near_bindgen, AccountId, Gas, Promise, PromiseError};
+
+use crate::fees::MAX_TRANSFER_FEE_BPS;
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const REFRESH_INTERVAL: u64 = 60 * 60 * 1_000_000_000;
+const MAX_FEE_STEP_BPS: u16 = 10;
+const MICRO_USD_PER_CENT: u128 = 10_000;
+const MAX_BPS: u128 = 10_000;
+const GAS_FOR_GET_PRICE: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_ON_FEE_PRICE: Gas = Gas(10_000_000_000_000);
+
+#[ext_contract(ext_price_oracle)]
+#[allow(dead_code)]
+pub trait PriceOracle {
+    /// USD price of one whole token of `asset_id` in millionths of a dollar.
+    fn get_price_usd(&self, asset_id: AccountId) -> Option<U128>;
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct FeeOracle {
+    pub oracle_id: Option<AccountId>,
+    pub target_usd_cents: Option<u64>,
+    pub last_refresh: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeOracleInfo {
+    pub oracle_id: Option<AccountId>,
+    pub target_usd_cents: Option<U64>,
+    pub last_refresh: Option<U64>,
+}
+
+/// Fee in bps worth `target_usd_cents` per whole token at `price` micro-USD per token.
+fn target_fee_bps(target_usd_cents: u64, price: u128) -> u16 {
+    let bps = mul_div(
+        u128::from(target_usd_cents) * MICRO_USD_PER_CENT,
+        MAX_BPS,
+        price,
+    );
+    std::cmp::min(bps, u128::from(MAX_TRANSFER_FEE_BPS)) as u16
+}
+
+#[cfg(feature = "fees")]
+#[near_bindgen]
+impl Contract {
+    pub fn set_fee_oracle(&mut self, oracle_id: Option<AccountId>) {
+        self.assert_owner();
+        self.fee_oracle.oracle_id = oracle_id;
+    }
+
+    /// Sets the USD value in cents the fee should take from each whole token transferred, or
+    /// `None` to stop adjusting the fee.
+    pub fn set_fee_target_usd(&mut self, cents: Option<U64>) {
+        self.assert_owner();
+        if let Some(cents) = cents {
+            assert!(cents.0 > 0, "The target should be a positive number");
+        }
+        self.fee_oracle.target_usd_cents = cents.map(|cents| cents.0);
+    }
+
+    /// Asks the oracle for the token price and moves the fee towards the USD target.
+    pub fn refresh_fee(&mut self) -> Promise {
+        let target_usd_cents = self
+            .fee_oracle
+            .target_usd_cents
+            .expect("No USD fee target is set");
+        let oracle_id = self
+            .fee_oracle
+            .oracle_id
+            .clone()
+            .expect("No fee oracle is set");
+        let now = env::block_timestamp();
+        if let Some(last_refresh) = self.fee_oracle.last_refresh {
+            assert!(
+                now >= last_refresh + REFRESH_INTERVAL,
+                "The next refresh is due at {}",
+                last_refresh + REFRESH_INTERVAL
+            );
+        }
+        self.fee_oracle.last_refresh = Some(now);
+        ext_price_oracle::ext(oracle_id)
+            .with_static_gas(GAS_FOR_GET_PRICE)
+            .get_price_usd(env::current_account_id())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_FEE_PRICE)
+                    .on_fee_price(target_usd_cents),
+            )
+    }
+
+    #[private]
+    pub fn on_fee_price(
+        &mut self,
+        target_usd_cents: u64,
+        #[callback_result] price: Result<Option<U128>, PromiseError>,
+    ) -> u16 {
+        let fee_bps = self.transfer_fee.fee_bps;
+        let price = match price {
+            Ok(Some(price)) if price.0 > 0 => price.0,
+            Ok(_) => return fee_bps,
+            Err(_) => {
+                self.internal_record_callback_failure("refresh_fee", vec![], 0);
+                return fee_bps;
+            }
+        };
+        let target_bps = target_fee_bps(target_usd_cents, price);
+        let new_bps = if target_bps > fee_bps {
+            std::cmp::min(target_bps, fee_bps + MAX_FEE_STEP_BPS)
+        } else {
+            std::cmp::max(target_bps, fee_bps.saturating_sub(MAX_FEE_STEP_BPS))
+        };
+        self.internal_set_transfer_fee(new_bps);
+        emit_event(
+            EVENT_VERSION,
+            "fee_refreshed",
+            json!({
+                "price_usd": U128(price),
+                "target_bps": target_bps,
+                "old_bps": fee_bps,
+                "new_bps": new_bps,
+            }),
+        );
+        new_bps
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_fee_oracle(&self) -> FeeOracleInfo {
+        FeeOracleInfo {
+            oracle_id: self.fee_oracle.oracle_id.clone(),
+            target_usd_cents: self.fee_oracle.target_usd_cents.map(U64),
+            last_refresh: self.fee_oracle.last_refresh.map(U64),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "fees"))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_fee_moves_towards_the_target_in_steps() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        contract.set_fee_oracle(Some(accounts(3)));
+        // 1 cent per token at $0.50 a token is 200 bps.
+        contract.set_fee_target_usd(Some(U64(1)));
+        assert_eq!(target_fee_bps(1, 500_000), 200);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        assert_eq!(contract.on_fee_price(1, Ok(Some(U128(500_000)))), 10);
+        assert_eq!(contract.on_fee_price(1, Ok(None)), 10);
+        assert_eq!(contract.on_fee_price(1, Ok(Some(U128(10_000_000)))), 10);
+        assert_eq!(contract.on_fee_price(1, Ok(Some(U128(100_000_000)))), 1);
+        assert_eq!(contract.get_transfer_fee().fee_bps, 1);
+    }
+}
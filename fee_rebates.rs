@@ -0,0 +1,278 @@
+/*!
+Month-end rebates of transfer fees for high-volume integrators.
+NOTES:
+  - The owner or the DAO registers integrators, typically exchanges and apps moving tokens for
+    their users. The transfer fees that reach the treasury on transfers an integrator sends add
+    up per month of 30 days, counted from the Unix epoch. Fees paid to a fee recipient or to
+    fee split legs other than the treasury do not count.
+  - Governance sets the rebate tiers, with a proposal or through the owner or the DAO: each tier
+    pays `rebate_bps` of the month's fees above its `min_monthly_fees`, up to the minimum of the
+    next tier. Fees below the first tier earn nothing. A month is settled with the tiers in effect
+    when the integrator next transfers or claims after it ended, and its rebate is added to the
+    integrator's claimable balance.
+  - Rebates are paid in ASTRO out of the treasury. A claim fails while the treasury cannot pay
+    it in full.
+  - The contract pays for the storage of the integrator stats. Removing an integrator drops its
+    stats, including rebates it did not claim.
+*/
+#![cfg_attr(not(feature = "fees"), allow(dead_code, unused_imports))]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+pub const MONTH: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+const MAX_REBATE_TIERS: usize = 10;
+const MAX_BPS: u16 = 10_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RebateTier {
+    /// Fees an integrator has to contribute in a month to reach the tier.
+    pub min_monthly_fees: U128,
+    /// Share of the month's fees within the tier paid back in basis points.
+    pub rebate_bps: u16,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IntegratorStats {
+    /// Month of `month_fees`.
+    pub month: U64,
+    pub month_fees: U128,
+    pub total_fees: U128,
+    pub claimable: U128,
+    pub claimed: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct FeeRebates {
+    pub tiers: Vec<RebateTier>,
+    pub integrators: LookupMap<AccountId, IntegratorStats>,
+}
+
+impl FeeRebates {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            tiers: vec![],
+            integrators: LookupMap::new(prefix),
+        }
+    }
+
+    /// Rebate of a month in which an integrator contributed `fees`, each tier applying to the
+    /// fees between its minimum and the next one.
+    fn rebate_for(&self, fees: Balance) -> Balance {
+        self.tiers
+            .iter()
+            .enumerate()
+            .map(|(i, tier)| {
+                let upper = self
+                    .tiers
+                    .get(i + 1)
+                    .map_or(fees, |next| fees.min(next.min_monthly_fees.0));
+                let in_tier = upper.saturating_sub(tier.min_monthly_fees.0);
+                mul_div(in_tier, u128::from(tier.rebate_bps), u128::from(MAX_BPS))
+            })
+            .sum()
+    }
+
+    /// Stats of `account_id` with the months before the current one settled, if it is a
+    /// registered integrator.
+    fn settled(&self, account_id: &AccountId) -> Option<IntegratorStats> {
+        let mut stats = self.integrators.get(account_id)?.clone();
+        let month = env::block_timestamp() / MONTH;
+        if stats.month.0 < month {
+            stats.claimable.0 += self.rebate_for(stats.month_fees.0);
+            stats.month = month.into();
+            stats.month_fees = U128(0);
+        }
+        Some(stats)
+    }
+}
+
+fn emit_fee_rebates_event(event: &str, data: near_sdk::serde_json::Value) {
+    emit_event(EVENT_VERSION, event, data);
+}
+
+#[cfg(feature = "fees")]
+#[near_bindgen]
+impl Contract {
+    pub fn register_integrator(&mut self, account_id: AccountId) {
+        self.assert_owner_or_dao();
+        assert!(
+            !self.fee_rebates.integrators.contains_key(&account_id),
+            "The integrator {} is already registered",
+            account_id
+        );
+        let stats = IntegratorStats {
+            month: (env::block_timestamp() / MONTH).into(),
+            month_fees: U128(0),
+            total_fees: U128(0),
+            claimable: U128(0),
+            claimed: U128(0),
+        };
+        self.fee_rebates
+            .integrators
+            .insert(account_id.clone(), stats);
+        emit_fee_rebates_event("integrator_registered", json!({ "account_id": account_id }));
+    }
+
+    pub fn remove_integrator(&mut self, account_id: AccountId) {
+        self.assert_owner_or_dao();
+        let stats = self
+            .fee_rebates
+            .integrators
+            .remove(&account_id)
+            .expect("Integrator not found");
+        emit_fee_rebates_event(
+            "integrator_removed",
+            json!({ "account_id": account_id, "forfeited": stats.claimable }),
+        );
+    }
+
+    /// Replaces the rebate tiers, sorted by `min_monthly_fees`. An empty list stops the rebates
+    /// of the months settled from now on.
+    pub fn set_fee_rebate_tiers(&mut self, tiers: Vec<RebateTier>) {
+        self.assert_owner_or_dao();
+        self.internal_set_fee_rebate_tiers(tiers);
+    }
+
+    /// Pays the rebates of the ended months to the calling integrator.
+    #[payable]
+    pub fn claim_fee_rebate(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut stats = self
+            .fee_rebates
+            .settled(&account_id)
+            .expect("Only registered integrators can claim fee rebates");
+        let amount = stats.claimable.0;
+        assert!(amount > 0, "No fee rebate to claim");
+        assert!(
+            amount <= self.treasury_balance,
+            "The treasury balance is {}",
+            self.treasury_balance
+        );
+        stats.claimable = U128(0);
+        stats.claimed.0 += amount;
+        self.fee_rebates
+            .integrators
+            .insert(account_id.clone(), stats);
+        self.treasury_balance -= amount;
+        self.internal_unlock(&account_id, amount);
+        emit_fee_rebates_event(
+            "fee_rebate_claimed",
+            json!({ "account_id": account_id, "amount": U128(amount) }),
+        );
+        amount.into()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_fee_rebate_tiers(&self) -> Vec<RebateTier> {
+        self.fee_rebates.tiers.clone()
+    }
+
+    /// Fee contributions and rebates of `account_id`, with the ended months settled.
+    pub fn get_integrator_stats(&self, account_id: AccountId) -> Option<IntegratorStats> {
+        self.fee_rebates.settled(&account_id)
+    }
+}
+
+impl Contract {
+    /// Sets the rebate tiers. Shared by the admin method and governance proposals.
+    pub(crate) fn internal_set_fee_rebate_tiers(&mut self, tiers: Vec<RebateTier>) {
+        assert!(
+            tiers.len() <= MAX_REBATE_TIERS,
+            "There can be {} rebate tiers at most",
+            MAX_REBATE_TIERS
+        );
+        for (i, tier) in tiers.iter().enumerate() {
+            assert!(
+                tier.rebate_bps <= MAX_BPS,
+                "A rebate cannot exceed {} bps",
+                MAX_BPS
+            );
+            assert!(
+                i == 0 || tiers[i - 1].min_monthly_fees.0 < tier.min_monthly_fees.0,
+                "The tiers must be sorted by increasing minimum fees"
+            );
+        }
+        emit_fee_rebates_event("fee_rebate_tiers", json!({ "tiers": tiers }));
+        self.fee_rebates.tiers = tiers;
+    }
+
+    /// Adds `fee`, charged on a transfer sent by `sender_id`, to its month if it is a registered
+    /// integrator.
+    pub(crate) fn internal_record_integrator_fee(&mut self, sender_id: &AccountId, fee: Balance) {
+        if let Some(mut stats) = self.fee_rebates.settled(sender_id) {
+            stats.month_fees.0 += fee;
+            stats.total_fees.0 += fee;
+            self.fee_rebates
+                .integrators
+                .insert(sender_id.clone(), stats);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "fees"))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_integrator_claims_the_rebate_of_its_tier() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner.clone(), 0);
+        contract.set_transfer_fee(100);
+        contract.register_integrator(owner.clone());
+        contract.set_fee_rebate_tiers(vec![
+            RebateTier {
+                min_monthly_fees: U128(100),
+                rebate_bps: 1_000,
+            },
+            RebateTier {
+                min_monthly_fees: U128(1_000),
+                rebate_bps: 5_000,
+            },
+        ]);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(100_000), None);
+        contract.ft_transfer(accounts(1), U128(100_000), None);
+        let stats = contract.get_integrator_stats(owner.clone()).unwrap();
+        assert_eq!(stats.month_fees.0, 2_000);
+        assert_eq!(stats.claimable.0, 0);
+
+        // 10% of the 900 fees between 100 and 1_000, 50% of the 1_000 fees above.
+        testing_env!(context.block_timestamp(MONTH).build());
+        let balance = contract.ft_balance_of(owner.clone()).0;
+        assert_eq!(contract.claim_fee_rebate().0, 590);
+        assert_eq!(contract.ft_balance_of(owner.clone()).0, balance + 590);
+        let stats = contract.get_integrator_stats(owner.clone()).unwrap();
+        assert_eq!(stats.total_fees.0, 2_000);
+        assert_eq!(stats.claimed.0, 590);
+        assert_eq!(stats.claimable.0, 0);
+
+        // Fees paid to a fee recipient do not fund rebates.
+        testing_env!(context.attached_deposit(0).build());
+        contract.set_fee_recipient(accounts(2));
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(100_000), None);
+        let stats = contract.get_integrator_stats(owner).unwrap();
+        assert_eq!(stats.month_fees.0, 0);
+        assert_eq!(stats.total_fees.0, 2_000);
+    }
+}
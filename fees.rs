@@ -0,0 +1,486 @@
+/*!
+Optional fee on `ft_transfer` and `ft_transfer_call`, credited to a fee recipient or the
+treasury, and an optional burn on the same transfers.
+NOTES:
+  - The fee is `fee_bps` basis points of the transferred amount, at most `MAX_TRANSFER_FEE_BPS`,
+    rounded down. The receiver gets the amount minus the fee.
+  - Fees go to the treasury unless the owner configures a fee recipient. No fee is charged when
+    the sender, the receiver or both are exempt. Transfers to and from the fee collector are
+    always exempt.
+  - In burn-on-transfer mode another `burn_bps` of the amount, at most `MAX_TRANSFER_BURN_BPS`,
+    is burned from the sender, reducing the total supply. It applies in addition to the fee and
+    respects the same exemptions, except that the fee recipient is not exempt from burns.
+  - The owner can split the fee across several destinations with `set_fee_splits`: accounts, the
+    treasury, the staking reward pool or a burn, each with its share of the fee in basis points.
+    The shares add up to 10000 and the last destination gets the rounding remainder. Each leg is
+    logged as a `fee_split` event. Without splits the whole fee goes to the fee collector.
+  - For `ft_transfer_call` the fee and the burn are charged upfront and are not refunded with
+    unused tokens.
+  - Internal movements of the contract (staking, escrows, streams, ...) are not charged.
+  - The part of the fee that goes to the treasury on transfers sent by registered integrators
+    counts toward their rebates, see `fee_rebates`.
+*/
+#![cfg_attr(not(feature = "fees"), allow(dead_code, unused_imports))]
+
+use near_contract_standards::fungible_token::events::FtBurn;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupSet;
+use near_sdk::{env, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+pub const MAX_TRANSFER_FEE_BPS: u16 = 500;
+pub const MAX_TRANSFER_BURN_BPS: u16 = 500;
+const MAX_BPS: u128 = 10_000;
+const MAX_FEE_SPLITS: usize = 10;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum FeeDestination {
+    Account(AccountId),
+    Treasury,
+    StakingRewards,
+    Burn,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeSplit {
+    pub destination: FeeDestination,
+    /// Share of the fee in basis points.
+    pub bps: u16,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TransferFee {
+    pub fee_bps: u16,
+    pub fee_recipient: Option<AccountId>,
+    pub exempt: LookupSet<AccountId>,
+    pub burn_enabled: bool,
+    pub burn_bps: u16,
+    pub splits: Vec<FeeSplit>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferFeeInfo {
+    pub fee_bps: u16,
+    pub fee_recipient: Option<AccountId>,
+    pub burn_enabled: bool,
+    pub burn_bps: u16,
+    pub splits: Vec<FeeSplit>,
+}
+
+fn bps_of(amount: Balance, bps: u16) -> Balance {
+    mul_div(amount, u128::from(bps), MAX_BPS)
+}
+
+impl TransferFee {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            fee_bps: 0,
+            fee_recipient: None,
+            exempt: LookupSet::new(prefix),
+            burn_enabled: false,
+            burn_bps: 0,
+            splits: vec![],
+        }
+    }
+
+    /// Account credited with the fees: the fee recipient, or the contract itself for the
+    /// treasury.
+    pub fn fee_collector(&self) -> AccountId {
+        self.fee_recipient
+            .clone()
+            .unwrap_or_else(env::current_account_id)
+    }
+
+    /// Fee on a transfer of `amount` from `sender_id` to `receiver_id`.
+    pub fn fee_for(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Balance {
+        if self.fee_bps == 0
+            || self.is_fee_account(sender_id)
+            || self.is_fee_account(receiver_id)
+            || self.is_exempt(sender_id, receiver_id)
+        {
+            return 0;
+        }
+        bps_of(amount, self.fee_bps)
+    }
+
+    /// Amount burned on a transfer of `amount` from `sender_id` to `receiver_id`.
+    pub fn burn_for(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Balance {
+        if !self.burn_enabled || self.burn_bps == 0 || self.is_exempt(sender_id, receiver_id) {
+            return 0;
+        }
+        bps_of(amount, self.burn_bps)
+    }
+
+    /// Accounts credited with fees: the fee collector, or the accounts the fee is split to.
+    pub fn fee_accounts(&self) -> Vec<AccountId> {
+        if self.splits.is_empty() {
+            return vec![self.fee_collector()];
+        }
+        let mut account_ids = vec![];
+        for split in self.splits.iter() {
+            let account_id = match &split.destination {
+                FeeDestination::Account(account_id) => account_id.clone(),
+                FeeDestination::Treasury | FeeDestination::StakingRewards => {
+                    env::current_account_id()
+                }
+                FeeDestination::Burn => continue,
+            };
+            if !account_ids.contains(&account_id) {
+                account_ids.push(account_id);
+            }
+        }
+        account_ids
+    }
+
+    fn is_fee_account(&self, account_id: &AccountId) -> bool {
+        if self.splits.is_empty() {
+            return account_id == &self.fee_collector();
+        }
+        self.splits
+            .iter()
+            .any(|split| split.destination == FeeDestination::Account(account_id.clone()))
+    }
+
+    fn is_exempt(&self, sender_id: &AccountId, receiver_id: &AccountId) -> bool {
+        self.exempt.contains(sender_id) || self.exempt.contains(receiver_id)
+    }
+}
+
+#[cfg(feature = "fees")]
+#[near_bindgen]
+impl Contract {
+    pub fn set_transfer_fee(&mut self, bps: u16) {
+        self.assert_owner();
+        self.internal_set_transfer_fee(bps);
+    }
+
+    pub fn set_fee_recipient(&mut self, account: AccountId) {
+        self.assert_owner();
+        assert!(
+            self.token.accounts.contains_key(&account),
+            "The account {} is not registered",
+            account
+        );
+        self.transfer_fee.fee_recipient = Some(account);
+    }
+
+    pub fn set_transfer_burn_bps(&mut self, bps: u16) {
+        self.assert_owner();
+        self.internal_set_transfer_burn_bps(bps);
+    }
+
+    pub fn set_transfer_burn_enabled(&mut self, enabled: bool) {
+        self.assert_owner();
+        self.transfer_fee.burn_enabled = enabled;
+    }
+
+    /// Splits the fee across `splits`, whose shares must add up to 10000 bps. An empty list sends
+    /// the whole fee to the fee collector again.
+    pub fn set_fee_splits(&mut self, splits: Vec<FeeSplit>) {
+        self.assert_owner();
+        if !splits.is_empty() {
+            assert!(
+                splits.len() <= MAX_FEE_SPLITS,
+                "The fee can be split {} ways at most",
+                MAX_FEE_SPLITS
+            );
+            assert!(
+                splits.iter().all(|split| split.bps > 0),
+                "Every split needs a positive share"
+            );
+            let total: u128 = splits.iter().map(|split| u128::from(split.bps)).sum();
+            assert_eq!(total, MAX_BPS, "The splits must add up to {} bps", MAX_BPS);
+            for (i, split) in splits.iter().enumerate() {
+                assert!(
+                    !splits[..i]
+                        .iter()
+                        .any(|other| other.destination == split.destination),
+                    "Duplicate fee destination {:?}",
+                    split.destination
+                );
+                if let FeeDestination::Account(account_id) = &split.destination {
+                    assert!(
+                        self.token.accounts.contains_key(account_id),
+                        "The account {} is not registered",
+                        account_id
+                    );
+                }
+            }
+        }
+        self.transfer_fee.splits = splits;
+    }
+
+    pub fn add_fee_exemption(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.transfer_fee.exempt.insert(account_id);
+    }
+
+    pub fn remove_fee_exemption(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.transfer_fee.exempt.remove(&account_id);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_transfer_fee(&self) -> TransferFeeInfo {
+        TransferFeeInfo {
+            fee_bps: self.transfer_fee.fee_bps,
+            fee_recipient: self.transfer_fee.fee_recipient.clone(),
+            burn_enabled: self.transfer_fee.burn_enabled,
+            burn_bps: self.transfer_fee.burn_bps,
+            splits: self.transfer_fee.splits.clone(),
+        }
+    }
+
+    pub fn is_fee_exempt(&self, account_id: AccountId) -> bool {
+        self.transfer_fee.exempt.contains(&account_id)
+    }
+}
+
+impl Contract {
+    /// Sets the transfer fee. Shared by the admin method and DAO proposals.
+    pub(crate) fn internal_set_transfer_fee(&mut self, bps: u16) {
+        assert!(
+            bps <= MAX_TRANSFER_FEE_BPS,
+            "Transfer fee cannot exceed {} bps",
+            MAX_TRANSFER_FEE_BPS
+        );
+        self.transfer_fee.fee_bps = bps;
+    }
+
+    /// Sets the burn share of transfers. Shared by the admin method and DAO proposals.
+    pub(crate) fn internal_set_transfer_burn_bps(&mut self, bps: u16) {
+        assert!(
+            bps <= MAX_TRANSFER_BURN_BPS,
+            "Transfer burn cannot exceed {} bps",
+            MAX_TRANSFER_BURN_BPS
+        );
+        self.transfer_fee.burn_bps = bps;
+    }
+
+    /// Accounts whose balances a user transfer from `sender_id` to `receiver_id` can change.
+    pub(crate) fn transfer_account_ids(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+    ) -> Vec<AccountId> {
+        let mut account_ids = vec![sender_id.clone(), receiver_id.clone()];
+        for account_id in self.transfer_fee.fee_accounts() {
+            if !account_ids.contains(&account_id) {
+                account_ids.push(account_id);
+            }
+        }
+        account_ids
+    }
+
+    /// Moves the fee on a transfer of `amount` to the fee collector, burns the burn share and
+    /// returns the amount left for the receiver.
+    pub(crate) fn internal_charge_transfer_fee(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Balance {
+        if self.retired_modules.contains(&Module::Fees) {
+            return amount;
+        }
+        let fee = self.transfer_fee.fee_for(sender_id, receiver_id, amount);
+        let burn = self.transfer_fee.burn_for(sender_id, receiver_id, amount);
+        if fee > 0 && !self.transfer_fee.splits.is_empty() {
+            self.internal_split_fee(sender_id, receiver_id, fee);
+        } else if fee > 0 {
+            let fee_recipient = self.transfer_fee.fee_collector();
+            if fee_recipient == env::current_account_id() {
+                self.internal_register_contract_account();
+                self.treasury_balance += fee;
+                self.internal_record_integrator_fee(sender_id, fee);
+            }
+            self.token.internal_transfer(
+                sender_id,
+                &fee_recipient,
+                fee,
+                Some("Transfer fee".to_string()),
+            );
+            emit_event(
+                EVENT_VERSION,
+                "transfer_fee",
+                json!({
+                    "sender_id": sender_id,
+                    "receiver_id": receiver_id,
+                    "fee_recipient": fee_recipient,
+                    "amount": U128(amount),
+                    "fee": U128(fee),
+                }),
+            );
+        }
+        if burn > 0 {
+            self.token.internal_withdraw(sender_id, burn);
+            FtBurn {
+                owner_id: sender_id,
+                amount: &U128(burn),
+                memo: Some("Burn on transfer"),
+            }
+            .emit();
+        }
+        amount - fee - burn
+    }
+
+    /// Moves `fee` from `sender_id` to the fee splits.
+    fn internal_split_fee(&mut self, sender_id: &AccountId, receiver_id: &AccountId, fee: Balance) {
+        let splits = self.transfer_fee.splits.clone();
+        let mut left = fee;
+        for (i, split) in splits.iter().enumerate() {
+            let share = if i + 1 == splits.len() {
+                left
+            } else {
+                bps_of(fee, split.bps)
+            };
+            left -= share;
+            if share == 0 {
+                continue;
+            }
+            let contract_id = env::current_account_id();
+            match &split.destination {
+                FeeDestination::Account(account_id) => self.token.internal_transfer(
+                    sender_id,
+                    account_id,
+                    share,
+                    Some("Transfer fee".to_string()),
+                ),
+                FeeDestination::Treasury => {
+                    self.internal_register_contract_account();
+                    self.treasury_balance += share;
+                    self.internal_record_integrator_fee(sender_id, share);
+                    self.token.internal_transfer(
+                        sender_id,
+                        &contract_id,
+                        share,
+                        Some("Transfer fee".to_string()),
+                    );
+                }
+                FeeDestination::StakingRewards => {
+                    self.internal_register_contract_account();
+                    self.staking.update();
+                    self.staking.reward_pool += share;
+                    self.token.internal_transfer(
+                        sender_id,
+                        &contract_id,
+                        share,
+                        Some("Transfer fee".to_string()),
+                    );
+                }
+                FeeDestination::Burn => {
+                    self.token.internal_withdraw(sender_id, share);
+                    FtBurn {
+                        owner_id: sender_id,
+                        amount: &U128(share),
+                        memo: Some("Transfer fee"),
+                    }
+                    .emit();
+                }
+            }
+            emit_event(
+                EVENT_VERSION,
+                "fee_split",
+                json!({
+                    "sender_id": sender_id,
+                    "receiver_id": receiver_id,
+                    "destination": split.destination,
+                    "bps": split.bps,
+                    "fee": U128(share),
+                }),
+            );
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "fees"))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner(), 0);
+        contract.set_fee_recipient(accounts(2));
+        contract.set_transfer_fee(100);
+        testing_env!(context.attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_transfer_fee() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), U128(10_000), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 9_900);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 10_000);
+    }
+
+    #[test]
+    fn test_burn_on_transfer() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(0).build());
+        contract.set_transfer_burn_bps(50);
+        contract.set_transfer_burn_enabled(true);
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(10_000), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 9_850);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 50);
+    }
+
+    #[test]
+    fn test_fee_splits() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(0).build());
+        contract.set_fee_splits(vec![
+            FeeSplit {
+                destination: FeeDestination::Account(accounts(2)),
+                bps: 6_000,
+            },
+            FeeSplit {
+                destination: FeeDestination::Burn,
+                bps: 4_000,
+            },
+        ]);
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(10_000), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 9_900);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 60);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 40);
+    }
+
+    #[test]
+    fn test_exempt_sender_pays_no_fee() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(0).build());
+        contract.add_fee_exemption(owner());
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(10_000), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 10_000);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+    }
+}
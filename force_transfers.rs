@@ -0,0 +1,125 @@
+/*!
+Forced transfers for legal seizures.
+NOTES:
+  - The owner or the DAO appoints a compliance officer, who can move tokens out of any account
+    with `force_transfer` without the holder's consent, e.g. to execute a court order. Pauses
+    and freezes do not apply: a seized account is often frozen.
+  - Every forced transfer requires a justification and emits a `force_transfer` event on top
+    of the NEP-141 transfer event, so monitoring can pick them out. `get_forced_transfer_count`
+    is a public counter of the forced transfers made so far.
+  - No forced transfer is possible once ownership is renounced.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde_json::json;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const MAX_JUSTIFICATION_LEN: usize = 1_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct ForceTransfers {
+    pub compliance_id: Option<AccountId>,
+    pub count: u64,
+}
+
+impl ForceTransfers {
+    pub fn is_compliance_officer(&self, account_id: &AccountId) -> bool {
+        self.compliance_id.as_ref() == Some(account_id)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_compliance_officer(&mut self, compliance_id: Option<AccountId>) {
+        self.assert_owner_or_dao();
+        match &compliance_id {
+            Some(compliance_id) => log!("@{} is the compliance officer", compliance_id),
+            None => log!("The compliance officer was removed"),
+        }
+        self.force_transfers.compliance_id = compliance_id;
+    }
+
+    /// Moves `amount` from `from` to `to` without the consent of `from`. Only callable by the
+    /// compliance officer.
+    #[payable]
+    pub fn force_transfer(
+        &mut self,
+        from: AccountId,
+        to: AccountId,
+        amount: U128,
+        justification: String,
+    ) {
+        assert_one_yocto();
+        self.assert_not_renounced();
+        let compliance_id = env::predecessor_account_id();
+        assert_eq!(
+            Some(&compliance_id),
+            self.force_transfers.compliance_id.as_ref(),
+            "Only the compliance officer can force transfers"
+        );
+        assert!(
+            !justification.trim().is_empty() && justification.len() <= MAX_JUSTIFICATION_LEN,
+            "The justification has 1 to {} bytes",
+            MAX_JUSTIFICATION_LEN
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        self.has_transfers = true;
+        self.with_checkpoints(&[from.clone(), to.clone()], |this| {
+            this.token
+                .internal_transfer(&from, &to, amount.0, Some("Forced transfer".to_string()))
+        });
+        self.force_transfers.count += 1;
+        emit_event(
+            EVENT_VERSION,
+            "force_transfer",
+            json!({
+                "compliance_id": compliance_id,
+                "from": from,
+                "to": to,
+                "amount": amount,
+                "justification": justification,
+                "count": U64(self.force_transfers.count),
+            }),
+        );
+    }
+
+    pub fn get_compliance_officer(&self) -> Option<AccountId> {
+        self.force_transfers.compliance_id.clone()
+    }
+
+    pub fn get_forced_transfer_count(&self) -> U64 {
+        self.force_transfers.count.into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{owner, setup};
+
+    #[test]
+    fn test_force_transfer_from_frozen_account() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        contract.set_compliance_officer(Some(accounts(2)));
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.freeze_account(owner.clone(), "Court order".to_string());
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.force_transfer(
+            owner.clone(),
+            accounts(1),
+            U128(1_000),
+            "Court order 42".to_string(),
+        );
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.ft_balance_of(owner).0, TOTAL_SUPPLY - 1_000);
+        assert_eq!(contract.get_forced_transfer_count().0, 1);
+    }
+}
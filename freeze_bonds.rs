@@ -0,0 +1,184 @@
+/*!
+Dispute bonds of account freezes.
+NOTES:
+  - Once governance sets a freeze bond with a `SetFreezeBond` proposal, every freeze locks that
+    much ASTRO from the compliance officer's balance, see `freezes`. The freeze record keeps who
+    posted it, so the compliance power stays accountable.
+  - A `RuleFreezeWrongful` proposal lifts the freeze if it is still active and pays the bond to
+    the frozen account. Otherwise anyone can return the bond to the officer who posted it
+    `FREEZE_DISPUTE_PERIOD` after the freeze was lifted.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance};
+
+use crate::freezes::{FreezeRecord, Freezes};
+use crate::pool_ledger::Pool;
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+/// Time after lifting a freeze during which governance can still rule it wrongful, 30 days in
+/// nanoseconds.
+pub const FREEZE_DISPUTE_PERIOD: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum BondOutcome {
+    /// Returned to the compliance officer that posted it.
+    Returned,
+    /// Paid to the frozen account after governance ruled the freeze wrongful.
+    Compensated,
+}
+
+impl Freezes {
+    fn unwrap_record(&self, account_id: &AccountId, index: u32) -> FreezeRecord {
+        self.history
+            .get(account_id)
+            .and_then(|history| history.get(index as usize))
+            .cloned()
+            .expect("Freeze not found")
+    }
+
+    fn set_record(&mut self, account_id: &AccountId, index: u32, record: FreezeRecord) {
+        let mut history = self.history.get(account_id).cloned().unwrap();
+        history[index as usize] = record;
+        self.history.insert(account_id.clone(), history);
+        self.history.flush();
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns the bond of the freeze `index` of `account_id` to the officer that posted it, once
+    /// the dispute period after lifting it is over.
+    pub fn reclaim_freeze_bond(&mut self, account_id: AccountId, index: u32) -> U128 {
+        let mut record = self.freezes.unwrap_record(&account_id, index);
+        assert!(record.bond_outcome.is_none(), "The bond is already settled");
+        let lifted_at = record.lifted_at.expect("The freeze is still active").0;
+        assert!(
+            env::block_timestamp() >= lifted_at + FREEZE_DISPUTE_PERIOD,
+            "The freeze can be disputed until {}",
+            lifted_at + FREEZE_DISPUTE_PERIOD
+        );
+        let bond = record.bond;
+        let frozen_by = record.frozen_by.clone();
+        record.bond_outcome = Some(BondOutcome::Returned);
+        self.freezes.set_record(&account_id, index, record);
+        if bond.0 > 0 {
+            self.internal_unlock_from(Pool::FreezeBonds, &frozen_by, bond.0);
+        }
+        emit_event(
+            EVENT_VERSION,
+            "freeze_bond_returned",
+            json!({ "account_id": account_id, "index": index, "frozen_by": frozen_by, "bond": bond }),
+        );
+        bond
+    }
+
+    pub fn get_freeze_bond(&self) -> U128 {
+        self.freezes.bond.into()
+    }
+}
+
+impl Contract {
+    /// Locks the freeze bond from the compliance officer `frozen_by` and returns it.
+    pub(crate) fn internal_post_freeze_bond(&mut self, frozen_by: &AccountId) -> Balance {
+        let bond = self.freezes.bond;
+        if bond > 0 {
+            self.internal_lock_to(Pool::FreezeBonds, frozen_by, bond);
+        }
+        bond
+    }
+
+    /// Sets the bond of new freezes. Used by governance proposals.
+    pub(crate) fn internal_set_freeze_bond(&mut self, bond: Balance) {
+        self.freezes.bond = bond;
+        log!("Set the freeze bond to {}", bond);
+    }
+
+    /// Lifts the freeze `index` of `account_id` if it is still active and pays its bond to the
+    /// frozen account. Used by governance proposals.
+    pub(crate) fn internal_rule_freeze_wrongful(&mut self, account_id: &AccountId, index: u32) {
+        let mut record = self.freezes.unwrap_record(account_id, index);
+        assert!(record.bond_outcome.is_none(), "The bond is already settled");
+        if record.lifted_at.is_none() {
+            record.lifted_by = Some(env::current_account_id());
+            record.lifted_at = Some(env::block_timestamp().into());
+            record.lift_reason = Some("Ruled wrongful by governance".to_string());
+        }
+        let bond = record.bond;
+        record.bond_outcome = Some(BondOutcome::Compensated);
+        self.freezes.set_record(account_id, index, record);
+        if bond.0 > 0 {
+            self.internal_unlock_from(Pool::FreezeBonds, account_id, bond.0);
+        }
+        emit_event(
+            EVENT_VERSION,
+            "freeze_ruled_wrongful",
+            json!({ "account_id": account_id, "index": index, "compensation": bond }),
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    /// The compliance officer accounts(2) holds 10_000 tokens, with a freeze bond of 1_000.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner(), 1);
+        contract.ft_transfer(accounts(2), U128(10_000), None);
+        contract.set_compliance_officer(Some(accounts(2)));
+        contract.internal_set_freeze_bond(1_000);
+        call_as(&mut context, accounts(2), ONE_NEAR);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_wrongful_freeze_pays_the_bond_to_the_frozen_account() {
+        let (_, mut contract) = setup();
+        contract.freeze_account(accounts(1), "Suspicious activity".to_string());
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 9_000);
+        let record = &contract.get_freeze_history(accounts(1))[0];
+        assert_eq!(record.frozen_by, accounts(2));
+        assert_eq!(record.bond.0, 1_000);
+
+        contract.internal_rule_freeze_wrongful(&accounts(1), 0);
+        assert!(!contract.is_frozen(accounts(1)));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        let record = &contract.get_freeze_history(accounts(1))[0];
+        assert_eq!(record.bond_outcome, Some(BondOutcome::Compensated));
+    }
+
+    #[test]
+    fn test_bond_returns_to_the_officer_after_the_dispute_period() {
+        let (mut context, mut contract) = setup();
+        contract.freeze_account(accounts(1), "Suspicious activity".to_string());
+        contract.unfreeze_account(accounts(1), "Cleared".to_string());
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 9_000);
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(FREEZE_DISPUTE_PERIOD)
+            .build());
+        assert_eq!(contract.reclaim_freeze_bond(accounts(1), 0).0, 1_000);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "The freeze can be disputed until")]
+    fn test_bond_is_held_during_the_dispute_period() {
+        let (mut context, mut contract) = setup();
+        contract.freeze_account(accounts(1), "Suspicious activity".to_string());
+        contract.unfreeze_account(accounts(1), "Cleared".to_string());
+        testing_env!(context.block_timestamp(FREEZE_DISPUTE_PERIOD - 1).build());
+        contract.reclaim_freeze_bond(accounts(1), 0);
+    }
+}
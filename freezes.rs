@@ -0,0 +1,197 @@
+/*!
+Admin account freezes with retained history.
+NOTES:
+  - The compliance officer, see `force_transfers`, can freeze an account, giving a reason. A frozen
+    account can neither send nor receive `ft_transfer` and `ft_transfer_call`. The officer, the
+    owner or the DAO can lift a freeze.
+  - Lifting a freeze never deletes it: the record is marked as lifted, with who lifted it, when and
+    why, so erroneous freezes stay visible. `get_freeze_history` returns every freeze of an account
+    for audits.
+  - The caller pays for the storage of new records.
+  - Every freeze escrows the bond set by governance from the officer, see `freeze_bonds`.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance};
+
+use crate::freeze_bonds::BondOutcome;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FreezeRecord {
+    /// Compliance officer that froze the account and posted the bond.
+    pub frozen_by: AccountId,
+    pub frozen_at: U64,
+    pub reason: String,
+    pub lifted_by: Option<AccountId>,
+    pub lifted_at: Option<U64>,
+    pub lift_reason: Option<String>,
+    /// ASTRO escrowed by `frozen_by`, settled once `bond_outcome` is set.
+    pub bond: U128,
+    pub bond_outcome: Option<BondOutcome>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Freezes {
+    /// Freezes of each account, oldest first. Only the last one can be active.
+    pub history: LookupMap<AccountId, Vec<FreezeRecord>>,
+    /// Bond escrowed with every new freeze.
+    pub bond: Balance,
+}
+
+impl Freezes {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            history: LookupMap::new(prefix),
+            bond: 0,
+        }
+    }
+
+    pub fn is_frozen(&self, account_id: &AccountId) -> bool {
+        self.history
+            .get(account_id)
+            .and_then(|history| history.last())
+            .is_some_and(|record| record.lifted_at.is_none())
+    }
+
+    pub fn assert_not_frozen(&self, account_id: &AccountId) {
+        assert!(
+            !self.is_frozen(account_id),
+            "The account {} is frozen",
+            account_id
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Freezes `account_id`, locking the freeze bond from the caller. Only callable by the
+    /// compliance officer.
+    #[payable]
+    pub fn freeze_account(&mut self, account_id: AccountId, reason: String) {
+        self.assert_not_renounced();
+        let frozen_by = env::predecessor_account_id();
+        assert!(
+            self.force_transfers.is_compliance_officer(&frozen_by),
+            "Only the compliance officer can freeze accounts"
+        );
+        assert!(
+            !self.freezes.is_frozen(&account_id),
+            "The account is already frozen"
+        );
+        let initial_storage = env::storage_usage();
+        let bond = self.internal_post_freeze_bond(&frozen_by);
+        let mut history = self
+            .freezes
+            .history
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default();
+        history.push(FreezeRecord {
+            frozen_by,
+            frozen_at: env::block_timestamp().into(),
+            reason: reason.clone(),
+            lifted_by: None,
+            lifted_at: None,
+            lift_reason: None,
+            bond: bond.into(),
+            bond_outcome: None,
+        });
+        self.freezes.history.insert(account_id.clone(), history);
+        self.freezes.history.flush();
+        refund_deposit(env::storage_usage() - initial_storage);
+        log!("Froze @{}: {}", account_id, reason);
+    }
+
+    /// Lifts the active freeze of `account_id`, keeping it in the history. Callable by the
+    /// compliance officer, the owner or the DAO.
+    #[payable]
+    pub fn unfreeze_account(&mut self, account_id: AccountId, reason: String) {
+        if !self
+            .force_transfers
+            .is_compliance_officer(&env::predecessor_account_id())
+        {
+            self.assert_owner_or_dao();
+        }
+        assert!(
+            self.freezes.is_frozen(&account_id),
+            "The account is not frozen"
+        );
+        let initial_storage = env::storage_usage();
+        let mut history = self.freezes.history.get(&account_id).cloned().unwrap();
+        let record = history.last_mut().unwrap();
+        record.lifted_by = Some(env::predecessor_account_id());
+        record.lifted_at = Some(env::block_timestamp().into());
+        record.lift_reason = Some(reason.clone());
+        self.freezes.history.insert(account_id.clone(), history);
+        self.freezes.history.flush();
+        refund_deposit(env::storage_usage() - initial_storage);
+        log!("Unfroze @{}: {}", account_id, reason);
+    }
+
+    pub fn is_frozen(&self, account_id: AccountId) -> bool {
+        self.freezes.is_frozen(&account_id)
+    }
+
+    pub fn get_freeze_history(&self, account_id: AccountId) -> Vec<FreezeRecord> {
+        self.freezes
+            .history
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    /// accounts(1) frozen by the compliance officer accounts(2), which holds 10_000 tokens.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner(), 1);
+        contract.ft_transfer(accounts(2), U128(10_000), None);
+        contract.set_compliance_officer(Some(accounts(2)));
+        call_as(&mut context, accounts(2), ONE_NEAR);
+        contract.freeze_account(accounts(1), "Stolen keys".to_string());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_unfreeze_keeps_history() {
+        let (mut context, mut contract) = setup();
+        contract.unfreeze_account(accounts(1), "Wrong account".to_string());
+        assert!(!contract.is_frozen(accounts(1)));
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(10), None);
+
+        let history = contract.get_freeze_history(accounts(1));
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].reason, "Stolen keys");
+        assert_eq!(history[0].lift_reason.as_deref(), Some("Wrong account"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the compliance officer can freeze accounts")]
+    fn test_owner_cannot_freeze() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.freeze_account(accounts(3), "Suspicious activity".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "is frozen")]
+    fn test_frozen_account_cannot_receive() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(10), None);
+    }
+}
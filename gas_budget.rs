@@ -0,0 +1,116 @@
+/*!
+Gas budgeting of batch methods, and batch transfers.
+NOTES:
+  - A `GasBudget` is checked before each item of a batch. It keeps the most gas any item used so
+    far and refuses the next one once the gas left could not cover another such item plus a
+    reserve for the end of the method. Batches then stop cleanly instead of running out of gas
+    and losing the whole transaction.
+  - Items processed before the stop are kept. `ft_transfer_batch`, `sweep_inactive_accounts`
+    and `process_distribution` report how far they went so callers can resume with the rest.
+*/
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Gas};
+
+use crate::*;
+
+/// Gas kept for the work done after the last item, e.g. storage refunds and logs.
+const GAS_RESERVE: Gas = Gas(15_000_000_000_000);
+const MAX_BATCH_TRANSFERS: usize = 200;
+
+pub struct GasBudget {
+    reserve: Gas,
+    last_used: Option<Gas>,
+    max_item: Gas,
+}
+
+impl Default for GasBudget {
+    fn default() -> Self {
+        Self::with_reserve(GAS_RESERVE)
+    }
+}
+
+impl GasBudget {
+    pub fn with_reserve(reserve: Gas) -> Self {
+        Self {
+            reserve,
+            last_used: None,
+            max_item: Gas(0),
+        }
+    }
+
+    /// Whether another item fits in the gas left. Called before each item.
+    pub fn has_room(&mut self) -> bool {
+        let used = env::used_gas();
+        if let Some(last_used) = self.last_used {
+            self.max_item = Gas(self.max_item.0.max(used.0 - last_used.0));
+        }
+        self.last_used = Some(used);
+        env::prepaid_gas().0.saturating_sub(used.0) >= self.reserve.0 + self.max_item.0
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Transfers to each of `transfers` in order, as `ft_transfer` would, until the gas runs low.
+    /// Returns the number of transfers made; the caller sends the rest again.
+    #[payable]
+    pub fn ft_transfer_batch(
+        &mut self,
+        transfers: Vec<(AccountId, U128)>,
+        memo: Option<String>,
+    ) -> u32 {
+        assert_one_yocto();
+        assert!(
+            !transfers.is_empty() && transfers.len() <= MAX_BATCH_TRANSFERS,
+            "A batch has 1 to {} transfers",
+            MAX_BATCH_TRANSFERS
+        );
+        let sender_id = env::predecessor_account_id();
+        let mut budget = GasBudget::default();
+        let mut processed = 0;
+        for (receiver_id, amount) in transfers.iter().cloned() {
+            if !budget.has_room() {
+                break;
+            }
+            self.internal_ft_transfer(&sender_id, receiver_id, amount.0, memo.clone());
+            processed += 1;
+        }
+        if processed < transfers.len() as u32 {
+            log!(
+                "Gas ran low after {} of {} transfers",
+                processed,
+                transfers.len()
+            );
+        }
+        self.telemetry.record("ft_transfer_batch");
+        processed
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_ft_transfer_batch() {
+        let (mut context, mut contract) = setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner(), 1);
+        let processed =
+            contract.ft_transfer_batch(vec![(accounts(1), U128(5)), (accounts(2), U128(7))], None);
+        assert_eq!(processed, 2);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 7);
+    }
+
+    #[test]
+    fn test_budget_stops_before_the_reserve() {
+        let mut context = VMContextBuilder::new();
+        context.prepaid_gas(Gas(GAS_RESERVE.0 - 1));
+        testing_env!(context.build());
+        assert!(!GasBudget::default().has_room());
+    }
+}
@@ -0,0 +1,223 @@
+/*!
+Promotional NEAR rebates on the first transfers of each account.
+NOTES:
+  - While the promo is on, `ft_transfer` and `ft_transfer_call` pay `amount` back to the caller
+    for its first `max_per_account` transfers, out of a NEAR pool funded beforehand with
+    `fund_gas_rebates`, typically by the owner.
+  - The promo is opt-in: the owner or the DAO starts it with `start_gas_rebates` and can stop it
+    at any time. It stops by itself, with a `gas_rebates_depleted` event, once the pool cannot
+    pay another rebate.
+  - The pool also pays for the storage of the per-account counters. Counters are kept when an
+    account closes, so registering again does not restart its rebates.
+  - Only the owner or the DAO can withdraw what is left in the pool.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupMap;
+use near_sdk::{env, near_bindgen, AccountId, Balance, Promise};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct GasRebates {
+    pub active: bool,
+    /// NEAR set aside for rebates.
+    pub pool: Balance,
+    /// NEAR paid back per transfer.
+    pub amount: Balance,
+    pub max_per_account: u32,
+    /// Rebates paid to each account.
+    pub used: LookupMap<AccountId, u32>,
+}
+
+impl GasRebates {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            active: false,
+            pool: 0,
+            amount: 0,
+            max_per_account: 0,
+            used: LookupMap::new(prefix),
+        }
+    }
+
+    fn used(&self, account_id: &AccountId) -> u32 {
+        self.used.get(account_id).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasRebatesInfo {
+    pub active: bool,
+    pub pool: U128,
+    pub amount: U128,
+    pub max_per_account: u32,
+}
+
+fn emit_gas_rebates_event(event: &str, data: near_sdk::serde_json::Value) {
+    emit_event(EVENT_VERSION, event, data);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Adds the attached deposit to the rebate pool.
+    #[payable]
+    pub fn fund_gas_rebates(&mut self) -> U128 {
+        let amount = env::attached_deposit();
+        assert!(amount > 0, "Requires a positive attached deposit");
+        self.gas_rebates.pool += amount;
+        emit_gas_rebates_event(
+            "gas_rebates_funded",
+            json!({
+                "account_id": env::predecessor_account_id(),
+                "amount": U128(amount),
+                "pool": U128(self.gas_rebates.pool),
+            }),
+        );
+        self.gas_rebates.pool.into()
+    }
+
+    /// Pays `amount` back on the first `max_per_account` transfers of every account.
+    pub fn start_gas_rebates(&mut self, amount: U128, max_per_account: u32) {
+        self.assert_owner_or_dao();
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        assert!(
+            max_per_account > 0,
+            "The number of rebates per account should be a positive number"
+        );
+        assert!(
+            amount.0 <= self.gas_rebates.pool,
+            "The rebate pool holds {}",
+            self.gas_rebates.pool
+        );
+        self.gas_rebates.active = true;
+        self.gas_rebates.amount = amount.0;
+        self.gas_rebates.max_per_account = max_per_account;
+        emit_gas_rebates_event(
+            "gas_rebates_start",
+            json!({ "amount": amount, "max_per_account": max_per_account }),
+        );
+    }
+
+    pub fn stop_gas_rebates(&mut self) {
+        self.assert_owner_or_dao();
+        self.gas_rebates.active = false;
+        emit_gas_rebates_event(
+            "gas_rebates_stop",
+            json!({ "pool": U128(self.gas_rebates.pool) }),
+        );
+    }
+
+    pub fn withdraw_gas_rebates(&mut self, receiver_id: AccountId, amount: U128) {
+        self.assert_owner_or_dao();
+        assert!(
+            amount.0 <= self.gas_rebates.pool,
+            "The rebate pool holds {}",
+            self.gas_rebates.pool
+        );
+        self.gas_rebates.pool -= amount.0;
+        Promise::new(receiver_id.clone()).transfer(amount.0);
+        emit_gas_rebates_event(
+            "gas_rebates_withdrawn",
+            json!({
+                "account_id": receiver_id,
+                "amount": amount,
+                "pool": U128(self.gas_rebates.pool),
+            }),
+        );
+    }
+
+    pub fn get_gas_rebates(&self) -> GasRebatesInfo {
+        GasRebatesInfo {
+            active: self.gas_rebates.active,
+            pool: self.gas_rebates.pool.into(),
+            amount: self.gas_rebates.amount.into(),
+            max_per_account: self.gas_rebates.max_per_account,
+        }
+    }
+
+    /// Rebates `account_id` can still get while the promo is on.
+    pub fn gas_rebates_left(&self, account_id: AccountId) -> u32 {
+        self.gas_rebates
+            .max_per_account
+            .saturating_sub(self.gas_rebates.used(&account_id))
+    }
+}
+
+impl Contract {
+    /// Pays the rebate of a transfer by `account_id`, if any, and stops the promo once the pool
+    /// cannot pay the next one.
+    pub(crate) fn internal_pay_gas_rebate(&mut self, account_id: &AccountId) {
+        let rebates = &mut self.gas_rebates;
+        let used = rebates.used(account_id);
+        if !rebates.active || used >= rebates.max_per_account {
+            return;
+        }
+        let initial_storage = env::storage_usage();
+        rebates.used.insert(account_id.clone(), used + 1);
+        rebates.used.flush();
+        let storage_cost =
+            Balance::from(env::storage_usage() - initial_storage) * env::storage_byte_cost();
+        if rebates.amount + storage_cost > rebates.pool {
+            if used == 0 {
+                rebates.used.remove(account_id);
+            } else {
+                rebates.used.insert(account_id.clone(), used);
+            }
+            rebates.used.flush();
+            rebates.active = false;
+            emit_gas_rebates_event(
+                "gas_rebates_depleted",
+                json!({ "pool": U128(rebates.pool) }),
+            );
+            return;
+        }
+        rebates.pool -= rebates.amount + storage_cost;
+        Promise::new(account_id.clone()).transfer(rebates.amount);
+        if rebates.pool < rebates.amount {
+            rebates.active = false;
+            emit_gas_rebates_event(
+                "gas_rebates_depleted",
+                json!({ "pool": U128(rebates.pool) }),
+            );
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    const REBATE: Balance = ONE_NEAR / 100;
+
+    #[test]
+    fn test_rebates_stop_per_account_and_when_depleted() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        call_as(&mut context, owner.clone(), 3 * REBATE);
+        contract.fund_gas_rebates();
+        contract.start_gas_rebates(U128(REBATE), 2);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(10), None);
+        contract.ft_transfer(accounts(1), U128(10), None);
+        assert_eq!(contract.gas_rebates_left(owner.clone()), 0);
+        let pool = contract.get_gas_rebates().pool.0;
+        assert!(pool < REBATE && pool > 0);
+        assert!(!contract.get_gas_rebates().active);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(owner, U128(10), None);
+        assert_eq!(contract.gas_rebates_left(accounts(1)), 2);
+    }
+}
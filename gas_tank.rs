@@ -0,0 +1,143 @@
+/*!
+NEAR gas tank paying for maintenance calls.
+NOTES:
+  - Maintenance actions nobody has a direct interest in calling, like executing a proposal or
+    finalizing a funding round, are paid for by the contract: the caller gets `keeper_fee` from
+    the tank. The contract cannot wake itself up with the `near-sdk` version in use, so somebody
+    still has to send the call, but it costs them nothing.
+  - Anyone can fund the tank with `fund_gas_tank`, typically the DAO. Only the owner or the DAO can
+    withdraw from it or change its settings.
+  - A `gas_tank_low` event is emitted whenever a payment leaves less than `low_threshold` in the
+    tank, so it can be topped up before it runs dry. An empty tank stops paying, it never blocks
+    the maintenance action.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::{env, near_bindgen, AccountId, Balance, Promise};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasTank {
+    pub balance: U128,
+    pub low_threshold: U128,
+    /// NEAR paid for each maintenance call.
+    pub keeper_fee: U128,
+}
+
+impl Default for GasTank {
+    fn default() -> Self {
+        Self {
+            balance: U128(0),
+            low_threshold: U128(0),
+            keeper_fee: U128(0),
+        }
+    }
+}
+
+fn emit_gas_tank_event(event: &str, account_id: &AccountId, amount: Balance, balance: Balance) {
+    emit_event(
+        EVENT_VERSION,
+        event,
+        json!({
+            "account_id": account_id,
+            "amount": U128(amount),
+            "balance": U128(balance),
+        }),
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Adds the attached deposit to the gas tank.
+    #[payable]
+    pub fn fund_gas_tank(&mut self) -> U128 {
+        let amount = env::attached_deposit();
+        assert!(amount > 0, "Requires a positive attached deposit");
+        self.gas_tank.balance = (self.gas_tank.balance.0 + amount).into();
+        emit_gas_tank_event(
+            "gas_tank_funded",
+            &env::predecessor_account_id(),
+            amount,
+            self.gas_tank.balance.0,
+        );
+        self.gas_tank.balance
+    }
+
+    pub fn withdraw_gas_tank(&mut self, receiver_id: AccountId, amount: U128) {
+        self.assert_owner_or_dao();
+        assert!(
+            amount.0 <= self.gas_tank.balance.0,
+            "The gas tank balance is {}",
+            self.gas_tank.balance.0
+        );
+        self.gas_tank.balance = (self.gas_tank.balance.0 - amount.0).into();
+        Promise::new(receiver_id.clone()).transfer(amount.0);
+        emit_gas_tank_event(
+            "gas_tank_withdrawn",
+            &receiver_id,
+            amount.0,
+            self.gas_tank.balance.0,
+        );
+    }
+
+    pub fn set_gas_tank_config(&mut self, low_threshold: U128, keeper_fee: U128) {
+        self.assert_owner_or_dao();
+        self.gas_tank.low_threshold = low_threshold;
+        self.gas_tank.keeper_fee = keeper_fee;
+    }
+
+    pub fn get_gas_tank(&self) -> GasTank {
+        self.gas_tank.clone()
+    }
+}
+
+impl Contract {
+    /// Pays the keeper fee to the caller of a maintenance action while the tank can afford it.
+    pub(crate) fn internal_pay_keeper(&mut self) {
+        let fee = self.gas_tank.keeper_fee.0;
+        if fee == 0 || fee > self.gas_tank.balance.0 {
+            return;
+        }
+        let keeper_id = env::predecessor_account_id();
+        self.gas_tank.balance = (self.gas_tank.balance.0 - fee).into();
+        Promise::new(keeper_id.clone()).transfer(fee);
+        if self.gas_tank.balance.0 < self.gas_tank.low_threshold.0 {
+            emit_gas_tank_event("gas_tank_low", &keeper_id, fee, self.gas_tank.balance.0);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_keeper_fee_until_empty() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 350);
+        contract.fund_gas_tank();
+        contract.set_gas_tank_config(U128(200), U128(100));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(0)
+            .build());
+        contract.internal_pay_keeper();
+        assert!(get_logs().is_empty());
+        contract.internal_pay_keeper();
+        assert!(get_logs()[0].contains("gas_tank_low"));
+        contract.internal_pay_keeper();
+        contract.internal_pay_keeper();
+        assert_eq!(contract.get_gas_tank().balance.0, 50);
+    }
+}
@@ -0,0 +1,557 @@
+/*!
+Token-weighted governance over admin actions.
+NOTES:
+  - Any holder can create a proposal with a list of actions. Votes are weighted by the voter's
+    voting power, including delegations, at the block before the proposal was created, so tokens
+    moved after that cannot vote twice.
+  - Once `voting_period` has passed anyone can finalize the proposal. It passes if more weight
+    voted for than against and the turnout reaches `quorum_bps` of the total supply at the
+    snapshot; its actions are then executed in order.
+  - Voters can burn tokens with `vote_with_burn` for extra weight: each burned token adds one
+    vote, up to `burn_boost_cap_bps` of the voter's snapshot weight. Proposals record the boosted
+    part of their totals separately.
+  - Proposers pay for the storage of their proposals, voters for the storage of their votes.
+  - Balance and vote checkpoints needed at the snapshot of a proposal open for voting are not
+    pruned, see `checkpoints`. The snapshots of open proposals are kept in their own map, paid by
+    the proposer, and dropped when the proposal is executed or once its voting has ended.
+  - Name and symbol changes of passed proposals wait `METADATA_CHANGE_DELAY` like the other
+    metadata changes, see `metadata_control`.
+*/
+use near_contract_standards::fungible_token::events::FtBurn;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::{LookupMap, LookupSet, UnorderedMap};
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, BlockHeight};
+
+use crate::fee_rebates::RebateTier;
+use crate::inactivity_decay::InactivityDecayConfig;
+use crate::randomness::RandomnessSourceKind;
+use crate::retirement::Module;
+use crate::*;
+
+/// Default voting period of 3 days in nanoseconds.
+const DEFAULT_VOTING_PERIOD: u64 = 3 * 24 * 60 * 60 * 1_000_000_000;
+/// Default quorum of 10% of the total supply.
+const DEFAULT_QUORUM_BPS: u16 = 1_000;
+/// Default cap of the burn boost, doubling the voter's weight at most.
+const DEFAULT_BURN_BOOST_CAP_BPS: u16 = 10_000;
+const MAX_BPS: u16 = 10_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ProposalAction {
+    Mint {
+        receiver_id: AccountId,
+        amount: U128,
+    },
+    UpdateMetadata {
+        name: Option<String>,
+        symbol: Option<String>,
+        icon: Option<String>,
+        reference: Option<String>,
+        reference_hash: Option<Base64VecU8>,
+        decimals: Option<u8>,
+    },
+    SetRandomnessSource {
+        source: RandomnessSourceKind,
+    },
+    RetireModule {
+        module: Module,
+    },
+    SetFeeRebateTiers {
+        tiers: Vec<RebateTier>,
+    },
+    SetInactivityDecay {
+        config: Option<InactivityDecayConfig>,
+    },
+    SetFreezeBond {
+        amount: U128,
+    },
+    RuleFreezeWrongful {
+        account_id: AccountId,
+        index: u32,
+    },
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ProposalStatus {
+    Active,
+    Executed,
+    Rejected,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Proposal {
+    pub proposer_id: AccountId,
+    pub description: String,
+    pub actions: Vec<ProposalAction>,
+    pub snapshot_block: U64,
+    pub voting_ends_at: U64,
+    pub votes_for: U128,
+    pub votes_against: U128,
+    /// Parts of `votes_for` and `votes_against` gained by burning tokens.
+    pub burn_boost_for: U128,
+    pub burn_boost_against: U128,
+    pub status: ProposalStatus,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Governance {
+    pub proposals: LookupMap<u64, Proposal>,
+    pub votes: LookupSet<(u64, AccountId)>,
+    /// Time in nanoseconds a proposal is open for voting.
+    pub voting_period: u64,
+    /// Minimum turnout in basis points of the total supply at the snapshot.
+    pub quorum_bps: u16,
+    /// Maximum burn boost in basis points of the voter's weight.
+    pub burn_boost_cap_bps: u16,
+    pub next_proposal_id: u64,
+    /// Snapshot block and end of voting of each active proposal.
+    pub open_snapshots: UnorderedMap<u64, (BlockHeight, u64)>,
+}
+
+impl Governance {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            proposals: LookupMap::new([prefix.as_slice(), b"p"].concat()),
+            votes: LookupSet::new([prefix.as_slice(), b"v"].concat()),
+            voting_period: DEFAULT_VOTING_PERIOD,
+            quorum_bps: DEFAULT_QUORUM_BPS,
+            burn_boost_cap_bps: DEFAULT_BURN_BOOST_CAP_BPS,
+            next_proposal_id: 0,
+            open_snapshots: UnorderedMap::new([prefix.as_slice(), b"o"].concat()),
+        }
+    }
+
+    /// Snapshot block of the oldest proposal still open for voting.
+    pub fn oldest_snapshot(&self) -> Option<BlockHeight> {
+        let now = env::block_timestamp();
+        self.open_snapshots
+            .values()
+            .filter(|(_, voting_ends_at)| *voting_ends_at > now)
+            .map(|(snapshot_block, _)| *snapshot_block)
+            .min()
+    }
+
+    /// Drops the snapshots of the proposals whose voting has ended.
+    fn prune_open_snapshots(&mut self) {
+        let now = env::block_timestamp();
+        let expired: Vec<u64> = self
+            .open_snapshots
+            .iter()
+            .filter(|(_, (_, voting_ends_at))| *voting_ends_at <= now)
+            .map(|(proposal_id, _)| *proposal_id)
+            .collect();
+        for proposal_id in expired {
+            self.open_snapshots.remove(&proposal_id);
+        }
+    }
+
+    pub fn unwrap_proposal(&self, proposal_id: u64) -> Proposal {
+        self.proposals
+            .get(&proposal_id)
+            .cloned()
+            .expect("Proposal not found")
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_governance_voting_period(&mut self, voting_period: U64) {
+        self.assert_owner();
+        assert!(voting_period.0 > 0, "Voting period must be positive");
+        self.governance.voting_period = voting_period.0;
+    }
+
+    pub fn set_governance_quorum(&mut self, quorum_bps: u16) {
+        self.assert_owner();
+        assert!(quorum_bps <= MAX_BPS, "Quorum cannot exceed {}", MAX_BPS);
+        self.governance.quorum_bps = quorum_bps;
+    }
+
+    pub fn set_burn_boost_cap(&mut self, burn_boost_cap_bps: u16) {
+        self.assert_owner();
+        self.governance.burn_boost_cap_bps = burn_boost_cap_bps;
+    }
+
+    /// Creates a proposal to execute `actions` and returns its id. The caller must hold tokens
+    /// at the snapshot block. The attached deposit covers the proposal storage.
+    #[payable]
+    pub fn create_proposal(&mut self, description: String, actions: Vec<ProposalAction>) -> U64 {
+        assert!(
+            !actions.is_empty(),
+            "Proposal must have at least one action"
+        );
+        let proposer_id = env::predecessor_account_id();
+        let snapshot_block = env::block_height().saturating_sub(1);
+        assert!(
+            self.voting_weight(&proposer_id, snapshot_block) > 0,
+            "Only token holders can create proposals"
+        );
+        self.governance.prune_open_snapshots();
+        self.governance.open_snapshots.flush();
+        let initial_storage = env::storage_usage();
+        let proposal_id = self.governance.next_proposal_id;
+        self.governance.next_proposal_id += 1;
+        let voting_ends_at = env::block_timestamp() + self.governance.voting_period;
+        self.governance
+            .open_snapshots
+            .insert(proposal_id, (snapshot_block, voting_ends_at));
+        self.governance.proposals.insert(
+            proposal_id,
+            Proposal {
+                proposer_id: proposer_id.clone(),
+                description,
+                actions,
+                snapshot_block: snapshot_block.into(),
+                voting_ends_at: voting_ends_at.into(),
+                votes_for: U128(0),
+                votes_against: U128(0),
+                burn_boost_for: U128(0),
+                burn_boost_against: U128(0),
+                status: ProposalStatus::Active,
+            },
+        );
+        self.governance.proposals.flush();
+        self.governance.open_snapshots.flush();
+        self.scheduler.schedule(
+            ScheduledAction::ExecuteProposal {
+                proposal_id: proposal_id.into(),
+            },
+            voting_ends_at,
+        );
+        refund_deposit(env::storage_usage() - initial_storage);
+        log!("@{} created proposal {}", proposer_id, proposal_id);
+        proposal_id.into()
+    }
+
+    /// Votes on an active proposal with the caller's balance at the proposal snapshot.
+    /// The attached deposit covers the vote storage.
+    #[payable]
+    pub fn vote(&mut self, proposal_id: U64, approve: bool) {
+        self.internal_vote(proposal_id.0, approve, 0);
+    }
+
+    /// Votes like `vote` and burns `burn_amount` of the caller's current balance for a boost of
+    /// the same weight, capped at `burn_boost_cap_bps` of the snapshot weight. Returns the boost.
+    #[payable]
+    pub fn vote_with_burn(&mut self, proposal_id: U64, approve: bool, burn_amount: U128) -> U128 {
+        assert!(
+            burn_amount.0 > 0,
+            "The burn amount should be a positive number"
+        );
+        self.internal_vote(proposal_id.0, approve, burn_amount.0)
+            .into()
+    }
+
+    /// Finalizes a proposal after its voting period, executing its actions if it passed.
+    pub fn execute_proposal(&mut self, proposal_id: U64) -> ProposalStatus {
+        let mut proposal = self.governance.unwrap_proposal(proposal_id.0);
+        assert_eq!(
+            proposal.status,
+            ProposalStatus::Active,
+            "Proposal is not active"
+        );
+        assert!(
+            env::block_timestamp() >= proposal.voting_ends_at.0,
+            "Voting period has not ended"
+        );
+        let turnout = proposal.votes_for.0 + proposal.votes_against.0;
+        let total_supply = self
+            .checkpoints
+            .total_supply_at(proposal.snapshot_block.0)
+            .unwrap_or(self.token.total_supply);
+        let quorum =
+            total_supply / Balance::from(MAX_BPS) * Balance::from(self.governance.quorum_bps);
+        proposal.status = if proposal.votes_for.0 > proposal.votes_against.0 && turnout >= quorum {
+            ProposalStatus::Executed
+        } else {
+            ProposalStatus::Rejected
+        };
+        self.governance
+            .proposals
+            .insert(proposal_id.0, proposal.clone());
+        self.governance.open_snapshots.remove(&proposal_id.0);
+        self.scheduler
+            .unschedule(&ScheduledAction::ExecuteProposal { proposal_id });
+        if proposal.status == ProposalStatus::Executed {
+            for action in proposal.actions {
+                self.internal_execute_action(action);
+            }
+        }
+        self.internal_pay_keeper();
+        log!("Proposal {} {:?}", proposal_id.0, proposal.status);
+        proposal.status
+    }
+
+    pub fn get_proposal(&self, proposal_id: U64) -> Option<Proposal> {
+        self.governance.proposals.get(&proposal_id.0).cloned()
+    }
+
+    pub fn has_voted(&self, proposal_id: U64, account_id: AccountId) -> bool {
+        self.governance.votes.contains(&(proposal_id.0, account_id))
+    }
+}
+
+impl Contract {
+    fn voting_weight(&self, account_id: &AccountId, block_height: BlockHeight) -> Balance {
+        self.votes_at(account_id, block_height)
+    }
+
+    fn internal_vote(&mut self, proposal_id: u64, approve: bool, burn_amount: Balance) -> Balance {
+        let mut proposal = self.governance.unwrap_proposal(proposal_id);
+        assert_eq!(
+            proposal.status,
+            ProposalStatus::Active,
+            "Proposal is not active"
+        );
+        assert!(
+            env::block_timestamp() < proposal.voting_ends_at.0,
+            "Voting period has ended"
+        );
+        let voter_id = env::predecessor_account_id();
+        let weight = self.voting_weight(&voter_id, proposal.snapshot_block.0);
+        assert!(weight > 0, "No voting weight at the proposal snapshot");
+        let max_boost =
+            weight / Balance::from(MAX_BPS) * Balance::from(self.governance.burn_boost_cap_bps);
+        assert!(
+            burn_amount <= max_boost,
+            "The burn boost is capped at {}",
+            max_boost
+        );
+        let initial_storage = env::storage_usage();
+        assert!(
+            self.governance
+                .votes
+                .insert((proposal_id, voter_id.clone())),
+            "Already voted"
+        );
+        if burn_amount > 0 {
+            self.with_checkpoints(std::slice::from_ref(&voter_id), |this| {
+                this.token.internal_withdraw(&voter_id, burn_amount)
+            });
+            FtBurn {
+                owner_id: &voter_id,
+                amount: &U128(burn_amount),
+                memo: Some("Burn to vote"),
+            }
+            .emit();
+        }
+        let total = weight + burn_amount;
+        if approve {
+            proposal.votes_for = (proposal.votes_for.0 + total).into();
+            proposal.burn_boost_for = (proposal.burn_boost_for.0 + burn_amount).into();
+        } else {
+            proposal.votes_against = (proposal.votes_against.0 + total).into();
+            proposal.burn_boost_against = (proposal.burn_boost_against.0 + burn_amount).into();
+        }
+        self.governance.proposals.insert(proposal_id, proposal);
+        refund_deposit(env::storage_usage() - initial_storage);
+        log!(
+            "@{} voted {} on proposal {} with {}",
+            voter_id,
+            if approve { "for" } else { "against" },
+            proposal_id,
+            total
+        );
+        burn_amount
+    }
+
+    fn internal_execute_action(&mut self, action: ProposalAction) {
+        match action {
+            ProposalAction::Mint {
+                receiver_id,
+                amount,
+            } => {
+                self.assert_not_renounced();
+                self.internal_mint(&env::current_account_id(), &receiver_id, amount.into());
+                log!("Minted {} to @{}", amount.0, receiver_id);
+            }
+            ProposalAction::UpdateMetadata {
+                name,
+                symbol,
+                icon,
+                reference,
+                reference_hash,
+                decimals,
+            } => {
+                self.assert_not_renounced();
+                self.internal_update_metadata(
+                    None,
+                    None,
+                    icon,
+                    reference,
+                    reference_hash,
+                    decimals,
+                );
+                if name.is_some() || symbol.is_some() {
+                    self.internal_announce_metadata_change(name, symbol);
+                }
+            }
+            ProposalAction::SetRandomnessSource { source } => {
+                self.internal_set_randomness_source(source);
+            }
+            ProposalAction::RetireModule { module } => {
+                self.internal_retire_module(module);
+            }
+            ProposalAction::SetFeeRebateTiers { tiers } => {
+                self.internal_set_fee_rebate_tiers(tiers);
+            }
+            ProposalAction::SetInactivityDecay { config } => {
+                self.internal_set_inactivity_decay(config);
+            }
+            ProposalAction::SetFreezeBond { amount } => {
+                self.internal_set_freeze_bond(amount.0);
+            }
+            ProposalAction::RuleFreezeWrongful { account_id, index } => {
+                self.internal_rule_freeze_wrongful(&account_id, index);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::metadata::FungibleTokenMetadataProvider;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::chunked::{CHUNK_SIZE, MAX_CHUNKS};
+    use crate::metadata_control::METADATA_CHANGE_DELAY;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        testing_env!(context.block_index(20).build());
+        contract.create_proposal(
+            "Mint to accounts(1)".to_string(),
+            vec![ProposalAction::Mint {
+                receiver_id: accounts(1),
+                amount: U128(1_000),
+            }],
+        );
+        (context, contract)
+    }
+
+    #[test]
+    fn test_passed_proposal_executes_actions() {
+        let (mut context, mut contract) = setup();
+        contract.vote(U64(0), true);
+        testing_env!(context
+            .block_timestamp(DEFAULT_VOTING_PERIOD)
+            .attached_deposit(0)
+            .build());
+        assert_eq!(contract.execute_proposal(U64(0)), ProposalStatus::Executed);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY + 1_000);
+    }
+
+    #[test]
+    fn test_burn_boost() {
+        let (_, mut contract) = setup();
+        assert_eq!(contract.vote_with_burn(U64(0), false, U128(1_000)).0, 1_000);
+        let proposal = contract.get_proposal(U64(0)).unwrap();
+        assert_eq!(proposal.votes_against.0, TOTAL_SUPPLY + 1_000);
+        assert_eq!(proposal.burn_boost_against.0, 1_000);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "No voting weight at the proposal snapshot")]
+    fn test_tokens_received_after_snapshot_cannot_vote() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(TOTAL_SUPPLY / 2), None);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.vote(U64(0), true);
+    }
+
+    #[test]
+    fn test_snapshots_are_dropped_once_voting_ends() {
+        let (mut context, mut contract) = setup();
+        assert_eq!(contract.governance.open_snapshots.len(), 1);
+        testing_env!(context.block_timestamp(DEFAULT_VOTING_PERIOD).build());
+        contract.create_proposal(
+            "Mint to accounts(2)".to_string(),
+            vec![ProposalAction::Mint {
+                receiver_id: accounts(2),
+                amount: U128(1_000),
+            }],
+        );
+        assert_eq!(contract.governance.open_snapshots.len(), 1);
+        assert!(contract.governance.open_snapshots.contains_key(&1));
+    }
+
+    #[test]
+    fn test_name_change_waits_for_the_metadata_delay() {
+        let (mut context, mut contract) = setup();
+        contract.create_proposal(
+            "Rename".to_string(),
+            vec![ProposalAction::UpdateMetadata {
+                name: None,
+                symbol: Some("ASTRO2".to_string()),
+                icon: None,
+                reference: None,
+                reference_hash: None,
+                decimals: None,
+            }],
+        );
+        contract.vote(U64(1), true);
+        testing_env!(context
+            .block_timestamp(DEFAULT_VOTING_PERIOD)
+            .attached_deposit(0)
+            .build());
+        assert_eq!(contract.execute_proposal(U64(1)), ProposalStatus::Executed);
+        assert_eq!(contract.ft_metadata().symbol, "ASTRO");
+        let change = contract.get_pending_metadata_change().unwrap();
+        assert_eq!(
+            change.effective_at,
+            Some(U64(DEFAULT_VOTING_PERIOD + METADATA_CHANGE_DELAY))
+        );
+
+        testing_env!(context
+            .block_timestamp(DEFAULT_VOTING_PERIOD + METADATA_CHANGE_DELAY)
+            .build());
+        contract.apply_metadata_change();
+        assert_eq!(contract.ft_metadata().symbol, "ASTRO2");
+    }
+
+    #[test]
+    fn test_dust_spam_cannot_prune_the_snapshot() {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 1);
+        testing_env!(context.block_index(11).build());
+        contract.ft_transfer(accounts(1), U128(1_000), None);
+        testing_env!(context.attached_deposit(ONE_NEAR).block_index(20).build());
+        contract.create_proposal(
+            "Mint to accounts(1)".to_string(),
+            vec![ProposalAction::Mint {
+                receiver_id: accounts(1),
+                amount: U128(1_000),
+            }],
+        );
+
+        let spam = MAX_CHUNKS * CHUNK_SIZE + CHUNK_SIZE;
+        for block_height in 21..21 + u64::from(spam) {
+            testing_env!(context
+                .attached_deposit(1)
+                .block_index(block_height)
+                .build());
+            contract.ft_transfer(accounts(1), U128(1), None);
+        }
+        assert_eq!(contract.ft_balance_of_at(accounts(1), U64(19)).0, 1_000);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.vote(U64(0), true);
+        assert_eq!(contract.get_proposal(U64(0)).unwrap().votes_for.0, 1_000);
+    }
+}
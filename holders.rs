@@ -0,0 +1,90 @@
+/*!
+Holder analytics for explorers without an indexer.
+NOTES:
+  - Holders are the registered accounts with a positive balance, the contract account included.
+  - The views scan the account index, they are meant for view calls only. `get_balances` pages
+    through the index; `get_holders_count` and `get_top_holders` read every account and run out of
+    view gas once the index grows past a few tens of thousands of accounts.
+*/
+use near_sdk::json_types::U128;
+use near_sdk::{near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+/// Maximum number of accounts returned by `get_top_holders`.
+const MAX_TOP_HOLDERS: u64 = 100;
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_holders_count(&self) -> u32 {
+        self.registered_accounts
+            .iter()
+            .filter(|account_id| self.token.accounts.get(account_id).unwrap_or(0) > 0)
+            .count() as u32
+    }
+
+    /// The `limit` (at most 100) largest holders, largest first.
+    pub fn get_top_holders(&self, limit: Option<u64>) -> Vec<(AccountId, U128)> {
+        let limit = std::cmp::min(limit.unwrap_or(MAX_TOP_HOLDERS), MAX_TOP_HOLDERS) as usize;
+        let mut holders: Vec<(AccountId, Balance)> = self
+            .registered_accounts
+            .iter()
+            .map(|account_id| {
+                let balance = self.token.accounts.get(account_id).unwrap_or(0);
+                (account_id.clone(), balance)
+            })
+            .filter(|(_, balance)| *balance > 0)
+            .collect();
+        holders.sort_by_key(|holder| std::cmp::Reverse(holder.1));
+        holders
+            .into_iter()
+            .take(limit)
+            .map(|(account_id, balance)| (account_id, balance.into()))
+            .collect()
+    }
+
+    /// Balances of the registered accounts in index order, see `get_accounts`.
+    pub fn get_balances(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<(AccountId, U128)> {
+        self.get_accounts(from_index, limit)
+            .into_iter()
+            .map(|account_id| {
+                let balance = self.token.accounts.get(&account_id).unwrap_or(0);
+                (account_id, balance.into())
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_top_holders() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1), accounts(2)]);
+        assert_eq!(contract.get_holders_count(), 1);
+
+        call_as(&mut context, owner.clone(), 1);
+        contract.ft_transfer(accounts(1), U128(1_000), None);
+        assert_eq!(contract.get_holders_count(), 2);
+        assert_eq!(
+            contract.get_top_holders(Some(2)),
+            vec![
+                (owner, U128(TOTAL_SUPPLY - 1_000)),
+                (accounts(1), U128(1_000))
+            ]
+        );
+        assert_eq!(
+            contract.get_balances(Some(2), None),
+            vec![(accounts(1), U128(1_000)), (accounts(2), U128(0))]
+        );
+    }
+}
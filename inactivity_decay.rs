@@ -0,0 +1,346 @@
+/*!
+Optional demurrage on long inactive accounts, funding the staking reward pool.
+NOTES:
+  - Off by default. Only a governance proposal can turn it on, set its parameters or turn it off
+    again with `SetInactivityDecay`.
+  - Activity is the one `dust_sweeping` records: registrations and outgoing transfers. An
+    account is inactive once it has not been active for `inactive_years`. Epochs are counted at
+    `EPOCHS_PER_YEAR` per year, about 12 hours each.
+  - Anyone can process a batch of accounts with `apply_inactivity_decay`. An inactive account is
+    first warned with an `inactivity_warning` event; there is no on-chain inbox, wallets and
+    indexers deliver the event to the holder. Any activity afterwards cancels the warning.
+  - Decay starts `WARNING_EPOCHS` after the warning. Each full year of inactivity from then on
+    moves `decay_bps` of the account's free balance, at most `MAX_DECAY_BPS`, to the staking
+    reward pool, with an `inactivity_decay` event. Tokens held by balance locks and reservations
+    do not decay, see `balance_locks` and `reservations`.
+  - The owner account, the contract account and the exemptions set by the owner or the DAO never
+    decay. Tokens in custody of the contract, like staked ones, are not touched.
+  - The contract pays for the storage of the warnings. A batch stops early if the gas runs low,
+    see `gas_budget`.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::store::{LookupMap, LookupSet};
+use near_sdk::{env, near_bindgen, AccountId, Balance, EpochHeight};
+
+use crate::gas_budget::GasBudget;
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+pub const EPOCHS_PER_YEAR: u64 = 730;
+/// Epochs between the warning of an account and the start of its decay, about a month.
+pub const WARNING_EPOCHS: u64 = 60;
+pub const MAX_DECAY_BPS: u16 = 100;
+const MAX_BPS: u128 = 10_000;
+const MAX_DECAYED_ACCOUNTS: usize = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InactivityDecayConfig {
+    /// Years without activity after which an account is warned.
+    pub inactive_years: u8,
+    /// Share of the balance moved to the reward pool per year of decay, in basis points.
+    pub decay_bps: u16,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DecayStatus {
+    pub warned_at: U64,
+    /// Epoch up to which the decay of the account has been charged.
+    pub charged_until: U64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DecayResult {
+    /// Accounts of the batch considered, from the start.
+    pub processed: u32,
+    pub warned: u32,
+    pub decayed: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct InactivityDecay {
+    pub config: Option<InactivityDecayConfig>,
+    pub exempt: LookupSet<AccountId>,
+    pub accounts: LookupMap<AccountId, DecayStatus>,
+}
+
+impl InactivityDecay {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            config: None,
+            exempt: LookupSet::new([prefix.as_slice(), b"e"].concat()),
+            accounts: LookupMap::new([prefix.as_slice(), b"a"].concat()),
+        }
+    }
+}
+
+fn emit_inactivity_event(event: &str, data: near_sdk::serde_json::Value) {
+    emit_event(EVENT_VERSION, event, data);
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn add_decay_exemption(&mut self, account_id: AccountId) {
+        self.assert_owner_or_dao();
+        self.inactivity_decay.accounts.remove(&account_id);
+        self.inactivity_decay.exempt.insert(account_id.clone());
+        emit_inactivity_event("decay_exemption_added", json!({ "account_id": account_id }));
+    }
+
+    pub fn remove_decay_exemption(&mut self, account_id: AccountId) {
+        self.assert_owner_or_dao();
+        self.inactivity_decay.exempt.remove(&account_id);
+        emit_inactivity_event(
+            "decay_exemption_removed",
+            json!({ "account_id": account_id }),
+        );
+    }
+
+    /// Warns the inactive accounts among `account_ids` and charges the decay due on the warned
+    /// ones. Stops early if the gas runs low.
+    pub fn apply_inactivity_decay(&mut self, account_ids: Vec<AccountId>) -> DecayResult {
+        self.assert_not_paused();
+        let config = self
+            .inactivity_decay
+            .config
+            .clone()
+            .expect("The inactivity decay is off");
+        assert!(
+            account_ids.len() <= MAX_DECAYED_ACCOUNTS,
+            "At most {} accounts can be processed at once",
+            MAX_DECAYED_ACCOUNTS
+        );
+        let mut budget = GasBudget::default();
+        let mut result = DecayResult {
+            processed: 0,
+            warned: 0,
+            decayed: U128(0),
+        };
+        for account_id in account_ids {
+            if !budget.has_room() {
+                break;
+            }
+            result.processed += 1;
+            match self.decay_status(&account_id, &config) {
+                None => continue,
+                Some(None) => {
+                    self.internal_warn_inactive(&account_id);
+                    result.warned += 1;
+                }
+                Some(Some(status)) => {
+                    result.decayed.0 += self.internal_decay(&account_id, status, &config);
+                }
+            }
+        }
+        self.inactivity_decay.accounts.flush();
+        result
+    }
+
+    pub fn get_inactivity_decay(&self) -> Option<InactivityDecayConfig> {
+        self.inactivity_decay.config.clone()
+    }
+
+    pub fn is_decay_exempt(&self, account_id: AccountId) -> bool {
+        self.inactivity_decay.exempt.contains(&account_id)
+    }
+
+    /// Warning and decay of `account_id`, if it was warned since it was last active.
+    pub fn get_decay_status(&self, account_id: AccountId) -> Option<DecayStatus> {
+        let last_active = self.dust_sweeping.last_active(&account_id);
+        self.inactivity_decay
+            .accounts
+            .get(&account_id)
+            .filter(|status| status.warned_at.0 >= last_active)
+            .cloned()
+    }
+}
+
+impl Contract {
+    /// Sets or clears the decay parameters. Used by governance proposals.
+    pub(crate) fn internal_set_inactivity_decay(&mut self, config: Option<InactivityDecayConfig>) {
+        if let Some(config) = &config {
+            assert!(
+                config.inactive_years > 0,
+                "The inactivity period should be a positive number of years"
+            );
+            assert!(
+                config.decay_bps > 0 && config.decay_bps <= MAX_DECAY_BPS,
+                "The decay should be between 1 and {} bps",
+                MAX_DECAY_BPS
+            );
+        }
+        emit_inactivity_event("inactivity_decay_set", json!({ "config": config }));
+        self.inactivity_decay.config = config;
+    }
+
+    /// `None` if `account_id` cannot decay now, `Some(None)` if it has to be warned first and
+    /// the status of its warning otherwise.
+    fn decay_status(
+        &self,
+        account_id: &AccountId,
+        config: &InactivityDecayConfig,
+    ) -> Option<Option<DecayStatus>> {
+        if account_id == &self.owner_id
+            || account_id == &env::current_account_id()
+            || self.inactivity_decay.exempt.contains(account_id)
+            || !self.token.accounts.contains_key(account_id)
+        {
+            return None;
+        }
+        let inactive_epochs =
+            env::epoch_height().saturating_sub(self.dust_sweeping.last_active(account_id));
+        if inactive_epochs < u64::from(config.inactive_years) * EPOCHS_PER_YEAR {
+            return None;
+        }
+        Some(self.get_decay_status(account_id.clone()))
+    }
+
+    fn internal_warn_inactive(&mut self, account_id: &AccountId) {
+        let epoch: EpochHeight = env::epoch_height();
+        let status = DecayStatus {
+            warned_at: epoch.into(),
+            charged_until: (epoch + WARNING_EPOCHS).into(),
+        };
+        emit_inactivity_event(
+            "inactivity_warning",
+            json!({
+                "account_id": account_id,
+                "last_active_epoch": U64(self.dust_sweeping.last_active(account_id)),
+                "decay_starts_at_epoch": status.charged_until,
+            }),
+        );
+        self.inactivity_decay
+            .accounts
+            .insert(account_id.clone(), status);
+    }
+
+    /// Moves the decay due since `status.charged_until` to the reward pool. Returns the amount
+    /// moved.
+    fn internal_decay(
+        &mut self,
+        account_id: &AccountId,
+        mut status: DecayStatus,
+        config: &InactivityDecayConfig,
+    ) -> Balance {
+        let years = env::epoch_height().saturating_sub(status.charged_until.0) / EPOCHS_PER_YEAR;
+        if years == 0 {
+            return 0;
+        }
+        let balance = self
+            .token
+            .accounts
+            .get(account_id)
+            .unwrap_or(0)
+            .saturating_sub(self.balance_locks.locked(account_id))
+            .saturating_sub(self.reservations.reserved(account_id));
+        let amount = std::cmp::min(
+            balance,
+            mul_div(
+                balance,
+                u128::from(config.decay_bps) * u128::from(years),
+                MAX_BPS,
+            ),
+        );
+        status.charged_until.0 += years * EPOCHS_PER_YEAR;
+        self.inactivity_decay
+            .accounts
+            .insert(account_id.clone(), status);
+        if amount > 0 {
+            self.staking.update();
+            self.internal_lock(account_id, amount);
+            self.staking.reward_pool += amount;
+        }
+        emit_inactivity_event(
+            "inactivity_decay",
+            json!({
+                "account_id": account_id,
+                "years": U64(years),
+                "amount": U128(amount),
+            }),
+        );
+        amount
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    #[test]
+    fn test_inactive_accounts_are_warned_then_decay() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner.clone(), 1);
+        contract.ft_transfer(accounts(1), U128(10_000), None);
+        contract.ft_transfer(accounts(2), U128(10_000), None);
+        contract.internal_set_inactivity_decay(Some(InactivityDecayConfig {
+            inactive_years: 1,
+            decay_bps: 100,
+        }));
+
+        let epoch = EPOCHS_PER_YEAR;
+        testing_env!(context.epoch_height(epoch).attached_deposit(0).build());
+        let account_ids = vec![accounts(1), accounts(2), owner.clone()];
+        let result = contract.apply_inactivity_decay(account_ids.clone());
+        assert_eq!(result.warned, 2);
+        assert_eq!(result.decayed.0, 0);
+
+        // accounts(2) comes back and cancels its warning.
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .epoch_height(epoch + 1)
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(owner.clone(), U128(1), None);
+        assert!(contract.get_decay_status(accounts(2)).is_none());
+
+        let pool = contract.get_staking_info().reward_pool.0;
+        let epoch = epoch + WARNING_EPOCHS + 2 * EPOCHS_PER_YEAR;
+        testing_env!(context.epoch_height(epoch).attached_deposit(0).build());
+        let result = contract.apply_inactivity_decay(account_ids);
+        assert_eq!(result.warned, 1);
+        assert_eq!(result.decayed.0, 200);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 9_800);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 9_999);
+        assert_eq!(contract.get_staking_info().reward_pool.0, pool + 200);
+    }
+
+    #[test]
+    fn test_locked_tokens_do_not_decay() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        contract.add_locker(accounts(3));
+        call_as(&mut context, accounts(1), ONE_NEAR);
+        contract.approve(accounts(3), U128(5_000), None, None);
+        call_as(&mut context, owner.clone(), 1);
+        contract.ft_transfer(accounts(1), U128(10_000), None);
+        contract.internal_set_inactivity_decay(Some(InactivityDecayConfig {
+            inactive_years: 1,
+            decay_bps: 100,
+        }));
+        call_as(&mut context, accounts(3), ONE_NEAR);
+        contract.lock(accounts(1), U128(5_000), "collateral".to_string());
+
+        testing_env!(context
+            .epoch_height(EPOCHS_PER_YEAR)
+            .attached_deposit(0)
+            .build());
+        contract.apply_inactivity_decay(vec![accounts(1)]);
+        let epoch = EPOCHS_PER_YEAR + WARNING_EPOCHS + 2 * EPOCHS_PER_YEAR;
+        testing_env!(context.epoch_height(epoch).build());
+        let result = contract.apply_inactivity_decay(vec![accounts(1)]);
+        assert_eq!(result.decayed.0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 9_900);
+        assert_eq!(contract.get_locked_balance(accounts(1)).0, 5_000);
+    }
+}
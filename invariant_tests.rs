@@ -0,0 +1,234 @@
+/*!
+Cross-module tests that interleave transfers, transfer call resolutions, staking and
+unregistration in adversarial orders.
+NOTES:
+  - Resolvers are called directly with a mocked promise result, so a test can run any number of
+    calls between `ft_transfer_call` and its `ft_resolve_transfer`, or resolve calls in reverse
+    order.
+  - After every step `assert_invariants` checks that the registered balances add up to the total
+    supply and that the circulating supply and the pools of the ledger add up to it, see
+    `invariants`.
+  - `test_random_sequences_keep_invariants` runs seeded random sequences of transfers,
+    registrations, unregistrations, mints and burns. A failure names its seed to replay it.
+*/
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::json_types::U128;
+use near_sdk::test_utils::{accounts, VMContextBuilder};
+use near_sdk::{testing_env, AccountId, Balance, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+use crate::*;
+
+/// Contract with `accounts(1)` and `accounts(2)` registered, called by the owner with 1 yocto.
+fn setup() -> (VMContextBuilder, Contract) {
+    let (mut context, contract) = test_fixtures::setup(&[accounts(1), accounts(2)]);
+    call_as(&mut context, owner(), 1);
+    (context, contract)
+}
+
+/// Resolves a transfer call whose receiver returned `unused` as the unused amount.
+fn resolve(
+    context: &mut VMContextBuilder,
+    contract: &mut Contract,
+    sender_id: AccountId,
+    receiver_id: AccountId,
+    amount: Balance,
+    unused: Balance,
+) -> Balance {
+    testing_env!(
+        context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(0)
+            .build(),
+        VMConfig::test(),
+        RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![PromiseResult::Successful(
+            near_sdk::serde_json::to_vec(&U128(unused)).unwrap()
+        )],
+    );
+    contract
+        .ft_resolve_transfer(sender_id, receiver_id, amount.into())
+        .0
+}
+
+fn assert_invariants(contract: &Contract) {
+    contract.assert_supply_invariant();
+    contract.assert_pool_invariant();
+}
+
+/// Xorshift generator, so that a failing sequence can be replayed from its seed.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
+
+#[test]
+fn test_receiver_stakes_before_refund() {
+    let (mut context, mut contract) = setup();
+    contract.ft_transfer_call(accounts(1), U128(1_000), None, "".to_string());
+
+    call_as(&mut context, accounts(1), ONE_NEAR);
+    contract.stake(U128(600), None);
+    assert_invariants(&contract);
+
+    // The receiver claims it used nothing, but only 400 are left to refund.
+    let used = resolve(
+        &mut context,
+        &mut contract,
+        owner(),
+        accounts(1),
+        1_000,
+        1_000,
+    );
+    assert_eq!(used, 600);
+    assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+    assert_eq!(contract.get_staked_balance(accounts(1)).0, 600);
+    assert_invariants(&contract);
+    assert!(contract
+        .get_pending_transfer_calls(owner(), accounts(1), U128(1_000))
+        .is_empty());
+}
+
+#[test]
+fn test_receiver_unregisters_before_refund() {
+    let (mut context, mut contract) = setup();
+    contract.ft_transfer_call(accounts(1), U128(1_000), None, "".to_string());
+
+    call_as(&mut context, accounts(1), 1);
+    contract.storage_unregister(Some(true));
+    assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 1_000);
+    assert_invariants(&contract);
+
+    let used = resolve(
+        &mut context,
+        &mut contract,
+        owner(),
+        accounts(1),
+        1_000,
+        1_000,
+    );
+    assert_eq!(used, 1_000);
+    assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 1_000);
+    assert_invariants(&contract);
+}
+
+#[test]
+fn test_sender_unregisters_before_refund() {
+    let (mut context, mut contract) = setup();
+    contract.ft_transfer(accounts(1), U128(1_000), None);
+    call_as(&mut context, accounts(1), 1);
+    contract.ft_transfer_call(accounts(2), U128(1_000), None, "".to_string());
+    contract.storage_unregister(None);
+    assert_invariants(&contract);
+
+    // The refund has no sender to go back to and is burned.
+    let used = resolve(
+        &mut context,
+        &mut contract,
+        accounts(1),
+        accounts(2),
+        1_000,
+        300,
+    );
+    assert_eq!(used, 1_000);
+    assert_eq!(contract.ft_balance_of(accounts(2)).0, 700);
+    assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 300);
+    assert_invariants(&contract);
+}
+
+#[test]
+fn test_identical_calls_resolved_in_reverse_order() {
+    let (mut context, mut contract) = setup();
+    contract.ft_transfer_call(accounts(1), U128(500), None, "".to_string());
+    call_as(&mut context, owner(), 1);
+    contract.ft_transfer_call(accounts(1), U128(500), None, "".to_string());
+    call_as(&mut context, accounts(1), 1);
+    contract.ft_transfer(accounts(2), U128(800), None);
+    assert_invariants(&contract);
+
+    let second = resolve(&mut context, &mut contract, owner(), accounts(1), 500, 500);
+    let first = resolve(&mut context, &mut contract, owner(), accounts(1), 500, 500);
+    assert_eq!(second + first, 800);
+    assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 800);
+    assert_invariants(&contract);
+    assert!(contract
+        .get_pending_transfer_calls(owner(), accounts(1), U128(500))
+        .is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Unstake and claim rewards before unregistering")]
+fn test_cannot_unregister_with_stake() {
+    let (mut context, mut contract) = setup();
+    contract.ft_transfer(accounts(1), U128(1_000), None);
+    call_as(&mut context, accounts(1), ONE_NEAR);
+    contract.stake(U128(1_000), None);
+    call_as(&mut context, accounts(1), 1);
+    contract.storage_unregister(Some(true));
+}
+
+#[test]
+fn test_random_sequences_keep_invariants() {
+    let burn_id = accounts(5);
+    for seed in 1..=20 {
+        // Every seed starts from empty storage.
+        near_sdk::mock::with_mocked_blockchain(|blockchain| blockchain.take_storage());
+        let (mut context, mut contract) = setup();
+        call_as(&mut context, owner(), 0);
+        contract.set_burn_account(Some(burn_id.clone()));
+        let holders = [owner(), accounts(1), accounts(2), accounts(3), accounts(4)];
+        let mut rng = Rng(seed);
+        for step in 0..50 {
+            let account_id = holders[rng.below(5) as usize].clone();
+            let registered = contract.storage_balance_of(account_id.clone()).is_some();
+            let balance = contract.ft_balance_of(account_id.clone()).0;
+            let amount = std::cmp::min(balance, Balance::from(rng.below(1_000)) + 1);
+            match rng.below(5) {
+                0 if balance > 0 => {
+                    let receiver_id = holders[rng.below(5) as usize].clone();
+                    if receiver_id != account_id
+                        && contract.storage_balance_of(receiver_id.clone()).is_some()
+                    {
+                        call_as(&mut context, account_id, 1);
+                        contract.ft_transfer(receiver_id, U128(amount), None);
+                    }
+                }
+                1 if !registered => {
+                    call_as(&mut context, account_id, ONE_NEAR);
+                    contract.storage_deposit(None, None);
+                }
+                2 if registered && account_id != owner() => {
+                    call_as(&mut context, account_id, 1);
+                    contract.storage_unregister(Some(true));
+                }
+                3 if registered => {
+                    call_as(&mut context, owner(), 0);
+                    contract.internal_mint(&owner(), &account_id, amount + 1);
+                }
+                4 if balance > 0 => {
+                    call_as(&mut context, account_id, 1);
+                    contract.ft_transfer(burn_id.clone(), U128(amount), None);
+                }
+                _ => {}
+            }
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                assert_invariants(&contract)
+            }));
+            assert!(
+                result.is_ok(),
+                "Invariant broken at step {} of seed {}",
+                step,
+                seed
+            );
+        }
+    }
+}
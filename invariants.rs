@@ -0,0 +1,70 @@
+/*!
+Accounting invariants of the token, for tests and instrumented builds.
+NOTES:
+  - Supply: the balances of the indexed accounts, the contract account included, plus the
+    archived balances add up to the total supply. Pools and custody balances are part of the
+    contract account's balance, so they are covered too.
+  - Pools: the contract account holds at least what the pools of the ledger claim, and the
+    circulating supply, the pools and the unassigned custody add up to the total supply, see
+    `pool_ledger`.
+  - Builds with the `invariants` feature check the supply after every balance change and account
+    closure with `debug_assert_invariants`, and panic on any drift. The check walks every
+    account, so the feature is for sandbox and test builds only, and it assumes every account is
+    in the account index, see `index_accounts`.
+  - The pool check is not part of it: subsystems update their books around the token
+    movement, in either order, so it only holds between calls.
+*/
+#![cfg_attr(not(test), allow(unused_imports))]
+
+use near_sdk::{env, Balance};
+
+use crate::*;
+
+impl Contract {
+    /// Checks the supply invariant in builds with the `invariants` feature, a no-op otherwise.
+    pub(crate) fn debug_assert_invariants(&self) {
+        #[cfg(feature = "invariants")]
+        self.assert_supply_invariant();
+    }
+
+    #[cfg(any(test, feature = "invariants"))]
+    pub(crate) fn assert_supply_invariant(&self) {
+        let total: Balance = self
+            .registered_accounts
+            .iter()
+            .map(|account_id| self.token.accounts.get(account_id).unwrap_or(0))
+            .sum();
+        assert_eq!(
+            total + self.archive.archived,
+            self.token.total_supply,
+            "Balances do not add up to the total supply"
+        );
+    }
+
+    #[cfg(test)]
+    pub(crate) fn assert_pool_invariant(&self) {
+        let contract_id = env::current_account_id();
+        let circulating: Balance = self
+            .registered_accounts
+            .iter()
+            .filter(|account_id| **account_id != contract_id)
+            .map(|account_id| self.token.accounts.get(account_id).unwrap_or(0))
+            .sum::<Balance>()
+            + self.archive.archived;
+        let balances = self.pool_balances();
+        let pooled: Balance = balances.pools.iter().map(|pool| pool.balance.0).sum();
+        let custody = self.custody_balance();
+        assert!(
+            custody >= pooled,
+            "The contract account holds {} for {} in pools",
+            custody,
+            pooled
+        );
+        assert_eq!(
+            circulating + pooled + balances.unassigned.0,
+            self.token.total_supply,
+            "Circulating and pooled tokens do not add up to the total supply"
+        );
+        assert_eq!(balances.circulating_supply.0, circulating);
+    }
+}
@@ -0,0 +1,79 @@
+/*!
+Alerts on large transfers for monitoring bots.
+NOTES:
+  - Once the owner or the DAO sets a threshold, every `ft_transfer` and `ft_transfer_call` of at
+    least that amount emits an `astro` `large_transfer` event on top of the NEP-141 one,
+    with the sender balance left after the transfer. Bots can follow these events instead of
+    filtering every transfer off-chain.
+  - The amount is the one sent by the sender, before transfer fees.
+*/
+use near_sdk::json_types::U128;
+use near_sdk::serde_json::json;
+use near_sdk::{near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the amount from which transfers emit a `large_transfer` event, `None` to stop.
+    pub fn set_large_transfer_threshold(&mut self, threshold: Option<U128>) {
+        self.assert_owner_or_dao();
+        assert!(
+            threshold.is_none_or(|threshold| threshold.0 > 0),
+            "The threshold should be a positive number"
+        );
+        self.large_transfer_threshold = threshold.map(|threshold| threshold.0);
+    }
+
+    pub fn get_large_transfer_threshold(&self) -> Option<U128> {
+        self.large_transfer_threshold.map(U128)
+    }
+}
+
+impl Contract {
+    /// Emits a `large_transfer` event if `amount` reaches the threshold.
+    pub(crate) fn alert_large_transfer(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        if self
+            .large_transfer_threshold
+            .is_none_or(|threshold| amount < threshold)
+        {
+            return;
+        }
+        emit_event(
+            EVENT_VERSION,
+            "large_transfer",
+            json!({
+                "sender_id": sender_id,
+                "receiver_id": receiver_id,
+                "amount": U128(amount),
+                "sender_balance": self.ft_balance_of(sender_id.clone()),
+            }),
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs};
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_large_transfer_event() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 1);
+        contract.set_large_transfer_threshold(Some(U128(1_000)));
+        contract.ft_transfer(accounts(1), U128(999), None);
+        assert!(!get_logs().iter().any(|log| log.contains("large_transfer")));
+        contract.ft_transfer(accounts(1), U128(1_000), None);
+        assert!(get_logs().iter().any(|log| log.contains("large_transfer")));
+    }
+}
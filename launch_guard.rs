@@ -0,0 +1,116 @@
+/*!
+Anti-whale launch mode.
+NOTES:
+  - Until `ends_at`, `ft_transfer` and `ft_transfer_call` cannot move more than `max_transfer` at
+    once or leave the receiver with more than `max_balance`. The guard expires by itself, no call
+    is needed to lift it.
+  - Transfers from or to the owner and the contract account, which holds the treasury, are exempt.
+  - The limits apply to the amount the receiver gets, after transfer fees and burns.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LaunchGuard {
+    pub max_balance: U128,
+    pub max_transfer: U128,
+    pub ends_at: U64,
+}
+
+impl LaunchGuard {
+    pub fn is_active(&self) -> bool {
+        env::block_timestamp() < self.ends_at.0
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Enables the launch guard until `ends_at`, or disables it with `None`.
+    pub fn set_launch_guard(&mut self, guard: Option<LaunchGuard>) {
+        self.assert_owner();
+        if let Some(guard) = &guard {
+            assert!(
+                guard.ends_at.0 > env::block_timestamp(),
+                "The launch guard must end in the future"
+            );
+            log!(
+                "Launch guard: max balance {}, max transfer {} until {}",
+                guard.max_balance.0,
+                guard.max_transfer.0,
+                guard.ends_at.0
+            );
+        }
+        self.launch_guard = guard;
+    }
+
+    /// The launch guard while it is active.
+    pub fn get_launch_guard(&self) -> Option<LaunchGuard> {
+        self.launch_guard.clone().filter(LaunchGuard::is_active)
+    }
+}
+
+impl Contract {
+    /// Panics if moving `amount` from `sender_id` to `receiver_id` breaks the launch guard.
+    pub(crate) fn assert_launch_guard(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        let guard = match self.get_launch_guard() {
+            Some(guard) => guard,
+            None => return,
+        };
+        let contract_id = env::current_account_id();
+        let is_exempt =
+            |account_id: &AccountId| account_id == &self.owner_id || account_id == &contract_id;
+        if is_exempt(sender_id) || is_exempt(receiver_id) {
+            return;
+        }
+        assert!(
+            amount <= guard.max_transfer.0,
+            "Transfers are limited to {} until {} during launch",
+            guard.max_transfer.0,
+            guard.ends_at.0
+        );
+        let balance = self.token.accounts.get(receiver_id).unwrap_or(0);
+        assert!(
+            balance + amount <= guard.max_balance.0,
+            "Balances are limited to {} until {} during launch, the receiver can get {} more",
+            guard.max_balance.0,
+            guard.ends_at.0,
+            guard.max_balance.0.saturating_sub(balance)
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    #[should_panic(expected = "the receiver can get 200 more")]
+    fn test_max_wallet_size() {
+        let (mut context, mut contract) = setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner(), 1);
+        contract.set_launch_guard(Some(LaunchGuard {
+            max_balance: U128(1_000),
+            max_transfer: U128(500),
+            ends_at: U64(100),
+        }));
+        contract.ft_transfer(accounts(1), U128(5_000), None);
+        contract.ft_transfer(accounts(2), U128(800), None);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), U128(300), None);
+    }
+}
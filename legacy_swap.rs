@@ -0,0 +1,156 @@
+/*!
+1:1 migration of holders of the legacy AV token.
+NOTES:
+  - The owner or the DAO configures the legacy token contract and a deadline. Until then, legacy
+    tokens sent to this contract with `ft_transfer_call`, whatever the `msg`, are swapped for the
+    same amount of ASTRO credited to the sender. The sender must be registered with ASTRO.
+  - The ASTRO is either minted, counting against the minter allowance of the legacy contract and
+    the emission schedule, or released from the treasury. A swap that cannot be paid panics, so
+    the legacy contract refunds the legacy tokens.
+  - Legacy tokens received stay on the balance of this contract. `get_legacy_swap` reports the
+    amount migrated and the number of swaps.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LegacySwapConfig {
+    pub legacy_id: AccountId,
+    /// Timestamp in nanoseconds after which swaps are refused.
+    pub deadline: U64,
+    /// Whether swaps mint ASTRO or release it from the treasury.
+    pub mint: bool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct LegacySwap {
+    pub config: Option<LegacySwapConfig>,
+    pub migrated: Balance,
+    pub swaps: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LegacySwapView {
+    pub config: Option<LegacySwapConfig>,
+    pub migrated: U128,
+    pub swaps: U64,
+    pub active: bool,
+}
+
+impl LegacySwap {
+    pub fn is_legacy_token(&self, token_id: &AccountId) -> bool {
+        self.config
+            .as_ref()
+            .is_some_and(|config| &config.legacy_id == token_id)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_legacy_swap(&mut self, legacy_id: AccountId, deadline: U64, mint: bool) {
+        self.assert_owner_or_dao();
+        assert!(
+            deadline.0 > env::block_timestamp(),
+            "The deadline must be in the future"
+        );
+        assert!(
+            self.legacy_swap
+                .config
+                .as_ref()
+                .is_none_or(|config| config.legacy_id == legacy_id),
+            "The legacy token cannot change"
+        );
+        log!("Holders of {} can migrate until {}", legacy_id, deadline.0);
+        self.legacy_swap.config = Some(LegacySwapConfig {
+            legacy_id,
+            deadline,
+            mint,
+        });
+    }
+
+    pub fn get_legacy_swap(&self) -> LegacySwapView {
+        let swap = &self.legacy_swap;
+        LegacySwapView {
+            config: swap.config.clone(),
+            migrated: swap.migrated.into(),
+            swaps: swap.swaps.into(),
+            active: swap
+                .config
+                .as_ref()
+                .is_some_and(|config| env::block_timestamp() <= config.deadline.0),
+        }
+    }
+}
+
+impl Contract {
+    /// Credits `amount` of ASTRO to `sender_id` for the legacy tokens it sent.
+    pub(crate) fn internal_legacy_swap(&mut self, sender_id: &AccountId, amount: Balance) {
+        let config = self.legacy_swap.config.clone().unwrap();
+        assert!(
+            env::block_timestamp() <= config.deadline.0,
+            "The migration has ended"
+        );
+        assert!(amount > 0, "The amount should be a positive number");
+        assert!(
+            self.token.accounts.contains_key(sender_id),
+            "The account {} is not registered",
+            sender_id
+        );
+        if config.mint {
+            self.internal_mint(&config.legacy_id, sender_id, amount);
+        } else {
+            assert!(
+                amount <= self.treasury_balance,
+                "The treasury holds {}",
+                self.treasury_balance
+            );
+            self.treasury_balance -= amount;
+            self.internal_unlock(sender_id, amount);
+        }
+        self.legacy_swap.migrated += amount;
+        self.legacy_swap.swaps += 1;
+        log!("@{} migrated {} legacy tokens", sender_id, amount);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 0);
+        contract.set_legacy_swap(accounts(3), U64(100), true);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_legacy_tokens_are_swapped() {
+        let (_, mut contract) = setup();
+        contract.ft_on_transfer(accounts(1), U128(500), "".to_string());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 500);
+        let view = contract.get_legacy_swap();
+        assert_eq!(view.migrated.0, 500);
+        assert_eq!(view.swaps.0, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "The migration has ended")]
+    fn test_no_swap_after_deadline() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(101).build());
+        contract.ft_on_transfer(accounts(1), U128(500), "".to_string());
+    }
+}
@@ -12,109 +12,616 @@ NOTES:
     If the storage decreases, the contract will issue a refund for the cost of the released storage.
     The unused tokens from the attached deposit are also refunded, so it's safe to
     attach more deposit than required.
+  - New registrations are rate limited per block and per caller, see `registration_limits`.
   - To prevent the deployed contract from being modified or deleted, it should not have any access
-    keys on its account.
+    keys on its account. New code is shipped through `upgrade`, callable by the owner or the
+    configured DAO.
+  - The token itself is split into `token_core` (NEP-141 and the `AstroTokenExt` trait), `storage`
+    (NEP-145), `metadata` (NEP-148), `admin` and `views`. Every other module is a subsystem built
+    on them. `token_core` and `events` are public for contracts that use this crate as a library.
+  - The staking, vesting, sale, bridge and fee subsystems are behind Cargo features, all on by
+    default. Without a feature the state of the subsystem is kept and its views stay available,
+    only its other methods are left out, see `Cargo.toml`.
 */
-use near_contract_standards::fungible_token::metadata::{
-    FungibleTokenMetadata, FungibleTokenMetadataProvider, FT_METADATA_SPEC,
-};
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
 use near_contract_standards::fungible_token::FungibleToken;
+use near_contract_standards::storage_management::StorageManagement;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::LazyOption;
-use near_sdk::json_types::{ValidAccountId, U128};
-use near_sdk::{env, log, near_bindgen, AccountId, Balance, PanicOnDefault, PromiseOrValue};
+use near_sdk::json_types::U128;
+use near_sdk::store::{LookupMap, UnorderedSet};
+use near_sdk::{
+    env, near_bindgen, AccountId, Balance, BorshStorageKey, CryptoHash, IntoStorageKey,
+    PanicOnDefault, Promise,
+};
 
-near_sdk::setup_alloc!();
+use crate::airdrops::Airdrops;
+use crate::allowances::AllowanceIndex;
+use crate::archival::Archive;
+use crate::balance_locks::BalanceLocks;
+use crate::balance_proofs::BalanceProofs;
+use crate::bonding_curve::BondingCurve;
+use crate::bridge::Bridge;
+use crate::buyback::Buyback;
+use crate::callback_failures::CallbackFailures;
+use crate::checkpoints::Checkpoints;
+use crate::compliance::Compliance;
+use crate::council::Council;
+use crate::delegation::Delegation;
+use crate::dividends::Dividends;
+use crate::drops::Drops;
+use crate::dust_sweeping::DustSweeping;
+use crate::emission::EmissionSchedule;
+use crate::emission_distributor::EmissionDistributor;
+use crate::escrow::Escrow;
+use crate::events::emit_event;
+use crate::factory::Factory;
+use crate::farming::Farming;
+use crate::fee_oracle::FeeOracle;
+use crate::fee_rebates::FeeRebates;
+use crate::fees::TransferFee;
+use crate::force_transfers::ForceTransfers;
+use crate::freezes::Freezes;
+use crate::gas_rebates::GasRebates;
+use crate::gas_tank::GasTank;
+use crate::governance::Governance;
+use crate::inactivity_decay::InactivityDecay;
+use crate::launch_guard::LaunchGuard;
+use crate::legacy_swap::LegacySwap;
+use crate::liquid_staking::LiquidStaking;
+use crate::liquidity_bootstrap::LiquidityBootstrap;
+use crate::localized_metadata::LocalizedMetadata;
+use crate::mass_distribution::MassDistribution;
+use crate::matching::Matching;
+use crate::math::mul_div;
+use crate::memo_requirements::MemoRequirements;
+use crate::meta_transactions::MetaTransactions;
+use crate::metadata::default_metadata;
+use crate::metadata_control::PendingMetadataChange;
+use crate::metadata_validation::MetadataOverrides;
+use crate::metrics::Metrics;
+use crate::mint_requests::MintQueue;
+use crate::minter_allowances::MinterAllowances;
+use crate::nft_boost::NftBoost;
+use crate::pause::Pause;
+use crate::payment_links::PaymentLink;
+use crate::payment_requests::PaymentRequests;
+use crate::pool_ledger::PoolLedger;
+use crate::randomness::Randomness;
+use crate::receive_hooks::ReceiveHooks;
+use crate::receiver_allowlist::ReceiverAllowlist;
+use crate::receiver_capabilities::ReceiverCapabilitiesRegistry;
+use crate::recovery::Recovery;
+use crate::referrals::Referrals;
+use crate::registration_limits::RegistrationLimits;
+use crate::remote_mint::RemoteMint;
+use crate::rescue::Rescue;
+use crate::reservations::Reservations;
+use crate::retirement::Module;
+use crate::sale::Sale;
+use crate::savings::Savings;
+use crate::scheduled_distributions::ScheduledDistributions;
+use crate::scheduled_transfers::ScheduledTransfers;
+use crate::scheduler::{ScheduledAction, Scheduler};
+use crate::spend_hooks::SpendHooks;
+use crate::sponsorship::Sponsorship;
+use crate::stake_positions::StakePositions;
+use crate::staking::StakingPool;
+use crate::staking_vault::StakingVault;
+use crate::storage::{refund_deposit, refund_released_storage};
+use crate::storage_grace::StorageGrace;
+use crate::streams::Stream;
+use crate::subscriptions::Subscriptions;
+use crate::telemetry::Telemetry;
+use crate::timelock::Timelock;
+pub use crate::token_core::AstroTokenExt;
+use crate::token_deposits::TokenDeposits;
+use crate::trading_start::TradingStart;
+use crate::transfer_calls::TransferCalls;
+use crate::transfer_limits::TransferLimits;
+use crate::transfer_log::TransferLog;
+use crate::treasury::TreasuryPayouts;
+use crate::vote_escrow::VoteEscrow;
+
+mod account_index;
+mod admin;
+mod airdrops;
+mod allowances;
+mod archival;
+mod balance_locks;
+mod balance_proofs;
+mod batch_views;
+mod bonding_curve;
+mod bridge;
+mod build_info;
+mod burn_address;
+mod buyback;
+mod callback_failures;
+mod checkpoints;
+mod chunked;
+mod circuit_breaker;
+mod compliance;
+mod contract_info;
+mod council;
+mod dao_actions;
+mod data_export;
+mod delegation;
+mod dividends;
+mod drops;
+mod dust_sweeping;
+mod dutch_auction;
+mod emission;
+mod emission_distributor;
+mod escrow;
+pub mod events;
+mod factory;
+mod farming;
+mod fee_oracle;
+mod fee_rebates;
+mod fees;
+mod force_transfers;
+mod freeze_bonds;
+mod freezes;
+mod gas_budget;
+mod gas_rebates;
+mod gas_tank;
+mod governance;
+mod holders;
+mod inactivity_decay;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod invariant_tests;
+mod invariants;
+mod large_transfers;
+mod launch_guard;
+mod legacy_swap;
+mod liquid_staking;
+mod liquidity_bootstrap;
+mod localized_metadata;
+mod mass_distribution;
+mod matching;
+mod math;
+mod memo_requirements;
+mod meta_transactions;
+mod metadata;
+mod metadata_control;
+mod metadata_validation;
+mod metrics;
+mod migration;
+mod mint_requests;
+mod minter_allowances;
+mod nft_boost;
+mod ownership;
+mod pause;
+mod payment_links;
+mod payment_requests;
+mod pool_ledger;
+mod presale;
+mod randomness;
+mod receive_hooks;
+mod receiver_allowlist;
+mod receiver_capabilities;
+mod recovery;
+mod referrals;
+mod registration_limits;
+mod remote_mint;
+mod rescue;
+mod reservations;
+mod retirement;
+mod reward_tokens;
+mod sale;
+mod sale_vesting;
+mod savings;
+mod scheduled_distributions;
+mod scheduled_transfers;
+mod scheduler;
+mod spend_hooks;
+mod sponsorship;
+mod stake_positions;
+mod staking;
+mod staking_vault;
+mod storage;
+mod storage_estimates;
+mod storage_grace;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod storage_refund_tests;
+mod streams;
+mod subscriptions;
+mod telemetry;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test_fixtures;
+mod timelock;
+pub mod token_core;
+mod token_deposits;
+mod token_gating;
+mod trading_start;
+mod transfer_calls;
+mod transfer_deadlines;
+mod transfer_limits;
+mod transfer_log;
+mod transfer_registration;
+mod treasury;
+mod upgrade;
+mod views;
+mod vote_escrow;
+
+/// Prefixes of the top-level collections, one byte each. Variants are serialized by position,
+/// so new ones are only ever appended; subsystems nest their own collections under theirs.
+/// Contracts migrated from the first version keep the `b"a"` and `b"m"` prefixes of their token
+/// and metadata, stored with the collections themselves, which stay clear of these bytes.
+#[derive(BorshStorageKey, BorshSerialize)]
+enum StorageKey {
+    Token,
+    Metadata,
+    Staking,
+    MintQueue,
+    PaymentLinks,
+    Streams,
+    Escrows,
+    Checkpoints,
+    Governance,
+    BalanceProofs,
+    RegistrationLimits,
+    Delegation,
+    Recovery,
+    TransferFee,
+    TransferCalls,
+    Airdrops,
+    Telemetry,
+    Randomness,
+    ReceiverAllowlist,
+    TransferLimits,
+    Freezes,
+    StakePositions,
+    StorageGrace,
+    RegisteredAccounts,
+    Matching,
+    MetaTransactions,
+    Allowances,
+    Scheduler,
+    DustSweeping,
+    ReceiverCapabilities,
+    Council,
+    Timelock,
+    Rescue,
+    TokenDeposits,
+    Dividends,
+    Bridge,
+    Sale,
+    Farming,
+    Referrals,
+    CallbackFailures,
+    BalanceLocks,
+    Vault,
+    ScheduledDistributions,
+    ScheduledTransfers,
+    Subscriptions,
+    PaymentRequests,
+    MassDistribution,
+    Drops,
+    MinterAllowances,
+    Factory,
+    VoteEscrow,
+    Archive,
+    MemoRequirements,
+    SpendHooks,
+    Compliance,
+    Savings,
+    TradingStart,
+    TransferLog,
+    Sponsorship,
+    ReceiveHooks,
+    Reservations,
+    NftBoost,
+    TreasuryPayouts,
+    LocalizedMetadata,
+    AllowanceIndex,
+    PoolLedger,
+    RemoteMint,
+    GasRebates,
+    FeeRebates,
+    InactivityDecay,
+}
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
     token: FungibleToken,
     metadata: LazyOption<FungibleTokenMetadata>,
+    staking: StakingPool,
+    mint_queue: MintQueue,
+    payment_links: LookupMap<CryptoHash, PaymentLink>,
+    streams: LookupMap<u64, Stream>,
+    next_stream_id: u64,
+    escrows: LookupMap<u64, Escrow>,
+    next_escrow_id: u64,
+    /// Set once tokens have moved between accounts; metadata decimals are frozen from then on.
+    has_transfers: bool,
+    /// Account holding the admin powers, see `admin` and `ownership`.
+    owner_id: AccountId,
+    /// Account the owner offered the ownership to, until it accepts it.
+    pending_owner_id: Option<AccountId>,
+    /// DAO allowed to upgrade the contract in addition to the owner.
+    dao_id: Option<AccountId>,
+    checkpoints: Checkpoints,
+    governance: Governance,
+    balance_proofs: BalanceProofs,
+    registration_limits: RegistrationLimits,
+    delegation: Delegation,
+    recovery: Recovery,
+    transfer_fee: TransferFee,
+    transfer_calls: TransferCalls,
+    emission: Option<EmissionSchedule>,
+    pending_metadata_change: Option<PendingMetadataChange>,
+    airdrops: Airdrops,
+    /// Part of the contract account's balance that belongs to the treasury.
+    treasury_balance: Balance,
+    buyback: Buyback,
+    telemetry: Telemetry,
+    randomness: Randomness,
+    receiver_allowlist: ReceiverAllowlist,
+    transfer_limits: TransferLimits,
+    freezes: Freezes,
+    launch_guard: Option<LaunchGuard>,
+    stake_positions: StakePositions,
+    storage_grace: StorageGrace,
+    registered_accounts: UnorderedSet<AccountId>,
+    matching: Matching,
+    gas_tank: GasTank,
+    meta_transactions: MetaTransactions,
+    allowances: LookupMap<(AccountId, AccountId), Balance>,
+    pause: Option<Pause>,
+    liquid_staking: LiquidStaking,
+    scheduler: Scheduler,
+    dust_sweeping: DustSweeping,
+    receiver_capabilities: ReceiverCapabilitiesRegistry,
+    council: Council,
+    retired_modules: Vec<Module>,
+    timelock: Timelock,
+    rescue: Rescue,
+    token_deposits: TokenDeposits,
+    dividends: Dividends,
+    bridge: Bridge,
+    sale: Sale,
+    farming: Farming,
+    referrals: Referrals,
+    callback_failures: CallbackFailures,
+    max_icon_bytes: u32,
+    balance_locks: BalanceLocks,
+    bonding_curve: BondingCurve,
+    vault: StakingVault,
+    scheduled_distributions: ScheduledDistributions,
+    scheduled_transfers: ScheduledTransfers,
+    subscriptions: Subscriptions,
+    payment_requests: PaymentRequests,
+    mass_distribution: MassDistribution,
+    drops: Drops,
+    minter_allowances: MinterAllowances,
+    ownership_renounced: bool,
+    force_transfers: ForceTransfers,
+    large_transfer_threshold: Option<Balance>,
+    factory: Factory,
+    legacy_swap: LegacySwap,
+    vote_escrow: VoteEscrow,
+    archive: Archive,
+    memo_requirements: MemoRequirements,
+    spend_hooks: SpendHooks,
+    compliance: Compliance,
+    emission_distributor: EmissionDistributor,
+    savings: Savings,
+    trading_start: TradingStart,
+    transfer_log: TransferLog,
+    fee_oracle: FeeOracle,
+    burn_account_id: Option<AccountId>,
+    sponsorship: Sponsorship,
+    receive_hooks: ReceiveHooks,
+    liquidity_bootstrap: LiquidityBootstrap,
+    reservations: Reservations,
+    nft_boost: NftBoost,
+    metrics: Metrics,
+    treasury_payouts: TreasuryPayouts,
+    paused_modules: Vec<Module>,
+    localized_metadata: LocalizedMetadata,
+    allowance_index: AllowanceIndex,
+    pool_ledger: PoolLedger,
+    remote_mint: RemoteMint,
+    gas_rebates: GasRebates,
+    fee_rebates: FeeRebates,
+    inactivity_decay: InactivityDecay,
 }
 
-const SVG_TOKEN_ICON: &str = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAGIAAABiCAMAAACce/Y8AAAGf2lUWHRYTUw6Y29tLmFkb2JlLnhtcAAAAAAAPD94cGFja2V0IGJlZ2luPSLvu78iIGlkPSJXNU0wTXBDZWhpSHpyZVN6TlRjemtjOWQiPz4gPHg6eG1wbWV0YSB4bWxuczp4PSJhZG9iZTpuczptZXRhLyIgeDp4bXB0az0iQWRvYmUgWE1QIENvcmUgNS42LWMxNDIgNzkuMTYwOTI0LCAyMDE3LzA3LzEzLTAxOjA2OjM5ICAgICAgICAiPiA8cmRmOlJERiB4bWxuczpyZGY9Imh0dHA6Ly93d3cudzMub3JnLzE5OTkvMDIvMjItcmRmLXN5bnRheC1ucyMiPiA8cmRmOkRlc2NyaXB0aW9uIHJkZjphYm91dD0iIiB4bWxuczp4bXA9Imh0dHA6Ly9ucy5hZG9iZS5jb20veGFwLzEuMC8iIHhtbG5zOmRjPSJodHRwOi8vcHVybC5vcmcvZGMvZWxlbWVudHMvMS4xLyIgeG1sbnM6cGhvdG9zaG9wPSJodHRwOi8vbnMuYWRvYmUuY29tL3Bob3Rvc2hvcC8xLjAvIiB4bWxuczp4bXBNTT0iaHR0cDovL25zLmFkb2JlLmNvbS94YXAvMS4wL21tLyIgeG1sbnM6c3RFdnQ9Imh0dHA6Ly9ucy5hZG9iZS5jb20veGFwLzEuMC9zVHlwZS9SZXNvdXJjZUV2ZW50IyIgeG1wOkNyZWF0b3JUb29sPSJBZG9iZSBQaG90b3Nob3AgQ0MgKFdpbmRvd3MpIiB4bXA6Q3JlYXRlRGF0ZT0iMjAyMi0wNS0yNFQxODo1ODowOSswMzowMCIgeG1wOk1vZGlmeURhdGU9IjIwMjItMDUtMjRUMTk6MTQ6MjMrMDM6MDAiIHhtcDpNZXRhZGF0YURhdGU9IjIwMjItMDUtMjRUMTk6MTQ6MjMrMDM6MDAiIGRjOmZvcm1hdD0iaW1hZ2UvcG5nIiBwaG90b3Nob3A6Q29sb3JNb2RlPSIzIiB4bXBNTTpJbnN0YW5jZUlEPSJ4bXAuaWlkOjc0ZjQ4NmFiLWFiYzQtNWU0Yy05MDc3LTNmYjNjMjQzM2E5NCIgeG1wTU06RG9jdW1lbnRJRD0ieG1wLmRpZDo2YzQ3M2U0OS00MWYzLTg3NDItYmQyYS0yZGM5NWFmMjlkOTkiIHhtcE1NOk9yaWdpbmFsRG9jdW1lbnRJRD0ieG1wLmRpZDo2YzQ3M2U0OS00MWYzLTg3NDItYmQyYS0yZGM5NWFmMjlkOTkiPiA8eG1wTU06SGlzdG9yeT4gPHJkZjpTZXE+IDxyZGY6bGkgc3RFdnQ6YWN0aW9uPSJjcmVhdGVkIiBzdEV2dDppbnN0YW5jZUlEPSJ4bXAuaWlkOjZjNDczZTQ5LTQxZjMtODc0Mi1iZDJhLTJkYzk1YWYyOWQ5OSIgc3RFdnQ6d2hlbj0iMjAyMi0wNS0yNFQxODo1ODowOSswMzowMCIgc3RFdnQ6c29mdHdhcmVBZ2VudD0iQWRvYmUgUGhvdG9zaG9wIENDIChXaW5kb3dzKSIvPiA8cmRmOmxpIHN0RXZ0OmFjdGlvbj0ic2F2ZWQiIHN0RXZ0Omluc3RhbmNlSUQ9InhtcC5paWQ6MDRhY2NkNGEtMTUwMC05YTQ3LWJjM2QtODBkYzJmOTMwNzA0IiBzdEV2dDp3aGVuPSIyMDIyLTA1LTI0VDE5OjA0OjM4KzAzOjAwIiBzdEV2dDpzb2Z0d2FyZUFnZW50PSJBZG9iZSBQaG90b3Nob3AgQ0MgKFdpbmRvd3MpIiBzdEV2dDpjaGFuZ2VkPSIvIi8+IDxyZGY6bGkgc3RFdnQ6YWN0aW9uPSJzYXZlZCIgc3RFdnQ6aW5zdGFuY2VJRD0ieG1wLmlpZDo3NGY0ODZhYi1hYmM0LTVlNGMtOTA3Ny0zZmIzYzI0MzNhOTQiIHN0RXZ0OndoZW49IjIwMjItMDUtMjRUMTk6MTQ6MjMrMDM6MDAiIHN0RXZ0OnNvZnR3YXJlQWdlbnQ9IkFkb2JlIFBob3Rvc2hvcCBDQyAoV2luZG93cykiIHN0RXZ0OmNoYW5nZWQ9Ii8iLz4gPC9yZGY6U2VxPiA8L3htcE1NOkhpc3Rvcnk+IDwvcmRmOkRlc2NyaXB0aW9uPiA8L3JkZjpSREY+IDwveDp4bXBtZXRhPiA8P3hwYWNrZXQgZW5kPSJyIj8+qpcrfgAAAAlwSFlzAAALEwAACxMBAJqcGAAAAvRQTFRFR3BMGBUY+ff46OToKyk99fHr9vLqsK+xAAAA5uXj9/Ty+Pb39fLxIyNABQQHBQo6AQEBAAAACgwfAQEBAgIGCRqbDhhy9fT1+vj5AAAABQQDAAAB/8xUAAAAAAAAAAECDxyA9/X2CBumAwQJBhifYl9eAAAA9/TwAAAA9Mt5+Pb3BBJ/xZ1TAAAB+fj5AgICSkpO+Pb1BhaOg25B+Pb3AAAA+PX1BxiVChiL+vj0jouVc3CM67ZIHyVWtrXN+ff3+vXwNTpf269V775b5sR31bRy+Pb39/X25+Tlx7+3dXmYBROEBxeVy6pmtZ529+/MBhaJ393hNkKd0M3WxMHJR1Ou5ePobm1x/+OZtbG1YmmnWEUhFhxdBhR/KSgjrItP/c5q5K5F7NOZzMrFAwo9Ky0vAAET4+HmMSsgy6hfmpmYT1BTJwD//Pv8CIOjAAAACBung4PTCBykARSh4+P3CBys6Of5gYHS///+ABGfc3O9h4fUBRijCByqdnfCAAycenrHzc7vjIzVUl29f4DPBA1N9fT9AgIE7u39fX7L/v3629v0n6DceXi+1dbxABGnvb7or7DjAAIPHCypk5TYxcbrAgctbm66p6jeFCWp4t/l8urW7errBheV8/HyJTOt7eTK/Pv0LTyyAgUeQ0+29/HjOUa059y+5NezBQ9WintRIhoL2cmc/7wl+fbtmZnZChyd2NXit7jmfX3ABRN0BRR9BRWL28WKo6PQaXLHBBBlhofECR2wHh0fjY7Im4pcAwo+gYHB/L48ubnXl5bMXmjC39Glq5hmS1a7/uSVCwkJz83euKVzNSsZU0ku8uO4yMbZc3rL//bPv6+G/9l0UDsTz7+Sy7iBbmFA49WqFxMM5eTw69OZYlY1cnBxppp+MTum6aYm2qpN/++z1Mq0hozOKyw1//3pQDsvmG0dfVcS2Zoh3cBzjoZ2V1uYNj6Qn39A1tLNoJ6nura0r6eqAAVVkJG0u4osd3qqHCeEXVd8AAM0ECZQNgAAAG50Uk5TABon/g8SCQF4BRn+NgYxJLfFQepj1zVOQphZ2/2B+vNqgffU6f5LjuT4rlclbV2M/laN+22p2MGedfv+xPr87Z37rfmOecZk5jBreqxYRNSm2Ku0ZNd4mqqxoICcqn+w0NOOkqKf8/y/47CWDw0iX7RuAAAO7UlEQVRo3q1ZeVwT1xZmCQSQTRQRRakCKmrVqtWqrVq1bt1f+9q+fd9//GaYIcCQiZEETNiXABoWEYgooLK4AQZEFmWRHaoCAopbW7du9rX+886dmSQTCBBe/ZIMZHLv/e45373nnnvHwmJKEAqF9va2AQE2DlaOHitWrPDweMVjtqOjlZWDjY29xQsBw2DLMDh6rF2wYMPatSs8Zs+ejThsXxAD+9eWsWLt4q1bty7esGvFK7MdrRwcAixeEJwcHD/+ZAFg7a61i5fu3LkTSBAFmCF8IQR7Pv7ow93p6enW1tb/2L1u57otW7asW7pghccLoACZoYE9f9ltrUMm4PA0BOBAgjha/Sy9kchC208+zOQaP8yBodiybufSrYsXbNhl83N1FnxkbQ0eyrQubeno6Ggptc7UUSxdvAsZYWU0pP4Prwl+lYkISjfXX4qLi7t0ob68riWTo2D0djCeGMIpczj96nB6emZpedzBgwfD4uJOX7hQDSSlmdPAXes2rJgNg9aYwn7KFJ8Cg3UdIjgYhiguIYpyIDl8ODNzKUMRYCz3VCl+/7V1unX5wYMsRRjyVHV1fT2wtACF9QagsAn4WSNq5uBhsIFpHzGwZgAJsFS3AMVSD0eHANsxFFMwxHnGt/3pLXGofR4HkADLhfr0TOvd41Cw08kcqV3vpZeml6O2mfaBAXGcRizwqstM/8cuRysbExS2QnuzOIQvY4P9paWnw/iAgcuQIBoIKIshgIylsAgIsLU1RyGXGYpv0kvrwsL2GQjgSxzDAg473ZFe+qHD6EHLUjiYF+M9sSqYzOVh+zjE7dsHBGHoH8aUuPL00lJHexubgNE+EQbAamVGWLGcga0K7+io3rfvNEvBcu2P0zNWl5a2fGIBa+FoCvuA2TBjzBiwmGJTYl1d1H499sELvfefRl+Ara6l4yN7oe3YiWH7ytINZlC4Ys3PDpUf2h8F0JFE8QiBpbyubrMVWnNH+clx8fs562ab46d79dXlxfujIqJ0iNi/PySKh/pD5eUfjwlLe3b4f34nOmfBpBRzMOzNzoP1sqgIBOCJiAhpVKka9V/hJau/cPfXo4KrzVv/WtNa5Zd59BUzxpPiSaOmPjCEaRNdxXISIBfrb0SIqxsafunEDxhO7/6hrdnnb3v/0nXHY9JlYi6Wl52dXxzCg4okCDJbzLtzNz83ew+/2m9am11nWlo4vN/+aFIKt/lYjYTUFEdwjUWEiCsIAK3uDISvgQD4I8ulqb1CntorX3UTwID6bKTwU8fJKOwwrFJEVaQYegx+InCcphvEbPMhcJFpKOoX/MAqYK5/brrqs8dhsvjkiinO4cRdziuB0G1xLomfy8JJjTgkkLsZIm4gCV/B6Mpv9Tws2mtvM/mQzQO/86QQR6hpUUGllswOZD2FKAI7CRp3G83gtb11JSSOEzPYu2FYpIhqZCiQX8AIkELyvEZLE51iloIxREVK3h7NUNvr6gRBZBI/eWLYGQm1Txaoh6yRxEuS8wiclMsYMZiLOJci1vOnhdOOWmCwRCnYJIuRD5achaQwQJZLis6CQBIyXyYOFOuJKcLXhVdzW+2t3umW5qyo87E0nMwtFsvEMuYtlgWqaW0BDDMtqYK7CMAgllXQNLFMX2/5tpO3en1czFlSX8awAhElj5XpkVJBE5I0DKsREUSnTGawTkXiq3XuffdPwDDdLAaLVzHsrITqjE3Ro1hO4lnJGJZXAmIU86jzKXy9Ezsn/lB78qtWs7xkYQ/RIzmLJmSxxXrEMlJgmOIsiMGjjm2giIWo38L3Xq89+UPRq5bmJTcwZJ9LSFWsAYdSQIpIoMAKtLS6mL1ZjN4ymBkvgQlvbT958ou+lQIz03E7Ror8pEN6JEVA9EhDFM8lBC3m/XIom8RfA51rT976cu4cc5M0J1fkD0pelqRHmZySZHkjChjMVEOi/n5SYiOV4I5M+Kp3urPZSSCKHiUE1ZlYVpZUxiIRpPjddESBnQX7UhM5lCWmhiQ8uVILTirytDSbwWIRjE0JTcTqG0pMTQIpls1jKCK1pLosPFWH8KSfbiMTfOZMJVleyQTy7FQD4mVo3CxiKNJwgkyJD0dIhU/LeVBhaiaAFNNRnJDK45kmGMQ0ULi7EziQEUNCVcRwP5Qe9/eq/aH37zOnlvG7eDNSyLhm4sPj4+Nz0bCBvAehUkvlx8TEIxwb6Om5/WVzTZbL1CjmoDBBE4lsK/HxMfFBiQRNvD1r1l/z0tLS8irRj0ExMTH97U1N/muKqs5IRL9zcxGYO2CFAsu/Po88h1O50FF4MQhOoQjc19d3YQkDgqBSgmP6D5/qGul+ULWpNB/mjK/7+ndWv7RslovAaQIJXGYte2n1a+vdCVyLE9KGYGgb+tofExSklEspioIMJwGBpEipPPNIV070Hb97m1qUByqk8FsCyoDohb7u76xeZprFZfV6d19ohmIglUpTlEDAvYIacw3IBuR/1zTSlfH1oN9mpTIo+FC2ioOaoIGIWG2Kw8UdvK3Kzs3P1wDgKg8PDjKAcRejC1zDW+4MNDUNDD36tiNYiToQBPfCGdnCU8tiI+QEPssExUsSVWdsYnyQkkFwMHx4DMEH9FAqM9t7enr8C1v9NgcpgxiGICXch38RQUpURaM2y1QkmZmlzdXIK6JSYpMSU8PjgziCYO6KugkTOjGpLL7d36vH/2lr1UZEwJVKkss14EGVSIK0yjrjYyLeCmfmVZ6TMmLiErU6e3i4DEzRIagR+R88TQ8/8/fy2v60Nfn7YaWeIUgZC9rBgMjKOnf2TEFNnsJunJU0Oa8msvLM2bPnzmVRUjrVQHGgDOojdvWzY009t5+2KiKztHSSgSI4HlKdyry8vORkBZqbPiYXDee5GAtFcnIypDcaJAnyfDAIcVdKlFQWFHz/5v2ugcJW6ExNCS6NOABtB3PKaSjJGUwH73ECorOrvgisCFJxqDK9BTaTwQeUwcpGqQStqVhfRncrW4TtxAFlP5wflQYrQzulzMLOYMmicWeenTdXBoXS/37jd6+qqureqo0dB4JUpKiA+eXOI65IpIhUx/R/N7iKKbNp8A2aIPLYn3wmWpfcpuvq41l5epuq/J7B6sysqdhQt64XBIE/ftCM6b1bghNMkfmTBHXBPG/WCzzHQv0CEeeFBycuc61C1i76nlcGUiLGUDMW70VzGSlEkfzqDOX8+X1rBkba+7h7lRLJWQWmALDfC0RILk9n89JAkILzi159yW/n/H7N7Z6mz697zmO9GSkhSvJ4FGk4nvV3OydzYjmT3hhGB5PV4HTCe+9CHnb7ep+rwMKNaRTWLEkNpiNg+oH/1rzVwnWsFN9rVZv+BAnAl0XTZ8KcEizRiVEJf3hioGXRnPRmCeqPtqC5uVlXW/HjptsGAjbfRWKI8HMKHkWBiHAXmJnegBRqNCX6mhlH910HgltfFs192dIgF4ayIP1M0E2mhbPMoZjHSFHiVzh4/WZbW9uDtjW3Idt+6udjZ8nfeyDnlxCiyL6+IkAVcykhUGprlhTg1CdXu7uHCgsLL1717/ECC1bt5ScYAh/O+ZIfoRc3b14H3Lx580cc/8C8TBMNjU3dd+50Xxw6MTDStP2LouSC14xPOTw559Ml0PiaQg5PEswSg5GCJh5dO55558SRrpGBwiIUuJcZmzpH53zaD5nKYZA2SwwkhRYf7j6RMe3U/a72R1XNbT+JJAtdxqRy7ExI+AmavsLhYgktMUMMJIUo4fHAkVPR0RlDD5rbrjx8DPY7jUlI2bgCKfmVK1d1eEKOL4atgCeFNGH4u5Gu+9Ffr2p+cPHhw2sqWrLaRFrNBGS6ZKgbcO1aN7yuPR5fDMG23xikeD68cQAypG+/2eh38fz5aw+HaBpfZkIyFEPg5Gjj+c91OL8REjXTYizfdt1OL0Xro/NNI+1Dq1YNdgPBtWvnNyUY79x11rIxhHx8gsVleH+upk3PjOWvt+m3sf8sfNjT03Sltbnt6nkOj0l8vdCUZkBRKSKHLzO4wWCYJN4Zh4Hr5fIdD728vK70Kh4MXb7M9e4Eb+NuHI8xNn3/9viN4xza/0uNFkPIMNzkbBBavj/SA5uR5rRHN9pvXOZ6953R8QP/yI0N6NSz9mM6HPmGHD0z4OjTEhh0nnbOaPqhV5F2RnWsne3VDbZjvpYmDw4ZMWBXe0SPjK/VhNpYDFsL29fX6I9EhJaXCxVYAZ6gMfTrWLuGMiWFIaCT2UcyMuDNIp9WG68ZDrafPZ2rX2yX/7lpDYRPCXWpi9cvkOLtcdZfZpdP03VHM45yuH+aVhmLYbXhqjd35iZcvsO/qecmClDk5i5dpzLubybHHssZB3Rp9f2jpzjk1MH2wUiMBcdb2X2m/Xs7YNv5tFeBMjBV+lGuX6eO3r8kZQ8Xx/hKH9CpxpycnFM5LNKzVblGYmwZ/A+qbfPuNq/aW1+0zvBm8sjG+6f0vcoBKd4x/ThQF9BJVX90Dryi0SdHrsr9hRHFRscAh/d2/NGrFhbmJZ6LmGVbejea7dS0nJzofjUnxdinT7qATlOJodEccqLvqvJ/yRdj2vtbP/sjMqC3z2eeMzogSoOdWhnTKdSt6NAkios6Y59v6QO6NAoodCx12fkavhi7u5q2f/VFb9EMdMjNrBVIin59p6Ih3x43duoDOqUJDY0O5Uj6NRo5X4x/+7W19s1wtXN20mdQImljKA9wJvfBBOckXIYepCsOHBUaOV8Mx0//tnKOs4AbLpBBQeiURkQbdo7x44VOXkCnCekhQ5XQWE0DXwwbo8dibkx5Ol9uQP5Eq7EhoOdyxRsqKqIq4MqrYmtEYcdmX2hTrweBuztNtAgzMURXhSRUufmNDQ0Ve/kP3/jPgFyZ4qMwUZpqp08KWajhXKARjOj8NT8O8oYiI4VWNAraZRM+Q2FWV66SFmzQIIaQMIHpZ/hQQRE5Bm9YTvgkCIlRoytbUPAG4E2ApenH0i9jJjB/ziQn0qOgUMA+TTHT7PKAlZM8vTQFheeEVo/C9IlTVGdvbCqV2PhvjBnOkz7jGIslzlMwGnw6yYN3z7GVvMdXz22e3SjMmymcjMJ5bCUjhv8Bj3LnNzTgEYIAAAAASUVORK5CYII=";
 const TOTAL_SUPPLY: Balance = 90_000_000_000_000_000_000_000_000;
+/// Owner of the deployments made before the owner was stored in the state, see `migration`.
+const OWNER_ID: &str = "avtoken.near";
 
 #[near_bindgen]
 impl Contract {
     #[init]
-    pub fn new_default_meta(owner_id: ValidAccountId) -> Self {
-        Self::new(
-            owner_id,
-            U128(TOTAL_SUPPLY),
-            FungibleTokenMetadata {
-                spec: FT_METADATA_SPEC.to_string(),
-                name: "AV TOKEN".to_string(),
-                symbol: "ASTRO".to_string(),
-                icon: Some(SVG_TOKEN_ICON.to_string()),
-                reference: None,
-                reference_hash: None,
-                decimals: 18
-            },
-        )
+    pub fn new_default_meta(owner_id: AccountId) -> Self {
+        Self::new(owner_id, U128(TOTAL_SUPPLY), default_metadata())
     }
 
-    /// Initializes the contract with the given total supply owned by the given `owner_id` with
-    /// the given fungible token metadata.
+    /// Initializes the contract with `total_supply` owned by `owner_id` and the default metadata,
+    /// where the fields set in `meta_overrides` replace the defaults.
     #[init]
-    pub fn new(
-        owner_id: ValidAccountId,
+    pub fn new_with_config(
+        owner_id: AccountId,
         total_supply: U128,
-        metadata: FungibleTokenMetadata,
+        meta_overrides: MetadataOverrides,
     ) -> Self {
-        assert!(!env::state_exists(), "Already initialized");
-        metadata.assert_valid();
-        let mut this = Self {
-            token: FungibleToken::new(b"a".to_vec()),
-            metadata: LazyOption::new(b"m".to_vec(), Some(&metadata)),
-        };
-        this.token.internal_register_account(owner_id.as_ref());
-        this.token.internal_deposit(owner_id.as_ref(), total_supply.into());
+        let mut this = Self::new(owner_id, total_supply, default_metadata());
+        let MetadataOverrides {
+            name,
+            symbol,
+            icon,
+            reference,
+            reference_hash,
+            decimals,
+        } = meta_overrides;
+        this.internal_update_metadata(name, symbol, icon, reference, reference_hash, decimals);
         this
     }
-	
-	pub fn update_image(&mut self, image: String) {
-      assert_eq!(
-			env::predecessor_account_id(),
-			"avtoken.near".to_string(),
-			"Owner's method"
-		);
-      let mut metadata = self.metadata.get().unwrap();
-      metadata.icon = Some(image);
-      self.metadata.set(&metadata);
-    }
 
-    fn on_account_closed(&mut self, account_id: AccountId, balance: Balance) {
-        log!("Closed @{} with {}", account_id, balance);
-    }
-
-    fn on_tokens_burned(&mut self, account_id: AccountId, amount: Balance) {
-        log!("Account @{} burned {}", account_id, amount);
+    /// Initializes the contract with the given total supply owned by the given `owner_id` with
+    /// the given fungible token metadata.
+    #[init]
+    pub fn new(owner_id: AccountId, total_supply: U128, metadata: FungibleTokenMetadata) -> Self {
+        assert!(!env::state_exists(), "Already initialized");
+        metadata.assert_valid();
+        let mut this = Self::with_token(
+            owner_id.clone(),
+            FungibleToken::new(StorageKey::Token),
+            LazyOption::new(StorageKey::Metadata, Some(&metadata)),
+        );
+        this.internal_register_account(&owner_id);
+        this.internal_register_account(&env::current_account_id());
+        this.internal_mint(&env::current_account_id(), &owner_id, total_supply.into());
+        migration::write_state_version();
+        this
     }
-}
 
-near_contract_standards::impl_fungible_token_core!(Contract, token, on_tokens_burned);
-near_contract_standards::impl_fungible_token_storage!(Contract, token, on_account_closed);
-
-#[near_bindgen]
-impl FungibleTokenMetadataProvider for Contract {
-    fn ft_metadata(&self) -> FungibleTokenMetadata {
-        self.metadata.get().unwrap()
+    /// Builds the contract around the given token state with every other subsystem empty.
+    /// Shared by the initializer and state migrations.
+    fn with_token(
+        owner_id: AccountId,
+        token: FungibleToken,
+        metadata: LazyOption<FungibleTokenMetadata>,
+    ) -> Self {
+        Self {
+            token,
+            metadata,
+            staking: StakingPool::new(StorageKey::Staking.into_storage_key()),
+            mint_queue: MintQueue::new(StorageKey::MintQueue.into_storage_key()),
+            payment_links: LookupMap::new(StorageKey::PaymentLinks),
+            streams: LookupMap::new(StorageKey::Streams),
+            next_stream_id: 0,
+            escrows: LookupMap::new(StorageKey::Escrows),
+            next_escrow_id: 0,
+            has_transfers: false,
+            owner_id,
+            pending_owner_id: None,
+            dao_id: None,
+            checkpoints: Checkpoints::new(StorageKey::Checkpoints.into_storage_key()),
+            governance: Governance::new(StorageKey::Governance.into_storage_key()),
+            balance_proofs: BalanceProofs::new(StorageKey::BalanceProofs.into_storage_key()),
+            registration_limits: RegistrationLimits::new(
+                StorageKey::RegistrationLimits.into_storage_key(),
+            ),
+            delegation: Delegation::new(StorageKey::Delegation.into_storage_key()),
+            recovery: Recovery::new(StorageKey::Recovery.into_storage_key()),
+            transfer_fee: TransferFee::new(StorageKey::TransferFee.into_storage_key()),
+            transfer_calls: TransferCalls::new(StorageKey::TransferCalls.into_storage_key()),
+            emission: None,
+            pending_metadata_change: None,
+            airdrops: Airdrops::new(StorageKey::Airdrops.into_storage_key()),
+            treasury_balance: 0,
+            buyback: Buyback::new(),
+            telemetry: Telemetry::new(StorageKey::Telemetry.into_storage_key()),
+            randomness: Randomness::new(StorageKey::Randomness.into_storage_key()),
+            receiver_allowlist: ReceiverAllowlist::new(
+                StorageKey::ReceiverAllowlist.into_storage_key(),
+            ),
+            transfer_limits: TransferLimits::new(StorageKey::TransferLimits.into_storage_key()),
+            freezes: Freezes::new(StorageKey::Freezes.into_storage_key()),
+            launch_guard: None,
+            stake_positions: StakePositions::new(StorageKey::StakePositions.into_storage_key()),
+            storage_grace: StorageGrace::new(StorageKey::StorageGrace.into_storage_key()),
+            registered_accounts: UnorderedSet::new(StorageKey::RegisteredAccounts),
+            matching: Matching::new(StorageKey::Matching.into_storage_key()),
+            gas_tank: GasTank::default(),
+            meta_transactions: MetaTransactions::new(
+                StorageKey::MetaTransactions.into_storage_key(),
+            ),
+            allowances: LookupMap::new(StorageKey::Allowances),
+            pause: None,
+            liquid_staking: LiquidStaking::default(),
+            scheduler: Scheduler::new(StorageKey::Scheduler.into_storage_key()),
+            dust_sweeping: DustSweeping::new(StorageKey::DustSweeping.into_storage_key()),
+            receiver_capabilities: ReceiverCapabilitiesRegistry::new(
+                StorageKey::ReceiverCapabilities.into_storage_key(),
+            ),
+            council: Council::new(StorageKey::Council.into_storage_key()),
+            retired_modules: vec![],
+            timelock: Timelock::new(StorageKey::Timelock.into_storage_key()),
+            rescue: Rescue::new(StorageKey::Rescue.into_storage_key()),
+            token_deposits: TokenDeposits::new(StorageKey::TokenDeposits.into_storage_key()),
+            dividends: Dividends::new(StorageKey::Dividends.into_storage_key()),
+            bridge: Bridge::new(StorageKey::Bridge.into_storage_key()),
+            sale: Sale::new(StorageKey::Sale.into_storage_key()),
+            farming: Farming::new(StorageKey::Farming.into_storage_key()),
+            referrals: Referrals::new(StorageKey::Referrals.into_storage_key()),
+            callback_failures: CallbackFailures::new(
+                StorageKey::CallbackFailures.into_storage_key(),
+            ),
+            max_icon_bytes: metadata_validation::DEFAULT_MAX_ICON_BYTES,
+            balance_locks: BalanceLocks::new(StorageKey::BalanceLocks.into_storage_key()),
+            bonding_curve: BondingCurve::default(),
+            vault: StakingVault::new(StorageKey::Vault.into_storage_key()),
+            scheduled_distributions: ScheduledDistributions::new(
+                StorageKey::ScheduledDistributions.into_storage_key(),
+            ),
+            scheduled_transfers: ScheduledTransfers::new(
+                StorageKey::ScheduledTransfers.into_storage_key(),
+            ),
+            subscriptions: Subscriptions::new(StorageKey::Subscriptions.into_storage_key()),
+            payment_requests: PaymentRequests::new(StorageKey::PaymentRequests.into_storage_key()),
+            mass_distribution: MassDistribution::new(
+                StorageKey::MassDistribution.into_storage_key(),
+            ),
+            drops: Drops::new(StorageKey::Drops.into_storage_key()),
+            minter_allowances: MinterAllowances::new(
+                StorageKey::MinterAllowances.into_storage_key(),
+            ),
+            ownership_renounced: false,
+            force_transfers: ForceTransfers::default(),
+            large_transfer_threshold: None,
+            factory: Factory::new(StorageKey::Factory.into_storage_key()),
+            legacy_swap: LegacySwap::default(),
+            vote_escrow: VoteEscrow::new(StorageKey::VoteEscrow.into_storage_key()),
+            archive: Archive::new(StorageKey::Archive.into_storage_key()),
+            memo_requirements: MemoRequirements::new(
+                StorageKey::MemoRequirements.into_storage_key(),
+            ),
+            spend_hooks: SpendHooks::new(StorageKey::SpendHooks.into_storage_key()),
+            compliance: Compliance::new(StorageKey::Compliance.into_storage_key()),
+            emission_distributor: EmissionDistributor::default(),
+            savings: Savings::new(StorageKey::Savings.into_storage_key()),
+            trading_start: TradingStart::new(StorageKey::TradingStart.into_storage_key()),
+            transfer_log: TransferLog::new(StorageKey::TransferLog.into_storage_key()),
+            fee_oracle: FeeOracle::default(),
+            burn_account_id: None,
+            sponsorship: Sponsorship::new(StorageKey::Sponsorship.into_storage_key()),
+            receive_hooks: ReceiveHooks::new(StorageKey::ReceiveHooks.into_storage_key()),
+            liquidity_bootstrap: LiquidityBootstrap::default(),
+            reservations: Reservations::new(StorageKey::Reservations.into_storage_key()),
+            nft_boost: NftBoost::new(StorageKey::NftBoost.into_storage_key()),
+            metrics: Metrics::default(),
+            treasury_payouts: TreasuryPayouts::new(StorageKey::TreasuryPayouts.into_storage_key()),
+            paused_modules: vec![],
+            localized_metadata: LocalizedMetadata::new(
+                StorageKey::LocalizedMetadata.into_storage_key(),
+            ),
+            allowance_index: AllowanceIndex::new(StorageKey::AllowanceIndex.into_storage_key()),
+            pool_ledger: PoolLedger::new(StorageKey::PoolLedger.into_storage_key()),
+            remote_mint: RemoteMint::new(StorageKey::RemoteMint.into_storage_key()),
+            gas_rebates: GasRebates::new(StorageKey::GasRebates.into_storage_key()),
+            fee_rebates: FeeRebates::new(StorageKey::FeeRebates.into_storage_key()),
+            inactivity_decay: InactivityDecay::new(StorageKey::InactivityDecay.into_storage_key()),
+        }
     }
 }
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
+    use near_contract_standards::fungible_token::metadata::FungibleTokenMetadataProvider;
+    use near_sdk::json_types::Base64VecU8;
     use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::MockedBlockchain;
-    use near_sdk::{testing_env, Balance};
+    use near_sdk::testing_env;
 
     use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
 
-    const TOTAL_SUPPLY: Balance = 100_000_000_000_000_000_000_000_000;
-
-    fn get_context(predecessor_account_id: ValidAccountId) -> VMContextBuilder {
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
         let mut builder = VMContextBuilder::new();
         builder
             .current_account_id(accounts(0))
@@ -123,11 +630,19 @@ mod tests {
         builder
     }
 
+    /// Writes the state and reads it back. Dropping the contract flushes the store collections,
+    /// which is not allowed in view calls.
+    fn flush(contract: Contract) -> Contract {
+        env::state_write(&contract);
+        drop(contract);
+        env::state_read().unwrap()
+    }
+
     #[test]
     fn test_new() {
         let mut context = get_context(accounts(1));
         testing_env!(context.build());
-        let contract = Contract::new_paras_meta(accounts(1).into(), TOTAL_SUPPLY.into());
+        let contract = flush(Contract::new_default_meta(accounts(1)));
         testing_env!(context.is_view(true).build());
         assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
         assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY);
@@ -145,7 +660,7 @@ mod tests {
     fn test_transfer() {
         let mut context = get_context(accounts(2));
         testing_env!(context.build());
-        let mut contract = Contract::new_paras_meta(accounts(2).into(), TOTAL_SUPPLY.into());
+        let mut contract = Contract::new_default_meta(accounts(2));
         testing_env!(context
             .storage_usage(env::storage_usage())
             .attached_deposit(contract.storage_balance_bounds().min.into())
@@ -161,6 +676,7 @@ mod tests {
             .build());
         let transfer_amount = TOTAL_SUPPLY / 3;
         contract.ft_transfer(accounts(1), transfer_amount.into(), None);
+        let contract = flush(contract);
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -168,7 +684,35 @@ mod tests {
             .is_view(true)
             .attached_deposit(0)
             .build());
-        assert_eq!(contract.ft_balance_of(accounts(2)).0, (TOTAL_SUPPLY - transfer_amount));
+        assert_eq!(
+            contract.ft_balance_of(accounts(2)).0,
+            (TOTAL_SUPPLY - transfer_amount)
+        );
         assert_eq!(contract.ft_balance_of(accounts(1)).0, transfer_amount);
     }
+
+    #[test]
+    fn test_update_metadata() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        contract.update_metadata(
+            None,
+            Some("https://astro.example".to_string()),
+            Some(Base64VecU8(vec![0; 32])),
+            Some(24),
+        );
+        let metadata = contract.ft_metadata();
+        assert_eq!(metadata.reference.unwrap(), "https://astro.example");
+        assert_eq!(metadata.symbol, "ASTRO");
+        assert_eq!(metadata.decimals, 24);
+    }
+
+    #[test]
+    #[should_panic(expected = "Decimals cannot change after tokens have been transferred")]
+    fn test_update_decimals_after_transfer() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 1);
+        contract.ft_transfer(accounts(1), 1.into(), None);
+        contract.update_metadata(None, None, None, Some(24));
+    }
 }
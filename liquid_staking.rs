@@ -0,0 +1,235 @@
+/*!
+Yield on idle contract NEAR through a liquid staking token.
+NOTES:
+  - The contract account holds NEAR beyond what its storage needs, e.g. storage deposits of
+    accounts that unregister later. The owner or the DAO can stake it with `stake_treasury_near`
+    on the configured liquid staking contract (e.g. LiNEAR or Meta Pool, `deposit_and_stake`),
+    receiving its token in return.
+  - Only idle NEAR can be staked: the balance minus the storage cost, the gas tank and `reserve`.
+    The reserve is a buffer for NEAR the contract owes without tracking it, like registration
+    deposits of open payment links.
+  - Staked NEAR comes back with `unstake_treasury_near`, which starts the delayed unstaking of
+    shares, then `withdraw_treasury_near` once the liquid staking contract releases them.
+  - `deposited` is the NEAR principal staked. `shares` is the token balance as of the last
+    `sync_liquid_staking`, which anyone can call.
+*/
+#![cfg_attr(not(feature = "staking"), allow(dead_code, unused_imports))]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{
+    env, ext_contract, is_promise_success, log, near_bindgen, AccountId, Balance, Gas, Promise,
+    PromiseError,
+};
+
+use crate::*;
+
+const GAS_FOR_LIQUID_STAKING: Gas = Gas(50_000_000_000_000);
+const GAS_FOR_FT_BALANCE_OF: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_CALLBACK: Gas = Gas(10_000_000_000_000);
+/// Default reserve of 10 NEAR kept out of staking.
+const DEFAULT_RESERVE: Balance = 10_000_000_000_000_000_000_000_000;
+
+#[ext_contract(ext_liquid_staking)]
+#[allow(dead_code)]
+pub trait LiquidStakingPool {
+    fn deposit_and_stake(&mut self);
+    fn unstake(&mut self, amount: U128);
+    fn withdraw_all(&mut self);
+    fn ft_balance_of(&self, account_id: AccountId) -> U128;
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LiquidStaking {
+    pub contract_id: Option<AccountId>,
+    pub reserve: Balance,
+    pub deposited: Balance,
+    pub shares: Balance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LiquidStakingPosition {
+    pub contract_id: Option<AccountId>,
+    pub deposited: U128,
+    pub shares: U128,
+    pub idle_near: U128,
+}
+
+impl Default for LiquidStaking {
+    fn default() -> Self {
+        Self {
+            contract_id: None,
+            reserve: DEFAULT_RESERVE,
+            deposited: 0,
+            shares: 0,
+        }
+    }
+}
+
+impl LiquidStaking {
+    fn unwrap_contract_id(&self) -> AccountId {
+        self.contract_id
+            .clone()
+            .expect("Liquid staking is not configured")
+    }
+}
+
+#[cfg(feature = "staking")]
+#[near_bindgen]
+impl Contract {
+    pub fn set_liquid_staking_contract(&mut self, contract_id: Option<AccountId>) {
+        self.assert_owner_or_dao();
+        assert!(
+            self.liquid_staking.deposited == 0,
+            "Withdraw the staked NEAR before changing the liquid staking contract"
+        );
+        self.liquid_staking.contract_id = contract_id;
+    }
+
+    pub fn set_liquid_staking_reserve(&mut self, reserve: U128) {
+        self.assert_owner_or_dao();
+        self.liquid_staking.reserve = reserve.0;
+    }
+
+    /// Stakes `amount` of idle NEAR with the liquid staking contract.
+    pub fn stake_treasury_near(&mut self, amount: U128) -> Promise {
+        self.assert_owner_or_dao();
+        let idle = self.idle_near();
+        assert!(
+            amount.0 > 0 && amount.0 <= idle,
+            "Only {} idle NEAR can be staked",
+            idle
+        );
+        ext_liquid_staking::ext(self.liquid_staking.unwrap_contract_id())
+            .with_attached_deposit(amount.0)
+            .with_static_gas(GAS_FOR_LIQUID_STAKING)
+            .deposit_and_stake()
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_CALLBACK)
+                    .on_treasury_near_staked(amount),
+            )
+    }
+
+    /// Starts unstaking `shares` of the liquid staking token.
+    pub fn unstake_treasury_near(&mut self, shares: U128) -> Promise {
+        self.assert_owner_or_dao();
+        ext_liquid_staking::ext(self.liquid_staking.unwrap_contract_id())
+            .with_static_gas(GAS_FOR_LIQUID_STAKING)
+            .unstake(shares)
+    }
+
+    /// Withdraws the NEAR released by the liquid staking contract.
+    pub fn withdraw_treasury_near(&mut self) -> Promise {
+        self.assert_owner_or_dao();
+        ext_liquid_staking::ext(self.liquid_staking.unwrap_contract_id())
+            .with_static_gas(GAS_FOR_LIQUID_STAKING)
+            .withdraw_all()
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_CALLBACK)
+                    .on_treasury_near_withdrawn(U128(env::account_balance())),
+            )
+    }
+
+    /// Updates `shares` with the token balance of the contract account.
+    pub fn sync_liquid_staking(&mut self) -> Promise {
+        ext_liquid_staking::ext(self.liquid_staking.unwrap_contract_id())
+            .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+            .ft_balance_of(env::current_account_id())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_CALLBACK)
+                    .on_liquid_staking_synced(),
+            )
+    }
+
+    #[private]
+    pub fn on_treasury_near_staked(&mut self, amount: U128) {
+        if is_promise_success() {
+            self.liquid_staking.deposited += amount.0;
+            log!("Staked {} treasury NEAR", amount.0);
+        } else {
+            log!("Staking {} treasury NEAR failed", amount.0);
+            self.internal_record_callback_failure(
+                "stake_treasury_near",
+                self.liquid_staking.contract_id.iter().cloned().collect(),
+                amount.0,
+            );
+        }
+    }
+
+    #[private]
+    pub fn on_treasury_near_withdrawn(&mut self, balance_before: U128) {
+        let received = env::account_balance().saturating_sub(balance_before.0);
+        self.liquid_staking.deposited = self.liquid_staking.deposited.saturating_sub(received);
+        log!("Withdrew {} treasury NEAR", received);
+    }
+
+    #[private]
+    pub fn on_liquid_staking_synced(
+        &mut self,
+        #[callback_result] shares: Result<U128, PromiseError>,
+    ) {
+        match shares {
+            Ok(shares) => self.liquid_staking.shares = shares.0,
+            Err(_) => self.internal_record_callback_failure(
+                "sync_liquid_staking",
+                self.liquid_staking.contract_id.iter().cloned().collect(),
+                0,
+            ),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_liquid_staking_position(&self) -> LiquidStakingPosition {
+        LiquidStakingPosition {
+            contract_id: self.liquid_staking.contract_id.clone(),
+            deposited: self.liquid_staking.deposited.into(),
+            shares: self.liquid_staking.shares.into(),
+            idle_near: self.idle_near().into(),
+        }
+    }
+}
+
+impl Contract {
+    /// NEAR of the contract account that is neither needed for storage nor set aside.
+    pub(crate) fn idle_near(&self) -> Balance {
+        let storage_cost = env::storage_byte_cost() * Balance::from(env::storage_usage());
+        env::account_balance()
+            .saturating_sub(storage_cost)
+            .saturating_sub(self.gas_tank.balance.0)
+            .saturating_sub(self.liquid_staking.reserve)
+            .saturating_sub(self.sale.held_proceeds())
+            .saturating_sub(self.bonding_curve.held())
+            .saturating_sub(self.sponsorship.balance)
+            .saturating_sub(self.gas_rebates.pool)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "staking"))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::setup;
+
+    #[test]
+    #[should_panic(expected = "idle NEAR can be staked")]
+    fn test_cannot_stake_reserve() {
+        let (mut context, mut contract) = setup(&[]);
+        testing_env!(context
+            .attached_deposit(0)
+            .account_balance(DEFAULT_RESERVE * 2)
+            .build());
+        contract.set_liquid_staking_contract(Some(accounts(1)));
+        let idle = contract.get_liquid_staking_position().idle_near.0;
+        assert!(idle > 0 && idle < DEFAULT_RESERVE);
+        contract.stake_treasury_near(U128(idle + 1));
+    }
+}
@@ -0,0 +1,428 @@
+/*!
+One-shot bootstrap of an ASTRO/wNEAR pool on Ref Finance.
+NOTES:
+  - `bootstrap_liquidity` takes ASTRO from the treasury and the attached NEAR and, in one chain of
+    promises, registers the contract on Ref, deposits both tokens, creates the pool if no
+    `pool_id` is given and adds the liquidity. The LP shares are held by the contract account.
+  - Each step is checked by a callback. If a deposit, the pool creation or the liquidity fails,
+    the deposits are withdrawn from Ref: the ASTRO goes back to the treasury and the wNEAR is
+    unwrapped and refunded to the caller. A failed refund is recorded in `callback_failures`.
+  - The attached deposit pays `REF_STORAGE_DEPOSIT` for the registration on Ref and
+    `REF_POOL_DEPOSIT` for a new pool, the rest is wrapped and deposited. Ref refunds any excess
+    of the pool deposit to the contract account.
+  - Liquidity is added as `[ASTRO, wNEAR]`, the order of a pool created here. The Ref contract
+    must be registered with ASTRO and the contract account with the wNEAR contract.
+  - One bootstrap can run at a time. The chain needs close to the 300 Tgas a transaction can
+    attach.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::{
+    env, ext_contract, log, near_bindgen, AccountId, Balance, Gas, Promise, PromiseError,
+    PromiseOrValue,
+};
+
+use crate::buyback::ext_wrap;
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+pub const REF_STORAGE_DEPOSIT: Balance = 100_000_000_000_000_000_000_000;
+pub const REF_POOL_DEPOSIT: Balance = 100_000_000_000_000_000_000_000;
+const MIN_GAS_FOR_BOOTSTRAP: Gas = Gas(280_000_000_000_000);
+const GAS_FOR_REF_CALL: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_REF_DEPOSIT: Gas = Gas(20_000_000_000_000);
+const GAS_FOR_REF_WITHDRAW: Gas = Gas(45_000_000_000_000);
+const GAS_FOR_POOL_CALL: Gas = Gas(20_000_000_000_000);
+const GAS_FOR_NEAR_DEPOSIT: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_NEAR_WITHDRAW: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_WNEAR_TRANSFER_CALL: Gas = Gas(50_000_000_000_000);
+const GAS_FOR_CALLBACK: Gas = Gas(5_000_000_000_000);
+
+#[ext_contract(ext_ref)]
+#[allow(dead_code)]
+pub trait RefExchange {
+    fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>);
+    fn register_tokens(&mut self, token_ids: Vec<AccountId>);
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128>;
+    fn add_simple_pool(&mut self, tokens: Vec<AccountId>, fee: u32) -> u64;
+    fn add_liquidity(
+        &mut self,
+        pool_id: u64,
+        amounts: Vec<U128>,
+        min_amounts: Option<Vec<U128>>,
+    ) -> U128;
+    fn withdraw(&mut self, token_id: AccountId, amount: U128, unregister: Option<bool>);
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolParams {
+    pub wnear_id: AccountId,
+    /// Existing pool to add to, a new one is created if `None`.
+    pub pool_id: Option<u64>,
+    /// Fee of a new pool in bps.
+    pub fee_bps: u32,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BootstrapRun {
+    pub ref_id: AccountId,
+    pub wnear_id: AccountId,
+    pub caller_id: AccountId,
+    pub pool_id: Option<u64>,
+    pub fee_bps: u32,
+    pub astro_amount: U128,
+    pub wnear_amount: U128,
+    pub astro_deposited: U128,
+    pub wnear_deposited: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct LiquidityBootstrap {
+    pub run: Option<BootstrapRun>,
+    /// ASTRO withdrawn from Ref by a rollback and not received back yet.
+    pub returning: Balance,
+}
+
+impl LiquidityBootstrap {
+    /// Whether `amount` received from the exchange is ASTRO returned by a rollback.
+    pub fn take_returning(&mut self, amount: Balance) -> bool {
+        if amount > self.returning {
+            return false;
+        }
+        self.returning -= amount;
+        true
+    }
+
+    fn unwrap_run(&self) -> BootstrapRun {
+        self.run
+            .clone()
+            .unwrap_or_else(|| env::panic_str("No liquidity bootstrap is running"))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Adds `astro_amount` of treasury ASTRO and the attached NEAR, less Ref's deposits, to a
+    /// pool on `ref_finance_id`.
+    #[payable]
+    pub fn bootstrap_liquidity(
+        &mut self,
+        ref_finance_id: AccountId,
+        pool_params: PoolParams,
+        astro_amount: U128,
+    ) -> Promise {
+        self.assert_owner();
+        assert!(
+            self.liquidity_bootstrap.run.is_none(),
+            "A liquidity bootstrap is already running"
+        );
+        assert!(
+            env::prepaid_gas() >= MIN_GAS_FOR_BOOTSTRAP,
+            "Attach at least {} gas",
+            MIN_GAS_FOR_BOOTSTRAP.0
+        );
+        assert!(
+            self.token.accounts.contains_key(&ref_finance_id),
+            "Register @{} with ASTRO first",
+            ref_finance_id
+        );
+        assert!(astro_amount.0 > 0, "The amount should be a positive number");
+        assert!(
+            astro_amount.0 <= self.treasury_balance,
+            "The treasury holds {}",
+            self.treasury_balance
+        );
+        let pool_deposit = if pool_params.pool_id.is_some() {
+            0
+        } else {
+            REF_POOL_DEPOSIT
+        };
+        let wnear_amount = env::attached_deposit()
+            .checked_sub(REF_STORAGE_DEPOSIT + pool_deposit)
+            .filter(|amount| *amount > 0)
+            .unwrap_or_else(|| env::panic_str("Attach NEAR to pair with the ASTRO"));
+        self.treasury_balance -= astro_amount.0;
+        self.liquidity_bootstrap.run = Some(BootstrapRun {
+            ref_id: ref_finance_id.clone(),
+            wnear_id: pool_params.wnear_id.clone(),
+            caller_id: env::predecessor_account_id(),
+            pool_id: pool_params.pool_id,
+            fee_bps: pool_params.fee_bps,
+            astro_amount,
+            wnear_amount: U128(wnear_amount),
+            astro_deposited: U128(0),
+            wnear_deposited: U128(0),
+        });
+        ext_ref::ext(ref_finance_id.clone())
+            .with_attached_deposit(REF_STORAGE_DEPOSIT)
+            .with_static_gas(GAS_FOR_REF_CALL)
+            .with_unused_gas_weight(0)
+            .storage_deposit(None, Some(false))
+            .then(
+                ext_ref::ext(ref_finance_id)
+                    .with_attached_deposit(1)
+                    .with_static_gas(GAS_FOR_REF_CALL)
+                    .with_unused_gas_weight(0)
+                    .register_tokens(vec![env::current_account_id(), pool_params.wnear_id]),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_CALLBACK)
+                    .on_bootstrap_registered(),
+            )
+    }
+
+    /// Deposits both tokens on Ref once the contract is registered there.
+    #[private]
+    pub fn on_bootstrap_registered(
+        &mut self,
+        #[callback_result] registered: Result<(), PromiseError>,
+    ) -> PromiseOrValue<bool> {
+        let run = self.liquidity_bootstrap.unwrap_run();
+        if registered.is_err() {
+            return PromiseOrValue::Value(self.internal_rollback_bootstrap(run, false, 0));
+        }
+        let contract_id = env::current_account_id();
+        self.with_checkpoints(&[contract_id.clone(), run.ref_id.clone()], |this| {
+            this.token.internal_transfer(
+                &contract_id,
+                &run.ref_id,
+                run.astro_amount.0,
+                Some("Liquidity bootstrap".to_string()),
+            )
+        });
+        let astro_deposit = ext_ref::ext(run.ref_id.clone())
+            .with_static_gas(GAS_FOR_REF_DEPOSIT)
+            .with_unused_gas_weight(0)
+            .ft_on_transfer(contract_id.clone(), run.astro_amount, String::new());
+        let wnear_deposit = ext_wrap::ext(run.wnear_id.clone())
+            .with_attached_deposit(run.wnear_amount.0)
+            .with_static_gas(GAS_FOR_NEAR_DEPOSIT)
+            .with_unused_gas_weight(0)
+            .near_deposit()
+            .then(
+                ext_wrap::ext(run.wnear_id)
+                    .with_attached_deposit(1)
+                    .with_static_gas(GAS_FOR_WNEAR_TRANSFER_CALL)
+                    .with_unused_gas_weight(0)
+                    .ft_transfer_call(run.ref_id, run.wnear_amount, None, String::new()),
+            );
+        astro_deposit
+            .and(wnear_deposit)
+            .then(
+                Self::ext(contract_id)
+                    .with_static_gas(GAS_FOR_CALLBACK)
+                    .on_bootstrap_deposited(),
+            )
+            .into()
+    }
+
+    /// Takes back the ASTRO Ref did not accept, then creates the pool or adds the liquidity.
+    #[private]
+    pub fn on_bootstrap_deposited(
+        &mut self,
+        #[callback_result] astro_unused: Result<U128, PromiseError>,
+        #[callback_result] wnear_used: Result<U128, PromiseError>,
+    ) -> PromiseOrValue<bool> {
+        let mut run = self.liquidity_bootstrap.unwrap_run();
+        let astro_unused = astro_unused.map_or(run.astro_amount.0, |unused| {
+            std::cmp::min(unused.0, run.astro_amount.0)
+        });
+        if astro_unused > 0 {
+            let contract_id = env::current_account_id();
+            let refund = std::cmp::min(
+                astro_unused,
+                self.token.accounts.get(&run.ref_id).unwrap_or(0),
+            );
+            self.with_checkpoints(&[run.ref_id.clone(), contract_id.clone()], |this| {
+                this.token.internal_transfer(
+                    &run.ref_id,
+                    &contract_id,
+                    refund,
+                    Some("Liquidity bootstrap refund".to_string()),
+                )
+            });
+            self.treasury_balance += refund;
+        }
+        run.astro_deposited = U128(run.astro_amount.0 - astro_unused);
+        run.wnear_deposited = U128(wnear_used.map_or(0, |used| used.0));
+        self.liquidity_bootstrap.run = Some(run.clone());
+        if run.astro_deposited.0 < run.astro_amount.0 || run.wnear_deposited.0 < run.wnear_amount.0
+        {
+            return PromiseOrValue::Value(self.internal_rollback_bootstrap(run, true, 0));
+        }
+        match run.pool_id {
+            Some(pool_id) => self.internal_add_bootstrap_liquidity(&run, pool_id).into(),
+            None => ext_ref::ext(run.ref_id)
+                .with_attached_deposit(REF_POOL_DEPOSIT)
+                .with_static_gas(GAS_FOR_POOL_CALL)
+                .with_unused_gas_weight(0)
+                .add_simple_pool(vec![env::current_account_id(), run.wnear_id], run.fee_bps)
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_CALLBACK)
+                        .on_bootstrap_pool_created(),
+                )
+                .into(),
+        }
+    }
+
+    #[private]
+    pub fn on_bootstrap_pool_created(
+        &mut self,
+        #[callback_result] pool_id: Result<u64, PromiseError>,
+    ) -> PromiseOrValue<bool> {
+        let mut run = self.liquidity_bootstrap.unwrap_run();
+        match pool_id {
+            Ok(pool_id) => {
+                log!("Created pool {} on @{}", pool_id, run.ref_id);
+                run.pool_id = Some(pool_id);
+                self.liquidity_bootstrap.run = Some(run.clone());
+                self.internal_add_bootstrap_liquidity(&run, pool_id).into()
+            }
+            // Ref refunds the pool deposit of a failed call to the contract account.
+            Err(_) => {
+                PromiseOrValue::Value(self.internal_rollback_bootstrap(run, true, REF_POOL_DEPOSIT))
+            }
+        }
+    }
+
+    /// Ends the bootstrap. Returns whether the liquidity was added.
+    #[private]
+    pub fn on_liquidity_bootstrapped(
+        &mut self,
+        #[callback_result] shares: Result<U128, PromiseError>,
+    ) -> PromiseOrValue<bool> {
+        let run = self.liquidity_bootstrap.unwrap_run();
+        let shares = match shares {
+            Ok(shares) => shares,
+            Err(_) => return PromiseOrValue::Value(self.internal_rollback_bootstrap(run, true, 0)),
+        };
+        self.liquidity_bootstrap.run = None;
+        emit_event(
+            EVENT_VERSION,
+            "liquidity_bootstrapped",
+            json!({
+                "ref_id": run.ref_id,
+                "pool_id": run.pool_id,
+                "astro_amount": run.astro_amount,
+                "wnear_amount": run.wnear_amount,
+                "shares": shares,
+            }),
+        );
+        PromiseOrValue::Value(true)
+    }
+
+    pub fn get_liquidity_bootstrap(&self) -> Option<BootstrapRun> {
+        self.liquidity_bootstrap.run.clone()
+    }
+}
+
+impl Contract {
+    fn internal_add_bootstrap_liquidity(&self, run: &BootstrapRun, pool_id: u64) -> Promise {
+        ext_ref::ext(run.ref_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_POOL_CALL)
+            .with_unused_gas_weight(0)
+            .add_liquidity(pool_id, vec![run.astro_amount, run.wnear_amount], None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_CALLBACK)
+                    .on_liquidity_bootstrapped(),
+            )
+    }
+
+    /// Withdraws the deposits of `run` from Ref and refunds its NEAR, plus `extra_refund`, to the
+    /// caller, unwrapping it first if it was `wrapped`. The ASTRO goes back to the treasury when
+    /// Ref sends it. Returns `false`, the result of the bootstrap.
+    fn internal_rollback_bootstrap(
+        &mut self,
+        run: BootstrapRun,
+        wrapped: bool,
+        extra_refund: Balance,
+    ) -> bool {
+        self.liquidity_bootstrap.run = None;
+        log!(
+            "Liquidity bootstrap on @{} failed, rolling back",
+            run.ref_id
+        );
+        self.internal_record_callback_failure(
+            "bootstrap_liquidity",
+            vec![run.caller_id.clone()],
+            run.wnear_amount.0,
+        );
+        if run.astro_deposited.0 > 0 {
+            if self.buyback.is_exchange(&run.ref_id) {
+                self.liquidity_bootstrap.returning += run.astro_deposited.0;
+            }
+            ext_ref::ext(run.ref_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_REF_WITHDRAW)
+                .withdraw(env::current_account_id(), run.astro_deposited, None);
+        }
+        let refund = run.wnear_amount.0 + extra_refund;
+        if !wrapped {
+            Promise::new(run.caller_id).transfer(refund);
+            return false;
+        }
+        let unwrap = ext_wrap::ext(run.wnear_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NEAR_WITHDRAW)
+            .near_withdraw(run.wnear_amount);
+        let unwrap = if run.wnear_deposited.0 > 0 {
+            ext_ref::ext(run.ref_id)
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_REF_WITHDRAW)
+                .withdraw(run.wnear_id, run.wnear_deposited, None)
+                .then(unwrap)
+        } else {
+            unwrap
+        };
+        unwrap.then(Promise::new(run.caller_id).transfer(refund));
+        false
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    #[test]
+    fn test_unaccepted_astro_returns_to_the_treasury() {
+        let (mut context, mut contract) = setup(&[accounts(2)]);
+        call_as(&mut context, owner(), 1);
+        contract.ft_transfer(accounts(0), U128(1_000), None);
+        assert_eq!(contract.get_treasury_balance().0, 1_000);
+
+        testing_env!(context.attached_deposit(5 * ONE_NEAR).build());
+        let pool_params = PoolParams {
+            wnear_id: accounts(3),
+            pool_id: None,
+            fee_bps: 30,
+        };
+        contract.bootstrap_liquidity(accounts(2), pool_params, U128(1_000));
+        assert_eq!(contract.get_treasury_balance().0, 0);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.on_bootstrap_registered(Ok(()));
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 1_000);
+        contract.on_bootstrap_deposited(Ok(U128(1_000)), Ok(U128(0)));
+        assert!(contract.get_liquidity_bootstrap().is_none());
+        assert_eq!(contract.get_treasury_balance().0, 1_000);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+    }
+}
@@ -0,0 +1,142 @@
+/*!
+Localized reference documents of the token metadata.
+NOTES:
+  - The owner can point a locale, a BCP 47 tag like `pt-BR` or `ja`, to a translated reference
+    document with `set_localized_reference`. The URL and hash are validated like the default
+    reference, see `metadata_validation`.
+  - `ft_metadata_localized(locale)` is `ft_metadata` with the reference of the locale, else of its
+    language (`pt` for `pt-BR`), else the default one. Tags are matched case-insensitively.
+  - Only the reference and its hash are localized: name, symbol, icon and decimals are the same
+    in every locale.
+  - The contract pays for the storage of at most `MAX_LOCALES` entries.
+*/
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::Serialize;
+use near_sdk::store::UnorderedMap;
+use near_sdk::{log, near_bindgen};
+
+use crate::*;
+
+const MAX_LOCALES: u32 = 50;
+const MAX_LOCALE_LEN: usize = 35;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LocalizedReference {
+    pub reference: String,
+    pub reference_hash: Base64VecU8,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LocalizedMetadata {
+    /// References by lowercase locale tag.
+    pub references: UnorderedMap<String, LocalizedReference>,
+}
+
+impl LocalizedMetadata {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            references: UnorderedMap::new(prefix),
+        }
+    }
+
+    /// Reference of `locale` or, failing that, of its language.
+    fn resolve(&self, locale: &str) -> Option<&LocalizedReference> {
+        let locale = locale.to_ascii_lowercase();
+        self.references.get(&locale).or_else(|| {
+            let language = locale.split('-').next()?;
+            self.references.get(language)
+        })
+    }
+}
+
+fn normalize_locale(locale: &str) -> String {
+    assert!(
+        !locale.is_empty()
+            && locale.len() <= MAX_LOCALE_LEN
+            && locale
+                .split('-')
+                .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_alphanumeric())),
+        "The locale should be a BCP 47 tag of at most {} bytes",
+        MAX_LOCALE_LEN
+    );
+    locale.to_ascii_lowercase()
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Points `locale` to the translated reference document at `url` with sha256 hash `hash`.
+    pub fn set_localized_reference(&mut self, locale: String, url: String, hash: Base64VecU8) {
+        self.assert_owner();
+        let locale = normalize_locale(&locale);
+        metadata_validation::assert_valid_reference(&url, &hash);
+        let references = &mut self.localized_metadata.references;
+        assert!(
+            references.contains_key(&locale) || references.len() < MAX_LOCALES,
+            "At most {} locales",
+            MAX_LOCALES
+        );
+        log!("Reference for {} set to {}", locale, url);
+        references.insert(
+            locale,
+            LocalizedReference {
+                reference: url,
+                reference_hash: hash,
+            },
+        );
+    }
+
+    pub fn remove_localized_reference(&mut self, locale: String) {
+        self.assert_owner();
+        let locale = normalize_locale(&locale);
+        assert!(
+            self.localized_metadata.references.remove(&locale).is_some(),
+            "No reference for {}",
+            locale
+        );
+        log!("Reference for {} removed", locale);
+    }
+
+    /// The token metadata with the reference document of `locale`, see the module notes.
+    pub fn ft_metadata_localized(&self, locale: String) -> FungibleTokenMetadata {
+        let mut metadata = self.metadata.get().unwrap();
+        if let Some(localized) = self.localized_metadata.resolve(&locale) {
+            metadata.reference = Some(localized.reference.clone());
+            metadata.reference_hash = Some(localized.reference_hash.clone());
+        }
+        metadata
+    }
+
+    pub fn get_localized_references(&self) -> Vec<(String, LocalizedReference)> {
+        self.localized_metadata
+            .references
+            .iter()
+            .map(|(locale, reference)| (locale.clone(), reference.clone()))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_locale_falls_back_to_language_then_default() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        contract.set_localized_reference(
+            "pt".to_string(),
+            "ipfs://translated-pt".to_string(),
+            Base64VecU8(vec![7; 32]),
+        );
+
+        let metadata = contract.ft_metadata_localized("pt-BR".to_string());
+        assert_eq!(metadata.reference.unwrap(), "ipfs://translated-pt");
+        assert_eq!(metadata.symbol, "ASTRO");
+        let metadata = contract.ft_metadata_localized("ja".to_string());
+        assert!(metadata.reference.is_none());
+    }
+}
@@ -0,0 +1,186 @@
+/*!
+Direct distributions to very large recipient lists, paid out over many transactions.
+NOTES:
+  - For lists too large for one transaction, where a Merkle airdrop would make every recipient
+    claim. The owner or the DAO stages recipients with `load_distribution_chunk`, which locks the
+    chunk total from the caller's balance and charges the storage of the staged entries.
+  - `process_distribution` pays the next `limit` staged recipients, at most
+    `MAX_PROCESS_LIMIT` or fewer if the gas runs low, and moves the cursor past them. Anyone can
+    call it, so a bot can drive a distribution of 100k+ recipients across as many transactions
+    as it takes. Each entry is deleted as it is paid, so it can never be paid twice, and its
+    storage goes back to the funder.
+  - Recipients that are not registered when their turn comes are skipped and their amount is
+    returned to the funder.
+  - One distribution runs at a time. More chunks can be loaded while it runs, by the same funder.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance};
+
+use crate::gas_budget::GasBudget;
+use crate::*;
+
+const MAX_CHUNK_LEN: usize = 500;
+const MAX_PROCESS_LIMIT: u32 = 50;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct MassDistribution {
+    pub funder_id: Option<AccountId>,
+    pub entries: LookupMap<u64, (AccountId, Balance)>,
+    /// Entries staged so far.
+    pub loaded: u64,
+    /// Index of the next entry to pay.
+    pub cursor: u64,
+    /// Tokens staged and not paid yet.
+    pub pending: Balance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MassDistributionView {
+    pub funder_id: Option<AccountId>,
+    pub loaded: U64,
+    pub processed: U64,
+    pub pending: U128,
+}
+
+impl MassDistribution {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            funder_id: None,
+            entries: LookupMap::new(prefix),
+            loaded: 0,
+            cursor: 0,
+            pending: 0,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Stages `recipients` at the end of the distribution and locks their total from the caller.
+    /// The attached deposit covers the storage of the entries.
+    #[payable]
+    pub fn load_distribution_chunk(&mut self, recipients: Vec<(AccountId, U128)>) -> U64 {
+        self.assert_owner_or_dao();
+        self.assert_module_active(Module::Airdrops);
+        assert!(
+            !recipients.is_empty() && recipients.len() <= MAX_CHUNK_LEN,
+            "A chunk has 1 to {} recipients",
+            MAX_CHUNK_LEN
+        );
+        let funder_id = env::predecessor_account_id();
+        let distribution = &mut self.mass_distribution;
+        assert!(
+            distribution
+                .funder_id
+                .as_ref()
+                .is_none_or(|current| current == &funder_id),
+            "Another distribution is in progress"
+        );
+        distribution.funder_id = Some(funder_id.clone());
+        let initial_storage = env::storage_usage();
+        let mut total: Balance = 0;
+        for (account_id, amount) in recipients {
+            assert!(amount.0 > 0, "The amount should be a positive number");
+            total += amount.0;
+            distribution
+                .entries
+                .insert(distribution.loaded, (account_id, amount.0));
+            distribution.loaded += 1;
+        }
+        distribution.pending += total;
+        distribution.entries.flush();
+        self.internal_lock(&funder_id, total);
+        refund_deposit(env::storage_usage() - initial_storage);
+        log!(
+            "Staged {} for distribution, {} recipients in total",
+            total,
+            self.mass_distribution.loaded
+        );
+        self.mass_distribution.loaded.into()
+    }
+
+    /// Pays up to `limit` staged recipients and returns how many are left.
+    pub fn process_distribution(&mut self, limit: u32) -> U64 {
+        let funder_id = self
+            .mass_distribution
+            .funder_id
+            .clone()
+            .expect("No distribution is in progress");
+        let initial_storage = env::storage_usage();
+        let end = (self.mass_distribution.cursor + u64::from(limit.min(MAX_PROCESS_LIMIT)))
+            .min(self.mass_distribution.loaded);
+        let mut paid = 0;
+        let mut skipped = 0;
+        let mut budget = GasBudget::default();
+        while self.mass_distribution.cursor < end && budget.has_room() {
+            let index = self.mass_distribution.cursor;
+            let (account_id, amount) = self.mass_distribution.entries.remove(&index).unwrap();
+            self.mass_distribution.cursor += 1;
+            self.mass_distribution.pending -= amount;
+            if self.token.accounts.contains_key(&account_id) {
+                self.internal_unlock(&account_id, amount);
+                paid += amount;
+            } else {
+                skipped += amount;
+            }
+        }
+        if skipped > 0 {
+            self.internal_unlock(&funder_id, skipped);
+        }
+        let left = self.mass_distribution.loaded - self.mass_distribution.cursor;
+        if left == 0 {
+            self.mass_distribution.funder_id = None;
+        }
+        self.mass_distribution.entries.flush();
+        refund_released_storage(&funder_id, initial_storage);
+        log!(
+            "Distributed {}, returned {} of unregistered recipients, {} recipients left",
+            paid,
+            skipped,
+            left
+        );
+        left.into()
+    }
+
+    pub fn get_mass_distribution(&self) -> MassDistributionView {
+        let distribution = &self.mass_distribution;
+        MassDistributionView {
+            funder_id: distribution.funder_id.clone(),
+            loaded: distribution.loaded.into(),
+            processed: distribution.cursor.into(),
+            pending: distribution.pending.into(),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_distribution_resumes_from_cursor() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1), accounts(2)]);
+        contract.load_distribution_chunk(vec![(accounts(1), U128(10)), (accounts(2), U128(20))]);
+        contract.load_distribution_chunk(vec![(accounts(3), U128(30))]);
+
+        call_as(&mut context, accounts(4), 0);
+        assert_eq!(contract.process_distribution(2).0, 1);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 10);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 20);
+        assert_eq!(contract.process_distribution(2).0, 0);
+        // accounts(3) is not registered, its tokens went back to the funder.
+        assert_eq!(contract.ft_balance_of(owner).0, TOTAL_SUPPLY - 30);
+        let view = contract.get_mass_distribution();
+        assert_eq!(view.processed.0, 3);
+        assert_eq!(view.pending.0, 0);
+        assert!(view.funder_id.is_none());
+    }
+}
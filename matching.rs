@@ -0,0 +1,380 @@
+/*!
+Public goods funding rounds with quadratic matching.
+NOTES:
+  - The owner or the DAO opens a round for a fixed list of registered projects and seeds its
+    matching pool from the treasury. Anyone can add to the pool with `fund_matching_pool`.
+  - Until `ends_at` anyone can `contribute` to the projects of the round. Contributions are held by
+    the contract account until the round is finalized.
+  - After `ends_at` anyone can call `finalize_matching_round`. Each project receives its
+    contributions plus a share of the pool proportional to `(Σ√cᵢ)² - Σcᵢ`, where `cᵢ` are the
+    totals of its contributors. Many small contributions are matched more than a few large ones.
+  - The pool goes back to the treasury when nothing was contributed, as does the rounding dust.
+  - Contributors pay for the storage of their contribution records.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::pool_ledger::Pool;
+use crate::*;
+
+/// Maximum number of projects in a round, so a round can be finalized in one call.
+const MAX_PROJECTS: usize = 50;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MatchingRound {
+    pub projects: Vec<AccountId>,
+    pub matching_pool: U128,
+    pub ends_at: U64,
+    pub finalized: bool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Default)]
+pub struct ProjectTotals {
+    pub contributed: Balance,
+    /// Sum of the square roots of the contributor totals.
+    pub sum_sqrt: Balance,
+}
+
+impl ProjectTotals {
+    /// Quadratic matching weight `(Σ√cᵢ)² - Σcᵢ`. Cannot overflow: `(Σ√cᵢ)² <= n * Σcᵢ`.
+    fn weight(&self) -> Balance {
+        (self.sum_sqrt * self.sum_sqrt).saturating_sub(self.contributed)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProjectMatch {
+    pub project_id: AccountId,
+    pub contributed: U128,
+    /// Share of the matching pool, estimated until the round is finalized.
+    pub matched: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Matching {
+    pub rounds: LookupMap<u64, MatchingRound>,
+    pub totals: LookupMap<(u64, AccountId), ProjectTotals>,
+    /// Total contributed by a contributor to a project in a round.
+    pub contributions: LookupMap<(u64, AccountId, AccountId), Balance>,
+    pub next_round_id: u64,
+}
+
+impl Matching {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            rounds: LookupMap::new([prefix.as_slice(), b"r"].concat()),
+            totals: LookupMap::new([prefix.as_slice(), b"t"].concat()),
+            contributions: LookupMap::new([prefix.as_slice(), b"c"].concat()),
+            next_round_id: 0,
+        }
+    }
+
+    fn round(&self, round_id: u64) -> MatchingRound {
+        self.rounds
+            .get(&round_id)
+            .cloned()
+            .expect("Matching round not found")
+    }
+
+    fn project_totals(&self, round_id: u64, project_id: &AccountId) -> ProjectTotals {
+        self.totals
+            .get(&(round_id, project_id.clone()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Shares of the matching pool of each project, in round order.
+    fn matches(&self, round_id: u64, round: &MatchingRound) -> Vec<(ProjectTotals, Balance)> {
+        let totals: Vec<ProjectTotals> = round
+            .projects
+            .iter()
+            .map(|project_id| self.project_totals(round_id, project_id))
+            .collect();
+        let total_weight: Balance = totals.iter().map(ProjectTotals::weight).sum();
+        totals
+            .into_iter()
+            .map(|totals| {
+                let matched = if total_weight == 0 {
+                    0
+                } else {
+                    mul_div(round.matching_pool.0, totals.weight(), total_weight)
+                };
+                (totals, matched)
+            })
+            .collect()
+    }
+
+    fn flush(&mut self) {
+        self.rounds.flush();
+        self.totals.flush();
+        self.contributions.flush();
+    }
+}
+
+/// Integer square root, rounded down.
+fn isqrt(n: Balance) -> Balance {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Opens a round for `projects` until `ends_at`, with `matching_pool` taken from the treasury.
+    /// Returns the round id. The attached deposit covers the round storage.
+    #[payable]
+    pub fn create_matching_round(
+        &mut self,
+        projects: Vec<AccountId>,
+        matching_pool: U128,
+        ends_at: U64,
+    ) -> U64 {
+        self.assert_module_active(Module::Matching);
+        self.assert_owner_or_dao();
+        assert!(
+            !projects.is_empty() && projects.len() <= MAX_PROJECTS,
+            "A round has 1 to {} projects",
+            MAX_PROJECTS
+        );
+        for (i, project_id) in projects.iter().enumerate() {
+            assert!(
+                self.token.accounts.contains_key(project_id),
+                "The project {} is not registered",
+                project_id
+            );
+            assert!(
+                !projects[..i].contains(project_id),
+                "Duplicate project {}",
+                project_id
+            );
+        }
+        assert!(
+            ends_at.0 > env::block_timestamp(),
+            "The round must end in the future"
+        );
+        assert!(
+            matching_pool.0 <= self.treasury_balance,
+            "The treasury balance is {}",
+            self.treasury_balance
+        );
+        let initial_storage = env::storage_usage();
+        self.treasury_balance -= matching_pool.0;
+        self.pool_ledger.credit(Pool::Matching, matching_pool.0);
+        let round_id = self.matching.next_round_id;
+        self.matching.next_round_id += 1;
+        self.matching.rounds.insert(
+            round_id,
+            MatchingRound {
+                projects,
+                matching_pool,
+                ends_at,
+                finalized: false,
+            },
+        );
+        self.matching.flush();
+        self.scheduler.schedule(
+            ScheduledAction::FinalizeMatchingRound {
+                round_id: round_id.into(),
+            },
+            ends_at.0,
+        );
+        refund_deposit(env::storage_usage() - initial_storage);
+        log!(
+            "Matching round {} opened with a pool of {}",
+            round_id,
+            matching_pool.0
+        );
+        round_id.into()
+    }
+
+    /// Adds `amount` of the caller's tokens to the matching pool of an open round.
+    #[payable]
+    pub fn fund_matching_pool(&mut self, round_id: U64, amount: U128) {
+        assert_one_yocto();
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        let mut round = self.matching.round(round_id.0);
+        assert!(
+            env::block_timestamp() < round.ends_at.0,
+            "The round has ended"
+        );
+        let account_id = env::predecessor_account_id();
+        self.internal_lock_to(Pool::Matching, &account_id, amount.0);
+        round.matching_pool = (round.matching_pool.0 + amount.0).into();
+        self.matching.rounds.insert(round_id.0, round);
+        log!(
+            "@{} added {} to the pool of matching round {}",
+            account_id,
+            amount.0,
+            round_id.0
+        );
+    }
+
+    /// Contributes `amount` of the caller's tokens to `project_id` in an open round.
+    /// The attached deposit covers the contribution storage.
+    #[payable]
+    pub fn contribute(&mut self, round_id: U64, project_id: AccountId, amount: U128) {
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        let round = self.matching.round(round_id.0);
+        assert!(
+            env::block_timestamp() < round.ends_at.0,
+            "The round has ended"
+        );
+        assert!(
+            round.projects.contains(&project_id),
+            "The project {} is not in the round",
+            project_id
+        );
+        let initial_storage = env::storage_usage();
+        let contributor_id = env::predecessor_account_id();
+        self.internal_lock_to(Pool::Matching, &contributor_id, amount.0);
+        let key = (round_id.0, project_id.clone(), contributor_id.clone());
+        let previous = self.matching.contributions.get(&key).copied().unwrap_or(0);
+        let contribution = previous + amount.0;
+        self.matching.contributions.insert(key, contribution);
+        let mut totals = self.matching.project_totals(round_id.0, &project_id);
+        totals.contributed += amount.0;
+        totals.sum_sqrt = totals.sum_sqrt - isqrt(previous) + isqrt(contribution);
+        self.matching
+            .totals
+            .insert((round_id.0, project_id.clone()), totals);
+        self.matching.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        log!(
+            "@{} contributed {} to @{} in matching round {}",
+            contributor_id,
+            amount.0,
+            project_id,
+            round_id.0
+        );
+    }
+
+    /// Pays out the contributions and matches of an ended round.
+    pub fn finalize_matching_round(&mut self, round_id: U64) {
+        let mut round = self.matching.round(round_id.0);
+        assert!(
+            env::block_timestamp() >= round.ends_at.0,
+            "The round ends at {}",
+            round.ends_at.0
+        );
+        assert!(!round.finalized, "The round is already finalized");
+        let mut paid_from_pool = 0;
+        for (project_id, (totals, matched)) in round
+            .projects
+            .iter()
+            .zip(self.matching.matches(round_id.0, &round))
+        {
+            let amount = totals.contributed + matched;
+            if amount > 0 {
+                self.internal_unlock_from(Pool::Matching, project_id, amount);
+                log!(
+                    "@{} received {} in contributions and {} in matching from round {}",
+                    project_id,
+                    totals.contributed,
+                    matched,
+                    round_id.0
+                );
+            }
+            paid_from_pool += matched;
+        }
+        let unmatched = round.matching_pool.0 - paid_from_pool;
+        self.pool_ledger.debit(Pool::Matching, unmatched);
+        self.treasury_balance += unmatched;
+        round.finalized = true;
+        self.matching.rounds.insert(round_id.0, round);
+        self.scheduler
+            .unschedule(&ScheduledAction::FinalizeMatchingRound { round_id });
+        self.internal_pay_keeper();
+    }
+
+    pub fn get_matching_round(&self, round_id: U64) -> Option<MatchingRound> {
+        self.matching.rounds.get(&round_id.0).cloned()
+    }
+
+    /// Contributions and matches of each project of a round.
+    pub fn get_matching_results(&self, round_id: U64) -> Vec<ProjectMatch> {
+        let round = self.matching.round(round_id.0);
+        round
+            .projects
+            .iter()
+            .zip(self.matching.matches(round_id.0, &round))
+            .map(|(project_id, (totals, matched))| ProjectMatch {
+                project_id: project_id.clone(),
+                contributed: totals.contributed.into(),
+                matched: matched.into(),
+            })
+            .collect()
+    }
+
+    pub fn get_contribution(
+        &self,
+        round_id: U64,
+        project_id: AccountId,
+        contributor_id: AccountId,
+    ) -> U128 {
+        self.matching
+            .contributions
+            .get(&(round_id.0, project_id, contributor_id))
+            .copied()
+            .unwrap_or(0)
+            .into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    #[test]
+    fn test_isqrt() {
+        assert_eq!(isqrt(0), 0);
+        assert_eq!(isqrt(99), 9);
+        assert_eq!(isqrt(100), 10);
+        assert_eq!(isqrt(TOTAL_SUPPLY), 9_486_832_980_505);
+    }
+
+    #[test]
+    fn test_many_small_contributions_get_more_matching() {
+        let owner = owner();
+        let (mut context, mut contract) =
+            setup(&[accounts(1), accounts(2), accounts(3), accounts(4)]);
+        call_as(&mut context, owner.clone(), 1);
+        contract.ft_transfer(accounts(3), U128(100), None);
+        contract.ft_transfer(accounts(4), U128(100), None);
+        contract.treasury_deposit(U128(1_000));
+
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        let round_id =
+            contract.create_matching_round(vec![accounts(1), accounts(2)], U128(1_000), U64(100));
+        contract.contribute(round_id, accounts(1), U128(400));
+        for i in 3..5 {
+            testing_env!(context.predecessor_account_id(accounts(i)).build());
+            contract.contribute(round_id, accounts(2), U128(100));
+        }
+
+        testing_env!(context.block_timestamp(100).attached_deposit(0).build());
+        contract.finalize_matching_round(round_id);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 400);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 1_200);
+        assert_eq!(contract.get_treasury_balance().0, 0);
+    }
+}
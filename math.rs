@@ -0,0 +1,54 @@
+/*!
+Checked arithmetic on balances.
+NOTES:
+  - `mul_div` computes `a * b / c` with the product on 256 bits, so fees, reward-per-share
+    accumulators and curve prices cannot overflow in the intermediate product. Only a quotient
+    that does not fit in 128 bits panics.
+  - `checked_mul_div` returns `None` instead, and on a division by zero.
+*/
+
+/// `a * b / c` rounded down, with the product computed on 256 bits, or `None` if `c` is zero or
+/// the quotient overflows.
+pub(crate) fn checked_mul_div(a: u128, b: u128, c: u128) -> Option<u128> {
+    const LOW: u128 = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & LOW);
+    let (b_hi, b_lo) = (b >> 64, b & LOW);
+    let lo_lo = a_lo * b_lo;
+    let mid = a_hi * b_lo + (lo_lo >> 64);
+    let mid2 = a_lo * b_hi + (mid & LOW);
+    let hi = a_hi * b_hi + (mid >> 64) + (mid2 >> 64);
+    let lo = (mid2 << 64) | (lo_lo & LOW);
+    if hi >= c {
+        return None;
+    }
+    let (mut quotient, mut remainder) = (0u128, hi);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((lo >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+    Some(quotient)
+}
+
+/// `a * b / c` rounded down, with the product computed on 256 bits.
+pub(crate) fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    checked_mul_div(a, b, c).expect("Multiplication overflow")
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mul_div_beyond_128_bits() {
+        assert_eq!(mul_div(7, 3, 2), 10);
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), u128::MAX);
+        assert_eq!(mul_div(u128::MAX, 1 << 64, 1 << 65), u128::MAX >> 1);
+        assert_eq!(checked_mul_div(u128::MAX, 2, 1), None);
+        assert_eq!(checked_mul_div(1, 1, 0), None);
+    }
+}
@@ -0,0 +1,79 @@
+/*!
+Opt-in memo requirement for deposit accounts, e.g. exchange hot wallets.
+NOTES:
+  - An account calls `require_memo(true)` to reject every `ft_transfer` and `ft_transfer_call`
+    to it without a memo, so no deposit reaches it without a tag to attribute it. Transfers made
+    on behalf of a holder, e.g. through allowances, follow the same rule.
+  - The account pays for the storage of its flag and gets it back with `require_memo(false)`.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::store::LookupSet;
+use near_sdk::{env, log, near_bindgen, AccountId};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct MemoRequirements {
+    pub accounts: LookupSet<AccountId>,
+}
+
+impl MemoRequirements {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            accounts: LookupSet::new(prefix),
+        }
+    }
+
+    pub fn assert_memo(&self, receiver_id: &AccountId, memo: &Option<String>) {
+        assert!(
+            memo.as_ref().is_some_and(|memo| !memo.trim().is_empty())
+                || !self.accounts.contains(receiver_id),
+            "Transfers to {} require a memo",
+            receiver_id
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets whether transfers to the caller need a memo. Requiring one takes a deposit for the
+    /// storage of the flag, the excess is refunded.
+    #[payable]
+    pub fn require_memo(&mut self, required: bool) {
+        let account_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        if required {
+            self.memo_requirements.accounts.insert(account_id.clone());
+            refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        } else {
+            self.memo_requirements.accounts.remove(&account_id);
+            refund_released_storage(&account_id, initial_storage);
+        }
+        log!("Transfers to @{} require a memo: {}", account_id, required);
+    }
+
+    pub fn is_memo_required(&self, account_id: AccountId) -> bool {
+        self.memo_requirements.accounts.contains(&account_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::accounts;
+
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    #[test]
+    #[should_panic(expected = "require a memo")]
+    fn test_transfer_without_memo_is_rejected() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        call_as(&mut context, accounts(1), ONE_NEAR);
+        contract.require_memo(true);
+        call_as(&mut context, owner(), 1);
+        contract.ft_transfer(accounts(1), U128(10), Some("user 42".to_string()));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 10);
+        contract.ft_transfer(accounts(1), U128(10), None);
+    }
+}
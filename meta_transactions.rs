@@ -0,0 +1,359 @@
+/*!
+Gasless transfers signed by the sender and submitted by a relayer.
+NOTES:
+  - An account registers an ed25519 public key with `set_signing_key`. Any relayer can then
+    submit a `SignedTransfer` signed with that key to `ft_transfer_signed`, paying the gas so the
+    sender needs no NEAR.
+  - The signed message is the Borsh serialization of the contract account id followed by the
+    payload, so a signature is only valid on this contract.
+  - Each payload carries a nonce that must be higher than the last one used by the sender, which
+    prevents replays, and a deadline after which it is rejected.
+  - Signed transfers go through the same checks, fees and hooks as `ft_transfer`.
+  - Implicit accounts without NEAR are registered by a relayer with `storage_deposit_signed`: the
+    account signs a `SignedRegistration` with the key its id derives from, and the relayer submits
+    it with the storage deposit attached. The key becomes the signing key of the account if it
+    has none, so the onboarding relayer can submit its transfers as well. Named accounts cannot
+    prove their keys to the contract and register as usual.
+*/
+use std::convert::{TryFrom, TryInto};
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::LookupMap;
+use near_sdk::{
+    assert_one_yocto, env, log, near_bindgen, AccountId, Balance, CurveType, Promise, PublicKey,
+};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SignedTransfer {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub nonce: U64,
+    /// Timestamp in nanoseconds after which the transfer is rejected.
+    pub deadline: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SignedRegistration {
+    /// Implicit account to register.
+    pub account_id: AccountId,
+    /// Timestamp in nanoseconds after which the registration is rejected.
+    pub deadline: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct SigningKey {
+    pub public_key: PublicKey,
+    /// Last nonce used with this key.
+    pub nonce: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct MetaTransactions {
+    pub signing_keys: LookupMap<AccountId, SigningKey>,
+    /// Last nonce of the accounts that removed their signing key.
+    pub removed_nonces: LookupMap<AccountId, u64>,
+}
+
+impl MetaTransactions {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            // No borsh account id starts with `n`, a length above 64.
+            removed_nonces: LookupMap::new([prefix.as_slice(), b"n"].concat()),
+            signing_keys: LookupMap::new(prefix),
+        }
+    }
+
+    /// Last nonce used by `account_id`, with or without a signing key.
+    fn nonce(&self, account_id: &AccountId) -> u64 {
+        self.signing_keys
+            .get(account_id)
+            .map(|key| key.nonce)
+            .or_else(|| self.removed_nonces.get(account_id).copied())
+            .unwrap_or(0)
+    }
+
+    /// Sets the signing key of `account_id`, carrying over its last nonce.
+    fn set_key(&mut self, account_id: &AccountId, public_key: PublicKey) {
+        let nonce = self.nonce(account_id);
+        self.removed_nonces.remove(account_id);
+        self.signing_keys
+            .insert(account_id.clone(), SigningKey { public_key, nonce });
+        self.removed_nonces.flush();
+        self.signing_keys.flush();
+    }
+}
+
+/// The ed25519 key of an implicit account, whose id is the hex encoding of the key.
+fn implicit_account_key(account_id: &AccountId) -> Option<[u8; 32]> {
+    let hex = account_id.as_str();
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut key = [0u8; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(key)
+}
+
+/// Whether `signature` is a valid ed25519 signature of `message` by `public_key`. `near-sdk` 4.1
+/// has no host function for it, so the signature is checked in contract code.
+pub(crate) fn ed25519_verify(signature: &[u8; 64], message: &[u8], public_key: &[u8; 32]) -> bool {
+    let public_key = match ed25519_dalek::PublicKey::from_bytes(public_key) {
+        Ok(public_key) => public_key,
+        Err(_) => return false,
+    };
+    match ed25519_dalek::Signature::from_bytes(signature) {
+        Ok(signature) => public_key.verify_strict(message, &signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Panics unless `signature` is the signature of `payload` on this contract by `public_key`.
+fn assert_signed<T: BorshSerialize>(payload: &T, signature: Base64VecU8, public_key: &[u8; 32]) {
+    let signature: [u8; 64] = signature
+        .0
+        .as_slice()
+        .try_into()
+        .expect("The signature must be 64 bytes");
+    let message = (env::current_account_id(), payload).try_to_vec().unwrap();
+    assert!(
+        ed25519_verify(&signature, &message, public_key),
+        "Invalid signature"
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the ed25519 key that signs the caller's transfers. The nonce carries over from a
+    /// previous key. The attached deposit covers the key storage.
+    #[payable]
+    pub fn set_signing_key(&mut self, public_key: PublicKey) {
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        assert!(
+            public_key.curve_type() == CurveType::ED25519,
+            "Only ed25519 keys are supported"
+        );
+        let account_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        self.meta_transactions.set_key(&account_id, public_key);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        log!("@{} set a signing key", account_id);
+    }
+
+    /// Removes the caller's signing key, invalidating every signed transfer not yet submitted.
+    /// The last nonce is kept, so setting a key again does not revive those transfers.
+    #[payable]
+    pub fn remove_signing_key(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        let key = self
+            .meta_transactions
+            .signing_keys
+            .remove(&account_id)
+            .expect("No signing key set");
+        self.meta_transactions
+            .removed_nonces
+            .insert(account_id.clone(), key.nonce);
+        self.meta_transactions.signing_keys.flush();
+        self.meta_transactions.removed_nonces.flush();
+        refund_released_storage(&account_id, initial_storage);
+        log!("@{} removed their signing key", account_id);
+    }
+
+    /// Transfers tokens on behalf of `payload.sender_id`, who signed the payload.
+    pub fn ft_transfer_signed(&mut self, payload: SignedTransfer, signature: Base64VecU8) {
+        let sender_id = payload.sender_id.clone();
+        let mut key = self
+            .meta_transactions
+            .signing_keys
+            .get(&sender_id)
+            .cloned()
+            .expect("No signing key set for the sender");
+        assert!(
+            env::block_timestamp() <= payload.deadline.0,
+            "The signed transfer has expired"
+        );
+        assert!(
+            payload.nonce.0 > key.nonce,
+            "The nonce must be higher than {}",
+            key.nonce
+        );
+        let public_key: [u8; 32] = key.public_key.as_bytes()[1..].try_into().unwrap();
+        assert_signed(&payload, signature, &public_key);
+        key.nonce = payload.nonce.0;
+        self.meta_transactions
+            .signing_keys
+            .insert(sender_id.clone(), key);
+        self.internal_ft_transfer(&sender_id, payload.receiver_id, payload.amount.0, None);
+    }
+
+    /// Registers `payload.account_id`, which signed the payload, with the caller's attached
+    /// deposit. The excess deposit is refunded to the caller.
+    #[payable]
+    pub fn storage_deposit_signed(&mut self, payload: SignedRegistration, signature: Base64VecU8) {
+        let account_id = payload.account_id.clone();
+        let public_key = implicit_account_key(&account_id)
+            .expect("Only implicit accounts can register with a signature");
+        assert!(
+            env::block_timestamp() <= payload.deadline.0,
+            "The signed registration has expired"
+        );
+        assert_signed(&payload, signature, &public_key);
+        assert!(
+            !self.token.accounts.contains_key(&account_id),
+            "The account is already registered"
+        );
+        let required = self.internal_paid_registration(&account_id);
+        let initial_storage = env::storage_usage();
+        if !self
+            .meta_transactions
+            .signing_keys
+            .contains_key(&account_id)
+        {
+            let public_key = PublicKey::try_from([&[0u8][..], &public_key[..]].concat()).unwrap();
+            self.meta_transactions.set_key(&account_id, public_key);
+        }
+        let key_storage = env::storage_usage().saturating_sub(initial_storage);
+        let required = required + env::storage_byte_cost() * Balance::from(key_storage);
+        assert!(
+            env::attached_deposit() >= required,
+            "Must attach {} yoctoNEAR to cover storage",
+            required
+        );
+        let refund = env::attached_deposit() - required;
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+        log!(
+            "@{} registered @{} with a signature",
+            env::predecessor_account_id(),
+            account_id
+        );
+    }
+
+    pub fn get_signing_key(&self, account_id: AccountId) -> Option<PublicKey> {
+        self.meta_transactions
+            .signing_keys
+            .get(&account_id)
+            .map(|key| key.public_key.clone())
+    }
+
+    pub fn get_transfer_nonce(&self, account_id: AccountId) -> U64 {
+        self.meta_transactions.nonce(&account_id).into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    /// Signature of `transfer()` with the RFC 8032 test key 1, whose public key is below.
+    const SIGNATURE: &str =
+        "wK4KpwDAUA+ihqb1d79y2gDsxogXLv4pi+r7I5w/b/x9NRsLrzY3lovzNHgmzNDlqpq7LR4UEudYEydnMbg+CQ==";
+    const PUBLIC_KEY: &str = "ed25519:FVen3X669xLzsi6N2V91DoiyzHzg1uAgqiT8jZ9nS96Z";
+    /// Implicit account of the test key and its signature of a registration until 100.
+    const IMPLICIT_ACCOUNT: &str =
+        "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const REGISTRATION_SIGNATURE: &str =
+        "W/CO7RU17gZ/g4rTtoPDAIDdxPrweHlovA3NyETzAS+OJ9huhFxRx+c6HXzEY+gvw9c7Klbn2Vn7V/m+bByoDQ==";
+
+    fn signature_of(signature: &str) -> Base64VecU8 {
+        near_sdk::serde_json::from_str(&format!("\"{}\"", signature)).unwrap()
+    }
+
+    fn signature() -> Base64VecU8 {
+        signature_of(SIGNATURE)
+    }
+
+    fn transfer() -> SignedTransfer {
+        SignedTransfer {
+            sender_id: accounts(1),
+            receiver_id: accounts(2),
+            amount: U128(1_000),
+            nonce: U64(1),
+            deadline: U64(100),
+        }
+    }
+
+    fn setup() -> Contract {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, accounts(1), ONE_NEAR);
+        contract.set_signing_key(PUBLIC_KEY.parse().unwrap());
+        call_as(&mut context, owner(), 1);
+        contract.ft_transfer(accounts(1), U128(5_000), None);
+        // The relayer attaches nothing.
+        call_as(&mut context, accounts(3), 0);
+        contract
+    }
+
+    #[test]
+    fn test_signed_transfer() {
+        let mut contract = setup();
+        contract.ft_transfer_signed(transfer(), signature());
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 1_000);
+        assert_eq!(contract.get_transfer_nonce(accounts(1)).0, 1);
+    }
+
+    #[test]
+    fn test_signed_registration() {
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, accounts(3), ONE_NEAR);
+        let account_id: AccountId = IMPLICIT_ACCOUNT.parse().unwrap();
+        contract.storage_deposit_signed(
+            SignedRegistration {
+                account_id: account_id.clone(),
+                deadline: U64(100),
+            },
+            signature_of(REGISTRATION_SIGNATURE),
+        );
+        assert!(contract.storage_balance_of(account_id.clone()).is_some());
+        assert_eq!(
+            contract.get_signing_key(account_id),
+            Some(PUBLIC_KEY.parse().unwrap())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The nonce must be higher than 1")]
+    fn test_signed_transfer_cannot_be_replayed() {
+        let mut contract = setup();
+        contract.ft_transfer_signed(transfer(), signature());
+        contract.ft_transfer_signed(transfer(), signature());
+    }
+
+    #[test]
+    #[should_panic(expected = "The nonce must be higher than 1")]
+    fn test_signed_transfer_cannot_be_replayed_after_key_reset() {
+        let mut contract = setup();
+        contract.ft_transfer_signed(transfer(), signature());
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1);
+        testing_env!(context.build());
+        contract.remove_signing_key();
+        assert_eq!(contract.get_transfer_nonce(accounts(1)).0, 1);
+        testing_env!(context
+            .attached_deposit(1_000_000_000_000_000_000_000)
+            .build());
+        contract.set_signing_key(PUBLIC_KEY.parse().unwrap());
+        contract.ft_transfer_signed(transfer(), signature());
+    }
+}
@@ -0,0 +1,180 @@
+/*!
+Dual control over the token name and symbol.
+NOTES:
+  - Exchanges and wallets key their listings on the symbol, so a unilateral change would break
+    tickers. A name or symbol change is proposed by the owner or the DAO, confirmed by the other
+    one, and can only be applied `METADATA_CHANGE_DELAY` after the confirmation.
+  - The proposal and the confirmation are logged as NEP-297 events, so integrators are warned
+    before the change takes effect. Either role can cancel a pending change.
+  - Icon, reference and decimals changes stay with the owner alone, see `update_metadata`.
+  - Governance proposals changing the name or symbol need no confirmation, since the vote is
+    public, but they replace any pending change and are applied after the same delay.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+/// Delay of 7 days in nanoseconds between the confirmation and the application of a change.
+pub const METADATA_CHANGE_DELAY: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingMetadataChange {
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub proposer_id: AccountId,
+    /// Set once the other role has confirmed: the time from which the change can be applied.
+    pub effective_at: Option<U64>,
+}
+
+fn emit_metadata_event(event: &str, change: &PendingMetadataChange) {
+    emit_event(
+        EVENT_VERSION,
+        event,
+        json!({
+            "name": change.name,
+            "symbol": change.symbol,
+            "proposer_id": change.proposer_id,
+            "effective_at": change.effective_at,
+        }),
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Proposes a new name and/or symbol, replacing any pending change. Requires a DAO distinct
+    /// from the owner, since the change must be confirmed by the other role.
+    pub fn propose_metadata_change(&mut self, name: Option<String>, symbol: Option<String>) {
+        self.assert_owner_or_dao();
+        assert!(
+            matches!(&self.dao_id, Some(dao_id) if dao_id != &self.owner_id),
+            "Dual control requires a DAO distinct from the owner"
+        );
+        assert!(name.is_some() || symbol.is_some(), "Nothing to change");
+        let change = PendingMetadataChange {
+            name,
+            symbol,
+            proposer_id: env::predecessor_account_id(),
+            effective_at: None,
+        };
+        emit_metadata_event("metadata_change_propose", &change);
+        self.pending_metadata_change = Some(change);
+    }
+
+    /// Confirms the pending change. Must be called by the role that did not propose it.
+    pub fn confirm_metadata_change(&mut self) {
+        self.assert_owner_or_dao();
+        let mut change = self
+            .pending_metadata_change
+            .clone()
+            .expect("No pending metadata change");
+        assert_ne!(
+            env::predecessor_account_id(),
+            change.proposer_id,
+            "The change must be confirmed by the other role"
+        );
+        assert!(change.effective_at.is_none(), "Already confirmed");
+        change.effective_at = Some((env::block_timestamp() + METADATA_CHANGE_DELAY).into());
+        emit_metadata_event("metadata_change_announce", &change);
+        self.pending_metadata_change = Some(change);
+    }
+
+    pub fn cancel_metadata_change(&mut self) {
+        self.assert_owner_or_dao();
+        let change = self
+            .pending_metadata_change
+            .take()
+            .expect("No pending metadata change");
+        emit_metadata_event("metadata_change_cancel", &change);
+    }
+
+    /// Applies a confirmed change once its delay has passed. Callable by anyone.
+    pub fn apply_metadata_change(&mut self) {
+        let change = self
+            .pending_metadata_change
+            .clone()
+            .expect("No pending metadata change");
+        let effective_at = change
+            .effective_at
+            .expect("The change is not confirmed yet");
+        assert!(
+            env::block_timestamp() >= effective_at.0,
+            "The change is effective from {}",
+            effective_at.0
+        );
+        self.pending_metadata_change = None;
+        self.internal_update_metadata(
+            change.name.clone(),
+            change.symbol.clone(),
+            None,
+            None,
+            None,
+            None,
+        );
+        emit_metadata_event("metadata_change_apply", &change);
+    }
+
+    pub fn get_pending_metadata_change(&self) -> Option<PendingMetadataChange> {
+        self.pending_metadata_change.clone()
+    }
+}
+
+impl Contract {
+    /// Announces a name and/or symbol change confirmed by a governance vote, applicable after
+    /// the delay.
+    pub(crate) fn internal_announce_metadata_change(
+        &mut self,
+        name: Option<String>,
+        symbol: Option<String>,
+    ) {
+        let change = PendingMetadataChange {
+            name,
+            symbol,
+            proposer_id: env::current_account_id(),
+            effective_at: Some((env::block_timestamp() + METADATA_CHANGE_DELAY).into()),
+        };
+        emit_metadata_event("metadata_change_announce", &change);
+        self.pending_metadata_change = Some(change);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::metadata::FungibleTokenMetadataProvider;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, owner(), 0);
+        contract.set_dao(Some(accounts(1)));
+        contract.propose_metadata_change(None, Some("ASTRO2".to_string()));
+        (context, contract)
+    }
+
+    #[test]
+    fn test_confirmed_change_applies_after_delay() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.confirm_metadata_change();
+        testing_env!(context.block_timestamp(METADATA_CHANGE_DELAY).build());
+        contract.apply_metadata_change();
+        assert_eq!(contract.ft_metadata().symbol, "ASTRO2");
+        assert!(contract.get_pending_metadata_change().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The change must be confirmed by the other role")]
+    fn test_proposer_cannot_confirm() {
+        let (_, mut contract) = setup();
+        contract.confirm_metadata_change();
+    }
+}
@@ -0,0 +1,222 @@
+/*!
+Validation of the icon and reference in the token metadata.
+NOTES:
+  - Wallets render the icon inline, and every byte of it is contract state. An icon must be a
+    `data:image/...` URL of at most `max_icon_bytes`, which the owner can set up to
+    `MAX_ICON_BYTES_LIMIT`. The default leaves room for the initial icon.
+  - Larger artwork belongs in the reference document: `set_reference` takes an `https://` or
+    `ipfs://` URL and the sha256 hash of the document, which must be 32 bytes.
+  - `update_image_or_reference` opts into moving an oversized icon there: it takes the icon and
+    the URL hosting it, and an icon over the limit is not stored. The metadata points to the URL
+    with the sha256 hash of the icon instead, and the previous icon is cleared.
+  - The name and the symbol cannot be empty and the decimals cannot exceed `MAX_DECIMALS`.
+  - The checks apply to every path that changes the metadata: `update_image`, `update_metadata`,
+    governance proposals and the overrides given to `new_with_config`.
+*/
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, log, near_bindgen};
+
+use crate::*;
+
+pub const DEFAULT_MAX_ICON_BYTES: u32 = 16_384;
+pub const MAX_ICON_BYTES_LIMIT: u32 = 65_536;
+const MAX_DECIMALS: u8 = 24;
+const MAX_REFERENCE_LEN: usize = 256;
+const REFERENCE_HASH_LEN: usize = 32;
+
+/// Metadata fields replacing the defaults in `new_with_config`.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(crate = "near_sdk::serde", deny_unknown_fields)]
+pub struct MetadataOverrides {
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub icon: Option<String>,
+    pub reference: Option<String>,
+    pub reference_hash: Option<Base64VecU8>,
+    pub decimals: Option<u8>,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_max_icon_bytes(&mut self, max_icon_bytes: u32) {
+        self.assert_owner();
+        assert!(
+            max_icon_bytes > 0 && max_icon_bytes <= MAX_ICON_BYTES_LIMIT,
+            "The icon limit is 1 to {} bytes",
+            MAX_ICON_BYTES_LIMIT
+        );
+        self.max_icon_bytes = max_icon_bytes;
+        log!("Icons are limited to {} bytes", max_icon_bytes);
+    }
+
+    pub fn get_max_icon_bytes(&self) -> u32 {
+        self.max_icon_bytes
+    }
+
+    /// Points the metadata to the reference document at `url` with sha256 hash `hash`.
+    pub fn set_reference(&mut self, url: String, hash: Base64VecU8) {
+        self.assert_owner();
+        self.internal_update_metadata(None, None, None, Some(url), Some(hash), None);
+    }
+
+    /// Sets the icon like `update_image`, or moves it to the reference `url` hosting it if it is
+    /// over the limit. Returns whether the icon was moved.
+    pub fn update_image_or_reference(&mut self, image: String, url: String) -> bool {
+        self.assert_owner();
+        if image.len() <= self.max_icon_bytes as usize {
+            self.internal_update_metadata(None, None, Some(image), None, None, None);
+            return false;
+        }
+        assert!(
+            image.starts_with("data:image/") && image.contains(','),
+            "The icon must be a data:image URL"
+        );
+        let hash = Base64VecU8(env::sha256(image.as_bytes()));
+        self.internal_update_metadata(None, None, None, Some(url), Some(hash), None);
+        let mut metadata = self.metadata.get().unwrap();
+        metadata.icon = None;
+        self.metadata.set(&metadata);
+        log!(
+            "The icon of {} bytes was moved to the reference",
+            image.len()
+        );
+        true
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_valid_icon(&self, icon: &str) {
+        assert!(
+            icon.starts_with("data:image/") && icon.contains(','),
+            "The icon must be a data:image URL"
+        );
+        assert!(
+            icon.len() <= self.max_icon_bytes as usize,
+            "The icon is {} bytes, over the limit of {}",
+            icon.len(),
+            self.max_icon_bytes
+        );
+    }
+}
+
+pub(crate) fn assert_valid_fields(metadata: &FungibleTokenMetadata) {
+    assert!(!metadata.name.trim().is_empty(), "The name cannot be empty");
+    assert!(
+        !metadata.symbol.trim().is_empty(),
+        "The symbol cannot be empty"
+    );
+    assert!(
+        metadata.decimals <= MAX_DECIMALS,
+        "The decimals cannot exceed {}",
+        MAX_DECIMALS
+    );
+}
+
+pub(crate) fn assert_valid_reference(reference: &str, reference_hash: &Base64VecU8) {
+    assert!(
+        (reference.starts_with("https://") || reference.starts_with("ipfs://"))
+            && reference.len() <= MAX_REFERENCE_LEN,
+        "The reference must be an https:// or ipfs:// URL of at most {} bytes",
+        MAX_REFERENCE_LEN
+    );
+    assert_eq!(
+        reference_hash.0.len(),
+        REFERENCE_HASH_LEN,
+        "The reference hash must be a sha256 hash"
+    );
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::metadata::FungibleTokenMetadataProvider;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    fn setup() -> Contract {
+        let (mut context, contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, owner(), 0);
+        contract
+    }
+
+    #[test]
+    #[should_panic(expected = "The icon is 109 bytes, over the limit of 100")]
+    fn test_icon_limit() {
+        let mut contract = setup();
+        contract.set_max_icon_bytes(100);
+        contract.update_image(format!("data:image/svg+xml,{}", "a".repeat(80)));
+        assert_eq!(contract.ft_metadata().icon.unwrap().len(), 99);
+        contract.update_image(format!("data:image/svg+xml,{}", "a".repeat(90)));
+    }
+
+    #[test]
+    fn test_oversized_icon_moves_to_the_reference() {
+        let mut contract = setup();
+        contract.set_max_icon_bytes(100);
+        let url = "https://astro.example/icon.svg".to_string();
+        let small = format!("data:image/svg+xml,{}", "a".repeat(80));
+        assert!(!contract.update_image_or_reference(small.clone(), url.clone()));
+        assert_eq!(contract.ft_metadata().icon, Some(small));
+
+        let large = format!("data:image/svg+xml,{}", "a".repeat(90));
+        assert!(contract.update_image_or_reference(large.clone(), url.clone()));
+        let metadata = contract.ft_metadata();
+        assert_eq!(metadata.icon, None);
+        assert_eq!(metadata.reference, Some(url));
+        assert_eq!(
+            metadata.reference_hash.unwrap().0,
+            env::sha256(large.as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_new_with_config() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id(accounts(0));
+        testing_env!(context.build());
+        let contract = Contract::new_with_config(
+            accounts(1),
+            U128(1_000),
+            MetadataOverrides {
+                symbol: Some("tASTRO".to_string()),
+                decimals: Some(6),
+                ..Default::default()
+            },
+        );
+        let metadata = contract.ft_metadata();
+        assert_eq!(metadata.symbol, "tASTRO");
+        assert_eq!(metadata.decimals, 6);
+        assert_eq!(metadata.name, "AV TOKEN");
+        assert_eq!(contract.ft_total_supply().0, 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "The decimals cannot exceed 24")]
+    fn test_decimals_limit() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id(accounts(0));
+        testing_env!(context.build());
+        Contract::new_with_config(
+            accounts(1),
+            U128(1_000),
+            MetadataOverrides {
+                decimals: Some(25),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The reference hash must be a sha256 hash")]
+    fn test_reference_hash_length() {
+        let mut contract = setup();
+        contract.set_reference(
+            "https://astro.example/token.json".to_string(),
+            Base64VecU8(vec![0; 20]),
+        );
+    }
+}
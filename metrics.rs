@@ -0,0 +1,95 @@
+/*!
+Counters kept in state for off-chain monitoring.
+NOTES:
+  - `get_metrics` returns a flat snapshot an exporter can scrape once per block instead of
+    aggregating events. Counters only grow, like Prometheus counters.
+  - Transfers are the user transfers: `ft_transfer`, `ft_transfer_call` and transfers made with
+    their checks on behalf of a holder. Transfers held for KYC are counted when released.
+  - A transfer call failure is one that emitted `transfer_call_failure`.
+  - Mints and burns count the token movements that changed the total supply, as recorded by
+    `with_checkpoints`, whatever subsystem made them.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, Balance};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct Metrics {
+    pub transfers: u64,
+    pub transfer_call_failures: u64,
+    pub mints: u64,
+    pub burns: u64,
+}
+
+impl Metrics {
+    /// Counts a mint or a burn from a change of the total supply.
+    pub fn record_supply_change(&mut self, previous: Balance, total_supply: Balance) {
+        if total_supply > previous {
+            self.mints += 1;
+        } else if total_supply < previous {
+            self.burns += 1;
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MetricsSnapshot {
+    pub block_height: U64,
+    pub timestamp: U64,
+    pub total_supply: U128,
+    pub transfers_total: U64,
+    pub transfer_call_failures_total: U64,
+    pub mints_total: U64,
+    pub burns_total: U64,
+    pub registered_accounts: u32,
+    pub paused: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_metrics(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            block_height: env::block_height().into(),
+            timestamp: env::block_timestamp().into(),
+            total_supply: self.internal_total_supply().into(),
+            transfers_total: self.metrics.transfers.into(),
+            transfer_call_failures_total: self.metrics.transfer_call_failures.into(),
+            mints_total: self.metrics.mints.into(),
+            burns_total: self.metrics.burns.into(),
+            registered_accounts: self.registered_accounts.len(),
+            paused: self.get_pause().is_some(),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_metrics_count_transfers_and_pause() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        let registered_accounts = contract.get_metrics().registered_accounts;
+
+        call_as(&mut context, owner(), 1);
+        contract.ft_transfer(accounts(1), U128(10), None);
+        contract.ft_transfer(accounts(1), U128(10), None);
+        testing_env!(context.attached_deposit(0).build());
+        contract.pause(None);
+
+        let metrics = contract.get_metrics();
+        assert_eq!(metrics.transfers_total.0, 2);
+        assert_eq!(metrics.burns_total.0, 0);
+        assert_eq!(metrics.registered_accounts, registered_accounts);
+        assert!(metrics.paused);
+    }
+}
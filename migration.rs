@@ -0,0 +1,253 @@
+/*!
+Versioned contract state and the `migrate` entrypoint.
+NOTES:
+  - The state version is kept under its own storage key next to the contract state, because the
+    originally deployed state has no version tag. A missing key means version 1.
+  - To change the `Contract` layout: freeze the current layout as `ContractVN`, add a variant to
+    `VersionedContract`, convert it in `into_current` and bump `CURRENT_STATE_VERSION`.
+  - Version 2 added staking, the mint queue, payment links, streams and escrows. Version 3 is
+    the current layout; the subsystems it added start empty on migrated deployments.
+  - After deploying new code, call `migrate` from the contract account itself.
+  - V1 and V2 deployments were administered by the hardcoded `OWNER_ID`, which becomes their
+    stored owner.
+  - V1 and V2 collections use the ASCII prefixes `a` to `t`, and the `STATE` key starts with
+    `S`. `StorageKey` prefixes are single bytes, so it must stay under 83 variants.
+*/
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_contract_standards::fungible_token::FungibleToken;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LazyOption;
+use near_sdk::store::LookupMap;
+use near_sdk::{env, near_bindgen, AccountId, Balance, CryptoHash};
+
+use crate::escrow::Escrow;
+use crate::mint_requests::MintQueue;
+use crate::payment_links::PaymentLink;
+use crate::reward_tokens::RewardTokens;
+use crate::staking::{StakerAccount, StakingPool};
+use crate::streams::Stream;
+use crate::*;
+
+const STATE_KEY: &[u8] = b"STATE";
+const STATE_VERSION_KEY: &[u8] = b"VERSION";
+pub const CURRENT_STATE_VERSION: u8 = 3;
+/// Prefix of the V2 staking accounts. Reward tokens go under `sr`: no borsh account id starts
+/// with `r`, a length above 64.
+const V2_STAKING_PREFIX: &[u8] = b"s";
+
+/// State layout of the initial deployment: a plain NEP-141 token.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+    pub token: FungibleToken,
+    pub metadata: LazyOption<FungibleTokenMetadata>,
+}
+
+/// Staking pool of the V2 layout, before reward tokens and the circuit breaker.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StakingPoolV2 {
+    pub accounts: LookupMap<AccountId, StakerAccount>,
+    pub total_staked: Balance,
+    pub reward_pool: Balance,
+    pub reward_rate: Balance,
+    pub acc_reward_per_share: Balance,
+    pub last_update_timestamp: u64,
+}
+
+/// State layout with staking, the mint queue, payment links, streams and escrows.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV2 {
+    pub token: FungibleToken,
+    pub metadata: LazyOption<FungibleTokenMetadata>,
+    pub staking: StakingPoolV2,
+    pub mint_queue: MintQueue,
+    pub payment_links: LookupMap<CryptoHash, PaymentLink>,
+    pub streams: LookupMap<u64, Stream>,
+    pub next_stream_id: u64,
+    pub escrows: LookupMap<u64, Escrow>,
+    pub next_escrow_id: u64,
+    pub has_transfers: bool,
+}
+
+pub enum VersionedContract {
+    V1(ContractV1),
+    V2(Box<ContractV2>),
+    V3(Box<Contract>),
+}
+
+impl VersionedContract {
+    /// Reads the stored state using the layout of its recorded version.
+    pub fn read() -> Self {
+        let state = env::storage_read(STATE_KEY).expect("The contract is not initialized");
+        match read_state_version() {
+            1 => Self::V1(ContractV1::try_from_slice(&state).expect("Cannot read V1 state")),
+            2 => Self::V2(Box::new(
+                ContractV2::try_from_slice(&state).expect("Cannot read V2 state"),
+            )),
+            3 => Self::V3(Box::new(
+                Contract::try_from_slice(&state).expect("Cannot read V3 state"),
+            )),
+            version => env::panic_str(&format!("Unknown state version {}", version)),
+        }
+    }
+
+    pub fn into_current(self) -> Contract {
+        match self {
+            Self::V1(state) => {
+                let mut contract =
+                    Contract::with_token(OWNER_ID.parse().unwrap(), state.token, state.metadata);
+                // Tokens have been circulating on V1 deployments.
+                contract.has_transfers = true;
+                contract
+            }
+            Self::V2(state) => {
+                let state = *state;
+                let mut contract =
+                    Contract::with_token(OWNER_ID.parse().unwrap(), state.token, state.metadata);
+                let staking = state.staking;
+                contract.staking = StakingPool {
+                    accounts: staking.accounts,
+                    total_staked: staking.total_staked,
+                    reward_pool: staking.reward_pool,
+                    reward_rate: staking.reward_rate,
+                    acc_reward_per_share: staking.acc_reward_per_share,
+                    last_update_timestamp: staking.last_update_timestamp,
+                    reward_tokens: RewardTokens::new([V2_STAKING_PREFIX, b"r"].concat()),
+                    rewards_halted: false,
+                };
+                contract.mint_queue = state.mint_queue;
+                contract.payment_links = state.payment_links;
+                contract.streams = state.streams;
+                contract.next_stream_id = state.next_stream_id;
+                contract.escrows = state.escrows;
+                contract.next_escrow_id = state.next_escrow_id;
+                contract.has_transfers = state.has_transfers;
+                contract
+            }
+            Self::V3(contract) => *contract,
+        }
+    }
+}
+
+pub fn read_state_version() -> u8 {
+    env::storage_read(STATE_VERSION_KEY)
+        .map(|version| version[0])
+        .unwrap_or(1)
+}
+
+pub fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &[CURRENT_STATE_VERSION]);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Upgrades the stored state to the current layout. Only callable by the contract itself.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let contract = VersionedContract::read().into_current();
+        write_state_version();
+        contract
+    }
+
+    pub fn get_state_version(&self) -> u8 {
+        read_state_version()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::metadata::{
+        FungibleTokenMetadataProvider, FT_METADATA_SPEC,
+    };
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    #[test]
+    fn test_migrate_from_v1() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(0));
+        testing_env!(context.build());
+        let mut token = FungibleToken::new(b"a".to_vec());
+        token.internal_register_account(&accounts(1));
+        token.internal_deposit(&accounts(1), 1_000);
+        let metadata = FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "AV TOKEN".to_string(),
+            symbol: "ASTRO".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 18,
+        };
+        env::state_write(&ContractV1 {
+            token,
+            metadata: LazyOption::new(b"m".to_vec(), Some(&metadata)),
+        });
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), CURRENT_STATE_VERSION);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.ft_total_supply().0, 1_000);
+        assert_eq!(contract.ft_metadata().symbol, "ASTRO");
+        assert_eq!(contract.get_owner_id().as_str(), OWNER_ID);
+    }
+
+    #[test]
+    fn test_migrate_from_v2() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(0));
+        testing_env!(context.build());
+        let mut token = FungibleToken::new(b"a".to_vec());
+        token.internal_register_account(&accounts(1));
+        token.internal_register_account(&accounts(0));
+        token.internal_deposit(&accounts(1), 1_000);
+        token.internal_deposit(&accounts(0), 500);
+        let mut staking = StakingPoolV2 {
+            accounts: LookupMap::new(V2_STAKING_PREFIX.to_vec()),
+            total_staked: 400,
+            reward_pool: 100,
+            reward_rate: 1,
+            acc_reward_per_share: 0,
+            last_update_timestamp: 0,
+        };
+        staking.accounts.insert(
+            accounts(1),
+            StakerAccount {
+                staked: 400,
+                ..Default::default()
+            },
+        );
+        staking.accounts.flush();
+        env::state_write(&ContractV2 {
+            token,
+            metadata: LazyOption::new(b"m".to_vec(), None),
+            staking,
+            mint_queue: MintQueue::new(b"q".to_vec()),
+            payment_links: LookupMap::new(b"l".to_vec()),
+            streams: LookupMap::new(b"t".to_vec()),
+            next_stream_id: 3,
+            escrows: LookupMap::new(b"e".to_vec()),
+            next_escrow_id: 2,
+            has_transfers: true,
+        });
+        env::storage_write(STATE_VERSION_KEY, &[2]);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), CURRENT_STATE_VERSION);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(
+            contract.staking.accounts.get(&accounts(1)).unwrap().staked,
+            400
+        );
+        assert_eq!(contract.get_staking_info().total_staked.0, 400);
+        assert_eq!(contract.get_staking_info().reward_pool.0, 100);
+        assert_eq!(contract.next_stream_id, 3);
+        assert_eq!(contract.next_escrow_id, 2);
+    }
+}
@@ -0,0 +1,273 @@
+/*!
+Mint request queue: automated submitters propose mints, a human approver confirms them.
+NOTES:
+  - Submitters and the approver are configured by the owner.
+  - A request must be approved within `approval_window` of its submission, otherwise it expires
+    and can only be removed.
+  - Submitters pay for the storage of their requests; it is released when the request is
+    approved, rejected or removed after expiry, and refunded to the submitter.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::{LookupSet, UnorderedMap};
+use near_sdk::{env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+/// Default approval window of 24 hours in nanoseconds.
+const DEFAULT_APPROVAL_WINDOW: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintRequest {
+    pub submitter_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+    pub submitted_at: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct MintQueue {
+    pub requests: UnorderedMap<u64, MintRequest>,
+    pub submitters: LookupSet<AccountId>,
+    pub approver_id: Option<AccountId>,
+    /// Time in nanoseconds a request stays approvable after submission.
+    pub approval_window: u64,
+    pub next_request_id: u64,
+}
+
+impl MintQueue {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            requests: UnorderedMap::new([prefix.as_slice(), b"r"].concat()),
+            submitters: LookupSet::new([prefix.as_slice(), b"s"].concat()),
+            approver_id: None,
+            approval_window: DEFAULT_APPROVAL_WINDOW,
+            next_request_id: 0,
+        }
+    }
+
+    pub fn is_expired(&self, request: &MintRequest) -> bool {
+        env::block_timestamp() > request.submitted_at.0 + self.approval_window
+    }
+
+    pub fn unwrap_request(&self, request_id: u64) -> MintRequest {
+        self.requests
+            .get(&request_id)
+            .cloned()
+            .expect("Mint request not found")
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn add_mint_submitter(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.mint_queue.submitters.insert(account_id);
+    }
+
+    pub fn remove_mint_submitter(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.mint_queue.submitters.remove(&account_id);
+    }
+
+    pub fn set_mint_approver(&mut self, approver_id: Option<AccountId>) {
+        self.assert_owner();
+        self.mint_queue.approver_id = approver_id;
+    }
+
+    pub fn set_mint_approval_window(&mut self, approval_window: U64) {
+        self.assert_owner();
+        assert!(approval_window.0 > 0, "Approval window must be positive");
+        self.mint_queue.approval_window = approval_window.into();
+    }
+
+    /// Queues a mint of `amount` to `receiver_id` and returns the request id. Only callable by
+    /// a registered submitter, who attaches deposit to cover the storage of the request.
+    #[payable]
+    pub fn submit_mint_request(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) -> U64 {
+        let submitter_id = env::predecessor_account_id();
+        assert!(
+            self.mint_queue.submitters.contains(&submitter_id),
+            "Only mint submitters can submit mint requests"
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        let initial_storage = env::storage_usage();
+        let request_id = self.mint_queue.next_request_id;
+        self.mint_queue.next_request_id += 1;
+        self.mint_queue.requests.insert(
+            request_id,
+            MintRequest {
+                submitter_id: submitter_id.clone(),
+                receiver_id,
+                amount,
+                memo,
+                submitted_at: env::block_timestamp().into(),
+            },
+        );
+        self.mint_queue.requests.flush();
+        self.scheduler.schedule(
+            ScheduledAction::RemoveExpiredMintRequest {
+                request_id: request_id.into(),
+            },
+            env::block_timestamp() + self.mint_queue.approval_window + 1,
+        );
+        log!(
+            "Mint request {} of {} submitted by @{}",
+            request_id,
+            amount.0,
+            submitter_id
+        );
+        refund_deposit(env::storage_usage() - initial_storage);
+        request_id.into()
+    }
+
+    /// Mints the tokens of a pending, non-expired request. Only callable by the approver.
+    pub fn approve_mint_request(&mut self, request_id: U64) {
+        self.assert_mint_approver();
+        let request = self.mint_queue.unwrap_request(request_id.0);
+        assert!(
+            !self.mint_queue.is_expired(&request),
+            "Mint request has expired"
+        );
+        self.internal_remove_mint_request(request_id.0, &request);
+        let amount: Balance = request.amount.into();
+        self.internal_mint(&env::predecessor_account_id(), &request.receiver_id, amount);
+        log!(
+            "Mint request {} approved: minted {} to @{}",
+            request_id.0,
+            amount,
+            request.receiver_id
+        );
+    }
+
+    pub fn reject_mint_request(&mut self, request_id: U64) {
+        self.assert_mint_approver();
+        let request = self.mint_queue.unwrap_request(request_id.0);
+        self.internal_remove_mint_request(request_id.0, &request);
+        log!("Mint request {} rejected", request_id.0);
+    }
+
+    /// Removes an expired request. Anyone can clean up expired requests.
+    pub fn remove_expired_mint_request(&mut self, request_id: U64) {
+        let request = self.mint_queue.unwrap_request(request_id.0);
+        assert!(
+            self.mint_queue.is_expired(&request),
+            "Mint request has not expired yet"
+        );
+        self.internal_remove_mint_request(request_id.0, &request);
+        log!("Mint request {} expired", request_id.0);
+    }
+
+    pub fn get_mint_request(&self, request_id: U64) -> Option<MintRequest> {
+        self.mint_queue.requests.get(&request_id.0).cloned()
+    }
+
+    /// Returns pending requests that can still be approved, as `(request_id, request)` pairs.
+    pub fn get_pending_mint_requests(
+        &self,
+        from_index: u64,
+        limit: u64,
+    ) -> Vec<(U64, MintRequest)> {
+        self.mint_queue
+            .requests
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .filter(|(_, request)| !self.mint_queue.is_expired(request))
+            .map(|(request_id, request)| ((*request_id).into(), request.clone()))
+            .collect()
+    }
+
+    pub fn get_mint_approver(&self) -> Option<AccountId> {
+        self.mint_queue.approver_id.clone()
+    }
+
+    pub fn is_mint_submitter(&self, account_id: AccountId) -> bool {
+        self.mint_queue.submitters.contains(&account_id)
+    }
+}
+
+impl Contract {
+    fn assert_mint_approver(&self) {
+        self.assert_not_renounced();
+        assert_eq!(
+            Some(env::predecessor_account_id()),
+            self.mint_queue.approver_id,
+            "Only the mint approver can call this method"
+        );
+    }
+
+    /// Removes a request, refunding its storage to the submitter.
+    fn internal_remove_mint_request(&mut self, request_id: u64, request: &MintRequest) {
+        let initial_storage = env::storage_usage();
+        self.mint_queue.requests.remove(&request_id);
+        self.mint_queue.requests.flush();
+        self.scheduler
+            .unschedule(&ScheduledAction::RemoveExpiredMintRequest {
+                request_id: request_id.into(),
+            });
+        refund_released_storage(&request.submitter_id, initial_storage);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, owner(), 0);
+        contract.add_mint_submitter(accounts(1));
+        contract.set_mint_approver(Some(accounts(2)));
+        contract.set_mint_approval_window(U64(100));
+        (context, contract)
+    }
+
+    #[test]
+    fn test_approve_mint_request() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let request_id = contract.submit_mint_request(owner(), U128(10), None);
+        assert_eq!(contract.get_pending_mint_requests(0, 10).len(), 1);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0)
+            .build());
+        contract.approve_mint_request(request_id);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY + 10);
+        assert!(contract.get_mint_request(request_id).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Mint request has expired")]
+    fn test_expired_mint_request() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let request_id = contract.submit_mint_request(owner(), U128(10), None);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0)
+            .block_timestamp(101)
+            .build());
+        contract.approve_mint_request(request_id);
+    }
+}
@@ -0,0 +1,97 @@
+/*!
+Per-minter caps on minting.
+NOTES:
+  - The owner or the DAO gives a minter an allowance with `set_minter_allowance`. Every mint by
+    that minter is taken out of it and fails once it is used up, so a compromised minter key
+    can mint no more than its allowance. A minter without an allowance is not capped.
+  - The minter of a bridge mint is the connector and the minter of an approved mint request is
+    the approver. Mints made by the contract itself, through the sale, the bonding curve and
+    governance, count against the allowance of the contract account.
+  - Allowances apply on top of the emission schedule and the bridge connector caps.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::store::LookupMap;
+use near_sdk::{log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct MinterAllowances {
+    pub allowances: LookupMap<AccountId, Balance>,
+}
+
+impl MinterAllowances {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            allowances: LookupMap::new(prefix),
+        }
+    }
+
+    /// Takes `amount` out of the allowance of `minter_id`, if it has one.
+    pub fn use_allowance(&mut self, minter_id: &AccountId, amount: Balance) {
+        if let Some(allowance) = self.allowances.get_mut(minter_id) {
+            assert!(
+                amount <= *allowance,
+                "The mint exceeds the allowance of @{} ({})",
+                minter_id,
+                allowance
+            );
+            *allowance -= amount;
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Caps the future mints of `minter_id` at `amount` in total.
+    pub fn set_minter_allowance(&mut self, minter_id: AccountId, amount: U128) {
+        self.assert_owner_or_dao();
+        self.minter_allowances
+            .allowances
+            .insert(minter_id.clone(), amount.0);
+        log!("@{} can mint {}", minter_id, amount.0);
+    }
+
+    /// Lifts the cap on the mints of `minter_id`.
+    pub fn remove_minter_allowance(&mut self, minter_id: AccountId) {
+        self.assert_owner_or_dao();
+        assert!(
+            self.minter_allowances
+                .allowances
+                .remove(&minter_id)
+                .is_some(),
+            "No allowance is set for this minter"
+        );
+        log!("@{} is no longer capped", minter_id);
+    }
+
+    /// Remaining allowance of `minter_id`, `None` if its mints are not capped.
+    pub fn get_minter_allowance(&self, minter_id: AccountId) -> Option<U128> {
+        self.minter_allowances
+            .allowances
+            .get(&minter_id)
+            .map(|allowance| U128(*allowance))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    #[should_panic(expected = "The mint exceeds the allowance")]
+    fn test_mints_use_the_allowance() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        let owner = owner();
+        contract.set_minter_allowance(accounts(1), U128(100));
+        contract.internal_mint(&accounts(1), &owner, 60);
+        assert_eq!(contract.get_minter_allowance(accounts(1)), Some(U128(40)));
+        assert_eq!(contract.ft_balance_of(owner.clone()).0, TOTAL_SUPPLY + 60);
+        contract.internal_mint(&accounts(1), &owner, 41);
+    }
+}
@@ -0,0 +1,158 @@
+/*!
+Mock receiver of `ft_transfer_call`, for the integration tests of ASTRO and of contracts built
+against it.
+NOTES:
+  - The behavior of `ft_on_transfer` is picked by its `msg`, or by the default set with
+    `set_behavior` when the `msg` is empty:
+    - `accept` keeps all the tokens.
+    - `refund:<amount>` returns up to `amount` unused, e.g. `refund:100`. `refund_all` returns
+      everything.
+    - `panic` fails, so the token refunds the whole amount.
+    - `slow` keeps the tokens, but answers only after a callback to itself, in a later block.
+  - An unknown `msg` fails like `panic`.
+  - The tokens kept from each token contract are counted in `get_received`.
+*/
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId, Balance, Gas, PanicOnDefault, PromiseOrValue};
+
+const GAS_FOR_SLOW_RESOLVE: Gas = Gas(5_000_000_000_000);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Behavior {
+    Accept,
+    Refund(Balance),
+    RefundAll,
+    Panic,
+    Slow,
+}
+
+impl Behavior {
+    pub fn parse(msg: &str) -> Option<Self> {
+        match msg {
+            "accept" => Some(Self::Accept),
+            "refund_all" => Some(Self::RefundAll),
+            "panic" => Some(Self::Panic),
+            "slow" => Some(Self::Slow),
+            _ => msg
+                .strip_prefix("refund:")
+                .and_then(|amount| amount.parse().ok())
+                .map(Self::Refund),
+        }
+    }
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct MockReceiver {
+    default_behavior: String,
+    received: LookupMap<AccountId, Balance>,
+}
+
+#[near_bindgen]
+impl MockReceiver {
+    #[init]
+    pub fn new() -> Self {
+        Self {
+            default_behavior: "accept".to_string(),
+            received: LookupMap::new(b"r"),
+        }
+    }
+
+    /// Sets the behavior of calls with an empty `msg`.
+    pub fn set_behavior(&mut self, behavior: String) {
+        assert!(Behavior::parse(&behavior).is_some(), "Unknown behavior");
+        self.default_behavior = behavior;
+    }
+
+    pub fn get_behavior(&self) -> String {
+        self.default_behavior.clone()
+    }
+
+    /// Tokens kept from the token contract `token_id`.
+    pub fn get_received(&self, token_id: AccountId) -> U128 {
+        self.received.get(&token_id).unwrap_or(0).into()
+    }
+
+    #[private]
+    pub fn on_slow_resolved(&self) -> U128 {
+        U128(0)
+    }
+}
+
+impl MockReceiver {
+    fn record(&mut self, amount: Balance) {
+        let token_id = env::predecessor_account_id();
+        let received = self.received.get(&token_id).unwrap_or(0);
+        self.received.insert(&token_id, &(received + amount));
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for MockReceiver {
+    /// Returns the amount unused, which the token refunds to the sender.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let msg = if msg.is_empty() {
+            self.default_behavior.clone()
+        } else {
+            msg
+        };
+        let behavior = Behavior::parse(&msg)
+            .unwrap_or_else(|| env::panic_str(&format!("Unknown behavior {}", msg)));
+        let unused = match behavior {
+            Behavior::Accept | Behavior::Slow => 0,
+            Behavior::Refund(refund) => std::cmp::min(refund, amount.0),
+            Behavior::RefundAll => amount.0,
+            Behavior::Panic => env::panic_str(&format!("Rejected a transfer from @{}", sender_id)),
+        };
+        self.record(amount.0 - unused);
+        if behavior == Behavior::Slow {
+            return PromiseOrValue::Promise(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_SLOW_RESOLVE)
+                    .on_slow_resolved(),
+            );
+        }
+        PromiseOrValue::Value(unused.into())
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    fn unused(value: PromiseOrValue<U128>) -> u128 {
+        match value {
+            PromiseOrValue::Value(unused) => unused.0,
+            PromiseOrValue::Promise(_) => panic!("Expected a value"),
+        }
+    }
+
+    #[test]
+    fn test_behaviors() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        let mut receiver = MockReceiver::new();
+        let on_transfer = |receiver: &mut MockReceiver, msg: &str| {
+            unused(receiver.ft_on_transfer(accounts(1), U128(10), msg.to_string()))
+        };
+        assert_eq!(on_transfer(&mut receiver, "accept"), 0);
+        assert_eq!(on_transfer(&mut receiver, "refund:4"), 4);
+        assert_eq!(on_transfer(&mut receiver, "refund:40"), 10);
+        assert_eq!(on_transfer(&mut receiver, "refund_all"), 10);
+        receiver.set_behavior("refund:1".to_string());
+        assert_eq!(on_transfer(&mut receiver, ""), 1);
+        assert_eq!(receiver.get_received(accounts(0)).0, 10 + 6 + 9);
+    }
+}
@@ -0,0 +1,235 @@
+/*!
+Staking reward boost for holders of an NFT of a configured collection.
+NOTES:
+  - The owner sets an NFT collection and a reward multiplier in basis points, e.g. 15_000 for
+    1.5x. A staker registers one token id of the collection with `set_boost_nft`.
+  - Ownership is checked lazily: `claim_boosted_rewards` settles the rewards like
+    `claim_rewards`, then asks the collection for `nft_token` and pays them in the callback, with
+    the multiplier if the staker still owns the token and without it otherwise.
+  - The extra reward is taken from the reward pool, as far as it goes. Referrers earn on the
+    base reward only.
+  - The staker pays for the storage of its registration and gets it back when removing it.
+*/
+#![cfg_attr(not(feature = "staking"), allow(dead_code, unused_imports))]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::LookupMap;
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, log, near_bindgen, AccountId, Balance, Gas, Promise,
+    PromiseError,
+};
+
+use crate::*;
+
+const BASE_MULTIPLIER_BPS: u32 = 10_000;
+const MAX_MULTIPLIER_BPS: u32 = 30_000;
+const MAX_TOKEN_ID_LEN: usize = 128;
+const GAS_FOR_NFT_TOKEN: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_ON_BOOST_VERIFIED: Gas = Gas(20_000_000_000_000);
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftToken {
+    pub owner_id: AccountId,
+}
+
+#[ext_contract(ext_nft)]
+#[allow(dead_code)]
+pub trait NonFungibleToken {
+    fn nft_token(&self, token_id: String) -> Option<NftToken>;
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct NftBoost {
+    pub collection_id: Option<AccountId>,
+    pub multiplier_bps: u32,
+    /// Token id registered by each staker.
+    pub tokens: LookupMap<AccountId, String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftBoostInfo {
+    pub collection_id: Option<AccountId>,
+    pub multiplier_bps: u32,
+}
+
+impl NftBoost {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            collection_id: None,
+            multiplier_bps: BASE_MULTIPLIER_BPS,
+            tokens: LookupMap::new(prefix),
+        }
+    }
+}
+
+#[cfg(feature = "staking")]
+#[near_bindgen]
+impl Contract {
+    /// Sets the collection whose holders get `multiplier_bps` of their staking rewards, or
+    /// `None` to stop boosting.
+    pub fn set_nft_boost(&mut self, collection_id: Option<AccountId>, multiplier_bps: u32) {
+        self.assert_owner();
+        assert!(
+            (BASE_MULTIPLIER_BPS..=MAX_MULTIPLIER_BPS).contains(&multiplier_bps),
+            "The multiplier should be between {} and {} bps",
+            BASE_MULTIPLIER_BPS,
+            MAX_MULTIPLIER_BPS
+        );
+        self.nft_boost.collection_id = collection_id;
+        self.nft_boost.multiplier_bps = multiplier_bps;
+    }
+
+    /// Registers the caller's token of the boost collection, `None` to remove it. Registering
+    /// takes a deposit for its storage, the excess is refunded.
+    #[payable]
+    pub fn set_boost_nft(&mut self, token_id: Option<String>) {
+        let account_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        match token_id {
+            Some(token_id) => {
+                assert!(
+                    !token_id.is_empty() && token_id.len() <= MAX_TOKEN_ID_LEN,
+                    "A token id has 1 to {} bytes",
+                    MAX_TOKEN_ID_LEN
+                );
+                log!("@{} registered the boost NFT {}", account_id, token_id);
+                self.nft_boost.tokens.insert(account_id, token_id);
+                self.nft_boost.tokens.flush();
+                refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+            }
+            None => {
+                self.nft_boost.tokens.remove(&account_id);
+                self.nft_boost.tokens.flush();
+                refund_released_storage(&account_id, initial_storage);
+            }
+        }
+    }
+
+    /// Settles the caller's staking rewards and pays them, boosted if the caller owns its
+    /// registered NFT. Resolves to the amount paid.
+    #[payable]
+    pub fn claim_boosted_rewards(&mut self) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let collection_id = self
+            .nft_boost
+            .collection_id
+            .clone()
+            .expect("No NFT boost is set");
+        let token_id = self
+            .nft_boost
+            .tokens
+            .get(&account_id)
+            .cloned()
+            .expect("Register an NFT with set_boost_nft first");
+        self.staking.update();
+        let mut account = self.staking.settled_account(&account_id);
+        let reward = account.unclaimed;
+        assert!(reward > 0, "No rewards to claim");
+        account.unclaimed = 0;
+        self.staking.save_account(&account_id, &account);
+        ext_nft::ext(collection_id)
+            .with_static_gas(GAS_FOR_NFT_TOKEN)
+            .nft_token(token_id)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_BOOST_VERIFIED)
+                    .on_boost_verified(account_id, U128(reward)),
+            )
+    }
+
+    #[private]
+    pub fn on_boost_verified(
+        &mut self,
+        account_id: AccountId,
+        reward: U128,
+        #[callback_result] token: Result<Option<NftToken>, PromiseError>,
+    ) -> U128 {
+        let owns_token = matches!(&token, Ok(Some(token)) if token.owner_id == account_id);
+        let mut paid = reward.0;
+        if owns_token {
+            self.staking.update();
+            let bonus = std::cmp::min(self.boost_bonus(reward.0), self.staking.reward_pool);
+            self.staking.reward_pool -= bonus;
+            paid += bonus;
+        }
+        self.internal_unlock(&account_id, paid);
+        self.internal_refer_staking_reward(&account_id, reward.0);
+        log!(
+            "Account @{} claimed {} of staking rewards, boosted: {}",
+            account_id,
+            paid,
+            owns_token
+        );
+        paid.into()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_nft_boost(&self) -> NftBoostInfo {
+        NftBoostInfo {
+            collection_id: self.nft_boost.collection_id.clone(),
+            multiplier_bps: self.nft_boost.multiplier_bps,
+        }
+    }
+
+    pub fn get_boost_nft(&self, account_id: AccountId) -> Option<String> {
+        self.nft_boost.tokens.get(&account_id).cloned()
+    }
+}
+
+impl Contract {
+    /// Reward paid on top of `reward` by the multiplier.
+    fn boost_bonus(&self, reward: Balance) -> Balance {
+        let extra_bps = self.nft_boost.multiplier_bps - BASE_MULTIPLIER_BPS;
+        mul_div(
+            reward,
+            Balance::from(extra_bps),
+            Balance::from(BASE_MULTIPLIER_BPS),
+        )
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "staking"))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    const ONE_TOKEN: Balance = 1_000_000_000_000_000_000;
+    #[test]
+    fn test_nft_holder_gets_the_multiplier() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 1);
+        let owner = owner();
+        contract.fund_reward_pool(U128(1_000 * ONE_TOKEN));
+        contract.set_reward_rate(U128(ONE_TOKEN));
+        contract.set_nft_boost(Some(accounts(3)), 15_000);
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.stake(U128(100 * ONE_TOKEN), None);
+        contract.set_boost_nft(Some("42".to_string()));
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(10_000_000_000)
+            .build());
+        contract.claim_boosted_rewards();
+        let balance = contract.ft_balance_of(owner.clone()).0;
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let token = NftToken {
+            owner_id: owner.clone(),
+        };
+        let paid = contract.on_boost_verified(owner.clone(), U128(10 * ONE_TOKEN), Ok(Some(token)));
+        assert_eq!(paid.0, 15 * ONE_TOKEN);
+        assert_eq!(contract.ft_balance_of(owner).0, balance + 15 * ONE_TOKEN);
+    }
+}
@@ -0,0 +1,152 @@
+/*!
+Transfer and permanent renouncement of the admin powers.
+NOTES:
+  - The owner is stored in the state. `transfer_ownership` offers the ownership to another
+    account, which takes it over with `accept_ownership`, so a mistyped account id cannot lock
+    the admin powers. The offer goes through the council or the timelock like any admin call.
+  - `renounce_ownership` can only be called once the timelock is enabled, so it is queued like
+    any other admin call and holders see it coming. Once executed it cannot be undone.
+  - After renouncing, every method gated to the owner, the DAO, the council or the timelock
+    fails, and so do approvals of mint requests and governance proposals that mint or change the
+    metadata. No privileged account can mint, change the metadata, upgrade the code or change
+    the configuration any more.
+  - What does not depend on an admin keeps working with the configuration it has: transfers,
+    staking, the sale and the bonding curve, and the bridge connectors within their caps.
+*/
+use near_sdk::{env, log, near_bindgen, AccountId};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Offers the ownership to `new_owner_id`, or withdraws the offer when `None`.
+    pub fn transfer_ownership(&mut self, new_owner_id: Option<AccountId>) {
+        self.assert_owner();
+        match &new_owner_id {
+            Some(new_owner_id) => log!("Ownership offered to {}", new_owner_id),
+            None => log!("Ownership offer withdrawn"),
+        }
+        self.pending_owner_id = new_owner_id;
+    }
+
+    /// Takes over the ownership offered to the caller.
+    pub fn accept_ownership(&mut self) {
+        self.assert_not_renounced();
+        let owner_id = env::predecessor_account_id();
+        assert_eq!(
+            self.pending_owner_id.as_ref(),
+            Some(&owner_id),
+            "The ownership is not offered to the caller"
+        );
+        self.pending_owner_id = None;
+        log!(
+            "Ownership transferred from {} to {}",
+            self.owner_id,
+            owner_id
+        );
+        self.owner_id = owner_id;
+    }
+
+    pub fn get_owner_id(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    pub fn get_pending_owner_id(&self) -> Option<AccountId> {
+        self.pending_owner_id.clone()
+    }
+
+    /// Disables every privileged method for good.
+    pub fn renounce_ownership(&mut self) {
+        assert!(
+            self.timelock.is_enabled(),
+            "Ownership can only be renounced through the timelock"
+        );
+        self.assert_owner();
+        self.ownership_renounced = true;
+        log!("Ownership renounced, admin methods are disabled for good");
+    }
+
+    pub fn is_ownership_renounced(&self) -> bool {
+        self.ownership_renounced
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_not_renounced(&self) {
+        assert!(!self.ownership_renounced, "Ownership has been renounced");
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U64;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_transfer_ownership() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+        contract.transfer_ownership(Some(accounts(2)));
+        assert_eq!(contract.get_owner_id(), accounts(1));
+        assert_eq!(contract.get_pending_owner_id(), Some(accounts(2)));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.accept_ownership();
+        assert_eq!(contract.get_owner_id(), accounts(2));
+        assert_eq!(contract.get_pending_owner_id(), None);
+        contract.set_dao(Some(accounts(3)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner's method")]
+    fn test_previous_owner_loses_the_admin_powers() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+        contract.transfer_ownership(Some(accounts(2)));
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.accept_ownership();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_dao(Some(accounts(3)));
+    }
+
+    #[test]
+    #[should_panic(expected = "The ownership is not offered to the caller")]
+    fn test_only_the_offered_account_accepts_the_ownership() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+        contract.transfer_ownership(Some(accounts(2)));
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    #[should_panic(expected = "Ownership has been renounced")]
+    fn test_admin_methods_disabled_after_renouncing() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        contract.set_timelock_delay(U64(1_000));
+        // Executed timelock actions are sent by the contract to itself.
+        testing_env!(context
+            .predecessor_account_id(env::current_account_id())
+            .build());
+        contract.renounce_ownership();
+        assert!(contract.is_ownership_renounced());
+        contract.set_dao(Some(accounts(1)));
+    }
+}
@@ -0,0 +1,174 @@
+/*!
+Emergency pause of token transfers.
+NOTES:
+  - The owner or the DAO can pause transfers, optionally until `expires_at`. After that timestamp
+    transfers resume by themselves: the pause is cleared on the next transfer, so a lost pauser
+    key cannot freeze the token forever.
+  - While paused `ft_transfer`, `ft_transfer_call` and every transfer made on behalf of a holder
+    panic. Views, storage management and custody operations keep working.
+  - For an incident in one subsystem the owner or the DAO can instead pause a single module with
+    `pause_module`. Like a retired module, a paused module takes no new activity while exits keep
+    working, see `retirement`, but `unpause_module` lifts it. Every toggle is an event.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::{env, log, near_bindgen, AccountId};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Pause {
+    pub paused_by: AccountId,
+    pub paused_at: U64,
+    /// Timestamp in nanoseconds from which transfers resume, or `None` until `unpause`.
+    pub expires_at: Option<U64>,
+}
+
+impl Pause {
+    pub fn is_active(&self) -> bool {
+        self.expires_at
+            .is_none_or(|expires_at| env::block_timestamp() < expires_at.0)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn pause(&mut self, expires_at: Option<U64>) {
+        self.assert_owner_or_dao();
+        self.internal_pause(expires_at);
+    }
+
+    pub fn unpause(&mut self) {
+        self.assert_owner_or_dao();
+        self.internal_unpause();
+    }
+
+    /// The pause while it is active.
+    pub fn get_pause(&self) -> Option<Pause> {
+        self.pause.clone().filter(Pause::is_active)
+    }
+
+    pub fn pause_module(&mut self, module: Module) {
+        self.assert_owner_or_dao();
+        assert!(
+            !self.paused_modules.contains(&module),
+            "The {:?} module is already paused",
+            module
+        );
+        self.paused_modules.push(module);
+        emit_module_pause_event("module_pause", module);
+    }
+
+    pub fn unpause_module(&mut self, module: Module) {
+        self.assert_owner_or_dao();
+        let len = self.paused_modules.len();
+        self.paused_modules.retain(|paused| *paused != module);
+        assert!(
+            self.paused_modules.len() < len,
+            "The {:?} module is not paused",
+            module
+        );
+        emit_module_pause_event("module_unpause", module);
+    }
+
+    pub fn paused_modules(&self) -> Vec<Module> {
+        self.paused_modules.clone()
+    }
+}
+
+fn emit_module_pause_event(event: &str, module: Module) {
+    emit_event(
+        EVENT_VERSION,
+        event,
+        json!({
+            "module": module,
+            "account_id": env::predecessor_account_id(),
+        }),
+    );
+}
+
+impl Contract {
+    /// Pauses transfers. Shared by the admin method and DAO proposals.
+    pub(crate) fn internal_pause(&mut self, expires_at: Option<U64>) {
+        if let Some(expires_at) = expires_at {
+            assert!(
+                expires_at.0 > env::block_timestamp(),
+                "The pause must expire in the future"
+            );
+        }
+        let paused_by = env::predecessor_account_id();
+        match expires_at {
+            Some(expires_at) => log!("@{} paused transfers until {}", paused_by, expires_at.0),
+            None => log!("@{} paused transfers", paused_by),
+        }
+        self.pause = Some(Pause {
+            paused_by,
+            paused_at: env::block_timestamp().into(),
+            expires_at,
+        });
+    }
+
+    pub(crate) fn internal_unpause(&mut self) {
+        assert!(self.pause.take().is_some(), "Transfers are not paused");
+        log!("@{} unpaused transfers", env::predecessor_account_id());
+    }
+
+    /// Panics while transfers are paused and clears an expired pause.
+    pub(crate) fn assert_not_paused(&mut self) {
+        if let Some(pause) = &self.pause {
+            assert!(!pause.is_active(), "Transfers are paused");
+            self.pause = None;
+            log!("Transfers resumed after the pause expired");
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 1);
+        contract.pause(Some(U64(100)));
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers are paused")]
+    fn test_paused_transfer() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), U128(10), None);
+    }
+
+    #[test]
+    #[cfg(feature = "staking")]
+    #[should_panic(expected = "The Staking module is paused")]
+    fn test_paused_module_takes_no_new_activity() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(0).build());
+        contract.unpause();
+        contract.pause_module(Module::Staking);
+        assert_eq!(contract.paused_modules(), vec![Module::Staking]);
+        contract.stake(U128(10), None);
+    }
+
+    #[test]
+    fn test_pause_expires() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(100).build());
+        assert!(contract.get_pause().is_none());
+        contract.ft_transfer(accounts(1), U128(10), None);
+        assert!(contract.pause.is_none());
+    }
+}
@@ -0,0 +1,189 @@
+/*!
+Payment links: tokens escrowed under the hash of a secret, claimable by whoever presents it.
+NOTES:
+  - The creator attaches enough deposit to cover the link storage and the registration of the
+    claimer, so links can onboard accounts that are not registered with the token yet. The
+    registration deposit is refunded to the creator if the claimer was already registered.
+  - After `expires_at` the link can no longer be claimed and the creator can take the tokens back.
+  - The secret is revealed in the claiming transaction, so links should only be shared privately.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance, CryptoHash};
+
+use crate::pool_ledger::Pool;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PaymentLink {
+    pub creator_id: AccountId,
+    pub amount: U128,
+    pub expires_at: U64,
+    /// NEAR reserved to register the claimer with the token.
+    pub registration_deposit: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Escrows `amount` of the caller's tokens under `secret_hash`, the sha256 of the secret.
+    /// `expiry` is a timestamp in nanoseconds. The attached deposit must cover the link storage
+    /// and the minimum storage balance of a new account.
+    #[payable]
+    pub fn create_payment_link(
+        &mut self,
+        amount: U128,
+        secret_hash: Base58CryptoHash,
+        expiry: U64,
+    ) {
+        self.assert_module_active(Module::PaymentLinks);
+        let amount: Balance = amount.into();
+        assert!(amount > 0, "The amount should be a positive number");
+        assert!(
+            expiry.0 > env::block_timestamp(),
+            "Expiry must be in the future"
+        );
+        let secret_hash: CryptoHash = secret_hash.into();
+        assert!(
+            !self.payment_links.contains_key(&secret_hash),
+            "Payment link already exists"
+        );
+        let initial_storage = env::storage_usage();
+        let creator_id = env::predecessor_account_id();
+        let registration_deposit = self.storage_balance_bounds().min.0;
+        self.internal_lock_to(Pool::PaymentLinks, &creator_id, amount);
+        self.rescue.hold(registration_deposit);
+        self.payment_links.insert(
+            secret_hash,
+            PaymentLink {
+                creator_id: creator_id.clone(),
+                amount: amount.into(),
+                expires_at: expiry,
+                registration_deposit: registration_deposit.into(),
+            },
+        );
+        self.payment_links.flush();
+        log!(
+            "Account @{} created a payment link for {}",
+            creator_id,
+            amount
+        );
+        let storage_used = env::storage_usage() - initial_storage;
+        let storage_cost = env::storage_byte_cost() * Balance::from(storage_used);
+        assert!(
+            env::attached_deposit() >= storage_cost + registration_deposit,
+            "Must attach {} yoctoNEAR to cover storage and registration",
+            storage_cost + registration_deposit
+        );
+        let refund = env::attached_deposit() - storage_cost - registration_deposit;
+        if refund > 1 {
+            Promise::new(creator_id).transfer(refund);
+        }
+    }
+
+    /// Transfers the tokens of the link matching `secret` to the caller, registering the caller
+    /// with the token if needed.
+    pub fn claim_payment_link(&mut self, secret: String) -> U128 {
+        let secret_hash = hash_secret(&secret);
+        let link = self.unwrap_payment_link(&secret_hash);
+        assert!(
+            env::block_timestamp() <= link.expires_at.0,
+            "Payment link has expired"
+        );
+        self.payment_links.remove(&secret_hash);
+        self.rescue.release(link.registration_deposit.0);
+        let claimer_id = env::predecessor_account_id();
+        if self.token.accounts.contains_key(&claimer_id) {
+            Promise::new(link.creator_id.clone()).transfer(link.registration_deposit.0);
+        } else {
+            self.internal_register_account(&claimer_id);
+        }
+        self.internal_unlock_from(Pool::PaymentLinks, &claimer_id, link.amount.0);
+        log!(
+            "Account @{} claimed {} from a payment link of @{}",
+            claimer_id,
+            link.amount.0,
+            link.creator_id
+        );
+        link.amount
+    }
+
+    /// Returns the tokens and the registration deposit of an expired link to its creator.
+    #[payable]
+    pub fn refund_payment_link(&mut self, secret_hash: Base58CryptoHash) {
+        assert_one_yocto();
+        let secret_hash: CryptoHash = secret_hash.into();
+        let link = self.unwrap_payment_link(&secret_hash);
+        assert_eq!(
+            env::predecessor_account_id(),
+            link.creator_id,
+            "Only the creator can refund a payment link"
+        );
+        assert!(
+            env::block_timestamp() > link.expires_at.0,
+            "Payment link has not expired yet"
+        );
+        self.payment_links.remove(&secret_hash);
+        self.rescue.release(link.registration_deposit.0);
+        self.internal_unlock_from(Pool::PaymentLinks, &link.creator_id, link.amount.0);
+        Promise::new(link.creator_id.clone()).transfer(link.registration_deposit.0);
+        log!(
+            "Payment link of @{} refunded {}",
+            link.creator_id,
+            link.amount.0
+        );
+    }
+
+    pub fn get_payment_link(&self, secret_hash: Base58CryptoHash) -> Option<PaymentLink> {
+        let secret_hash: CryptoHash = secret_hash.into();
+        self.payment_links.get(&secret_hash).cloned()
+    }
+}
+
+impl Contract {
+    fn unwrap_payment_link(&self, secret_hash: &CryptoHash) -> PaymentLink {
+        self.payment_links
+            .get(secret_hash)
+            .cloned()
+            .expect("Payment link not found")
+    }
+}
+
+fn hash_secret(secret: &str) -> CryptoHash {
+    let mut secret_hash = CryptoHash::default();
+    secret_hash.copy_from_slice(&env::sha256(secret.as_bytes()));
+    secret_hash
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    #[test]
+    fn test_claim_payment_link_registers_claimer() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        let owner = owner();
+
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        let secret_hash = hash_secret("open sesame");
+        contract.create_payment_link(U128(1_000), secret_hash.into(), U64(100));
+        assert_eq!(contract.ft_balance_of(owner).0, TOTAL_SUPPLY - 1_000);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(0)
+            .build());
+        assert_eq!(
+            contract.claim_payment_link("open sesame".to_string()).0,
+            1_000
+        );
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 1_000);
+        assert!(contract.get_payment_link(secret_hash.into()).is_none());
+    }
+}
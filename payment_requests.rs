@@ -0,0 +1,352 @@
+/*!
+Payment requests: on-chain invoices a merchant issues and a payer settles by id.
+NOTES:
+  - A merchant requests `amount` from a payer with a memo and an expiry. The payer settles it
+    with `pay_request`, an `ft_transfer` to the merchant with every check of the public method,
+    or declines it. Either way the request keeps its final status, so the merchant reconciles
+    payments by request id instead of parsing memos.
+  - The merchant pays for the storage of the request and gets it back with
+    `remove_payment_request`, which also withdraws a pending request.
+  - `get_outgoing_requests` and `get_incoming_requests` list the pending requests of a merchant
+    and of a payer, at most `MAX_PENDING_REQUESTS` each. Expired requests are dropped from a full
+    list to make room.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const MAX_PENDING_REQUESTS: usize = 100;
+const MAX_MEMO_LEN: usize = 256;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PaymentRequestStatus {
+    Pending,
+    Paid { paid_at: U64 },
+    Declined,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PaymentRequest {
+    pub merchant_id: AccountId,
+    pub payer_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+    pub expires_at: U64,
+    pub status: PaymentRequestStatus,
+}
+
+impl PaymentRequest {
+    fn is_expired(&self) -> bool {
+        env::block_timestamp() >= self.expires_at.0
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PaymentRequests {
+    pub requests: LookupMap<u64, PaymentRequest>,
+    /// Pending requests by merchant.
+    pub outgoing: LookupMap<AccountId, Vec<u64>>,
+    /// Pending requests by payer.
+    pub incoming: LookupMap<AccountId, Vec<u64>>,
+    pub next_request_id: u64,
+}
+
+impl PaymentRequests {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            requests: LookupMap::new([prefix.as_slice(), b"r"].concat()),
+            outgoing: LookupMap::new([prefix.as_slice(), b"o"].concat()),
+            incoming: LookupMap::new([prefix.as_slice(), b"i"].concat()),
+            next_request_id: 0,
+        }
+    }
+
+    fn flush(&mut self) {
+        self.requests.flush();
+        self.outgoing.flush();
+        self.incoming.flush();
+    }
+
+    fn pending(&self, account_id: &AccountId, outgoing: bool) -> Vec<u64> {
+        let index = if outgoing {
+            &self.outgoing
+        } else {
+            &self.incoming
+        };
+        index.get(account_id).cloned().unwrap_or_default()
+    }
+
+    fn set_pending(&mut self, account_id: &AccountId, outgoing: bool, request_ids: Vec<u64>) {
+        let index = if outgoing {
+            &mut self.outgoing
+        } else {
+            &mut self.incoming
+        };
+        if request_ids.is_empty() {
+            index.remove(account_id);
+        } else {
+            index.insert(account_id.clone(), request_ids);
+        }
+    }
+
+    /// Adds `request_id` to a pending list, dropping expired requests from it if it is full.
+    fn add_pending(&mut self, account_id: &AccountId, outgoing: bool, request_id: u64) {
+        let mut request_ids = self.pending(account_id, outgoing);
+        if request_ids.len() >= MAX_PENDING_REQUESTS {
+            let requests = &self.requests;
+            request_ids.retain(|request_id| {
+                requests
+                    .get(request_id)
+                    .is_some_and(|request| !request.is_expired())
+            });
+        }
+        assert!(
+            request_ids.len() < MAX_PENDING_REQUESTS,
+            "@{} has {} pending requests",
+            account_id,
+            MAX_PENDING_REQUESTS
+        );
+        request_ids.push(request_id);
+        self.set_pending(account_id, outgoing, request_ids);
+    }
+
+    fn remove_pending(&mut self, request: &PaymentRequest, request_id: u64) {
+        for (account_id, outgoing) in [(&request.merchant_id, true), (&request.payer_id, false)] {
+            let mut request_ids = self.pending(account_id, outgoing);
+            request_ids.retain(|id| *id != request_id);
+            self.set_pending(account_id, outgoing, request_ids);
+        }
+    }
+}
+
+fn emit_payment_request_event(event: &str, request_id: u64, request: &PaymentRequest) {
+    emit_event(
+        EVENT_VERSION,
+        event,
+        json!({
+            "request_id": U64(request_id),
+            "merchant_id": request.merchant_id,
+            "payer_id": request.payer_id,
+            "amount": request.amount,
+            "memo": request.memo,
+        }),
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requests `amount` from `payer_id` until the timestamp `expiry`, in nanoseconds. Returns
+    /// the request id. The attached deposit covers the request storage.
+    #[payable]
+    pub fn create_payment_request(
+        &mut self,
+        payer_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        expiry: U64,
+    ) -> U64 {
+        let merchant_id = env::predecessor_account_id();
+        assert_ne!(
+            merchant_id, payer_id,
+            "Merchant and payer should be different"
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        assert!(
+            expiry.0 > env::block_timestamp(),
+            "Expiry must be in the future"
+        );
+        assert!(
+            memo.as_ref().is_none_or(|memo| memo.len() <= MAX_MEMO_LEN),
+            "The memo is longer than {} bytes",
+            MAX_MEMO_LEN
+        );
+        let initial_storage = env::storage_usage();
+        let requests = &mut self.payment_requests;
+        let request_id = requests.next_request_id;
+        requests.next_request_id += 1;
+        let request = PaymentRequest {
+            merchant_id: merchant_id.clone(),
+            payer_id: payer_id.clone(),
+            amount,
+            memo,
+            expires_at: expiry,
+            status: PaymentRequestStatus::Pending,
+        };
+        emit_payment_request_event("payment_request_create", request_id, &request);
+        requests.add_pending(&merchant_id, true, request_id);
+        requests.add_pending(&payer_id, false, request_id);
+        requests.requests.insert(request_id, request);
+        requests.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        request_id.into()
+    }
+
+    /// Pays a pending request addressed to the caller.
+    #[payable]
+    pub fn pay_request(&mut self, id: U64) {
+        assert_one_yocto();
+        let mut request = self.unwrap_pending_request(id.0);
+        assert!(!request.is_expired(), "The request has expired");
+        request.status = PaymentRequestStatus::Paid {
+            paid_at: env::block_timestamp().into(),
+        };
+        self.internal_settle_payment_request(id.0, &request);
+        self.internal_ft_transfer(
+            &request.payer_id,
+            request.merchant_id.clone(),
+            request.amount.0,
+            Some(format!("Payment request {}", id.0)),
+        );
+        emit_payment_request_event("payment_request_pay", id.0, &request);
+    }
+
+    /// Declines a pending request addressed to the caller.
+    pub fn decline_payment_request(&mut self, id: U64) {
+        let mut request = self.unwrap_pending_request(id.0);
+        request.status = PaymentRequestStatus::Declined;
+        self.internal_settle_payment_request(id.0, &request);
+        emit_payment_request_event("payment_request_decline", id.0, &request);
+    }
+
+    /// Deletes a request of the caller, pending or not, and refunds its storage.
+    pub fn remove_payment_request(&mut self, id: U64) {
+        let initial_storage = env::storage_usage();
+        let requests = &mut self.payment_requests;
+        let request = requests
+            .requests
+            .remove(&id.0)
+            .expect("Payment request not found");
+        assert_eq!(
+            env::predecessor_account_id(),
+            request.merchant_id,
+            "Only the merchant can remove a payment request"
+        );
+        if request.status == PaymentRequestStatus::Pending {
+            requests.remove_pending(&request, id.0);
+        }
+        requests.flush();
+        refund_released_storage(&request.merchant_id, initial_storage);
+        emit_payment_request_event("payment_request_remove", id.0, &request);
+    }
+
+    pub fn get_payment_request(&self, id: U64) -> Option<PaymentRequest> {
+        self.payment_requests.requests.get(&id.0).cloned()
+    }
+
+    /// Pending requests issued by `account_id`.
+    pub fn get_outgoing_requests(&self, account_id: AccountId) -> Vec<(U64, PaymentRequest)> {
+        self.pending_requests(&account_id, true)
+    }
+
+    /// Pending requests addressed to `account_id`.
+    pub fn get_incoming_requests(&self, account_id: AccountId) -> Vec<(U64, PaymentRequest)> {
+        self.pending_requests(&account_id, false)
+    }
+}
+
+impl Contract {
+    /// Returns a pending request addressed to the caller.
+    fn unwrap_pending_request(&self, request_id: u64) -> PaymentRequest {
+        let request = self
+            .payment_requests
+            .requests
+            .get(&request_id)
+            .cloned()
+            .expect("Payment request not found");
+        assert_eq!(
+            env::predecessor_account_id(),
+            request.payer_id,
+            "Only the payer can settle a payment request"
+        );
+        assert_eq!(
+            request.status,
+            PaymentRequestStatus::Pending,
+            "The request is not pending"
+        );
+        request
+    }
+
+    /// Stores the final status of a request and refunds the storage of its pending entries to
+    /// the merchant.
+    fn internal_settle_payment_request(&mut self, request_id: u64, request: &PaymentRequest) {
+        let initial_storage = env::storage_usage();
+        let requests = &mut self.payment_requests;
+        requests.remove_pending(request, request_id);
+        requests.requests.insert(request_id, request.clone());
+        requests.flush();
+        refund_released_storage(&request.merchant_id, initial_storage);
+    }
+
+    fn pending_requests(
+        &self,
+        account_id: &AccountId,
+        outgoing: bool,
+    ) -> Vec<(U64, PaymentRequest)> {
+        let requests = &self.payment_requests;
+        requests
+            .pending(account_id, outgoing)
+            .into_iter()
+            .filter_map(|request_id| {
+                requests
+                    .requests
+                    .get(&request_id)
+                    .map(|request| (U64(request_id), request.clone()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        call_as(&mut context, accounts(1), ONE_NEAR);
+        contract.create_payment_request(owner(), U128(500), Some("Order 42".to_string()), U64(100));
+        (context, contract)
+    }
+
+    #[test]
+    fn test_pay_request() {
+        let (mut context, mut contract) = setup();
+        let owner = owner();
+        assert_eq!(contract.get_incoming_requests(owner.clone()).len(), 1);
+        testing_env!(context
+            .predecessor_account_id(owner.clone())
+            .attached_deposit(1)
+            .build());
+        contract.pay_request(U64(0));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 500);
+        assert!(matches!(
+            contract.get_payment_request(U64(0)).unwrap().status,
+            PaymentRequestStatus::Paid { .. }
+        ));
+        assert!(contract.get_incoming_requests(owner).is_empty());
+        assert!(contract.get_outgoing_requests(accounts(1)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "The request has expired")]
+    fn test_expired_request() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(1)
+            .block_timestamp(100)
+            .build());
+        contract.pay_request(U64(0));
+    }
+}
@@ -0,0 +1,232 @@
+/*!
+Ledger of the tokens out of circulation, by named pool.
+NOTES:
+  - Every token the contract account holds belongs to a pool: the treasury, staking, the sale,
+    escrows, streams, etc. `pool_balances` lists them with the circulating supply, and
+    circulating supply + pools + unassigned = total supply.
+  - Subsystems that keep a total, like `treasury_balance` or `staking.total_staked`, are their
+    own entry. Subsystems that only keep per-item records, like escrows and streams, credit and
+    debit their pool here as they take and release custody, with `internal_lock_to` and
+    `internal_unlock_from`.
+  - Unassigned tokens are held by the contract account without a pool, e.g. ASTRO returning from
+    a liquidity bootstrap rollback, or custody taken before the ledger existed. Releases of such
+    custody stop the pool at zero instead of failing.
+  - The invariant tests check that the pools never claim more than the contract account holds
+    and that the whole adds up to the total supply, see `invariants`.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::LookupMap;
+use near_sdk::{env, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Pool {
+    Treasury,
+    Staking,
+    StakingRewards,
+    Sale,
+    SaleVesting,
+    FarmingRewards,
+    Referrals,
+    Buyback,
+    ScheduledDistributions,
+    MassDistribution,
+    VoteEscrow,
+    Savings,
+    Escrows,
+    Streams,
+    Airdrops,
+    Drops,
+    PaymentLinks,
+    ScheduledTransfers,
+    Matching,
+    ComplianceHolds,
+    FreezeBonds,
+}
+
+const POOLS: [Pool; 21] = [
+    Pool::Treasury,
+    Pool::Staking,
+    Pool::StakingRewards,
+    Pool::Sale,
+    Pool::SaleVesting,
+    Pool::FarmingRewards,
+    Pool::Referrals,
+    Pool::Buyback,
+    Pool::ScheduledDistributions,
+    Pool::MassDistribution,
+    Pool::VoteEscrow,
+    Pool::Savings,
+    Pool::Escrows,
+    Pool::Streams,
+    Pool::Airdrops,
+    Pool::Drops,
+    Pool::PaymentLinks,
+    Pool::ScheduledTransfers,
+    Pool::Matching,
+    Pool::ComplianceHolds,
+    Pool::FreezeBonds,
+];
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PoolLedger {
+    /// Balances of the pools without a total of their own.
+    pub balances: LookupMap<Pool, Balance>,
+}
+
+impl PoolLedger {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            balances: LookupMap::new(prefix),
+        }
+    }
+
+    pub fn balance(&self, pool: Pool) -> Balance {
+        self.balances.get(&pool).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn credit(&mut self, pool: Pool, amount: Balance) {
+        let balance = self.balance(pool) + amount;
+        self.balances.insert(pool, balance);
+    }
+
+    pub(crate) fn debit(&mut self, pool: Pool, amount: Balance) {
+        let balance = self.balance(pool).saturating_sub(amount);
+        self.balances.insert(pool, balance);
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolBalance {
+    pub pool: Pool,
+    pub balance: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolBalances {
+    pub total_supply: U128,
+    pub circulating_supply: U128,
+    pub pools: Vec<PoolBalance>,
+    /// Held by the contract account without a pool.
+    pub unassigned: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn pool_balances(&self) -> PoolBalances {
+        let custody = self.custody_balance();
+        let pools: Vec<PoolBalance> = POOLS
+            .iter()
+            .map(|pool| PoolBalance {
+                pool: *pool,
+                balance: self.pool_balance(*pool).into(),
+            })
+            .collect();
+        let pooled: Balance = pools.iter().map(|pool| pool.balance.0).sum();
+        PoolBalances {
+            total_supply: self.token.total_supply.into(),
+            circulating_supply: (self.token.total_supply - custody).into(),
+            pools,
+            unassigned: custody.saturating_sub(pooled).into(),
+        }
+    }
+}
+
+impl Contract {
+    pub(crate) fn custody_balance(&self) -> Balance {
+        self.token
+            .accounts
+            .get(&env::current_account_id())
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn pool_balance(&self, pool: Pool) -> Balance {
+        match pool {
+            Pool::Treasury => self.treasury_balance,
+            Pool::Staking => self.staking.total_staked,
+            Pool::StakingRewards => self.staking.reward_pool,
+            Pool::Sale => self.sale.pool,
+            Pool::SaleVesting => self.sale.vesting.held,
+            Pool::FarmingRewards => self.farming.reward_pools(),
+            Pool::Referrals => self.referrals.pool + self.referrals.unclaimed,
+            Pool::Buyback => self.buyback.unburned,
+            Pool::ScheduledDistributions => self.scheduled_distributions.vesting_held(),
+            Pool::MassDistribution => self.mass_distribution.pending,
+            Pool::VoteEscrow => self.vote_escrow.locked,
+            Pool::Savings => self.savings.principal + self.savings.reward_pool,
+            _ => self.pool_ledger.balance(pool),
+        }
+    }
+
+    /// `internal_lock` crediting `pool` in the ledger.
+    pub(crate) fn internal_lock_to(&mut self, pool: Pool, account_id: &AccountId, amount: Balance) {
+        self.internal_lock(account_id, amount);
+        self.pool_ledger.credit(pool, amount);
+    }
+
+    /// `internal_unlock` debiting `pool` in the ledger.
+    pub(crate) fn internal_unlock_from(
+        &mut self,
+        pool: Pool,
+        account_id: &AccountId,
+        amount: Balance,
+    ) {
+        self.pool_ledger.debit(pool, amount);
+        self.internal_unlock(account_id, amount);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U64;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::setup;
+
+    #[test]
+    fn test_pools_and_circulating_add_up_to_supply() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        contract.create_escrow(accounts(1), accounts(2), U128(300), U64(1_000));
+        testing_env!(context.attached_deposit(1).build());
+        contract.treasury_deposit(U128(500));
+
+        let balances = contract.pool_balances();
+        let pool = |name: Pool| {
+            balances
+                .pools
+                .iter()
+                .find(|pool| pool.pool == name)
+                .unwrap()
+                .balance
+                .0
+        };
+        assert_eq!(pool(Pool::Treasury), 500);
+        assert_eq!(pool(Pool::Escrows), 300);
+        assert_eq!(balances.unassigned.0, 0);
+        assert_eq!(balances.circulating_supply.0, TOTAL_SUPPLY - 800);
+        assert_eq!(
+            balances.circulating_supply,
+            contract.ft_circulating_supply()
+        );
+    }
+}
@@ -0,0 +1,254 @@
+/*!
+Presale round of the sale for allowlisted accounts.
+NOTES:
+  - A sale config can include a presale, running from the sale start to the presale `end_at`.
+    Listed accounts buy at a discount, each up to the cap of its tier, and all of them together
+    up to the presale allocation.
+  - Accounts are listed by the owner or the DAO with `add_presale_accounts`, or through a Merkle
+    root of `(account_id, tier)` entries. Leaves are `sha256(0x00 || borsh(account_id, tier))`,
+    inner nodes are hashed as in `balance_proofs`. A buyer proves membership by passing the
+    proof to `buy_presale`.
+  - The presale allocation is part of the sale allocation: whatever the presale does not sell is
+    left to the public round.
+  - Presale purchases count towards the wallet cap of the public round.
+  - The lister pays for the storage of the allowlist.
+*/
+#![cfg_attr(not(feature = "sale"), allow(dead_code, unused_imports))]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, log, near_bindgen, AccountId, CryptoHash};
+
+use crate::balance_proofs::{root_from_proof, ProofStep};
+use crate::sale::SaleConfig;
+use crate::*;
+
+const MAX_TIERS: usize = 10;
+const MAX_ACCOUNTS_PER_CALL: usize = 100;
+const BPS_DENOMINATOR: u128 = 10_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PresaleTier {
+    /// Most an account of this tier can buy in the presale.
+    pub cap: U128,
+    /// Discount on the sale price in basis points.
+    pub discount_bps: u16,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PresaleConfig {
+    pub end_at: U64,
+    pub allocation: U128,
+    pub tiers: Vec<PresaleTier>,
+    /// Root of the Merkle tree of listed accounts, on top of `add_presale_accounts`.
+    pub merkle_root: Option<Base58CryptoHash>,
+}
+
+impl PresaleConfig {
+    pub fn assert_valid(&self, config: &SaleConfig) {
+        assert!(
+            self.end_at.0 > config.start_at.0 && self.end_at.0 <= config.end_at.0,
+            "The presale must end within the sale"
+        );
+        assert!(
+            self.allocation.0 <= config.allocation.0,
+            "The presale allocation exceeds the sale allocation"
+        );
+        assert!(
+            !self.tiers.is_empty() && self.tiers.len() <= MAX_TIERS,
+            "A presale has 1 to {} tiers",
+            MAX_TIERS
+        );
+        assert!(
+            self.tiers
+                .iter()
+                .all(|tier| u128::from(tier.discount_bps) < BPS_DENOMINATOR),
+            "A discount must be below 100%"
+        );
+    }
+}
+
+pub fn presale_leaf_hash(account_id: &AccountId, tier: u8) -> CryptoHash {
+    let mut data = vec![0];
+    data.extend((account_id, tier).try_to_vec().unwrap());
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(&data));
+    hash
+}
+
+#[cfg(feature = "sale")]
+#[near_bindgen]
+impl Contract {
+    /// Lists accounts with their tier for the presale of the current sale. The attached deposit
+    /// covers the allowlist storage.
+    #[payable]
+    pub fn add_presale_accounts(&mut self, accounts: Vec<(AccountId, u8)>) {
+        self.assert_owner_or_dao();
+        let presale = self.unwrap_presale();
+        assert!(
+            accounts.len() <= MAX_ACCOUNTS_PER_CALL,
+            "At most {} accounts per call",
+            MAX_ACCOUNTS_PER_CALL
+        );
+        let initial_storage = env::storage_usage();
+        for (account_id, tier) in accounts.iter() {
+            assert!(
+                usize::from(*tier) < presale.tiers.len(),
+                "Unknown tier {}",
+                tier
+            );
+            self.sale
+                .presale_accounts
+                .insert((self.sale.sale_id, account_id.clone()), *tier);
+        }
+        self.sale.presale_accounts.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        log!("Listed {} accounts for the presale", accounts.len());
+    }
+
+    /// Buys tokens in the presale at the discount of `tier`. Accounts listed through the Merkle
+    /// root pass their `proof`.
+    #[payable]
+    pub fn buy_presale(&mut self, tier: u8, proof: Option<Vec<ProofStep>>) -> U128 {
+        let config = self.sale.config.clone().expect("No sale is configured");
+        let presale = self.unwrap_presale();
+        let now = env::block_timestamp();
+        assert!(
+            now >= config.start_at.0 && now < presale.end_at.0,
+            "The presale is not open"
+        );
+        let buyer_id = env::predecessor_account_id();
+        let listed = match (proof, presale.merkle_root) {
+            (Some(proof), Some(root)) => {
+                root_from_proof(presale_leaf_hash(&buyer_id, tier), &proof)
+                    == CryptoHash::from(root)
+            }
+            _ => self.get_presale_tier(buyer_id.clone()) == Some(tier),
+        };
+        assert!(listed, "The account is not listed for this tier");
+        let tier_config = presale
+            .tiers
+            .get(usize::from(tier))
+            .unwrap_or_else(|| env::panic_str("Unknown tier"));
+        let price = mul_div(
+            config.price.0,
+            BPS_DENOMINATOR - u128::from(tier_config.discount_bps),
+            BPS_DENOMINATOR,
+        );
+        let max_amount = tier_config
+            .cap
+            .0
+            .saturating_sub(self.sale.purchased(&buyer_id))
+            .min(presale.allocation.0 - self.sale.presale_sold);
+        let amount = self.internal_buy(price, max_amount);
+        self.sale.presale_sold += amount;
+        amount.into()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Tier of `account_id` in the allowlist of the current sale. Accounts listed through the
+    /// Merkle root are not known to the contract.
+    pub fn get_presale_tier(&self, account_id: AccountId) -> Option<u8> {
+        self.sale
+            .presale_accounts
+            .get(&(self.sale.sale_id, account_id))
+            .copied()
+    }
+}
+
+impl Contract {
+    fn unwrap_presale(&self) -> PresaleConfig {
+        self.sale
+            .config
+            .as_ref()
+            .and_then(|config| config.presale.clone())
+            .expect("The sale has no presale")
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "sale"))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::balance_proofs::node_hash;
+    use crate::dutch_auction::SalePricing;
+    use crate::test_fixtures::{self, ONE_NEAR};
+
+    const ONE_ASTRO: Balance = 1_000_000_000_000_000_000;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = test_fixtures::setup(&[accounts(1), accounts(2)]);
+        let root = node_hash(
+            &presale_leaf_hash(&accounts(2), 1),
+            &presale_leaf_hash(&accounts(3), 0),
+        );
+        contract.set_sale_config(SaleConfig {
+            price: U128(ONE_NEAR / 10),
+            start_at: U64(0),
+            end_at: U64(2_000),
+            allocation: U128(100 * ONE_ASTRO),
+            per_wallet_cap: U128(50 * ONE_ASTRO),
+            mint: true,
+            pricing: SalePricing::Fixed,
+            presale: Some(PresaleConfig {
+                end_at: U64(1_000),
+                allocation: U128(40 * ONE_ASTRO),
+                tiers: vec![
+                    PresaleTier {
+                        cap: U128(10 * ONE_ASTRO),
+                        discount_bps: 5_000,
+                    },
+                    PresaleTier {
+                        cap: U128(20 * ONE_ASTRO),
+                        discount_bps: 2_000,
+                    },
+                ],
+                merkle_root: Some(root.into()),
+            }),
+            vesting: None,
+        });
+        contract.add_presale_accounts(vec![(accounts(1), 0)]);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_presale_discount_and_rollover() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        assert_eq!(contract.buy_presale(0, None).0, 10 * ONE_ASTRO);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(10 * ONE_NEAR)
+            .build());
+        let proof = vec![ProofStep {
+            hash: presale_leaf_hash(&accounts(3), 0).into(),
+            is_left: false,
+        }];
+        assert_eq!(contract.buy_presale(1, Some(proof)).0, 20 * ONE_ASTRO);
+        assert_eq!(contract.get_sale_stats().presale_sold.0, 30 * ONE_ASTRO);
+
+        // The public round sells the 70 left, including the 10 the presale did not sell.
+        testing_env!(context.block_timestamp(1_000).build());
+        assert_eq!(contract.buy(None).0, 30 * ONE_ASTRO);
+        assert_eq!(contract.get_sale_stats().remaining.0, 40 * ONE_ASTRO);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account is not listed for this tier")]
+    fn test_presale_requires_listing() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.buy_presale(1, None);
+    }
+}
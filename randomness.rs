@@ -0,0 +1,224 @@
+/*!
+Pluggable randomness for raffle and lottery-like modules.
+NOTES:
+  - Consumers ask for a seed by request id through `Contract::random_seed` instead of reading
+    `env::random_seed` directly, so the source can be swapped without touching them. Request ids
+    are chosen by the consumer and must be unique across modules.
+  - `BlockSeed` mixes the block's random seed with the request id. It is available immediately
+    but can be influenced by the block producer.
+  - `CommitReveal`: the owner or the DAO commits `sha256(secret)` for a request before the draw
+    and reveals the secret afterwards. The seed is `sha256(secret)`.
+  - `ExternalVrf`: `request_randomness`, called by the owner or the DAO, asks the configured VRF
+    contract for a value, which it delivers through `fulfill_randomness`.
+  - With the last two sources a seed is only available once revealed or fulfilled, consumers
+    have to wait for it. The source is selected by the owner, the DAO or a governance proposal.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base58CryptoHash, Base64VecU8, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::LookupMap;
+use near_sdk::{env, ext_contract, log, near_bindgen, AccountId, CryptoHash, Gas, Promise};
+
+use crate::*;
+
+const GAS_FOR_VRF_REQUEST: Gas = Gas(10_000_000_000_000);
+
+#[ext_contract(ext_vrf)]
+#[allow(dead_code)]
+pub trait VrfProvider {
+    /// Requests a random value for `request_id`, delivered with `fulfill_randomness`.
+    fn request_randomness(&mut self, request_id: U64);
+}
+
+/// A source of 32-byte random seeds keyed by request id.
+pub trait RandomnessSource {
+    /// Returns the seed for `request_id`, or `None` if it is not available yet.
+    fn seed(&self, request_id: u64) -> Option<CryptoHash>;
+}
+
+pub struct BlockSeed;
+
+impl RandomnessSource for BlockSeed {
+    fn seed(&self, request_id: u64) -> Option<CryptoHash> {
+        let mut data = env::random_seed();
+        data.extend_from_slice(&request_id.to_le_bytes());
+        Some(sha256_hash(&data))
+    }
+}
+
+pub struct CommitReveal<'a> {
+    revealed: &'a LookupMap<u64, CryptoHash>,
+}
+
+impl RandomnessSource for CommitReveal<'_> {
+    fn seed(&self, request_id: u64) -> Option<CryptoHash> {
+        self.revealed.get(&request_id).copied()
+    }
+}
+
+pub struct ExternalVrf<'a> {
+    fulfilled: &'a LookupMap<u64, CryptoHash>,
+}
+
+impl RandomnessSource for ExternalVrf<'_> {
+    fn seed(&self, request_id: u64) -> Option<CryptoHash> {
+        self.fulfilled.get(&request_id).copied()
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum RandomnessSourceKind {
+    BlockSeed,
+    CommitReveal,
+    ExternalVrf { vrf_contract_id: AccountId },
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Randomness {
+    pub kind: RandomnessSourceKind,
+    pub commits: LookupMap<u64, CryptoHash>,
+    pub revealed: LookupMap<u64, CryptoHash>,
+    pub fulfilled: LookupMap<u64, CryptoHash>,
+}
+
+fn sha256_hash(data: &[u8]) -> CryptoHash {
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(data));
+    hash
+}
+
+impl Randomness {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            kind: RandomnessSourceKind::BlockSeed,
+            commits: LookupMap::new([prefix.as_slice(), b"c"].concat()),
+            revealed: LookupMap::new([prefix.as_slice(), b"r"].concat()),
+            fulfilled: LookupMap::new([prefix.as_slice(), b"f"].concat()),
+        }
+    }
+
+    pub fn source(&self) -> Box<dyn RandomnessSource + '_> {
+        match &self.kind {
+            RandomnessSourceKind::BlockSeed => Box::new(BlockSeed),
+            RandomnessSourceKind::CommitReveal => Box::new(CommitReveal {
+                revealed: &self.revealed,
+            }),
+            RandomnessSourceKind::ExternalVrf { .. } => Box::new(ExternalVrf {
+                fulfilled: &self.fulfilled,
+            }),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_randomness_source(&mut self, source: RandomnessSourceKind) {
+        self.assert_owner_or_dao();
+        self.internal_set_randomness_source(source);
+    }
+
+    pub fn get_randomness_source(&self) -> RandomnessSourceKind {
+        self.randomness.kind.clone()
+    }
+
+    /// Commits to `sha256(secret)` for a request of the commit-reveal source.
+    pub fn commit_randomness(&mut self, request_id: U64, commitment: Base58CryptoHash) {
+        self.assert_owner_or_dao();
+        assert!(
+            !self.randomness.commits.contains_key(&request_id.0),
+            "Already committed"
+        );
+        self.randomness
+            .commits
+            .insert(request_id.0, commitment.into());
+    }
+
+    pub fn reveal_randomness(&mut self, request_id: U64, secret: Base64VecU8) {
+        self.assert_owner_or_dao();
+        let commitment = self
+            .randomness
+            .commits
+            .get(&request_id.0)
+            .copied()
+            .expect("No commitment for this request");
+        let seed = sha256_hash(&secret.0);
+        assert!(
+            seed == commitment,
+            "The secret does not match the commitment"
+        );
+        assert!(
+            !self.randomness.revealed.contains_key(&request_id.0),
+            "Already revealed"
+        );
+        self.randomness.revealed.insert(request_id.0, seed);
+    }
+
+    /// Called by the configured VRF contract with the value for a request.
+    pub fn fulfill_randomness(&mut self, request_id: U64, value: Base58CryptoHash) {
+        match &self.randomness.kind {
+            RandomnessSourceKind::ExternalVrf { vrf_contract_id } => assert_eq!(
+                &env::predecessor_account_id(),
+                vrf_contract_id,
+                "Only the VRF contract can fulfill randomness"
+            ),
+            _ => env::panic_str("The randomness source is not an external VRF"),
+        }
+        assert!(
+            !self.randomness.fulfilled.contains_key(&request_id.0),
+            "Already fulfilled"
+        );
+        self.randomness.fulfilled.insert(request_id.0, value.into());
+    }
+
+    /// Asks the VRF contract for the value of `request_id`.
+    pub fn request_randomness(&mut self, request_id: U64) -> Promise {
+        self.assert_owner_or_dao();
+        match &self.randomness.kind {
+            RandomnessSourceKind::ExternalVrf { vrf_contract_id } => {
+                ext_vrf::ext(vrf_contract_id.clone())
+                    .with_static_gas(GAS_FOR_VRF_REQUEST)
+                    .request_randomness(request_id)
+            }
+            _ => env::panic_str("The randomness source is not an external VRF"),
+        }
+    }
+
+    pub fn get_random_seed(&self, request_id: U64) -> Option<Base58CryptoHash> {
+        self.random_seed(request_id.0).map(Into::into)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_set_randomness_source(&mut self, source: RandomnessSourceKind) {
+        log!("Randomness source set to {:?}", source);
+        self.randomness.kind = source;
+    }
+
+    /// Seed for `request_id` from the selected source, `None` while it is not available.
+    pub(crate) fn random_seed(&self, request_id: u64) -> Option<CryptoHash> {
+        self.randomness.source().seed(request_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_commit_reveal_seed() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        assert!(contract.random_seed(7).is_some());
+
+        contract.set_randomness_source(RandomnessSourceKind::CommitReveal);
+        let secret = b"secret".to_vec();
+        let commitment = sha256_hash(&secret);
+        contract.commit_randomness(U64(7), commitment.into());
+        assert!(contract.random_seed(7).is_none());
+        contract.reveal_randomness(U64(7), secret.into());
+        assert_eq!(contract.random_seed(7), Some(commitment));
+    }
+}
@@ -0,0 +1,149 @@
+/*!
+Receive hooks: notifications of incoming transfers to a contract chosen by the receiver.
+NOTES:
+  - A registered account sets a contract and a method with `set_receive_hook`. Every plain
+    `ft_transfer` to the account then calls that method with `sender_id`, `receiver_id`, `amount`
+    and `memo` as JSON, so custodial wallets and games can react without polling.
+  - `ft_transfer_call` already calls the receiver and is not notified, nor are mints, payouts of
+    the contract or transfers held for KYC. The amount is the one received, after transfer fees.
+  - Like spend hooks, the call is best-effort: its result is not awaited, a failing hook does not
+    revert the transfer and it gets a fixed amount of gas paid by the transfer.
+  - The account pays for the storage of its hook and gets it back when removing it.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupMap;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, Gas, Promise};
+
+use crate::*;
+
+const GAS_FOR_RECEIVE_HOOK: Gas = Gas(5_000_000_000_000);
+const MAX_METHOD_LEN: usize = 64;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReceiveHook {
+    pub contract_id: AccountId,
+    pub method: String,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ReceiveHooks {
+    pub hooks: LookupMap<AccountId, ReceiveHook>,
+}
+
+impl ReceiveHooks {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            hooks: LookupMap::new(prefix),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the contract and method notified of the caller's incoming `ft_transfer`s, `None` to
+    /// remove it. Setting one takes a deposit for its storage, the excess is refunded.
+    #[payable]
+    pub fn set_receive_hook(&mut self, hook: Option<ReceiveHook>) {
+        let account_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        match hook {
+            Some(hook) => {
+                assert!(
+                    self.token.accounts.contains_key(&account_id),
+                    "The account is not registered"
+                );
+                assert_ne!(
+                    hook.contract_id,
+                    env::current_account_id(),
+                    "The hook cannot be this contract"
+                );
+                assert!(
+                    !hook.method.is_empty() && hook.method.len() <= MAX_METHOD_LEN,
+                    "The method name should be 1 to {} bytes",
+                    MAX_METHOD_LEN
+                );
+                log!(
+                    "@{} set the receive hook @{}.{}",
+                    account_id,
+                    hook.contract_id,
+                    hook.method
+                );
+                self.receive_hooks.hooks.insert(account_id, hook);
+                self.receive_hooks.hooks.flush();
+                refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+            }
+            None => {
+                self.receive_hooks.hooks.remove(&account_id);
+                self.receive_hooks.hooks.flush();
+                refund_released_storage(&account_id, initial_storage);
+                log!("@{} removed its receive hook", account_id);
+            }
+        }
+    }
+
+    pub fn get_receive_hook(&self, account_id: AccountId) -> Option<ReceiveHook> {
+        self.receive_hooks.hooks.get(&account_id).cloned()
+    }
+}
+
+impl Contract {
+    /// Notifies the receive hook of `receiver_id`, if any, that it got `amount` from `sender_id`.
+    pub(crate) fn notify_receive(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: &Option<String>,
+    ) {
+        if amount == 0 {
+            return;
+        }
+        if let Some(hook) = self.receive_hooks.hooks.get(receiver_id) {
+            let args = json!({
+                "sender_id": sender_id,
+                "receiver_id": receiver_id,
+                "amount": U128(amount),
+                "memo": memo,
+            });
+            Promise::new(hook.contract_id.clone()).function_call(
+                hook.method.clone(),
+                args.to_string().into_bytes(),
+                0,
+                GAS_FOR_RECEIVE_HOOK,
+            );
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, get_created_receipts};
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    #[test]
+    fn test_transfer_notifies_the_receive_hook() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        call_as(&mut context, accounts(1), ONE_NEAR);
+        contract.set_receive_hook(Some(ReceiveHook {
+            contract_id: accounts(3),
+            method: "on_astro_received".to_string(),
+        }));
+        assert_eq!(
+            contract.get_receive_hook(accounts(1)).unwrap().method,
+            "on_astro_received"
+        );
+
+        call_as(&mut context, owner(), 1);
+        contract.ft_transfer(accounts(1), U128(10), None);
+        assert!(get_created_receipts()
+            .iter()
+            .any(|receipt| receipt.receiver_id == accounts(3)));
+    }
+}
@@ -0,0 +1,83 @@
+/*!
+Optional allowlist of receiver contracts for `ft_transfer_call`.
+NOTES:
+  - While enabled, `ft_transfer_call` can only target receivers added by the owner, so malicious
+    receivers cannot grief the transfer call gas. Plain `ft_transfer` is not restricted.
+  - Meant for the launch period: the owner can switch the allowlist off at any time, the list is
+    kept and applies again if it is switched back on.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::store::LookupSet;
+use near_sdk::{near_bindgen, AccountId};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ReceiverAllowlist {
+    pub enabled: bool,
+    pub receivers: LookupSet<AccountId>,
+}
+
+impl ReceiverAllowlist {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            enabled: false,
+            receivers: LookupSet::new(prefix),
+        }
+    }
+
+    pub fn assert_allowed(&self, receiver_id: &AccountId) {
+        assert!(
+            !self.enabled || self.receivers.contains(receiver_id),
+            "The receiver {} is not allowed for transfer calls",
+            receiver_id
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_receiver_allowlist_enabled(&mut self, enabled: bool) {
+        self.assert_owner();
+        self.receiver_allowlist.enabled = enabled;
+    }
+
+    pub fn add_allowed_receiver(&mut self, receiver_id: AccountId) {
+        self.assert_owner();
+        self.receiver_allowlist.receivers.insert(receiver_id);
+    }
+
+    pub fn remove_allowed_receiver(&mut self, receiver_id: AccountId) {
+        self.assert_owner();
+        self.receiver_allowlist.receivers.remove(&receiver_id);
+    }
+
+    pub fn is_receiver_allowlist_enabled(&self) -> bool {
+        self.receiver_allowlist.enabled
+    }
+
+    pub fn is_allowed_receiver(&self, receiver_id: AccountId) -> bool {
+        self.receiver_allowlist.receivers.contains(&receiver_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    #[should_panic(expected = "is not allowed for transfer calls")]
+    fn test_transfer_call_to_unlisted_receiver() {
+        let (mut context, mut contract) = setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner(), 0);
+        contract.set_receiver_allowlist_enabled(true);
+        contract.add_allowed_receiver(accounts(1));
+        call_as(&mut context, owner(), 1);
+        contract.ft_transfer_call(accounts(1), U128(10), None, "".to_string());
+        contract.ft_transfer_call(accounts(2), U128(10), None, "".to_string());
+    }
+}
@@ -0,0 +1,236 @@
+/*!
+Capability descriptors published by `ft_transfer_call` receivers.
+NOTES:
+  - A receiver contract registers what it understands with `set_receiver_capabilities`: the
+    message schemas it accepts and the largest amount it handles. Transfer calls to a registered
+    receiver that do not match fail before any tokens move, instead of being lost or refunded
+    after a failed receiver call. Transfer calls to other receivers are not checked.
+  - A schema names a top-level key of a JSON object message, e.g. `"actions"` or `"Execute"`.
+    The empty schema `""` accepts an empty message.
+  - Receivers pay for the storage of their descriptor and get it back when they remove it.
+*/
+use std::fmt;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::{self, Value};
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const MAX_MSG_SCHEMAS: usize = 10;
+const MAX_SCHEMA_LEN: usize = 64;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReceiverCapabilities {
+    pub msg_schemas: Vec<String>,
+    pub max_amount: Option<U128>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum CapabilityError {
+    UnsupportedMsg { msg_schemas: Vec<String> },
+    AmountTooLarge { max_amount: U128 },
+}
+
+impl fmt::Display for CapabilityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CapabilityError::UnsupportedMsg { msg_schemas } => write!(
+                f,
+                "The receiver does not support this msg, supported schemas: {:?}",
+                msg_schemas
+            ),
+            CapabilityError::AmountTooLarge { max_amount } => {
+                write!(f, "The receiver accepts at most {}", max_amount.0)
+            }
+        }
+    }
+}
+
+impl ReceiverCapabilities {
+    pub fn check(&self, amount: Balance, msg: &str) -> Result<(), CapabilityError> {
+        if let Some(max_amount) = self.max_amount {
+            if amount > max_amount.0 {
+                return Err(CapabilityError::AmountTooLarge { max_amount });
+            }
+        }
+        let supported = if msg.is_empty() {
+            self.msg_schemas.iter().any(String::is_empty)
+        } else {
+            match serde_json::from_str::<Value>(msg) {
+                Ok(Value::Object(object)) => self
+                    .msg_schemas
+                    .iter()
+                    .any(|schema| object.contains_key(schema)),
+                _ => false,
+            }
+        };
+        if supported {
+            Ok(())
+        } else {
+            Err(CapabilityError::UnsupportedMsg {
+                msg_schemas: self.msg_schemas.clone(),
+            })
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ReceiverCapabilitiesRegistry {
+    pub receivers: LookupMap<AccountId, ReceiverCapabilities>,
+}
+
+impl ReceiverCapabilitiesRegistry {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            receivers: LookupMap::new(prefix),
+        }
+    }
+
+    /// Checks a transfer call against the descriptor of `receiver_id`, if it registered one.
+    pub fn check(
+        &self,
+        receiver_id: &AccountId,
+        amount: Balance,
+        msg: &str,
+    ) -> Result<(), CapabilityError> {
+        match self.receivers.get(receiver_id) {
+            Some(capabilities) => capabilities.check(amount, msg),
+            None => Ok(()),
+        }
+    }
+
+    pub fn assert_supported(&self, receiver_id: &AccountId, amount: Balance, msg: &str) {
+        if let Err(error) = self.check(receiver_id, amount, msg) {
+            env::panic_str(&error.to_string());
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers the capabilities of the calling receiver contract.
+    /// The attached deposit covers the descriptor storage.
+    #[payable]
+    pub fn set_receiver_capabilities(&mut self, capabilities: ReceiverCapabilities) {
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        assert!(
+            !capabilities.msg_schemas.is_empty()
+                && capabilities.msg_schemas.len() <= MAX_MSG_SCHEMAS,
+            "A receiver supports 1 to {} msg schemas",
+            MAX_MSG_SCHEMAS
+        );
+        assert!(
+            capabilities
+                .msg_schemas
+                .iter()
+                .all(|schema| schema.len() <= MAX_SCHEMA_LEN),
+            "A msg schema has at most {} bytes",
+            MAX_SCHEMA_LEN
+        );
+        let receiver_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        self.receiver_capabilities
+            .receivers
+            .insert(receiver_id.clone(), capabilities);
+        self.receiver_capabilities.receivers.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        log!("@{} registered its receiver capabilities", receiver_id);
+    }
+
+    #[payable]
+    pub fn remove_receiver_capabilities(&mut self) {
+        assert_one_yocto();
+        let receiver_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        assert!(
+            self.receiver_capabilities
+                .receivers
+                .remove(&receiver_id)
+                .is_some(),
+            "No receiver capabilities registered"
+        );
+        self.receiver_capabilities.receivers.flush();
+        refund_released_storage(&receiver_id, initial_storage);
+    }
+
+    pub fn get_receiver_capabilities(
+        &self,
+        receiver_id: AccountId,
+    ) -> Option<ReceiverCapabilities> {
+        self.receiver_capabilities
+            .receivers
+            .get(&receiver_id)
+            .cloned()
+    }
+
+    /// The error a transfer call to `receiver_id` would fail with, if any.
+    pub fn check_transfer_call(
+        &self,
+        receiver_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> Option<CapabilityError> {
+        self.receiver_capabilities
+            .check(&receiver_id, amount.0, &msg)
+            .err()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    fn setup() -> Contract {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        call_as(&mut context, accounts(1), ONE_NEAR);
+        contract.set_receiver_capabilities(ReceiverCapabilities {
+            msg_schemas: vec!["actions".to_string()],
+            max_amount: Some(U128(100)),
+        });
+        call_as(&mut context, owner(), 1);
+        contract
+    }
+
+    #[test]
+    fn test_check_transfer_call() {
+        let contract = setup();
+        let check = |amount, msg: &str| {
+            contract.check_transfer_call(accounts(1), U128(amount), msg.to_string())
+        };
+        assert_eq!(check(100, r#"{"actions": []}"#), None);
+        assert_eq!(
+            check(101, r#"{"actions": []}"#),
+            Some(CapabilityError::AmountTooLarge {
+                max_amount: U128(100)
+            })
+        );
+        assert!(matches!(
+            check(10, ""),
+            Some(CapabilityError::UnsupportedMsg { .. })
+        ));
+        assert!(check(10, "anything").is_some());
+        assert_eq!(
+            contract.check_transfer_call(accounts(2), U128(10), "".to_string()),
+            None
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The receiver does not support this msg")]
+    fn test_transfer_call_fails_fast() {
+        let mut contract = setup();
+        contract.ft_transfer_call(accounts(1), U128(10), None, "deposit".to_string());
+    }
+}
@@ -0,0 +1,533 @@
+/*!
+Account recovery through a guardian service chosen by the holder.
+NOTES:
+  - The owner maintains the list of guardian services. A holder opts in with `set_guardian`,
+    naming one of them, a recovery account and a challenge period of at least
+    `MIN_CHALLENGE_PERIOD`.
+  - The guardian starts a recovery with `initiate_recovery`. The holder can cancel it with
+    `cancel_recovery` until the challenge period has passed; after that the guardian can
+    execute it, moving the ASTRO balance and the staking position to the recovery account.
+  - Alternatively a holder sets their own guardians with `set_recovery_guardians`. Any of them can
+    propose a recovery to a new account; once `threshold` guardians approved it, it can be
+    executed after the timelock. The holder can cancel the request at any time before that.
+  - Every transition is logged as an NEP-297 event, so wallets can warn the holder.
+  - Holders pay for the storage of their guardian configuration, the proposing guardian for the
+    storage of a recovery request, which is refunded once the request is resolved.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::{LookupMap, LookupSet};
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+/// Minimum challenge period of 24 hours in nanoseconds.
+const MIN_CHALLENGE_PERIOD: u64 = 24 * 60 * 60 * 1_000_000_000;
+const MAX_GUARDIANS: usize = 10;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GuardianConfig {
+    pub guardian_id: AccountId,
+    pub recovery_id: AccountId,
+    pub challenge_period: U64,
+    /// Set while a recovery is pending: the time from which it can be executed.
+    pub executable_at: Option<U64>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GuardianSet {
+    pub guardians: Vec<AccountId>,
+    /// Number of guardian approvals a recovery needs.
+    pub threshold: u8,
+    /// Time in nanoseconds between the last approval and the execution of a recovery.
+    pub timelock: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RecoveryRequest {
+    pub new_account_id: AccountId,
+    /// Guardian who proposed the recovery and paid for the request storage.
+    pub proposer_id: AccountId,
+    pub approvals: Vec<AccountId>,
+    /// Set once the threshold is reached: the time from which the recovery can be executed.
+    pub executable_at: Option<U64>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Recovery {
+    pub guardian_services: LookupSet<AccountId>,
+    pub accounts: LookupMap<AccountId, GuardianConfig>,
+    pub guardian_sets: LookupMap<AccountId, GuardianSet>,
+    pub requests: LookupMap<AccountId, RecoveryRequest>,
+}
+
+impl Recovery {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            guardian_services: LookupSet::new([prefix.as_slice(), b"g"].concat()),
+            accounts: LookupMap::new([prefix.as_slice(), b"a"].concat()),
+            guardian_sets: LookupMap::new([prefix.as_slice(), b"s"].concat()),
+            requests: LookupMap::new([prefix.as_slice(), b"r"].concat()),
+        }
+    }
+
+    pub fn unwrap_guardian_set(&self, account_id: &AccountId) -> GuardianSet {
+        self.guardian_sets
+            .get(account_id)
+            .cloned()
+            .expect("No recovery guardians set")
+    }
+
+    pub fn unwrap_request(&self, account_id: &AccountId) -> RecoveryRequest {
+        self.requests
+            .get(account_id)
+            .cloned()
+            .expect("No recovery request")
+    }
+
+    pub fn unwrap_config(&self, account_id: &AccountId) -> GuardianConfig {
+        self.accounts
+            .get(account_id)
+            .cloned()
+            .expect("No guardian configured")
+    }
+}
+
+fn emit_recovery_event(event: &str, account_id: &AccountId, config: &GuardianConfig) {
+    emit_event(
+        EVENT_VERSION,
+        event,
+        json!({
+            "account_id": account_id,
+            "guardian_id": config.guardian_id,
+            "recovery_id": config.recovery_id,
+            "executable_at": config.executable_at,
+        }),
+    );
+}
+
+fn emit_request_event(event: &str, account_id: &AccountId, request: &RecoveryRequest) {
+    emit_event(
+        EVENT_VERSION,
+        event,
+        json!({
+            "account_id": account_id,
+            "new_account_id": request.new_account_id,
+            "approvals": request.approvals,
+            "executable_at": request.executable_at,
+        }),
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn add_guardian_service(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.recovery.guardian_services.insert(account_id);
+    }
+
+    pub fn remove_guardian_service(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.recovery.guardian_services.remove(&account_id);
+    }
+
+    /// Lets `guardian_id` recover the caller's holdings to `recovery_id` after
+    /// `challenge_period` nanoseconds. Replacing a configuration cancels a pending recovery.
+    /// The attached deposit covers the configuration storage.
+    #[payable]
+    pub fn set_guardian(
+        &mut self,
+        guardian_id: AccountId,
+        recovery_id: AccountId,
+        challenge_period: U64,
+    ) {
+        assert!(
+            self.recovery.guardian_services.contains(&guardian_id),
+            "@{} is not a guardian service",
+            guardian_id
+        );
+        assert!(
+            challenge_period.0 >= MIN_CHALLENGE_PERIOD,
+            "Challenge period must be at least {} nanoseconds",
+            MIN_CHALLENGE_PERIOD
+        );
+        let account_id = env::predecessor_account_id();
+        assert_ne!(
+            account_id, recovery_id,
+            "Recovery account must be another account"
+        );
+        assert!(
+            self.token.accounts.contains_key(&recovery_id),
+            "The account {} is not registered",
+            recovery_id
+        );
+        let initial_storage = env::storage_usage();
+        let config = GuardianConfig {
+            guardian_id,
+            recovery_id,
+            challenge_period,
+            executable_at: None,
+        };
+        emit_recovery_event("guardian_set", &account_id, &config);
+        self.recovery.accounts.insert(account_id, config);
+        self.recovery.accounts.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+    }
+
+    #[payable]
+    pub fn remove_guardian(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let config = self.recovery.unwrap_config(&account_id);
+        self.recovery.accounts.remove(&account_id);
+        emit_recovery_event("guardian_remove", &account_id, &config);
+    }
+
+    /// Starts the challenge period of a recovery of `account_id`. Only callable by its guardian.
+    pub fn initiate_recovery(&mut self, account_id: AccountId) {
+        let mut config = self.recovery.unwrap_config(&account_id);
+        assert_eq!(
+            env::predecessor_account_id(),
+            config.guardian_id,
+            "Only the guardian can initiate a recovery"
+        );
+        assert!(
+            config.executable_at.is_none(),
+            "Recovery is already pending"
+        );
+        config.executable_at = Some((env::block_timestamp() + config.challenge_period.0).into());
+        emit_recovery_event("recovery_initiate", &account_id, &config);
+        self.recovery.accounts.insert(account_id, config);
+    }
+
+    /// Cancels a pending recovery of the caller's account.
+    #[payable]
+    pub fn cancel_recovery(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut config = self.recovery.unwrap_config(&account_id);
+        assert!(config.executable_at.is_some(), "No recovery is pending");
+        emit_recovery_event("recovery_cancel", &account_id, &config);
+        config.executable_at = None;
+        self.recovery.accounts.insert(account_id, config);
+    }
+
+    /// Moves the balance and staking position of `account_id` to its recovery account once the
+    /// challenge period has passed. Only callable by its guardian.
+    pub fn execute_recovery(&mut self, account_id: AccountId) {
+        let config = self.recovery.unwrap_config(&account_id);
+        assert_eq!(
+            env::predecessor_account_id(),
+            config.guardian_id,
+            "Only the guardian can execute a recovery"
+        );
+        let executable_at = config.executable_at.expect("No recovery is pending");
+        assert!(
+            env::block_timestamp() >= executable_at.0,
+            "Challenge period has not passed"
+        );
+        self.recovery.accounts.remove(&account_id);
+        self.internal_recover(&account_id, &config.recovery_id);
+        emit_recovery_event("recovery_execute", &account_id, &config);
+    }
+
+    pub fn get_guardian(&self, account_id: AccountId) -> Option<GuardianConfig> {
+        self.recovery.accounts.get(&account_id).cloned()
+    }
+
+    /// Lets `threshold` of `guardians` recover the caller's holdings to an account of their
+    /// choice, `timelock` nanoseconds after the last approval. Replacing the guardians cancels a
+    /// pending request. The attached deposit covers the guardians storage.
+    #[payable]
+    pub fn set_recovery_guardians(
+        &mut self,
+        guardians: Vec<AccountId>,
+        threshold: u8,
+        timelock: U64,
+    ) {
+        assert!(
+            !guardians.is_empty() && guardians.len() <= MAX_GUARDIANS,
+            "Set 1 to {} guardians",
+            MAX_GUARDIANS
+        );
+        assert!(
+            threshold > 0 && usize::from(threshold) <= guardians.len(),
+            "The threshold must be between 1 and the number of guardians"
+        );
+        assert!(
+            timelock.0 >= MIN_CHALLENGE_PERIOD,
+            "Timelock must be at least {} nanoseconds",
+            MIN_CHALLENGE_PERIOD
+        );
+        let account_id = env::predecessor_account_id();
+        for (i, guardian_id) in guardians.iter().enumerate() {
+            assert_ne!(guardian_id, &account_id, "Cannot guard your own account");
+            assert!(
+                !guardians[..i].contains(guardian_id),
+                "Duplicate guardian {}",
+                guardian_id
+            );
+        }
+        self.internal_remove_recovery_request(&account_id, "recovery_request_cancel");
+        let initial_storage = env::storage_usage();
+        self.recovery.guardian_sets.insert(
+            account_id.clone(),
+            GuardianSet {
+                guardians,
+                threshold,
+                timelock,
+            },
+        );
+        self.recovery.guardian_sets.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        log!("@{} set recovery guardians", account_id);
+    }
+
+    #[payable]
+    pub fn remove_recovery_guardians(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.internal_remove_recovery_request(&account_id, "recovery_request_cancel");
+        let initial_storage = env::storage_usage();
+        assert!(
+            self.recovery.guardian_sets.remove(&account_id).is_some(),
+            "No recovery guardians set"
+        );
+        self.recovery.guardian_sets.flush();
+        refund_released_storage(&account_id, initial_storage);
+        log!("@{} removed their recovery guardians", account_id);
+    }
+
+    /// Proposes to recover `account_id` to `new_account_id`, counting as the first approval.
+    /// Only callable by a guardian of the account, who attaches deposit for the request storage.
+    #[payable]
+    pub fn propose_recovery(&mut self, account_id: AccountId, new_account_id: AccountId) {
+        let guardian_id = self.assert_recovery_guardian(&account_id);
+        assert!(
+            self.recovery.requests.get(&account_id).is_none(),
+            "A recovery request is already pending"
+        );
+        assert_ne!(
+            account_id, new_account_id,
+            "Recovery account must be another account"
+        );
+        assert!(
+            self.token.accounts.contains_key(&new_account_id),
+            "The account {} is not registered",
+            new_account_id
+        );
+        let initial_storage = env::storage_usage();
+        let request = RecoveryRequest {
+            new_account_id,
+            proposer_id: guardian_id.clone(),
+            approvals: vec![],
+            executable_at: None,
+        };
+        emit_request_event("recovery_request_create", &account_id, &request);
+        self.recovery.requests.insert(account_id.clone(), request);
+        self.internal_approve_recovery(&account_id, guardian_id);
+        self.recovery.requests.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+    }
+
+    /// Approves the pending recovery of `account_id`. Only callable by its guardians.
+    pub fn approve_recovery(&mut self, account_id: AccountId) {
+        let guardian_id = self.assert_recovery_guardian(&account_id);
+        self.internal_approve_recovery(&account_id, guardian_id);
+    }
+
+    /// Cancels the pending recovery request of the caller's account.
+    #[payable]
+    pub fn cancel_recovery_request(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.internal_remove_recovery_request(&account_id, "recovery_request_cancel"),
+            "No recovery request"
+        );
+    }
+
+    /// Moves the balance and staking position of `account_id` to the account of its approved
+    /// request once the timelock has passed. Callable by anyone.
+    pub fn execute_recovery_request(&mut self, account_id: AccountId) {
+        let request = self.recovery.unwrap_request(&account_id);
+        let executable_at = request
+            .executable_at
+            .expect("The recovery request is not approved");
+        assert!(
+            env::block_timestamp() >= executable_at.0,
+            "Timelock has not passed"
+        );
+        self.internal_remove_recovery_request(&account_id, "recovery_request_execute");
+        self.internal_recover(&account_id, &request.new_account_id);
+    }
+
+    pub fn get_recovery_guardians(&self, account_id: AccountId) -> Option<GuardianSet> {
+        self.recovery.guardian_sets.get(&account_id).cloned()
+    }
+
+    pub fn get_recovery_request(&self, account_id: AccountId) -> Option<RecoveryRequest> {
+        self.recovery.requests.get(&account_id).cloned()
+    }
+}
+
+impl Contract {
+    /// Returns the caller if it is one of the recovery guardians of `account_id`.
+    fn assert_recovery_guardian(&self, account_id: &AccountId) -> AccountId {
+        let guardian_id = env::predecessor_account_id();
+        assert!(
+            self.recovery
+                .unwrap_guardian_set(account_id)
+                .guardians
+                .contains(&guardian_id),
+            "Only a recovery guardian of @{} can do this",
+            account_id
+        );
+        guardian_id
+    }
+
+    fn internal_approve_recovery(&mut self, account_id: &AccountId, guardian_id: AccountId) {
+        let guardian_set = self.recovery.unwrap_guardian_set(account_id);
+        let mut request = self.recovery.unwrap_request(account_id);
+        assert!(
+            !request.approvals.contains(&guardian_id),
+            "Already approved"
+        );
+        request.approvals.push(guardian_id);
+        if request.executable_at.is_none()
+            && request.approvals.len() >= usize::from(guardian_set.threshold)
+        {
+            request.executable_at = Some((env::block_timestamp() + guardian_set.timelock.0).into());
+        }
+        emit_request_event("recovery_request_approve", account_id, &request);
+        self.recovery.requests.insert(account_id.clone(), request);
+    }
+
+    /// Removes the recovery request of `account_id`, if any, refunding its storage to the
+    /// proposer. Returns whether there was one.
+    fn internal_remove_recovery_request(&mut self, account_id: &AccountId, event: &str) -> bool {
+        let initial_storage = env::storage_usage();
+        match self.recovery.requests.remove(account_id) {
+            Some(request) => {
+                self.recovery.requests.flush();
+                refund_released_storage(&request.proposer_id, initial_storage);
+                emit_request_event(event, account_id, &request);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the balance, staking position and stake positions of `account_id` to `recovery_id`.
+    fn internal_recover(&mut self, account_id: &AccountId, recovery_id: &AccountId) {
+        self.staking.update();
+        let position = self.staking.settled_account(account_id);
+        if position.staked > 0 || position.unclaimed > 0 {
+            let mut recovered = self.staking.settled_account(recovery_id);
+            recovered.staked += position.staked;
+            recovered.unclaimed += position.unclaimed;
+            self.staking.save_account(recovery_id, &recovered);
+            self.staking.save_account(account_id, &Default::default());
+        }
+        self.staking
+            .reward_tokens
+            .move_unclaimed(account_id, recovery_id);
+        self.internal_move_stake_positions(account_id, recovery_id);
+
+        let balance = self.token.accounts.get(account_id).unwrap_or(0);
+        if balance > 0 {
+            self.has_transfers = true;
+            self.with_checkpoints(&[account_id.clone(), recovery_id.clone()], |this| {
+                this.token
+                    .internal_transfer(account_id, recovery_id, balance, None)
+            });
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 0);
+        contract.add_guardian_service(accounts(2));
+        call_as(&mut context, owner(), ONE_NEAR);
+        contract.stake(U128(1_000), None);
+        contract.set_guardian(accounts(2), accounts(1), U64(MIN_CHALLENGE_PERIOD));
+        call_as(&mut context, accounts(2), 0);
+        contract.initiate_recovery(owner());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_execute_recovery_after_challenge_period() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(MIN_CHALLENGE_PERIOD).build());
+        contract.execute_recovery(owner());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY - 1_000);
+        assert_eq!(contract.get_staked_balance(accounts(1)).0, 1_000);
+        assert!(contract.get_guardian(owner()).is_none());
+    }
+
+    #[test]
+    fn test_guardian_threshold_recovery() {
+        let (mut context, mut contract) = setup();
+        let owner = owner();
+        testing_env!(context
+            .predecessor_account_id(owner.clone())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.set_recovery_guardians(
+            vec![accounts(3), accounts(4), accounts(5)],
+            2,
+            U64(MIN_CHALLENGE_PERIOD),
+        );
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.propose_recovery(owner.clone(), accounts(1));
+        assert!(contract
+            .get_recovery_request(owner.clone())
+            .unwrap()
+            .executable_at
+            .is_none());
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(0)
+            .build());
+        contract.approve_recovery(owner.clone());
+
+        testing_env!(context.block_timestamp(MIN_CHALLENGE_PERIOD).build());
+        contract.execute_recovery_request(owner.clone());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY - 1_000);
+        assert!(contract.get_recovery_request(owner).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "No recovery is pending")]
+    fn test_cancelled_recovery_cannot_execute() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(1)
+            .build());
+        contract.cancel_recovery();
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0)
+            .block_timestamp(MIN_CHALLENGE_PERIOD)
+            .build());
+        contract.execute_recovery(owner());
+    }
+}
@@ -0,0 +1,336 @@
+/*!
+Referral rewards on sales and staking.
+NOTES:
+  - A registered account claims a referral code with `register_referral_code`. Codes are 3 to
+    32 lowercase letters, digits, `-` or `_`, one per account, and cannot be changed.
+  - Buyers pass a code to `buy`: the referrer earns `sale_bps` of the tokens bought. Stakers pass
+    a code to `stake` on their first stake, which binds them to the referrer for good: the
+    referrer earns `staking_bps` of every reward the staker claims.
+  - Nobody can use their own code.
+  - Referral rewards are paid in ASTRO on top of what the buyer or the staker gets, out of a
+    referral pool the owner or the DAO funds from the treasury. Once the pool is empty, referrals
+    earn nothing until it is funded again. Referrers claim with `claim_referral_rewards`.
+  - Codes and bindings are paid for by the account that creates them.
+*/
+#![cfg_attr(
+    not(all(feature = "sale", feature = "staking")),
+    allow(dead_code, unused_imports)
+)]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+pub const MAX_REFERRAL_BPS: u16 = 2_000;
+const MAX_BPS: u128 = 10_000;
+const MIN_CODE_LEN: usize = 3;
+const MAX_CODE_LEN: usize = 32;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReferralConfig {
+    /// Share of the tokens bought earned by the referrer, in basis points.
+    pub sale_bps: u16,
+    /// Share of the staking rewards claimed earned by the referrer, in basis points.
+    pub staking_bps: u16,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Referrer {
+    pub code: String,
+    /// Purchases and staking reward claims credited to the referrer.
+    pub referrals: U64,
+    /// Tokens bought and staking rewards claimed by the referred accounts.
+    pub volume: U128,
+    pub earned: U128,
+    pub unclaimed: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReferralInfo {
+    pub config: ReferralConfig,
+    pub pool: U128,
+    pub unclaimed: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Referrals {
+    pub config: ReferralConfig,
+    /// Treasury tokens set aside for referral rewards.
+    pub pool: Balance,
+    /// Rewards earned by all referrers and not claimed yet.
+    pub unclaimed: Balance,
+    pub codes: LookupMap<String, AccountId>,
+    pub referrers: LookupMap<AccountId, Referrer>,
+    /// Referrer of each staker.
+    pub referred_by: LookupMap<AccountId, AccountId>,
+}
+
+impl Referrals {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            config: ReferralConfig::default(),
+            pool: 0,
+            unclaimed: 0,
+            codes: LookupMap::new([prefix.as_slice(), b"c"].concat()),
+            referrers: LookupMap::new([prefix.as_slice(), b"r"].concat()),
+            referred_by: LookupMap::new([prefix.as_slice(), b"b"].concat()),
+        }
+    }
+
+    /// Owner of `code`, who must not be `account_id`.
+    fn referrer_of_code(&self, code: &str, account_id: &AccountId) -> AccountId {
+        let referrer_id = self
+            .codes
+            .get(code)
+            .cloned()
+            .unwrap_or_else(|| env::panic_str("Unknown referral code"));
+        assert_ne!(
+            &referrer_id, account_id,
+            "Cannot use your own referral code"
+        );
+        referrer_id
+    }
+
+    /// Credits `referrer_id` with `bps` of `volume`, as far as the pool allows.
+    fn accrue(&mut self, referrer_id: &AccountId, volume: Balance, bps: u16) {
+        let mut referrer = match self.referrers.get(referrer_id) {
+            Some(referrer) => referrer.clone(),
+            None => return,
+        };
+        let reward = (volume * u128::from(bps) / MAX_BPS).min(self.pool);
+        self.pool -= reward;
+        self.unclaimed += reward;
+        referrer.referrals = (referrer.referrals.0 + 1).into();
+        referrer.volume = (referrer.volume.0 + volume).into();
+        referrer.earned = (referrer.earned.0 + reward).into();
+        referrer.unclaimed = (referrer.unclaimed.0 + reward).into();
+        self.referrers.insert(referrer_id.clone(), referrer);
+        log!("Referrer @{} earned {} on {}", referrer_id, reward, volume);
+    }
+}
+
+fn is_valid_code(code: &str) -> bool {
+    (MIN_CODE_LEN..=MAX_CODE_LEN).contains(&code.len())
+        && code
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Claims `code` as the caller's referral code. The attached deposit covers its storage.
+    #[payable]
+    pub fn register_referral_code(&mut self, code: String) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.token.accounts.contains_key(&account_id),
+            "The account {} is not registered",
+            account_id
+        );
+        assert!(
+            is_valid_code(&code),
+            "A referral code has {} to {} lowercase letters, digits, - or _",
+            MIN_CODE_LEN,
+            MAX_CODE_LEN
+        );
+        assert!(
+            !self.referrals.referrers.contains_key(&account_id),
+            "The account already has a referral code"
+        );
+        assert!(
+            !self.referrals.codes.contains_key(&code),
+            "The referral code is taken"
+        );
+        let initial_storage = env::storage_usage();
+        self.referrals
+            .codes
+            .insert(code.clone(), account_id.clone());
+        self.referrals.referrers.insert(
+            account_id.clone(),
+            Referrer {
+                code: code.clone(),
+                referrals: U64(0),
+                volume: U128(0),
+                earned: U128(0),
+                unclaimed: U128(0),
+            },
+        );
+        self.referrals.codes.flush();
+        self.referrals.referrers.flush();
+        refund_deposit(env::storage_usage() - initial_storage);
+        log!("Account @{} registered referral code {}", account_id, code);
+    }
+
+    pub fn set_referral_config(&mut self, config: ReferralConfig) {
+        self.assert_owner_or_dao();
+        assert!(
+            config.sale_bps <= MAX_REFERRAL_BPS && config.staking_bps <= MAX_REFERRAL_BPS,
+            "Referral rewards are at most {} basis points",
+            MAX_REFERRAL_BPS
+        );
+        self.referrals.config = config;
+    }
+
+    /// Moves `amount` of the treasury into the referral pool.
+    pub fn fund_referral_pool(&mut self, amount: U128) {
+        self.assert_owner_or_dao();
+        assert!(
+            amount.0 <= self.treasury_balance,
+            "The treasury holds {}",
+            self.treasury_balance
+        );
+        self.treasury_balance -= amount.0;
+        self.referrals.pool += amount.0;
+    }
+
+    /// Sends the caller's referral rewards. Returns the amount claimed.
+    #[payable]
+    pub fn claim_referral_rewards(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut referrer = self
+            .referrals
+            .referrers
+            .get(&account_id)
+            .cloned()
+            .expect("The account has no referral code");
+        let amount = std::mem::replace(&mut referrer.unclaimed, U128(0)).0;
+        assert!(amount > 0, "No referral rewards to claim");
+        self.referrals
+            .referrers
+            .insert(account_id.clone(), referrer);
+        self.referrals.unclaimed -= amount;
+        self.internal_unlock(&account_id, amount);
+        log!(
+            "Account @{} claimed {} of referral rewards",
+            account_id,
+            amount
+        );
+        amount.into()
+    }
+
+    pub fn get_referrer(&self, account_id: AccountId) -> Option<Referrer> {
+        self.referrals.referrers.get(&account_id).cloned()
+    }
+
+    pub fn get_referral_code_owner(&self, code: String) -> Option<AccountId> {
+        self.referrals.codes.get(&code).cloned()
+    }
+
+    /// Referrer a staker is bound to.
+    pub fn get_referred_by(&self, account_id: AccountId) -> Option<AccountId> {
+        self.referrals.referred_by.get(&account_id).cloned()
+    }
+
+    pub fn get_referral_info(&self) -> ReferralInfo {
+        ReferralInfo {
+            config: self.referrals.config,
+            pool: self.referrals.pool.into(),
+            unclaimed: self.referrals.unclaimed.into(),
+        }
+    }
+}
+
+impl Contract {
+    /// Credits the owner of `code` for `amount` bought by `buyer_id`.
+    pub(crate) fn internal_refer_purchase(
+        &mut self,
+        code: &str,
+        buyer_id: &AccountId,
+        amount: Balance,
+    ) {
+        let referrer_id = self.referrals.referrer_of_code(code, buyer_id);
+        let bps = self.referrals.config.sale_bps;
+        self.referrals.accrue(&referrer_id, amount, bps);
+    }
+
+    /// Binds `staker_id` to the owner of `code` unless it is bound already.
+    pub(crate) fn internal_bind_referrer(&mut self, code: &str, staker_id: &AccountId) {
+        if self.referrals.referred_by.contains_key(staker_id) {
+            return;
+        }
+        let referrer_id = self.referrals.referrer_of_code(code, staker_id);
+        self.referrals
+            .referred_by
+            .insert(staker_id.clone(), referrer_id);
+        self.referrals.referred_by.flush();
+    }
+
+    /// Credits the referrer of `staker_id`, if any, for `reward` claimed.
+    pub(crate) fn internal_refer_staking_reward(&mut self, staker_id: &AccountId, reward: Balance) {
+        if let Some(referrer_id) = self.referrals.referred_by.get(staker_id).cloned() {
+            let bps = self.referrals.config.staking_bps;
+            self.referrals.accrue(&referrer_id, reward, bps);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::dutch_auction::SalePricing;
+    use crate::sale::SaleConfig;
+    use crate::test_fixtures::{self, call_as, ONE_NEAR};
+
+    const ONE_ASTRO: Balance = 1_000_000_000_000_000_000;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1), accounts(2)]);
+        contract.set_sale_config(SaleConfig {
+            price: U128(ONE_NEAR / 10),
+            start_at: U64(0),
+            end_at: U64(1_000),
+            allocation: U128(100 * ONE_ASTRO),
+            per_wallet_cap: U128(5 * ONE_ASTRO),
+            mint: true,
+            presale: None,
+            pricing: SalePricing::Fixed,
+            vesting: None,
+        });
+        contract.set_referral_config(ReferralConfig {
+            sale_bps: 500,
+            staking_bps: 1_000,
+        });
+        testing_env!(context.attached_deposit(1).build());
+        contract.treasury_deposit(U128(ONE_ASTRO));
+        contract.fund_referral_pool(U128(ONE_ASTRO));
+        call_as(&mut context, accounts(2), ONE_NEAR);
+        contract.register_referral_code("astro-fan".to_string());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_referral_on_purchase() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        assert_eq!(contract.buy(Some("astro-fan".to_string())).0, 5 * ONE_ASTRO);
+        let referrer = contract.get_referrer(accounts(2)).unwrap();
+        assert_eq!(referrer.unclaimed.0, ONE_ASTRO / 4);
+        assert_eq!(referrer.referrals.0, 1);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        assert_eq!(contract.claim_referral_rewards().0, ONE_ASTRO / 4);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, ONE_ASTRO / 4);
+        assert_eq!(contract.get_referral_info().pool.0, 3 * ONE_ASTRO / 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot use your own referral code")]
+    fn test_no_self_referral() {
+        let (_, mut contract) = setup();
+        contract.buy(Some("astro-fan".to_string()));
+    }
+}
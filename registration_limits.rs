@@ -0,0 +1,197 @@
+/*!
+Rate limits on new account registrations through `storage_deposit`.
+NOTES:
+  - Every registration adds a permanent entry to the token state, so mass registrations can be
+    used to grief the contract. The limits make bursts slow and more expensive.
+  - At most `max_per_block` accounts can be registered in one block. Registrations beyond
+    `burst_threshold` in the same block must attach `burst_surcharge` on top of the minimum
+    storage balance; the surcharge is kept by the contract.
+  - Each caller can register at most `max_per_registrar` accounts within `registrar_window`
+    blocks. The contract pays for tracking callers, so wallets attaching exactly
+    `storage_balance_bounds().min` keep working outside bursts.
+  - Top-ups of accounts that are already registered are not limited.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::LookupMap;
+use near_sdk::{env, near_bindgen, AccountId, Balance, BlockHeight};
+
+use crate::*;
+
+const DEFAULT_MAX_PER_BLOCK: u32 = 50;
+const DEFAULT_BURST_THRESHOLD: u32 = 10;
+/// Default surcharge of 0.00125 NEAR, about the storage cost of one more account.
+const DEFAULT_BURST_SURCHARGE: Balance = 1_250_000_000_000_000_000_000;
+const DEFAULT_MAX_PER_REGISTRAR: u32 = 20;
+const DEFAULT_REGISTRAR_WINDOW: BlockHeight = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+pub struct RegistrarWindow {
+    pub start_block: BlockHeight,
+    pub count: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RegistrationLimitsConfig {
+    pub max_per_block: u32,
+    pub burst_threshold: u32,
+    pub burst_surcharge: U128,
+    pub max_per_registrar: u32,
+    pub registrar_window: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RegistrationLimits {
+    pub max_per_block: u32,
+    pub burst_threshold: u32,
+    pub burst_surcharge: Balance,
+    pub max_per_registrar: u32,
+    /// Length in blocks of the window `max_per_registrar` applies to.
+    pub registrar_window: BlockHeight,
+    pub current_block: BlockHeight,
+    /// Registrations in `current_block`.
+    pub block_count: u32,
+    pub registrars: LookupMap<AccountId, RegistrarWindow>,
+}
+
+impl RegistrationLimits {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            max_per_block: DEFAULT_MAX_PER_BLOCK,
+            burst_threshold: DEFAULT_BURST_THRESHOLD,
+            burst_surcharge: DEFAULT_BURST_SURCHARGE,
+            max_per_registrar: DEFAULT_MAX_PER_REGISTRAR,
+            registrar_window: DEFAULT_REGISTRAR_WINDOW,
+            current_block: 0,
+            block_count: 0,
+            registrars: LookupMap::new(prefix),
+        }
+    }
+
+    /// The surcharge the next registration in this block would pay.
+    pub fn next_surcharge(&self) -> Balance {
+        let block_count = if self.current_block == env::block_height() {
+            self.block_count
+        } else {
+            0
+        };
+        if block_count >= self.burst_threshold {
+            self.burst_surcharge
+        } else {
+            0
+        }
+    }
+
+    /// Counts a registration by `registrar_id` and returns the deposit required on top of the
+    /// minimum storage balance. Panics if a limit is exceeded.
+    pub fn register(&mut self, registrar_id: &AccountId) -> Balance {
+        let block_height = env::block_height();
+        if self.current_block != block_height {
+            self.current_block = block_height;
+            self.block_count = 0;
+        }
+        assert!(
+            self.block_count < self.max_per_block,
+            "Too many registrations in this block, try again later"
+        );
+        self.block_count += 1;
+
+        let mut window = match self.registrars.get(registrar_id) {
+            Some(window) if block_height < window.start_block + self.registrar_window => *window,
+            _ => RegistrarWindow {
+                start_block: block_height,
+                count: 0,
+            },
+        };
+        assert!(
+            window.count < self.max_per_registrar,
+            "Too many registrations by @{}, try again later",
+            registrar_id
+        );
+        window.count += 1;
+        self.registrars.insert(registrar_id.clone(), window);
+
+        if self.block_count > self.burst_threshold {
+            self.burst_surcharge
+        } else {
+            0
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_registration_limits(&mut self, config: RegistrationLimitsConfig) {
+        self.assert_owner();
+        assert!(
+            config.max_per_block > 0 && config.max_per_registrar > 0,
+            "Limits must be positive"
+        );
+        assert!(config.registrar_window.0 > 0, "Window must be positive");
+        let limits = &mut self.registration_limits;
+        limits.max_per_block = config.max_per_block;
+        limits.burst_threshold = config.burst_threshold;
+        limits.burst_surcharge = config.burst_surcharge.into();
+        limits.max_per_registrar = config.max_per_registrar;
+        limits.registrar_window = config.registrar_window.into();
+    }
+
+    pub fn get_registration_limits(&self) -> RegistrationLimitsConfig {
+        let limits = &self.registration_limits;
+        RegistrationLimitsConfig {
+            max_per_block: limits.max_per_block,
+            burst_threshold: limits.burst_threshold,
+            burst_surcharge: limits.burst_surcharge.into(),
+            max_per_registrar: limits.max_per_registrar,
+            registrar_window: limits.registrar_window.into(),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    fn setup(max_per_block: u32, burst_threshold: u32) -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, owner(), 0);
+        contract.set_registration_limits(RegistrationLimitsConfig {
+            max_per_block,
+            burst_threshold,
+            burst_surcharge: U128(DEFAULT_BURST_SURCHARGE),
+            max_per_registrar: 10,
+            registrar_window: U64(DEFAULT_REGISTRAR_WINDOW),
+        });
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR)
+            .block_index(1)
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many registrations in this block")]
+    fn test_max_registrations_per_block() {
+        let (_, mut contract) = setup(2, 2);
+        contract.storage_deposit(Some(accounts(2)), None);
+        contract.storage_deposit(Some(accounts(3)), None);
+        contract.storage_deposit(Some(accounts(4)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the minimum storage balance")]
+    fn test_burst_registration_surcharge() {
+        let (mut context, mut contract) = setup(10, 1);
+        let min = contract.storage_balance_bounds().min.0;
+        testing_env!(context.attached_deposit(min).build());
+        contract.storage_deposit(Some(accounts(2)), None);
+        contract.storage_deposit(Some(accounts(3)), None);
+    }
+}
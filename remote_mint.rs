@@ -0,0 +1,317 @@
+/*!
+Mints authenticated by relayer signatures, for messaging bridges other than Rainbow.
+NOTES:
+  - The owner or the DAO configures a set of relayer ed25519 keys and a threshold. Anyone can
+    submit a `remote_mint` carrying a payload signed by at least `threshold` of the relayers:
+    the relayers attest the message, the submitter only pays the gas.
+  - The signed message is the borsh serialization of the token account id and the payload
+    `(nonce, recipient, amount, source_chain)`, like signed transfers, see `meta_transactions`.
+  - Each `(source_chain, nonce)` mints once. The submitter pays for the storage of the record.
+  - Remote mints draw down the cap set with `set_remote_mint_cap`, so compromised relayer keys
+    cannot mint more than it. They do not use the emission allowance but stay under the max
+    supply, see `emission`.
+  - Pausing the bridge also stops remote mints. Changing the relayer set does not invalidate
+    used nonces.
+*/
+#![cfg_attr(not(feature = "bridge"), allow(dead_code, unused_imports))]
+
+use std::convert::TryInto;
+
+use near_contract_standards::fungible_token::events::FtMint;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupSet;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, CurveType, PublicKey};
+
+use crate::meta_transactions::ed25519_verify;
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const MAX_RELAYERS: usize = 16;
+const MAX_SOURCE_CHAIN_LEN: usize = 64;
+
+#[derive(BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RemoteMintPayload {
+    pub nonce: U64,
+    pub recipient: AccountId,
+    pub amount: U128,
+    pub source_chain: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RelayerSignature {
+    pub public_key: PublicKey,
+    pub signature: Base64VecU8,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RemoteMint {
+    pub relayer_keys: Vec<PublicKey>,
+    pub threshold: u8,
+    /// Amount remote mints can still create.
+    pub mint_cap: Balance,
+    pub used_nonces: LookupSet<(String, u64)>,
+}
+
+impl RemoteMint {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            relayer_keys: vec![],
+            threshold: 0,
+            mint_cap: 0,
+            used_nonces: LookupSet::new(prefix),
+        }
+    }
+
+    /// Panics unless at least `threshold` distinct relayers signed `payload`.
+    fn assert_attested(&self, payload: &RemoteMintPayload, signatures: &[RelayerSignature]) {
+        assert!(self.threshold > 0, "No relayers are configured");
+        let message = (env::current_account_id(), payload).try_to_vec().unwrap();
+        let mut signers: Vec<&PublicKey> = vec![];
+        for relayer_signature in signatures {
+            let public_key = &relayer_signature.public_key;
+            if !self.relayer_keys.contains(public_key) || signers.contains(&public_key) {
+                continue;
+            }
+            let signature: [u8; 64] = relayer_signature
+                .signature
+                .0
+                .as_slice()
+                .try_into()
+                .expect("The signature must be 64 bytes");
+            let key: [u8; 32] = public_key.as_bytes()[1..].try_into().unwrap();
+            assert!(
+                ed25519_verify(&signature, &message, &key),
+                "Invalid signature"
+            );
+            signers.push(public_key);
+        }
+        assert!(
+            signers.len() >= usize::from(self.threshold),
+            "{} relayer signatures are required",
+            self.threshold
+        );
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RemoteMintInfo {
+    pub relayer_keys: Vec<PublicKey>,
+    pub threshold: u8,
+    pub mint_cap: U128,
+}
+
+#[cfg(feature = "bridge")]
+#[near_bindgen]
+impl Contract {
+    /// Replaces the relayer set. A remote mint needs the signatures of `threshold` of `keys`.
+    pub fn set_remote_relayers(&mut self, keys: Vec<PublicKey>, threshold: u8) {
+        self.assert_owner_or_dao();
+        assert!(
+            keys.len() <= MAX_RELAYERS,
+            "At most {} relayers",
+            MAX_RELAYERS
+        );
+        assert!(
+            keys.iter()
+                .all(|key| key.curve_type() == CurveType::ED25519),
+            "Only ed25519 keys are supported"
+        );
+        assert!(
+            keys.iter()
+                .enumerate()
+                .all(|(i, key)| !keys[..i].contains(key)),
+            "The relayer keys must be distinct"
+        );
+        assert!(
+            threshold > 0 && usize::from(threshold) <= keys.len(),
+            "The threshold must be between 1 and the number of relayers"
+        );
+        log!(
+            "Remote mints need {} of {} relayer signatures",
+            threshold,
+            keys.len()
+        );
+        self.remote_mint.relayer_keys = keys;
+        self.remote_mint.threshold = threshold;
+    }
+
+    pub fn set_remote_mint_cap(&mut self, mint_cap: U128) {
+        self.assert_owner_or_dao();
+        self.remote_mint.mint_cap = mint_cap.0;
+        log!("Remote mints can create {}", mint_cap.0);
+    }
+
+    /// Mints the amount of `payload` to its recipient once enough relayers signed it. The
+    /// attached deposit covers the storage of the nonce record.
+    #[payable]
+    pub fn remote_mint(&mut self, payload: RemoteMintPayload, signatures: Vec<RelayerSignature>) {
+        assert!(!self.bridge.paused, "The bridge is paused");
+        assert!(
+            payload.amount.0 > 0,
+            "The amount should be a positive number"
+        );
+        assert!(
+            !payload.source_chain.is_empty() && payload.source_chain.len() <= MAX_SOURCE_CHAIN_LEN,
+            "The source chain has 1 to {} bytes",
+            MAX_SOURCE_CHAIN_LEN
+        );
+        assert!(
+            payload.amount.0 <= self.remote_mint.mint_cap,
+            "Remote mints can create at most {}",
+            self.remote_mint.mint_cap
+        );
+        if let Some(max_supply) = self.max_supply() {
+            assert!(
+                payload.amount.0 <= max_supply.0.saturating_sub(self.token.total_supply),
+                "Remote mints cannot take the supply above the max supply of {}",
+                max_supply.0
+            );
+        }
+        self.remote_mint.assert_attested(&payload, &signatures);
+
+        let initial_storage = env::storage_usage();
+        assert!(
+            self.remote_mint
+                .used_nonces
+                .insert((payload.source_chain.clone(), payload.nonce.0)),
+            "The nonce was already used"
+        );
+        refund_deposit(env::storage_usage() - initial_storage);
+        self.remote_mint.mint_cap -= payload.amount.0;
+        let recipient = payload.recipient.clone();
+        self.with_checkpoints(std::slice::from_ref(&recipient), |this| {
+            this.token.internal_deposit(&recipient, payload.amount.0)
+        });
+        FtMint {
+            owner_id: &recipient,
+            amount: &payload.amount,
+            memo: Some("Remote mint"),
+        }
+        .emit();
+        emit_event(
+            EVENT_VERSION,
+            "remote_mint",
+            json!({
+                "source_chain": payload.source_chain,
+                "nonce": payload.nonce,
+                "account_id": recipient,
+                "amount": payload.amount,
+            }),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_remote_mint_info(&self) -> RemoteMintInfo {
+        RemoteMintInfo {
+            relayer_keys: self.remote_mint.relayer_keys.clone(),
+            threshold: self.remote_mint.threshold,
+            mint_cap: self.remote_mint.mint_cap.into(),
+        }
+    }
+
+    pub fn is_remote_nonce_used(&self, source_chain: String, nonce: U64) -> bool {
+        self.remote_mint
+            .used_nonces
+            .contains(&(source_chain, nonce.0))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "bridge"))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    /// RFC 8032 test keys 1 and 2 and their signatures of `payload()`.
+    const RELAYERS: [(&str, &str); 2] = [
+        (
+            "ed25519:FVen3X669xLzsi6N2V91DoiyzHzg1uAgqiT8jZ9nS96Z",
+            "0C7By03zU2BJcsO5bA3mVGZADMReawgoGdUwmynltf5Ix8WYJ0ew72200xyFr/zZm2I+PJJOBjkdZHA/b/rMDg==",
+        ),
+        (
+            "ed25519:586Z7H2vpX9qNhN2T4e9Utugie3ogjbxzGaMtM3E6HR5",
+            "ptyhELj67dma0112b1Zycl0K694ZbeN2AiXIhKvSIc9TfJiWcRCbWHqQ9sAHtLs8gfEnWyuKzOI+vuRUIkhxCQ==",
+        ),
+    ];
+
+    fn payload() -> RemoteMintPayload {
+        RemoteMintPayload {
+            nonce: U64(1),
+            recipient: accounts(1),
+            amount: U128(1_000),
+            source_chain: "ethereum".to_string(),
+        }
+    }
+
+    fn signatures() -> Vec<RelayerSignature> {
+        RELAYERS
+            .iter()
+            .map(|(public_key, signature)| RelayerSignature {
+                public_key: public_key.parse().unwrap(),
+                signature: near_sdk::serde_json::from_str(&format!("\"{}\"", signature)).unwrap(),
+            })
+            .collect()
+    }
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        let keys = signatures()
+            .into_iter()
+            .map(|signature| signature.public_key)
+            .collect();
+        contract.set_remote_relayers(keys, 2);
+        contract.set_remote_mint_cap(U128(5_000));
+        // Anyone can submit the attested payload.
+        call_as(&mut context, accounts(3), ONE_NEAR);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_remote_mint_needs_threshold() {
+        let (_, mut contract) = setup();
+        contract.remote_mint(payload(), signatures());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY + 1_000);
+        assert_eq!(contract.get_remote_mint_info().mint_cap.0, 4_000);
+        assert!(contract.is_remote_nonce_used("ethereum".to_string(), U64(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "The nonce was already used")]
+    fn test_remote_mint_replay() {
+        let (_, mut contract) = setup();
+        contract.remote_mint(payload(), signatures());
+        contract.remote_mint(payload(), signatures());
+    }
+
+    #[test]
+    #[should_panic(expected = "2 relayer signatures are required")]
+    fn test_remote_mint_with_duplicate_signatures() {
+        let (_, mut contract) = setup();
+        let mut signatures = signatures();
+        signatures[1] = RelayerSignature {
+            public_key: signatures[0].public_key.clone(),
+            signature: signatures[0].signature.clone(),
+        };
+        contract.remote_mint(payload(), signatures);
+    }
+
+    #[test]
+    #[should_panic(expected = "Remote mints cannot take the supply above the max supply")]
+    fn test_remote_mint_respects_the_max_supply() {
+        let (mut context, mut contract) = setup();
+        call_as(&mut context, owner(), ONE_NEAR);
+        contract.set_emission_schedule(U128(TOTAL_SUPPLY + 999), U64(1_000), U128(1_000));
+        call_as(&mut context, accounts(3), ONE_NEAR);
+        contract.remote_mint(payload(), signatures());
+    }
+}
@@ -0,0 +1,184 @@
+/*!
+Rescue of foreign tokens and NEAR sent to the contract account by mistake.
+NOTES:
+  - Foreign tokens sent with `ft_transfer_call` and an empty `msg` are kept and counted per token
+    contract in `stray_tokens`. The owner can send it on with `rescue_ft`, up to the amount
+    counted. Tokens the contract holds for its own use, e.g. liquid staking shares or wNEAR
+    between buyback steps, never arrive through `ft_on_transfer`, so they cannot be rescued.
+  - `rescue_ft` never touches the ASTRO ledger: ASTRO sent to the contract account is handled by
+    the treasury and the buyback.
+  - `rescue_near` sends NEAR the contract does not need: the balance above the storage cost, the
+    gas tank, the liquid staking reserve and the deposits held for accounts, i.e. storage
+    top-ups and payment link registration deposits.
+  - A failed `rescue_ft` transfer adds the amount back to the count.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::store::UnorderedMap;
+use near_sdk::{
+    env, ext_contract, is_promise_success, log, near_bindgen, AccountId, Balance, Gas, Promise,
+};
+
+use crate::*;
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_ON_FT_RESCUED: Gas = Gas(10_000_000_000_000);
+
+#[ext_contract(ext_ft)]
+#[allow(dead_code)]
+pub trait ForeignToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Rescue {
+    /// Foreign tokens received through `ft_on_transfer` and not rescued yet, by token contract.
+    pub stray_tokens: UnorderedMap<AccountId, Balance>,
+    /// NEAR held for accounts on top of the storage cost.
+    pub deposits_held: Balance,
+}
+
+impl Rescue {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            stray_tokens: UnorderedMap::new(prefix),
+            deposits_held: 0,
+        }
+    }
+
+    pub fn stray_balance(&self, token_id: &AccountId) -> Balance {
+        self.stray_tokens.get(token_id).copied().unwrap_or(0)
+    }
+
+    pub fn hold(&mut self, amount: Balance) {
+        self.deposits_held += amount;
+    }
+
+    pub fn release(&mut self, amount: Balance) {
+        self.deposits_held = self.deposits_held.saturating_sub(amount);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sends foreign tokens received by mistake to `receiver_id`, who must be registered with
+    /// the token contract.
+    pub fn rescue_ft(
+        &mut self,
+        token_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> Promise {
+        self.assert_owner();
+        let balance = self.rescue.stray_balance(&token_id);
+        assert!(
+            amount.0 > 0 && amount.0 <= balance,
+            "Can rescue at most {} of {}",
+            balance,
+            token_id
+        );
+        self.internal_set_stray_balance(&token_id, balance - amount.0);
+        log!("Rescuing {} of {} to @{}", amount.0, token_id, receiver_id);
+        ext_ft::ext(token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(receiver_id, amount, Some("Rescued".to_string()))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_FT_RESCUED)
+                    .on_ft_rescued(token_id, amount),
+            )
+    }
+
+    #[private]
+    pub fn on_ft_rescued(&mut self, token_id: AccountId, amount: U128) {
+        if !is_promise_success() {
+            let balance = self.rescue.stray_balance(&token_id) + amount.0;
+            self.rescue.stray_tokens.insert(token_id.clone(), balance);
+            log!("Rescue of {} of {} failed", amount.0, token_id);
+            self.internal_record_callback_failure("rescue_ft", vec![token_id], amount.0);
+        }
+    }
+
+    /// Sends NEAR the contract does not need to `receiver_id`.
+    pub fn rescue_near(&mut self, receiver_id: AccountId, amount: U128) -> Promise {
+        self.assert_owner();
+        let rescuable = self.rescuable_near();
+        assert!(
+            amount.0 > 0 && amount.0 <= rescuable,
+            "Can rescue at most {} yoctoNEAR",
+            rescuable
+        );
+        log!("Rescuing {} yoctoNEAR to @{}", amount.0, receiver_id);
+        Promise::new(receiver_id).transfer(amount.0)
+    }
+
+    pub fn get_stray_tokens(&self) -> Vec<(AccountId, U128)> {
+        self.rescue
+            .stray_tokens
+            .iter()
+            .map(|(token_id, balance)| (token_id.clone(), (*balance).into()))
+            .collect()
+    }
+
+    pub fn get_rescuable_near(&self) -> U128 {
+        self.rescuable_near().into()
+    }
+}
+
+impl Contract {
+    /// Counts foreign tokens received without a pool as rescuable.
+    pub(crate) fn internal_record_stray_tokens(
+        &mut self,
+        token_id: &AccountId,
+        sender_id: &AccountId,
+        amount: Balance,
+    ) {
+        let balance = self.rescue.stray_balance(token_id) + amount;
+        self.rescue.stray_tokens.insert(token_id.clone(), balance);
+        log!("Received {} of {} from @{}", amount, token_id, sender_id);
+    }
+
+    fn internal_set_stray_balance(&mut self, token_id: &AccountId, balance: Balance) {
+        if balance == 0 {
+            self.rescue.stray_tokens.remove(token_id);
+        } else {
+            self.rescue.stray_tokens.insert(token_id.clone(), balance);
+        }
+    }
+
+    fn rescuable_near(&self) -> Balance {
+        self.idle_near().saturating_sub(self.rescue.deposits_held)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, accounts(1), 0);
+        contract.internal_record_stray_tokens(&accounts(1), &accounts(2), 500);
+        call_as(&mut context, owner(), 0);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_rescue_ft() {
+        let (_, mut contract) = setup();
+        assert_eq!(contract.get_stray_tokens(), vec![(accounts(1), U128(500))]);
+        contract.rescue_ft(accounts(1), accounts(2), U128(500));
+        assert!(contract.get_stray_tokens().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can rescue at most 500 of bob")]
+    fn test_rescue_ft_is_limited_to_stray_tokens() {
+        let (_, mut contract) = setup();
+        contract.rescue_ft(accounts(1), accounts(2), U128(501));
+    }
+}
@@ -0,0 +1,305 @@
+/*!
+Reservations: part of a balance set aside for a beneficiary, for two-phase payments.
+NOTES:
+  - A holder reserves `amount` for a beneficiary for `ttl` nanoseconds and gets a reservation id.
+    The tokens stay on the holder's account but cannot be spent elsewhere: every balance change
+    that would drop the account below its reserved total fails, like a balance lock.
+  - The beneficiary settles with `commit_reservation`, an `ft_transfer` of the amount from the
+    holder with every check of the public method, or releases it with `cancel_reservation`.
+  - A reservation expires after its ttl: it no longer holds the balance and cannot be committed.
+    Anyone can then cancel it, and a new reservation of the holder drops its expired ones.
+  - The holder pays for the storage of a reservation and gets it back when it ends.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const MAX_RESERVATIONS_PER_ACCOUNT: usize = 20;
+const MAX_TTL: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Reservation {
+    pub owner_id: AccountId,
+    pub beneficiary_id: AccountId,
+    pub amount: U128,
+    pub expires_at: U64,
+}
+
+impl Reservation {
+    fn is_expired(&self) -> bool {
+        env::block_timestamp() >= self.expires_at.0
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Reservations {
+    pub reservations: LookupMap<u64, Reservation>,
+    /// Reservations by holder.
+    pub accounts: LookupMap<AccountId, Vec<u64>>,
+    pub next_reservation_id: u64,
+}
+
+impl Reservations {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            reservations: LookupMap::new([prefix.as_slice(), b"r"].concat()),
+            accounts: LookupMap::new([prefix.as_slice(), b"a"].concat()),
+            next_reservation_id: 0,
+        }
+    }
+
+    fn flush(&mut self) {
+        self.reservations.flush();
+        self.accounts.flush();
+    }
+
+    /// Total of the reservations of `account_id` that have not expired.
+    pub fn reserved(&self, account_id: &AccountId) -> Balance {
+        self.accounts.get(account_id).map_or(0, |reservation_ids| {
+            reservation_ids
+                .iter()
+                .filter_map(|id| self.reservations.get(id))
+                .filter(|reservation| !reservation.is_expired())
+                .map(|reservation| reservation.amount.0)
+                .sum()
+        })
+    }
+
+    /// Panics if `account_id` holding `balance` would not cover its reservations.
+    pub fn assert_covered(&self, account_id: &AccountId, balance: Balance) {
+        let reserved = self.reserved(account_id);
+        assert!(
+            balance >= reserved,
+            "The account @{} has {} reserved",
+            account_id,
+            reserved
+        );
+    }
+
+    fn remove(&mut self, reservation_id: u64) -> Reservation {
+        let reservation = self
+            .reservations
+            .remove(&reservation_id)
+            .unwrap_or_else(|| env::panic_str("Reservation not found"));
+        let mut reservation_ids = self.accounts.get(&reservation.owner_id).unwrap().clone();
+        reservation_ids.retain(|id| *id != reservation_id);
+        if reservation_ids.is_empty() {
+            self.accounts.remove(&reservation.owner_id);
+        } else {
+            self.accounts
+                .insert(reservation.owner_id.clone(), reservation_ids);
+        }
+        reservation
+    }
+}
+
+fn emit_reservation_event(event: &str, reservation_id: u64, reservation: &Reservation) {
+    emit_event(
+        EVENT_VERSION,
+        event,
+        json!({
+            "reservation_id": U64(reservation_id),
+            "owner_id": reservation.owner_id,
+            "beneficiary_id": reservation.beneficiary_id,
+            "amount": reservation.amount,
+        }),
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Reserves `amount` of the caller's balance for `beneficiary` during `ttl` nanoseconds.
+    /// Returns the reservation id. The attached deposit covers the reservation storage.
+    #[payable]
+    pub fn reserve(&mut self, amount: U128, beneficiary: AccountId, ttl: U64) -> U64 {
+        let owner_id = env::predecessor_account_id();
+        assert_ne!(
+            owner_id, beneficiary,
+            "Holder and beneficiary should be different"
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        assert!(
+            ttl.0 > 0 && ttl.0 <= MAX_TTL,
+            "The ttl should be between 1 and {} nanoseconds",
+            MAX_TTL
+        );
+        let initial_storage = env::storage_usage();
+        let reservations = &mut self.reservations;
+        let mut reservation_ids = reservations
+            .accounts
+            .get(&owner_id)
+            .cloned()
+            .unwrap_or_default();
+        // Expired reservations of the caller are cleaned up to make room.
+        reservation_ids.retain(|id| {
+            let expired = reservations
+                .reservations
+                .get(id)
+                .is_none_or(Reservation::is_expired);
+            if expired {
+                reservations.reservations.remove(id);
+            }
+            !expired
+        });
+        assert!(
+            reservation_ids.len() < MAX_RESERVATIONS_PER_ACCOUNT,
+            "At most {} reservations per account",
+            MAX_RESERVATIONS_PER_ACCOUNT
+        );
+        let balance = self.token.accounts.get(&owner_id).unwrap_or(0);
+        let unlocked = balance.saturating_sub(self.balance_locks.locked(&owner_id));
+        let reserved = self.reservations.reserved(&owner_id);
+        assert!(
+            reserved + amount.0 <= unlocked,
+            "The account @{} has {} available",
+            owner_id,
+            unlocked.saturating_sub(reserved)
+        );
+
+        let reservation_id = self.reservations.next_reservation_id;
+        self.reservations.next_reservation_id += 1;
+        let reservation = Reservation {
+            owner_id: owner_id.clone(),
+            beneficiary_id: beneficiary,
+            amount,
+            expires_at: (env::block_timestamp() + ttl.0).into(),
+        };
+        emit_reservation_event("reservation_create", reservation_id, &reservation);
+        reservation_ids.push(reservation_id);
+        self.reservations.accounts.insert(owner_id, reservation_ids);
+        self.reservations
+            .reservations
+            .insert(reservation_id, reservation);
+        self.reservations.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        reservation_id.into()
+    }
+
+    /// Transfers a reservation to its beneficiary, the caller.
+    #[payable]
+    pub fn commit_reservation(&mut self, id: U64) {
+        assert_one_yocto();
+        let reservation = self.unwrap_reservation(id.0);
+        assert_eq!(
+            env::predecessor_account_id(),
+            reservation.beneficiary_id,
+            "Only the beneficiary can commit a reservation"
+        );
+        assert!(!reservation.is_expired(), "The reservation has expired");
+        self.internal_end_reservation(id.0);
+        self.internal_ft_transfer(
+            &reservation.owner_id,
+            reservation.beneficiary_id.clone(),
+            reservation.amount.0,
+            Some(format!("Reservation {}", id.0)),
+        );
+        emit_reservation_event("reservation_commit", id.0, &reservation);
+    }
+
+    /// Releases a reservation: by its beneficiary at any time, by anyone once expired.
+    pub fn cancel_reservation(&mut self, id: U64) {
+        let reservation = self.unwrap_reservation(id.0);
+        assert!(
+            reservation.is_expired() || env::predecessor_account_id() == reservation.beneficiary_id,
+            "Only the beneficiary can cancel a reservation before it expires"
+        );
+        self.internal_end_reservation(id.0);
+        emit_reservation_event("reservation_cancel", id.0, &reservation);
+    }
+
+    pub fn get_reservation(&self, id: U64) -> Option<Reservation> {
+        self.reservations.reservations.get(&id.0).cloned()
+    }
+
+    /// Reservations of `account_id`, expired ones included until they are cleaned up.
+    pub fn get_reservations(&self, account_id: AccountId) -> Vec<(U64, Reservation)> {
+        let reservations = &self.reservations;
+        reservations
+            .accounts
+            .get(&account_id)
+            .map_or(vec![], |reservation_ids| {
+                reservation_ids
+                    .iter()
+                    .filter_map(|id| {
+                        let reservation = reservations.reservations.get(id)?;
+                        Some((U64(*id), reservation.clone()))
+                    })
+                    .collect()
+            })
+    }
+
+    pub fn get_reserved_balance(&self, account_id: AccountId) -> U128 {
+        self.reservations.reserved(&account_id).into()
+    }
+}
+
+impl Contract {
+    fn unwrap_reservation(&self, reservation_id: u64) -> Reservation {
+        self.reservations
+            .reservations
+            .get(&reservation_id)
+            .cloned()
+            .unwrap_or_else(|| env::panic_str("Reservation not found"))
+    }
+
+    /// Removes a reservation and refunds its storage to the holder.
+    fn internal_end_reservation(&mut self, reservation_id: u64) {
+        let initial_storage = env::storage_usage();
+        let reservation = self.reservations.remove(reservation_id);
+        self.reservations.flush();
+        refund_released_storage(&reservation.owner_id, initial_storage);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, ONE_NEAR};
+
+    fn setup() -> (VMContextBuilder, Contract, u64) {
+        let (context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        let id = contract.reserve(U128(TOTAL_SUPPLY - 100), accounts(1), U64(1_000));
+        (context, contract, id.0)
+    }
+
+    #[test]
+    #[should_panic(expected = "reserved")]
+    fn test_reserved_tokens_cannot_move() {
+        let (mut context, mut contract, _) = setup();
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(101), None);
+    }
+
+    #[test]
+    fn test_commit_pays_the_beneficiary_and_expiry_frees_the_balance() {
+        let (mut context, mut contract, id) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.commit_reservation(U64(id));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY - 100);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let id = contract.reserve(U128(50), accounts(2), U64(1_000));
+        assert_eq!(contract.get_reserved_balance(accounts(1)).0, 50);
+        testing_env!(context.block_timestamp(1_000).build());
+        assert_eq!(contract.get_reserved_balance(accounts(1)).0, 0);
+        contract.cancel_reservation(id);
+        assert!(contract.get_reservations(accounts(1)).is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+/*!
+Retirement of optional modules.
+NOTES:
+  - The owner, the DAO or a governance proposal can retire an optional module. A retired module
+    accepts no new activity: no new stakes, positions, streams, escrows, airdrops, payment links,
+    matching rounds, sales, farms, curve mints, scheduled transfers, subscriptions or term
+    deposits, no more transfer fees or burns, and no more KYC checks.
+  - Everything already in a retired module keeps working for good: unstaking, reward claims,
+    redemptions, withdrawals, cancellations, refunds, finalization of open rounds and charges of
+    existing subscriptions. No user funds are stranded, and the module state is left as is.
+  - Retirement cannot be undone.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{log, near_bindgen};
+
+use crate::*;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Module {
+    Fees,
+    Staking,
+    StakePositions,
+    Streams,
+    Escrows,
+    Airdrops,
+    PaymentLinks,
+    Matching,
+    Sale,
+    Farming,
+    BondingCurve,
+    ScheduledTransfers,
+    Subscriptions,
+    Compliance,
+    Savings,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn retire_module(&mut self, module: Module) {
+        self.assert_owner_or_dao();
+        self.internal_retire_module(module);
+    }
+
+    pub fn get_retired_modules(&self) -> Vec<Module> {
+        self.retired_modules.clone()
+    }
+
+    pub fn is_module_retired(&self, module: Module) -> bool {
+        self.retired_modules.contains(&module)
+    }
+}
+
+impl Contract {
+    /// Retires `module`. Shared by the admin method and governance actions.
+    pub(crate) fn internal_retire_module(&mut self, module: Module) {
+        assert!(
+            !self.retired_modules.contains(&module),
+            "The {:?} module is already retired",
+            module
+        );
+        self.retired_modules.push(module);
+        log!("The {:?} module is retired", module);
+    }
+
+    /// Panics if `module` is retired or paused. Called where a module takes on new activity.
+    pub(crate) fn assert_module_active(&self, module: Module) {
+        assert!(
+            !self.retired_modules.contains(&module),
+            "The {:?} module is retired",
+            module
+        );
+        assert!(
+            !self.paused_modules.contains(&module),
+            "The {:?} module is paused",
+            module
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, owner};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        contract.stake(U128(1_000), None);
+        contract.retire_module(Module::Staking);
+        testing_env!(context.attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_retired_staking_can_be_unstaked() {
+        let (_, mut contract) = setup();
+        contract.unstake(U128(1_000));
+        assert_eq!(contract.get_staked_balance(owner()).0, 0);
+        assert_eq!(contract.get_retired_modules(), vec![Module::Staking]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The Staking module is retired")]
+    fn test_retired_staking_takes_no_stakes() {
+        let (_, mut contract) = setup();
+        contract.stake(U128(1_000), None);
+    }
+}
@@ -0,0 +1,450 @@
+/*!
+Staking rewards paid in other NEP-141 tokens, on top of the ASTRO emissions.
+NOTES:
+  - The owner registers up to `MAX_REWARD_TOKENS` reward tokens, each with its own emission rate
+    per second. Anyone funds a reward token with `ft_transfer_call` on its contract and
+    `"staking_rewards"` as `msg`; tokens of an unregistered contract are refunded.
+  - Each reward token follows the same accumulated-reward-per-share model as the ASTRO rewards,
+    with its own accumulator, so stakers accrue every token independently and in proportion to
+    their stake. Emissions never exceed the funded amount of the token.
+  - The token rewards of an account are settled whenever its stake changes, wherever the change
+    comes from: staking, positions, the vault or recovery. Recovery also moves the unclaimed token
+    rewards to the recovery account.
+  - Rewards are claimed per token with `claim_token_rewards` or all at once with
+    `claim_all_token_rewards`. A payout the token contract rejects is credited back.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::{LookupMap, UnorderedMap};
+use near_sdk::{
+    assert_one_yocto, env, is_promise_success, log, near_bindgen, AccountId, Balance, Gas, Promise,
+};
+
+use crate::rescue::ext_ft;
+use crate::*;
+
+const MAX_REWARD_TOKENS: u32 = 5;
+/// Fixed point precision of the accumulators, as for the ASTRO rewards.
+const ACC_REWARD_PRECISION: u128 = 1_000_000_000_000;
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_ON_CLAIM: Gas = Gas(10_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct RewardToken {
+    /// Emission in the smallest unit of the token per second, split across all stakers.
+    pub rate: Balance,
+    /// Funded rewards that were not emitted yet.
+    pub pool: Balance,
+    pub acc_reward_per_share: Balance,
+    pub last_update_timestamp: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+pub struct TokenRewardAccount {
+    /// Value of `acc_reward_per_share` when the rewards of this account were last settled.
+    pub reward_per_share_paid: Balance,
+    pub unclaimed: Balance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RewardTokenView {
+    pub token_id: AccountId,
+    pub rate: U128,
+    pub pool: U128,
+    pub last_update_timestamp: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RewardTokens {
+    pub tokens: UnorderedMap<AccountId, RewardToken>,
+    /// Rewards of each (account, token).
+    pub accounts: LookupMap<(AccountId, AccountId), TokenRewardAccount>,
+}
+
+impl RewardToken {
+    /// Returns the whole seconds elapsed since the last update and the reward emitted in them.
+    fn accrued(&self, total_staked: Balance) -> (u64, Balance) {
+        let elapsed = (env::block_timestamp() - self.last_update_timestamp) / NANOS_PER_SECOND;
+        if total_staked == 0 {
+            return (elapsed, 0);
+        }
+        let reward = std::cmp::min(self.rate * Balance::from(elapsed), self.pool);
+        (elapsed, reward)
+    }
+
+    fn current_acc_reward_per_share(&self, total_staked: Balance) -> Balance {
+        let (_, reward) = self.accrued(total_staked);
+        if reward == 0 {
+            return self.acc_reward_per_share;
+        }
+        self.acc_reward_per_share + mul_div(reward, ACC_REWARD_PRECISION, total_staked)
+    }
+
+    fn update(&mut self, total_staked: Balance) {
+        let (elapsed, reward) = self.accrued(total_staked);
+        self.acc_reward_per_share = self.current_acc_reward_per_share(total_staked);
+        self.pool -= reward;
+        self.last_update_timestamp += elapsed * NANOS_PER_SECOND;
+    }
+}
+
+impl RewardTokens {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            tokens: UnorderedMap::new([prefix.as_slice(), b"t"].concat()),
+            accounts: LookupMap::new([prefix.as_slice(), b"a"].concat()),
+        }
+    }
+
+    /// Emits the rewards of every token accrued since its last update.
+    pub fn update(&mut self, total_staked: Balance) {
+        for (_, token) in self.tokens.iter_mut() {
+            token.update(total_staked);
+        }
+    }
+
+    /// Settles the rewards of `account_id` earned with `staked` before its stake changes to
+    /// `new_staked`. Expects the tokens to be updated.
+    pub fn settle(&mut self, account_id: &AccountId, staked: Balance, new_staked: Balance) {
+        for (token_id, token) in self.tokens.iter() {
+            let key = (account_id.clone(), token_id.clone());
+            let mut account = self.accounts.get(&key).cloned().unwrap_or_default();
+            account.unclaimed += mul_div(
+                staked,
+                token.acc_reward_per_share - account.reward_per_share_paid,
+                ACC_REWARD_PRECISION,
+            );
+            account.reward_per_share_paid = token.acc_reward_per_share;
+            if new_staked == 0 && account.unclaimed == 0 {
+                self.accounts.remove(&key);
+            } else {
+                self.accounts.insert(key, account);
+            }
+        }
+    }
+
+    /// Drops the rewards of `account_id` in every token, which keeps `staked` staked.
+    pub fn forfeit(&mut self, account_id: &AccountId, staked: Balance) {
+        for (token_id, token) in self.tokens.iter() {
+            let key = (account_id.clone(), token_id.clone());
+            if staked == 0 {
+                self.accounts.remove(&key);
+            } else {
+                self.accounts.insert(
+                    key,
+                    TokenRewardAccount {
+                        reward_per_share_paid: token.acc_reward_per_share,
+                        unclaimed: 0,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Moves the settled rewards of `account_id`, which has no stake left, in every token to
+    /// `receiver_id`.
+    pub fn move_unclaimed(&mut self, account_id: &AccountId, receiver_id: &AccountId) {
+        let token_ids: Vec<AccountId> = self.tokens.keys().cloned().collect();
+        for token_id in token_ids {
+            let unclaimed = match self
+                .accounts
+                .remove(&(account_id.clone(), token_id.clone()))
+            {
+                Some(account) if account.unclaimed > 0 => account.unclaimed,
+                _ => continue,
+            };
+            let key = (receiver_id.clone(), token_id);
+            let mut receiver = self.accounts.get(&key).cloned().unwrap_or_default();
+            receiver.unclaimed += unclaimed;
+            self.accounts.insert(key, receiver);
+        }
+    }
+
+    /// Rewards of `token_id` claimable by an account with `staked`, including emissions not yet
+    /// written to state.
+    pub fn pending(
+        &self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        staked: Balance,
+        total_staked: Balance,
+    ) -> Balance {
+        let token = match self.tokens.get(token_id) {
+            Some(token) => token,
+            None => return 0,
+        };
+        let account = self
+            .accounts
+            .get(&(account_id.clone(), token_id.clone()))
+            .cloned()
+            .unwrap_or_default();
+        account.unclaimed
+            + mul_div(
+                staked,
+                token.current_acc_reward_per_share(total_staked) - account.reward_per_share_paid,
+                ACC_REWARD_PRECISION,
+            )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn register_reward_token(&mut self, token_id: AccountId, rate: U128) {
+        self.assert_owner();
+        assert_ne!(
+            token_id,
+            env::current_account_id(),
+            "ASTRO rewards use the reward pool"
+        );
+        let tokens = &self.staking.reward_tokens.tokens;
+        assert!(
+            !tokens.contains_key(&token_id),
+            "The reward token {} is already registered",
+            token_id
+        );
+        assert!(
+            tokens.len() < MAX_REWARD_TOKENS,
+            "At most {} reward tokens",
+            MAX_REWARD_TOKENS
+        );
+        self.staking.update();
+        log!("@{} registered as a reward token", token_id);
+        self.staking.reward_tokens.tokens.insert(
+            token_id,
+            RewardToken {
+                rate: rate.0,
+                pool: 0,
+                acc_reward_per_share: 0,
+                last_update_timestamp: env::block_timestamp(),
+            },
+        );
+    }
+
+    pub fn set_reward_token_rate(&mut self, token_id: AccountId, rate: U128) {
+        self.assert_owner();
+        self.staking.update();
+        let token = self
+            .staking
+            .reward_tokens
+            .tokens
+            .get_mut(&token_id)
+            .expect("Unknown reward token");
+        token.rate = rate.0;
+        log!("Rewards of {} set to {} per second", token_id, rate.0);
+    }
+
+    /// Transfers the accrued rewards of `token_id` to the caller.
+    #[payable]
+    pub fn claim_token_rewards(&mut self, token_id: AccountId) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.internal_settle_token_rewards(&account_id);
+        let amount = self.internal_take_token_rewards(&account_id, &token_id);
+        assert!(amount > 0, "No rewards to claim");
+        self.internal_pay_token_rewards(account_id, token_id, amount)
+    }
+
+    /// Transfers the accrued rewards of every reward token to the caller. Returns the amounts
+    /// sent, one transfer per token.
+    #[payable]
+    pub fn claim_all_token_rewards(&mut self) -> Vec<(AccountId, U128)> {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.internal_settle_token_rewards(&account_id);
+        let token_ids: Vec<AccountId> = self.staking.reward_tokens.tokens.keys().cloned().collect();
+        let mut claimed = vec![];
+        for token_id in token_ids {
+            let amount = self.internal_take_token_rewards(&account_id, &token_id);
+            if amount > 0 {
+                self.internal_pay_token_rewards(account_id.clone(), token_id.clone(), amount);
+                claimed.push((token_id, U128(amount)));
+            }
+        }
+        assert!(!claimed.is_empty(), "No rewards to claim");
+        claimed
+    }
+
+    #[private]
+    pub fn on_token_rewards_claimed(
+        &mut self,
+        account_id: AccountId,
+        token_id: AccountId,
+        amount: U128,
+    ) {
+        if !is_promise_success() {
+            let rewards = &mut self.staking.reward_tokens;
+            let key = (account_id.clone(), token_id.clone());
+            let mut account = rewards.accounts.get(&key).cloned().unwrap_or_default();
+            account.unclaimed += amount.0;
+            rewards.accounts.insert(key, account);
+            log!(
+                "Paying {} of {} to @{} failed, the rewards were credited back",
+                amount.0,
+                token_id,
+                account_id
+            );
+        }
+    }
+
+    pub fn get_reward_tokens(&self) -> Vec<RewardTokenView> {
+        self.staking
+            .reward_tokens
+            .tokens
+            .iter()
+            .map(|(token_id, token)| RewardTokenView {
+                token_id: token_id.clone(),
+                rate: token.rate.into(),
+                pool: token.pool.into(),
+                last_update_timestamp: token.last_update_timestamp.into(),
+            })
+            .collect()
+    }
+
+    /// Rewards of every reward token claimable by `account_id` right now.
+    pub fn get_unclaimed_token_rewards(&self, account_id: AccountId) -> Vec<(AccountId, U128)> {
+        let staked = self.get_staked_balance(account_id.clone()).0;
+        let rewards = &self.staking.reward_tokens;
+        rewards
+            .tokens
+            .keys()
+            .map(|token_id| {
+                let pending =
+                    rewards.pending(&account_id, token_id, staked, self.staking.accruing_stake());
+                (token_id.clone(), U128(pending))
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Adds `amount` to the funded rewards of `token_id`.
+    pub(crate) fn internal_fund_token_rewards(
+        &mut self,
+        token_id: &AccountId,
+        sender_id: &AccountId,
+        amount: Balance,
+    ) {
+        assert!(
+            self.staking.reward_tokens.tokens.contains_key(token_id),
+            "{} is not a reward token",
+            token_id
+        );
+        self.staking.update();
+        let token = self.staking.reward_tokens.tokens.get_mut(token_id).unwrap();
+        token.pool += amount;
+        log!(
+            "@{} funded the staking rewards with {} of {}",
+            sender_id,
+            amount,
+            token_id
+        );
+    }
+
+    fn internal_settle_token_rewards(&mut self, account_id: &AccountId) {
+        self.staking.update();
+        let account = self.staking.settled_account(account_id);
+        self.staking.save_account(account_id, &account);
+    }
+
+    /// Zeroes the settled rewards of `token_id` of `account_id` and returns them.
+    fn internal_take_token_rewards(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+    ) -> Balance {
+        let key = (account_id.clone(), token_id.clone());
+        let mut account = match self.staking.reward_tokens.accounts.get(&key).cloned() {
+            Some(account) => account,
+            None => return 0,
+        };
+        let amount = account.unclaimed;
+        account.unclaimed = 0;
+        if self.get_staked_balance(account_id.clone()).0 == 0 {
+            self.staking.reward_tokens.accounts.remove(&key);
+        } else {
+            self.staking.reward_tokens.accounts.insert(key, account);
+        }
+        amount
+    }
+
+    fn internal_pay_token_rewards(
+        &mut self,
+        account_id: AccountId,
+        token_id: AccountId,
+        amount: Balance,
+    ) -> Promise {
+        log!(
+            "Account @{} claimed {} of {} in staking rewards",
+            account_id,
+            amount,
+            token_id
+        );
+        ext_ft::ext(token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(account_id.clone(), U128(amount), None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_CLAIM)
+                    .on_token_rewards_claimed(account_id, token_id, U128(amount)),
+            )
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    #[test]
+    fn test_reward_tokens_accrue_independently() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        let owner = owner();
+        contract.register_reward_token(accounts(3), U128(10));
+        contract.register_reward_token(accounts(4), U128(1));
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.ft_on_transfer(owner.clone(), U128(1_000), "staking_rewards".to_string());
+        testing_env!(context.predecessor_account_id(accounts(4)).build());
+        contract.ft_on_transfer(owner.clone(), U128(5), "staking_rewards".to_string());
+
+        testing_env!(context
+            .predecessor_account_id(owner.clone())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.stake(U128(100), None);
+        testing_env!(context
+            .block_timestamp(10 * NANOS_PER_SECOND)
+            .attached_deposit(1)
+            .build());
+        assert_eq!(
+            contract.get_unclaimed_token_rewards(owner.clone()),
+            vec![(accounts(3), U128(100)), (accounts(4), U128(5))]
+        );
+        contract.unstake(U128(100));
+        let claimed = contract.claim_all_token_rewards();
+        assert_eq!(
+            claimed,
+            vec![(accounts(3), U128(100)), (accounts(4), U128(5))]
+        );
+        assert!(contract
+            .get_unclaimed_token_rewards(owner)
+            .iter()
+            .all(|(_, amount)| amount.0 == 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a reward token")]
+    fn test_unregistered_token_is_refused() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, accounts(3), 0);
+        contract.ft_on_transfer(accounts(1), U128(1_000), "staking_rewards".to_string());
+    }
+}
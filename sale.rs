@@ -0,0 +1,459 @@
+/*!
+Public sale of ASTRO for NEAR, at a fixed price or by Dutch auction.
+NOTES:
+  - The owner or the DAO configures a sale: the price in yoctoNEAR per whole ASTRO, the sale
+    window, the allocation and the cap per wallet. The tokens are either minted within the
+    emission schedule or drawn from a sale pool funded out of the treasury.
+  - Buyers call `buy` with NEAR attached and get as many tokens as it pays for, within what is
+    left of the allocation and of their cap. The NEAR above the price of the tokens bought is
+    refunded. Buyers must be registered and pay for the storage of their purchase record.
+  - With Dutch auction pricing the price falls from `price` to a floor over the sale window and
+    every buyer pays the clearing price in the end, see `dutch_auction`.
+  - A sale can open with a presale for listed accounts, see `presale`. The public round starts
+    when the presale ends and sells what is left of the allocation.
+  - A sale can be changed or replaced until it starts, and replaced once it has ended. Caps are
+    per sale.
+  - After the end the owner or the DAO withdraws the proceeds with `withdraw_sale_proceeds`.
+    Unsold pool tokens go back to the treasury at the same time.
+  - A sale can release its purchases on a vesting schedule instead of paying them out, see
+    `sale_vesting`.
+  - The proceeds are not idle NEAR until they are withdrawn, so neither liquid staking nor
+    `rescue_near` can use them.
+*/
+#![cfg_attr(not(feature = "sale"), allow(dead_code, unused_imports))]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::LookupMap;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, Promise};
+
+use crate::dutch_auction::SalePricing;
+use crate::presale::PresaleConfig;
+use crate::sale_vesting::{SaleVesting, VestingSchedule};
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleConfig {
+    /// Price in yoctoNEAR of one whole ASTRO, i.e. 10^decimals of its smallest unit.
+    pub price: U128,
+    pub start_at: U64,
+    pub end_at: U64,
+    pub allocation: U128,
+    pub per_wallet_cap: U128,
+    /// Whether sold tokens are minted rather than drawn from the sale pool.
+    pub mint: bool,
+    #[serde(default)]
+    pub presale: Option<PresaleConfig>,
+    #[serde(default)]
+    pub pricing: SalePricing,
+    /// Schedule on which purchases are released, `None` to pay them out at once.
+    #[serde(default)]
+    pub vesting: Option<VestingSchedule>,
+}
+
+impl SaleConfig {
+    /// Start of the public round, after the presale if there is one.
+    pub fn public_start_at(&self) -> u64 {
+        self.presale
+            .as_ref()
+            .map_or(self.start_at.0, |presale| presale.end_at.0)
+    }
+
+    /// Price of one whole token at `timestamp`.
+    pub fn price_at(&self, timestamp: u64) -> Balance {
+        match &self.pricing {
+            SalePricing::Fixed => self.price.0,
+            SalePricing::DutchAuction { .. } => self.auction_price_at(timestamp),
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Purchase {
+    pub amount: U128,
+    /// NEAR paid for `amount`, less auction refunds.
+    pub paid: U128,
+}
+
+impl Default for Purchase {
+    fn default() -> Self {
+        Self {
+            amount: U128(0),
+            paid: U128(0),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleStats {
+    pub sale_id: U64,
+    pub config: Option<SaleConfig>,
+    pub sold: U128,
+    pub presale_sold: U128,
+    pub remaining: U128,
+    pub raised: U128,
+    pub clearing_price: Option<U128>,
+    pub buyers: U64,
+    pub pool: U128,
+    pub proceeds_withdrawn: bool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Sale {
+    pub sale_id: u64,
+    pub config: Option<SaleConfig>,
+    pub sold: Balance,
+    pub presale_sold: Balance,
+    /// NEAR paid by buyers, held by the contract until withdrawn or refunded.
+    pub raised: Balance,
+    pub refunded: Balance,
+    pub withdrawn: Balance,
+    /// Auction price at which the allocation sold out.
+    pub sold_out_price: Option<Balance>,
+    pub buyers: u64,
+    /// Treasury tokens set aside for sales that do not mint.
+    pub pool: Balance,
+    pub proceeds_withdrawn: bool,
+    /// Purchase of each account in each sale.
+    pub purchases: LookupMap<(u64, AccountId), Purchase>,
+    /// Presale tier of each listed account in each sale.
+    pub presale_accounts: LookupMap<(u64, AccountId), u8>,
+    pub vesting: SaleVesting,
+}
+
+impl Sale {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            sale_id: 0,
+            config: None,
+            sold: 0,
+            presale_sold: 0,
+            raised: 0,
+            refunded: 0,
+            withdrawn: 0,
+            sold_out_price: None,
+            buyers: 0,
+            pool: 0,
+            proceeds_withdrawn: false,
+            purchases: LookupMap::new([prefix.as_slice(), b"p"].concat()),
+            presale_accounts: LookupMap::new([prefix.as_slice(), b"a"].concat()),
+            vesting: SaleVesting::new([prefix.as_slice(), b"v"].concat()),
+        }
+    }
+
+    pub fn purchase(&self, account_id: &AccountId) -> Purchase {
+        self.purchases
+            .get(&(self.sale_id, account_id.clone()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn purchased(&self, account_id: &AccountId) -> Balance {
+        self.purchase(account_id).amount.0
+    }
+
+    /// NEAR raised that was neither withdrawn nor refunded yet.
+    pub fn held_proceeds(&self) -> Balance {
+        self.raised - self.refunded - self.withdrawn
+    }
+
+    pub(crate) fn has_started(&self) -> bool {
+        matches!(&self.config, Some(config) if env::block_timestamp() >= config.start_at.0)
+    }
+
+    pub(crate) fn has_ended(&self) -> bool {
+        matches!(&self.config, Some(config) if env::block_timestamp() >= config.end_at.0)
+    }
+}
+
+#[cfg(feature = "sale")]
+#[near_bindgen]
+impl Contract {
+    /// Configures the sale. Replaces a sale that has not started, or starts a new one once the
+    /// previous one ended and its proceeds were withdrawn.
+    pub fn set_sale_config(&mut self, config: SaleConfig) {
+        self.assert_owner_or_dao();
+        assert!(config.price.0 > 0, "The price should be a positive number");
+        assert!(
+            config.start_at.0 < config.end_at.0,
+            "The sale must end after it starts"
+        );
+        assert!(
+            config.end_at.0 > env::block_timestamp(),
+            "The sale must end in the future"
+        );
+        if let Some(presale) = config.presale.as_ref() {
+            presale.assert_valid(&config);
+        }
+        config.pricing.assert_valid(&config);
+        if let Some(vesting) = config.vesting.as_ref() {
+            vesting.assert_valid();
+        }
+        if self.sale.has_started() {
+            assert!(
+                self.sale.has_ended() && self.sale.proceeds_withdrawn,
+                "The current sale is not over"
+            );
+        }
+        if self.sale.config.is_none() || self.sale.has_ended() {
+            self.sale.sale_id += 1;
+            self.sale.sold = 0;
+            self.sale.presale_sold = 0;
+            self.sale.raised = 0;
+            self.sale.refunded = 0;
+            self.sale.withdrawn = 0;
+            self.sale.sold_out_price = None;
+            self.sale.buyers = 0;
+            self.sale.proceeds_withdrawn = false;
+        }
+        log!(
+            "Sale {} of {} at {} yoctoNEAR per token",
+            self.sale.sale_id,
+            config.allocation.0,
+            config.price.0
+        );
+        self.sale.config = Some(config);
+    }
+
+    /// Moves `amount` of the treasury into the sale pool.
+    pub fn fund_sale_pool(&mut self, amount: U128) {
+        self.assert_owner_or_dao();
+        assert!(
+            amount.0 <= self.treasury_balance,
+            "The treasury holds {}",
+            self.treasury_balance
+        );
+        self.treasury_balance -= amount.0;
+        self.sale.pool += amount.0;
+    }
+
+    /// Buys tokens in the public round with the attached NEAR and refunds what is not used.
+    /// The owner of `referral_code` earns a referral reward. Returns the amount bought.
+    #[payable]
+    pub fn buy(&mut self, referral_code: Option<String>) -> U128 {
+        let config = self.sale.config.clone().expect("No sale is configured");
+        let now = env::block_timestamp();
+        assert!(
+            now >= config.public_start_at() && now < config.end_at.0,
+            "The public sale is not open"
+        );
+        let buyer_id = env::predecessor_account_id();
+        let purchased = self.sale.purchased(&buyer_id);
+        let max_amount = config.per_wallet_cap.0.saturating_sub(purchased);
+        let amount = self.internal_buy(config.price_at(now), max_amount);
+        if let Some(code) = referral_code {
+            self.internal_refer_purchase(&code, &buyer_id, amount);
+        }
+        amount.into()
+    }
+
+    /// Sends the proceeds of an ended sale to the caller and returns unsold pool tokens to the
+    /// treasury.
+    pub fn withdraw_sale_proceeds(&mut self) -> U128 {
+        self.assert_owner_or_dao();
+        assert!(self.sale.has_ended(), "The sale has not ended");
+        assert!(
+            !self.sale.proceeds_withdrawn,
+            "The proceeds were already withdrawn"
+        );
+        self.sale.proceeds_withdrawn = true;
+        let unsold = std::mem::take(&mut self.sale.pool);
+        self.treasury_balance += unsold;
+        let proceeds = match self.sale.clearing_price() {
+            Some(clearing_price) => mul_div(self.sale.sold, clearing_price, self.one_token()),
+            None => self.sale.raised,
+        };
+        self.sale.withdrawn = proceeds;
+        if proceeds > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(proceeds);
+        }
+        log!(
+            "Withdrew {} yoctoNEAR of sale proceeds, {} unsold tokens back to the treasury",
+            proceeds,
+            unsold
+        );
+        proceeds.into()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_sale_stats(&self) -> SaleStats {
+        let allocation = self
+            .sale
+            .config
+            .as_ref()
+            .map_or(0, |config| config.allocation.0);
+        SaleStats {
+            sale_id: self.sale.sale_id.into(),
+            config: self.sale.config.clone(),
+            sold: self.sale.sold.into(),
+            presale_sold: self.sale.presale_sold.into(),
+            remaining: (allocation - self.sale.sold).into(),
+            raised: self.sale.raised.into(),
+            clearing_price: self.sale.clearing_price().map(U128),
+            buyers: self.sale.buyers.into(),
+            pool: self.sale.pool.into(),
+            proceeds_withdrawn: self.sale.proceeds_withdrawn,
+        }
+    }
+
+    /// Purchase of `account_id` in the current sale.
+    pub fn get_sale_purchase(&self, account_id: AccountId) -> Purchase {
+        self.sale.purchase(&account_id)
+    }
+}
+
+impl Contract {
+    /// Sells the caller as many tokens as the attached NEAR pays for at `price`, up to
+    /// `max_amount` and what is left of the allocation, and refunds the rest.
+    pub(crate) fn internal_buy(&mut self, price: Balance, max_amount: Balance) -> Balance {
+        self.assert_module_active(Module::Sale);
+        let config = self.sale.config.clone().expect("No sale is configured");
+        let buyer_id = env::predecessor_account_id();
+        assert!(
+            self.token.accounts.contains_key(&buyer_id),
+            "The account {} is not registered",
+            buyer_id
+        );
+        let purchase = self.sale.purchase(&buyer_id);
+
+        // The purchase record and the vesting entry have the same size whatever the amount, so
+        // they are written first to charge their storage.
+        let initial_storage = env::storage_usage();
+        self.sale
+            .purchases
+            .insert((self.sale.sale_id, buyer_id.clone()), purchase);
+        self.sale.purchases.flush();
+        if let Some(vesting) = config.vesting {
+            self.sale
+                .vesting
+                .open_entry(&buyer_id, self.sale.sale_id, vesting);
+            self.sale.vesting.entries.flush();
+        }
+        let storage_cost =
+            env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage);
+        let deposit = env::attached_deposit();
+        assert!(
+            deposit > storage_cost,
+            "Must attach more than {} yoctoNEAR for storage",
+            storage_cost
+        );
+
+        let one_token = self.one_token();
+        let affordable = mul_div(deposit - storage_cost, one_token, price);
+        let amount = affordable
+            .min(config.allocation.0 - self.sale.sold)
+            .min(max_amount);
+        assert!(amount > 0, "Nothing left to buy");
+        let cost = mul_div(amount, price, one_token);
+
+        let contract_id = env::current_account_id();
+        if config.vesting.is_some() {
+            if config.mint {
+                self.internal_register_contract_account();
+                self.internal_mint(&contract_id, &contract_id, amount);
+            } else {
+                assert!(amount <= self.sale.pool, "The sale pool is empty");
+                self.sale.pool -= amount;
+            }
+            self.sale.vesting.add(&buyer_id, self.sale.sale_id, amount);
+        } else if config.mint {
+            self.internal_mint(&contract_id, &buyer_id, amount);
+        } else {
+            assert!(amount <= self.sale.pool, "The sale pool is empty");
+            self.sale.pool -= amount;
+            self.internal_unlock(&buyer_id, amount);
+        }
+        if purchase.amount.0 == 0 {
+            self.sale.buyers += 1;
+        }
+        self.sale.purchases.insert(
+            (self.sale.sale_id, buyer_id.clone()),
+            Purchase {
+                amount: (purchase.amount.0 + amount).into(),
+                paid: (purchase.paid.0 + cost).into(),
+            },
+        );
+        self.sale.sold += amount;
+        self.sale.raised += cost;
+        if self.sale.sold == config.allocation.0 && config.pricing != SalePricing::Fixed {
+            self.sale.sold_out_price = Some(price);
+        }
+        let refund = deposit - storage_cost - cost;
+        if refund > 0 {
+            Promise::new(buyer_id.clone()).transfer(refund);
+        }
+        log!("@{} bought {} for {} yoctoNEAR", buyer_id, amount, cost);
+        amount
+    }
+
+    /// Smallest units in one whole token.
+    pub(crate) fn one_token(&self) -> Balance {
+        10u128.pow(u32::from(self.metadata.get().unwrap().decimals))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "sale"))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    const ONE_ASTRO: Balance = 1_000_000_000_000_000_000;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 1);
+        contract.treasury_deposit(U128(100 * ONE_ASTRO));
+        contract.fund_sale_pool(U128(100 * ONE_ASTRO));
+        contract.set_sale_config(SaleConfig {
+            price: U128(ONE_NEAR / 10),
+            start_at: U64(0),
+            end_at: U64(1_000),
+            allocation: U128(100 * ONE_ASTRO),
+            per_wallet_cap: U128(30 * ONE_ASTRO),
+            mint: false,
+            presale: None,
+            pricing: SalePricing::Fixed,
+            vesting: None,
+        });
+        call_as(&mut context, accounts(1), ONE_NEAR);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_buy_within_wallet_cap() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(5 * ONE_NEAR).build());
+        assert_eq!(contract.buy(None).0, 30 * ONE_ASTRO);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 30 * ONE_ASTRO);
+        let stats = contract.get_sale_stats();
+        assert_eq!(stats.raised.0, 3 * ONE_NEAR);
+        assert_eq!(stats.pool.0, 70 * ONE_ASTRO);
+        assert_eq!(stats.buyers.0, 1);
+    }
+
+    #[test]
+    fn test_withdraw_proceeds_after_end() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        let bought = contract.buy(None).0;
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .block_timestamp(1_000)
+            .build());
+        assert_eq!(
+            contract.withdraw_sale_proceeds().0,
+            contract.get_sale_stats().raised.0
+        );
+        assert_eq!(contract.get_treasury_balance().0, 100 * ONE_ASTRO - bought);
+        assert_eq!(contract.get_sale_stats().pool.0, 0);
+    }
+}
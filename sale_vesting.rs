@@ -0,0 +1,250 @@
+/*!
+Vesting of sale purchases: tokens bought are released on the schedule of the sale.
+NOTES:
+  - A sale config can carry a `VestingSchedule`: `tge_bps` of a purchase unlocks at `start_at`,
+    the token generation event, and the rest linearly over `duration` from there.
+  - Purchases of such a sale, presale included, are not paid out. The tokens stay in the
+    contract's custody and are recorded as one vesting entry per buyer and sale, with a copy of
+    the schedule so that replacing the sale config later does not change it.
+  - `claim_sale_vesting` pays everything vested across the caller's entries, `claimable` shows
+    that amount. Fully claimed entries are removed and their storage refunded.
+  - The buyer pays for the storage of its entry with the storage of the purchase record.
+*/
+#![cfg_attr(not(feature = "sale"), allow(dead_code, unused_imports))]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const MAX_BPS: u16 = 10_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingSchedule {
+    pub start_at: U64,
+    /// Part of a purchase unlocked at `start_at`, in basis points.
+    pub tge_bps: u16,
+    /// Nanoseconds over which the rest unlocks linearly.
+    pub duration: U64,
+}
+
+impl VestingSchedule {
+    pub fn assert_valid(&self) {
+        assert!(
+            self.tge_bps <= MAX_BPS,
+            "The TGE unlock should be at most {} bps",
+            MAX_BPS
+        );
+        assert!(
+            self.duration.0 > 0 || self.tge_bps == MAX_BPS,
+            "The vesting duration should be a positive number"
+        );
+    }
+
+    /// Part of `total` unlocked at `timestamp`.
+    pub fn vested(&self, total: Balance, timestamp: u64) -> Balance {
+        if timestamp < self.start_at.0 {
+            return 0;
+        }
+        let tge = mul_div(total, Balance::from(self.tge_bps), Balance::from(MAX_BPS));
+        let elapsed = std::cmp::min(timestamp - self.start_at.0, self.duration.0);
+        if elapsed == self.duration.0 {
+            return total;
+        }
+        tge + mul_div(
+            total - tge,
+            Balance::from(elapsed),
+            Balance::from(self.duration.0),
+        )
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingEntry {
+    pub sale_id: U64,
+    pub schedule: VestingSchedule,
+    pub total: U128,
+    pub claimed: U128,
+}
+
+impl VestingEntry {
+    fn claimable(&self, timestamp: u64) -> Balance {
+        self.schedule.vested(self.total.0, timestamp) - self.claimed.0
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct SaleVesting {
+    /// Tokens held for vesting entries.
+    pub held: Balance,
+    pub entries: LookupMap<AccountId, Vec<VestingEntry>>,
+}
+
+impl SaleVesting {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            held: 0,
+            entries: LookupMap::new(prefix),
+        }
+    }
+
+    /// Makes sure `account_id` has an entry for `sale_id`, so its storage can be charged.
+    pub(crate) fn open_entry(
+        &mut self,
+        account_id: &AccountId,
+        sale_id: u64,
+        schedule: VestingSchedule,
+    ) {
+        let mut entries = self.entries.get(account_id).cloned().unwrap_or_default();
+        if entries.iter().all(|entry| entry.sale_id.0 != sale_id) {
+            entries.push(VestingEntry {
+                sale_id: sale_id.into(),
+                schedule,
+                total: U128(0),
+                claimed: U128(0),
+            });
+            self.entries.insert(account_id.clone(), entries);
+        }
+    }
+
+    /// Adds `amount` to the entry of `account_id` for `sale_id`, opened by `open_entry`.
+    pub(crate) fn add(&mut self, account_id: &AccountId, sale_id: u64, amount: Balance) {
+        let entries = self.entries.get_mut(account_id).unwrap();
+        let entry = entries
+            .iter_mut()
+            .find(|entry| entry.sale_id.0 == sale_id)
+            .unwrap();
+        entry.total.0 += amount;
+        self.held += amount;
+    }
+
+    pub fn claimable(&self, account_id: &AccountId) -> Balance {
+        let now = env::block_timestamp();
+        self.entries.get(account_id).map_or(0, |entries| {
+            entries.iter().map(|entry| entry.claimable(now)).sum()
+        })
+    }
+}
+
+#[cfg(feature = "sale")]
+#[near_bindgen]
+impl Contract {
+    /// Pays the caller everything vested across its sale purchases. Returns the amount claimed.
+    #[payable]
+    pub fn claim_sale_vesting(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut entries = self
+            .sale
+            .vesting
+            .entries
+            .get(&account_id)
+            .cloned()
+            .expect("The account has no vesting purchases");
+        let now = env::block_timestamp();
+        let mut amount = 0;
+        for entry in entries.iter_mut() {
+            let claimable = entry.claimable(now);
+            entry.claimed.0 += claimable;
+            amount += claimable;
+        }
+        assert!(amount > 0, "Nothing vested to claim");
+        entries.retain(|entry| entry.claimed != entry.total);
+
+        let initial_storage = env::storage_usage();
+        if entries.is_empty() {
+            self.sale.vesting.entries.remove(&account_id);
+        } else {
+            self.sale
+                .vesting
+                .entries
+                .insert(account_id.clone(), entries);
+        }
+        self.sale.vesting.entries.flush();
+        self.sale.vesting.held -= amount;
+        self.internal_unlock(&account_id, amount);
+        refund_released_storage(&account_id, initial_storage);
+        log!(
+            "Account @{} claimed {} of vested purchases",
+            account_id,
+            amount
+        );
+        amount.into()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Vested and unclaimed tokens of `account_id` across all its sale purchases.
+    pub fn claimable(&self, account_id: AccountId) -> U128 {
+        self.sale.vesting.claimable(&account_id).into()
+    }
+
+    pub fn get_sale_vesting(&self, account_id: AccountId) -> Vec<VestingEntry> {
+        self.sale
+            .vesting
+            .entries
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "sale"))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::dutch_auction::SalePricing;
+    use crate::sale::SaleConfig;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    const ONE_ASTRO: Balance = 1_000_000_000_000_000_000;
+
+    #[test]
+    fn test_purchase_vests_after_tge() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 1);
+        contract.treasury_deposit(U128(100 * ONE_ASTRO));
+        contract.fund_sale_pool(U128(100 * ONE_ASTRO));
+        contract.set_sale_config(SaleConfig {
+            price: U128(ONE_NEAR / 10),
+            start_at: U64(0),
+            end_at: U64(1_000),
+            allocation: U128(100 * ONE_ASTRO),
+            per_wallet_cap: U128(10 * ONE_ASTRO),
+            mint: false,
+            presale: None,
+            pricing: SalePricing::Fixed,
+            vesting: Some(VestingSchedule {
+                start_at: U64(1_000),
+                tge_bps: 2_500,
+                duration: U64(1_000),
+            }),
+        });
+        call_as(&mut context, accounts(1), 2 * ONE_NEAR);
+        assert_eq!(contract.buy(None).0, 10 * ONE_ASTRO);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.claimable(accounts(1)).0, 0);
+
+        testing_env!(context.block_timestamp(1_500).attached_deposit(1).build());
+        assert_eq!(contract.claimable(accounts(1)).0, 6_250 * ONE_ASTRO / 1_000);
+        contract.claim_sale_vesting();
+        assert_eq!(
+            contract.ft_balance_of(accounts(1)).0,
+            6_250 * ONE_ASTRO / 1_000
+        );
+
+        testing_env!(context.block_timestamp(2_000).build());
+        contract.claim_sale_vesting();
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 10 * ONE_ASTRO);
+        assert!(contract.get_sale_vesting(accounts(1)).is_empty());
+    }
+}
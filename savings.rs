@@ -0,0 +1,268 @@
+/*!
+Savings: fixed-term deposits with a reward paid at maturity.
+NOTES:
+  - `open_term_deposit` locks tokens in the contract custody for 30, 90 or 180 days. The reward of
+    the term is set by the owner or the DAO as basis points of the principal for the whole term,
+    and is reserved from the savings reward pool when the deposit opens, so a matured deposit is
+    always paid.
+  - `close_term_deposit` after maturity pays the principal and the reward. Closing earlier pays
+    the principal only; the reserved reward goes back to the pool.
+  - Rates apply to deposits opened after they change. The pool is funded from the treasury with
+    `fund_savings_rewards`.
+  - Depositors pay for the storage of their deposits and get it back on closing.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+const TERMS: [u32; 3] = [30, 90, 180];
+const MAX_BPS: u128 = 10_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TermDeposit {
+    pub owner_id: AccountId,
+    pub amount: U128,
+    pub reward: U128,
+    pub opened_at: U64,
+    pub matures_at: U64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TermDepositView {
+    pub deposit_id: U64,
+    #[serde(flatten)]
+    pub deposit: TermDeposit,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Savings {
+    pub deposits: LookupMap<u64, TermDeposit>,
+    /// Deposit ids of each owner.
+    pub by_owner: LookupMap<AccountId, Vec<u64>>,
+    pub next_deposit_id: u64,
+    /// Reward of each term in days, in basis points of the principal.
+    pub rates: Vec<(u32, u16)>,
+    pub reward_pool: Balance,
+    /// Part of the reward pool promised to open deposits.
+    pub reserved: Balance,
+    /// Principal of the open deposits.
+    pub principal: Balance,
+}
+
+impl Savings {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            deposits: LookupMap::new([prefix.as_slice(), b"d"].concat()),
+            by_owner: LookupMap::new([prefix.as_slice(), b"o"].concat()),
+            next_deposit_id: 0,
+            rates: vec![],
+            reward_pool: 0,
+            reserved: 0,
+            principal: 0,
+        }
+    }
+
+    fn rate(&self, term_days: u32) -> Option<u16> {
+        self.rates
+            .iter()
+            .find(|(days, _)| *days == term_days)
+            .map(|(_, bps)| *bps)
+    }
+
+    fn deposit_ids(&self, account_id: &AccountId) -> Vec<u64> {
+        self.by_owner.get(account_id).cloned().unwrap_or_default()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the reward of deposits for `term_days` to `bps` of the principal, 0 to stop offering
+    /// the term.
+    pub fn set_term_rate(&mut self, term_days: u32, bps: u16) {
+        self.assert_owner_or_dao();
+        assert!(TERMS.contains(&term_days), "Terms are {:?} days", TERMS);
+        self.savings.rates.retain(|(days, _)| *days != term_days);
+        if bps > 0 {
+            self.savings.rates.push((term_days, bps));
+        }
+    }
+
+    /// Moves `amount` of the treasury into the savings reward pool.
+    pub fn fund_savings_rewards(&mut self, amount: U128) {
+        self.assert_owner_or_dao();
+        assert!(
+            amount.0 <= self.treasury_balance,
+            "The treasury holds {}",
+            self.treasury_balance
+        );
+        self.treasury_balance -= amount.0;
+        self.savings.reward_pool += amount.0;
+        log!("Funded the savings rewards with {}", amount.0);
+    }
+
+    /// Locks `amount` of the caller's tokens for `term_days` and returns the deposit id.
+    #[payable]
+    pub fn open_term_deposit(&mut self, amount: U128, term_days: u32) -> U64 {
+        self.assert_module_active(Module::Savings);
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        let bps = self
+            .savings
+            .rate(term_days)
+            .unwrap_or_else(|| env::panic_str("The term is not offered"));
+        let reward = mul_div(amount.0, u128::from(bps), MAX_BPS);
+        assert!(
+            reward <= self.savings.reward_pool - self.savings.reserved,
+            "The savings reward pool cannot cover the reward"
+        );
+        let initial_storage = env::storage_usage();
+        let owner_id = env::predecessor_account_id();
+        self.freezes.assert_not_frozen(&owner_id);
+        self.internal_lock(&owner_id, amount.0);
+        self.savings.principal += amount.0;
+        self.savings.reserved += reward;
+        let deposit_id = self.savings.next_deposit_id;
+        self.savings.next_deposit_id += 1;
+        let now = env::block_timestamp();
+        self.savings.deposits.insert(
+            deposit_id,
+            TermDeposit {
+                owner_id: owner_id.clone(),
+                amount,
+                reward: reward.into(),
+                opened_at: now.into(),
+                matures_at: (now + u64::from(term_days) * DAY).into(),
+            },
+        );
+        let mut ids = self.savings.deposit_ids(&owner_id);
+        ids.push(deposit_id);
+        self.savings.by_owner.insert(owner_id, ids);
+        self.savings.deposits.flush();
+        self.savings.by_owner.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        deposit_id.into()
+    }
+
+    /// Closes a deposit of the caller and returns the amount paid: principal and reward after
+    /// maturity, principal only before.
+    #[payable]
+    pub fn close_term_deposit(&mut self, deposit_id: U64) -> U128 {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let deposit = self
+            .savings
+            .deposits
+            .get(&deposit_id.0)
+            .cloned()
+            .expect("Term deposit not found");
+        assert_eq!(
+            deposit.owner_id, owner_id,
+            "Only the owner can close a term deposit"
+        );
+        let initial_storage = env::storage_usage();
+        self.savings.deposits.remove(&deposit_id.0);
+        let mut ids = self.savings.deposit_ids(&owner_id);
+        ids.retain(|&id| id != deposit_id.0);
+        if ids.is_empty() {
+            self.savings.by_owner.remove(&owner_id);
+        } else {
+            self.savings.by_owner.insert(owner_id.clone(), ids);
+        }
+        self.savings.deposits.flush();
+        self.savings.by_owner.flush();
+        let reward = if env::block_timestamp() >= deposit.matures_at.0 {
+            deposit.reward.0
+        } else {
+            0
+        };
+        self.savings.reserved -= deposit.reward.0;
+        self.savings.reward_pool -= reward;
+        self.savings.principal -= deposit.amount.0;
+        let amount = deposit.amount.0 + reward;
+        self.internal_unlock(&owner_id, amount);
+        refund_released_storage(&owner_id, initial_storage);
+        log!(
+            "Term deposit {} closed, paid {} with a reward of {}",
+            deposit_id.0,
+            amount,
+            reward
+        );
+        amount.into()
+    }
+
+    pub fn get_term_deposits(&self, account_id: AccountId) -> Vec<TermDepositView> {
+        self.savings
+            .deposit_ids(&account_id)
+            .into_iter()
+            .map(|deposit_id| TermDepositView {
+                deposit_id: deposit_id.into(),
+                deposit: self.savings.deposits.get(&deposit_id).cloned().unwrap(),
+            })
+            .collect()
+    }
+
+    /// Offered terms in days with their reward in basis points of the principal.
+    pub fn get_term_rates(&self) -> Vec<(u32, u16)> {
+        self.savings.rates.clone()
+    }
+
+    pub fn get_savings_reward_pool(&self) -> U128 {
+        (self.savings.reward_pool - self.savings.reserved).into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, owner(), 1);
+        contract.treasury_deposit(U128(1_000));
+        contract.fund_savings_rewards(U128(1_000));
+        contract.set_term_rate(90, 500);
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_matured_deposit_pays_the_reward() {
+        let (mut context, mut contract) = setup();
+        let owner = owner();
+        let deposit_id = contract.open_term_deposit(U128(10_000), 90);
+        assert_eq!(
+            contract.get_term_deposits(owner.clone())[0]
+                .deposit
+                .reward
+                .0,
+            500
+        );
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(90 * DAY)
+            .build());
+        assert_eq!(contract.close_term_deposit(deposit_id).0, 10_500);
+        assert_eq!(contract.ft_balance_of(owner).0, TOTAL_SUPPLY - 1_000 + 500);
+        assert_eq!(contract.get_savings_reward_pool().0, 500);
+    }
+
+    #[test]
+    fn test_early_close_forfeits_the_reward() {
+        let (mut context, mut contract) = setup();
+        let deposit_id = contract.open_term_deposit(U128(10_000), 90);
+        testing_env!(context.attached_deposit(1).block_timestamp(DAY).build());
+        assert_eq!(contract.close_term_deposit(deposit_id).0, 10_000);
+        assert_eq!(contract.get_savings_reward_pool().0, 1_000);
+    }
+}
@@ -0,0 +1,319 @@
+/*!
+Periodic releases from the treasury for automation services such as Croncat.
+NOTES:
+  - The owner or the DAO sets an epoch length, the tokens to move from the treasury into the
+    staking reward pool each epoch and the account of the automation agent. Epochs count from
+    the time the schedule is set.
+  - Vesting buckets are funded from the treasury and release `per_epoch` to their beneficiary
+    each epoch until they are empty.
+  - `scheduled_distribute` feeds the reward pool and `scheduled_unlock` releases the vesting
+    buckets, for all the epochs ended since their last run. A second call within the same epoch
+    is a no-op that returns 0, so an agent firing twice costs nothing but gas.
+  - Only the agent or the owner can trigger them. Both are free to call: tasks attach no NEAR.
+  - A distribution takes what is left in the treasury if it holds less than is due, and a
+    bucket whose beneficiary has unregistered is skipped until they register again.
+*/
+#![cfg_attr(not(feature = "vesting"), allow(dead_code, unused_imports))]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::UnorderedMap;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const MAX_VESTING_BUCKETS: u32 = 20;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DistributionSchedule {
+    pub automation_id: AccountId,
+    pub start: U64,
+    /// Epoch length in nanoseconds.
+    pub epoch_length: U64,
+    /// Tokens moved into the staking reward pool each epoch.
+    pub staking_per_epoch: U128,
+}
+
+impl DistributionSchedule {
+    /// Epochs ended since `start`.
+    pub fn current_epoch(&self) -> u64 {
+        (env::block_timestamp() - self.start.0) / self.epoch_length.0
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingBucket {
+    pub beneficiary_id: AccountId,
+    pub per_epoch: U128,
+    pub remaining: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ScheduledDistributions {
+    pub schedule: Option<DistributionSchedule>,
+    pub last_distribution_epoch: u64,
+    pub last_unlock_epoch: u64,
+    pub buckets: UnorderedMap<u64, VestingBucket>,
+    pub next_bucket_id: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DistributionStatus {
+    pub schedule: Option<DistributionSchedule>,
+    pub current_epoch: U64,
+    pub last_distribution_epoch: U64,
+    pub last_unlock_epoch: U64,
+}
+
+impl ScheduledDistributions {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            schedule: None,
+            last_distribution_epoch: 0,
+            last_unlock_epoch: 0,
+            buckets: UnorderedMap::new(prefix),
+            next_bucket_id: 0,
+        }
+    }
+
+    /// Tokens held in vesting buckets.
+    pub fn vesting_held(&self) -> Balance {
+        self.buckets.values().map(|bucket| bucket.remaining.0).sum()
+    }
+
+    fn current_epoch(&self) -> u64 {
+        self.schedule
+            .as_ref()
+            .map_or(0, DistributionSchedule::current_epoch)
+    }
+}
+
+#[cfg(feature = "vesting")]
+#[near_bindgen]
+impl Contract {
+    pub fn set_distribution_schedule(
+        &mut self,
+        automation_id: AccountId,
+        epoch_length: U64,
+        staking_per_epoch: U128,
+    ) {
+        self.assert_owner_or_dao();
+        assert!(epoch_length.0 > 0, "Epoch length must be positive");
+        let distributions = &mut self.scheduled_distributions;
+        distributions.schedule = Some(DistributionSchedule {
+            automation_id: automation_id.clone(),
+            start: env::block_timestamp().into(),
+            epoch_length,
+            staking_per_epoch,
+        });
+        distributions.last_distribution_epoch = 0;
+        distributions.last_unlock_epoch = 0;
+        log!(
+            "@{} distributes {} to staking every {} ns",
+            automation_id,
+            staking_per_epoch.0,
+            epoch_length.0
+        );
+    }
+
+    /// Moves `amount` from the treasury into a bucket releasing `per_epoch` to `beneficiary_id`.
+    pub fn add_vesting_bucket(
+        &mut self,
+        beneficiary_id: AccountId,
+        amount: U128,
+        per_epoch: U128,
+    ) -> U64 {
+        self.assert_owner_or_dao();
+        assert!(
+            self.scheduled_distributions.schedule.is_some(),
+            "No distribution schedule is set"
+        );
+        assert!(
+            self.token.accounts.contains_key(&beneficiary_id),
+            "The account {} is not registered",
+            beneficiary_id
+        );
+        assert!(
+            amount.0 > 0 && per_epoch.0 > 0,
+            "The amounts should be positive numbers"
+        );
+        assert!(
+            self.scheduled_distributions.buckets.len() < MAX_VESTING_BUCKETS,
+            "At most {} vesting buckets",
+            MAX_VESTING_BUCKETS
+        );
+        assert!(
+            amount.0 <= self.treasury_balance,
+            "The treasury holds {}",
+            self.treasury_balance
+        );
+        self.treasury_balance -= amount.0;
+        let distributions = &mut self.scheduled_distributions;
+        let bucket_id = distributions.next_bucket_id;
+        distributions.next_bucket_id += 1;
+        distributions.buckets.insert(
+            bucket_id,
+            VestingBucket {
+                beneficiary_id: beneficiary_id.clone(),
+                per_epoch,
+                remaining: amount,
+            },
+        );
+        log!(
+            "Vesting bucket {} releases {} to @{}, {} per epoch",
+            bucket_id,
+            amount.0,
+            beneficiary_id,
+            per_epoch.0
+        );
+        bucket_id.into()
+    }
+
+    /// Moves the staking emissions of the epochs ended since the last run from the treasury into
+    /// the reward pool. Returns the amount moved, 0 if it already ran this epoch.
+    pub fn scheduled_distribute(&mut self) -> U128 {
+        let schedule = self.assert_automation();
+        let epoch = schedule.current_epoch();
+        let last_epoch = self.scheduled_distributions.last_distribution_epoch;
+        if epoch <= last_epoch {
+            log!("The distribution of epoch {} already ran", epoch);
+            return U128(0);
+        }
+        self.scheduled_distributions.last_distribution_epoch = epoch;
+        let due = schedule.staking_per_epoch.0 * Balance::from(epoch - last_epoch);
+        let amount = due.min(self.treasury_balance);
+        self.staking.update();
+        self.treasury_balance -= amount;
+        self.staking.reward_pool += amount;
+        log!(
+            "Distributed {} of {} due to the staking reward pool for epoch {}",
+            amount,
+            due,
+            epoch
+        );
+        amount.into()
+    }
+
+    /// Releases the vesting buckets for the epochs ended since the last run. Returns the amount
+    /// released, 0 if it already ran this epoch.
+    pub fn scheduled_unlock(&mut self) -> U128 {
+        let schedule = self.assert_automation();
+        let epoch = schedule.current_epoch();
+        let last_epoch = self.scheduled_distributions.last_unlock_epoch;
+        if epoch <= last_epoch {
+            log!("The unlock of epoch {} already ran", epoch);
+            return U128(0);
+        }
+        self.scheduled_distributions.last_unlock_epoch = epoch;
+        let buckets: Vec<(u64, VestingBucket)> = self
+            .scheduled_distributions
+            .buckets
+            .iter()
+            .map(|(bucket_id, bucket)| (*bucket_id, bucket.clone()))
+            .collect();
+        let mut released = 0;
+        for (bucket_id, mut bucket) in buckets {
+            if !self.token.accounts.contains_key(&bucket.beneficiary_id) {
+                continue;
+            }
+            let amount =
+                (bucket.per_epoch.0 * Balance::from(epoch - last_epoch)).min(bucket.remaining.0);
+            bucket.remaining = (bucket.remaining.0 - amount).into();
+            if bucket.remaining.0 == 0 {
+                self.scheduled_distributions.buckets.remove(&bucket_id);
+            } else {
+                self.scheduled_distributions
+                    .buckets
+                    .insert(bucket_id, bucket.clone());
+            }
+            self.internal_unlock(&bucket.beneficiary_id, amount);
+            released += amount;
+        }
+        log!(
+            "Released {} from vesting buckets for epoch {}",
+            released,
+            epoch
+        );
+        released.into()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_distribution_status(&self) -> DistributionStatus {
+        let distributions = &self.scheduled_distributions;
+        DistributionStatus {
+            schedule: distributions.schedule.clone(),
+            current_epoch: distributions.current_epoch().into(),
+            last_distribution_epoch: distributions.last_distribution_epoch.into(),
+            last_unlock_epoch: distributions.last_unlock_epoch.into(),
+        }
+    }
+
+    pub fn get_vesting_buckets(&self) -> Vec<(U64, VestingBucket)> {
+        self.scheduled_distributions
+            .buckets
+            .iter()
+            .map(|(bucket_id, bucket)| (U64(*bucket_id), bucket.clone()))
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Panics unless the caller is the automation agent or the owner. Returns the schedule.
+    fn assert_automation(&self) -> DistributionSchedule {
+        let schedule = self
+            .scheduled_distributions
+            .schedule
+            .clone()
+            .expect("No distribution schedule is set");
+        let caller_id = env::predecessor_account_id();
+        assert!(
+            caller_id == schedule.automation_id || caller_id == self.owner_id,
+            "Only the automation agent or the owner can trigger distributions"
+        );
+        schedule
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "vesting"))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    const ONE_TOKEN: Balance = 1_000_000_000_000_000_000;
+    const EPOCH: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_distributions_run_once_per_epoch() {
+        let (mut context, mut contract) = setup(&[accounts(2)]);
+        call_as(&mut context, owner(), 1);
+        contract.treasury_deposit(U128(1_000 * ONE_TOKEN));
+        contract.set_distribution_schedule(accounts(3), U64(EPOCH), U128(10 * ONE_TOKEN));
+        contract.add_vesting_bucket(accounts(2), U128(25 * ONE_TOKEN), U128(10 * ONE_TOKEN));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(0)
+            .block_timestamp(EPOCH)
+            .build());
+        assert_eq!(contract.scheduled_distribute().0, 10 * ONE_TOKEN);
+        assert_eq!(contract.scheduled_distribute().0, 0);
+        assert_eq!(contract.scheduled_unlock().0, 10 * ONE_TOKEN);
+        assert_eq!(contract.scheduled_unlock().0, 0);
+
+        testing_env!(context.block_timestamp(4 * EPOCH).build());
+        assert_eq!(contract.scheduled_distribute().0, 30 * ONE_TOKEN);
+        assert_eq!(contract.scheduled_unlock().0, 15 * ONE_TOKEN);
+        assert!(contract.get_vesting_buckets().is_empty());
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 25 * ONE_TOKEN);
+        assert_eq!(contract.get_staking_info().reward_pool.0, 40 * ONE_TOKEN);
+    }
+}
@@ -0,0 +1,223 @@
+/*!
+Transfers sent at a future time, for payroll and timed unlocks.
+NOTES:
+  - `schedule_transfer` locks `amount` plus an optional `bounty` of the sender's tokens in the
+    contract. Once `execute_after` has passed anyone can call `execute_scheduled`: the receiver
+    gets `amount` and the caller gets the bounty, which pays keepers for the call.
+  - The sender can cancel until the transfer is executed and gets everything back.
+  - The sender pays for the storage of the transfer and gets it back when it is executed or
+    cancelled. Due transfers show up in `get_due_actions`.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId};
+
+use crate::pool_ledger::Pool;
+use crate::scheduler::ScheduledAction;
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ScheduledTransfer {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub bounty: U128,
+    pub execute_after: U64,
+    pub memo: Option<String>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ScheduledTransfers {
+    pub transfers: LookupMap<u64, ScheduledTransfer>,
+    pub next_transfer_id: u64,
+}
+
+impl ScheduledTransfers {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            transfers: LookupMap::new(prefix),
+            next_transfer_id: 0,
+        }
+    }
+}
+
+fn emit_scheduled_transfer_event(event: &str, transfer_id: u64, transfer: &ScheduledTransfer) {
+    emit_event(
+        EVENT_VERSION,
+        event,
+        json!({
+            "transfer_id": U64(transfer_id),
+            "sender_id": transfer.sender_id,
+            "receiver_id": transfer.receiver_id,
+            "amount": transfer.amount,
+            "bounty": transfer.bounty,
+            "execute_after": transfer.execute_after,
+        }),
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Locks `amount` and `bounty` of the caller's tokens to send `amount` to `receiver_id` after
+    /// the timestamp `execute_after`, in nanoseconds. Returns the transfer id. The attached
+    /// deposit covers the transfer storage.
+    #[payable]
+    pub fn schedule_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        execute_after: U64,
+        bounty: Option<U128>,
+        memo: Option<String>,
+    ) -> U64 {
+        self.assert_module_active(Module::ScheduledTransfers);
+        let sender_id = env::predecessor_account_id();
+        let bounty = bounty.unwrap_or(U128(0));
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        assert!(
+            execute_after.0 > env::block_timestamp(),
+            "The execution time must be in the future"
+        );
+        assert_ne!(
+            sender_id, receiver_id,
+            "Sender and receiver should be different"
+        );
+        assert!(
+            self.token.accounts.contains_key(&receiver_id),
+            "The account {} is not registered",
+            receiver_id
+        );
+        let initial_storage = env::storage_usage();
+        self.internal_lock_to(Pool::ScheduledTransfers, &sender_id, amount.0 + bounty.0);
+        let transfer_id = self.scheduled_transfers.next_transfer_id;
+        self.scheduled_transfers.next_transfer_id += 1;
+        let transfer = ScheduledTransfer {
+            sender_id,
+            receiver_id,
+            amount,
+            bounty,
+            execute_after,
+            memo,
+        };
+        emit_scheduled_transfer_event("transfer_schedule", transfer_id, &transfer);
+        self.scheduled_transfers
+            .transfers
+            .insert(transfer_id, transfer);
+        self.scheduled_transfers.transfers.flush();
+        self.scheduler.schedule(
+            ScheduledAction::ExecuteScheduledTransfer {
+                transfer_id: transfer_id.into(),
+            },
+            execute_after.0,
+        );
+        refund_deposit(env::storage_usage() - initial_storage);
+        transfer_id.into()
+    }
+
+    /// Sends a due transfer to its receiver and the bounty to the caller.
+    pub fn execute_scheduled(&mut self, id: U64) {
+        let transfer = self.internal_remove_scheduled_transfer(id.0);
+        assert!(
+            env::block_timestamp() > transfer.execute_after.0,
+            "The transfer is not due"
+        );
+        self.internal_unlock_from(
+            Pool::ScheduledTransfers,
+            &transfer.receiver_id,
+            transfer.amount.0,
+        );
+        if transfer.bounty.0 > 0 {
+            self.internal_unlock_from(
+                Pool::ScheduledTransfers,
+                &env::predecessor_account_id(),
+                transfer.bounty.0,
+            );
+        }
+        emit_scheduled_transfer_event("transfer_execute", id.0, &transfer);
+    }
+
+    /// Returns the tokens of a pending transfer to its sender.
+    #[payable]
+    pub fn cancel_scheduled_transfer(&mut self, id: U64) {
+        assert_one_yocto();
+        let transfer = self.internal_remove_scheduled_transfer(id.0);
+        assert_eq!(
+            env::predecessor_account_id(),
+            transfer.sender_id,
+            "Only the sender can cancel a scheduled transfer"
+        );
+        self.internal_unlock_from(
+            Pool::ScheduledTransfers,
+            &transfer.sender_id,
+            transfer.amount.0 + transfer.bounty.0,
+        );
+        emit_scheduled_transfer_event("transfer_cancel", id.0, &transfer);
+    }
+
+    pub fn get_scheduled_transfer(&self, id: U64) -> Option<ScheduledTransfer> {
+        self.scheduled_transfers.transfers.get(&id.0).cloned()
+    }
+}
+
+impl Contract {
+    /// Removes a pending transfer and refunds its storage to the sender.
+    fn internal_remove_scheduled_transfer(&mut self, transfer_id: u64) -> ScheduledTransfer {
+        let initial_storage = env::storage_usage();
+        let transfer = self
+            .scheduled_transfers
+            .transfers
+            .remove(&transfer_id)
+            .expect("Scheduled transfer not found");
+        self.scheduled_transfers.transfers.flush();
+        self.scheduler
+            .unschedule(&ScheduledAction::ExecuteScheduledTransfer {
+                transfer_id: transfer_id.into(),
+            });
+        refund_released_storage(&transfer.sender_id, initial_storage);
+        transfer
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = test_fixtures::setup(&[accounts(1), accounts(2)]);
+        contract.schedule_transfer(accounts(1), U128(1_000), U64(100), Some(U128(10)), None);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_execute_pays_receiver_and_bounty() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0)
+            .block_timestamp(101)
+            .build());
+        contract.execute_scheduled(U64(0));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10);
+        assert!(contract.get_scheduled_transfer(U64(0)).is_none());
+        assert_eq!(contract.get_scheduled_actions_count().0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The transfer is not due")]
+    fn test_execute_before_due() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(0).block_timestamp(100).build());
+        contract.execute_scheduled(U64(0));
+    }
+}
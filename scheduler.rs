@@ -0,0 +1,131 @@
+/*!
+A single queue of the time-based work pending across modules, ordered by due date.
+NOTES:
+  - Modules schedule an action when they create something with a due date and unschedule it
+    when it is resolved: proposals to execute after their voting period, matching rounds to
+    finalize, mint requests to remove after expiry, streams fully accrued at their end,
+    timelock actions to execute after their delay and scheduled transfers to execute.
+  - Keepers find all pending work with one `get_due_actions` query instead of scanning every
+    module. Entries are only hints: the module methods still check that the action is due.
+  - The queue is bounded by `MAX_SCHEDULED_ACTIONS`, and scheduling into a full queue panics.
+    Its entries are paid for with the storage of the object they belong to.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::TreeMap;
+use near_sdk::json_types::U64;
+use near_sdk::near_bindgen;
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+
+use crate::*;
+
+const MAX_SCHEDULED_ACTIONS: u64 = 10_000;
+const MAX_DUE_ACTIONS: u32 = 100;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ScheduledAction {
+    ExecuteProposal { proposal_id: U64 },
+    FinalizeMatchingRound { round_id: U64 },
+    RemoveExpiredMintRequest { request_id: U64 },
+    WithdrawStream { stream_id: U64 },
+    ExecuteTimelockAction { action_id: U64 },
+    ExecuteScheduledTransfer { transfer_id: U64 },
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DueAction {
+    pub due_at: U64,
+    pub action: ScheduledAction,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Scheduler {
+    pub queue: TreeMap<(u64, ScheduledAction), ()>,
+    /// Due date of each scheduled action, to find its queue entry.
+    pub due_at: LookupMap<ScheduledAction, u64>,
+}
+
+impl Scheduler {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            queue: TreeMap::new([prefix.as_slice(), b"q"].concat()),
+            due_at: LookupMap::new([prefix.as_slice(), b"d"].concat()),
+        }
+    }
+
+    pub fn schedule(&mut self, action: ScheduledAction, due_at: u64) {
+        self.unschedule(&action);
+        assert!(
+            self.queue.len() < MAX_SCHEDULED_ACTIONS,
+            "The schedule is full"
+        );
+        self.queue.insert(&(due_at, action.clone()), &());
+        self.due_at.insert(action, due_at);
+        self.due_at.flush();
+    }
+
+    pub fn unschedule(&mut self, action: &ScheduledAction) {
+        if let Some(due_at) = self.due_at.remove(action) {
+            self.queue.remove(&(due_at, action.clone()));
+            self.due_at.flush();
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Actions due at `now`, earliest first.
+    pub fn get_due_actions(&self, now: U64, limit: Option<u32>) -> Vec<DueAction> {
+        let limit = limit.unwrap_or(MAX_DUE_ACTIONS).min(MAX_DUE_ACTIONS);
+        self.scheduler
+            .queue
+            .iter()
+            .take_while(|((due_at, _), _)| *due_at <= now.0)
+            .take(limit as usize)
+            .map(|((due_at, action), _)| DueAction {
+                due_at: due_at.into(),
+                action,
+            })
+            .collect()
+    }
+
+    pub fn get_scheduled_actions_count(&self) -> U64 {
+        self.scheduler.queue.len().into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::setup;
+
+    #[test]
+    fn test_due_actions_follow_module_state() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        let late = contract.create_stream(accounts(1), U128(100), U64(0), U64(200));
+        let early = contract.create_stream(accounts(1), U128(100), U64(0), U64(100));
+
+        assert!(contract.get_due_actions(U64(99), None).is_empty());
+        let due = contract.get_due_actions(U64(200), None);
+        assert_eq!(
+            due.iter().map(|due| due.action.clone()).collect::<Vec<_>>(),
+            vec![
+                ScheduledAction::WithdrawStream { stream_id: early },
+                ScheduledAction::WithdrawStream { stream_id: late },
+            ]
+        );
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.cancel_stream(early);
+        assert_eq!(contract.get_due_actions(U64(200), None).len(), 1);
+        assert_eq!(contract.get_scheduled_actions_count().0, 1);
+    }
+}
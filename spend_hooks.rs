@@ -0,0 +1,113 @@
+/*!
+Spend hooks: notifications of outgoing transfers to a contract chosen by the holder.
+NOTES:
+  - An account registers a hook contract with `set_spend_hook`. Every `ft_transfer`,
+    `ft_transfer_call`, `transfer_from` and `burn_from` out of the account then calls
+    `on_ft_spend` on the hook contract, so custodial services can mirror balance changes in
+    their own systems.
+  - The call is a notification: its result is not awaited and a failing hook does not revert the
+    transfer. The hook gets a fixed amount of gas, paid by the transfer.
+  - Burns are notified with no receiver. Amounts are the ones sent, before transfer fees.
+  - The account pays for the storage of its hook and gets it back when removing it.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::store::LookupMap;
+use near_sdk::{env, ext_contract, log, near_bindgen, AccountId, Balance, Gas};
+
+use crate::*;
+
+const GAS_FOR_SPEND_HOOK: Gas = Gas(5_000_000_000_000);
+
+#[ext_contract(ext_spend_hook)]
+#[allow(dead_code)]
+pub trait SpendHook {
+    /// Called after `amount` left `owner_id`, to `receiver_id` or burned if `None`.
+    fn on_ft_spend(&mut self, owner_id: AccountId, receiver_id: Option<AccountId>, amount: U128);
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct SpendHooks {
+    pub hooks: LookupMap<AccountId, AccountId>,
+}
+
+impl SpendHooks {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            hooks: LookupMap::new(prefix),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the contract notified of the caller's outgoing transfers, `None` to remove it.
+    /// Setting one takes a deposit for its storage, the excess is refunded.
+    #[payable]
+    pub fn set_spend_hook(&mut self, hook_id: Option<AccountId>) {
+        let account_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        match hook_id {
+            Some(hook_id) => {
+                assert_ne!(
+                    hook_id,
+                    env::current_account_id(),
+                    "The hook cannot be this contract"
+                );
+                log!("@{} set the spend hook @{}", account_id, hook_id);
+                self.spend_hooks.hooks.insert(account_id, hook_id);
+                self.spend_hooks.hooks.flush();
+                refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+            }
+            None => {
+                self.spend_hooks.hooks.remove(&account_id);
+                self.spend_hooks.hooks.flush();
+                refund_released_storage(&account_id, initial_storage);
+                log!("@{} removed its spend hook", account_id);
+            }
+        }
+    }
+
+    pub fn get_spend_hook(&self, account_id: AccountId) -> Option<AccountId> {
+        self.spend_hooks.hooks.get(&account_id).cloned()
+    }
+}
+
+impl Contract {
+    /// Notifies the spend hook of `owner_id`, if any, that `amount` left its balance.
+    pub(crate) fn notify_spend(
+        &self,
+        owner_id: &AccountId,
+        receiver_id: Option<&AccountId>,
+        amount: Balance,
+    ) {
+        if let Some(hook_id) = self.spend_hooks.hooks.get(owner_id) {
+            ext_spend_hook::ext(hook_id.clone())
+                .with_static_gas(GAS_FOR_SPEND_HOOK)
+                .on_ft_spend(owner_id.clone(), receiver_id.cloned(), amount.into());
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, get_created_receipts};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{owner, setup};
+
+    #[test]
+    fn test_transfer_notifies_the_spend_hook() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        contract.set_spend_hook(Some(accounts(3)));
+        assert_eq!(contract.get_spend_hook(owner()), Some(accounts(3)));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(10), None);
+        assert!(get_created_receipts()
+            .iter()
+            .any(|receipt| receipt.receiver_id == accounts(3)));
+    }
+}
@@ -0,0 +1,249 @@
+/*!
+Sponsorship pool paying the storage deposit of new accounts.
+NOTES:
+  - Anyone can fund the pool with NEAR through `fund_sponsorship_pool`. The owner turns
+    sponsorship on with a cap on registrations per day and can withdraw what is left.
+  - `storage_deposit_sponsored` registers an account with the registration minimum taken from the
+    pool, so new users need no NEAR. The usual registration limits apply; sponsored registrations
+    are refused during a burst rather than paying its surcharge from the pool.
+  - When a sponsored account unregisters, its storage deposit goes back to the pool instead of to
+    the account, so sponsoring cannot be used to drain the pool.
+*/
+use near_contract_standards::storage_management::{StorageBalance, StorageManagement};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupSet;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Sponsorship {
+    pub balance: Balance,
+    pub enabled: bool,
+    pub daily_cap: u32,
+    /// Day of `day_count`, in days since the epoch.
+    pub day: u64,
+    pub day_count: u32,
+    pub sponsored: LookupSet<AccountId>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SponsorshipInfo {
+    pub balance: U128,
+    pub enabled: bool,
+    pub daily_cap: u32,
+    pub sponsored_today: u32,
+}
+
+impl Sponsorship {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            balance: 0,
+            enabled: false,
+            daily_cap: 0,
+            day: 0,
+            day_count: 0,
+            sponsored: LookupSet::new(prefix),
+        }
+    }
+
+    fn sponsored_today(&self) -> u32 {
+        if self.day == env::block_timestamp() / DAY {
+            self.day_count
+        } else {
+            0
+        }
+    }
+
+    fn use_daily_cap(&mut self) {
+        let sponsored_today = self.sponsored_today();
+        assert!(
+            sponsored_today < self.daily_cap,
+            "The daily cap of {} sponsored registrations is reached",
+            self.daily_cap
+        );
+        self.day = env::block_timestamp() / DAY;
+        self.day_count = sponsored_today + 1;
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Adds the attached deposit to the sponsorship pool.
+    #[payable]
+    pub fn fund_sponsorship_pool(&mut self) -> U128 {
+        let amount = env::attached_deposit();
+        assert!(amount > 0, "Requires a positive attached deposit");
+        self.sponsorship.balance += amount;
+        emit_event(
+            EVENT_VERSION,
+            "sponsorship_funded",
+            json!({
+                "account_id": env::predecessor_account_id(),
+                "amount": U128(amount),
+                "balance": U128(self.sponsorship.balance),
+            }),
+        );
+        self.sponsorship.balance.into()
+    }
+
+    pub fn withdraw_sponsorship_pool(&mut self, receiver_id: AccountId, amount: U128) {
+        self.assert_owner();
+        assert!(
+            amount.0 <= self.sponsorship.balance,
+            "The sponsorship pool holds {}",
+            self.sponsorship.balance
+        );
+        self.sponsorship.balance -= amount.0;
+        Promise::new(receiver_id).transfer(amount.0);
+    }
+
+    pub fn set_sponsorship(&mut self, enabled: bool, daily_cap: u32) {
+        self.assert_owner();
+        self.sponsorship.enabled = enabled;
+        self.sponsorship.daily_cap = daily_cap;
+    }
+
+    /// Registers `account_id`, the caller by default, with the storage deposit paid by the
+    /// sponsorship pool.
+    pub fn storage_deposit_sponsored(&mut self, account_id: Option<AccountId>) -> StorageBalance {
+        assert!(self.sponsorship.enabled, "Sponsorship is disabled");
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        assert!(
+            !self.token.accounts.contains_key(&account_id),
+            "The account is already registered"
+        );
+        let surcharge = self
+            .registration_limits
+            .register(&env::predecessor_account_id());
+        assert_eq!(
+            surcharge, 0,
+            "Sponsored registrations are paused during a burst, try again later"
+        );
+        let cost = self.storage_balance_bounds().min.0;
+        assert!(
+            cost <= self.sponsorship.balance,
+            "The sponsorship pool cannot cover the registration"
+        );
+        self.sponsorship.use_daily_cap();
+        self.sponsorship.balance -= cost;
+        self.sponsorship.sponsored.insert(account_id.clone());
+        self.internal_register_account(&account_id);
+        let top_up = cost - self.token.storage_balance_bounds().min.0;
+        self.internal_record_top_up(&account_id, top_up);
+        emit_event(
+            EVENT_VERSION,
+            "registration_sponsored",
+            json!({ "account_id": &account_id, "amount": U128(cost) }),
+        );
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    pub fn is_sponsored(&self, account_id: AccountId) -> bool {
+        self.sponsorship.sponsored.contains(&account_id)
+    }
+
+    pub fn get_sponsorship(&self) -> SponsorshipInfo {
+        SponsorshipInfo {
+            balance: self.sponsorship.balance.into(),
+            enabled: self.sponsorship.enabled,
+            daily_cap: self.sponsorship.daily_cap,
+            sponsored_today: self.sponsorship.sponsored_today(),
+        }
+    }
+}
+
+impl Contract {
+    /// `storage_unregister` of a sponsored caller: its storage deposit and top-ups go back to
+    /// the pool.
+    pub(crate) fn internal_unregister_sponsored(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let deposit = self.internal_storage_balance(&account_id);
+        let balance = self.with_checkpoints(std::slice::from_ref(&account_id), |this| {
+            let balance = this.token.accounts.get(&account_id).unwrap_or(0);
+            assert!(
+                balance == 0 || force.unwrap_or(false),
+                "Can't unregister the account with the positive balance without force"
+            );
+            this.token.accounts.remove(&account_id);
+            this.token.total_supply -= balance;
+            balance
+        });
+        self.registered_accounts.remove(&account_id);
+        if let Some(top_up) = self.storage_grace.top_ups.remove(&account_id) {
+            self.rescue.release(top_up);
+        }
+        self.sponsorship.sponsored.remove(&account_id);
+        self.sponsorship.balance += deposit;
+        self.on_account_closed(account_id, balance);
+        true
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U64;
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{owner, setup, ONE_NEAR};
+
+    #[test]
+    fn test_sponsored_deposit_returns_to_the_pool() {
+        let (mut context, mut contract) = setup(&[]);
+        contract.fund_sponsorship_pool();
+        contract.set_sponsorship(true, 1);
+        let cost = contract.storage_balance_bounds().min.0;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(0)
+            .build());
+        contract.storage_deposit_sponsored(None);
+        assert!(contract.is_sponsored(accounts(1)));
+        assert_eq!(contract.get_sponsorship().balance.0, ONE_NEAR - cost);
+
+        testing_env!(context.attached_deposit(1).build());
+        assert!(contract.storage_unregister(None));
+        assert_eq!(contract.get_sponsorship().balance.0, ONE_NEAR);
+    }
+
+    #[test]
+    fn test_forced_sponsored_unregister_burns_the_balance() {
+        let (mut context, mut contract) = setup(&[]);
+        testing_env!(context.block_index(10).build());
+        let owner = owner();
+        contract.fund_sponsorship_pool();
+        contract.set_sponsorship(true, 1);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(0)
+            .build());
+        contract.storage_deposit_sponsored(None);
+        testing_env!(context
+            .predecessor_account_id(owner)
+            .attached_deposit(1)
+            .block_index(20)
+            .build());
+        contract.ft_transfer(accounts(1), U128(100), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_index(30)
+            .build());
+        assert!(contract.storage_unregister(Some(true)));
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 100);
+        assert_eq!(contract.ft_total_supply_at(U64(25)).0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_total_supply_at(U64(30)).0, TOTAL_SUPPLY - 100);
+        assert!(get_logs().iter().any(|log| log.contains("\"ft_burn\"")));
+    }
+}
@@ -0,0 +1,298 @@
+/*!
+Locked stakes represented as transferable position records.
+NOTES:
+  - `stake_position` stakes tokens like `stake` but locks them in a position until `unlocks_at`.
+    Locked tokens earn staking rewards for the position owner and cannot be unstaked with
+    `unstake`.
+  - A position can be transferred with `transfer_stake_position`, e.g. to sell it on a secondary
+    market. The lock is unchanged: the staked tokens move to the new owner's stake, rewards
+    accrued until the transfer stay with the previous owner.
+  - After `unlocks_at` the owner redeems the position to get the tokens back.
+  - Callers pay for the storage of the positions they create or receive.
+*/
+#![cfg_attr(not(feature = "staking"), allow(dead_code, unused_imports))]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakePosition {
+    pub owner_id: AccountId,
+    pub amount: U128,
+    pub unlocks_at: U64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakePositionView {
+    pub position_id: U64,
+    #[serde(flatten)]
+    pub position: StakePosition,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StakePositions {
+    pub positions: LookupMap<u64, StakePosition>,
+    /// Position ids of each owner.
+    pub by_owner: LookupMap<AccountId, Vec<u64>>,
+    /// Staked amount of each owner locked in positions.
+    pub locked: LookupMap<AccountId, Balance>,
+    pub next_position_id: u64,
+}
+
+impl StakePositions {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            positions: LookupMap::new([prefix.as_slice(), b"p"].concat()),
+            by_owner: LookupMap::new([prefix.as_slice(), b"o"].concat()),
+            locked: LookupMap::new([prefix.as_slice(), b"l"].concat()),
+            next_position_id: 0,
+        }
+    }
+
+    fn add(&mut self, position_id: u64, position: StakePosition) {
+        let mut ids = self
+            .by_owner
+            .get(&position.owner_id)
+            .cloned()
+            .unwrap_or_default();
+        ids.push(position_id);
+        self.by_owner.insert(position.owner_id.clone(), ids);
+        let locked = self.locked.get(&position.owner_id).copied().unwrap_or(0);
+        self.locked
+            .insert(position.owner_id.clone(), locked + position.amount.0);
+        self.positions.insert(position_id, position);
+    }
+
+    fn remove(&mut self, position_id: u64) -> StakePosition {
+        let position = self
+            .positions
+            .remove(&position_id)
+            .expect("Stake position not found");
+        let mut ids = self.by_owner.get(&position.owner_id).cloned().unwrap();
+        ids.retain(|&id| id != position_id);
+        if ids.is_empty() {
+            self.by_owner.remove(&position.owner_id);
+        } else {
+            self.by_owner.insert(position.owner_id.clone(), ids);
+        }
+        let locked = self.locked.get(&position.owner_id).copied().unwrap() - position.amount.0;
+        if locked == 0 {
+            self.locked.remove(&position.owner_id);
+        } else {
+            self.locked.insert(position.owner_id.clone(), locked);
+        }
+        position
+    }
+
+    fn flush(&mut self) {
+        self.positions.flush();
+        self.by_owner.flush();
+        self.locked.flush();
+    }
+}
+
+#[cfg(feature = "staking")]
+#[near_bindgen]
+impl Contract {
+    /// Stakes `amount` of the caller's tokens locked for `lock_duration` nanoseconds and returns
+    /// the position id.
+    #[payable]
+    pub fn stake_position(&mut self, amount: U128, lock_duration: U64) -> U64 {
+        self.assert_module_active(Module::StakePositions);
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        let initial_storage = env::storage_usage();
+        let owner_id = env::predecessor_account_id();
+        self.internal_stake(&owner_id, amount.0);
+        let position_id = self.stake_positions.next_position_id;
+        self.stake_positions.next_position_id += 1;
+        self.stake_positions.add(
+            position_id,
+            StakePosition {
+                owner_id,
+                amount,
+                unlocks_at: (env::block_timestamp() + lock_duration.0).into(),
+            },
+        );
+        self.stake_positions.flush();
+        self.staking.accounts.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        position_id.into()
+    }
+
+    /// Transfers a position and its locked stake to `receiver_id`, who must be registered.
+    #[payable]
+    pub fn transfer_stake_position(&mut self, position_id: U64, receiver_id: AccountId) {
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        assert!(
+            self.token.accounts.contains_key(&receiver_id),
+            "The account {} is not registered",
+            receiver_id
+        );
+        let initial_storage = env::storage_usage();
+        let mut position = self.stake_positions.remove(position_id.0);
+        let sender_id = position.owner_id.clone();
+        assert_eq!(
+            env::predecessor_account_id(),
+            sender_id,
+            "Only the owner can transfer a stake position"
+        );
+        assert_ne!(sender_id, receiver_id, "The receiver is the owner");
+        self.internal_move_stake(&sender_id, &receiver_id, position.amount.0);
+        position.owner_id = receiver_id.clone();
+        self.stake_positions.add(position_id.0, position);
+        self.stake_positions.flush();
+        self.staking.accounts.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        log!(
+            "Stake position {} transferred from @{} to @{}",
+            position_id.0,
+            sender_id,
+            receiver_id
+        );
+    }
+
+    /// Unstakes an unlocked position to its owner and returns the amount.
+    #[payable]
+    pub fn redeem_stake_position(&mut self, position_id: U64) -> U128 {
+        assert_one_yocto();
+        let position = self.stake_positions.remove(position_id.0);
+        assert_eq!(
+            env::predecessor_account_id(),
+            position.owner_id,
+            "Only the owner can redeem a stake position"
+        );
+        assert!(
+            env::block_timestamp() >= position.unlocks_at.0,
+            "The position is locked until {}",
+            position.unlocks_at.0
+        );
+        let amount = position.amount.0;
+        self.staking.update();
+        let mut account = self.staking.settled_account(&position.owner_id);
+        account.staked -= amount;
+        self.staking.total_staked -= amount;
+        self.staking.save_account(&position.owner_id, &account);
+        self.internal_unlock(&position.owner_id, amount);
+        log!("Stake position {} redeemed", position_id.0);
+        position.amount
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_stake_position(&self, position_id: U64) -> Option<StakePosition> {
+        self.stake_positions.positions.get(&position_id.0).cloned()
+    }
+
+    pub fn get_stake_positions(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<StakePositionView> {
+        let ids = self
+            .stake_positions
+            .by_owner
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default();
+        ids.into_iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .map(|position_id| StakePositionView {
+                position_id: position_id.into(),
+                position: self.get_stake_position(position_id.into()).unwrap(),
+            })
+            .collect()
+    }
+
+    pub fn get_locked_stake(&self, account_id: AccountId) -> U128 {
+        self.locked_stake(&account_id).into()
+    }
+}
+
+impl Contract {
+    pub(crate) fn locked_stake(&self, account_id: &AccountId) -> Balance {
+        self.stake_positions
+            .locked
+            .get(account_id)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Hands all positions of `sender_id` over to `receiver_id`. The staked tokens must be moved
+    /// separately.
+    pub(crate) fn internal_move_stake_positions(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+    ) {
+        let ids = self
+            .stake_positions
+            .by_owner
+            .get(sender_id)
+            .cloned()
+            .unwrap_or_default();
+        for position_id in ids {
+            let mut position = self.stake_positions.remove(position_id);
+            position.owner_id = receiver_id.clone();
+            self.stake_positions.add(position_id, position);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "staking"))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{owner, setup};
+
+    #[test]
+    fn test_transfer_and_redeem_position() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        let position_id = contract.stake_position(U128(1_000), U64(100));
+        contract.transfer_stake_position(position_id, accounts(1));
+        assert_eq!(contract.get_staked_balance(owner).0, 0);
+        assert_eq!(contract.get_staked_balance(accounts(1)).0, 1_000);
+        assert_eq!(contract.get_locked_stake(accounts(1)).0, 1_000);
+        assert_eq!(
+            contract.get_stake_positions(accounts(1), None, None).len(),
+            1
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .block_timestamp(100)
+            .build());
+        assert_eq!(contract.redeem_stake_position(position_id).0, 1_000);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.get_locked_stake(accounts(1)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough staked balance")]
+    fn test_cannot_unstake_locked_position() {
+        let (mut context, mut contract) = setup(&[]);
+        contract.stake_position(U128(1_000), U64(100));
+        testing_env!(context.attached_deposit(1).build());
+        contract.unstake(U128(1));
+    }
+}
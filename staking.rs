@@ -0,0 +1,353 @@
+/*!
+Native staking of ASTRO with emissions paid from an owner-funded reward pool.
+NOTES:
+  - Staked tokens are moved into the contract's own balance, so they are excluded from the
+    holder's transferable `ft_balance_of`.
+  - Rewards use the accumulated-reward-per-share model: the pool keeps a global
+    `acc_reward_per_share` and each staker remembers the value at their last update, so
+    `claim_rewards` is O(1) regardless of the number of stakers.
+  - Emissions are released at `reward_rate` per second and never exceed the funded pool.
+*/
+#![cfg_attr(not(feature = "staking"), allow(dead_code, unused_imports))]
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::reward_tokens::RewardTokens;
+use crate::*;
+
+/// Fixed point precision of `acc_reward_per_share`.
+const ACC_REWARD_PRECISION: u128 = 1_000_000_000_000;
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+pub struct StakerAccount {
+    pub staked: Balance,
+    /// Value of `acc_reward_per_share` when the rewards of this account were last settled.
+    pub reward_per_share_paid: Balance,
+    /// Rewards settled but not claimed yet.
+    pub unclaimed: Balance,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StakingPool {
+    pub accounts: LookupMap<AccountId, StakerAccount>,
+    pub total_staked: Balance,
+    /// Funded rewards that were not emitted yet.
+    pub reward_pool: Balance,
+    /// Emission in yocto ASTRO per second, split across all stakers.
+    pub reward_rate: Balance,
+    pub acc_reward_per_share: Balance,
+    pub last_update_timestamp: u64,
+    /// Rewards in other tokens, see `reward_tokens`.
+    pub reward_tokens: RewardTokens,
+    /// Set by the reward circuit breaker, see `circuit_breaker`.
+    pub rewards_halted: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakingInfo {
+    pub total_staked: U128,
+    pub reward_pool: U128,
+    pub reward_rate: U128,
+    pub last_update_timestamp: U64,
+}
+
+impl StakingPool {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            accounts: LookupMap::new(prefix.clone()),
+            total_staked: 0,
+            reward_pool: 0,
+            reward_rate: 0,
+            acc_reward_per_share: 0,
+            last_update_timestamp: env::block_timestamp(),
+            reward_tokens: RewardTokens::new([prefix.as_slice(), b"r"].concat()),
+            rewards_halted: false,
+        }
+    }
+
+    /// The stake rewards are emitted to, none while rewards are halted.
+    pub fn accruing_stake(&self) -> Balance {
+        if self.rewards_halted {
+            0
+        } else {
+            self.total_staked
+        }
+    }
+
+    /// Returns the whole seconds elapsed since the last update and the reward emitted in them.
+    fn accrued(&self) -> (u64, Balance) {
+        let elapsed = (env::block_timestamp() - self.last_update_timestamp) / NANOS_PER_SECOND;
+        if self.accruing_stake() == 0 {
+            return (elapsed, 0);
+        }
+        let reward = std::cmp::min(self.reward_rate * Balance::from(elapsed), self.reward_pool);
+        (elapsed, reward)
+    }
+
+    /// `acc_reward_per_share` as it would be after an update at the current block.
+    pub fn current_acc_reward_per_share(&self) -> Balance {
+        let (_, reward) = self.accrued();
+        if reward == 0 {
+            return self.acc_reward_per_share;
+        }
+        self.acc_reward_per_share + mul_div(reward, ACC_REWARD_PRECISION, self.total_staked)
+    }
+
+    /// Emits the rewards accrued since the last update into `acc_reward_per_share`.
+    pub fn update(&mut self) {
+        let (elapsed, reward) = self.accrued();
+        self.acc_reward_per_share = self.current_acc_reward_per_share();
+        self.reward_pool -= reward;
+        self.last_update_timestamp += elapsed * NANOS_PER_SECOND;
+        self.reward_tokens.update(self.accruing_stake());
+    }
+
+    pub fn pending_rewards(
+        &self,
+        account: &StakerAccount,
+        acc_reward_per_share: Balance,
+    ) -> Balance {
+        account.unclaimed
+            + mul_div(
+                account.staked,
+                acc_reward_per_share - account.reward_per_share_paid,
+                ACC_REWARD_PRECISION,
+            )
+    }
+
+    /// Returns the account with its pending rewards moved into `unclaimed`.
+    pub fn settled_account(&self, account_id: &AccountId) -> StakerAccount {
+        let mut account = self.accounts.get(account_id).cloned().unwrap_or_default();
+        account.unclaimed = self.pending_rewards(&account, self.acc_reward_per_share);
+        account.reward_per_share_paid = self.acc_reward_per_share;
+        account
+    }
+
+    /// Stores `account`, settling its rewards in other tokens with the stake it had so far.
+    pub fn save_account(&mut self, account_id: &AccountId, account: &StakerAccount) {
+        let staked = self
+            .accounts
+            .get(account_id)
+            .map_or(0, |account| account.staked);
+        self.reward_tokens
+            .settle(account_id, staked, account.staked);
+        if account.staked == 0 && account.unclaimed == 0 {
+            self.accounts.remove(account_id);
+        } else {
+            self.accounts.insert(account_id.clone(), account.clone());
+        }
+    }
+}
+
+#[cfg(feature = "staking")]
+#[near_bindgen]
+impl Contract {
+    /// Stakes `amount` of the caller's tokens. Requires at least 1 yoctoNEAR attached; deposit
+    /// beyond the storage used by a new staking entry is refunded. A first stake with a
+    /// `referral_code` binds the caller to its owner.
+    #[payable]
+    pub fn stake(&mut self, amount: U128, referral_code: Option<String>) {
+        self.assert_module_active(Module::Staking);
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let amount: Balance = amount.into();
+        assert!(amount > 0, "The amount should be a positive number");
+        let account_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        if let Some(code) = referral_code {
+            self.internal_bind_referrer(&code, &account_id);
+        }
+        self.internal_stake(&account_id, amount);
+        self.staking.accounts.flush();
+        self.staking.reward_tokens.accounts.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+    }
+
+    #[payable]
+    pub fn unstake(&mut self, amount: U128) {
+        assert_one_yocto();
+        let amount: Balance = amount.into();
+        assert!(amount > 0, "The amount should be a positive number");
+        let account_id = env::predecessor_account_id();
+        self.staking.update();
+        let mut account = self.staking.settled_account(&account_id);
+        assert!(
+            account.staked - self.locked_stake(&account_id) >= amount,
+            "Not enough staked balance"
+        );
+        account.staked -= amount;
+        self.staking.total_staked -= amount;
+        self.staking.save_account(&account_id, &account);
+        self.internal_unlock(&account_id, amount);
+        log!("Account @{} unstaked {}", account_id, amount);
+    }
+
+    /// Transfers all accrued rewards to the caller and returns the claimed amount.
+    #[payable]
+    pub fn claim_rewards(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.staking.update();
+        let mut account = self.staking.settled_account(&account_id);
+        let reward = account.unclaimed;
+        assert!(reward > 0, "No rewards to claim");
+        account.unclaimed = 0;
+        self.staking.save_account(&account_id, &account);
+        self.internal_unlock(&account_id, reward);
+        self.internal_refer_staking_reward(&account_id, reward);
+        log!(
+            "Account @{} claimed {} of staking rewards",
+            account_id,
+            reward
+        );
+        reward.into()
+    }
+
+    /// Owner tops up the reward pool from the owner's balance.
+    #[payable]
+    pub fn fund_reward_pool(&mut self, amount: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        let amount: Balance = amount.into();
+        self.staking.update();
+        self.internal_lock(&env::predecessor_account_id(), amount);
+        self.staking.reward_pool += amount;
+        log!("Reward pool funded with {}", amount);
+    }
+
+    pub fn set_reward_rate(&mut self, reward_rate: U128) {
+        self.assert_owner();
+        self.staking.update();
+        self.staking.reward_rate = reward_rate.into();
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_staked_balance(&self, account_id: AccountId) -> U128 {
+        self.staking
+            .accounts
+            .get(&account_id)
+            .map(|account| account.staked)
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Rewards claimable right now, including emissions not yet written to state.
+    pub fn get_unclaimed_rewards(&self, account_id: AccountId) -> U128 {
+        let account = self
+            .staking
+            .accounts
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default();
+        self.staking
+            .pending_rewards(&account, self.staking.current_acc_reward_per_share())
+            .into()
+    }
+
+    pub fn get_staking_info(&self) -> StakingInfo {
+        StakingInfo {
+            total_staked: self.staking.total_staked.into(),
+            reward_pool: self.staking.reward_pool.into(),
+            reward_rate: self.staking.reward_rate.into(),
+            last_update_timestamp: self.staking.last_update_timestamp.into(),
+        }
+    }
+}
+
+impl Contract {
+    /// Moves `amount` of `account_id`'s tokens into its stake.
+    pub(crate) fn internal_stake(&mut self, account_id: &AccountId, amount: Balance) {
+        self.staking.update();
+        let mut account = self.staking.settled_account(account_id);
+        self.internal_lock(account_id, amount);
+        account.staked += amount;
+        self.staking.total_staked += amount;
+        self.staking.save_account(account_id, &account);
+        log!("Account @{} staked {}", account_id, amount);
+    }
+
+    /// Moves `amount` of staked tokens from `sender_id` to `receiver_id`, settling the rewards
+    /// of both first. The tokens stay in the contract's custody.
+    pub(crate) fn internal_move_stake(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        self.staking.update();
+        let mut sender = self.staking.settled_account(sender_id);
+        let mut receiver = self.staking.settled_account(receiver_id);
+        assert!(sender.staked >= amount, "Not enough staked balance");
+        sender.staked -= amount;
+        receiver.staked += amount;
+        self.staking.save_account(sender_id, &sender);
+        self.staking.save_account(receiver_id, &receiver);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "staking"))]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    const ONE_TOKEN: Balance = 1_000_000_000_000_000_000;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, owner(), 0);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_stake_and_claim_rewards() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).build());
+        contract.fund_reward_pool(U128(1_000 * ONE_TOKEN));
+        contract.set_reward_rate(U128(ONE_TOKEN));
+
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.stake(U128(100 * ONE_TOKEN), None);
+        assert_eq!(contract.get_staked_balance(owner()).0, 100 * ONE_TOKEN);
+        assert_eq!(
+            contract.ft_balance_of(owner()).0,
+            TOTAL_SUPPLY - 1_100 * ONE_TOKEN
+        );
+
+        testing_env!(context
+            .block_timestamp(10 * NANOS_PER_SECOND)
+            .attached_deposit(1)
+            .build());
+        assert_eq!(contract.get_unclaimed_rewards(owner()).0, 10 * ONE_TOKEN);
+        assert_eq!(contract.claim_rewards().0, 10 * ONE_TOKEN);
+        contract.unstake(U128(100 * ONE_TOKEN));
+        assert_eq!(contract.get_staked_balance(owner()).0, 0);
+        assert_eq!(
+            contract.ft_balance_of(owner()).0,
+            TOTAL_SUPPLY - 990 * ONE_TOKEN
+        );
+        assert_eq!(contract.get_staking_info().reward_pool.0, 990 * ONE_TOKEN);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough staked balance")]
+    fn test_unstake_more_than_staked() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.stake(U128(ONE_TOKEN), None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.unstake(U128(2 * ONE_TOKEN));
+    }
+}
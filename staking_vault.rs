@@ -0,0 +1,309 @@
+/*!
+Auto-compounding staking vault issuing stASTRO shares.
+NOTES:
+  - `vault_deposit` stakes ASTRO in the staking pool on behalf of the vault and mints stASTRO
+    shares at the current exchange rate. The vault stakes under the contract account, which no
+    user can stake as.
+  - Before every deposit, withdrawal and on `vault_compound`, the vault restakes its pending
+    staking rewards, so the ASTRO behind each share grows as the reward pool pays out. The views
+    count the rewards not compounded yet.
+  - `vault_withdraw` burns shares for their part of the vault's stake, principal and rewards.
+  - stASTRO balances follow NEP-141 naming with a `vault_` prefix: `vault_ft_transfer`,
+    `vault_ft_balance_of`, `vault_ft_total_supply` and `vault_ft_metadata`. They live in this
+    contract and cannot be sent with `ft_transfer_call`. Holders pay for the storage of their
+    share balance: a deposit or a transfer to a new holder takes it from the attached deposit.
+  - Retiring staking stops deposits; withdrawals keep working.
+*/
+#![cfg_attr(not(feature = "staking"), allow(dead_code, unused_imports))]
+
+use near_contract_standards::fungible_token::metadata::{FungibleTokenMetadata, FT_METADATA_SPEC};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StakingVault {
+    pub shares: LookupMap<AccountId, Balance>,
+    pub total_shares: Balance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultInfo {
+    pub total_shares: U128,
+    pub total_assets: U128,
+    /// ASTRO behind one whole stASTRO.
+    pub exchange_rate: U128,
+}
+
+impl StakingVault {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            shares: LookupMap::new(prefix),
+            total_shares: 0,
+        }
+    }
+
+    pub fn shares_of(&self, account_id: &AccountId) -> Balance {
+        self.shares.get(account_id).copied().unwrap_or(0)
+    }
+
+    fn set_shares(&mut self, account_id: &AccountId, shares: Balance) {
+        if shares == 0 {
+            self.shares.remove(account_id);
+        } else {
+            self.shares.insert(account_id.clone(), shares);
+        }
+    }
+}
+
+fn emit_vault_event(event: &str, data: near_sdk::serde_json::Value) {
+    emit_event(EVENT_VERSION, event, data);
+}
+
+#[cfg(feature = "staking")]
+#[near_bindgen]
+impl Contract {
+    /// Stakes `amount` of the caller's ASTRO in the vault and returns the shares minted.
+    #[payable]
+    pub fn vault_deposit(&mut self, amount: U128) -> U128 {
+        self.assert_module_active(Module::Staking);
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        let account_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        self.internal_compound_vault();
+        let total_assets = self.vault_total_assets();
+        let shares = if self.vault.total_shares == 0 {
+            amount.0
+        } else {
+            mul_div(amount.0, self.vault.total_shares, total_assets)
+        };
+        assert!(shares > 0, "The amount is worth no shares");
+
+        self.internal_lock(&account_id, amount.0);
+        let vault_id = env::current_account_id();
+        let mut vault = self.staking.settled_account(&vault_id);
+        vault.staked += amount.0;
+        self.staking.total_staked += amount.0;
+        self.staking.save_account(&vault_id, &vault);
+        let balance = self.vault.shares_of(&account_id) + shares;
+        self.vault.set_shares(&account_id, balance);
+        self.vault.total_shares += shares;
+
+        self.staking.accounts.flush();
+        self.vault.shares.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        emit_vault_event(
+            "vault_deposit",
+            json!({
+                "account_id": account_id,
+                "amount": amount,
+                "shares": U128(shares),
+            }),
+        );
+        shares.into()
+    }
+
+    /// Burns `shares` of the caller and returns the ASTRO paid out for them.
+    #[payable]
+    pub fn vault_withdraw(&mut self, shares: U128) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let balance = self.vault.shares_of(&account_id);
+        assert!(shares.0 > 0, "The amount should be a positive number");
+        assert!(shares.0 <= balance, "Not enough vault shares");
+        let initial_storage = env::storage_usage();
+        self.internal_compound_vault();
+        let amount = mul_div(shares.0, self.vault_total_assets(), self.vault.total_shares);
+
+        let vault_id = env::current_account_id();
+        let mut vault = self.staking.settled_account(&vault_id);
+        vault.staked -= amount;
+        self.staking.total_staked -= amount;
+        self.staking.save_account(&vault_id, &vault);
+        self.vault.set_shares(&account_id, balance - shares.0);
+        self.vault.total_shares -= shares.0;
+        self.internal_unlock(&account_id, amount);
+
+        self.staking.accounts.flush();
+        self.vault.shares.flush();
+        refund_released_storage(&account_id, initial_storage);
+        emit_vault_event(
+            "vault_withdraw",
+            json!({
+                "account_id": account_id,
+                "amount": U128(amount),
+                "shares": shares,
+            }),
+        );
+        amount.into()
+    }
+
+    /// Restakes the vault's pending rewards. Anyone can call it.
+    pub fn vault_compound(&mut self) -> U128 {
+        self.internal_compound_vault().into()
+    }
+
+    #[payable]
+    pub fn vault_ft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let sender_id = env::predecessor_account_id();
+        assert_ne!(
+            sender_id, receiver_id,
+            "Sender and receiver should be different"
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        let sender_shares = self.vault.shares_of(&sender_id);
+        assert!(amount.0 <= sender_shares, "Not enough vault shares");
+        let initial_storage = env::storage_usage();
+        let receiver_shares = self.vault.shares_of(&receiver_id);
+        self.vault.set_shares(&sender_id, sender_shares - amount.0);
+        self.vault
+            .set_shares(&receiver_id, receiver_shares + amount.0);
+        self.vault.shares.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        refund_released_storage(&sender_id, initial_storage);
+        emit_vault_event(
+            "vault_transfer",
+            json!({
+                "old_owner_id": sender_id,
+                "new_owner_id": receiver_id,
+                "amount": amount,
+                "memo": memo,
+            }),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn vault_ft_balance_of(&self, account_id: AccountId) -> U128 {
+        self.vault.shares_of(&account_id).into()
+    }
+
+    pub fn vault_ft_total_supply(&self) -> U128 {
+        self.vault.total_shares.into()
+    }
+
+    pub fn vault_ft_metadata(&self) -> FungibleTokenMetadata {
+        let metadata = self.metadata.get().unwrap();
+        FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: format!("Staked {}", metadata.name),
+            symbol: format!("st{}", metadata.symbol),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: metadata.decimals,
+        }
+    }
+
+    pub fn get_vault_info(&self) -> VaultInfo {
+        let total_assets = self.vault_total_assets();
+        let exchange_rate = if self.vault.total_shares == 0 {
+            self.one_token()
+        } else {
+            mul_div(self.one_token(), total_assets, self.vault.total_shares)
+        };
+        VaultInfo {
+            total_shares: self.vault.total_shares.into(),
+            total_assets: total_assets.into(),
+            exchange_rate: exchange_rate.into(),
+        }
+    }
+}
+
+impl Contract {
+    /// ASTRO staked by the vault, with the rewards not compounded yet.
+    fn vault_total_assets(&self) -> Balance {
+        let vault = self
+            .staking
+            .accounts
+            .get(&env::current_account_id())
+            .cloned()
+            .unwrap_or_default();
+        vault.staked
+            + self
+                .staking
+                .pending_rewards(&vault, self.staking.current_acc_reward_per_share())
+    }
+
+    /// Moves the vault's pending rewards into its stake and returns them.
+    fn internal_compound_vault(&mut self) -> Balance {
+        self.staking.update();
+        let vault_id = env::current_account_id();
+        let mut vault = self.staking.settled_account(&vault_id);
+        let reward = std::mem::take(&mut vault.unclaimed);
+        vault.staked += reward;
+        self.staking.total_staked += reward;
+        self.staking.save_account(&vault_id, &vault);
+        if reward > 0 {
+            log!("The vault compounded {} of staking rewards", reward);
+        }
+        reward
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32"), feature = "staking"))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    const ONE_TOKEN: Balance = 1_000_000_000_000_000_000;
+    const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_vault_compounds_rewards() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 1);
+        let owner = owner();
+        contract.fund_reward_pool(U128(1_000 * ONE_TOKEN));
+        contract.set_reward_rate(U128(ONE_TOKEN));
+
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        assert_eq!(
+            contract.vault_deposit(U128(100 * ONE_TOKEN)).0,
+            100 * ONE_TOKEN
+        );
+        contract.vault_ft_transfer(accounts(1), U128(50 * ONE_TOKEN), None);
+        assert_eq!(contract.vault_ft_balance_of(accounts(1)).0, 50 * ONE_TOKEN);
+
+        testing_env!(context
+            .block_timestamp(100 * NANOS_PER_SECOND)
+            .attached_deposit(1)
+            .build());
+        // 100 seconds at 1 token per second doubled the vault.
+        assert_eq!(contract.get_vault_info().exchange_rate.0, 2 * ONE_TOKEN);
+        assert_eq!(
+            contract.vault_withdraw(U128(50 * ONE_TOKEN)).0,
+            100 * ONE_TOKEN
+        );
+        assert_eq!(
+            contract.ft_balance_of(owner).0,
+            TOTAL_SUPPLY - 1_000 * ONE_TOKEN
+        );
+        assert_eq!(contract.get_vault_info().total_assets.0, 100 * ONE_TOKEN);
+    }
+}
@@ -0,0 +1,196 @@
+/*!
+NEP-145 storage management and the storage deposit helpers shared by every subsystem.
+NOTES:
+  - Subsystems that store data for a caller measure the storage used with `env::storage_usage`
+    and charge it with `refund_deposit`; they give it back with `refund_released_storage`.
+  - `storage_unregister(force)` burns the remaining balance, with the checkpoints, votes and
+    `ft_burn` event of any other burn. `storage_unregister_to(beneficiary)`
+    instead transfers it to the beneficiary, with the checks and fees of `ft_transfer`, and
+    sends the storage refund there too, in the same call.
+*/
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise, StorageUsage};
+
+use crate::*;
+
+/// Asserts the attached deposit covers `storage_used` bytes and refunds the excess.
+pub(crate) fn refund_deposit(storage_used: StorageUsage) {
+    let required_cost = env::storage_byte_cost() * Balance::from(storage_used);
+    let attached_deposit = env::attached_deposit();
+    assert!(
+        required_cost <= attached_deposit,
+        "Must attach {} yoctoNEAR to cover storage",
+        required_cost
+    );
+    let refund = attached_deposit - required_cost;
+    if refund > 1 {
+        Promise::new(env::predecessor_account_id()).transfer(refund);
+    }
+}
+
+/// Refunds the cost of the storage released since `initial_storage` to `account_id`, the account
+/// that paid for it. Collections must be flushed first.
+pub(crate) fn refund_released_storage(account_id: &AccountId, initial_storage: StorageUsage) {
+    let released = initial_storage.saturating_sub(env::storage_usage());
+    if released > 0 {
+        let refund = env::storage_byte_cost() * Balance::from(released);
+        Promise::new(account_id.clone()).transfer(refund);
+    }
+}
+
+impl Contract {
+    /// Registers `account_id` with the attached deposit of the caller and returns the part of the
+    /// deposit used, which includes any registration rate limit fee.
+    pub(crate) fn internal_paid_registration(&mut self, account_id: &AccountId) -> Balance {
+        let required = self.storage_balance_bounds().min.0
+            + self
+                .registration_limits
+                .register(&env::predecessor_account_id());
+        assert!(
+            env::attached_deposit() >= required,
+            "The attached deposit is less than the minimum storage balance ({})",
+            required
+        );
+        self.internal_register_account(account_id);
+        let top_up =
+            self.storage_balance_bounds().min.0 - self.token.storage_balance_bounds().min.0;
+        self.internal_record_top_up(account_id, top_up);
+        required
+    }
+
+    /// Registers the contract account, which holds custody and treasury balances, if needed.
+    /// Contracts migrated from older state may not have it registered yet.
+    pub(crate) fn internal_register_contract_account(&mut self) {
+        let contract_id = env::current_account_id();
+        if !self.token.accounts.contains_key(&contract_id) {
+            self.internal_register_account(&contract_id);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Closes the caller's account, moving its balance and its storage refund to `beneficiary`.
+    /// The storage deposit of a sponsored account goes back to the sponsorship pool.
+    #[payable]
+    pub fn storage_unregister_to(&mut self, beneficiary: AccountId) -> bool {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert_ne!(
+            account_id, beneficiary,
+            "The beneficiary should be another account"
+        );
+        self.assert_no_stake(&account_id);
+        let balance = self
+            .token
+            .accounts
+            .get(&account_id)
+            .expect("The account is not registered");
+        if balance > 0 {
+            self.internal_ft_transfer(
+                &account_id,
+                beneficiary.clone(),
+                balance,
+                Some("Account closed".to_string()),
+            );
+            assert_eq!(
+                self.token.accounts.get(&account_id),
+                Some(0),
+                "The balance could not be transferred"
+            );
+        }
+        if self.is_sponsored(account_id.clone()) {
+            return self.internal_unregister_sponsored(None);
+        }
+        let deposit = self.internal_storage_balance(&account_id);
+        self.token.accounts.remove(&account_id);
+        self.registered_accounts.remove(&account_id);
+        if let Some(top_up) = self.storage_grace.top_ups.remove(&account_id) {
+            self.rescue.release(top_up);
+        }
+        self.on_account_closed(account_id, 0);
+        Promise::new(beneficiary).transfer(deposit + 1);
+        true
+    }
+}
+
+impl Contract {
+    fn assert_no_stake(&self, account_id: &AccountId) {
+        assert!(
+            !self.staking.accounts.contains_key(account_id),
+            "Unstake and claim rewards before unregistering"
+        );
+    }
+}
+
+#[near_bindgen]
+impl StorageManagement for Contract {
+    /// Registers new accounts subject to `registration_limits`. For registered accounts the
+    /// deposit tops up a storage shortfall, see `storage_grace`.
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        if self.token.accounts.contains_key(&account_id) {
+            self.internal_storage_top_up(&account_id, registration_only.unwrap_or(false));
+            return self.storage_balance_of(account_id).unwrap();
+        }
+        let required = self.internal_paid_registration(&account_id);
+        let refund = env::attached_deposit() - required;
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+        self.telemetry.record("storage_deposit");
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        self.token.storage_withdraw(amount);
+        self.storage_balance_of(env::predecessor_account_id())
+            .unwrap()
+    }
+
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        self.assert_no_stake(&env::predecessor_account_id());
+        if self.is_sponsored(env::predecessor_account_id()) {
+            return self.internal_unregister_sponsored(force);
+        }
+        let account_id = env::predecessor_account_id();
+        let closed = self.with_checkpoints(std::slice::from_ref(&account_id), |this| {
+            this.token.internal_storage_unregister(force)
+        });
+        if let Some((account_id, balance)) = closed {
+            self.registered_accounts.remove(&account_id);
+            self.internal_refund_top_up(&account_id);
+            self.on_account_closed(account_id, balance);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        let mut bounds = self.token.storage_balance_bounds();
+        bounds.min = std::cmp::max(bounds.min.0, self.storage_grace.requirement).into();
+        bounds.max = Some(bounds.min);
+        bounds
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        if !self.token.accounts.contains_key(&account_id) {
+            return None;
+        }
+        Some(StorageBalance {
+            total: self.internal_storage_balance(&account_id).into(),
+            available: 0.into(),
+        })
+    }
+}
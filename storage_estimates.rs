@@ -0,0 +1,61 @@
+/*!
+Storage deposit estimates for wallets.
+NOTES:
+  - `estimate_storage_deposit` returns what `storage_deposit` for an account needs right now: the
+    minimum storage balance plus the current burst surcharge for a new account, the storage
+    shortfall for a registered one. Wallets should read it instead of hardcoding 0.00125 N.
+  - `estimate_batch_transfer_cost` returns the deposit `load_distribution_chunk` needs to stage
+    that many recipients, counting each at the longest account ID. The excess is refunded.
+  - Both follow the current storage byte cost of the protocol.
+*/
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+/// Bytes of one staged `mass_distribution` entry: the record overhead, the key (prefix and
+/// index) and the value (an account ID of 64 bytes at most and an amount).
+const MAX_DISTRIBUTION_ENTRY_BYTES: u64 = 40 + (1 + 8) + (4 + 64 + 16);
+
+#[near_bindgen]
+impl Contract {
+    /// The yoctoNEAR to attach to `storage_deposit` for `account_id`.
+    pub fn estimate_storage_deposit(&self, account_id: AccountId) -> U128 {
+        if self.token.accounts.contains_key(&account_id) {
+            return self.storage_shortfall(&account_id).into();
+        }
+        (self.storage_balance_bounds().min.0 + self.registration_limits.next_surcharge()).into()
+    }
+
+    /// The yoctoNEAR to attach to `load_distribution_chunk` for `n_recipients` recipients.
+    pub fn estimate_batch_transfer_cost(&self, n_recipients: u32) -> U128 {
+        (env::storage_byte_cost()
+            * Balance::from(MAX_DISTRIBUTION_ENTRY_BYTES)
+            * Balance::from(n_recipients))
+        .into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{owner, setup};
+
+    #[test]
+    fn test_estimates() {
+        let (_, contract) = setup(&[]);
+        let owner = owner();
+        assert_eq!(
+            contract.estimate_storage_deposit(accounts(1)),
+            contract.storage_balance_bounds().min
+        );
+        assert_eq!(contract.estimate_storage_deposit(owner).0, 0);
+        assert_eq!(
+            contract.estimate_batch_transfer_cost(2).0,
+            2 * contract.estimate_batch_transfer_cost(1).0
+        );
+    }
+}
@@ -0,0 +1,202 @@
+/*!
+Grace period for storage balance shortfalls.
+NOTES:
+  - Per-account features can make an account cost more storage than it paid for at registration.
+    The owner then raises `requirement`, the storage balance every account must hold. New
+    accounts pay it in full; existing accounts top up with `storage_deposit`.
+  - Raising the requirement does not restrict anyone right away: accounts below it keep working
+    until `restricted_from`, set to `grace_period` after the raise. From then on they cannot send
+    tokens until they top up. They can still receive tokens and unregister.
+  - Top-ups are kept next to the registration deposit and refunded on `storage_unregister`.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, Promise};
+
+use crate::*;
+
+/// Default grace period of 30 days in nanoseconds.
+const DEFAULT_GRACE_PERIOD: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StorageGrace {
+    /// Storage balance required per account, 0 while the registration minimum is enough.
+    pub requirement: Balance,
+    pub grace_period: u64,
+    /// Time from which accounts below the requirement are restricted.
+    pub restricted_from: u64,
+    /// Storage deposits on top of the registration minimum.
+    pub top_ups: LookupMap<AccountId, Balance>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageShortfall {
+    pub shortfall: U128,
+    pub restricted_from: U64,
+}
+
+impl StorageGrace {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            requirement: 0,
+            grace_period: DEFAULT_GRACE_PERIOD,
+            restricted_from: 0,
+            top_ups: LookupMap::new(prefix),
+        }
+    }
+
+    pub fn top_up(&self, account_id: &AccountId) -> Balance {
+        self.top_ups.get(account_id).copied().unwrap_or(0)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the storage balance required per account. Raising it starts a new grace period.
+    pub fn set_storage_requirement(&mut self, requirement: U128) {
+        self.assert_owner();
+        if requirement.0 > self.storage_grace.requirement {
+            self.storage_grace.restricted_from =
+                env::block_timestamp() + self.storage_grace.grace_period;
+        }
+        self.storage_grace.requirement = requirement.0;
+        log!(
+            "Storage requirement set to {}, restricted from {}",
+            requirement.0,
+            self.storage_grace.restricted_from
+        );
+    }
+
+    pub fn set_storage_grace_period(&mut self, grace_period: U64) {
+        self.assert_owner();
+        self.storage_grace.grace_period = grace_period.0;
+    }
+
+    pub fn get_storage_shortfall(&self, account_id: AccountId) -> StorageShortfall {
+        StorageShortfall {
+            shortfall: self.storage_shortfall(&account_id).into(),
+            restricted_from: self.storage_grace.restricted_from.into(),
+        }
+    }
+}
+
+impl Contract {
+    /// Storage balance of a registered account: the registration minimum and its top-ups.
+    pub(crate) fn internal_storage_balance(&self, account_id: &AccountId) -> Balance {
+        self.token.storage_balance_bounds().min.0 + self.storage_grace.top_up(account_id)
+    }
+
+    pub(crate) fn storage_shortfall(&self, account_id: &AccountId) -> Balance {
+        if !self.token.accounts.contains_key(account_id) {
+            return 0;
+        }
+        self.storage_grace
+            .requirement
+            .saturating_sub(self.internal_storage_balance(account_id))
+    }
+
+    /// Panics if `account_id` is below the storage requirement after the grace period.
+    pub(crate) fn assert_storage_covered(&self, account_id: &AccountId) {
+        if env::block_timestamp() < self.storage_grace.restricted_from {
+            return;
+        }
+        let shortfall = self.storage_shortfall(account_id);
+        assert!(
+            shortfall == 0,
+            "The storage balance of {} is {} short, top it up with storage_deposit",
+            account_id,
+            shortfall
+        );
+    }
+
+    /// Adds up to the shortfall of `account_id` from the attached deposit, refunding the rest.
+    /// With `registration_only` the whole deposit is refunded.
+    pub(crate) fn internal_storage_top_up(
+        &mut self,
+        account_id: &AccountId,
+        registration_only: bool,
+    ) {
+        let amount = env::attached_deposit();
+        let top_up = if registration_only {
+            0
+        } else {
+            std::cmp::min(amount, self.storage_shortfall(account_id))
+        };
+        if top_up > 0 {
+            let total = self.storage_grace.top_up(account_id) + top_up;
+            self.storage_grace.top_ups.insert(account_id.clone(), total);
+            self.rescue.hold(top_up);
+        }
+        if amount > top_up {
+            Promise::new(env::predecessor_account_id()).transfer(amount - top_up);
+        }
+    }
+
+    /// Records the part of a new account's deposit above the registration minimum.
+    pub(crate) fn internal_record_top_up(&mut self, account_id: &AccountId, amount: Balance) {
+        if amount > 0 {
+            self.storage_grace
+                .top_ups
+                .insert(account_id.clone(), amount);
+            self.rescue.hold(amount);
+        }
+    }
+
+    /// Refunds the top-ups of a closed account.
+    pub(crate) fn internal_refund_top_up(&mut self, account_id: &AccountId) {
+        if let Some(top_up) = self.storage_grace.top_ups.remove(account_id) {
+            self.rescue.release(top_up);
+            Promise::new(account_id.clone()).transfer(top_up);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    fn setup() -> (VMContextBuilder, Contract, Balance) {
+        let (mut context, mut contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, owner(), 0);
+        let min = contract.storage_balance_bounds().min.0;
+        contract.set_storage_grace_period(U64(100));
+        contract.set_storage_requirement(U128(min + 1_000));
+        (context, contract, min)
+    }
+
+    #[test]
+    #[should_panic(expected = "is 1000 short")]
+    fn test_shortfall_restricts_after_grace_period() {
+        let (mut context, mut contract, _) = setup();
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(0), U128(10), None);
+        testing_env!(context.block_timestamp(100).build());
+        contract.ft_transfer(accounts(0), U128(10), None);
+    }
+
+    #[test]
+    fn test_top_up_lifts_restriction() {
+        let (mut context, mut contract, min) = setup();
+        let owner = owner();
+        assert_eq!(
+            contract.get_storage_shortfall(owner.clone()).shortfall.0,
+            1_000
+        );
+        testing_env!(context.block_timestamp(100).attached_deposit(5_000).build());
+        contract.storage_deposit(None, None);
+        assert_eq!(contract.get_storage_shortfall(owner.clone()).shortfall.0, 0);
+        assert_eq!(
+            contract.storage_balance_of(owner).unwrap().total.0,
+            min + 1_000
+        );
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(0), U128(10), None);
+    }
+}
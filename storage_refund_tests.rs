@@ -0,0 +1,123 @@
+/*!
+Tests that state-shrinking operations refund exactly the released storage to the account that
+paid for it.
+NOTES:
+  - Each test measures the storage usage around a single call and compares the released bytes
+    with the NEAR transfers the call created.
+  - Storage the contract paid for itself, like the token metadata, is not refunded to anyone.
+*/
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::mock::VmAction;
+use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+use near_sdk::{env, testing_env, AccountId, Balance};
+
+use crate::test_fixtures::{call_as, owner, ONE_NEAR};
+use crate::*;
+
+/// Contract with `accounts(1)` and `accounts(2)` registered, called by the owner with 1 NEAR.
+fn setup() -> (VMContextBuilder, Contract) {
+    test_fixtures::setup(&[accounts(1), accounts(2)])
+}
+
+/// NEAR transferred to `account_id` by the receipts of the current call.
+fn refunds_to(account_id: &AccountId) -> Balance {
+    get_created_receipts()
+        .into_iter()
+        .filter(|receipt| &receipt.receiver_id == account_id)
+        .flat_map(|receipt| receipt.actions)
+        .map(|action| match action {
+            VmAction::Transfer { deposit } => deposit,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Runs `f` and returns the cost of the storage it released.
+fn released_cost(f: impl FnOnce()) -> Balance {
+    let initial_storage = env::storage_usage();
+    f();
+    let released = initial_storage - env::storage_usage();
+    assert!(released > 0, "Nothing was released");
+    env::storage_byte_cost() * Balance::from(released)
+}
+
+#[test]
+fn test_unregister_refunds_registration() {
+    let (mut context, mut contract) = setup();
+    let min = contract.storage_balance_bounds().min.0;
+    call_as(&mut context, accounts(1), 1);
+    contract.storage_unregister(None);
+    // The attached yocto is returned with the registration deposit.
+    assert_eq!(refunds_to(&accounts(1)), min + 1);
+}
+
+#[test]
+fn test_unregister_to_moves_balance_and_refund_to_beneficiary() {
+    let (mut context, mut contract) = setup();
+    let min = contract.storage_balance_bounds().min.0;
+    call_as(&mut context, owner(), 1);
+    contract.ft_transfer(accounts(1), U128(100), None);
+    call_as(&mut context, accounts(1), 1);
+    contract.storage_unregister_to(accounts(2));
+    assert!(contract.storage_balance_of(accounts(1)).is_none());
+    assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+    assert_eq!(refunds_to(&accounts(2)), min + 1);
+}
+
+#[test]
+fn test_metadata_shrink_refunds_nobody() {
+    let (mut context, mut contract) = setup();
+    call_as(&mut context, owner(), 0);
+    contract.update_metadata(Some("data:image/svg+xml,".to_string()), None, None, None);
+    assert!(get_created_receipts().is_empty());
+}
+
+#[test]
+fn test_expired_mint_request_refunds_submitter() {
+    let (mut context, mut contract) = setup();
+    contract.add_mint_submitter(accounts(1));
+    contract.set_mint_approval_window(U64(100));
+    call_as(&mut context, accounts(1), ONE_NEAR);
+    let request_id = contract.submit_mint_request(accounts(2), U128(10), None);
+
+    // Anyone can remove the request, the submitter gets the storage back.
+    testing_env!(context
+        .predecessor_account_id(accounts(3))
+        .attached_deposit(0)
+        .block_timestamp(101)
+        .build());
+    let cost = released_cost(|| contract.remove_expired_mint_request(request_id));
+    assert_eq!(refunds_to(&accounts(1)), cost);
+    assert_eq!(refunds_to(&accounts(3)), 0);
+}
+
+#[test]
+fn test_finished_stream_refunds_sender() {
+    let (mut context, mut contract) = setup();
+    call_as(&mut context, owner(), ONE_NEAR);
+    let stream_id = contract.create_stream(accounts(1), U128(1_000), U64(0), U64(100));
+
+    testing_env!(context
+        .predecessor_account_id(accounts(1))
+        .attached_deposit(1)
+        .block_timestamp(100)
+        .build());
+    let cost = released_cost(|| {
+        contract.withdraw_from_stream(stream_id);
+    });
+    assert_eq!(refunds_to(&owner()), cost);
+    assert_eq!(refunds_to(&accounts(1)), 0);
+}
+
+#[test]
+fn test_used_allowance_refunds_owner() {
+    let (mut context, mut contract) = setup();
+    call_as(&mut context, owner(), ONE_NEAR);
+    contract.approve(accounts(1), U128(100), None, None);
+
+    call_as(&mut context, accounts(1), 1);
+    let cost = released_cost(|| contract.transfer_from(owner(), accounts(2), U128(100), None));
+    assert_eq!(refunds_to(&owner()), cost);
+}
@@ -0,0 +1,242 @@
+/*!
+Per-second token streams from a sender to a receiver.
+NOTES:
+  - The whole stream amount is deposited into the contract when the stream is created and is
+    released linearly between `start` and `end` (timestamps in nanoseconds).
+  - The receiver can withdraw the accrued portion at any time.
+  - The sender can cancel a stream: the accrued part goes to the receiver and the remainder is
+    returned to the sender.
+  - The sender pays for the storage of the stream and gets it back once the stream is fully
+    withdrawn or cancelled.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::pool_ledger::Pool;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Stream {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub withdrawn: U128,
+    pub start: U64,
+    pub end: U64,
+}
+
+impl Stream {
+    /// Total amount released to the receiver by the given timestamp.
+    pub fn accrued_at(&self, timestamp: u64) -> Balance {
+        if timestamp <= self.start.0 {
+            0
+        } else if timestamp >= self.end.0 {
+            self.amount.0
+        } else {
+            let elapsed = Balance::from(timestamp - self.start.0);
+            let duration = Balance::from(self.end.0 - self.start.0);
+            // Split to avoid overflowing `amount * elapsed` on long streams.
+            self.amount.0 / duration * elapsed + self.amount.0 % duration * elapsed / duration
+        }
+    }
+
+    /// Accrued amount that was not withdrawn yet.
+    pub fn withdrawable_at(&self, timestamp: u64) -> Balance {
+        self.accrued_at(timestamp) - self.withdrawn.0
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Deposits `amount` of the caller's tokens into a new stream to `receiver` and returns the
+    /// stream id. The attached deposit covers the stream storage.
+    #[payable]
+    pub fn create_stream(
+        &mut self,
+        receiver: AccountId,
+        amount: U128,
+        start: U64,
+        end: U64,
+    ) -> U64 {
+        self.assert_module_active(Module::Streams);
+        let amount: Balance = amount.into();
+        assert!(amount > 0, "The amount should be a positive number");
+        assert!(start.0 < end.0, "Stream must end after it starts");
+        assert!(
+            end.0 > env::block_timestamp(),
+            "Stream must end in the future"
+        );
+        let sender_id = env::predecessor_account_id();
+        let receiver_id = receiver;
+        assert_ne!(
+            sender_id, receiver_id,
+            "Sender and receiver should be different"
+        );
+        assert!(
+            self.token.accounts.contains_key(&receiver_id),
+            "The account {} is not registered",
+            receiver_id
+        );
+        let initial_storage = env::storage_usage();
+        self.internal_lock_to(Pool::Streams, &sender_id, amount);
+        let stream_id = self.next_stream_id;
+        self.next_stream_id += 1;
+        self.streams.insert(
+            stream_id,
+            Stream {
+                sender_id: sender_id.clone(),
+                receiver_id: receiver_id.clone(),
+                amount: amount.into(),
+                withdrawn: 0.into(),
+                start,
+                end,
+            },
+        );
+        self.streams.flush();
+        self.scheduler.schedule(
+            ScheduledAction::WithdrawStream {
+                stream_id: stream_id.into(),
+            },
+            end.0,
+        );
+        log!(
+            "Stream {} of {} created from @{} to @{}",
+            stream_id,
+            amount,
+            sender_id,
+            receiver_id
+        );
+        refund_deposit(env::storage_usage() - initial_storage);
+        stream_id.into()
+    }
+
+    /// Transfers the accrued, not yet withdrawn part of the stream to its receiver.
+    #[payable]
+    pub fn withdraw_from_stream(&mut self, stream_id: U64) -> U128 {
+        assert_one_yocto();
+        let mut stream = self.unwrap_stream(stream_id.0);
+        assert_eq!(
+            env::predecessor_account_id(),
+            stream.receiver_id,
+            "Only the receiver can withdraw from a stream"
+        );
+        let amount = stream.withdrawable_at(env::block_timestamp());
+        assert!(amount > 0, "Nothing to withdraw");
+        stream.withdrawn = (stream.withdrawn.0 + amount).into();
+        if stream.withdrawn.0 == stream.amount.0 {
+            self.internal_remove_stream(stream_id.0, &stream);
+        } else {
+            self.streams.insert(stream_id.0, stream.clone());
+        }
+        self.internal_unlock_from(Pool::Streams, &stream.receiver_id, amount);
+        log!(
+            "Account @{} withdrew {} from stream {}",
+            stream.receiver_id,
+            amount,
+            stream_id.0
+        );
+        amount.into()
+    }
+
+    /// Stops the stream: the receiver gets what has accrued and the sender gets the rest back.
+    #[payable]
+    pub fn cancel_stream(&mut self, stream_id: U64) {
+        assert_one_yocto();
+        let stream = self.unwrap_stream(stream_id.0);
+        assert_eq!(
+            env::predecessor_account_id(),
+            stream.sender_id,
+            "Only the sender can cancel a stream"
+        );
+        self.internal_remove_stream(stream_id.0, &stream);
+        let now = env::block_timestamp();
+        let receiver_amount = stream.withdrawable_at(now);
+        let sender_amount = stream.amount.0 - stream.accrued_at(now);
+        if receiver_amount > 0 {
+            self.internal_unlock_from(Pool::Streams, &stream.receiver_id, receiver_amount);
+        }
+        if sender_amount > 0 {
+            self.internal_unlock_from(Pool::Streams, &stream.sender_id, sender_amount);
+        }
+        log!(
+            "Stream {} cancelled: {} to @{}, {} back to @{}",
+            stream_id.0,
+            receiver_amount,
+            stream.receiver_id,
+            sender_amount,
+            stream.sender_id
+        );
+    }
+
+    pub fn get_stream(&self, stream_id: U64) -> Option<Stream> {
+        self.streams.get(&stream_id.0).cloned()
+    }
+
+    pub fn get_stream_withdrawable(&self, stream_id: U64) -> U128 {
+        self.unwrap_stream(stream_id.0)
+            .withdrawable_at(env::block_timestamp())
+            .into()
+    }
+}
+
+impl Contract {
+    fn unwrap_stream(&self, stream_id: u64) -> Stream {
+        self.streams
+            .get(&stream_id)
+            .cloned()
+            .expect("Stream not found")
+    }
+
+    /// Removes a finished or cancelled stream, refunding its storage to the sender.
+    fn internal_remove_stream(&mut self, stream_id: u64, stream: &Stream) {
+        let initial_storage = env::storage_usage();
+        self.streams.remove(&stream_id);
+        self.streams.flush();
+        self.scheduler.unschedule(&ScheduledAction::WithdrawStream {
+            stream_id: stream_id.into(),
+        });
+        refund_released_storage(&stream.sender_id, initial_storage);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, owner};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        contract.create_stream(accounts(1), U128(1_000), U64(0), U64(100));
+        (context, contract)
+    }
+
+    #[test]
+    fn test_withdraw_from_stream() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .block_timestamp(25)
+            .build());
+        assert_eq!(contract.withdraw_from_stream(U64(0)).0, 250);
+        testing_env!(context.block_timestamp(200).build());
+        assert_eq!(contract.withdraw_from_stream(U64(0)).0, 750);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert!(contract.get_stream(U64(0)).is_none());
+    }
+
+    #[test]
+    fn test_cancel_stream() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).block_timestamp(40).build());
+        contract.cancel_stream(U64(0));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 400);
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 400);
+    }
+}
@@ -0,0 +1,286 @@
+/*!
+Recurring payments: merchants pull a fixed amount per period from subscribers.
+NOTES:
+  - A payer subscribes to a merchant for `amount` per `period`, optionally up to a total `cap`.
+    The merchant calls `charge_subscription` once per period, the first period starting at
+    creation. A period the merchant did not charge is not billed later.
+  - A charge is an `ft_transfer` from the payer with every check of the public method: pauses,
+    freezes, fees and transfer limits apply, and it fails if the payer cannot cover it.
+  - Either side can cancel at any time. The payer pays for the storage of the subscription and
+    gets it back when it is cancelled.
+  - `get_subscriptions` lists the subscriptions of a payer, at most
+    `MAX_SUBSCRIPTIONS_PER_PAYER`. Merchants follow theirs through the events.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const MAX_SUBSCRIPTIONS_PER_PAYER: usize = 50;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Subscription {
+    pub payer_id: AccountId,
+    pub merchant_id: AccountId,
+    pub amount: U128,
+    /// Period length in nanoseconds.
+    pub period: U64,
+    pub start: U64,
+    pub cap: Option<U128>,
+    pub charged: U128,
+    /// Index of the last period charged, counted from `start`.
+    pub last_charged_period: Option<U64>,
+}
+
+impl Subscription {
+    fn current_period(&self) -> u64 {
+        (env::block_timestamp() - self.start.0) / self.period.0
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Subscriptions {
+    pub subscriptions: LookupMap<u64, Subscription>,
+    pub by_payer: LookupMap<AccountId, Vec<u64>>,
+    pub next_subscription_id: u64,
+}
+
+impl Subscriptions {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            subscriptions: LookupMap::new([prefix.as_slice(), b"s"].concat()),
+            by_payer: LookupMap::new([prefix.as_slice(), b"p"].concat()),
+            next_subscription_id: 0,
+        }
+    }
+
+    fn flush(&mut self) {
+        self.subscriptions.flush();
+        self.by_payer.flush();
+    }
+}
+
+fn emit_subscription_event(event: &str, subscription_id: u64, subscription: &Subscription) {
+    emit_event(
+        EVENT_VERSION,
+        event,
+        json!({
+            "subscription_id": U64(subscription_id),
+            "payer_id": subscription.payer_id,
+            "merchant_id": subscription.merchant_id,
+            "amount": subscription.amount,
+        }),
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Lets `merchant_id` charge `amount` of the caller's tokens every `period` nanoseconds, up to
+    /// `cap` in total. Returns the subscription id. The attached deposit covers the storage.
+    #[payable]
+    pub fn create_subscription(
+        &mut self,
+        merchant_id: AccountId,
+        amount: U128,
+        period: U64,
+        cap: Option<U128>,
+    ) -> U64 {
+        self.assert_module_active(Module::Subscriptions);
+        let payer_id = env::predecessor_account_id();
+        assert_ne!(
+            payer_id, merchant_id,
+            "Payer and merchant should be different"
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        assert!(period.0 > 0, "The period must be positive");
+        assert!(
+            cap.is_none_or(|cap| cap.0 >= amount.0),
+            "The cap is below the amount"
+        );
+        assert!(
+            self.token.accounts.contains_key(&merchant_id),
+            "The account {} is not registered",
+            merchant_id
+        );
+        let mut subscription_ids = self
+            .subscriptions
+            .by_payer
+            .get(&payer_id)
+            .cloned()
+            .unwrap_or_default();
+        assert!(
+            subscription_ids.len() < MAX_SUBSCRIPTIONS_PER_PAYER,
+            "At most {} subscriptions per payer",
+            MAX_SUBSCRIPTIONS_PER_PAYER
+        );
+        let initial_storage = env::storage_usage();
+        let subscription_id = self.subscriptions.next_subscription_id;
+        self.subscriptions.next_subscription_id += 1;
+        let subscription = Subscription {
+            payer_id: payer_id.clone(),
+            merchant_id,
+            amount,
+            period,
+            start: env::block_timestamp().into(),
+            cap,
+            charged: U128(0),
+            last_charged_period: None,
+        };
+        emit_subscription_event("subscription_create", subscription_id, &subscription);
+        subscription_ids.push(subscription_id);
+        self.subscriptions
+            .by_payer
+            .insert(payer_id, subscription_ids);
+        self.subscriptions
+            .subscriptions
+            .insert(subscription_id, subscription);
+        self.subscriptions.flush();
+        refund_deposit(env::storage_usage() - initial_storage);
+        subscription_id.into()
+    }
+
+    /// Pulls the amount of the current period from the payer. Callable by the merchant once per
+    /// period.
+    pub fn charge_subscription(&mut self, id: U64) -> U128 {
+        let mut subscription = self
+            .subscriptions
+            .subscriptions
+            .get(&id.0)
+            .cloned()
+            .expect("Subscription not found");
+        let merchant_id = env::predecessor_account_id();
+        assert_eq!(
+            merchant_id, subscription.merchant_id,
+            "Only the merchant can charge a subscription"
+        );
+        let period = subscription.current_period();
+        assert!(
+            subscription
+                .last_charged_period
+                .is_none_or(|last| last.0 < period),
+            "The current period was already charged"
+        );
+        let amount = subscription.amount.0;
+        let charged = subscription.charged.0 + amount;
+        assert!(
+            subscription.cap.is_none_or(|cap| charged <= cap.0),
+            "The subscription cap is reached"
+        );
+        subscription.charged = charged.into();
+        subscription.last_charged_period = Some(period.into());
+        self.subscriptions
+            .subscriptions
+            .insert(id.0, subscription.clone());
+        self.internal_ft_transfer(
+            &subscription.payer_id,
+            merchant_id,
+            amount,
+            Some(format!("Subscription {}", id.0)),
+        );
+        emit_subscription_event("subscription_charge", id.0, &subscription);
+        amount.into()
+    }
+
+    /// Ends a subscription. Callable by the payer or the merchant.
+    #[payable]
+    pub fn cancel_subscription(&mut self, id: U64) {
+        assert_one_yocto();
+        let initial_storage = env::storage_usage();
+        let subscription = self
+            .subscriptions
+            .subscriptions
+            .remove(&id.0)
+            .expect("Subscription not found");
+        let caller_id = env::predecessor_account_id();
+        assert!(
+            caller_id == subscription.payer_id || caller_id == subscription.merchant_id,
+            "Only the payer or the merchant can cancel a subscription"
+        );
+        let mut subscription_ids = self
+            .subscriptions
+            .by_payer
+            .get(&subscription.payer_id)
+            .cloned()
+            .unwrap_or_default();
+        subscription_ids.retain(|subscription_id| *subscription_id != id.0);
+        if subscription_ids.is_empty() {
+            self.subscriptions.by_payer.remove(&subscription.payer_id);
+        } else {
+            self.subscriptions
+                .by_payer
+                .insert(subscription.payer_id.clone(), subscription_ids);
+        }
+        self.subscriptions.flush();
+        refund_released_storage(&subscription.payer_id, initial_storage);
+        emit_subscription_event("subscription_cancel", id.0, &subscription);
+    }
+
+    pub fn get_subscription(&self, id: U64) -> Option<Subscription> {
+        self.subscriptions.subscriptions.get(&id.0).cloned()
+    }
+
+    /// Active subscriptions paid by `account_id`, oldest first.
+    pub fn get_subscriptions(&self, account_id: AccountId) -> Vec<(U64, Subscription)> {
+        self.subscriptions
+            .by_payer
+            .get(&account_id)
+            .map_or(vec![], |subscription_ids| {
+                subscription_ids
+                    .iter()
+                    .filter_map(|subscription_id| {
+                        self.subscriptions
+                            .subscriptions
+                            .get(subscription_id)
+                            .map(|subscription| (U64(*subscription_id), subscription.clone()))
+                    })
+                    .collect()
+            })
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    const PERIOD: u64 = 1_000;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        contract.create_subscription(accounts(1), U128(100), U64(PERIOD), Some(U128(200)));
+        call_as(&mut context, accounts(1), 0);
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "The current period was already charged")]
+    fn test_one_charge_per_period() {
+        let (mut context, mut contract) = setup();
+        contract.charge_subscription(U64(0));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        testing_env!(context.block_timestamp(PERIOD - 1).build());
+        contract.charge_subscription(U64(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "The subscription cap is reached")]
+    fn test_cap() {
+        let (mut context, mut contract) = setup();
+        contract.charge_subscription(U64(0));
+        testing_env!(context.block_timestamp(PERIOD).build());
+        contract.charge_subscription(U64(0));
+        let owner = owner();
+        assert_eq!(contract.get_subscriptions(owner)[0].1.charged.0, 200);
+        testing_env!(context.block_timestamp(2 * PERIOD).build());
+        contract.charge_subscription(U64(0));
+    }
+}
@@ -0,0 +1,113 @@
+/*!
+Optional per-method gas usage counters.
+NOTES:
+  - Disabled by default. While enabled, instrumented methods record their invocation count and
+    the gas burnt up to the end of the method (`env::used_gas`), so hot paths can be spotted from
+    production data.
+  - The gas of the counter update itself is not included. Failed calls are reverted and not
+    counted.
+  - Only a fixed set of methods is instrumented, so the contract pays for the few entries.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::Serialize;
+use near_sdk::store::UnorderedMap;
+use near_sdk::{env, near_bindgen};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Default)]
+pub struct MethodCounter {
+    pub calls: u64,
+    pub total_gas: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MethodStats {
+    pub method: String,
+    pub calls: U64,
+    pub average_gas: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Telemetry {
+    pub enabled: bool,
+    pub counters: UnorderedMap<String, MethodCounter>,
+}
+
+impl Telemetry {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            enabled: false,
+            counters: UnorderedMap::new(prefix),
+        }
+    }
+
+    /// Counts a call of `method` with the gas used so far.
+    pub fn record(&mut self, method: &str) {
+        if !self.enabled {
+            return;
+        }
+        let used_gas = env::used_gas().0;
+        let mut counter = self.counters.get(method).copied().unwrap_or_default();
+        counter.calls += 1;
+        counter.total_gas += used_gas;
+        self.counters.insert(method.to_string(), counter);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_telemetry_enabled(&mut self, enabled: bool) {
+        self.assert_owner();
+        self.telemetry.enabled = enabled;
+    }
+
+    /// Clears all counters.
+    pub fn reset_method_stats(&mut self) {
+        self.assert_owner();
+        self.telemetry.counters.clear();
+    }
+
+    pub fn get_method_stats(&self) -> Vec<MethodStats> {
+        self.telemetry
+            .counters
+            .iter()
+            .map(|(method, counter)| MethodStats {
+                method: method.clone(),
+                calls: counter.calls.into(),
+                average_gas: (counter.total_gas / counter.calls).into(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_counts_calls_when_enabled() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(0), U128(10), None);
+        assert!(contract.get_method_stats().is_empty());
+
+        testing_env!(context.attached_deposit(0).build());
+        contract.set_telemetry_enabled(true);
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(0), U128(10), None);
+        contract.ft_transfer(accounts(0), U128(10), None);
+        let stats = contract.get_method_stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].method, "ft_transfer");
+        assert_eq!(stats[0].calls.0, 2);
+    }
+}
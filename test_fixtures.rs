@@ -0,0 +1,45 @@
+/*!
+Fixture shared by the unit tests of every module.
+NOTES:
+  - `setup` deploys the token on `accounts(0)` with the whole default supply held by `owner()`
+    and registers the given accounts, each paying its own storage deposit. The returned context
+    calls as the owner with 1 NEAR attached.
+  - Tests switch callers with `call_as`, which keeps the rest of the context: block height,
+    timestamp and gas.
+*/
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::test_utils::{accounts, VMContextBuilder};
+use near_sdk::{testing_env, AccountId, Balance};
+
+use crate::*;
+
+pub const ONE_NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+
+pub fn owner() -> AccountId {
+    OWNER_ID.parse().unwrap()
+}
+
+/// Token on `accounts(0)` owned by `owner()`, with `account_ids` registered.
+pub fn setup(account_ids: &[AccountId]) -> (VMContextBuilder, Contract) {
+    let mut context = VMContextBuilder::new();
+    context
+        .current_account_id(accounts(0))
+        .predecessor_account_id(owner())
+        .attached_deposit(ONE_NEAR);
+    testing_env!(context.build());
+    let mut contract = Contract::new_default_meta(owner());
+    for account_id in account_ids {
+        call_as(&mut context, account_id.clone(), ONE_NEAR);
+        contract.storage_deposit(None, None);
+    }
+    call_as(&mut context, owner(), ONE_NEAR);
+    (context, contract)
+}
+
+/// Switches to a new call by `account_id` attaching `deposit`, dropping earlier receipts.
+pub fn call_as(context: &mut VMContextBuilder, account_id: AccountId, deposit: Balance) {
+    testing_env!(context
+        .predecessor_account_id(account_id)
+        .attached_deposit(deposit)
+        .build());
+}
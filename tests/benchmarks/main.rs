@@ -0,0 +1,162 @@
+//! Gas and storage benchmarks run against a sandbox node.
+//!
+//! Each benchmark measures the gas burnt by one user path, or the storage used by one account,
+//! and fails when it goes over its budget. Run with `--nocapture` to see the measurements. When a
+//! change legitimately costs more, raise the budget in the same change and say why.
+
+#[allow(dead_code)]
+#[path = "../scenarios/fixtures.rs"]
+mod fixtures;
+
+use near_sdk::json_types::{U128, U64};
+use serde_json::json;
+use workspaces::operations::Function;
+use workspaces::result::ExecutionFinalResult;
+
+use fixtures::{Env, ONE_TOKEN, STORAGE_DEPOSIT};
+
+const TGAS: u64 = 1_000_000_000_000;
+const FT_TRANSFER_BUDGET: u64 = 10 * TGAS;
+const FT_TRANSFER_CALL_BUDGET: u64 = 40 * TGAS;
+const BATCH_OF_TEN_TRANSFERS_BUDGET: u64 = 80 * TGAS;
+const CLAIM_REWARDS_BUDGET: u64 = 15 * TGAS;
+const WITHDRAW_FROM_STREAM_BUDGET: u64 = 15 * TGAS;
+/// Bytes of contract storage used by one registered account.
+const ACCOUNT_STORAGE_BUDGET: u64 = 500;
+
+fn assert_gas(name: &str, outcome: ExecutionFinalResult, budget: u64) -> anyhow::Result<()> {
+    let gas = outcome.total_gas_burnt;
+    outcome.into_result()?;
+    println!("{}: {:.2} Tgas", name, gas as f64 / TGAS as f64);
+    assert!(
+        gas <= budget,
+        "{} burnt {} gas, over the budget of {}",
+        name,
+        gas,
+        budget
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn ft_transfer_gas() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let alice = env.user("alice").await?;
+    let outcome = env
+        .owner
+        .call(env.contract.id(), "ft_transfer")
+        .args_json(json!({ "receiver_id": alice.id(), "amount": U128(ONE_TOKEN) }))
+        .deposit(1)
+        .transact()
+        .await?;
+    assert_gas("ft_transfer", outcome, FT_TRANSFER_BUDGET)
+}
+
+#[tokio::test]
+async fn ft_transfer_call_gas() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let receiver = env.receiver().await?;
+    let outcome = env
+        .owner
+        .call(env.contract.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": receiver.id(),
+            "amount": U128(ONE_TOKEN),
+            "msg": "treasury",
+        }))
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?;
+    assert_gas("ft_transfer_call", outcome, FT_TRANSFER_CALL_BUDGET)
+}
+
+#[tokio::test]
+async fn batch_transfers_gas() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let alice = env.user("alice").await?;
+    let mut batch = env.owner.batch(env.contract.id());
+    for _ in 0..10 {
+        batch = batch.call(
+            Function::new("ft_transfer")
+                .args_json(json!({ "receiver_id": alice.id(), "amount": U128(ONE_TOKEN) }))
+                .deposit(1)
+                .gas(BATCH_OF_TEN_TRANSFERS_BUDGET / 10),
+        );
+    }
+    let outcome = batch.transact().await?;
+    assert_gas("10 ft_transfer", outcome, BATCH_OF_TEN_TRANSFERS_BUDGET)
+}
+
+#[tokio::test]
+async fn claim_paths_gas() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let alice = env.user("alice").await?;
+    env.owner
+        .call(env.contract.id(), "fund_reward_pool")
+        .args_json(json!({ "amount": U128(1_000 * ONE_TOKEN) }))
+        .deposit(1)
+        .transact()
+        .await?
+        .into_result()?;
+    env.owner
+        .call(env.contract.id(), "set_reward_rate")
+        .args_json(json!({ "reward_rate": U128(ONE_TOKEN) }))
+        .transact()
+        .await?
+        .into_result()?;
+    env.owner
+        .call(env.contract.id(), "stake")
+        .args_json(json!({ "amount": U128(100 * ONE_TOKEN) }))
+        .deposit(STORAGE_DEPOSIT)
+        .transact()
+        .await?
+        .into_result()?;
+    let start = env.worker.view_block().await?.timestamp();
+    let stream_id: U64 = env
+        .owner
+        .call(env.contract.id(), "create_stream")
+        .args_json(json!({
+            "receiver": alice.id(),
+            "amount": U128(10 * ONE_TOKEN),
+            "start": U64(start),
+            "end": U64(start + 3_600_000_000_000),
+        }))
+        .deposit(STORAGE_DEPOSIT)
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    env.worker.fast_forward(100).await?;
+
+    let outcome = env
+        .owner
+        .call(env.contract.id(), "claim_rewards")
+        .deposit(1)
+        .transact()
+        .await?;
+    assert_gas("claim_rewards", outcome, CLAIM_REWARDS_BUDGET)?;
+    let outcome = alice
+        .call(env.contract.id(), "withdraw_from_stream")
+        .args_json(json!({ "stream_id": stream_id }))
+        .deposit(1)
+        .transact()
+        .await?;
+    assert_gas("withdraw_from_stream", outcome, WITHDRAW_FROM_STREAM_BUDGET)
+}
+
+#[tokio::test]
+async fn account_storage() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let before = env.contract.view_account().await?.storage_usage;
+    env.user("alice").await?;
+    let used = env.contract.view_account().await?.storage_usage - before;
+    println!("registered account: {} bytes", used);
+    assert!(
+        used <= ACCOUNT_STORAGE_BUDGET,
+        "A registered account uses {} bytes, over the budget of {}",
+        used,
+        ACCOUNT_STORAGE_BUDGET
+    );
+    Ok(())
+}
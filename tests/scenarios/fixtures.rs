@@ -0,0 +1,92 @@
+use serde_json::json;
+use workspaces::network::Sandbox;
+use workspaces::{Account, Contract, Worker};
+
+pub const ONE_TOKEN: u128 = 1_000_000_000_000_000_000;
+pub const TOTAL_SUPPLY: u128 = 90_000_000 * ONE_TOKEN;
+/// Deposit attached to calls that may allocate storage; the excess is refunded.
+pub const STORAGE_DEPOSIT: u128 = 100_000_000_000_000_000_000_000;
+
+/// A freshly deployed token with the whole supply held by `owner`.
+pub struct Env {
+    pub worker: Worker<Sandbox>,
+    pub contract: Contract,
+    pub owner: Account,
+}
+
+impl Env {
+    pub async fn init() -> anyhow::Result<Self> {
+        let worker = workspaces::sandbox().await?;
+        let wasm = workspaces::compile_project(".").await?;
+        let owner = worker.dev_create_account().await?;
+        let contract = deploy_token(&worker, &wasm, &owner).await?;
+        Ok(Self {
+            worker,
+            contract,
+            owner,
+        })
+    }
+
+    /// Creates a sub-account of the root account registered with the token.
+    pub async fn user(&self, name: &str) -> anyhow::Result<Account> {
+        let account = self.unregistered_user(name).await?;
+        account
+            .call(self.contract.id(), "storage_deposit")
+            .args_json(json!({}))
+            .deposit(STORAGE_DEPOSIT)
+            .transact()
+            .await?
+            .into_result()?;
+        Ok(account)
+    }
+
+    /// Creates a sub-account of the root account that is not registered with the token.
+    pub async fn unregistered_user(&self, name: &str) -> anyhow::Result<Account> {
+        let account = self
+            .worker
+            .root_account()?
+            .create_subaccount(name)
+            .initial_balance(1_000 * STORAGE_DEPOSIT)
+            .transact()
+            .await?
+            .into_result()?;
+        Ok(account)
+    }
+
+    /// Deploys a `mock_receiver` for `ft_transfer_call`, registered with the token. Its
+    /// `ft_on_transfer` behaves as the `msg` says: `accept`, `refund:<amount>`, `refund_all`,
+    /// `panic` or `slow`.
+    pub async fn receiver(&self) -> anyhow::Result<Contract> {
+        let wasm = workspaces::compile_project("./mock_receiver").await?;
+        let receiver = self.worker.dev_deploy(&wasm).await?;
+        receiver
+            .call("new")
+            .args_json(json!({}))
+            .transact()
+            .await?
+            .into_result()?;
+        self.owner
+            .call(self.contract.id(), "storage_deposit")
+            .args_json(json!({ "account_id": receiver.id() }))
+            .deposit(STORAGE_DEPOSIT)
+            .transact()
+            .await?
+            .into_result()?;
+        Ok(receiver)
+    }
+}
+
+async fn deploy_token(
+    worker: &Worker<Sandbox>,
+    wasm: &[u8],
+    owner: &Account,
+) -> anyhow::Result<Contract> {
+    let contract = worker.dev_deploy(wasm).await?;
+    contract
+        .call("new_default_meta")
+        .args_json(json!({ "owner_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(contract)
+}
@@ -0,0 +1,163 @@
+//! Full-lifecycle scenarios run against a sandbox node, from init and registration through
+//! cross-contract `ft_transfer_call`s to the admin paths.
+//!
+//! Each scenario is a sequence of composable steps from `steps`, operating on the deployment
+//! created by `fixtures::Env`. New modules should add their steps to `steps` and at least one
+//! scenario here that walks a user through the whole flow.
+//!
+//! The scenarios need the contract compiled for `wasm32-unknown-unknown`, which
+//! `workspaces::compile_project` does on first run.
+
+mod fixtures;
+mod steps;
+
+use fixtures::{Env, ONE_TOKEN};
+
+#[tokio::test]
+async fn transfer_stake_unstake() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let alice = env.user("alice").await?;
+
+    steps::transfer(&env, &env.owner, &alice, 100 * ONE_TOKEN).await?;
+    steps::stake(&env, &alice, 60 * ONE_TOKEN).await?;
+    steps::assert_balance(&env, &alice, 40 * ONE_TOKEN).await?;
+    steps::assert_staked(&env, &alice, 60 * ONE_TOKEN).await?;
+
+    steps::unstake(&env, &alice, 60 * ONE_TOKEN).await?;
+    steps::assert_balance(&env, &alice, 100 * ONE_TOKEN).await?;
+    steps::assert_staked(&env, &alice, 0).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn escrow_released_by_arbiter() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let buyer = env.user("buyer").await?;
+    let seller = env.user("seller").await?;
+    let arbiter = env.user("arbiter").await?;
+
+    steps::transfer(&env, &env.owner, &buyer, 50 * ONE_TOKEN).await?;
+    let escrow_id = steps::create_escrow(&env, &buyer, &seller, &arbiter, 50 * ONE_TOKEN).await?;
+    steps::assert_balance(&env, &buyer, 0).await?;
+
+    steps::release_escrow(&env, &arbiter, escrow_id).await?;
+    steps::assert_balance(&env, &seller, 50 * ONE_TOKEN).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn stream_withdraw_then_cancel() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let contributor = env.user("contributor").await?;
+
+    let stream_id = steps::create_stream(&env, &env.owner, &contributor, 10 * ONE_TOKEN).await?;
+    env.worker.fast_forward(100).await?;
+    let withdrawn = steps::withdraw_from_stream(&env, &contributor, stream_id).await?;
+    steps::assert_balance(&env, &contributor, withdrawn).await?;
+
+    steps::cancel_stream(&env, &env.owner, stream_id).await?;
+    steps::assert_total_supply(&env, fixtures::TOTAL_SUPPLY).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn unregister_interleaved_with_staking() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let alice = env.user("alice").await?;
+    let bob = env.user("bob").await?;
+
+    steps::transfer(&env, &env.owner, &alice, 100 * ONE_TOKEN).await?;
+    steps::stake(&env, &alice, 60 * ONE_TOKEN).await?;
+    assert!(steps::unregister(&env, &alice, true).await.is_err());
+    steps::assert_staked(&env, &alice, 60 * ONE_TOKEN).await?;
+
+    steps::transfer(&env, &alice, &bob, 40 * ONE_TOKEN).await?;
+    steps::unstake(&env, &alice, 60 * ONE_TOKEN).await?;
+    steps::unregister(&env, &alice, true).await?;
+    steps::assert_balance(&env, &bob, 40 * ONE_TOKEN).await?;
+    steps::assert_total_supply(&env, fixtures::TOTAL_SUPPLY - 60 * ONE_TOKEN).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn init_gives_the_supply_to_the_owner() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    steps::assert_total_supply(&env, fixtures::TOTAL_SUPPLY).await?;
+    steps::assert_balance(&env, &env.owner, fixtures::TOTAL_SUPPLY).await?;
+    let metadata: serde_json::Value = env.contract.view("ft_metadata").await?.json()?;
+    assert_eq!(metadata["decimals"], 18);
+    assert!(env
+        .contract
+        .call("new_default_meta")
+        .args_json(serde_json::json!({ "owner_id": env.owner.id() }))
+        .transact()
+        .await?
+        .into_result()
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn transfers_need_storage_deposit() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let carol = env.unregistered_user("carol").await?;
+    steps::assert_registered(&env, &carol, false).await?;
+    assert!(steps::transfer(&env, &env.owner, &carol, ONE_TOKEN)
+        .await
+        .is_err());
+
+    steps::storage_deposit(&env, &env.owner, carol.id()).await?;
+    steps::assert_registered(&env, &carol, true).await?;
+    steps::transfer(&env, &env.owner, &carol, ONE_TOKEN).await?;
+    steps::transfer(&env, &carol, &env.owner, ONE_TOKEN).await?;
+    steps::assert_balance(&env, &env.owner, fixtures::TOTAL_SUPPLY).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn transfer_call_used_or_refunded() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let receiver = env.receiver().await?;
+
+    let used =
+        steps::transfer_call(&env, &env.owner, receiver.id(), 10 * ONE_TOKEN, "accept").await?;
+    assert_eq!(used, 10 * ONE_TOKEN);
+    steps::assert_balance_of(&env, receiver.id(), 10 * ONE_TOKEN).await?;
+
+    let refund = format!("refund:{}", 3 * ONE_TOKEN);
+    let used =
+        steps::transfer_call(&env, &env.owner, receiver.id(), 5 * ONE_TOKEN, &refund).await?;
+    assert_eq!(used, 2 * ONE_TOKEN);
+    steps::assert_balance_of(&env, receiver.id(), 12 * ONE_TOKEN).await?;
+
+    let used =
+        steps::transfer_call(&env, &env.owner, receiver.id(), 5 * ONE_TOKEN, "panic").await?;
+    assert_eq!(used, 0);
+    let used = steps::transfer_call(&env, &env.owner, receiver.id(), 5 * ONE_TOKEN, "slow").await?;
+    assert_eq!(used, 5 * ONE_TOKEN);
+    steps::assert_balance_of(&env, receiver.id(), 17 * ONE_TOKEN).await?;
+    steps::assert_balance(&env, &env.owner, fixtures::TOTAL_SUPPLY - 17 * ONE_TOKEN).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn admin_pause_and_freeze() -> anyhow::Result<()> {
+    let env = Env::init().await?;
+    let alice = env.user("alice").await?;
+    steps::transfer(&env, &env.owner, &alice, 10 * ONE_TOKEN).await?;
+
+    assert!(steps::pause(&env, &alice).await.is_err());
+    steps::pause(&env, &env.owner).await?;
+    assert!(steps::transfer(&env, &alice, &env.owner, ONE_TOKEN)
+        .await
+        .is_err());
+    steps::unpause(&env, &env.owner).await?;
+    steps::transfer(&env, &alice, &env.owner, ONE_TOKEN).await?;
+
+    steps::freeze(&env, &env.owner, &alice).await?;
+    assert!(steps::transfer(&env, &alice, &env.owner, ONE_TOKEN)
+        .await
+        .is_err());
+    steps::assert_balance(&env, &alice, 9 * ONE_TOKEN).await?;
+    Ok(())
+}
@@ -0,0 +1,258 @@
+//! Composable scenario steps. Each step performs one user action and fails the scenario if the
+//! transaction fails.
+use near_sdk::json_types::{U128, U64};
+use serde_json::json;
+use workspaces::{Account, AccountId};
+
+use crate::fixtures::{Env, STORAGE_DEPOSIT};
+
+pub async fn transfer(env: &Env, from: &Account, to: &Account, amount: u128) -> anyhow::Result<()> {
+    from.call(env.contract.id(), "ft_transfer")
+        .args_json(json!({ "receiver_id": to.id(), "amount": U128(amount) }))
+        .deposit(1)
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+/// Sends `amount` to `receiver_id` with `ft_transfer_call`. Returns the amount the receiver
+/// used.
+pub async fn transfer_call(
+    env: &Env,
+    from: &Account,
+    receiver_id: &AccountId,
+    amount: u128,
+    msg: &str,
+) -> anyhow::Result<u128> {
+    let used: U128 = from
+        .call(env.contract.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": receiver_id,
+            "amount": U128(amount),
+            "msg": msg,
+        }))
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    Ok(used.0)
+}
+
+pub async fn storage_deposit(
+    env: &Env,
+    payer: &Account,
+    account_id: &AccountId,
+) -> anyhow::Result<()> {
+    payer
+        .call(env.contract.id(), "storage_deposit")
+        .args_json(json!({ "account_id": account_id }))
+        .deposit(STORAGE_DEPOSIT)
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn pause(env: &Env, caller: &Account) -> anyhow::Result<()> {
+    caller
+        .call(env.contract.id(), "pause")
+        .args_json(json!({}))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn unpause(env: &Env, caller: &Account) -> anyhow::Result<()> {
+    caller
+        .call(env.contract.id(), "unpause")
+        .args_json(json!({}))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn freeze(env: &Env, caller: &Account, account: &Account) -> anyhow::Result<()> {
+    caller
+        .call(env.contract.id(), "freeze_account")
+        .args_json(json!({ "account_id": account.id(), "reason": "scenario" }))
+        .deposit(STORAGE_DEPOSIT)
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn stake(env: &Env, account: &Account, amount: u128) -> anyhow::Result<()> {
+    account
+        .call(env.contract.id(), "stake")
+        .args_json(json!({ "amount": U128(amount) }))
+        .deposit(STORAGE_DEPOSIT)
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn unstake(env: &Env, account: &Account, amount: u128) -> anyhow::Result<()> {
+    account
+        .call(env.contract.id(), "unstake")
+        .args_json(json!({ "amount": U128(amount) }))
+        .deposit(1)
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn unregister(env: &Env, account: &Account, force: bool) -> anyhow::Result<()> {
+    account
+        .call(env.contract.id(), "storage_unregister")
+        .args_json(json!({ "force": force }))
+        .deposit(1)
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn create_escrow(
+    env: &Env,
+    depositor: &Account,
+    counterparty: &Account,
+    arbiter: &Account,
+    amount: u128,
+) -> anyhow::Result<U64> {
+    let deadline = env.worker.view_block().await?.timestamp() + 3_600_000_000_000;
+    let escrow_id = depositor
+        .call(env.contract.id(), "create_escrow")
+        .args_json(json!({
+            "counterparty": counterparty.id(),
+            "arbiter": arbiter.id(),
+            "amount": U128(amount),
+            "deadline": U64(deadline),
+        }))
+        .deposit(STORAGE_DEPOSIT)
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    Ok(escrow_id)
+}
+
+pub async fn release_escrow(env: &Env, caller: &Account, escrow_id: U64) -> anyhow::Result<()> {
+    caller
+        .call(env.contract.id(), "release_escrow")
+        .args_json(json!({ "id": escrow_id }))
+        .deposit(1)
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn create_stream(
+    env: &Env,
+    sender: &Account,
+    receiver: &Account,
+    amount: u128,
+) -> anyhow::Result<U64> {
+    let start = env.worker.view_block().await?.timestamp();
+    let stream_id = sender
+        .call(env.contract.id(), "create_stream")
+        .args_json(json!({
+            "receiver": receiver.id(),
+            "amount": U128(amount),
+            "start": U64(start),
+            "end": U64(start + 3_600_000_000_000),
+        }))
+        .deposit(STORAGE_DEPOSIT)
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    Ok(stream_id)
+}
+
+pub async fn withdraw_from_stream(
+    env: &Env,
+    receiver: &Account,
+    stream_id: U64,
+) -> anyhow::Result<u128> {
+    let withdrawn: U128 = receiver
+        .call(env.contract.id(), "withdraw_from_stream")
+        .args_json(json!({ "stream_id": stream_id }))
+        .deposit(1)
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    Ok(withdrawn.0)
+}
+
+pub async fn cancel_stream(env: &Env, sender: &Account, stream_id: U64) -> anyhow::Result<()> {
+    sender
+        .call(env.contract.id(), "cancel_stream")
+        .args_json(json!({ "stream_id": stream_id }))
+        .deposit(1)
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn assert_balance(env: &Env, account: &Account, expected: u128) -> anyhow::Result<()> {
+    assert_balance_of(env, account.id(), expected).await
+}
+
+pub async fn assert_balance_of(
+    env: &Env,
+    account_id: &AccountId,
+    expected: u128,
+) -> anyhow::Result<()> {
+    let balance: U128 = env
+        .contract
+        .view("ft_balance_of")
+        .args_json(json!({ "account_id": account_id }))
+        .await?
+        .json()?;
+    assert_eq!(balance.0, expected, "balance of {}", account_id);
+    Ok(())
+}
+
+pub async fn assert_registered(env: &Env, account: &Account, expected: bool) -> anyhow::Result<()> {
+    let balance: Option<serde_json::Value> = env
+        .contract
+        .view("storage_balance_of")
+        .args_json(json!({ "account_id": account.id() }))
+        .await?
+        .json()?;
+    assert_eq!(
+        balance.is_some(),
+        expected,
+        "registration of {}",
+        account.id()
+    );
+    Ok(())
+}
+
+pub async fn assert_staked(env: &Env, account: &Account, expected: u128) -> anyhow::Result<()> {
+    let staked: U128 = env
+        .contract
+        .view("get_staked_balance")
+        .args_json(json!({ "account_id": account.id() }))
+        .await?
+        .json()?;
+    assert_eq!(staked.0, expected, "staked balance of {}", account.id());
+    Ok(())
+}
+
+pub async fn assert_total_supply(env: &Env, expected: u128) -> anyhow::Result<()> {
+    let total_supply: U128 = env.contract.view("ft_total_supply").await?.json()?;
+    assert_eq!(total_supply.0, expected);
+    Ok(())
+}
@@ -0,0 +1,36 @@
+//! Fails when the release artifact grows over the size budget. The embedded icon alone takes a
+//! good part of it, so keep an eye on this when adding modules.
+//!
+//! The test builds the wasm the way `build.sh` does, into its own target directory so it does
+//! not wait on the lock of the one running the tests, and measures the fresh artifact.
+use std::path::Path;
+use std::process::Command;
+
+/// Keep in sync with `WASM_SIZE_BUDGET` in `build.sh`.
+const WASM_SIZE_BUDGET: u64 = 450_000;
+
+#[test]
+fn release_wasm_within_budget() {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let target_dir = Path::new(manifest_dir).join("target").join("wasm_size");
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .current_dir(manifest_dir)
+        .env("RUSTFLAGS", "-C link-arg=-s")
+        .args(["build", "-p", "fungible-token", "--release"])
+        .args(["--target", "wasm32-unknown-unknown"])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "building the release wasm failed");
+
+    let path = target_dir.join("wasm32-unknown-unknown/release/fungible_token.wasm");
+    let size = std::fs::metadata(&path).unwrap().len();
+    assert!(
+        size <= WASM_SIZE_BUDGET,
+        "fungible_token.wasm is {} bytes, over the budget of {} bytes",
+        size,
+        WASM_SIZE_BUDGET
+    );
+}
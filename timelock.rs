@@ -0,0 +1,258 @@
+/*!
+Timelock for admin operations.
+NOTES:
+  - Once `delay` is set, admin methods no longer accept the owner or the DAO directly. Admin
+    calls, e.g. `set_transfer_fee`, `add_mint_submitter` or `upgrade`, are queued with
+    `queue_action` and can be executed by anyone with `execute_action` once the delay has
+    passed, giving holders time to react. The contract then sends them to itself.
+  - With a council, approved council batches are queued automatically and `queue_action` is
+    reserved to the DAO.
+  - Queued actions can be executed during `GRACE_PERIOD` after their delay, then they expire.
+    The owner, the DAO, the council members and whoever queued an action can cancel it.
+  - Changing the delay is an admin operation, so it goes through the timelock too. A delay of 0
+    disables the timelock.
+  - Whoever queues an action pays for its storage and gets it back when the action is executed
+    or cancelled.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId};
+
+use crate::council::{send_admin_calls, AdminCall};
+use crate::scheduler::ScheduledAction;
+use crate::*;
+
+/// Time in nanoseconds during which a released action can be executed.
+const GRACE_PERIOD: u64 = 14 * 24 * 60 * 60 * 1_000_000_000;
+/// Maximum delay of 30 days in nanoseconds.
+const MAX_DELAY: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct QueuedAction {
+    pub calls: Vec<AdminCall>,
+    pub queued_by: AccountId,
+    pub executable_at: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Timelock {
+    /// Time in nanoseconds between queueing and executing an action, 0 while disabled.
+    pub delay: u64,
+    pub actions: LookupMap<u64, QueuedAction>,
+    pub next_action_id: u64,
+}
+
+impl Timelock {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            delay: 0,
+            actions: LookupMap::new(prefix),
+            next_action_id: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.delay > 0
+    }
+
+    pub fn unwrap_action(&self, action_id: u64) -> QueuedAction {
+        self.actions
+            .get(&action_id)
+            .cloned()
+            .expect("Timelock action not found")
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_timelock_delay(&mut self, delay: U64) {
+        self.assert_owner();
+        assert!(
+            delay.0 <= MAX_DELAY,
+            "The delay cannot exceed {} nanoseconds",
+            MAX_DELAY
+        );
+        self.timelock.delay = delay.0;
+        log!("Timelock delay set to {}", delay.0);
+    }
+
+    /// Queues an admin call and returns its id. The attached deposit covers the action storage.
+    #[payable]
+    pub fn queue_action(&mut self, action: AdminCall) -> U64 {
+        assert!(self.timelock.is_enabled(), "The timelock is disabled");
+        let predecessor_id = env::predecessor_account_id();
+        assert!(
+            Some(&predecessor_id) == self.dao_id.as_ref()
+                || (!self.council.is_active() && predecessor_id == self.owner_id),
+            "Only the owner or the DAO can queue actions"
+        );
+        let initial_storage = env::storage_usage();
+        let action_id = self.internal_queue_action(vec![action], predecessor_id);
+        refund_deposit(env::storage_usage() - initial_storage);
+        action_id.into()
+    }
+
+    /// Sends the calls of an action whose delay has passed. Callable by anyone.
+    pub fn execute_action(&mut self, action_id: U64) {
+        let action = self.timelock.unwrap_action(action_id.0);
+        assert!(
+            env::block_timestamp() >= action.executable_at.0,
+            "The action is executable from {}",
+            action.executable_at.0
+        );
+        assert!(
+            env::block_timestamp() < action.executable_at.0 + GRACE_PERIOD,
+            "The action has expired"
+        );
+        self.internal_remove_action(action_id.0, &action);
+        send_admin_calls(&action.calls);
+        log!("Timelock action {} executed", action_id.0);
+    }
+
+    #[payable]
+    pub fn cancel_action(&mut self, action_id: U64) {
+        assert_one_yocto();
+        let action = self.timelock.unwrap_action(action_id.0);
+        let predecessor_id = env::predecessor_account_id();
+        assert!(
+            predecessor_id == action.queued_by
+                || predecessor_id == self.owner_id
+                || Some(&predecessor_id) == self.dao_id.as_ref()
+                || self.council.members.contains(&predecessor_id),
+            "Not allowed to cancel this action"
+        );
+        self.internal_remove_action(action_id.0, &action);
+        log!(
+            "Timelock action {} cancelled by @{}",
+            action_id.0,
+            predecessor_id
+        );
+    }
+
+    pub fn get_timelock_delay(&self) -> U64 {
+        self.timelock.delay.into()
+    }
+
+    pub fn get_timelock_action(&self, action_id: U64) -> Option<QueuedAction> {
+        self.timelock.actions.get(&action_id.0).cloned()
+    }
+
+    /// Queued actions that have not expired, from `from_index` on.
+    pub fn get_pending_actions(&self, from_index: U64, limit: u64) -> Vec<(U64, QueuedAction)> {
+        (from_index.0..self.timelock.next_action_id)
+            .filter_map(|action_id| {
+                self.timelock
+                    .actions
+                    .get(&action_id)
+                    .filter(|action| env::block_timestamp() < action.executable_at.0 + GRACE_PERIOD)
+                    .map(|action| (action_id.into(), action.clone()))
+            })
+            .take(limit as usize)
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Queues `calls` for execution after the delay and returns the action id.
+    pub(crate) fn internal_queue_action(
+        &mut self,
+        calls: Vec<AdminCall>,
+        queued_by: AccountId,
+    ) -> u64 {
+        let action_id = self.timelock.next_action_id;
+        self.timelock.next_action_id += 1;
+        let executable_at = env::block_timestamp() + self.timelock.delay;
+        self.timelock.actions.insert(
+            action_id,
+            QueuedAction {
+                calls,
+                queued_by: queued_by.clone(),
+                executable_at: executable_at.into(),
+            },
+        );
+        self.timelock.actions.flush();
+        self.scheduler.schedule(
+            ScheduledAction::ExecuteTimelockAction {
+                action_id: action_id.into(),
+            },
+            executable_at,
+        );
+        log!(
+            "@{} queued timelock action {}, executable from {}",
+            queued_by,
+            action_id,
+            executable_at
+        );
+        action_id
+    }
+
+    fn internal_remove_action(&mut self, action_id: u64, action: &QueuedAction) {
+        let initial_storage = env::storage_usage();
+        self.timelock.actions.remove(&action_id);
+        self.timelock.actions.flush();
+        self.scheduler
+            .unschedule(&ScheduledAction::ExecuteTimelockAction {
+                action_id: action_id.into(),
+            });
+        refund_released_storage(&action.queued_by, initial_storage);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures;
+
+    const DELAY: u64 = 1_000;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = test_fixtures::setup(&[]);
+        contract.set_timelock_delay(U64(DELAY));
+        contract.queue_action(AdminCall {
+            method_name: "set_transfer_fee".to_string(),
+            args: br#"{"bps": 100}"#.to_vec().into(),
+            deposit: U128(0),
+            gas: U64(10_000_000_000_000),
+        });
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "Admin operations must go through the timelock")]
+    fn test_owner_cannot_skip_the_timelock() {
+        let (_, mut contract) = setup();
+        contract.set_transfer_fee(100);
+    }
+
+    #[test]
+    fn test_execute_after_delay() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(0)
+            .block_timestamp(DELAY)
+            .build());
+        assert_eq!(contract.get_pending_actions(U64(0), 10).len(), 1);
+        assert_eq!(contract.get_due_actions(U64(DELAY), None).len(), 1);
+        contract.execute_action(U64(0));
+        assert!(contract.get_timelock_action(U64(0)).is_none());
+        assert!(contract.get_due_actions(U64(DELAY), None).is_empty());
+        assert!(get_created_receipts()
+            .iter()
+            .any(|receipt| receipt.receiver_id == accounts(0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "The action is executable from 1000")]
+    fn test_execute_before_delay() {
+        let (_, mut contract) = setup();
+        contract.execute_action(U64(0));
+    }
+}
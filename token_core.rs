@@ -0,0 +1,233 @@
+/*!
+Token core: NEP-141 transfers and the token movements shared by every subsystem.
+NOTES:
+  - `AstroTokenExt` is the extension point for contracts built on this crate: user transfers with
+    every check and hook of `ft_transfer`, custody locks and unlocks, and mints within the
+    emission schedule. Subsystems move tokens only through it, or through `FungibleToken` inside
+    `with_checkpoints`.
+*/
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::fungible_token::events::FtBurn;
+use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance, PromiseOrValue};
+
+use crate::*;
+
+/// Token movements of the contract, with their checks, checkpoints and hooks.
+pub trait AstroTokenExt {
+    /// Moves `amount` from `account_id` into the contract's own balance, registering the
+    /// contract account on first use. Used by subsystems that hold tokens in custody.
+    fn internal_lock(&mut self, account_id: &AccountId, amount: Balance);
+
+    /// `ft_transfer` from `sender_id`, with every check and hook of the public method. Returns
+    /// false when the transfer is held for the KYC of the receiver instead of settled.
+    fn internal_ft_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) -> bool;
+
+    /// Pays `amount` out of the contract's custody balance to `account_id`.
+    fn internal_unlock(&mut self, account_id: &AccountId, amount: Balance);
+
+    /// Creates `amount` new tokens on the balance of `account_id` within the emission schedule
+    /// and the allowance of `minter_id`.
+    fn internal_mint(&mut self, minter_id: &AccountId, account_id: &AccountId, amount: Balance);
+}
+
+impl AstroTokenExt for Contract {
+    fn internal_lock(&mut self, account_id: &AccountId, amount: Balance) {
+        let contract_id = env::current_account_id();
+        self.internal_register_contract_account();
+        self.has_transfers = true;
+        self.with_checkpoints(&[account_id.clone(), contract_id.clone()], |this| {
+            this.token
+                .internal_transfer(account_id, &contract_id, amount, None)
+        });
+    }
+
+    fn internal_ft_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) -> bool {
+        self.assert_not_paused();
+        self.memo_requirements.assert_memo(&receiver_id, &memo);
+        if self.hold_for_kyc(sender_id, &receiver_id, amount, &memo) {
+            return false;
+        }
+        self.has_transfers = true;
+        self.metrics.transfers += 1;
+        self.dust_sweeping.touch(sender_id);
+        self.assert_storage_covered(sender_id);
+        self.freezes.assert_not_frozen(sender_id);
+        self.freezes.assert_not_frozen(&receiver_id);
+        self.transfer_limits.use_allowance(sender_id, amount);
+        let account_ids = self.transfer_account_ids(sender_id, &receiver_id);
+        self.with_checkpoints(&account_ids, |this| {
+            let amount = this.internal_charge_transfer_fee(sender_id, &receiver_id, amount);
+            this.trading_start
+                .assert_can_send(sender_id, &this.owner_id);
+            this.assert_launch_guard(sender_id, &receiver_id, amount);
+            if this.is_burn_account(&receiver_id) {
+                return this.internal_burn_transfer(sender_id, amount, memo);
+            }
+            if receiver_id == env::current_account_id() {
+                if this.buyback.is_exchange(sender_id)
+                    && !this.liquidity_bootstrap.take_returning(amount)
+                {
+                    this.buyback.unburned += amount;
+                } else {
+                    this.treasury_balance += amount;
+                }
+            }
+            this.transfer_log
+                .record(sender_id, &receiver_id, amount, &memo);
+            this.token
+                .internal_transfer(sender_id, &receiver_id, amount, memo)
+        });
+        self.alert_large_transfer(sender_id, &receiver_id, amount);
+        self.notify_spend(sender_id, Some(&receiver_id), amount);
+        true
+    }
+
+    fn internal_unlock(&mut self, account_id: &AccountId, amount: Balance) {
+        let contract_id = env::current_account_id();
+        self.has_transfers = true;
+        self.with_checkpoints(&[contract_id.clone(), account_id.clone()], |this| {
+            this.token
+                .internal_transfer(&contract_id, account_id, amount, None)
+        });
+    }
+
+    fn internal_mint(&mut self, minter_id: &AccountId, account_id: &AccountId, amount: Balance) {
+        self.minter_allowances.use_allowance(minter_id, amount);
+        self.use_emission(amount);
+        self.with_checkpoints(std::slice::from_ref(account_id), |this| {
+            this.token.internal_deposit(account_id, amount)
+        });
+    }
+}
+
+impl Contract {
+    /// Cleans up after `account_id` was removed. A positive `balance` was burned with the account,
+    /// inside `with_checkpoints`.
+    pub(crate) fn on_account_closed(&mut self, account_id: AccountId, balance: Balance) {
+        self.transfer_limits.usage.remove(&account_id);
+        if balance > 0 {
+            FtBurn {
+                owner_id: &account_id,
+                amount: &U128(balance),
+                memo: Some("Account closed"),
+            }
+            .emit();
+        }
+        log!("Closed @{} with {}", account_id, balance);
+        self.debug_assert_invariants();
+    }
+
+    pub(crate) fn on_tokens_burned(&mut self, account_id: AccountId, amount: Balance) {
+        log!("Account @{} burned {}", account_id, amount);
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenCore for Contract {
+    #[payable]
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        let initial_balance = self.internal_balance_of(&receiver_id);
+        let settled =
+            self.internal_ft_transfer(&sender_id, receiver_id.clone(), amount.0, memo.clone());
+        let received = self
+            .internal_balance_of(&receiver_id)
+            .saturating_sub(initial_balance);
+        self.notify_receive(&sender_id, &receiver_id, received, &memo);
+        self.telemetry.record("ft_transfer");
+        if settled {
+            self.internal_pay_gas_rebate(&sender_id);
+        }
+    }
+
+    #[payable]
+    fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        self.assert_not_paused();
+        assert!(
+            !self.is_burn_account(&receiver_id),
+            "Use ft_transfer to burn tokens"
+        );
+        self.receiver_allowlist.assert_allowed(&receiver_id);
+        self.memo_requirements.assert_memo(&receiver_id, &memo);
+        self.assert_kyc_approved(&receiver_id);
+        self.receiver_capabilities
+            .assert_supported(&receiver_id, amount.0, &msg);
+        self.has_transfers = true;
+        self.metrics.transfers += 1;
+        let sender_id = env::predecessor_account_id();
+        self.dust_sweeping.touch(&sender_id);
+        self.assert_storage_covered(&sender_id);
+        self.freezes.assert_not_frozen(&sender_id);
+        self.freezes.assert_not_frozen(&receiver_id);
+        self.transfer_limits.use_allowance(&sender_id, amount.0);
+        let account_ids = self.transfer_account_ids(&sender_id, &receiver_id);
+        let promise = self.with_checkpoints(&account_ids, |this| {
+            let amount = this.internal_charge_transfer_fee(&sender_id, &receiver_id, amount.0);
+            this.trading_start
+                .assert_can_send(&sender_id, &this.owner_id);
+            this.assert_launch_guard(&sender_id, &receiver_id, amount);
+            this.transfer_calls.record(&sender_id, &receiver_id, amount);
+            this.transfer_log
+                .record(&sender_id, &receiver_id, amount, &memo);
+            this.token
+                .ft_transfer_call(receiver_id.clone(), amount.into(), memo, msg)
+        });
+        self.alert_large_transfer(&sender_id, &receiver_id, amount.0);
+        self.notify_spend(&sender_id, Some(&receiver_id), amount.0);
+        self.telemetry.record("ft_transfer_call");
+        self.internal_pay_gas_rebate(&sender_id);
+        promise
+    }
+
+    fn ft_total_supply(&self) -> U128 {
+        self.internal_total_supply().into()
+    }
+
+    fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        self.internal_balance_of(&account_id).into()
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenResolver for Contract {
+    #[private]
+    fn ft_resolve_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        self.transfer_calls
+            .clear(&sender_id, &receiver_id, amount.0);
+        let account_ids = [sender_id.clone(), receiver_id.clone()];
+        let (used_amount, burned_amount) = self.with_checkpoints(&account_ids, |this| {
+            this.internal_resolve_transfer(&sender_id, &receiver_id, amount.0)
+        });
+        if burned_amount > 0 {
+            self.on_tokens_burned(sender_id, burned_amount);
+        }
+        self.telemetry.record("ft_resolve_transfer");
+        used_amount.into()
+    }
+}
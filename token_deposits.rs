@@ -0,0 +1,322 @@
+/*!
+Deposits of other NEP-141 tokens into contract-managed pools.
+NOTES:
+  - Other tokens, e.g. wNEAR or USDC, are deposited with `ft_transfer_call` on their contract,
+    with this contract as the receiver and the pool as `msg`: `"reward_pool"`, `"treasury"` or
+    `"buyback"`. Dividend tokens can also be sent with `"dividends"` to share them with the
+    holders, LP tokens with `"farm"` to farm them, see `farming`, and staking reward tokens with
+    `"staking_rewards"`, see `reward_tokens`. A transfer with any other `msg` is refunded by the
+    token contract. Legacy AV tokens are swapped for ASTRO instead, see `legacy_swap`.
+  - An empty `msg` is taken as a transfer by mistake: the tokens are kept as stray tokens the
+    owner can rescue.
+  - The buyback pool only takes the wNEAR of the buyback config. The owner or the DAO spends it
+    with `buyback_from_pool`; the wNEAR of a failed swap goes back to the pool.
+  - The owner or the DAO can withdraw treasury tokens with `withdraw_treasury_tokens`. Reward
+    pool tokens stay with the contract.
+*/
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::store::UnorderedMap;
+use near_sdk::{
+    env, is_promise_success, log, near_bindgen, AccountId, Balance, Gas, Promise, PromiseError,
+    PromiseOrValue,
+};
+
+use crate::buyback::ext_wrap;
+use crate::rescue::ext_ft;
+use crate::*;
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_SWAP: Gas = Gas(100_000_000_000_000);
+const GAS_FOR_ON_POOL_CALL: Gas = Gas(20_000_000_000_000);
+
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum TokenPool {
+    RewardPool,
+    Treasury,
+    Buyback,
+}
+
+impl TokenPool {
+    fn from_msg(msg: &str) -> Option<Self> {
+        match msg {
+            "reward_pool" => Some(TokenPool::RewardPool),
+            "treasury" => Some(TokenPool::Treasury),
+            "buyback" => Some(TokenPool::Buyback),
+            _ => None,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TokenDeposits {
+    pub balances: UnorderedMap<(TokenPool, AccountId), Balance>,
+}
+
+impl TokenDeposits {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            balances: UnorderedMap::new(prefix),
+        }
+    }
+
+    pub fn balance(&self, pool: TokenPool, token_id: &AccountId) -> Balance {
+        self.balances
+            .get(&(pool, token_id.clone()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn deposit(&mut self, pool: TokenPool, token_id: &AccountId, amount: Balance) {
+        let balance = self.balance(pool, token_id) + amount;
+        self.balances.insert((pool, token_id.clone()), balance);
+    }
+
+    pub fn withdraw(&mut self, pool: TokenPool, token_id: &AccountId, amount: Balance) {
+        let balance = self.balance(pool, token_id);
+        assert!(
+            amount > 0 && amount <= balance,
+            "The {:?} pool holds {} of {}",
+            pool,
+            balance,
+            token_id
+        );
+        if amount == balance {
+            self.balances.remove(&(pool, token_id.clone()));
+        } else {
+            self.balances
+                .insert((pool, token_id.clone()), balance - amount);
+        }
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    /// Adds the tokens to the pool named by `msg`. Keeps them as stray tokens if `msg` is empty.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token_id = env::predecessor_account_id();
+        assert_ne!(
+            token_id,
+            env::current_account_id(),
+            "ASTRO is not accepted through ft_on_transfer"
+        );
+        if self.legacy_swap.is_legacy_token(&token_id) {
+            self.internal_legacy_swap(&sender_id, amount.0);
+            return PromiseOrValue::Value(U128(0));
+        }
+        if msg.is_empty() {
+            self.internal_record_stray_tokens(&token_id, &sender_id, amount.0);
+            return PromiseOrValue::Value(U128(0));
+        }
+        if msg == "dividends" {
+            self.internal_distribute_dividends(&token_id, amount.0);
+            return PromiseOrValue::Value(U128(0));
+        }
+        if msg == "staking_rewards" {
+            self.internal_fund_token_rewards(&token_id, &sender_id, amount.0);
+            return PromiseOrValue::Value(U128(0));
+        }
+        if msg == "farm" {
+            self.assert_module_active(Module::Farming);
+            self.internal_farm_deposit(&token_id, &sender_id, amount.0);
+            return PromiseOrValue::Value(U128(0));
+        }
+        let pool = TokenPool::from_msg(&msg).unwrap_or_else(|| {
+            env::panic_str("Unknown pool, use \"reward_pool\", \"treasury\" or \"buyback\"")
+        });
+        if pool == TokenPool::Buyback {
+            let config = self
+                .buyback
+                .config
+                .as_ref()
+                .expect("Buyback is not configured");
+            assert_eq!(
+                token_id, config.wnear_id,
+                "The buyback pool only takes wNEAR"
+            );
+        }
+        self.token_deposits.deposit(pool, &token_id, amount.0);
+        log!(
+            "@{} deposited {} of {} into the {:?} pool",
+            sender_id,
+            amount.0,
+            token_id,
+            pool
+        );
+        PromiseOrValue::Value(U128(0))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn withdraw_treasury_tokens(
+        &mut self,
+        token_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> Promise {
+        self.assert_owner_or_dao();
+        self.token_deposits
+            .withdraw(TokenPool::Treasury, &token_id, amount.0);
+        log!(
+            "Withdrawing {} of {} from the treasury to @{}",
+            amount.0,
+            token_id,
+            receiver_id
+        );
+        ext_ft::ext(token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(receiver_id, amount, None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_POOL_CALL)
+                    .on_treasury_tokens_withdrawn(token_id, amount),
+            )
+    }
+
+    #[private]
+    pub fn on_treasury_tokens_withdrawn(&mut self, token_id: AccountId, amount: U128) {
+        if !is_promise_success() {
+            self.token_deposits
+                .deposit(TokenPool::Treasury, &token_id, amount.0);
+            log!("Treasury withdrawal of {} of {} failed", amount.0, token_id);
+            self.internal_record_callback_failure(
+                "withdraw_treasury_tokens",
+                vec![token_id],
+                amount.0,
+            );
+        }
+    }
+
+    /// Swaps `amount` of the buyback pool wNEAR for ASTRO on Ref and burns the tokens received.
+    pub fn buyback_from_pool(&mut self, amount: U128, min_tokens_out: U128) -> Promise {
+        self.assert_owner_or_dao();
+        let config = self
+            .buyback
+            .config
+            .clone()
+            .expect("Buyback is not configured");
+        self.token_deposits
+            .withdraw(TokenPool::Buyback, &config.wnear_id, amount.0);
+        let msg = json!({
+            "actions": [{
+                "pool_id": config.pool_id,
+                "token_in": config.wnear_id,
+                "token_out": env::current_account_id(),
+                "amount_in": amount,
+                "min_amount_out": min_tokens_out,
+            }],
+        })
+        .to_string();
+        ext_wrap::ext(config.wnear_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_SWAP)
+            .ft_transfer_call(config.ref_contract_id, amount, None, msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_POOL_CALL)
+                    .on_pool_buyback(config.wnear_id, amount),
+            )
+    }
+
+    /// Returns the unswapped wNEAR to the pool and burns the proceeds received so far.
+    #[private]
+    pub fn on_pool_buyback(
+        &mut self,
+        wnear_id: AccountId,
+        amount_in: U128,
+        #[callback_result] used: Result<U128, PromiseError>,
+    ) -> U128 {
+        let used = used.map(|used| used.0).unwrap_or(0);
+        if used < amount_in.0 {
+            self.token_deposits
+                .deposit(TokenPool::Buyback, &wnear_id, amount_in.0 - used);
+            log!(
+                "Buyback swap failed, {} returned to the pool",
+                amount_in.0 - used
+            );
+            self.internal_record_callback_failure(
+                "buyback_from_pool",
+                vec![wnear_id],
+                amount_in.0 - used,
+            );
+        }
+        self.burn_buyback_proceeds()
+    }
+
+    pub fn get_token_pool_balance(&self, pool: TokenPool, token_id: AccountId) -> U128 {
+        self.token_deposits.balance(pool, &token_id).into()
+    }
+
+    pub fn get_token_pool_balances(&self, pool: TokenPool) -> Vec<(AccountId, U128)> {
+        self.token_deposits
+            .balances
+            .iter()
+            .filter(|((balance_pool, _), _)| *balance_pool == pool)
+            .map(|((_, token_id), balance)| (token_id.clone(), (*balance).into()))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, contract) = test_fixtures::setup(&[]);
+        call_as(&mut context, accounts(1), 0);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_deposit_into_pools() {
+        let (_, mut contract) = setup();
+        contract.ft_on_transfer(accounts(2), U128(300), "treasury".to_string());
+        contract.ft_on_transfer(accounts(2), U128(200), "reward_pool".to_string());
+        contract.ft_on_transfer(accounts(2), U128(100), "".to_string());
+        assert_eq!(
+            contract.get_token_pool_balances(TokenPool::Treasury),
+            vec![(accounts(1), U128(300))]
+        );
+        assert_eq!(
+            contract
+                .get_token_pool_balance(TokenPool::RewardPool, accounts(1))
+                .0,
+            200
+        );
+        assert_eq!(contract.get_stray_tokens(), vec![(accounts(1), U128(100))]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown pool")]
+    fn test_unknown_pool_is_refunded() {
+        let (_, mut contract) = setup();
+        contract.ft_on_transfer(accounts(2), U128(300), "staking".to_string());
+    }
+}
@@ -0,0 +1,51 @@
+/*!
+Balance checks for token-gated contracts.
+NOTES:
+  - Gating contracts, e.g. gated chats or NFT mints, call these instead of `ft_balance_of` so they
+    neither parse `U128` strings nor handle unregistered accounts, which hold zero.
+  - `ft_balance_gte` answers with a bool. `assert_min_balance` fails below the minimum, so a
+    gating contract can chain its action after it and let the promise failure stop it.
+  - Balances are the ones of `ft_balance_of`: staked, locked or escrowed tokens do not count.
+*/
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_sdk::json_types::U128;
+use near_sdk::{near_bindgen, AccountId};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Whether `account_id` holds at least `threshold`.
+    pub fn ft_balance_gte(&self, account_id: AccountId, threshold: U128) -> bool {
+        self.ft_balance_of(account_id).0 >= threshold.0
+    }
+
+    /// Fails unless `account_id` holds at least `min_amount`, returns `true` otherwise.
+    pub fn assert_min_balance(&self, account_id: AccountId, min_amount: U128) -> bool {
+        assert!(
+            self.ft_balance_gte(account_id.clone(), min_amount),
+            "@{} holds less than {}",
+            account_id,
+            min_amount.0
+        );
+        true
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{owner, setup};
+
+    #[test]
+    fn test_balance_gte() {
+        let (_, contract) = setup(&[]);
+        let owner = owner();
+        assert!(contract.ft_balance_gte(owner.clone(), U128(TOTAL_SUPPLY)));
+        assert!(!contract.ft_balance_gte(owner.clone(), U128(TOTAL_SUPPLY + 1)));
+        assert!(!contract.ft_balance_gte(accounts(1), U128(1)));
+        assert!(contract.assert_min_balance(owner, U128(1)));
+    }
+}
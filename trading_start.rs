@@ -0,0 +1,114 @@
+/*!
+Trading start: no transfers between holders before the official listing.
+NOTES:
+  - Until `trading_enabled_at`, `ft_transfer`, `ft_transfer_call` and every transfer made on
+    behalf of a holder fail unless they come from the owner, the contract account or an account
+    the owner marked as a distributor, e.g. an external sale contract. Presale buyers can receive
+    tokens but cannot move them before the listing.
+  - The contract's own payouts, e.g. sale purchases, vesting releases, unstaking and claims, are
+    not transfers from a holder and are never restricted.
+  - Once the time has passed, transfers are unrestricted for good: the start can be moved or
+    removed only before it.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::store::LookupSet;
+use near_sdk::{env, log, near_bindgen, AccountId};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TradingStart {
+    pub enabled_at: Option<u64>,
+    pub distributors: LookupSet<AccountId>,
+}
+
+impl TradingStart {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            enabled_at: None,
+            distributors: LookupSet::new(prefix),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled_at
+            .is_none_or(|enabled_at| env::block_timestamp() >= enabled_at)
+    }
+
+    /// Panics if `sender_id` cannot move tokens yet. The owner `owner_id` always can.
+    pub fn assert_can_send(&self, sender_id: &AccountId, owner_id: &AccountId) {
+        if self.is_enabled()
+            || sender_id == owner_id
+            || sender_id == &env::current_account_id()
+            || self.distributors.contains(sender_id)
+        {
+            return;
+        }
+        env::panic_str(&format!(
+            "Trading starts at {}",
+            self.enabled_at.unwrap_or_default()
+        ));
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the time from which holders can transfer, or `None` for no restriction.
+    pub fn set_trading_start(&mut self, enabled_at: Option<U64>) {
+        self.assert_owner();
+        assert!(
+            !self.trading_start.is_enabled() || self.trading_start.enabled_at.is_none(),
+            "Trading has already started"
+        );
+        if let Some(enabled_at) = enabled_at {
+            assert!(
+                enabled_at.0 > env::block_timestamp(),
+                "The trading start must be in the future"
+            );
+            log!("Trading starts at {}", enabled_at.0);
+        }
+        self.trading_start.enabled_at = enabled_at.map(|enabled_at| enabled_at.0);
+    }
+
+    /// Lets `account_id` send tokens before the trading start.
+    pub fn set_trading_distributor(&mut self, account_id: AccountId, allowed: bool) {
+        self.assert_owner();
+        if allowed {
+            self.trading_start.distributors.insert(account_id);
+        } else {
+            self.trading_start.distributors.remove(&account_id);
+        }
+    }
+
+    pub fn get_trading_start(&self) -> Option<U64> {
+        self.trading_start.enabled_at.map(U64)
+    }
+
+    pub fn is_trading_enabled(&self) -> bool {
+        self.trading_start.is_enabled()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    #[should_panic(expected = "Trading starts at 100")]
+    fn test_holders_wait_for_the_trading_start() {
+        let (mut context, mut contract) = setup(&[accounts(1), accounts(2)]);
+        call_as(&mut context, owner(), 1);
+        contract.set_trading_start(Some(U64(100)));
+        contract.ft_transfer(accounts(1), U128(10), None);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), U128(10), None);
+    }
+}
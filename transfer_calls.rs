@@ -0,0 +1,382 @@
+/*!
+Tracking of in-flight `ft_transfer_call`s and finalization of those whose resolution failed.
+NOTES:
+  - Every `ft_transfer_call` is recorded under `(sender, receiver, amount)` until
+    `ft_resolve_transfer` runs. Identical transfers are queued by start time.
+  - The resolver normally always runs, but it can fail, e.g. when it runs out of gas. Its state
+    changes are then reverted and the record stays, while the receiver keeps the whole amount.
+  - Once a record is older than `max_wait`, anyone can finalize it according to the policy set
+    by the owner or the DAO: `Complete` leaves the tokens with the receiver, `Refund` moves up to
+    the transferred amount back from the receiver to the sender.
+  - `ft_resolve_transfer` is implemented here rather than by the standard library so that every
+    refund is logged as a `transfer_call_refund` event. Resolutions that did not go as planned,
+    i.e. a failed receiver call, an invalid return value, a receiver that no longer had the unused
+    amount or a sender that unregistered, are also kept in `failed` for reconciliation and logged
+    as `transfer_call_failure`.
+  - Records are short-lived and paid by the contract. Failure records are kept for good, they are
+    rare and small.
+*/
+use near_contract_standards::fungible_token::events::{FtBurn, FtTransfer};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::store::{LookupMap, Vector};
+use near_sdk::{env, near_bindgen, AccountId, Balance, PromiseResult};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+/// Default wait of 1 hour in nanoseconds before a transfer call can be finalized.
+const DEFAULT_MAX_WAIT: u64 = 60 * 60 * 1_000_000_000;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum StuckTransferPolicy {
+    Complete,
+    Refund,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ResolveFailure {
+    /// The receiver call failed, the whole amount was due back.
+    PromiseFailed,
+    /// The receiver returned something else than an amount, the whole amount was due back.
+    InvalidResult,
+    /// The receiver had less than the unused amount left unlocked to refund.
+    ReceiverBalanceShort,
+    /// The sender unregistered, the refund was burned.
+    SenderUnregistered,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FailedTransfer {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    /// Amount the receiver did not use and was due back to the sender.
+    pub unused: U128,
+    pub refunded: U128,
+    pub burned: U128,
+    pub failure: ResolveFailure,
+    pub resolved_at: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TransferCalls {
+    /// Start timestamps of unresolved transfer calls, oldest first.
+    pub pending: LookupMap<(AccountId, AccountId, Balance), Vec<u64>>,
+    /// Transfer calls whose resolution did not go as planned, oldest first.
+    pub failed: Vector<FailedTransfer>,
+    /// Time in nanoseconds after which an unresolved transfer call can be finalized.
+    pub max_wait: u64,
+    pub policy: StuckTransferPolicy,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferCallPolicy {
+    pub policy: StuckTransferPolicy,
+    pub max_wait: U64,
+}
+
+impl TransferCalls {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            // Keys of `pending` start with the length of an account id, at most 64, so they
+            // cannot clash with the `f` sub-prefix.
+            pending: LookupMap::new(prefix.clone()),
+            failed: Vector::new([prefix.as_slice(), b"f"].concat()),
+            max_wait: DEFAULT_MAX_WAIT,
+            policy: StuckTransferPolicy::Complete,
+        }
+    }
+
+    pub fn record(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: Balance) {
+        let key = (sender_id.clone(), receiver_id.clone(), amount);
+        let mut started = self.pending.get(&key).cloned().unwrap_or_default();
+        started.push(env::block_timestamp());
+        self.pending.insert(key, started);
+    }
+
+    /// Removes the oldest record of the given transfer call and returns its start timestamp.
+    pub fn clear(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Option<u64> {
+        let key = (sender_id.clone(), receiver_id.clone(), amount);
+        let mut started = self.pending.get(&key).cloned()?;
+        let started_at = started.remove(0);
+        if started.is_empty() {
+            self.pending.remove(&key);
+        } else {
+            self.pending.insert(key, started);
+        }
+        Some(started_at)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_transfer_call_policy(&mut self, policy: StuckTransferPolicy, max_wait: U64) {
+        self.assert_owner_or_dao();
+        assert!(max_wait.0 > 0, "Max wait must be positive");
+        self.transfer_calls.policy = policy;
+        self.transfer_calls.max_wait = max_wait.0;
+    }
+
+    pub fn get_transfer_call_policy(&self) -> TransferCallPolicy {
+        TransferCallPolicy {
+            policy: self.transfer_calls.policy,
+            max_wait: self.transfer_calls.max_wait.into(),
+        }
+    }
+
+    /// Start timestamps of the unresolved transfer calls with the given parameters.
+    pub fn get_pending_transfer_calls(
+        &self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> Vec<U64> {
+        self.transfer_calls
+            .pending
+            .get(&(sender_id, receiver_id, amount.0))
+            .map(|started| started.iter().map(|&t| t.into()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Finalizes the oldest unresolved transfer call with the given parameters once it is older
+    /// than `max_wait`. Returns the amount refunded to the sender.
+    pub fn finalize_transfer_call(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        let started = self
+            .get_pending_transfer_calls(sender_id.clone(), receiver_id.clone(), amount)
+            .first()
+            .copied()
+            .expect("No pending transfer call");
+        assert!(
+            env::block_timestamp() >= started.0 + self.transfer_calls.max_wait,
+            "Transfer call can be finalized after {}",
+            started.0 + self.transfer_calls.max_wait
+        );
+        self.transfer_calls
+            .clear(&sender_id, &receiver_id, amount.0);
+        let policy = self.transfer_calls.policy;
+        let mut refund = 0;
+        if policy == StuckTransferPolicy::Refund && self.token.accounts.contains_key(&sender_id) {
+            let receiver_balance = self.token.accounts.get(&receiver_id).unwrap_or(0);
+            refund = std::cmp::min(amount.0, receiver_balance);
+        }
+        if refund > 0 {
+            self.with_checkpoints(&[receiver_id.clone(), sender_id.clone()], |this| {
+                this.token.internal_transfer(
+                    &receiver_id,
+                    &sender_id,
+                    refund,
+                    Some("Refund of unresolved transfer call".to_string()),
+                )
+            });
+        }
+        emit_event(
+            EVENT_VERSION,
+            "transfer_call_finalize",
+            json!({
+                "sender_id": sender_id,
+                "receiver_id": receiver_id,
+                "amount": amount,
+                "started_at": started,
+                "policy": policy,
+                "refund": U128(refund),
+            }),
+        );
+        refund.into()
+    }
+
+    pub fn get_failed_transfers(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<FailedTransfer> {
+        self.transfer_calls
+            .failed
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Resolves a transfer call like the standard implementation: refunds up to the unused amount
+    /// to the sender, or burns it when the sender unregistered. Returns the used and burned
+    /// amounts.
+    pub(crate) fn internal_resolve_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> (Balance, Balance) {
+        let (unused, mut failure) = match env::promise_result(0) {
+            PromiseResult::NotReady => env::abort(),
+            PromiseResult::Successful(value) => {
+                match near_sdk::serde_json::from_slice::<U128>(&value) {
+                    Ok(unused) => (std::cmp::min(amount, unused.0), None),
+                    Err(_) => (amount, Some(ResolveFailure::InvalidResult)),
+                }
+            }
+            PromiseResult::Failed => (amount, Some(ResolveFailure::PromiseFailed)),
+        };
+        if unused == 0 {
+            return (amount, 0);
+        }
+        let receiver_balance = self.token.accounts.get(receiver_id).unwrap_or(0);
+        let unlocked = receiver_balance
+            .saturating_sub(self.balance_locks.locked(receiver_id))
+            .saturating_sub(self.reservations.reserved(receiver_id));
+        let refund = std::cmp::min(unlocked, unused);
+        if refund < unused {
+            failure = failure.or(Some(ResolveFailure::ReceiverBalanceShort));
+        }
+        let mut burned = 0;
+        if refund > 0 {
+            self.token
+                .accounts
+                .insert(receiver_id, &(receiver_balance - refund));
+            if let Some(sender_balance) = self.token.accounts.get(sender_id) {
+                self.token
+                    .accounts
+                    .insert(sender_id, &(sender_balance + refund));
+                FtTransfer {
+                    old_owner_id: receiver_id,
+                    new_owner_id: sender_id,
+                    amount: &U128(refund),
+                    memo: Some("refund"),
+                }
+                .emit();
+            } else {
+                self.token.total_supply -= refund;
+                burned = refund;
+                failure = Some(ResolveFailure::SenderUnregistered);
+                FtBurn {
+                    owner_id: receiver_id,
+                    amount: &U128(refund),
+                    memo: Some("refund"),
+                }
+                .emit();
+            }
+        }
+        let used = if burned > 0 { amount } else { amount - refund };
+        let data = json!({
+            "sender_id": sender_id,
+            "receiver_id": receiver_id,
+            "amount": U128(amount),
+            "unused": U128(unused),
+            "refunded": U128(refund - burned),
+            "burned": U128(burned),
+        });
+        if refund > burned {
+            emit_transfer_call_event("transfer_call_refund", data.clone());
+        }
+        if let Some(failure) = failure {
+            let mut data = data;
+            data["failure"] = json!(failure);
+            emit_transfer_call_event("transfer_call_failure", data);
+            self.metrics.transfer_call_failures += 1;
+            self.internal_record_callback_failure(
+                "ft_transfer_call",
+                vec![sender_id.clone(), receiver_id.clone()],
+                amount,
+            );
+            self.transfer_calls.failed.push(FailedTransfer {
+                sender_id: sender_id.clone(),
+                receiver_id: receiver_id.clone(),
+                amount: amount.into(),
+                unused: unused.into(),
+                refunded: (refund - burned).into(),
+                burned: burned.into(),
+                failure,
+                resolved_at: env::block_timestamp().into(),
+            });
+        }
+        (used, burned)
+    }
+}
+
+fn emit_transfer_call_event(event: &str, data: near_sdk::serde_json::Value) {
+    emit_event(EVENT_VERSION, event, json!(data));
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_refund_unresolved_transfer_call() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        call_as(&mut context, owner.clone(), 0);
+        contract.set_transfer_call_policy(StuckTransferPolicy::Refund, U64(100));
+        call_as(&mut context, owner.clone(), 1);
+        contract.ft_transfer_call(accounts(1), U128(1_000), None, "".to_string());
+        assert_eq!(
+            contract
+                .get_pending_transfer_calls(owner.clone(), accounts(1), U128(1_000))
+                .len(),
+            1
+        );
+
+        // The resolver never ran.
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0)
+            .block_timestamp(100)
+            .build());
+        let refund = contract.finalize_transfer_call(owner.clone(), accounts(1), U128(1_000));
+        assert_eq!(refund.0, 1_000);
+        assert_eq!(contract.ft_balance_of(owner).0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+    }
+
+    #[test]
+    fn test_failed_receiver_call_is_logged() {
+        let owner = owner();
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        call_as(&mut context, owner.clone(), 1);
+        contract.ft_transfer_call(accounts(1), U128(1_000), None, "".to_string());
+
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .attached_deposit(0)
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        let used = contract.ft_resolve_transfer(owner.clone(), accounts(1), U128(1_000));
+        assert_eq!(used.0, 0);
+        assert_eq!(contract.ft_balance_of(owner).0, TOTAL_SUPPLY);
+        let failed = contract.get_failed_transfers(None, None);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].failure, ResolveFailure::PromiseFailed);
+        assert_eq!(failed[0].refunded.0, 1_000);
+    }
+}
@@ -0,0 +1,58 @@
+/*!
+Deadlines on transfer calls.
+NOTES:
+  - `ft_transfer_call_with_deadline` is `ft_transfer_call` with a `valid_until` block timestamp.
+    A transaction that lands after it, e.g. one stuck in a queue while the price moved, fails
+    before anything is transferred instead of running a stale trade against a DEX receiver.
+  - The deadline is only checked when the transfer starts. The receiver call that follows is not
+    bounded; receivers that care check the time themselves.
+*/
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near_bindgen, AccountId, PromiseOrValue};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// `ft_transfer_call` that fails if the block timestamp is past `valid_until`, in
+    /// nanoseconds.
+    #[payable]
+    pub fn ft_transfer_call_with_deadline(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+        valid_until: U64,
+    ) -> PromiseOrValue<U128> {
+        assert!(
+            env::block_timestamp() <= valid_until.0,
+            "The transfer expired at {}",
+            valid_until.0
+        );
+        self.ft_transfer_call(receiver_id, amount, memo, msg)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::setup;
+
+    #[test]
+    #[should_panic(expected = "The transfer expired at 100")]
+    fn test_expired_transfer_call_is_rejected() {
+        let (mut context, mut contract) = setup(&[]);
+        testing_env!(context.attached_deposit(1).block_timestamp(101).build());
+        contract.ft_transfer_call_with_deadline(
+            accounts(1),
+            U128(10),
+            None,
+            "swap".to_string(),
+            U64(100),
+        );
+    }
+}
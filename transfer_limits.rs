@@ -0,0 +1,141 @@
+/*!
+Optional per-account daily transfer limits.
+NOTES:
+  - While the owner has set a daily limit, each account can send at most that amount per 24h
+    window with `ft_transfer` and `ft_transfer_call`. Windows are fixed buckets of
+    `block_timestamp / 24h`, so the allowance resets at the start of each bucket.
+  - The amount counted is the amount requested, fees and burns included.
+  - Exempt accounts, e.g. market makers, are not limited. Internal movements of the contract
+    (staking, escrows, ...) are not limited either.
+  - One usage entry is kept per sending account and removed when the account is closed.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::{LookupMap, LookupSet};
+use near_sdk::{env, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+/// Length of a rate limit window in nanoseconds.
+const WINDOW: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TransferLimits {
+    pub daily_limit: Option<Balance>,
+    /// Window index and amount sent in it, by account.
+    pub usage: LookupMap<AccountId, (u64, Balance)>,
+    pub exempt: LookupSet<AccountId>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferAllowance {
+    pub daily_limit: Option<U128>,
+    pub sent: U128,
+    pub window_ends_at: U64,
+}
+
+fn current_window() -> u64 {
+    env::block_timestamp() / WINDOW
+}
+
+impl TransferLimits {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            daily_limit: None,
+            usage: LookupMap::new([prefix.as_slice(), b"u"].concat()),
+            exempt: LookupSet::new([prefix.as_slice(), b"e"].concat()),
+        }
+    }
+
+    /// Amount sent by `account_id` in the current window.
+    pub fn sent(&self, account_id: &AccountId) -> Balance {
+        match self.usage.get(account_id) {
+            Some(&(window, sent)) if window == current_window() => sent,
+            _ => 0,
+        }
+    }
+
+    /// Counts a transfer of `amount` by `account_id`, panicking if it exceeds the daily limit.
+    pub fn use_allowance(&mut self, account_id: &AccountId, amount: Balance) {
+        let daily_limit = match self.daily_limit {
+            Some(daily_limit) if !self.exempt.contains(account_id) => daily_limit,
+            _ => return,
+        };
+        let sent = self.sent(account_id) + amount;
+        assert!(
+            sent <= daily_limit,
+            "Daily transfer limit exceeded, remaining today: {}",
+            daily_limit.saturating_sub(sent - amount)
+        );
+        self.usage
+            .insert(account_id.clone(), (current_window(), sent));
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the amount each account can transfer per window, or removes the limit with `None`.
+    pub fn set_daily_transfer_limit(&mut self, limit: Option<U128>) {
+        self.assert_owner();
+        self.transfer_limits.daily_limit = limit.map(|limit| limit.0);
+    }
+
+    pub fn add_transfer_limit_exemption(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.transfer_limits.exempt.insert(account_id);
+    }
+
+    pub fn remove_transfer_limit_exemption(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.transfer_limits.exempt.remove(&account_id);
+    }
+
+    pub fn is_transfer_limit_exempt(&self, account_id: AccountId) -> bool {
+        self.transfer_limits.exempt.contains(&account_id)
+    }
+
+    pub fn get_transfer_allowance(&self, account_id: AccountId) -> TransferAllowance {
+        TransferAllowance {
+            daily_limit: self.transfer_limits.daily_limit.map(U128),
+            sent: self.transfer_limits.sent(&account_id).into(),
+            window_ends_at: ((current_window() + 1) * WINDOW).into(),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = test_fixtures::setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 0);
+        contract.set_daily_transfer_limit(Some(U128(1_000)));
+        call_as(&mut context, owner(), 1);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_limit_resets_next_window() {
+        let (mut context, mut contract) = setup();
+        contract.ft_transfer(accounts(1), U128(600), None);
+        contract.ft_transfer(accounts(1), U128(400), None);
+        testing_env!(context.block_timestamp(WINDOW).build());
+        contract.ft_transfer(accounts(1), U128(1_000), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 2_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Daily transfer limit exceeded, remaining today: 400")]
+    fn test_limit_exceeded() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), U128(600), None);
+        contract.ft_transfer(accounts(1), U128(401), None);
+    }
+}
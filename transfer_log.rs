@@ -0,0 +1,192 @@
+/*!
+Opt-in log of the last incoming transfers of an account, for light clients without an indexer.
+NOTES:
+  - An account calls `enable_transfer_log(capacity)` to keep its last `capacity` incoming
+    transfers, up to 50, with their sender, amount, memo and timestamp. Older ones are overwritten.
+    `get_recent_transfers` returns them newest first.
+  - The deposit covers the log when full: memos are kept up to 64 bytes, so the size of a record is
+    bounded. `disable_transfer_log` drops the log and refunds the deposit.
+  - `ft_transfer`, `ft_transfer_call` and the transfers made on behalf of a holder are logged, with
+    the amount the receiver got after fees. Mints and payouts of the contract are not.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, Promise, StorageUsage};
+
+use crate::*;
+
+const MAX_CAPACITY: u32 = 50;
+const MAX_MEMO_BYTES: usize = 64;
+/// Borsh size of a record with the longest sender id and memo.
+const MAX_RECORD_BYTES: StorageUsage = (4 + 64) + 16 + (1 + 4 + MAX_MEMO_BYTES as u64) + 8;
+const DEFAULT_LIMIT: u32 = 10;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IncomingTransfer {
+    pub sender_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+    pub timestamp: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RecentTransfers {
+    pub capacity: u32,
+    /// Index of the slot to overwrite once the log is full.
+    pub next: u32,
+    pub records: Vec<IncomingTransfer>,
+    /// Storage paid for by the account.
+    pub reserved_storage: StorageUsage,
+}
+
+impl RecentTransfers {
+    fn push(&mut self, record: IncomingTransfer) {
+        if (self.records.len() as u32) < self.capacity {
+            self.records.push(record);
+        } else {
+            self.records[self.next as usize] = record;
+            self.next = (self.next + 1) % self.capacity;
+        }
+    }
+
+    /// Records from the newest to the oldest.
+    fn newest_first(&self) -> impl Iterator<Item = &IncomingTransfer> {
+        let (older, newer) = self.records.split_at(self.next as usize);
+        older.iter().rev().chain(newer.iter().rev())
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TransferLog {
+    pub logs: LookupMap<AccountId, RecentTransfers>,
+}
+
+impl TransferLog {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            logs: LookupMap::new(prefix),
+        }
+    }
+
+    /// Adds the transfer to the log of `receiver_id` if it keeps one.
+    pub fn record(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: &Option<String>,
+    ) {
+        if let Some(log) = self.logs.get_mut(receiver_id) {
+            log.push(IncomingTransfer {
+                sender_id: sender_id.clone(),
+                amount: amount.into(),
+                memo: memo.as_deref().map(truncate_memo),
+                timestamp: env::block_timestamp().into(),
+            });
+        }
+    }
+}
+
+/// The longest prefix of `memo` within `MAX_MEMO_BYTES` that ends on a char boundary.
+fn truncate_memo(memo: &str) -> String {
+    let mut end = std::cmp::min(memo.len(), MAX_MEMO_BYTES);
+    while !memo.is_char_boundary(end) {
+        end -= 1;
+    }
+    memo[..end].to_string()
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Keeps the last `capacity` incoming transfers of the caller. Takes a deposit for the storage
+    /// of the full log, the excess is refunded.
+    #[payable]
+    pub fn enable_transfer_log(&mut self, capacity: u32) {
+        assert!(
+            capacity > 0 && capacity <= MAX_CAPACITY,
+            "The capacity should be between 1 and {}",
+            MAX_CAPACITY
+        );
+        let account_id = env::predecessor_account_id();
+        assert!(
+            !self.transfer_log.logs.contains_key(&account_id),
+            "The transfer log is already enabled"
+        );
+        let initial_storage = env::storage_usage();
+        self.transfer_log.logs.insert(
+            account_id.clone(),
+            RecentTransfers {
+                capacity,
+                next: 0,
+                records: vec![],
+                reserved_storage: 0,
+            },
+        );
+        self.transfer_log.logs.flush();
+        let reserved_storage = env::storage_usage() - initial_storage
+            + StorageUsage::from(capacity) * MAX_RECORD_BYTES;
+        self.transfer_log
+            .logs
+            .get_mut(&account_id)
+            .unwrap()
+            .reserved_storage = reserved_storage;
+        refund_deposit(reserved_storage);
+        log!("Logging the last {} transfers to @{}", capacity, account_id);
+    }
+
+    /// Drops the transfer log of the caller and refunds its storage deposit.
+    pub fn disable_transfer_log(&mut self) {
+        let account_id = env::predecessor_account_id();
+        let log = self
+            .transfer_log
+            .logs
+            .remove(&account_id)
+            .expect("The transfer log is not enabled");
+        Promise::new(account_id)
+            .transfer(env::storage_byte_cost() * Balance::from(log.reserved_storage));
+    }
+
+    /// Up to `limit` of the last incoming transfers of `account_id`, newest first.
+    pub fn get_recent_transfers(
+        &self,
+        account_id: AccountId,
+        limit: Option<u32>,
+    ) -> Vec<IncomingTransfer> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT) as usize;
+        self.transfer_log
+            .logs
+            .get(&account_id)
+            .map(|log| log.newest_first().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup, ONE_NEAR};
+
+    #[test]
+    fn test_log_keeps_the_last_transfers() {
+        let (mut context, mut contract) = setup(&[accounts(1)]);
+        call_as(&mut context, accounts(1), ONE_NEAR);
+        contract.enable_transfer_log(2);
+
+        call_as(&mut context, owner(), 1);
+        for amount in [1, 2, 3] {
+            contract.ft_transfer(accounts(1), U128(amount), Some(format!("#{}", amount)));
+        }
+        let recent = contract.get_recent_transfers(accounts(1), None);
+        let amounts: Vec<u128> = recent.iter().map(|record| record.amount.0).collect();
+        assert_eq!(amounts, vec![3, 2]);
+        assert_eq!(recent[0].sender_id, owner());
+        assert_eq!(recent[0].memo.as_deref(), Some("#3"));
+        assert_eq!(contract.get_recent_transfers(accounts(1), Some(1)).len(), 1);
+    }
+}
@@ -0,0 +1,74 @@
+/*!
+Transfers that register the receiver on the fly.
+NOTES:
+  - `ft_transfer` panics when the receiver is not registered. With
+    `ft_transfer_with_registration` the sender attaches the storage deposit instead, the receiver
+    is registered and then receives the tokens, and the unused deposit is refunded to the sender.
+  - The deposit then belongs to the receiver like any storage deposit: it is refunded to the
+    receiver when they unregister.
+  - For a registered receiver it behaves like `ft_transfer`, refunding everything but 1 yocto.
+*/
+use near_sdk::json_types::U128;
+use near_sdk::{env, log, near_bindgen, AccountId, Promise};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn ft_transfer_with_registration(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let sender_id = env::predecessor_account_id();
+        let used = if self.token.accounts.contains_key(&receiver_id) {
+            1
+        } else {
+            let required = self.internal_paid_registration(&receiver_id);
+            log!("@{} registered @{}", sender_id, receiver_id);
+            required
+        };
+        self.internal_ft_transfer(&sender_id, receiver_id, amount.0, memo);
+        let refund = env::attached_deposit() - used;
+        if refund > 0 {
+            Promise::new(sender_id).transfer(refund);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_transfer_registers_receiver() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 0);
+        let min = contract.storage_balance_bounds().min.0;
+        testing_env!(context.attached_deposit(min + 1_000).build());
+        contract.ft_transfer_with_registration(accounts(1), U128(10), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 10);
+        assert_eq!(
+            contract.storage_balance_of(accounts(1)).unwrap().total.0,
+            min
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the minimum storage balance")]
+    fn test_transfer_requires_registration_deposit() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 1);
+        contract.ft_transfer_with_registration(accounts(1), U128(10), None);
+    }
+}
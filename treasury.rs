@@ -0,0 +1,386 @@
+/*!
+Treasury held by the contract account.
+NOTES:
+  - The contract account also holds tokens in custody for staking, escrows, streams, airdrops,
+    etc. `treasury_balance` is the part of its balance that belongs to the treasury, so custody
+    funds can never be paid out through `treasury_transfer`.
+  - Anyone can fund the treasury with `treasury_deposit` or a plain `ft_transfer` to the contract
+    account. Transfer fees land here unless a fee recipient is configured.
+  - Only the owner or the DAO can move tokens out of the treasury. Once a payout limit is set,
+    a `treasury_transfer` above it only creates a pending payout, paid when `approvals` council
+    members approved it before it expires. Approvals of former members do not count. The balance
+    is checked when the payout is made, not reserved while it is pending.
+  - Treasury tokens are not counted in the circulating supply. `ft_circulating_supply`, the
+    endpoint for listings, also leaves out everything else the contract account holds: staked
+    tokens and the reward pool, escrows, streams, airdrops, payment links, funding rounds and
+    buyback proceeds. Burned tokens are already gone from the total supply. `pool_balances`
+    breaks the difference down by pool.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+use near_sdk::store::UnorderedMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const EVENT_VERSION: &str = "1.0.0";
+const MAX_PENDING_PAYOUTS: u32 = 20;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingPayout {
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub approvals: Vec<AccountId>,
+    pub expires_at: U64,
+}
+
+impl PendingPayout {
+    fn is_expired(&self) -> bool {
+        env::block_timestamp() >= self.expires_at.0
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PayoutStatus {
+    pub payout_id: U64,
+    pub payout: PendingPayout,
+    /// Approvals of current council members.
+    pub approved: u8,
+    pub required: u8,
+    pub expired: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PayoutLimitInfo {
+    pub limit: Option<U128>,
+    pub approvals: u8,
+    pub validity: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TreasuryPayouts {
+    /// Largest `treasury_transfer` made at once, `None` for no limit.
+    pub limit: Option<Balance>,
+    pub approvals: u8,
+    /// Nanoseconds a pending payout can collect approvals.
+    pub validity: u64,
+    pub pending: UnorderedMap<u64, PendingPayout>,
+    pub next_payout_id: u64,
+}
+
+impl TreasuryPayouts {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            limit: None,
+            approvals: 0,
+            validity: 0,
+            pending: UnorderedMap::new(prefix),
+            next_payout_id: 0,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TreasuryInfo {
+    pub treasury_balance: U128,
+    pub circulating_supply: U128,
+}
+
+fn emit_treasury_event(event: &str, account_id: &AccountId, amount: Balance) {
+    emit_event(
+        EVENT_VERSION,
+        event,
+        json!({
+            "account_id": account_id,
+            "amount": U128(amount),
+        }),
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Moves `amount` of the caller's tokens to the treasury.
+    #[payable]
+    pub fn treasury_deposit(&mut self, amount: U128) {
+        assert_one_yocto();
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        let account_id = env::predecessor_account_id();
+        self.internal_lock(&account_id, amount.0);
+        self.treasury_balance += amount.0;
+        emit_treasury_event("treasury_deposit", &account_id, amount.0);
+    }
+
+    /// Pays `amount` out of the treasury to `receiver_id`. Above the payout limit it creates a
+    /// pending payout for the council to approve instead and returns its id.
+    #[payable]
+    pub fn treasury_transfer(&mut self, receiver_id: AccountId, amount: U128) -> Option<U64> {
+        assert_one_yocto();
+        self.assert_owner_or_dao();
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        if matches!(self.treasury_payouts.limit, Some(limit) if amount.0 > limit) {
+            return Some(self.internal_propose_payout(receiver_id, amount).into());
+        }
+        self.internal_treasury_payout(&receiver_id, amount.0);
+        None
+    }
+
+    /// Sets the largest treasury transfer made without council approval, `None` for no limit,
+    /// and the approvals and time in nanoseconds a larger payout has to collect them.
+    pub fn set_treasury_payout_limit(&mut self, limit: Option<U128>, approvals: u8, validity: U64) {
+        self.assert_owner_or_dao();
+        if limit.is_some() {
+            assert!(
+                approvals > 0 && usize::from(approvals) <= self.council.members.len(),
+                "The approvals must be between 1 and the number of council members"
+            );
+            assert!(validity.0 > 0, "The validity should be a positive number");
+        }
+        self.treasury_payouts.limit = limit.map(|limit| limit.0);
+        self.treasury_payouts.approvals = approvals;
+        self.treasury_payouts.validity = validity.0;
+    }
+
+    /// Approves a pending payout as a council member. The approval that reaches the required
+    /// number pays it.
+    pub fn approve_treasury_payout(&mut self, payout_id: U64) {
+        let member_id = env::predecessor_account_id();
+        assert!(
+            self.council.members.contains(&member_id),
+            "Only council members can do this"
+        );
+        let mut payout = self
+            .treasury_payouts
+            .pending
+            .get(&payout_id.0)
+            .cloned()
+            .expect("Pending payout not found");
+        assert!(!payout.is_expired(), "The payout has expired");
+        assert!(
+            !payout.approvals.contains(&member_id),
+            "Already approved by @{}",
+            member_id
+        );
+        payout.approvals.push(member_id.clone());
+        log!("@{} approved treasury payout {}", member_id, payout_id.0);
+        if self.approved(&payout) >= self.treasury_payouts.approvals {
+            self.treasury_payouts.pending.remove(&payout_id.0);
+            self.internal_treasury_payout(&payout.receiver_id, payout.amount.0);
+        } else {
+            self.treasury_payouts.pending.insert(payout_id.0, payout);
+        }
+    }
+
+    /// Drops a pending payout: the owner or the DAO at any time, anyone once it expired.
+    pub fn cancel_treasury_payout(&mut self, payout_id: U64) {
+        let payout = self
+            .treasury_payouts
+            .pending
+            .get(&payout_id.0)
+            .cloned()
+            .expect("Pending payout not found");
+        if !payout.is_expired() {
+            self.assert_owner_or_dao();
+        }
+        self.treasury_payouts.pending.remove(&payout_id.0);
+        log!("Treasury payout {} cancelled", payout_id.0);
+    }
+
+    pub fn get_treasury_payout_limit(&self) -> PayoutLimitInfo {
+        PayoutLimitInfo {
+            limit: self.treasury_payouts.limit.map(U128),
+            approvals: self.treasury_payouts.approvals,
+            validity: self.treasury_payouts.validity.into(),
+        }
+    }
+
+    pub fn get_treasury_payout(&self, payout_id: U64) -> Option<PayoutStatus> {
+        self.treasury_payouts
+            .pending
+            .get(&payout_id.0)
+            .map(|payout| self.payout_status(payout_id.0, payout))
+    }
+
+    /// Pending payouts, expired ones included until they are cleaned up.
+    pub fn get_pending_treasury_payouts(&self) -> Vec<PayoutStatus> {
+        self.treasury_payouts
+            .pending
+            .iter()
+            .map(|(payout_id, payout)| self.payout_status(*payout_id, payout))
+            .collect()
+    }
+
+    pub fn get_treasury_balance(&self) -> U128 {
+        self.treasury_balance.into()
+    }
+
+    /// Total supply minus the treasury balance.
+    pub fn get_circulating_supply(&self) -> U128 {
+        (self.token.total_supply - self.treasury_balance).into()
+    }
+
+    /// Total supply minus every token held by the contract account, i.e. the treasury and all
+    /// tokens locked in custody.
+    pub fn ft_circulating_supply(&self) -> U128 {
+        (self.token.total_supply - self.custody_balance()).into()
+    }
+
+    pub fn get_treasury_info(&self) -> TreasuryInfo {
+        TreasuryInfo {
+            treasury_balance: self.get_treasury_balance(),
+            circulating_supply: self.get_circulating_supply(),
+        }
+    }
+}
+
+impl Contract {
+    fn internal_treasury_payout(&mut self, receiver_id: &AccountId, amount: Balance) {
+        assert!(
+            amount <= self.treasury_balance,
+            "The treasury balance is {}",
+            self.treasury_balance
+        );
+        self.treasury_balance -= amount;
+        self.internal_unlock(receiver_id, amount);
+        emit_treasury_event("treasury_transfer", receiver_id, amount);
+    }
+
+    fn internal_propose_payout(&mut self, receiver_id: AccountId, amount: U128) -> u64 {
+        assert!(
+            self.council.is_active(),
+            "Payouts above the limit need a council"
+        );
+        let expired: Vec<u64> = self
+            .treasury_payouts
+            .pending
+            .iter()
+            .filter(|(_, payout)| payout.is_expired())
+            .map(|(payout_id, _)| *payout_id)
+            .collect();
+        for payout_id in expired {
+            self.treasury_payouts.pending.remove(&payout_id);
+        }
+        assert!(
+            self.treasury_payouts.pending.len() < MAX_PENDING_PAYOUTS,
+            "At most {} pending payouts",
+            MAX_PENDING_PAYOUTS
+        );
+        let payout_id = self.treasury_payouts.next_payout_id;
+        self.treasury_payouts.next_payout_id += 1;
+        emit_treasury_event("treasury_payout_proposed", &receiver_id, amount.0);
+        self.treasury_payouts.pending.insert(
+            payout_id,
+            PendingPayout {
+                receiver_id,
+                amount,
+                approvals: vec![],
+                expires_at: (env::block_timestamp() + self.treasury_payouts.validity).into(),
+            },
+        );
+        payout_id
+    }
+
+    /// Approvals of `payout` by current council members.
+    fn approved(&self, payout: &PendingPayout) -> u8 {
+        payout
+            .approvals
+            .iter()
+            .filter(|account_id| self.council.members.contains(account_id))
+            .count() as u8
+    }
+
+    fn payout_status(&self, payout_id: u64, payout: &PendingPayout) -> PayoutStatus {
+        PayoutStatus {
+            payout_id: payout_id.into(),
+            payout: payout.clone(),
+            approved: self.approved(payout),
+            required: self.treasury_payouts.approvals,
+            expired: payout.is_expired(),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{self, call_as, owner, ONE_NEAR};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, contract) = test_fixtures::setup(&[accounts(1)]);
+        call_as(&mut context, owner(), 1);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_fees_and_deposits_fund_treasury() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(0).build());
+        contract.set_transfer_fee(100);
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), U128(10_000), None);
+        contract.treasury_deposit(U128(500));
+        contract.ft_transfer(accounts(0), U128(400), None);
+        assert_eq!(contract.get_treasury_balance().0, 1_000);
+        assert_eq!(contract.get_circulating_supply().0, TOTAL_SUPPLY - 1_000);
+
+        contract.treasury_transfer(accounts(1), U128(1_000));
+        assert_eq!(contract.get_treasury_balance().0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 10_900);
+    }
+
+    #[test]
+    fn test_circulating_supply_excludes_custody() {
+        let (mut context, mut contract) = setup();
+        contract.treasury_deposit(U128(500));
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.stake(U128(1_000), None);
+        assert_eq!(contract.get_circulating_supply().0, TOTAL_SUPPLY - 500);
+        assert_eq!(contract.ft_circulating_supply().0, TOTAL_SUPPLY - 1_500);
+    }
+
+    #[test]
+    fn test_large_payout_needs_council_approvals() {
+        let (mut context, mut contract) = setup();
+        contract.treasury_deposit(U128(5_000));
+        testing_env!(context.attached_deposit(0).build());
+        contract.set_council(vec![accounts(2), accounts(3)], 1);
+        // Admin calls now come from the contract itself, as sent by the council.
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.set_treasury_payout_limit(Some(U128(1_000)), 2, U64(100));
+
+        testing_env!(context.attached_deposit(1).build());
+        assert!(contract
+            .treasury_transfer(accounts(1), U128(1_000))
+            .is_none());
+        let payout_id = contract
+            .treasury_transfer(accounts(1), U128(3_000))
+            .unwrap();
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.approve_treasury_payout(payout_id);
+        assert_eq!(contract.get_treasury_payout(payout_id).unwrap().approved, 1);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.approve_treasury_payout(payout_id);
+        assert!(contract.get_pending_treasury_payouts().is_empty());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 4_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "The treasury balance is 0")]
+    fn test_treasury_cannot_spend_custody() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(ONE_NEAR).build());
+        contract.stake(U128(1_000), None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.treasury_transfer(accounts(1), U128(1_000));
+    }
+}
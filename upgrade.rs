@@ -0,0 +1,56 @@
+/*!
+Self-upgrade of the contract code, gated by the owner or a configured DAO.
+NOTES:
+  - `upgrade` takes the raw wasm as the whole function input instead of JSON arguments, so the
+    code doesn't need to be base64 encoded and fits in the transaction size limit.
+  - The new code is deployed and `migrate` is called in the same batch, so a failing migration
+    reverts the deployment as well.
+*/
+use near_sdk::{env, near_bindgen, AccountId, Gas, Promise};
+
+use crate::*;
+
+/// Gas kept for finishing the `upgrade` call itself, the rest is forwarded to `migrate`.
+const GAS_FOR_UPGRADE: Gas = Gas(20_000_000_000_000);
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_dao(&mut self, dao_id: Option<AccountId>) {
+        self.assert_owner();
+        self.dao_id = dao_id;
+    }
+
+    pub fn get_dao(&self) -> Option<AccountId> {
+        self.dao_id.clone()
+    }
+
+    /// Deploys the wasm passed as input to this account and migrates the state.
+    pub fn upgrade(&self) -> Promise {
+        self.assert_owner_or_dao();
+        let code = env::input().expect("Expected the contract code as input");
+        let gas_for_migrate = env::prepaid_gas()
+            .0
+            .checked_sub(env::used_gas().0 + GAS_FOR_UPGRADE.0)
+            .expect("Not enough gas attached to upgrade and migrate the contract");
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call("migrate".to_string(), vec![], 0, Gas(gas_for_migrate))
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_owner_or_dao(&self) {
+        self.assert_not_renounced();
+        let predecessor_id = env::predecessor_account_id();
+        if self.timelock.is_enabled()
+            || (self.council.is_active() && Some(&predecessor_id) != self.dao_id.as_ref())
+        {
+            self.assert_routed_admin_call();
+            return;
+        }
+        assert!(
+            predecessor_id == self.owner_id || Some(&predecessor_id) == self.dao_id.as_ref(),
+            "Only the owner or the DAO can call this method"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+/*!
+Supply and balances in the units holders see.
+NOTES:
+  - The `_formatted` views return amounts as decimal strings with the token decimals, e.g.
+    `"1234.5"`, for wallets and explorers that do not scale amounts themselves. `precision` caps
+    the number of fraction digits, rounding down.
+*/
+use near_sdk::{near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+/// `amount` in whole tokens of `decimals` decimals, with at most `precision` fraction digits
+/// and no trailing zeros.
+pub(crate) fn format_amount(amount: Balance, decimals: u8, precision: Option<u8>) -> String {
+    let digits = amount.to_string();
+    let decimals = usize::from(decimals);
+    let (whole, fraction) = if digits.len() > decimals {
+        digits.split_at(digits.len() - decimals)
+    } else {
+        ("0", digits.as_str())
+    };
+    let mut fraction = format!("{:0>width$}", fraction, width = decimals);
+    if let Some(precision) = precision {
+        fraction.truncate(usize::from(precision));
+    }
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn ft_balance_of_formatted(&self, account_id: AccountId, precision: Option<u8>) -> String {
+        format_amount(
+            self.internal_balance_of(&account_id),
+            self.internal_decimals(),
+            precision,
+        )
+    }
+
+    pub fn ft_total_supply_formatted(&self, precision: Option<u8>) -> String {
+        format_amount(
+            self.internal_total_supply(),
+            self.internal_decimals(),
+            precision,
+        )
+    }
+}
+
+impl Contract {
+    /// Total supply in balances.
+    pub(crate) fn internal_total_supply(&self) -> Balance {
+        self.token.total_supply
+    }
+
+    /// Balance of `account_id`, 0 if it is not registered.
+    pub(crate) fn internal_balance_of(&self, account_id: &AccountId) -> Balance {
+        self.token.accounts.get(account_id).unwrap_or(0)
+    }
+
+    fn internal_decimals(&self) -> u8 {
+        self.metadata.get().map_or(0, |metadata| metadata.decimals)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(1_234_500, 3, None), "1234.5");
+        assert_eq!(format_amount(5, 3, None), "0.005");
+        assert_eq!(format_amount(5, 3, Some(2)), "0");
+        assert_eq!(format_amount(1_999, 3, Some(1)), "1.9");
+        assert_eq!(format_amount(42, 0, None), "42");
+    }
+}
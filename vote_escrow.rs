@@ -0,0 +1,204 @@
+/*!
+Vote-escrowed ASTRO: voting weight from tokens locked for a chosen time.
+NOTES:
+  - `lock_for_voting` locks tokens in the contract until a chosen time, at most
+    `MAX_LOCK_DURATION` away. The lock gives voting weight proportional to the amount and to the
+    time left: a full-length lock starts at its amount and decays linearly to zero at unlock.
+    Gauges read it with `voting_power`. The weight cannot be transferred.
+  - Each account has one lock. Locking more while it runs adds to the amount, and
+    `extend_lock` pushes the unlock time further, restoring weight.
+  - Once unlocked, the tokens are withdrawn with `withdraw_expired`. Callers pay for the storage
+    of their lock and get it back on withdrawal.
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::store::LookupMap;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance};
+
+use crate::*;
+
+const WEEK: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+const MIN_LOCK_DURATION: u64 = WEEK;
+const MAX_LOCK_DURATION: u64 = 208 * WEEK;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VoteLock {
+    pub amount: U128,
+    pub unlocks_at: U64,
+}
+
+impl VoteLock {
+    pub fn is_expired(&self) -> bool {
+        env::block_timestamp() >= self.unlocks_at.0
+    }
+
+    pub fn voting_power(&self) -> Balance {
+        let left = self.unlocks_at.0.saturating_sub(env::block_timestamp());
+        mul_div(
+            self.amount.0,
+            u128::from(left),
+            u128::from(MAX_LOCK_DURATION),
+        )
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct VoteEscrow {
+    pub locks: LookupMap<AccountId, VoteLock>,
+    /// Tokens held in locks, expired or not.
+    pub locked: Balance,
+}
+
+impl VoteEscrow {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            locks: LookupMap::new(prefix),
+            locked: 0,
+        }
+    }
+}
+
+fn assert_lock_duration(duration: u64) {
+    assert!(
+        (MIN_LOCK_DURATION..=MAX_LOCK_DURATION).contains(&duration),
+        "The lock lasts {} to {} nanoseconds",
+        MIN_LOCK_DURATION,
+        MAX_LOCK_DURATION
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Locks `amount` of the caller's tokens for `duration` nanoseconds, or adds them to the
+    /// running lock, which then ends at the later of the two times. The attached deposit covers
+    /// the storage of a new lock.
+    #[payable]
+    pub fn lock_for_voting(&mut self, amount: U128, duration: U64) -> VoteLock {
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        assert_lock_duration(duration.0);
+        let account_id = env::predecessor_account_id();
+        let unlocks_at = env::block_timestamp() + duration.0;
+        let lock = match self.vote_escrow.locks.get(&account_id) {
+            Some(lock) => {
+                assert!(!lock.is_expired(), "Withdraw the expired lock first");
+                VoteLock {
+                    amount: (lock.amount.0 + amount.0).into(),
+                    unlocks_at: lock.unlocks_at.0.max(unlocks_at).into(),
+                }
+            }
+            None => VoteLock {
+                amount,
+                unlocks_at: unlocks_at.into(),
+            },
+        };
+        let initial_storage = env::storage_usage();
+        self.internal_lock(&account_id, amount.0);
+        self.vote_escrow.locked += amount.0;
+        self.vote_escrow
+            .locks
+            .insert(account_id.clone(), lock.clone());
+        self.vote_escrow.locks.flush();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage));
+        log!(
+            "@{} locked {} for voting until {}",
+            account_id,
+            lock.amount.0,
+            lock.unlocks_at.0
+        );
+        lock
+    }
+
+    /// Moves the unlock time of the caller's lock to `duration` nanoseconds from now.
+    pub fn extend_lock(&mut self, duration: U64) -> VoteLock {
+        assert_lock_duration(duration.0);
+        let account_id = env::predecessor_account_id();
+        let mut lock = self
+            .vote_escrow
+            .locks
+            .get(&account_id)
+            .cloned()
+            .expect("No lock found");
+        assert!(!lock.is_expired(), "The lock has expired");
+        let unlocks_at = env::block_timestamp() + duration.0;
+        assert!(
+            unlocks_at > lock.unlocks_at.0,
+            "The lock already runs until {}",
+            lock.unlocks_at.0
+        );
+        lock.unlocks_at = unlocks_at.into();
+        self.vote_escrow
+            .locks
+            .insert(account_id.clone(), lock.clone());
+        log!("@{} extended their lock until {}", account_id, unlocks_at);
+        lock
+    }
+
+    /// Returns the tokens of the caller's expired lock.
+    #[payable]
+    pub fn withdraw_expired(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let lock = self
+            .vote_escrow
+            .locks
+            .get(&account_id)
+            .cloned()
+            .expect("No lock found");
+        assert!(lock.is_expired(), "The lock has not expired");
+        let initial_storage = env::storage_usage();
+        self.vote_escrow.locks.remove(&account_id);
+        self.vote_escrow.locks.flush();
+        self.vote_escrow.locked -= lock.amount.0;
+        self.internal_unlock(&account_id, lock.amount.0);
+        refund_released_storage(&account_id, initial_storage);
+        log!("@{} withdrew {} from their lock", account_id, lock.amount.0);
+        lock.amount
+    }
+
+    pub fn voting_power(&self, account_id: AccountId) -> U128 {
+        self.vote_escrow
+            .locks
+            .get(&account_id)
+            .map_or(0, VoteLock::voting_power)
+            .into()
+    }
+
+    pub fn get_vote_lock(&self, account_id: AccountId) -> Option<VoteLock> {
+        self.vote_escrow.locks.get(&account_id).cloned()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_fixtures::{call_as, owner, setup};
+
+    #[test]
+    fn test_voting_power_decays_to_zero() {
+        let (mut context, mut contract) = setup(&[]);
+        call_as(&mut context, owner(), 1_000_000_000_000_000_000_000);
+        let owner = owner();
+        contract.lock_for_voting(U128(1_000), U64(MAX_LOCK_DURATION));
+        assert_eq!(contract.voting_power(owner.clone()).0, 1_000);
+
+        testing_env!(context
+            .block_timestamp(MAX_LOCK_DURATION / 2)
+            .attached_deposit(0)
+            .build());
+        assert_eq!(contract.voting_power(owner.clone()).0, 500);
+        contract.extend_lock(U64(MAX_LOCK_DURATION));
+        assert_eq!(contract.voting_power(owner.clone()).0, 1_000);
+
+        testing_env!(context
+            .block_timestamp(MAX_LOCK_DURATION * 3 / 2)
+            .attached_deposit(1)
+            .build());
+        assert_eq!(contract.voting_power(owner.clone()).0, 0);
+        assert_eq!(contract.withdraw_expired().0, 1_000);
+        assert_eq!(contract.ft_balance_of(owner).0, TOTAL_SUPPLY);
+    }
+}